@@ -0,0 +1,131 @@
+use xous::{Message, ScalarMessage, SID};
+
+/// The name the bomber registers itself under
+pub const SERVER_NAME: &[u8; 16] = b"xous-ipc-bomber ";
+
+/// The most servers that may be attacked at once
+pub const MAX_VICTIMS: usize = 4;
+
+/// The most storm threads that may be alive at once during a thread storm
+pub const MAX_THREADS: usize = 4;
+
+/// The largest buffer that may be lent during a lend storm
+pub const MAX_LEND_SIZE: usize = 65536;
+
+/// Every message sent during a storm uses this ID.  Victims shouldn't
+/// recognize it, so they drop the message without acting on it.
+pub const STORM_ID: usize = 0xb0b0;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum StormKind {
+    /// Send non-blocking scalars, retrying whenever the victim's queue is
+    /// full
+    Scalars,
+
+    /// Lend a buffer of the given size, checking that it comes back
+    /// untouched
+    Lends,
+
+    /// Connect to the victim and disconnect again
+    Connections,
+
+    /// Start a thread that sends the victim a scalar and exits, keeping up
+    /// to `MAX_THREADS` of them alive at once
+    Threads,
+}
+
+impl StormKind {
+    fn from_usize(kind: usize) -> Option<StormKind> {
+        match kind {
+            0 => Some(StormKind::Scalars),
+            1 => Some(StormKind::Lends),
+            2 => Some(StormKind::Connections),
+            3 => Some(StormKind::Threads),
+            _ => None,
+        }
+    }
+
+    fn to_usize(self) -> usize {
+        match self {
+            StormKind::Scalars => 0,
+            StormKind::Lends => 1,
+            StormKind::Connections => 2,
+            StormKind::Threads => 3,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum Opcode {
+    /// Connect to a server and add it to the list of victims.  This must be
+    /// sent as a `BlockingScalar`, and returns `0` on success or an
+    /// `xous::Error` code.
+    AddVictim(SID),
+
+    /// Forget every victim
+    ClearVictims,
+
+    /// Run `count` rounds of a storm against every victim, then check that
+    /// every victim still works.  `size` is the number of bytes to lend in a
+    /// lend storm.  This must be sent as a `BlockingScalar`, and returns the
+    /// number of messages sent and the number of errors and failed checks.
+    Storm(StormKind, usize /* count */, usize /* size */),
+}
+
+impl<'a> core::convert::TryFrom<&'a Message> for Opcode {
+    type Error = &'static str;
+    fn try_from(message: &'a Message) -> Result<Self, Self::Error> {
+        match message {
+            Message::BlockingScalar(m) => match m.id {
+                1 => Ok(Opcode::AddVictim(SID::from_u32(
+                    m.arg1 as u32,
+                    m.arg2 as u32,
+                    m.arg3 as u32,
+                    m.arg4 as u32,
+                ))),
+                3 => Ok(Opcode::Storm(
+                    StormKind::from_usize(m.arg1).ok_or("unrecognized storm")?,
+                    m.arg2,
+                    m.arg3,
+                )),
+                _ => Err("unrecognized opcode"),
+            },
+            Message::Scalar(m) => match m.id {
+                2 => Ok(Opcode::ClearVictims),
+                _ => Err("unrecognized opcode"),
+            },
+            _ => Err("unhandled message type"),
+        }
+    }
+}
+
+impl Into<Message> for Opcode {
+    fn into(self) -> Message {
+        match self {
+            Opcode::AddVictim(sid) => {
+                let (a0, a1, a2, a3) = sid.to_u32();
+                Message::BlockingScalar(ScalarMessage {
+                    id: 1,
+                    arg1: a0 as usize,
+                    arg2: a1 as usize,
+                    arg3: a2 as usize,
+                    arg4: a3 as usize,
+                })
+            }
+            Opcode::ClearVictims => Message::Scalar(ScalarMessage {
+                id: 2,
+                arg1: 0,
+                arg2: 0,
+                arg3: 0,
+                arg4: 0,
+            }),
+            Opcode::Storm(kind, count, size) => Message::BlockingScalar(ScalarMessage {
+                id: 3,
+                arg1: kind.to_usize(),
+                arg2: count,
+                arg3: size,
+                arg4: 0,
+            }),
+        }
+    }
+}
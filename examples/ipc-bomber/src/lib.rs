@@ -0,0 +1,42 @@
+#![cfg_attr(target_os = "none", no_std)]
+
+pub mod api;
+pub use api::StormKind;
+
+use xous::{try_send_message, CID, SID};
+
+/// Add the server `victim` to the list of servers that storms are run
+/// against.
+///
+/// # Errors
+///
+/// * **OutOfMemory**: There are already `MAX_VICTIMS` victims
+/// * **ServerNotFound**: The bomber couldn't connect to `victim`
+pub fn add_victim(cid: CID, victim: SID) -> Result<(), xous::Error> {
+    match try_send_message(cid, api::Opcode::AddVictim(victim).into())? {
+        xous::Result::Scalar1(0) => Ok(()),
+        xous::Result::Scalar1(e) => Err(xous::Error::from_usize(e)),
+        _ => Err(xous::Error::InternalError),
+    }
+}
+
+/// Stop running storms against any server.
+pub fn clear_victims(cid: CID) -> Result<(), xous::Error> {
+    try_send_message(cid, api::Opcode::ClearVictims.into()).map(|_| ())
+}
+
+/// Run `count` rounds of a storm against every victim and check that they
+/// all survived, returning the number of messages sent and the number of
+/// errors and failed checks.  Lend storms lend `size` bytes at a time,
+/// which is ignored by every other kind of storm.
+pub fn storm(
+    cid: CID,
+    kind: StormKind,
+    count: usize,
+    size: usize,
+) -> Result<(usize, usize), xous::Error> {
+    match try_send_message(cid, api::Opcode::Storm(kind, count, size).into())? {
+        xous::Result::Scalar2(sent, failures) => Ok((sent, failures)),
+        _ => Err(xous::Error::InternalError),
+    }
+}
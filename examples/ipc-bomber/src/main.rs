@@ -0,0 +1,267 @@
+#![cfg_attr(target_os = "none", no_std)]
+#![cfg_attr(target_os = "none", no_main)]
+
+mod api;
+use api::{Opcode, StormKind, MAX_LEND_SIZE, MAX_THREADS, MAX_VICTIMS, STORM_ID};
+
+use core::convert::TryFrom;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use xous::{MemoryMessage, MemoryRange, Message, ScalarMessage, CID, SID};
+
+const PAGE_SIZE: usize = 4096;
+
+/// Thread storms send to this connection.  It's passed through a static
+/// rather than as the thread's argument so that every thread in a storm
+/// shares it.
+static STORM_TARGET: AtomicUsize = AtomicUsize::new(0);
+
+/// The number of storm threads that have finished sending, and how many of
+/// them failed to send
+static STORM_DONE: AtomicUsize = AtomicUsize::new(0);
+static STORM_FAILED: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Copy, Clone)]
+struct Victim {
+    sid: SID,
+    cid: CID,
+}
+
+/// The number of messages sent during a storm, and the number of errors and
+/// failed checks
+#[derive(Default)]
+struct Report {
+    sent: usize,
+    failures: usize,
+}
+
+fn storm_message(round: usize) -> ScalarMessage {
+    ScalarMessage {
+        id: STORM_ID,
+        arg1: round,
+        arg2: 0,
+        arg3: 0,
+        arg4: 0,
+    }
+}
+
+/// Send a scalar, waiting for room if the victim's queue is full.
+fn send_scalar(cid: CID, round: usize) -> Result<(), xous::Error> {
+    loop {
+        match xous::try_send_message(cid, Message::Scalar(storm_message(round))) {
+            Err(xous::Error::ServerQueueFull) => xous::yield_slice(),
+            result => return result.map(|_| ()),
+        }
+    }
+}
+
+fn fill_pattern(buf: &mut [u8]) {
+    for (idx, b) in buf.iter_mut().enumerate() {
+        *b = (idx as u8) ^ 0x5a;
+    }
+}
+
+fn has_pattern(buf: &[u8]) -> bool {
+    buf.iter()
+        .enumerate()
+        .all(|(idx, b)| *b == (idx as u8) ^ 0x5a)
+}
+
+/// Lend `range` to the victim, returning whether it came back unchanged.
+fn lend(cid: CID, range: MemoryRange) -> Result<bool, xous::Error> {
+    xous::try_send_message(
+        cid,
+        Message::Borrow(MemoryMessage {
+            id: STORM_ID,
+            buf: range,
+            offset: None,
+            valid: None,
+        }),
+    )?;
+    let buf = unsafe { core::slice::from_raw_parts(range.as_ptr(), range.len()) };
+    Ok(has_pattern(buf))
+}
+
+/// Map a buffer of at least `size` bytes filled with a pattern that lends
+/// are checked against.
+fn lend_buffer(size: usize) -> Result<MemoryRange, xous::Error> {
+    let size = (size.clamp(1, MAX_LEND_SIZE) + PAGE_SIZE - 1) & !(PAGE_SIZE - 1);
+    let range = xous::map_memory(
+        None,
+        None,
+        size,
+        xous::MemoryFlags::R | xous::MemoryFlags::W,
+    )?;
+    fill_pattern(unsafe { core::slice::from_raw_parts_mut(range.as_mut_ptr(), range.len()) });
+    Ok(range)
+}
+
+fn storm_thread(_arg: usize) -> usize {
+    let cid = STORM_TARGET.load(Ordering::SeqCst);
+    if send_scalar(cid, 0).is_err() {
+        STORM_FAILED.fetch_add(1, Ordering::SeqCst);
+    }
+    STORM_DONE.fetch_add(1, Ordering::SeqCst);
+    0
+}
+
+fn storm_victim(victim: &Victim, kind: StormKind, count: usize, size: usize, report: &mut Report) {
+    match kind {
+        StormKind::Scalars => {
+            for round in 0..count {
+                match send_scalar(victim.cid, round) {
+                    Ok(()) => report.sent += 1,
+                    Err(_) => report.failures += 1,
+                }
+            }
+        }
+        StormKind::Lends => {
+            let range = match lend_buffer(size) {
+                Ok(range) => range,
+                Err(_) => {
+                    report.failures += 1;
+                    return;
+                }
+            };
+            for _ in 0..count {
+                match lend(victim.cid, range) {
+                    Ok(true) => report.sent += 1,
+                    Ok(false) => {
+                        // The victim wrote to memory that was only lent to
+                        // it for reading.
+                        report.sent += 1;
+                        report.failures += 1;
+                        fill_pattern(unsafe {
+                            core::slice::from_raw_parts_mut(range.as_mut_ptr(), range.len())
+                        });
+                    }
+                    Err(_) => report.failures += 1,
+                }
+            }
+            xous::unmap_memory(range).expect("couldn't free lend buffer");
+        }
+        StormKind::Connections => {
+            // We already hold a connection to the victim, so these only move
+            // its reference count up and down.
+            for _ in 0..count {
+                match xous::try_connect(victim.sid) {
+                    Ok(cid) if cid == victim.cid => {
+                        report.sent += 1;
+                        if xous::disconnect(cid).is_err() {
+                            report.failures += 1;
+                        }
+                    }
+                    _ => report.failures += 1,
+                }
+            }
+        }
+        StormKind::Threads => {
+            STORM_TARGET.store(victim.cid, Ordering::SeqCst);
+            STORM_DONE.store(0, Ordering::SeqCst);
+            STORM_FAILED.store(0, Ordering::SeqCst);
+            let mut started = 0;
+            while started < count {
+                if started - STORM_DONE.load(Ordering::SeqCst) >= MAX_THREADS {
+                    xous::yield_slice();
+                    continue;
+                }
+                // Threads are never joined.  They report back through
+                // `STORM_DONE` instead.
+                match xous::create_thread_simple(storm_thread, 0) {
+                    Ok(_) => started += 1,
+                    Err(_) => {
+                        report.failures += 1;
+                        break;
+                    }
+                }
+            }
+            while STORM_DONE.load(Ordering::SeqCst) < started {
+                xous::yield_slice();
+            }
+            let failed = STORM_FAILED.load(Ordering::SeqCst);
+            report.sent += started - failed;
+            report.failures += failed;
+        }
+    }
+}
+
+/// Make sure a victim survived a storm: it must still return lent memory
+/// untouched, and connecting to it must hand back the connection we
+/// already have rather than a leaked one.  Returns the number of checks that
+/// failed.
+fn check_victim(victim: &Victim) -> usize {
+    let mut failures = 0;
+
+    match lend_buffer(PAGE_SIZE) {
+        Ok(range) => {
+            if lend(victim.cid, range) != Ok(true) {
+                failures += 1;
+            }
+            xous::unmap_memory(range).expect("couldn't free lend buffer");
+        }
+        Err(_) => failures += 1,
+    }
+
+    match xous::try_connect(victim.sid) {
+        Ok(cid) => {
+            if cid != victim.cid {
+                failures += 1;
+            }
+            xous::disconnect(cid).ok();
+        }
+        Err(_) => failures += 1,
+    }
+
+    failures
+}
+
+fn add_victim(victims: &mut [Option<Victim>], sid: SID) -> Result<(), xous::Error> {
+    let slot = victims
+        .iter_mut()
+        .find(|slot| slot.is_none())
+        .ok_or(xous::Error::OutOfMemory)?;
+    let cid = xous::try_connect(sid)?;
+    *slot = Some(Victim { sid, cid });
+    Ok(())
+}
+
+#[xous::xous_main]
+fn xmain() -> ! {
+    let mut victims: [Option<Victim>; MAX_VICTIMS] = [None; MAX_VICTIMS];
+
+    let sid = xous::create_server(api::SERVER_NAME).unwrap();
+    loop {
+        let envelope = xous::receive_message(sid).unwrap();
+        if let Ok(opcode) = Opcode::try_from(&envelope.body) {
+            match opcode {
+                Opcode::AddVictim(victim) => {
+                    let result = add_victim(&mut victims, victim)
+                        .err()
+                        .unwrap_or(xous::Error::NoError);
+                    xous::return_scalar(envelope.sender, result.to_usize())
+                        .expect("couldn't return add result");
+                }
+                Opcode::ClearVictims => {
+                    for victim in victims.iter_mut() {
+                        if let Some(victim) = victim.take() {
+                            xous::disconnect(victim.cid).ok();
+                        }
+                    }
+                }
+                Opcode::Storm(kind, count, size) => {
+                    let mut report = Report::default();
+                    for victim in victims.iter().flatten() {
+                        storm_victim(victim, kind, count, size, &mut report);
+                    }
+                    for victim in victims.iter().flatten() {
+                        report.failures += check_victim(victim);
+                    }
+                    xous::return_scalar2(envelope.sender, report.sent, report.failures)
+                        .expect("couldn't return storm report");
+                }
+            }
+        } else if let xous::Message::BlockingScalar(_) = envelope.body {
+            // Never leave a client blocked on a message we didn't understand.
+            xous::return_scalar(envelope.sender, xous::Error::UnknownError.to_usize()).ok();
+        }
+    }
+}
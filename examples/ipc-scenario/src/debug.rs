@@ -0,0 +1,60 @@
+use core::fmt::{Error, Write};
+
+#[macro_export]
+macro_rules! print
+{
+	($($args:tt)+) => ({
+			use core::fmt::Write;
+			let _ = write!(crate::debug::DEFAULT, $($args)+);
+	});
+}
+#[macro_export]
+macro_rules! println
+{
+	() => ({
+		print!("\r\n")
+	});
+	($fmt:expr) => ({
+		print!(concat!($fmt, "\r\n"))
+	});
+	($fmt:expr, $($args:tt)+) => ({
+		print!(concat!($fmt, "\r\n"), $($args)+)
+	});
+}
+
+pub struct Uart {}
+
+pub static mut DEFAULT_UART_ADDR: *mut usize = 0x0000_0000 as *mut usize;
+
+pub const DEFAULT: Uart = Uart {};
+
+impl Uart {
+    pub fn putc(&self, c: u8) {
+        unsafe {
+            if DEFAULT_UART_ADDR as usize == 0 {
+                let uart = xous::syscall::map_memory(
+                    xous::MemoryAddress::new(0xf000_1000),
+                    None,
+                    4096,
+                    xous::MemoryFlags::R | xous::MemoryFlags::W,
+                )
+                .expect("couldn't map uart");
+                DEFAULT_UART_ADDR = uart.as_mut_ptr() as _;
+            }
+            let base = DEFAULT_UART_ADDR;
+
+            // Wait until TXFULL is `0`
+            while base.add(1).read_volatile() != 0 {}
+            base.add(0).write_volatile(c as usize)
+        };
+    }
+}
+
+impl Write for Uart {
+    fn write_str(&mut self, s: &str) -> Result<(), Error> {
+        for c in s.bytes() {
+            self.putc(c);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,144 @@
+#![cfg_attr(baremetal, no_main)]
+#![cfg_attr(baremetal, no_std)]
+
+//! Run a fixed set of IPC scenarios and print what each one observed as
+//! `SCENARIO <name>: <result>` lines.  The same program runs under both the
+//! hosted and the baremetal kernel, and the `diff-scenarios` tool compares the
+//! two console logs to find places where the kernels behave differently.
+//!
+//! Client threads print their own results before telling the server they're
+//! done, and the server only prints once the client has finished, so the
+//! output is in the same order on every run.
+
+#[cfg(baremetal)]
+#[macro_use]
+mod debug;
+
+use core::fmt::Debug;
+use xous::{Message, ScalarMessage, CID, SID};
+
+const SERVER_NAME: &[u8; 16] = b"ipc-scenario-srv";
+
+/// Sent by client threads once they've printed all of their results.
+const CLIENT_DONE: usize = 0xffff;
+
+/// Stop filling the queue after this many messages, in case the kernel never
+/// reports that it's full.
+const QUEUE_LIMIT: usize = 1024;
+
+fn report<T: Debug>(name: &str, result: T) {
+    println!("SCENARIO {}: {:?}", name, result);
+}
+
+fn scalar(id: usize) -> Message {
+    Message::Scalar(ScalarMessage {
+        id,
+        arg1: id + 1,
+        arg2: id + 2,
+        arg3: id + 3,
+        arg4: id + 4,
+    })
+}
+
+fn client_done(cid: CID) {
+    xous::try_send_message(cid, scalar(CLIENT_DONE)).expect("couldn't finish client");
+}
+
+fn wait_for_client(sid: SID) {
+    loop {
+        let envelope = xous::receive_message(sid).expect("couldn't receive message");
+        if let Message::Scalar(ScalarMessage { id: CLIENT_DONE, .. }) = envelope.body {
+            return;
+        }
+        report("unexpected-message", &envelope.body);
+    }
+}
+
+fn blocking_client(sid: SID) {
+    let cid = xous::try_connect(sid).expect("couldn't connect");
+    let result = xous::try_send_message(
+        cid,
+        Message::BlockingScalar(ScalarMessage {
+            id: 1,
+            arg1: 41,
+            arg2: 0,
+            arg3: 0,
+            arg4: 0,
+        }),
+    );
+    report("blocking-scalar-reply", result);
+    client_done(cid);
+}
+
+#[xous::xous_main]
+fn scenario_main() -> ! {
+    let sid = xous::create_server(SERVER_NAME).expect("couldn't create server");
+    let cid = xous::try_connect(sid).expect("couldn't connect to our own server");
+
+    report("connect-twice", xous::try_connect(sid).map(|c| c == cid));
+    report(
+        "connect-unknown",
+        xous::try_connect(SID::from_bytes(b"no-such-server!!").unwrap()).map(|_| ()),
+    );
+
+    // Messages from a single sender must arrive in the order they were sent.
+    for id in 1..=3 {
+        report("scalar-send", xous::try_send_message(cid, scalar(id)));
+    }
+    for _ in 1..=3 {
+        let envelope = xous::receive_message(sid).expect("couldn't receive message");
+        report("scalar-order", &envelope.body);
+    }
+
+    // Fill the queue to find out how deep it is, then drain it again.
+    let mut depth = 0;
+    let mut error = None;
+    while depth < QUEUE_LIMIT {
+        match xous::try_send_message(cid, scalar(depth)) {
+            Ok(_) => depth += 1,
+            Err(e) => {
+                error = Some(e);
+                break;
+            }
+        }
+    }
+    report("queue-depth", (depth, error));
+    for _ in 0..depth {
+        xous::receive_message(sid).expect("couldn't drain queue");
+    }
+
+    // A blocking scalar gets exactly one reply.
+    xous::create_thread_simple(blocking_client, sid).expect("couldn't start client");
+    let envelope = xous::receive_message(sid).expect("couldn't receive message");
+    let sender = envelope.sender;
+    let first = xous::return_scalar(sender, 42);
+    let second = xous::return_scalar(sender, 43);
+    wait_for_client(sid);
+    report("blocking-scalar-received", &envelope.body);
+    report("return-scalar", first);
+    report("return-scalar-twice", second);
+
+    report(
+        "map-memory-wx",
+        xous::map_memory(
+            None,
+            None,
+            4096,
+            xous::MemoryFlags::R | xous::MemoryFlags::W | xous::MemoryFlags::X,
+        )
+        .map(|_| ()),
+    );
+
+    println!("SCENARIO done");
+
+    #[cfg(not(baremetal))]
+    {
+        xous::rsyscall(xous::SysCall::Shutdown(xous::ScrubLevel::None)).ok();
+        std::process::exit(0);
+    }
+
+    #[cfg(baremetal)]
+    loop {
+        xous::wait_event();
+    }
+}
@@ -0,0 +1,32 @@
+/// The name the log server registers itself under
+pub const SERVER_NAME: &[u8; 16] = b"xous-logs-output";
+
+/// Register a format string, which is lent as UTF-8 text.  The format is
+/// identified by its `format_id()` from then on.
+pub const REGISTER_FORMAT: usize = 1;
+
+/// Print a registered format.  Sent as a `Scalar` with the format ID in
+/// `arg1` and up to three arguments in the remaining words.
+pub const FORMAT_SCALAR: usize = 2;
+
+/// Print a registered format with up to `MAX_ARGS` arguments.  Sent as a
+/// lent buffer of native-endian words, the first of which is the format ID.
+pub const FORMAT_ARGS: usize = 3;
+
+/// The longest format string the server will remember
+pub const MAX_FORMAT_LEN: usize = 128;
+
+/// The most arguments that may be passed with a single message
+pub const MAX_ARGS: usize = 16;
+
+/// Identify a format string with a 32-bit FNV-1a hash.  Both the client and
+/// the server calculate this, so the string itself only needs to be sent
+/// once.
+pub fn format_id(format: &[u8]) -> usize {
+    let mut hash: u32 = 0x811c_9dc5;
+    for byte in format {
+        hash ^= *byte as u32;
+        hash = hash.wrapping_mul(0x0100_0193);
+    }
+    hash as usize
+}
@@ -0,0 +1,102 @@
+//! Format strings that clients have registered, so that they can send just
+//! an ID and some arguments and leave the formatting to us.
+
+use crate::api::{format_id, MAX_FORMAT_LEN};
+use core::fmt::{self, Write};
+
+/// The number of format strings that are remembered at once.  Registering
+/// more than this replaces the oldest one.
+const MAX_FORMATS: usize = 32;
+
+#[derive(Copy, Clone)]
+struct Format {
+    id: usize,
+    len: usize,
+    text: [u8; MAX_FORMAT_LEN],
+}
+
+pub struct Formats {
+    formats: [Option<Format>; MAX_FORMATS],
+    next: usize,
+}
+
+impl Formats {
+    pub fn new() -> Formats {
+        Formats {
+            formats: [None; MAX_FORMATS],
+            next: 0,
+        }
+    }
+
+    /// Remember `text`, returning its ID, or `None` if it's too long or
+    /// isn't valid UTF-8.
+    pub fn register(&mut self, text: &[u8]) -> Option<usize> {
+        if text.len() > MAX_FORMAT_LEN || core::str::from_utf8(text).is_err() {
+            return None;
+        }
+        let id = format_id(text);
+        if self.get(id).is_some() {
+            return Some(id);
+        }
+
+        let mut format = Format {
+            id,
+            len: text.len(),
+            text: [0; MAX_FORMAT_LEN],
+        };
+        format.text[..text.len()].copy_from_slice(text);
+        self.formats[self.next] = Some(format);
+        self.next = (self.next + 1) % MAX_FORMATS;
+        Some(id)
+    }
+
+    pub fn get(&self, id: usize) -> Option<&str> {
+        self.formats
+            .iter()
+            .flatten()
+            .find(|format| format.id == id)
+            .and_then(|format| core::str::from_utf8(&format.text[..format.len]).ok())
+    }
+}
+
+/// Write `format` to `out`, replacing each `{}` with the next argument in
+/// decimal, or in hex if the placeholder contains an `x`.  Placeholders
+/// without a matching argument are printed as `{?}`.
+pub fn render<W: Write>(out: &mut W, format: &str, args: &[usize]) -> fmt::Result {
+    let mut args = args.iter();
+    let mut chars = format.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' => {
+                let rest = chars.as_str();
+                if rest.starts_with('{') {
+                    chars.next();
+                    out.write_char('{')?;
+                    continue;
+                }
+                let end = match rest.find('}') {
+                    Some(end) => end,
+                    None => {
+                        out.write_char('{')?;
+                        return out.write_str(rest);
+                    }
+                };
+                let spec = &rest[..end];
+                chars = rest[end + 1..].chars();
+                match args.next() {
+                    Some(arg) if spec.contains('x') => write!(out, "{:x}", arg)?,
+                    Some(arg) => write!(out, "{}", arg)?,
+                    None => out.write_str("{?}")?,
+                }
+            }
+            '}' => {
+                if chars.as_str().starts_with('}') {
+                    chars.next();
+                }
+                out.write_char('}')?;
+            }
+            c => out.write_char(c)?,
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,91 @@
+#![cfg_attr(target_os = "none", no_std)]
+
+pub mod api;
+pub use api::{format_id, MAX_ARGS, MAX_FORMAT_LEN};
+
+use xous::{carton::Carton, try_send_message, Message, ScalarMessage, CID};
+
+/// Send `format` to the log server so that later messages can refer to it by
+/// ID instead of formatting text themselves.  Returns the ID to pass to
+/// `log_args()`.
+///
+/// # Errors
+///
+/// * **OutOfMemory**: The format is longer than `MAX_FORMAT_LEN` bytes
+pub fn register_format(cid: CID, format: &str) -> Result<usize, xous::Error> {
+    if format.len() > MAX_FORMAT_LEN {
+        return Err(xous::Error::OutOfMemory);
+    }
+    let carton = Carton::from_bytes(format.as_bytes());
+    carton.lend(cid, api::REGISTER_FORMAT)?;
+    Ok(format_id(format.as_bytes()))
+}
+
+/// Have the log server print the format identified by `id`, substituting
+/// `args` for its placeholders.  Up to three arguments fit in a single
+/// scalar message; more than that are serialized into a lent buffer.
+///
+/// # Errors
+///
+/// * **OutOfMemory**: More than `MAX_ARGS` arguments were passed
+pub fn log_args(cid: CID, id: usize, args: &[usize]) -> Result<(), xous::Error> {
+    if args.len() <= 3 {
+        let arg = |n: usize| args.get(n).copied().unwrap_or(0);
+        return try_send_message(
+            cid,
+            Message::Scalar(ScalarMessage {
+                id: api::FORMAT_SCALAR,
+                arg1: id,
+                arg2: arg(0),
+                arg3: arg(1),
+                arg4: arg(2),
+            }),
+        )
+        .map(|_| ());
+    }
+    if args.len() > MAX_ARGS {
+        return Err(xous::Error::OutOfMemory);
+    }
+
+    let mut words = [0usize; MAX_ARGS + 1];
+    words[0] = id;
+    words[1..=args.len()].copy_from_slice(args);
+    let bytes = unsafe {
+        core::slice::from_raw_parts(
+            words.as_ptr() as *const u8,
+            (args.len() + 1) * core::mem::size_of::<usize>(),
+        )
+    };
+    Carton::from_bytes(bytes)
+        .lend(cid, api::FORMAT_ARGS)
+        .map(|_| ())
+}
+
+/// Log a message whose formatting is done by the log server rather than by
+/// the caller, which keeps `core::fmt` off the calling thread's stack.  The
+/// format string is registered the first time each call site runs, and
+/// every argument is converted to a `usize`.
+///
+/// The server understands `{}` for decimal and `{:x}` for hexadecimal, along
+/// with `{{` and `}}` for literal braces.
+///
+/// ```ignore
+/// log_server::log_deferred!(log_conn, "loop {} took {:x} cycles", counter, cycles)?;
+/// ```
+#[macro_export]
+macro_rules! log_deferred {
+    ($cid:expr, $format:expr $(, $arg:expr)* $(,)?) => {{
+        static REGISTERED: core::sync::atomic::AtomicBool =
+            core::sync::atomic::AtomicBool::new(false);
+        let cid = $cid;
+        let registered = if REGISTERED.load(core::sync::atomic::Ordering::Relaxed) {
+            Ok($crate::format_id($format.as_bytes()))
+        } else {
+            $crate::register_format(cid, $format).map(|id| {
+                REGISTERED.store(true, core::sync::atomic::Ordering::Relaxed);
+                id
+            })
+        };
+        registered.and_then(|id| $crate::log_args(cid, id, &[$($arg as usize),*]))
+    }};
+}
@@ -5,9 +5,12 @@
 #[macro_use]
 mod debug;
 
+mod api;
+mod formats;
 mod log_string;
 
 use core::fmt::Write;
+use formats::Formats;
 use log_string::LogString;
 
 #[cfg(not(target_os = "none"))]
@@ -195,13 +198,41 @@ mod implementation {
     }
 }
 
+/// Return the bytes of a lent buffer that are in use.
+fn lent_bytes(msg: &xous::MemoryMessage) -> &[u8] {
+    let len = msg.valid.map(|v| v.get()).unwrap_or_else(|| msg.buf.len());
+    unsafe { core::slice::from_raw_parts(msg.buf.as_ptr(), len.min(msg.buf.len())) }
+}
+
+fn print_deferred(
+    output: &mut implementation::OutputWriter,
+    formats: &Formats,
+    sender: xous::MessageSender,
+    id: usize,
+    args: &[usize],
+) {
+    if let Some(format) = formats.get(id) {
+        write!(output, "LOG: {}: ", sender).unwrap();
+        formats::render(output, format, args).unwrap();
+        writeln!(output).unwrap();
+    } else {
+        writeln!(
+            output,
+            "LOG: Unregistered format {:08x} from {}: {:?}",
+            id, sender, args
+        )
+        .unwrap();
+    }
+}
+
 fn reader_thread(mut output: implementation::OutputWriter) {
     writeln!(output, "LOG: Xous Logging Server starting up...").unwrap();
 
     writeln!(output, "LOG: Starting log server...").unwrap();
-    let server_addr = xous::create_server(b"xous-logs-output").unwrap();
+    let server_addr = xous::create_server(api::SERVER_NAME).unwrap();
     writeln!(output, "LOG: Server listening on address {:?}", server_addr).unwrap();
 
+    let mut formats = Formats::new();
     let mut counter: usize = 0;
     loop {
         if counter.trailing_zeros() >= 12 {
@@ -213,6 +244,28 @@ fn reader_thread(mut output: implementation::OutputWriter) {
             xous::syscall::receive_message(server_addr).expect("couldn't get address");
         writeln!(output, "LOG: Got message envelope: {:?}", envelope).unwrap();
         match &mut envelope.body {
+            xous::Message::Scalar(msg) if msg.id == api::FORMAT_SCALAR => {
+                let args = [msg.arg2, msg.arg3, msg.arg4];
+                print_deferred(&mut output, &formats, envelope.sender, msg.arg1, &args);
+            }
+            xous::Message::Borrow(msg) if msg.id == api::REGISTER_FORMAT => {
+                if formats.register(lent_bytes(msg)).is_none() {
+                    writeln!(output, "LOG: Invalid format from {}", envelope.sender).unwrap();
+                }
+            }
+            xous::Message::Borrow(msg) if msg.id == api::FORMAT_ARGS => {
+                let bytes = lent_bytes(msg);
+                let words = unsafe {
+                    core::slice::from_raw_parts(
+                        bytes.as_ptr() as *const usize,
+                        bytes.len() / core::mem::size_of::<usize>(),
+                    )
+                };
+                if let Some((id, args)) = words.split_first() {
+                    let args = &args[..args.len().min(api::MAX_ARGS)];
+                    print_deferred(&mut output, &formats, envelope.sender, *id, args);
+                }
+            }
             xous::Message::Scalar(msg) => {
                 writeln!(output, "LOG: Scalar message from {}: {:?}", envelope.sender, msg).unwrap();
             }
@@ -0,0 +1,62 @@
+#![cfg_attr(baremetal, no_main)]
+#![cfg_attr(baremetal, no_std)]
+
+//! Print the text export of every metric held by the metrics server every
+//! few seconds, so that the metrics of a running system can be read from its
+//! console.  The program also keeps a counter of its own exports, which makes
+//! it a small example of publishing metrics with `metrics_server::Metrics`.
+
+#[cfg(baremetal)]
+#[macro_use]
+mod debug;
+
+use metrics_server::Metrics;
+
+/// How long to wait between exports
+const EXPORT_INTERVAL_MS: usize = 5000;
+
+/// The most text that one export prints.  Anything past it is cut off.
+const EXPORT_SIZE: usize = 4096;
+
+fn ensure_connection(server: xous::SID) -> xous::CID {
+    loop {
+        if let Ok(cid) = xous::try_connect(server) {
+            return cid;
+        }
+        xous::yield_slice();
+    }
+}
+
+#[xous::xous_main]
+fn xmain() -> ! {
+    let sid = xous::SID::from_bytes(metrics_server::api::SERVER_NAME).unwrap();
+    let cid = ensure_connection(sid);
+
+    let mut metrics = Metrics::new(cid).expect("couldn't map descriptor block");
+    let exports = metrics
+        .counter("exports_total")
+        .expect("couldn't add counter");
+
+    let mut buf = [0u8; EXPORT_SIZE];
+    loop {
+        metrics.increment(exports, 1);
+        if let Err(e) = metrics.publish() {
+            println!("METRICS: couldn't publish: {:?}", e);
+        }
+
+        match metrics_server::export(cid, &mut buf) {
+            // Print line by line, so that the debug UART gets its `\r\n`.
+            Ok(len) => match core::str::from_utf8(&buf[..len]) {
+                Ok(text) => {
+                    for line in text.lines() {
+                        println!("{}", line);
+                    }
+                }
+                Err(_) => println!("METRICS: export was not valid UTF-8"),
+            },
+            Err(e) => println!("METRICS: couldn't export: {:?}", e),
+        }
+
+        xous::sleep_thread(EXPORT_INTERVAL_MS).ok();
+    }
+}
@@ -0,0 +1,195 @@
+use xous::Message;
+
+/// The name the metrics server registers itself under
+pub const SERVER_NAME: &[u8; 16] = b"xous-metrics-srv";
+
+/// The largest number of metrics that the server keeps track of
+pub const MAX_METRICS: usize = 32;
+
+/// The longest name a metric may have
+pub const MAX_NAME_LEN: usize = 16;
+
+/// A descriptor block fills one page.  It starts with a header that holds
+/// the number of descriptors in use, followed by the descriptors.
+pub const BLOCK_SIZE: usize = 4096;
+const COUNT_OFFSET: usize = 0;
+const HEADER_SIZE: usize = 16;
+
+/// Each descriptor holds a metric's name, its kind, the result of the last
+/// update, and its value.
+const DESCRIPTOR_SIZE: usize = 32;
+const NAME_OFFSET: usize = 0;
+const KIND_OFFSET: usize = NAME_OFFSET + MAX_NAME_LEN;
+const RESULT_OFFSET: usize = KIND_OFFSET + 4;
+const VALUE_OFFSET: usize = RESULT_OFFSET + 4;
+
+/// The number of descriptors that fit in a block
+pub const BLOCK_DESCRIPTORS: usize = (BLOCK_SIZE - HEADER_SIZE) / DESCRIPTOR_SIZE;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum MetricKind {
+    /// A value that only ever increases
+    Counter = 1,
+
+    /// A value that may be set to anything
+    Gauge = 2,
+}
+
+impl MetricKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MetricKind::Counter => "counter",
+            MetricKind::Gauge => "gauge",
+        }
+    }
+
+    fn from_u32(kind: u32) -> Option<MetricKind> {
+        match kind {
+            1 => Some(MetricKind::Counter),
+            2 => Some(MetricKind::Gauge),
+            _ => None,
+        }
+    }
+}
+
+/// A metric name of up to `MAX_NAME_LEN` bytes.  Names follow the rules
+/// Prometheus has for them: letters, digits, `_` and `:`, and not starting
+/// with a digit.  Unused bytes are `0`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct MetricName {
+    bytes: [u8; MAX_NAME_LEN],
+}
+
+impl MetricName {
+    /// Create a new name, or return `None` if `name` is empty, too long, or
+    /// has characters that aren't allowed in it.
+    pub fn new(name: &str) -> Option<MetricName> {
+        let valid = |(idx, c): (usize, &u8)| {
+            c.is_ascii_alphabetic() || *c == b'_' || *c == b':' || (idx > 0 && c.is_ascii_digit())
+        };
+        if name.is_empty()
+            || name.len() > MAX_NAME_LEN
+            || !name.as_bytes().iter().enumerate().all(valid)
+        {
+            return None;
+        }
+        let mut bytes = [0u8; MAX_NAME_LEN];
+        bytes[..name.len()].copy_from_slice(name.as_bytes());
+        Some(MetricName { bytes })
+    }
+
+    pub fn as_str(&self) -> &str {
+        let len = self
+            .bytes
+            .iter()
+            .position(|&b| b == 0)
+            .unwrap_or(self.bytes.len());
+        core::str::from_utf8(&self.bytes[..len]).unwrap_or("invalid")
+    }
+}
+
+/// One metric in a descriptor block
+pub struct Descriptor {
+    pub name: Option<MetricName>,
+    pub kind: Option<MetricKind>,
+
+    /// Set by the server to `NoError` or an `xous::Error`
+    pub result: xous::Error,
+
+    /// How much a counter has increased since the last update, or the
+    /// current value of a gauge
+    pub value: u64,
+}
+
+fn word(buf: &[u8], offset: usize) -> u32 {
+    let mut word = [0u8; 4];
+    word.copy_from_slice(&buf[offset..offset + 4]);
+    u32::from_le_bytes(word)
+}
+
+fn descriptor_offset(index: usize) -> usize {
+    HEADER_SIZE + index * DESCRIPTOR_SIZE
+}
+
+impl Descriptor {
+    /// Decode descriptor `index` of `block`, which must be `BLOCK_SIZE`
+    /// bytes long.
+    pub fn decode(block: &[u8], index: usize) -> Descriptor {
+        let buf = &block[descriptor_offset(index)..descriptor_offset(index + 1)];
+        let name = &buf[NAME_OFFSET..KIND_OFFSET];
+        let len = name.iter().position(|&b| b == 0).unwrap_or(name.len());
+        Descriptor {
+            name: core::str::from_utf8(&name[..len])
+                .ok()
+                .and_then(MetricName::new),
+            kind: MetricKind::from_u32(word(buf, KIND_OFFSET)),
+            result: xous::Error::from_usize(word(buf, RESULT_OFFSET) as usize),
+            value: read_value(block, index),
+        }
+    }
+
+    /// Write the descriptor into slot `index` of `block`, which must be
+    /// `BLOCK_SIZE` bytes long.
+    pub fn encode(&self, block: &mut [u8], index: usize) {
+        let buf = &mut block[descriptor_offset(index)..descriptor_offset(index + 1)];
+        let name = self.name.map(|n| n.bytes).unwrap_or([0u8; MAX_NAME_LEN]);
+        buf[NAME_OFFSET..KIND_OFFSET].copy_from_slice(&name);
+        let kind = self.kind.map(|k| k as u32).unwrap_or(0);
+        buf[KIND_OFFSET..RESULT_OFFSET].copy_from_slice(&kind.to_le_bytes());
+        buf[RESULT_OFFSET..VALUE_OFFSET]
+            .copy_from_slice(&(self.result.to_usize() as u32).to_le_bytes());
+        write_value(block, index, self.value);
+    }
+}
+
+/// The number of descriptors in use in `block`
+pub fn read_count(block: &[u8]) -> usize {
+    (word(block, COUNT_OFFSET) as usize).min(BLOCK_DESCRIPTORS)
+}
+
+/// Only clients add descriptors to a block, so the server never calls this.
+#[allow(dead_code)]
+pub fn write_count(block: &mut [u8], count: usize) {
+    block[COUNT_OFFSET..COUNT_OFFSET + 4].copy_from_slice(&(count as u32).to_le_bytes());
+}
+
+/// The value of descriptor `index`.  Values are read and written on their
+/// own, since they are what changes between updates.
+pub fn read_value(block: &[u8], index: usize) -> u64 {
+    let offset = descriptor_offset(index) + VALUE_OFFSET;
+    let mut value = [0u8; 8];
+    value.copy_from_slice(&block[offset..offset + 8]);
+    u64::from_le_bytes(value)
+}
+
+pub fn write_value(block: &mut [u8], index: usize, value: u64) {
+    let offset = descriptor_offset(index) + VALUE_OFFSET;
+    block[offset..offset + 8].copy_from_slice(&value.to_le_bytes());
+}
+
+/// Both opcodes are sent as mutably-lent buffers.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Opcode {
+    /// Apply every descriptor in a lent descriptor block.  Counters are
+    /// increased by their value, which the server then sets back to `0`,
+    /// and gauges are set to theirs.  The server fills in each
+    /// descriptor's `result`.
+    Update = 1,
+
+    /// Fill a lent buffer with the text representation of all metrics
+    Export = 2,
+}
+
+impl<'a> core::convert::TryFrom<&'a Message> for Opcode {
+    type Error = &'static str;
+    fn try_from(message: &'a Message) -> Result<Self, Self::Error> {
+        match message {
+            Message::MutableBorrow(m) => match m.id {
+                1 => Ok(Opcode::Update),
+                2 => Ok(Opcode::Export),
+                _ => Err("unrecognized opcode"),
+            },
+            _ => Err("unhandled message type"),
+        }
+    }
+}
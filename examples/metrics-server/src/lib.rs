@@ -0,0 +1,180 @@
+#![cfg_attr(target_os = "none", no_std)]
+
+pub mod api;
+pub use api::{MetricKind, MetricName};
+
+use api::{Descriptor, Opcode};
+use xous::{MemoryMessage, MemoryRange, Message, CID};
+
+/// A counter in a `Metrics` block.  Counters may only be increased.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Counter {
+    index: usize,
+}
+
+/// A gauge in a `Metrics` block.  Gauges may be set to any value.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Gauge {
+    index: usize,
+}
+
+/// A page of metric descriptors that belongs to one service.  Updating a
+/// metric only writes to the page, and `publish()` lends the whole page to
+/// the server at once, so a service can update its metrics as often as it
+/// likes without sending a message each time.
+pub struct Metrics {
+    cid: CID,
+    block: MemoryRange,
+    count: usize,
+}
+
+impl Metrics {
+    /// Map a new descriptor block for metrics that get published to the
+    /// server connected on `cid`.
+    pub fn new(cid: CID) -> Result<Metrics, xous::Error> {
+        let block = xous::map_memory(
+            None,
+            None,
+            api::BLOCK_SIZE,
+            xous::MemoryFlags::R | xous::MemoryFlags::W,
+        )?;
+        Ok(Metrics {
+            cid,
+            block,
+            count: 0,
+        })
+    }
+
+    fn block(&self) -> &[u8] {
+        unsafe { core::slice::from_raw_parts(self.block.as_ptr(), self.block.len()) }
+    }
+
+    fn block_mut(&mut self) -> &mut [u8] {
+        unsafe { core::slice::from_raw_parts_mut(self.block.as_mut_ptr(), self.block.len()) }
+    }
+
+    fn add(&mut self, name: &str, kind: MetricKind) -> Result<usize, xous::Error> {
+        let name = MetricName::new(name).ok_or(xous::Error::InvalidString)?;
+        for index in 0..self.count {
+            let existing = Descriptor::decode(self.block(), index);
+            if existing.name == Some(name) {
+                return if existing.kind == Some(kind) {
+                    Ok(index)
+                } else {
+                    Err(xous::Error::ShareViolation)
+                };
+            }
+        }
+        if self.count >= api::BLOCK_DESCRIPTORS {
+            return Err(xous::Error::OutOfMemory);
+        }
+
+        let index = self.count;
+        let descriptor = Descriptor {
+            name: Some(name),
+            kind: Some(kind),
+            result: xous::Error::NoError,
+            value: 0,
+        };
+        descriptor.encode(self.block_mut(), index);
+        self.count += 1;
+        let count = self.count;
+        api::write_count(self.block_mut(), count);
+        Ok(index)
+    }
+
+    /// Add a counter called `name` to this block, or return the existing
+    /// one if there is already a counter with that name.
+    ///
+    /// # Errors
+    ///
+    /// * **InvalidString**: The name is not a valid metric name
+    /// * **ShareViolation**: The name is already used by a gauge
+    /// * **OutOfMemory**: The block has no room for any more metrics
+    pub fn counter(&mut self, name: &str) -> Result<Counter, xous::Error> {
+        self.add(name, MetricKind::Counter)
+            .map(|index| Counter { index })
+    }
+
+    /// Add a gauge called `name` to this block, or return the existing one
+    /// if there is already a gauge with that name.
+    ///
+    /// # Errors
+    ///
+    /// * **InvalidString**: The name is not a valid metric name
+    /// * **ShareViolation**: The name is already used by a counter
+    /// * **OutOfMemory**: The block has no room for any more metrics
+    pub fn gauge(&mut self, name: &str) -> Result<Gauge, xous::Error> {
+        self.add(name, MetricKind::Gauge)
+            .map(|index| Gauge { index })
+    }
+
+    /// Increase `counter` by `delta`.  The server sees the increase the next
+    /// time this block is published.
+    pub fn increment(&mut self, counter: Counter, delta: u64) {
+        if counter.index < self.count {
+            let value = api::read_value(self.block(), counter.index).wrapping_add(delta);
+            api::write_value(self.block_mut(), counter.index, value);
+        }
+    }
+
+    /// Set `gauge` to `value`.  The server sees the new value the next time
+    /// this block is published.
+    pub fn set(&mut self, gauge: Gauge, value: u64) {
+        if gauge.index < self.count {
+            api::write_value(self.block_mut(), gauge.index, value);
+        }
+    }
+
+    /// Lend this block to the server so that it can apply every update made
+    /// since the last time it was published.  Counters that the server
+    /// accepts start again from `0`, and counters that it rejects keep
+    /// their value so that nothing is lost if they are published again.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error that the server reported for any metric:
+    ///
+    /// * **ShareViolation**: Another service published the name as a
+    ///   different kind of metric
+    /// * **OutOfMemory**: The server has no room for any more metrics
+    pub fn publish(&mut self) -> Result<(), xous::Error> {
+        let message = MemoryMessage {
+            id: Opcode::Update as usize,
+            buf: self.block,
+            offset: None,
+            valid: None,
+        };
+        xous::try_send_message(self.cid, Message::MutableBorrow(message))?;
+        for index in 0..self.count {
+            let result = Descriptor::decode(self.block(), index).result;
+            if result != xous::Error::NoError {
+                return Err(result);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Metrics {
+    fn drop(&mut self) {
+        xous::unmap_memory(self.block).ok();
+    }
+}
+
+/// Fill `buf` with the text export of every metric, returning the number of
+/// bytes that were written.  Output that doesn't fit in `buf` is truncated.
+pub fn export(cid: CID, buf: &mut [u8]) -> Result<usize, xous::Error> {
+    for b in buf.iter_mut() {
+        *b = 0;
+    }
+    let mut carton = xous::carton::Carton::from_bytes(buf);
+    carton.lend_mut(cid, Opcode::Export as usize)?;
+    let exported: &[u8] = carton.as_ref();
+    let len = exported
+        .iter()
+        .position(|&b| b == 0)
+        .unwrap_or(exported.len());
+    buf[..len].copy_from_slice(&exported[..len]);
+    Ok(len)
+}
@@ -0,0 +1,138 @@
+#![cfg_attr(target_os = "none", no_std)]
+#![cfg_attr(target_os = "none", no_main)]
+
+mod api;
+use api::{Descriptor, MetricKind, MetricName, Opcode};
+
+use core::convert::TryFrom;
+use core::fmt::{self, Write};
+
+#[derive(Copy, Clone)]
+struct Metric {
+    kind: MetricKind,
+    name: MetricName,
+    value: u64,
+}
+
+/// Writes text into a lent buffer, silently dropping anything that doesn't fit.
+struct BufferWriter<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+}
+
+impl<'a> Write for BufferWriter<'a> {
+    fn write_str(&mut self, s: &str) -> Result<(), fmt::Error> {
+        for c in s.bytes() {
+            if self.len >= self.buf.len() {
+                return Err(fmt::Error);
+            }
+            self.buf[self.len] = c;
+            self.len += 1;
+        }
+        Ok(())
+    }
+}
+
+fn export(metrics: &[Option<Metric>], buf: &mut [u8]) {
+    let mut writer = BufferWriter { buf, len: 0 };
+    for metric in metrics.iter().flatten() {
+        let name = metric.name.as_str();
+        if write!(
+            writer,
+            "# TYPE {} {}\n{} {}\n",
+            name,
+            metric.kind.as_str(),
+            name,
+            metric.value
+        )
+        .is_err()
+        {
+            break;
+        }
+    }
+
+    // Clients find the end of the export by looking for the first zero byte.
+    for b in writer.buf[writer.len..].iter_mut() {
+        *b = 0;
+    }
+}
+
+/// Apply one descriptor from an update.  Counters and gauges are kept
+/// apart by name, so a name that was first published as one kind can't later
+/// be updated as the other.
+fn update(metrics: &mut [Option<Metric>], descriptor: &Descriptor) -> Result<(), xous::Error> {
+    let (name, kind) = match (descriptor.name, descriptor.kind) {
+        (Some(name), Some(kind)) => (name, kind),
+        _ => return Err(xous::Error::InvalidString),
+    };
+    let existing = metrics
+        .iter()
+        .position(|m| m.map(|m| m.name == name).unwrap_or(false));
+    let idx = match existing.or_else(|| metrics.iter().position(|m| m.is_none())) {
+        Some(idx) => idx,
+        None => return Err(xous::Error::OutOfMemory),
+    };
+    let metric = metrics[idx].get_or_insert(Metric {
+        kind,
+        name,
+        value: 0,
+    });
+    if metric.kind != kind {
+        return Err(xous::Error::ShareViolation);
+    }
+    match kind {
+        MetricKind::Counter => metric.value = metric.value.wrapping_add(descriptor.value),
+        MetricKind::Gauge => metric.value = descriptor.value,
+    }
+    Ok(())
+}
+
+#[xous::xous_main]
+fn xmain() -> ! {
+    let mut metrics: [Option<Metric>; api::MAX_METRICS] = [None; api::MAX_METRICS];
+
+    let sid = xous::create_server(api::SERVER_NAME).unwrap();
+    loop {
+        let envelope = xous::receive_message(sid).unwrap();
+        let opcode = match Opcode::try_from(&envelope.body) {
+            Ok(opcode) => opcode,
+            Err(_) => {
+                // Never leave a client blocked on a message we didn't understand.
+                if let xous::Message::BlockingScalar(_) = envelope.body {
+                    xous::return_scalar(envelope.sender, 0).ok();
+                }
+                continue;
+            }
+        };
+        // Both opcodes lend a buffer, which is returned when `envelope` is
+        // dropped.
+        let m = match &envelope.body {
+            xous::Message::MutableBorrow(m) => m,
+            _ => continue,
+        };
+        let buf = unsafe { core::slice::from_raw_parts_mut(m.buf.as_mut_ptr(), m.buf.len()) };
+        match opcode {
+            Opcode::Update => {
+                if buf.len() < api::BLOCK_SIZE {
+                    continue;
+                }
+                for index in 0..api::read_count(buf) {
+                    let mut descriptor = Descriptor::decode(buf, index);
+                    descriptor.result = match update(&mut metrics, &descriptor) {
+                        Ok(()) => {
+                            // The counter's increase has been counted, so the
+                            // client starts again from zero.
+                            if descriptor.kind == Some(MetricKind::Counter) {
+                                descriptor.value = 0;
+                            }
+                            xous::Error::NoError
+                        }
+                        Err(e) => e,
+                    };
+                    descriptor.encode(buf, index);
+                }
+            }
+            Opcode::Export => export(&metrics, buf),
+        }
+    }
+}
@@ -0,0 +1,110 @@
+use xous::{Message, ScalarMessage, PID};
+
+/// The name the power server registers itself under
+pub const SERVER_NAME: &[u8; 16] = b"xous-power-srv  ";
+
+/// The number of processes and threads per process the kernel keeps
+/// scheduler statistics for
+pub const MAX_PROCESSES: usize = 32;
+pub const MAX_THREADS: usize = 32;
+
+/// The most operating points whose residency is tracked
+pub const MAX_OPERATING_POINTS: usize = 8;
+
+#[derive(Debug)]
+pub enum Opcode {
+    /// The CPU is now running at `mhz`, drawing `active_mw` milliwatts while
+    /// running and `idle_mw` milliwatts while idle.  Sent by whichever
+    /// driver changes the clock, every time it does.
+    SetOperatingPoint(
+        usize, /* mhz */
+        usize, /* active_mw */
+        usize, /* idle_mw */
+    ),
+
+    /// A driver used `uj` microjoules in peripheral activity on behalf of
+    /// process `pid`
+    Charge(PID, usize /* uj */),
+
+    /// Get the energy used by a process since it started, in millijoules.
+    /// This must be sent as a `BlockingScalar`, and returns the CPU energy
+    /// and the peripheral energy.
+    QueryProcess(PID),
+
+    /// Get the clock of the operating point at `index`, and the number of
+    /// milliseconds spent at it.  This must be sent as a `BlockingScalar`.
+    /// A clock of `0` means there is no operating point at `index`.
+    QueryResidency(usize /* index */),
+
+    /// Get the number of milliseconds the CPU has spent idle, and the
+    /// energy used while idle in millijoules.  This must be sent as a
+    /// `BlockingScalar`.
+    QueryIdle,
+}
+
+impl<'a> core::convert::TryFrom<&'a Message> for Opcode {
+    type Error = &'static str;
+    fn try_from(message: &'a Message) -> Result<Self, Self::Error> {
+        match message {
+            Message::Scalar(m) => match m.id {
+                1 => Ok(Opcode::SetOperatingPoint(m.arg1, m.arg2, m.arg3)),
+                2 => Ok(Opcode::Charge(
+                    PID::new(m.arg1 as u8).ok_or("invalid pid")?,
+                    m.arg2,
+                )),
+                _ => Err("unrecognized opcode"),
+            },
+            Message::BlockingScalar(m) => match m.id {
+                3 => Ok(Opcode::QueryProcess(
+                    PID::new(m.arg1 as u8).ok_or("invalid pid")?,
+                )),
+                4 => Ok(Opcode::QueryResidency(m.arg1)),
+                5 => Ok(Opcode::QueryIdle),
+                _ => Err("unrecognized opcode"),
+            },
+            _ => Err("unhandled message type"),
+        }
+    }
+}
+
+impl Into<Message> for Opcode {
+    fn into(self) -> Message {
+        match self {
+            Opcode::SetOperatingPoint(mhz, active_mw, idle_mw) => Message::Scalar(ScalarMessage {
+                id: 1,
+                arg1: mhz,
+                arg2: active_mw,
+                arg3: idle_mw,
+                arg4: 0,
+            }),
+            Opcode::Charge(pid, uj) => Message::Scalar(ScalarMessage {
+                id: 2,
+                arg1: pid.get() as usize,
+                arg2: uj,
+                arg3: 0,
+                arg4: 0,
+            }),
+            Opcode::QueryProcess(pid) => Message::BlockingScalar(ScalarMessage {
+                id: 3,
+                arg1: pid.get() as usize,
+                arg2: 0,
+                arg3: 0,
+                arg4: 0,
+            }),
+            Opcode::QueryResidency(index) => Message::BlockingScalar(ScalarMessage {
+                id: 4,
+                arg1: index,
+                arg2: 0,
+                arg3: 0,
+                arg4: 0,
+            }),
+            Opcode::QueryIdle => Message::BlockingScalar(ScalarMessage {
+                id: 5,
+                arg1: 0,
+                arg2: 0,
+                arg3: 0,
+                arg4: 0,
+            }),
+        }
+    }
+}
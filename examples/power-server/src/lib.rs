@@ -0,0 +1,58 @@
+#![cfg_attr(target_os = "none", no_std)]
+
+pub mod api;
+pub use api::MAX_OPERATING_POINTS;
+
+use xous::{try_send_message, CID, PID};
+
+/// Tell the power server that the CPU has moved to a new operating point.
+/// This should be called by the driver that controls the clock every time
+/// it changes.
+pub fn set_operating_point(
+    cid: CID,
+    mhz: usize,
+    active_mw: usize,
+    idle_mw: usize,
+) -> Result<(), xous::Error> {
+    try_send_message(
+        cid,
+        api::Opcode::SetOperatingPoint(mhz, active_mw, idle_mw).into(),
+    )
+    .map(|_| ())
+}
+
+/// Charge `uj` microjoules of peripheral activity to process `pid`.  Drivers
+/// call this for the work they do on behalf of their clients, which they
+/// can identify with `MessageEnvelope::sender_pid()`.
+pub fn charge(cid: CID, pid: PID, uj: usize) -> Result<(), xous::Error> {
+    try_send_message(cid, api::Opcode::Charge(pid, uj).into()).map(|_| ())
+}
+
+/// Return the CPU energy and the peripheral energy, in millijoules, that
+/// process `pid` has used since it started.
+pub fn process_energy(cid: CID, pid: PID) -> Result<(usize, usize), xous::Error> {
+    match try_send_message(cid, api::Opcode::QueryProcess(pid).into())? {
+        xous::Result::Scalar2(cpu, peripherals) => Ok((cpu, peripherals)),
+        _ => Err(xous::Error::InternalError),
+    }
+}
+
+/// Return the clock in MHz of the operating point at `index`, along with
+/// the number of milliseconds the CPU has spent at it, or `None` once
+/// `index` is past the last operating point that has been used.
+pub fn residency(cid: CID, index: usize) -> Result<Option<(usize, usize)>, xous::Error> {
+    match try_send_message(cid, api::Opcode::QueryResidency(index).into())? {
+        xous::Result::Scalar2(0, _) => Ok(None),
+        xous::Result::Scalar2(mhz, ms) => Ok(Some((mhz, ms))),
+        _ => Err(xous::Error::InternalError),
+    }
+}
+
+/// Return the number of milliseconds the CPU has spent idle, and the energy
+/// in millijoules it used while idle.
+pub fn idle(cid: CID) -> Result<(usize, usize), xous::Error> {
+    match try_send_message(cid, api::Opcode::QueryIdle.into())? {
+        xous::Result::Scalar2(ms, mj) => Ok((ms, mj)),
+        _ => Err(xous::Error::InternalError),
+    }
+}
@@ -0,0 +1,208 @@
+#![cfg_attr(target_os = "none", no_std)]
+#![cfg_attr(target_os = "none", no_main)]
+
+mod api;
+use api::{Opcode, MAX_OPERATING_POINTS, MAX_PROCESSES, MAX_THREADS};
+
+use core::convert::TryFrom;
+use xous::PID;
+
+#[derive(Copy, Clone)]
+struct OperatingPoint {
+    mhz: usize,
+    active_mw: usize,
+    idle_mw: usize,
+
+    /// The number of ticks spent at this operating point
+    residency: u64,
+}
+
+#[derive(Copy, Clone)]
+struct Process {
+    /// The run time of each thread as of the last sample
+    run_time: [u64; MAX_THREADS],
+
+    cpu_uj: u64,
+    peripheral_uj: u64,
+}
+
+impl Process {
+    fn new() -> Process {
+        Process {
+            run_time: [0; MAX_THREADS],
+            cpu_uj: 0,
+            peripheral_uj: 0,
+        }
+    }
+}
+
+struct Accounting {
+    ticks_per_second: u64,
+    processes: [Option<Process>; MAX_PROCESSES],
+    points: [Option<OperatingPoint>; MAX_OPERATING_POINTS],
+
+    /// The index of the operating point the CPU is running at, if it has
+    /// been reported
+    current: Option<usize>,
+
+    last_sample: u64,
+    idle_ticks: u64,
+    idle_uj: u64,
+}
+
+impl Accounting {
+    fn new() -> Accounting {
+        Accounting {
+            ticks_per_second: xous::timestamp::Timebase::get()
+                .expect("couldn't get timebase")
+                .ticks_per_second(),
+            processes: [None; MAX_PROCESSES],
+            points: [None; MAX_OPERATING_POINTS],
+            current: None,
+            last_sample: xous::timestamp::now(),
+            idle_ticks: 0,
+            idle_uj: 0,
+        }
+    }
+
+    fn energy_uj(&self, ticks: u64, mw: usize) -> u64 {
+        (ticks as u128 * mw as u128 * 1000 / self.ticks_per_second as u128) as u64
+    }
+
+    fn to_ms(&self, ticks: u64) -> usize {
+        (ticks as u128 * 1000 / self.ticks_per_second as u128) as usize
+    }
+
+    /// Charge the CPU time every thread has used since the last sample to
+    /// its process at the current operating point, and the time nothing ran
+    /// to the idle counters.  This is done before anything changes and
+    /// before answering queries, so no time is ever charged at the wrong
+    /// operating point.
+    fn sample(&mut self) {
+        let now = xous::timestamp::now();
+        let elapsed = now.saturating_sub(self.last_sample);
+        self.last_sample = now;
+
+        let point = self.current.and_then(|idx| self.points[idx]);
+        let active_mw = point.map(|p| p.active_mw).unwrap_or(0);
+        let idle_mw = point.map(|p| p.idle_mw).unwrap_or(0);
+
+        let mut busy = 0;
+        for idx in 0..MAX_PROCESSES {
+            let pid = PID::new(idx as u8 + 1).unwrap();
+            let mut used = 0;
+            for tid in 0..MAX_THREADS {
+                match xous::query_scheduler(pid, tid) {
+                    Ok(stats) => {
+                        let process = self.processes[idx].get_or_insert(Process::new());
+                        let last = &mut process.run_time[tid];
+                        // A thread that was recreated since the last sample
+                        // starts counting from zero again.
+                        used += if stats.run_time < *last {
+                            stats.run_time
+                        } else {
+                            stats.run_time - *last
+                        };
+                        *last = stats.run_time;
+                    }
+                    // The process exited, so its PID may be reused by a new
+                    // one that should start from nothing.
+                    Err(xous::Error::ProcessNotFound) => {
+                        self.processes[idx] = None;
+                        break;
+                    }
+                    Err(_) => break,
+                }
+            }
+            let energy = self.energy_uj(used, active_mw);
+            if let Some(process) = self.processes[idx].as_mut() {
+                process.cpu_uj += energy;
+            }
+            busy += used;
+        }
+
+        if let Some(idx) = self.current {
+            if let Some(point) = self.points[idx].as_mut() {
+                point.residency += elapsed;
+            }
+        }
+        let idle = elapsed.saturating_sub(busy);
+        self.idle_ticks += idle;
+        self.idle_uj += self.energy_uj(idle, idle_mw);
+    }
+
+    fn set_operating_point(&mut self, mhz: usize, active_mw: usize, idle_mw: usize) {
+        self.sample();
+        let existing = self
+            .points
+            .iter()
+            .position(|p| p.map(|p| p.mhz == mhz).unwrap_or(false));
+        // Operating points past `MAX_OPERATING_POINTS` aren't accounted for.
+        self.current = existing.or_else(|| self.points.iter().position(|p| p.is_none()));
+        if let Some(idx) = self.current {
+            let residency = self.points[idx].map(|p| p.residency).unwrap_or(0);
+            self.points[idx] = Some(OperatingPoint {
+                mhz,
+                active_mw,
+                idle_mw,
+                residency,
+            });
+        }
+    }
+}
+
+#[xous::xous_main]
+fn xmain() -> ! {
+    let mut accounting = Accounting::new();
+
+    let sid = xous::create_server(api::SERVER_NAME).unwrap();
+    loop {
+        let envelope = xous::receive_message(sid).unwrap();
+        if let Ok(opcode) = Opcode::try_from(&envelope.body) {
+            match opcode {
+                Opcode::SetOperatingPoint(mhz, active_mw, idle_mw) => {
+                    accounting.set_operating_point(mhz, active_mw, idle_mw);
+                }
+                Opcode::Charge(pid, uj) => {
+                    if let Some(slot) = accounting.processes.get_mut(pid.get() as usize - 1) {
+                        slot.get_or_insert(Process::new()).peripheral_uj += uj as u64;
+                    }
+                }
+                Opcode::QueryProcess(pid) => {
+                    accounting.sample();
+                    let (cpu, peripherals) = accounting
+                        .processes
+                        .get(pid.get() as usize - 1)
+                        .and_then(|p| *p)
+                        .map(|p| (p.cpu_uj / 1000, p.peripheral_uj / 1000))
+                        .unwrap_or((0, 0));
+                    xous::return_scalar2(envelope.sender, cpu as usize, peripherals as usize)
+                        .expect("couldn't return process energy");
+                }
+                Opcode::QueryResidency(idx) => {
+                    accounting.sample();
+                    let (mhz, ms) = accounting
+                        .points
+                        .get(idx)
+                        .and_then(|p| *p)
+                        .map(|p| (p.mhz, accounting.to_ms(p.residency)))
+                        .unwrap_or((0, 0));
+                    xous::return_scalar2(envelope.sender, mhz, ms)
+                        .expect("couldn't return residency");
+                }
+                Opcode::QueryIdle => {
+                    accounting.sample();
+                    xous::return_scalar2(
+                        envelope.sender,
+                        accounting.to_ms(accounting.idle_ticks),
+                        (accounting.idle_uj / 1000) as usize,
+                    )
+                    .expect("couldn't return idle time");
+                }
+            }
+        } else if let xous::Message::BlockingScalar(_) = envelope.body {
+            // Never leave a client blocked on a message we didn't understand.
+            xous::return_scalar2(envelope.sender, 0, 0).ok();
+        }
+    }
+}
@@ -0,0 +1,103 @@
+use xous::{Message, ScalarMessage};
+
+/// The name the RAM disk registers itself under
+pub const SERVER_NAME: &[u8; 16] = b"xous-ramdisk-srv";
+
+/// The size of a single block, which is also the unit that reads and
+/// writes must be made in
+pub const BLOCK_SIZE: usize = 4096;
+
+/// The largest number of blocks the disk may be formatted with
+pub const MAX_BLOCKS: usize = 512;
+
+#[derive(Debug)]
+pub enum Opcode {
+    /// Discard the contents of the disk and give it a new size.  This must
+    /// be sent as a `BlockingScalar`, and returns `0` on success or an
+    /// `xous::Error` code.
+    Format(usize /* block count */, bool /* compress */),
+
+    /// Get the number of blocks on the disk and the number of bytes of RAM
+    /// being used to store them.  This must be sent as a `BlockingScalar`.
+    Info,
+
+    /// Fill a mutably-lent buffer with the blocks starting at the buffer's
+    /// `offset`
+    Read,
+
+    /// Store the contents of a lent buffer at the buffer's `offset`
+    Write,
+
+    /// Release the storage used by a range of blocks
+    Trim(usize /* first block */, usize /* block count */),
+
+    /// Writes can't report errors directly, since the buffer is simply
+    /// returned.  Instead, the first error since the last `Flush` is kept and
+    /// returned here.  This must be sent as a `BlockingScalar`, and returns
+    /// `0` if every write succeeded or an `xous::Error` code.
+    Flush,
+}
+
+impl<'a> core::convert::TryFrom<&'a Message> for Opcode {
+    type Error = &'static str;
+    fn try_from(message: &'a Message) -> Result<Self, Self::Error> {
+        match message {
+            Message::BlockingScalar(m) => match m.id {
+                1 => Ok(Opcode::Format(m.arg1, m.arg2 != 0)),
+                2 => Ok(Opcode::Info),
+                6 => Ok(Opcode::Flush),
+                _ => Err("unrecognized opcode"),
+            },
+            Message::MutableBorrow(m) => match m.id {
+                3 => Ok(Opcode::Read),
+                _ => Err("unrecognized opcode"),
+            },
+            Message::Borrow(m) => match m.id {
+                4 => Ok(Opcode::Write),
+                _ => Err("unrecognized opcode"),
+            },
+            Message::Scalar(m) => match m.id {
+                5 => Ok(Opcode::Trim(m.arg1, m.arg2)),
+                _ => Err("unrecognized opcode"),
+            },
+            _ => Err("unhandled message type"),
+        }
+    }
+}
+
+impl Into<Message> for Opcode {
+    fn into(self) -> Message {
+        match self {
+            Opcode::Format(blocks, compress) => Message::BlockingScalar(ScalarMessage {
+                id: 1,
+                arg1: blocks,
+                arg2: compress as usize,
+                arg3: 0,
+                arg4: 0,
+            }),
+            Opcode::Info => Message::BlockingScalar(ScalarMessage {
+                id: 2,
+                arg1: 0,
+                arg2: 0,
+                arg3: 0,
+                arg4: 0,
+            }),
+            Opcode::Flush => Message::BlockingScalar(ScalarMessage {
+                id: 6,
+                arg1: 0,
+                arg2: 0,
+                arg3: 0,
+                arg4: 0,
+            }),
+            Opcode::Read => panic!("Read must be sent as a lent buffer"),
+            Opcode::Write => panic!("Write must be sent as a lent buffer"),
+            Opcode::Trim(block, count) => Message::Scalar(ScalarMessage {
+                id: 5,
+                arg1: block,
+                arg2: count,
+                arg3: 0,
+                arg4: 0,
+            }),
+        }
+    }
+}
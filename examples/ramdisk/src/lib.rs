@@ -0,0 +1,101 @@
+#![cfg_attr(target_os = "none", no_std)]
+
+pub mod api;
+pub use api::{BLOCK_SIZE, MAX_BLOCKS};
+
+use xous::{try_send_message, MemoryAddress, MemoryMessage, MemoryRange, Message, CID};
+
+/// Discard the contents of the disk and resize it to `blocks` blocks,
+/// optionally compressing each block as it is written.
+///
+/// # Errors
+///
+/// * **OutOfMemory**: More than `MAX_BLOCKS` blocks were requested
+pub fn format(cid: CID, blocks: usize, compress: bool) -> Result<(), xous::Error> {
+    match try_send_message(cid, api::Opcode::Format(blocks, compress).into())? {
+        xous::Result::Scalar1(0) => Ok(()),
+        xous::Result::Scalar1(e) => Err(xous::Error::from_usize(e)),
+        _ => Err(xous::Error::InternalError),
+    }
+}
+
+/// Return the number of blocks on the disk, and the number of bytes of RAM
+/// currently being used to hold them.
+pub fn info(cid: CID) -> Result<(usize, usize), xous::Error> {
+    match try_send_message(cid, api::Opcode::Info.into())? {
+        xous::Result::Scalar2(blocks, used) => Ok((blocks, used)),
+        _ => Err(xous::Error::InternalError),
+    }
+}
+
+/// Make sure `len` bytes starting at `block` fit on the disk.
+fn check_range(cid: CID, block: usize, len: usize) -> Result<(), xous::Error> {
+    if len == 0 || len % BLOCK_SIZE != 0 {
+        return Err(xous::Error::BadAlignment);
+    }
+    let (blocks, _) = info(cid)?;
+    if block >= blocks || len / BLOCK_SIZE > blocks - block {
+        return Err(xous::Error::BadAddress);
+    }
+    Ok(())
+}
+
+fn block_message(id: usize, block: usize, range: &MemoryRange) -> MemoryMessage {
+    MemoryMessage {
+        id,
+        buf: *range,
+        offset: MemoryAddress::new(block * BLOCK_SIZE),
+        valid: None,
+    }
+}
+
+/// Read the blocks starting at `block` into `buf`, which must be a whole
+/// number of blocks long.
+///
+/// # Errors
+///
+/// * **BadAlignment**: `buf` is not a multiple of `BLOCK_SIZE`
+/// * **BadAddress**: The range runs past the end of the disk
+pub fn read(cid: CID, block: usize, buf: &mut [u8]) -> Result<(), xous::Error> {
+    check_range(cid, block, buf.len())?;
+    let carton = xous::carton::Carton::from_bytes(buf);
+    let range: &MemoryRange = carton.as_ref();
+    try_send_message(cid, Message::MutableBorrow(block_message(3, block, range)))?;
+    let data: &[u8] = carton.as_ref();
+    buf.copy_from_slice(data);
+    Ok(())
+}
+
+/// Write `buf`, which must be a whole number of blocks long, to the disk
+/// starting at `block`.
+///
+/// # Errors
+///
+/// * **BadAlignment**: `buf` is not a multiple of `BLOCK_SIZE`
+/// * **BadAddress**: The range runs past the end of the disk
+pub fn write(cid: CID, block: usize, buf: &[u8]) -> Result<(), xous::Error> {
+    check_range(cid, block, buf.len())?;
+    let carton = xous::carton::Carton::from_bytes(buf);
+    let range: &MemoryRange = carton.as_ref();
+    try_send_message(cid, Message::Borrow(block_message(4, block, range))).map(|_| ())
+}
+
+/// Report whether any write has failed since the last call to `flush()`.
+///
+/// # Errors
+///
+/// * **OutOfMemory**: There was no RAM left to hold a block
+/// * **BadAddress**: A write ran past the end of the disk
+pub fn flush(cid: CID) -> Result<(), xous::Error> {
+    match try_send_message(cid, api::Opcode::Flush.into())? {
+        xous::Result::Scalar1(0) => Ok(()),
+        xous::Result::Scalar1(e) => Err(xous::Error::from_usize(e)),
+        _ => Err(xous::Error::InternalError),
+    }
+}
+
+/// Release the storage behind `count` blocks starting at `block`.  They will
+/// read back as zeroes.
+pub fn trim(cid: CID, block: usize, count: usize) -> Result<(), xous::Error> {
+    try_send_message(cid, api::Opcode::Trim(block, count).into()).map(|_| ())
+}
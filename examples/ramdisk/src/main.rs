@@ -0,0 +1,282 @@
+#![cfg_attr(target_os = "none", no_std)]
+#![cfg_attr(target_os = "none", no_main)]
+
+mod api;
+mod rle;
+use api::{Opcode, BLOCK_SIZE, MAX_BLOCKS};
+
+use core::convert::TryFrom;
+use xous::MemoryRange;
+
+/// Compressed blocks are stored in slots of this size, so that several of
+/// them may share a single page.
+const SLOT_SIZE: usize = 512;
+const SLOTS_PER_PAGE: usize = BLOCK_SIZE / SLOT_SIZE;
+
+#[derive(Copy, Clone)]
+enum Block {
+    /// Nothing has been written here, so the block reads as zeroes
+    Empty,
+
+    /// The block is stored uncompressed and fills an entire page
+    Raw(u16 /* page */),
+
+    /// The block is compressed into one or more consecutive slots of a page
+    Packed {
+        page: u16,
+        slot: u8,
+        slots: u8,
+        len: u16,
+    },
+}
+
+#[derive(Copy, Clone)]
+struct Page {
+    range: MemoryRange,
+
+    /// A bitmask of the slots in this page that are in use
+    slots: u8,
+}
+
+fn slot_mask(slot: usize, count: usize) -> u8 {
+    (((1u16 << count) - 1) << slot) as u8
+}
+
+struct RamDisk {
+    blocks: [Block; MAX_BLOCKS],
+    pages: [Option<Page>; MAX_BLOCKS],
+    block_count: usize,
+    compress: bool,
+
+    /// The first write that failed since the last `Flush`
+    write_error: Option<xous::Error>,
+}
+
+impl RamDisk {
+    fn new() -> RamDisk {
+        RamDisk {
+            blocks: [Block::Empty; MAX_BLOCKS],
+            pages: [None; MAX_BLOCKS],
+            block_count: MAX_BLOCKS,
+            compress: false,
+            write_error: None,
+        }
+    }
+
+    fn bytes_used(&self) -> usize {
+        self.pages.iter().flatten().count() * BLOCK_SIZE
+    }
+
+    fn format(&mut self, block_count: usize, compress: bool) -> Result<(), xous::Error> {
+        if block_count > MAX_BLOCKS {
+            return Err(xous::Error::OutOfMemory);
+        }
+        for block in 0..MAX_BLOCKS {
+            self.trim(block);
+        }
+        self.block_count = block_count;
+        self.compress = compress;
+        self.write_error = None;
+        Ok(())
+    }
+
+    /// Reserve `count` consecutive slots, mapping a new page if none of the
+    /// existing pages have enough room.
+    fn allocate(&mut self, count: usize) -> Result<(u16, usize), xous::Error> {
+        if count < SLOTS_PER_PAGE {
+            for (idx, page) in self.pages.iter_mut().enumerate() {
+                if let Some(page) = page {
+                    for slot in 0..=(SLOTS_PER_PAGE - count) {
+                        let mask = slot_mask(slot, count);
+                        if page.slots & mask == 0 {
+                            page.slots |= mask;
+                            return Ok((idx as u16, slot));
+                        }
+                    }
+                }
+            }
+        }
+
+        let idx = self
+            .pages
+            .iter()
+            .position(|page| page.is_none())
+            .ok_or(xous::Error::OutOfMemory)?;
+        let range = xous::map_memory(
+            None,
+            None,
+            BLOCK_SIZE,
+            xous::MemoryFlags::R | xous::MemoryFlags::W,
+        )?;
+        self.pages[idx] = Some(Page {
+            range,
+            slots: slot_mask(0, count),
+        });
+        Ok((idx as u16, 0))
+    }
+
+    /// Give slots back, unmapping the page once nothing is left in it.
+    fn release(&mut self, page: u16, mask: u8) {
+        if let Some(entry) = self.pages[page as usize].as_mut() {
+            entry.slots &= !mask;
+            if entry.slots == 0 {
+                xous::unmap_memory(entry.range).expect("couldn't free ramdisk page");
+                self.pages[page as usize] = None;
+            }
+        }
+    }
+
+    fn slot_memory(&mut self, page: u16, slot: usize, count: usize) -> &mut [u8] {
+        let range = self.pages[page as usize]
+            .expect("block refers to a missing page")
+            .range;
+        unsafe {
+            core::slice::from_raw_parts_mut(
+                range.as_mut_ptr().add(slot * SLOT_SIZE),
+                count * SLOT_SIZE,
+            )
+        }
+    }
+
+    fn trim(&mut self, block: usize) {
+        let old = match self.blocks.get_mut(block) {
+            Some(entry) => core::mem::replace(entry, Block::Empty),
+            None => return,
+        };
+        match old {
+            Block::Empty => (),
+            Block::Raw(page) => self.release(page, slot_mask(0, SLOTS_PER_PAGE)),
+            Block::Packed {
+                page, slot, slots, ..
+            } => self.release(page, slot_mask(slot as usize, slots as usize)),
+        }
+    }
+
+    fn read(&mut self, block: usize, dest: &mut [u8]) {
+        let entry = if block < self.block_count {
+            self.blocks[block]
+        } else {
+            Block::Empty
+        };
+        match entry {
+            Block::Empty => {
+                for b in dest.iter_mut() {
+                    *b = 0;
+                }
+            }
+            Block::Raw(page) => dest.copy_from_slice(self.slot_memory(page, 0, SLOTS_PER_PAGE)),
+            Block::Packed {
+                page,
+                slot,
+                slots,
+                len,
+            } => {
+                let src = &self.slot_memory(page, slot as usize, slots as usize)[..len as usize];
+                if !rle::decompress(src, dest) {
+                    panic!("ramdisk block {} is corrupt", block);
+                }
+            }
+        }
+    }
+
+    fn write(&mut self, block: usize, src: &[u8]) -> Result<(), xous::Error> {
+        if block >= self.block_count {
+            return Err(xous::Error::BadAddress);
+        }
+        self.trim(block);
+
+        // Blocks of zeroes are what an empty block reads back as anyway.
+        if src.iter().all(|&b| b == 0) {
+            return Ok(());
+        }
+
+        if self.compress {
+            let len = rle::compress(src, None);
+            let slots = (len + SLOT_SIZE - 1) / SLOT_SIZE;
+            if slots < SLOTS_PER_PAGE {
+                let (page, slot) = self.allocate(slots)?;
+                rle::compress(src, Some(self.slot_memory(page, slot, slots)));
+                self.blocks[block] = Block::Packed {
+                    page,
+                    slot: slot as u8,
+                    slots: slots as u8,
+                    len: len as u16,
+                };
+                return Ok(());
+            }
+        }
+
+        let (page, _) = self.allocate(SLOTS_PER_PAGE)?;
+        self.slot_memory(page, 0, SLOTS_PER_PAGE)
+            .copy_from_slice(src);
+        self.blocks[block] = Block::Raw(page);
+        Ok(())
+    }
+}
+
+/// Return the lent buffer of a `Read` or `Write` along with the first block
+/// it refers to.
+fn lent_blocks(message: &xous::MemoryMessage) -> (usize, &mut [u8]) {
+    let first = message.offset.map(|o| o.get()).unwrap_or(0) / BLOCK_SIZE;
+    let buf =
+        unsafe { core::slice::from_raw_parts_mut(message.buf.as_mut_ptr(), message.buf.len()) };
+    (first, buf)
+}
+
+#[xous::xous_main]
+fn xmain() -> ! {
+    let mut disk = RamDisk::new();
+
+    let sid = xous::create_server(api::SERVER_NAME).unwrap();
+    loop {
+        let envelope = xous::receive_message(sid).unwrap();
+        if let Ok(opcode) = Opcode::try_from(&envelope.body) {
+            match opcode {
+                Opcode::Format(blocks, compress) => {
+                    let result = disk
+                        .format(blocks, compress)
+                        .err()
+                        .unwrap_or(xous::Error::NoError);
+                    xous::return_scalar(envelope.sender, result.to_usize())
+                        .expect("couldn't return format result");
+                }
+                Opcode::Info => {
+                    xous::return_scalar2(envelope.sender, disk.block_count, disk.bytes_used())
+                        .expect("couldn't return info");
+                }
+                Opcode::Flush => {
+                    let result = disk.write_error.take().unwrap_or(xous::Error::NoError);
+                    xous::return_scalar(envelope.sender, result.to_usize())
+                        .expect("couldn't return flush result");
+                }
+                // Buffers are returned when `envelope` is dropped.
+                Opcode::Read => {
+                    if let xous::Message::MutableBorrow(m) = &envelope.body {
+                        let (first, buf) = lent_blocks(m);
+                        for (idx, chunk) in buf.chunks_exact_mut(BLOCK_SIZE).enumerate() {
+                            disk.read(first + idx, chunk);
+                        }
+                    }
+                }
+                Opcode::Write => {
+                    if let xous::Message::Borrow(m) = &envelope.body {
+                        let (first, buf) = lent_blocks(m);
+                        for (idx, chunk) in buf.chunks_exact(BLOCK_SIZE).enumerate() {
+                            if let Err(e) = disk.write(first + idx, chunk) {
+                                disk.write_error.get_or_insert(e);
+                            }
+                        }
+                    }
+                }
+                Opcode::Trim(first, count) => {
+                    for block in first..first.saturating_add(count).min(MAX_BLOCKS) {
+                        disk.trim(block);
+                    }
+                }
+            }
+        } else if let xous::Message::BlockingScalar(_) = envelope.body {
+            // Never leave a client blocked on a message we didn't understand.
+            xous::return_scalar(envelope.sender, xous::Error::UnknownError.to_usize()).ok();
+        }
+    }
+}
@@ -0,0 +1,84 @@
+//! A PackBits-style run-length encoding.  Each run starts with a control
+//! byte `c`.  If `c` is less than 128, then `c + 1` literal bytes follow.
+//! Otherwise, the following byte is repeated `c - 125` times.
+
+/// Runs shorter than this are cheaper to store as literals
+const MIN_RUN: usize = 3;
+
+/// The longest run that a single control byte can describe
+const MAX_RUN: usize = 130;
+
+/// The most literal bytes that a single control byte can describe
+const MAX_LITERALS: usize = 128;
+
+fn run_length(src: &[u8]) -> usize {
+    src.iter()
+        .take(MAX_RUN)
+        .take_while(|&&b| b == src[0])
+        .count()
+}
+
+fn put(dest: &mut Option<&mut [u8]>, len: &mut usize, byte: u8) {
+    if let Some(dest) = dest {
+        dest[*len] = byte;
+    }
+    *len += 1;
+}
+
+/// Compress `src` into `dest`, returning the compressed length.  If `dest`
+/// is `None`, nothing is written, which may be used to find out how large
+/// the output will be.  `dest` must be at least that large.
+pub fn compress(src: &[u8], mut dest: Option<&mut [u8]>) -> usize {
+    let mut len = 0;
+    let mut i = 0;
+    while i < src.len() {
+        let run = run_length(&src[i..]);
+        if run >= MIN_RUN {
+            put(&mut dest, &mut len, (run + 125) as u8);
+            put(&mut dest, &mut len, src[i]);
+            i += run;
+            continue;
+        }
+
+        let start = i;
+        while i < src.len() && i - start < MAX_LITERALS && run_length(&src[i..]) < MIN_RUN {
+            i += 1;
+        }
+        put(&mut dest, &mut len, (i - start - 1) as u8);
+        for byte in &src[start..i] {
+            put(&mut dest, &mut len, *byte);
+        }
+    }
+    len
+}
+
+/// Expand `src` into `dest`.  Returns `false` if `src` is corrupt or doesn't
+/// describe exactly `dest.len()` bytes.
+pub fn decompress(src: &[u8], dest: &mut [u8]) -> bool {
+    let mut i = 0;
+    let mut len = 0;
+    while i < src.len() {
+        let control = src[i] as usize;
+        i += 1;
+        if control < 128 {
+            let count = control + 1;
+            if i + count > src.len() || len + count > dest.len() {
+                return false;
+            }
+            dest[len..len + count].copy_from_slice(&src[i..i + count]);
+            i += count;
+            len += count;
+        } else {
+            let count = control - 125;
+            if i >= src.len() || len + count > dest.len() {
+                return false;
+            }
+            for byte in dest[len..len + count].iter_mut() {
+                *byte = src[i];
+            }
+            i += 1;
+            len += count;
+        }
+    }
+    len == dest.len()
+}
@@ -0,0 +1,158 @@
+use xous::{Message, PID, SID};
+
+/// The name the name server registers itself under
+pub const SERVER_NAME: &[u8; 16] = b"xous-name-server";
+
+/// The longest name a server may be registered under, in bytes
+pub const MAX_NAME_LEN: usize = 64;
+
+/// The most names that may be registered at once, including the well-known
+/// ones
+pub const MAX_NAMES: usize = 32;
+
+/// The most access rules that may be installed at once, and the most
+/// processes each rule may allow
+pub const MAX_RULES: usize = 16;
+pub const MAX_RULE_PIDS: usize = 8;
+
+/// Every request is a mutably-lent buffer laid out as a result word written
+/// by the server, followed by a SID, a PID, and the name, padded with
+/// zeroes.
+const RESULT_OFFSET: usize = 0;
+const SID_OFFSET: usize = 4;
+const PID_OFFSET: usize = 20;
+const NAME_OFFSET: usize = 24;
+pub const REQUEST_SIZE: usize = NAME_OFFSET + MAX_NAME_LEN;
+
+/// A non-empty UTF-8 name of up to `MAX_NAME_LEN` bytes
+#[derive(Copy, Clone)]
+pub struct Name {
+    bytes: [u8; MAX_NAME_LEN],
+    len: usize,
+}
+
+impl Name {
+    /// Create a new name, or `None` if `name` is empty or too long.
+    pub fn new(name: &str) -> Option<Name> {
+        if name.is_empty() || name.len() > MAX_NAME_LEN {
+            return None;
+        }
+        let mut bytes = [0u8; MAX_NAME_LEN];
+        bytes[..name.len()].copy_from_slice(name.as_bytes());
+        Some(Name {
+            bytes,
+            len: name.len(),
+        })
+    }
+
+    #[allow(dead_code)]
+    pub fn as_str(&self) -> &str {
+        core::str::from_utf8(&self.bytes[..self.len]).unwrap_or("invalid")
+    }
+}
+
+impl PartialEq for Name {
+    fn eq(&self, other: &Name) -> bool {
+        self.bytes[..self.len] == other.bytes[..other.len]
+    }
+}
+
+/// The contents of a request buffer
+pub struct Request {
+    pub result: xous::Error,
+    pub sid: SID,
+    pub pid: Option<PID>,
+    pub name: Option<Name>,
+}
+
+impl Request {
+    /// Decode a request buffer.  `name` is `None` if the buffer doesn't hold
+    /// a valid name.
+    pub fn decode(buf: &[u8]) -> Option<Request> {
+        if buf.len() < REQUEST_SIZE {
+            return None;
+        }
+        let word = |offset: usize| {
+            let mut word = [0u8; 4];
+            word.copy_from_slice(&buf[offset..offset + 4]);
+            u32::from_le_bytes(word)
+        };
+        let name = &buf[NAME_OFFSET..REQUEST_SIZE];
+        let len = name.iter().position(|&b| b == 0).unwrap_or(name.len());
+        Some(Request {
+            result: xous::Error::from_usize(word(RESULT_OFFSET) as usize),
+            sid: SID::from_u32(
+                word(SID_OFFSET),
+                word(SID_OFFSET + 4),
+                word(SID_OFFSET + 8),
+                word(SID_OFFSET + 12),
+            ),
+            pid: PID::new(word(PID_OFFSET) as u8),
+            name: core::str::from_utf8(&name[..len]).ok().and_then(Name::new),
+        })
+    }
+
+    /// Write the request into the start of `buf`, which must be at least
+    /// `REQUEST_SIZE` bytes long.
+    pub fn encode(&self, buf: &mut [u8]) {
+        let (a0, a1, a2, a3) = self.sid.to_u32();
+        buf[RESULT_OFFSET..RESULT_OFFSET + 4]
+            .copy_from_slice(&(self.result.to_usize() as u32).to_le_bytes());
+        for (idx, word) in [a0, a1, a2, a3].iter().enumerate() {
+            let offset = SID_OFFSET + idx * 4;
+            buf[offset..offset + 4].copy_from_slice(&word.to_le_bytes());
+        }
+        let pid = self.pid.map(|pid| pid.get() as u32).unwrap_or(0);
+        buf[PID_OFFSET..PID_OFFSET + 4].copy_from_slice(&pid.to_le_bytes());
+        let name = &mut buf[NAME_OFFSET..REQUEST_SIZE];
+        for b in name.iter_mut() {
+            *b = 0;
+        }
+        if let Some(n) = &self.name {
+            name[..n.len].copy_from_slice(&n.bytes[..n.len]);
+        }
+    }
+}
+
+/// Every opcode is sent as a mutably-lent `Request`.  The server fills in
+/// `result` with `NoError` or an `xous::Error`, along with `sid` for a
+/// `Lookup`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Opcode {
+    /// Make `name` refer to `sid`.  A name may only be replaced by the
+    /// process that registered it.
+    Register = 1,
+
+    /// Find the SID that `name` refers to.  If an access rule has been
+    /// installed for `name`, only the processes it lists may do this.
+    Lookup = 2,
+
+    /// Forget `name`.  Only the process that registered it may do this.
+    Unregister = 3,
+
+    /// Add `pid` to the processes that may look up `name`.  Once a name
+    /// has a rule, every process the rule doesn't list is refused.  Only
+    /// PID 1 may do this.
+    Allow = 4,
+
+    /// Remove the rule for `name`, letting any process look it up again.
+    /// Only PID 1 may do this.
+    ClearRule = 5,
+}
+
+impl<'a> core::convert::TryFrom<&'a Message> for Opcode {
+    type Error = &'static str;
+    fn try_from(message: &'a Message) -> Result<Self, Self::Error> {
+        match message {
+            Message::MutableBorrow(m) => match m.id {
+                1 => Ok(Opcode::Register),
+                2 => Ok(Opcode::Lookup),
+                3 => Ok(Opcode::Unregister),
+                4 => Ok(Opcode::Allow),
+                5 => Ok(Opcode::ClearRule),
+                _ => Err("unrecognized opcode"),
+            },
+            _ => Err("unhandled message type"),
+        }
+    }
+}
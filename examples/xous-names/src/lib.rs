@@ -0,0 +1,113 @@
+#![cfg_attr(target_os = "none", no_std)]
+
+pub mod api;
+pub use api::{Name, MAX_NAME_LEN};
+
+use api::{Opcode, Request, REQUEST_SIZE};
+use xous::connection::Connection;
+use xous::{CID, PID, SID};
+
+/// Connect to the name server, blocking until it is running.
+pub fn connect_to_names() -> Result<Connection, xous::Error> {
+    Connection::connect(SID::from_bytes(api::SERVER_NAME).unwrap())
+}
+
+/// The SID sent with requests that don't refer to a server
+fn no_sid() -> SID {
+    SID::from_u32(0, 0, 0, 0)
+}
+
+/// Send a request to the name server and return the SID in its reply.
+fn request(
+    cid: CID,
+    opcode: Opcode,
+    name: &str,
+    sid: SID,
+    pid: Option<PID>,
+) -> Result<SID, xous::Error> {
+    let request = Request {
+        // Left in place if the server doesn't understand the request.
+        result: xous::Error::UnknownError,
+        sid,
+        pid,
+        name: Some(Name::new(name).ok_or(xous::Error::InvalidString)?),
+    };
+    let mut buf = [0u8; REQUEST_SIZE];
+    request.encode(&mut buf);
+
+    let mut carton = xous::carton::Carton::from_bytes(&buf);
+    carton.lend_mut(cid, opcode as usize)?;
+    let reply = Request::decode(carton.as_ref()).ok_or(xous::Error::InternalError)?;
+    match reply.result {
+        xous::Error::NoError => Ok(reply.sid),
+        e => Err(e),
+    }
+}
+
+/// Advertise the server `sid` under `name`, so that other processes can
+/// find it with `lookup()`.  Registering a name again from the same process
+/// points it at the new SID.
+///
+/// # Errors
+///
+/// * **InvalidString**: `name` is empty or longer than `MAX_NAME_LEN` bytes
+/// * **ServerExists**: Another process has already registered `name`
+/// * **OutOfMemory**: The name server has no room for any more names
+pub fn register(cid: CID, name: &str, sid: SID) -> Result<(), xous::Error> {
+    request(cid, Opcode::Register, name, sid, None).map(|_| ())
+}
+
+/// Find the SID of the server registered under `name`.
+///
+/// # Errors
+///
+/// * **InvalidString**: `name` is empty or longer than `MAX_NAME_LEN` bytes
+/// * **ServerNotFound**: Nothing has been registered under `name`
+/// * **AccessDenied**: The access rule for `name` doesn't allow this process
+pub fn lookup(cid: CID, name: &str) -> Result<SID, xous::Error> {
+    request(cid, Opcode::Lookup, name, no_sid(), None)
+}
+
+/// Stop advertising `name`.
+///
+/// # Errors
+///
+/// * **InvalidString**: `name` is empty or longer than `MAX_NAME_LEN` bytes
+/// * **ServerNotFound**: Nothing has been registered under `name`
+/// * **AccessDenied**: `name` was registered by another process
+pub fn unregister(cid: CID, name: &str) -> Result<(), xous::Error> {
+    request(cid, Opcode::Unregister, name, no_sid(), None).map(|_| ())
+}
+
+/// Allow `pid` to look up and connect to `name`.  The first rule added
+/// for a name shuts out every process that isn't listed.  Rules may be
+/// installed before the name is registered.  This may only be called from
+/// PID 1.
+///
+/// # Errors
+///
+/// * **InvalidString**: `name` is empty or longer than `MAX_NAME_LEN` bytes
+/// * **AccessDenied**: The caller is not PID 1
+/// * **OutOfMemory**: There is no room for another rule, or the rule for
+///   `name` already lists `MAX_RULE_PIDS` processes
+pub fn allow(cid: CID, name: &str, pid: PID) -> Result<(), xous::Error> {
+    request(cid, Opcode::Allow, name, no_sid(), Some(pid)).map(|_| ())
+}
+
+/// Remove the rule for `name`, so that any process may look it up.  This
+/// may only be called from PID 1.
+///
+/// # Errors
+///
+/// * **InvalidString**: `name` is empty or longer than `MAX_NAME_LEN` bytes
+/// * **AccessDenied**: The caller is not PID 1
+/// * **ServerNotFound**: There is no rule for `name`
+pub fn clear_rule(cid: CID, name: &str) -> Result<(), xous::Error> {
+    request(cid, Opcode::ClearRule, name, no_sid(), None).map(|_| ())
+}
+
+/// Look up the server registered under `name` and connect to it, blocking
+/// until the server is running.
+pub fn connect(cid: CID, name: &str) -> Result<Connection, xous::Error> {
+    Connection::connect(lookup(cid, name)?)
+}
@@ -0,0 +1,205 @@
+#![cfg_attr(target_os = "none", no_std)]
+#![cfg_attr(target_os = "none", no_main)]
+
+mod api;
+use api::{Name, Opcode, Request};
+
+use core::convert::TryFrom;
+use xous::{PID, SID};
+
+/// Servers that are always present, along with the names they may be found
+/// under.  These are registered when the name server starts and can't be
+/// replaced or removed.
+const WELL_KNOWN: [(&str, &[u8; 16]); 5] = [
+    ("names", api::SERVER_NAME),
+    ("log", b"xous-logs-output"),
+    ("graphics", b"graphics-server "),
+    ("metrics", b"xous-metrics-srv"),
+    ("ramdisk", b"xous-ramdisk-srv"),
+];
+
+#[derive(Copy, Clone)]
+struct Entry {
+    name: Name,
+    sid: SID,
+
+    /// The process that registered this name, or `None` for well-known names
+    owner: Option<PID>,
+}
+
+/// The processes that may look up a name
+#[derive(Copy, Clone)]
+struct Rule {
+    name: Name,
+    allowed: [Option<PID>; api::MAX_RULE_PIDS],
+}
+
+struct Names {
+    entries: [Option<Entry>; api::MAX_NAMES],
+    rules: [Option<Rule>; api::MAX_RULES],
+}
+
+impl Names {
+    fn new() -> Names {
+        let mut names = Names {
+            entries: [None; api::MAX_NAMES],
+            rules: [None; api::MAX_RULES],
+        };
+        for (entry, (name, sid)) in names.entries.iter_mut().zip(WELL_KNOWN.iter()) {
+            *entry = Some(Entry {
+                name: Name::new(name).unwrap(),
+                sid: SID::from_bytes(*sid).unwrap(),
+                owner: None,
+            });
+        }
+        names
+    }
+
+    fn find(&mut self, name: &Name) -> Option<&mut Option<Entry>> {
+        self.entries
+            .iter_mut()
+            .find(|e| e.map(|e| e.name == *name).unwrap_or(false))
+    }
+
+    fn register(&mut self, name: Name, sid: SID, owner: PID) -> Result<SID, xous::Error> {
+        let entry = Entry {
+            name,
+            sid,
+            owner: Some(owner),
+        };
+        if let Some(existing) = self.find(&name) {
+            // Well-known names have no owner, so they can never be replaced.
+            if existing.and_then(|e| e.owner) != Some(owner) {
+                return Err(xous::Error::ServerExists);
+            }
+            *existing = Some(entry);
+            return Ok(sid);
+        }
+        let slot = self
+            .entries
+            .iter_mut()
+            .find(|e| e.is_none())
+            .ok_or(xous::Error::OutOfMemory)?;
+        *slot = Some(entry);
+        Ok(sid)
+    }
+
+    fn find_rule(&mut self, name: &Name) -> Option<&mut Option<Rule>> {
+        self.rules
+            .iter_mut()
+            .find(|r| r.map(|r| r.name == *name).unwrap_or(false))
+    }
+
+    /// Names without a rule may be looked up by anyone.
+    fn permitted(&mut self, name: &Name, pid: Option<PID>) -> bool {
+        match self.find_rule(name).and_then(|r| *r) {
+            Some(rule) => pid.is_some() && rule.allowed.contains(&pid),
+            None => true,
+        }
+    }
+
+    fn allow(&mut self, name: Name, pid: PID) -> Result<(), xous::Error> {
+        let slot = match self.find_rule(&name) {
+            Some(slot) => slot,
+            None => {
+                let slot = self
+                    .rules
+                    .iter_mut()
+                    .find(|r| r.is_none())
+                    .ok_or(xous::Error::OutOfMemory)?;
+                *slot = Some(Rule {
+                    name,
+                    allowed: [None; api::MAX_RULE_PIDS],
+                });
+                slot
+            }
+        };
+        let rule = slot.as_mut().unwrap();
+        if rule.allowed.contains(&Some(pid)) {
+            return Ok(());
+        }
+        let free = rule
+            .allowed
+            .iter_mut()
+            .find(|p| p.is_none())
+            .ok_or(xous::Error::OutOfMemory)?;
+        *free = Some(pid);
+        Ok(())
+    }
+
+    fn clear_rule(&mut self, name: Name) -> Result<(), xous::Error> {
+        let slot = self.find_rule(&name).ok_or(xous::Error::ServerNotFound)?;
+        *slot = None;
+        Ok(())
+    }
+
+    fn lookup(&mut self, name: Name, pid: Option<PID>) -> Result<SID, xous::Error> {
+        if !self.permitted(&name, pid) {
+            return Err(xous::Error::AccessDenied);
+        }
+        self.find(&name)
+            .and_then(|e| e.map(|e| e.sid))
+            .ok_or(xous::Error::ServerNotFound)
+    }
+
+    fn unregister(&mut self, name: Name, owner: PID) -> Result<SID, xous::Error> {
+        let existing = self.find(&name).ok_or(xous::Error::ServerNotFound)?;
+        let entry = existing.ok_or(xous::Error::ServerNotFound)?;
+        if entry.owner != Some(owner) {
+            return Err(xous::Error::AccessDenied);
+        }
+        *existing = None;
+        Ok(entry.sid)
+    }
+}
+
+#[xous::xous_main]
+fn xmain() -> ! {
+    let mut names = Names::new();
+
+    let sid = xous::create_server(api::SERVER_NAME).unwrap();
+    loop {
+        let envelope = xous::receive_message(sid).unwrap();
+        let owner = envelope.sender_pid();
+        // Every request is lent, and the buffer is returned when `envelope`
+        // is dropped.  Messages that aren't understood are dropped with the
+        // result left as the client set it.
+        let (opcode, m) = match (Opcode::try_from(&envelope.body), &envelope.body) {
+            (Ok(opcode), xous::Message::MutableBorrow(m)) => (opcode, m),
+            _ => continue,
+        };
+        let buf = unsafe { core::slice::from_raw_parts_mut(m.buf.as_mut_ptr(), m.buf.len()) };
+        let mut request = match Request::decode(buf) {
+            Some(request) => request,
+            None => continue,
+        };
+        let result = match (request.name, opcode, owner) {
+            (None, _, _) => Err(xous::Error::InvalidString),
+            (Some(name), Opcode::Lookup, _) => names.lookup(name, owner),
+            // Names belong to the process that registered them, and rules
+            // may only be installed by PID 1, so changing anything requires
+            // knowing who is asking.
+            (Some(_), _, None) => Err(xous::Error::AccessDenied),
+            (Some(name), Opcode::Register, Some(pid)) => names.register(name, request.sid, pid),
+            (Some(name), Opcode::Unregister, Some(pid)) => names.unregister(name, pid),
+            (Some(_), Opcode::Allow, Some(pid)) | (Some(_), Opcode::ClearRule, Some(pid))
+                if pid.get() != 1 =>
+            {
+                Err(xous::Error::AccessDenied)
+            }
+            (Some(name), Opcode::Allow, Some(_)) => match request.pid {
+                Some(allowed) => names.allow(name, allowed).map(|_| request.sid),
+                None => Err(xous::Error::InvalidPID),
+            },
+            (Some(name), Opcode::ClearRule, Some(_)) => names.clear_rule(name).map(|_| request.sid),
+        };
+        match result {
+            Ok(sid) => {
+                request.result = xous::Error::NoError;
+                request.sid = sid;
+            }
+            Err(e) => request.result = e,
+        }
+        request.encode(buf);
+    }
+}
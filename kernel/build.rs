@@ -11,6 +11,9 @@ fn main() {
 
     let target_os = target.split('-').nth(2).unwrap_or("none");
 
+    // Declare the "baremetal" config setting, whether or not it gets set.
+    println!("cargo:rustc-check-cfg=cfg(baremetal)");
+
     // If we're not running on a desktop-class operating system, emit the "baremetal"
     // config setting. This will enable software to do tasks such as
     // managing memory.
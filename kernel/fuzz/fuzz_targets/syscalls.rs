@@ -0,0 +1,9 @@
+//! Run arbitrary syscalls against a fresh hosted kernel.  See
+//! `kernel::fuzz` for how the input is decoded.
+
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    kernel::fuzz::fuzz_syscall(data);
+});
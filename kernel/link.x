@@ -45,6 +45,12 @@ SECTIONS
     *(.sdata .sdata.* .sdata2 .sdata2.*);
     *(.data .data.*);
     . = ALIGN(4);
+
+    /* Counters for `cover!()`, which are only present with the `coverage` feature */
+    __start_xous_coverage = .;
+    KEEP(*(xous_coverage));
+    __stop_xous_coverage = .;
+    . = ALIGN(4);
     _edata = .;
   } > REGION_DATA AT > REGION_RODATA
 
@@ -1,29 +1,56 @@
 pub mod irq;
 pub mod mem;
+pub mod mmu;
 pub mod process;
+pub mod replay;
 pub mod syscall;
 
 use std::cell::RefCell;
 use std::convert::TryInto;
 use std::env;
-use std::io::Read;
-use std::net::{IpAddr, Ipv4Addr, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
-use std::sync::mpsc::{channel, Receiver, Sender};
+use std::io::{Read, Write};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender, TryRecvError};
 use std::thread_local;
 
 use crate::arch::process::Process;
 use crate::services::SystemServices;
+use crate::syscall::SysCallOutcome;
 
-use xous_kernel::{MemoryAddress, ProcessInit, ProcessKey, Result, SysCall, ThreadInit, PID, TID};
+use xous_kernel::arch::compress;
+use xous_kernel::arch::program::{self, Program};
+use xous_kernel::arch::shared::{self, Region, SharedMemory};
+use xous_kernel::arch::transport::{self, Address, Listener, Stream};
+use xous_kernel::{
+    Capabilities, MemoryAddress, ProcessInit, ProcessKey, Quotas, RebootMode, Result, ScrubLevel,
+    SysCall, ThreadInit, PID, TID,
+};
 
 enum ThreadMessage {
     SysCall(PID, TID, SysCall),
-    NewConnection(TcpStream, ProcessKey),
+    NewConnection(
+        Stream,
+        ProcessKey,
+        bool, /* compress */
+        Option<SharedMemory>,
+    ),
+
+    /// The host asked the kernel to stop
+    Shutdown,
+
+    /// A line was typed at the debug console
+    Console(String),
+
+    /// A thread ran to completion and won't make any more syscalls
+    ThreadFinished(PID, TID),
 }
 
 #[derive(Debug)]
 enum NewPidMessage {
     NewPid(PID),
+
+    /// No process is waiting for a connection with the key that was given
+    Refused,
 }
 
 #[derive(Debug)]
@@ -31,9 +58,47 @@ enum ExitMessage {
     Exit,
 }
 
-thread_local!(static NETWORK_LISTEN_ADDRESS: RefCell<SocketAddr> = RefCell::new(SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 0)));
-thread_local!(static SEND_ADDR: RefCell<Option<Sender<SocketAddr>>> = RefCell::new(None));
-thread_local!(static PID1_KEY: RefCell<[u8; 16]> = RefCell::new([0u8; 16]));
+thread_local!(static NETWORK_LISTEN_ADDRESS: RefCell<Address> = const { RefCell::new(Address::Tcp(SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 0))) });
+thread_local!(static SEND_ADDR: RefCell<Option<Sender<Address>>> = const { RefCell::new(None) });
+thread_local!(static PID1_KEY: RefCell<[u8; 16]> = const { RefCell::new([0u8; 16]) });
+thread_local!(static CONSOLE_INPUT: RefCell<Option<Receiver<String>>> = const { RefCell::new(None) });
+thread_local!(static REMOTE_KEYS: RefCell<Option<Vec<[u8; 16]>>> = const { RefCell::new(None) });
+
+/// The keys of processes that run elsewhere and connect over the network,
+/// which are given in hex in `XOUS_REMOTE_KEYS`, separated by commas.
+fn remote_keys() -> Vec<[u8; 16]> {
+    REMOTE_KEYS
+        .with(|rk| rk.borrow_mut().take())
+        .unwrap_or_else(|| {
+            env::var("XOUS_REMOTE_KEYS")
+                .map(|keys| {
+                    keys.split(',')
+                        .filter(|key| !key.trim().is_empty())
+                        .map(|key| {
+                            let mut process_key = [0u8; 16];
+                            hex::decode_to_slice(key.trim(), &mut process_key)
+                                .expect("invalid key in XOUS_REMOTE_KEYS");
+                            process_key
+                        })
+                        .collect()
+                })
+                .unwrap_or_default()
+        })
+}
+
+/// There's no tick timer in a hosted environment, so ticks are counted from
+/// the time the kernel started, one every millisecond.
+const TICK_NANOS: u64 = 1_000_000;
+thread_local!(static BOOT_TIME: std::cell::Cell<u64> = const { std::cell::Cell::new(0) });
+
+thread_local!(
+    /// The time on the kernel's virtual clock, or `None` if the kernel
+    /// follows the host's clock.  The virtual clock only moves when every
+    /// process is waiting for something, or when it's advanced from the
+    /// debug console, so that timers fire at the same points in a test on
+    /// every run.
+    static VIRTUAL_CLOCK: std::cell::Cell<Option<u64>> = const { std::cell::Cell::new(None) }
+);
 
 #[cfg(test)]
 pub fn set_pid1_key(new_key: [u8; 16]) {
@@ -42,22 +107,42 @@ pub fn set_pid1_key(new_key: [u8; 16]) {
 
 /// Set the network address for this particular thread.
 #[cfg(test)]
-pub fn set_listen_address(new_address: &SocketAddr) {
+pub fn set_listen_address(new_address: &Address) {
     NETWORK_LISTEN_ADDRESS.with(|nla| {
         let mut address = nla.borrow_mut();
-        *address = *new_address;
+        *address = new_address.clone();
     });
 }
 
+/// Register processes that will connect with `keys`, rather than reading
+/// them from `XOUS_REMOTE_KEYS`, which every test shares.
+#[cfg(test)]
+pub fn set_remote_keys(keys: Vec<[u8; 16]>) {
+    REMOTE_KEYS.with(|rk| *rk.borrow_mut() = Some(keys));
+}
+
+/// Run the kernel on a virtual clock, rather than reading
+/// `XOUS_VIRTUAL_TIME`, which every test shares.
+#[cfg(test)]
+pub fn set_virtual_clock() {
+    VIRTUAL_CLOCK.with(|vc| vc.set(Some(0)));
+}
+
+/// Have the debug console read lines from `input` rather than stdin, which
+/// every test shares.
+#[cfg(test)]
+pub fn set_console_input(input: Receiver<String>) {
+    CONSOLE_INPUT.with(|ci| *ci.borrow_mut() = Some(input));
+}
+
 /// Set the network address for this particular thread.
 #[allow(dead_code)]
-pub fn set_send_addr(send_addr: Sender<SocketAddr>) {
+pub fn set_send_addr(send_addr: Sender<Address>) {
     SEND_ADDR.with(|sa| {
         *sa.borrow_mut() = Some(send_addr);
     });
 }
 
-#[cfg(not(test))]
 fn generate_pid_key() -> [u8; 16] {
     use rand::{thread_rng, Rng};
     let mut process_key = [0u8; 16];
@@ -70,18 +155,26 @@ fn generate_pid_key() -> [u8; 16] {
 
 /// Each client gets its own connection and its own thread, which is handled here.
 fn handle_connection(
-    conn: TcpStream,
+    conn: Stream,
     pid: PID,
+    compress: bool,
+    shared: Option<(Region, usize /* kernel base */)>,
     chn: Sender<ThreadMessage>,
     should_exit: std::sync::Arc<core::sync::atomic::AtomicBool>,
 ) {
     enum ServerMessage {
         Exit,
+        ThreadFinished(TID),
         ServerPacket([usize; 9]),
         ServerPacketWithData([usize; 9], Vec<u8>),
     }
 
-    fn conn_thread(mut conn: TcpStream, sender: Sender<ServerMessage>) {
+    fn conn_thread(
+        mut conn: Stream,
+        compress: bool,
+        shared: Option<(Region, usize)>,
+        sender: Sender<ServerMessage>,
+    ) {
         loop {
             let mut raw_data = [0u8; 9 * std::mem::size_of::<usize>()];
             if let Err(_e) = conn.read_exact(&mut raw_data) {
@@ -101,18 +194,68 @@ fn handle_connection(
                 *word = usize::from_le_bytes(bytes.try_into().unwrap());
             }
 
+            if packet_data[1] == xous_kernel::arch::THREAD_FINISHED {
+                sender
+                    .send(ServerMessage::ThreadFinished(packet_data[0]))
+                    .unwrap();
+                continue;
+            }
+
+            // Memory lent from the process's shared region arrives as just
+            // its address there, which is the same memory at another address
+            // in the kernel.
+            if let Some((region, kernel_base)) = shared {
+                let lent = xous_kernel::SysCall::from_args(
+                    packet_data[1],
+                    packet_data[2],
+                    packet_data[3],
+                    packet_data[4],
+                    packet_data[5],
+                    packet_data[6],
+                    packet_data[7],
+                    packet_data[8],
+                )
+                .map(|call| region.lends(&call))
+                .unwrap_or(false);
+                if lent {
+                    packet_data[5] = packet_data[5] - region.base + kernel_base;
+                    sender
+                        .send(ServerMessage::ServerPacket(packet_data))
+                        .unwrap();
+                    continue;
+                }
+            }
+
             if (packet_data[1] == xous_kernel::syscall::SysCallNumber::SendMessage as _
-                || packet_data[1] == xous_kernel::syscall::SysCallNumber::TrySendMessage as _)
+                || packet_data[1] == xous_kernel::syscall::SysCallNumber::TrySendMessage as _
+                || packet_data[1] == xous_kernel::syscall::SysCallNumber::CallMessage as _)
                 && (packet_data[3] == 1 || packet_data[3] == 2 || packet_data[3] == 3)
             {
                 let mut v = vec![0; packet_data[6]];
-                if conn.read_exact(&mut v).is_err() {
+                if compress::read_payload(&mut conn, &mut v, compress).is_err() {
                     sender.send(ServerMessage::Exit).ok();
                     return;
                 }
                 sender
                     .send(ServerMessage::ServerPacketWithData(packet_data, v))
                     .unwrap();
+            } else if packet_data[1] == xous_kernel::syscall::SysCallNumber::CreateProcess as _
+                && packet_data[8] != 0
+            {
+                // A host program to start follows as a command block.
+                let mut block = vec![0; 4];
+                let read = conn.read_exact(&mut block).and_then(|_| {
+                    let len = program::block_len(&block).unwrap();
+                    block.resize(len, 0);
+                    conn.read_exact(&mut block[4..])
+                });
+                if read.is_err() {
+                    sender.send(ServerMessage::Exit).ok();
+                    return;
+                }
+                sender
+                    .send(ServerMessage::ServerPacketWithData(packet_data, block))
+                    .unwrap();
             } else {
                 sender
                     .send(ServerMessage::ServerPacket(packet_data))
@@ -126,7 +269,7 @@ fn handle_connection(
     std::thread::Builder::new()
         .name(format!("PID {}: client connection thread", pid))
         .spawn(move || {
-            conn_thread(conn, conn_sender);
+            conn_thread(conn, compress, shared, conn_sender);
         })
         .unwrap();
 
@@ -145,6 +288,9 @@ fn handle_connection(
     for msg in receiver {
         match msg {
             ServerMessage::Exit => break,
+            ServerMessage::ThreadFinished(tid) => chn
+                .send(ThreadMessage::ThreadFinished(pid, tid))
+                .expect("couldn't report finished thread"),
             ServerMessage::ServerPacket(pkt) => {
                 let thread_id = pkt[0];
                 let call = xous_kernel::SysCall::from_args(
@@ -183,7 +329,8 @@ fn handle_connection(
                         // );
                         match call {
                             SysCall::SendMessage(ref _cid, ref mut envelope)
-                            | SysCall::TrySendMessage(ref _cid, ref mut envelope) => {
+                            | SysCall::TrySendMessage(ref _cid, ref mut envelope)
+                            | SysCall::CallMessage(ref _cid, ref mut envelope) => {
                                 match envelope {
                                     xous_kernel::Message::MutableBorrow(msg)
                                     | xous_kernel::Message::Borrow(msg)
@@ -215,6 +362,10 @@ fn handle_connection(
                                     xous_kernel::Message::Scalar(_) | xous_kernel::Message::BlockingScalar(_) => (),
                                 }
                             }
+                            SysCall::CreateProcess(ref mut init) => {
+                                let block = Box::into_raw(data.into_boxed_slice());
+                                init.command = MemoryAddress::new(block as *mut u8 as usize);
+                            }
                             _ => panic!("unsupported message type"),
                         }
                         chn.send(ThreadMessage::SysCall(pid, thread_id, call))
@@ -234,56 +385,119 @@ fn handle_connection(
 }
 
 fn listen_thread(
-    listen_addr: SocketAddr,
+    listen_addr: Address,
     chn: Sender<ThreadMessage>,
-    mut local_addr_sender: Option<Sender<SocketAddr>>,
+    local_addr_sender: Sender<Address>,
     new_pid_channel: Receiver<NewPidMessage>,
     exit_channel: Receiver<ExitMessage>,
 ) {
     let should_exit = std::sync::Arc::new(core::sync::atomic::AtomicBool::new(false));
 
     // println!("KERNEL(1): Starting Xous server on {}...", listen_addr);
-    let listener = TcpListener::bind(listen_addr).unwrap_or_else(|e| {
+    let listener = Listener::bind(&listen_addr).unwrap_or_else(|e| {
         panic!("Unable to create server: {}", e);
     });
-    // Notify the host what our kernel address is, if a listener exists.
-    if let Some(las) = local_addr_sender.take() {
-        las.send(listener.local_addr().unwrap()).unwrap();
-    }
+    // Tell the kernel where it's listening.
+    local_addr_sender
+        .send(listener.local_addr().unwrap())
+        .unwrap();
 
     let mut clients = vec![];
 
     fn accept_new_connection(
-        mut conn: TcpStream,
+        mut conn: Stream,
         chn: &Sender<ThreadMessage>,
         new_pid_channel: &Receiver<NewPidMessage>,
-        clients: &mut Vec<(std::thread::JoinHandle<()>, TcpStream)>,
+        clients: &mut Vec<(std::thread::JoinHandle<()>, Stream)>,
         should_exit: &std::sync::Arc<core::sync::atomic::AtomicBool>,
     ) -> bool {
         let thr_chn = chn.clone();
 
-        // Read the challenge access key from the client
+        // Read the challenge access key from the client, followed by what it
+        // supports.  Anything may connect to a kernel listening on a network,
+        // so a client that hangs up part way through only loses its own
+        // connection.
         let mut access_key = [0u8; 16];
-        conn.read_exact(&mut access_key).unwrap();
+        let mut capabilities = [0u8; 4];
+        if let Err(e) = conn
+            .read_exact(&mut access_key)
+            .and_then(|_| conn.read_exact(&mut capabilities))
+        {
+            eprintln!("KERNEL: new connection hung up early: {}", e);
+            return false;
+        }
+        let offered = u32::from_le_bytes(capabilities);
+
+        // Packets are made of whole words, so a process with words of a
+        // different size couldn't be understood.
+        if (offered ^ transport::word_size_capability()) & transport::CAP_WORDS_64 != 0 {
+            eprintln!("KERNEL: refused a process whose words are a different size");
+            conn.shutdown().ok();
+            return false;
+        }
+
+        // A process on the same machine offers memory to share, which the
+        // kernel uses if it's able to map it too.
+        let mut shared_memory = None;
+        if offered & shared::CAP_SHARED_MEMORY != 0 {
+            match shared::read_descriptor(&mut conn) {
+                Ok((path, region)) => {
+                    shared_memory = SharedMemory::open(&path, region.len)
+                        .ok()
+                        .map(|memory| (memory, region));
+                }
+                Err(e) => {
+                    eprintln!("KERNEL: new connection hung up early: {}", e);
+                    return false;
+                }
+            }
+        }
+
+        // Agree on whichever of the client's capabilities we also support.
+        let mut supported = compress::capabilities() | transport::word_size_capability();
+        if shared_memory.is_some() {
+            supported |= shared::capabilities();
+        }
+        let agreed = offered & supported;
+        let compress = agreed & compress::CAP_COMPRESSION != 0;
+        let shared = shared_memory
+            .as_ref()
+            .map(|(memory, region)| (*region, memory.region().base));
 
         // Spawn a new process. This process will start out in the "Allocated" state.
         chn.send(ThreadMessage::NewConnection(
             conn.try_clone()
                 .expect("couldn't make a copy of the network connection for the kernel"),
             ProcessKey::new(access_key),
+            compress,
+            shared_memory.map(|(memory, _)| memory),
         ))
         .expect("couldn't request a new PID");
 
-        // The kernel will immediately respond with a new PID.
-        let NewPidMessage::NewPid(new_pid) = new_pid_channel
+        // The kernel will immediately respond with a new PID.  The client
+        // only hears back once its key has been accepted.
+        let new_pid = match new_pid_channel
             .recv()
-            .expect("couldn't receive message from main thread");
+            .expect("couldn't receive message from main thread")
+        {
+            NewPidMessage::NewPid(new_pid) => new_pid,
+            NewPidMessage::Refused => {
+                eprintln!("KERNEL: refused a connection with an unknown key");
+                conn.shutdown().ok();
+                return false;
+            }
+        };
+        if conn.write_all(&agreed.to_le_bytes()).is_err() {
+            // The kernel notices the process is gone once the connection
+            // thread fails to read from it.
+            eprintln!("KERNEL({}): couldn't answer new connection", new_pid);
+        }
         // println!("KERNEL({}): New client connected from {}", new_pid, _addr);
         let conn_copy = conn.try_clone().expect("couldn't duplicate connection");
         let should_exit = should_exit.clone();
         let jh = std::thread::Builder::new()
             .name(format!("kernel PID {} listener", new_pid))
-            .spawn(move || handle_connection(conn, new_pid, thr_chn, should_exit))
+            .spawn(move || handle_connection(conn, new_pid, compress, shared, thr_chn, should_exit))
             .expect("couldn't spawn listen thread");
         clients.push((jh, conn_copy));
         false
@@ -291,19 +505,18 @@ fn listen_thread(
 
     fn exit_server(
         should_exit: std::sync::Arc<core::sync::atomic::AtomicBool>,
-        clients: Vec<(std::thread::JoinHandle<()>, TcpStream)>,
+        clients: Vec<(std::thread::JoinHandle<()>, Stream)>,
     ) {
         should_exit.store(true, core::sync::atomic::Ordering::Relaxed);
         for (jh, conn) in clients {
-            use std::net::Shutdown;
-            conn.shutdown(Shutdown::Both).ok();
+            conn.shutdown().ok();
             jh.join().expect("couldn't join client thread");
         }
     }
 
     // Use `listener` in a nonblocking setup so that we can exit when doing tests
     enum ClientMessage {
-        NewConnection(TcpStream),
+        NewConnection(Stream),
         Exit,
     };
     let (sender, receiver) = channel();
@@ -315,14 +528,16 @@ fn listen_thread(
     // `listener.accept()` has no way to break, so we must put it in nonblocking mode
     listener.set_nonblocking(true).unwrap();
 
-    std::thread::Builder::new()
+    let accept_thread = std::thread::Builder::new()
         .name("kernel accept thread".to_owned())
         .spawn(move || loop {
             match listener.accept() {
-                Ok((conn, _addr)) => {
-                    conn.set_nonblocking(false).unwrap();
+                Ok(conn) => {
                     tcp_sender.send(ClientMessage::NewConnection(conn)).unwrap();
                 }
+                Err(e) if e.kind() == std::io::ErrorKind::ConnectionAborted => {
+                    eprintln!("KERNEL: couldn't set up a new connection: {}", e);
+                }
                 Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
                     match shutdown_listener_receiver
                         .recv_timeout(std::time::Duration::from_millis(500))
@@ -376,54 +591,374 @@ fn listen_thread(
         }
     }
     shutdown_listener.send(()).unwrap();
+    // Wait for the listener to be dropped, so that a UNIX socket is removed
+    // before the kernel exits.
+    accept_thread.join().ok();
     exit_server(should_exit, clients);
 }
 
+/// Get the number of nanoseconds since the Unix epoch, matching the
+/// timestamps that processes see.  A replayed kernel gets the time from the
+/// recording instead.
+pub fn timestamp() -> u64 {
+    replay::timestamp(clock)
+}
+
+/// Read the virtual clock if there is one, or the host's clock otherwise.
+fn clock() -> u64 {
+    VIRTUAL_CLOCK
+        .with(|vc| vc.get())
+        .unwrap_or_else(host_timestamp)
+}
+
+/// Move the virtual clock forward by `ms` milliseconds, and fire whatever
+/// timers are due.
+///
+/// # Errors
+///
+/// * **UnhandledSyscall**: The kernel follows the host's clock
+pub fn advance_clock(ms: u64) -> core::result::Result<(), xous_kernel::Error> {
+    let now = VIRTUAL_CLOCK
+        .with(|vc| vc.get())
+        .ok_or(xous_kernel::Error::UnhandledSyscall)?;
+    set_clock(now.saturating_add(ms.saturating_mul(TICK_NANOS)));
+    crate::timers::check();
+    Ok(())
+}
+
+/// Set the virtual clock to `time`, which is never earlier than it already
+/// reads.
+fn set_clock(time: u64) {
+    VIRTUAL_CLOCK.with(|vc| vc.set(vc.get().map(|now| now.max(time))));
+}
+
+/// Read the host's clock, for waits that don't affect the kernel's state.
+fn host_timestamp() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or_default()
+}
+
+/// There's no cycle counter to read in hosted mode, so count nanoseconds
+/// instead.
+#[cfg(feature = "syscall-latency")]
+pub fn cycles() -> u64 {
+    timestamp()
+}
+
+thread_local!(
+    /// How the kernel should start again once `kmain()` returns, if it
+    /// should at all
+    static REBOOT: std::cell::Cell<Option<RebootMode>> = const { std::cell::Cell::new(None) }
+);
+
+/// Whether the system is able to reboot the way `mode` asks.  A hosted
+/// kernel can start over, but there's no loader to wait for an update.
+///
+/// # Errors
+///
+/// * **UnhandledSyscall**: `mode` is `RebootMode::Update`
+pub fn check_reboot(mode: RebootMode) -> core::result::Result<(), xous_kernel::Error> {
+    match mode {
+        RebootMode::Warm | RebootMode::Cold => Ok(()),
+        RebootMode::Update => Err(xous_kernel::Error::UnhandledSyscall),
+    }
+}
+
+/// Have the kernel start again as `mode` asks, once everything has been
+/// torn down and `kmain()` returns.
+pub fn reboot(mode: RebootMode) {
+    REBOOT.with(|reboot| reboot.set(Some(mode)));
+}
+
+/// How the kernel should start again now that `kmain()` has returned, or
+/// `None` if it was shut down.
+pub fn reboot_mode() -> Option<RebootMode> {
+    REBOOT.with(|reboot| reboot.get())
+}
+
+/// Run the kernel's program again from scratch, as a cold reboot does, so
+/// that nothing at all is left over from before.
+pub fn restart() -> ! {
+    let program = env::current_exe().expect("couldn't find the kernel's program");
+    let mut command = std::process::Command::new(program);
+    command.args(env::args_os().skip(1));
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        panic!("couldn't restart the kernel: {}", command.exec());
+    }
+    #[cfg(not(unix))]
+    {
+        let status = command.status().expect("couldn't restart the kernel");
+        std::process::exit(status.code().unwrap_or(1));
+    }
+}
+
+thread_local!(
+    /// Whether `wake()` was called before the system got around to
+    /// suspending, the way a wake interrupt stays pending until the core
+    /// goes to sleep
+    static WAKE: std::cell::Cell<bool> = const { std::cell::Cell::new(false) }
+);
+
+/// Whether the system is able to suspend.  A hosted kernel suspends by
+/// leaving every process waiting until it's woken from the debug console.
+pub fn check_suspend() -> core::result::Result<(), xous_kernel::Error> {
+    Ok(())
+}
+
+/// Suspend now that every process is waiting.  Returns `true` if a wake was
+/// already pending, or `false` if the system stays asleep until `wake()`.
+pub fn suspend() -> bool {
+    WAKE.with(|wake| wake.replace(false))
+}
+
+/// Wake the system from a suspend, as a wake interrupt would on real
+/// hardware.  If it hasn't suspended yet, it wakes as soon as it does.
+///
+/// # Errors
+///
+/// * **InvalidSyscall**: No suspend is pending
+pub fn wake() -> core::result::Result<(), xous_kernel::Error> {
+    if crate::suspend::suspended() {
+        crate::suspend::resume();
+    } else if crate::suspend::pending() {
+        WAKE.with(|wake| wake.set(true));
+    } else {
+        return Err(xous_kernel::Error::InvalidSyscall);
+    }
+    Ok(())
+}
+
+/// The number of clock domains a hosted kernel pretends to have
+const CLOCK_DOMAINS: usize = 8;
+
+/// The fastest and slowest a hosted kernel pretends the CPU can run, in Hz
+const CPU_MAX_HZ: usize = 100_000_000;
+const CPU_MIN_HZ: usize = 1_000_000;
+
+/// How many clock domains there are to gate.
+pub fn clock_domains() -> usize {
+    CLOCK_DOMAINS
+}
+
+/// Gate clock `domain`, or let it run.  Nothing is actually clocked in a
+/// hosted environment, so there's nothing to do.
+pub fn set_clock_domain(_domain: usize, _running: bool) {}
+
+/// Pretend to run the CPU at least `hz` Hz, or as slowly as it can if `hz`
+/// is `0`, and return how fast it runs now.
+pub fn set_cpu_frequency(hz: usize) -> usize {
+    hz.clamp(CPU_MIN_HZ, CPU_MAX_HZ)
+}
+
+/// Timestamps count nanoseconds in hosted mode.
+pub fn timebase() -> Option<usize> {
+    Some(1_000_000_000)
+}
+
+/// The number of milliseconds since the kernel started.
+pub fn ticks() -> Option<u64> {
+    Some(timestamp().saturating_sub(BOOT_TIME.with(|boot| boot.get())) / TICK_NANOS)
+}
+
+/// Fill `buf` with randomness from the host, which stands in for a
+/// hardware TRNG.
+pub fn trng_read(buf: &mut [u8]) -> bool {
+    use rand::RngCore;
+    replay::random(buf, |buf| rand::thread_rng().fill_bytes(buf));
+    true
+}
+
+/// The number of harts the scheduler keeps state for.
+pub const MAX_HARTS: usize = 1;
+
+/// The hart the kernel is currently running on.  Hosted kernels handle
+/// every syscall on a single thread.
+pub fn current_hart() -> usize {
+    0
+}
+
+/// Switch to a process that just connected, which moves it from `Setup(_)`
+/// to `Running(0)`.  Note that in this system, multiple processes can be
+/// active at once.  This is similar to having one core for each process.
+fn start_process(pid: PID) {
+    SystemServices::with_mut(|ss| {
+        ss.create_thread(pid, ThreadInit {})?;
+        ss.switch_to_thread(pid, None)
+    })
+    .unwrap();
+}
+
+/// Start the host program in the command block at `command`, which the
+/// connection thread copied out of the process that made `CreateProcess`,
+/// as process `pid`.
+pub fn start_command(
+    pid: PID,
+    command: MemoryAddress,
+) -> core::result::Result<(), xous_kernel::Error> {
+    // A replay starts no programs, and its addresses belonged to the kernel
+    // that made the recording.
+    if replay::replaying() {
+        return Ok(());
+    }
+    let block = unsafe {
+        let len = program::block(command).len();
+        Box::from_raw(core::ptr::slice_from_raw_parts_mut(
+            command.get() as *mut u8,
+            len,
+        ))
+    };
+    let program = Program::decode(&block).ok_or(xous_kernel::Error::InvalidSyscall)?;
+    crate::arch::process::start_program(pid, &program)
+}
+
+/// Handle a syscall within the Xous kernel, turning an error into a
+/// response.
+fn handle_syscall(pid: PID, tid: TID, call: SysCall) -> SysCallOutcome {
+    crate::syscall::handle(pid, tid, call)
+        .unwrap_or_else(|e| SysCallOutcome::Return(Result::Error(e)))
+}
+
+/// Terminate a process that can't be sent its response, on the assumption
+/// that it's dead.
+fn terminate_unreachable(pid: PID, tid: TID) {
+    replay::syscall(pid, tid, &SysCall::TerminateProcess);
+    handle_syscall(pid, tid, SysCall::TerminateProcess);
+}
+
 /// The idle function is run when there are no directly-runnable processes
 /// that kmain can activate. In a hosted environment,this is the primary
 /// thread that handles network communications, and this function never returns.
+/// It sleeps on the message channel between syscalls rather than spinning,
+/// which plays the part of `wfi` on real hardware.
 pub fn idle() -> bool {
+    replay::init();
+    // A clock that was set up ahead of time keeps its starting point, so
+    // that every run starts at the same time.
+    if env::var("XOUS_VIRTUAL_TIME").is_ok() && VIRTUAL_CLOCK.with(|vc| vc.get()).is_none() {
+        VIRTUAL_CLOCK.with(|vc| vc.set(Some(host_timestamp())));
+    }
+    BOOT_TIME.with(|boot| boot.set(timestamp()));
+    crate::entropy::init();
+
     // Start listening.
     let (sender, message_receiver) = channel();
     let (new_pid_sender, new_pid_receiver) = channel();
     let (exit_sender, exit_receiver) = channel();
+    #[cfg(not(test))]
+    let signal_sender = sender.clone();
 
     // Allocate PID1 with the key we were passed.
     let pid1_key = PID1_KEY.with(|p1k| *p1k.borrow());
     let pid1_init = ProcessInit {
         key: ProcessKey::new(pid1_key),
+        capabilities: Capabilities::all(),
+        quotas: Quotas::unlimited(),
+        command: None,
     };
     let pid1 = SystemServices::with_mut(|ss| ss.create_process(pid1_init)).unwrap();
     assert_eq!(pid1.get(), 1);
 
+    // A replay has no processes attached, so there's nothing to listen for.
+    if replay::replaying() {
+        replay::run(pid1);
+        return false;
+    }
+
     let listen_addr = env::var("XOUS_LISTEN_ADDR")
-        .map(|s| {
-            s.to_socket_addrs()
-                .expect("invalid server address")
-                .next()
-                .expect("unable to resolve server address")
-        })
-        .unwrap_or_else(|_| NETWORK_LISTEN_ADDRESS.with(|nla| *nla.borrow()));
+        .map(|s| Address::parse(&s).expect("invalid server address"))
+        .unwrap_or_else(|_| NETWORK_LISTEN_ADDRESS.with(|nla| nla.borrow().clone()));
+
+    // Processes on other machines are started there, pointed at this kernel
+    // with `XOUS_SERVER` and given one of these keys, so they get a PID now
+    // just as the processes the kernel starts itself do.
+    crate::arch::process::set_current_pid(pid1);
+    for key in remote_keys() {
+        let init = ProcessInit {
+            key: ProcessKey::new(key),
+            capabilities: Capabilities::all(),
+            quotas: Quotas::unlimited(),
+            command: None,
+        };
+        let pid = SystemServices::with_mut(|ss| ss.create_process(init))
+            .expect("couldn't register remote process");
+        replay::event(&format!("SPAWN {}", pid));
+        println!("KERNEL: PID {} is waiting for a remote process", pid);
+    }
 
+    // Lines typed at the terminal the kernel was started from are run by the
+    // debug console.
     #[cfg(not(test))]
-    let address_receiver = {
-        let (sender, receiver) = channel();
-        set_send_addr(sender);
-        receiver
+    let console_input: Box<dyn Iterator<Item = String> + Send> = {
+        use std::io::BufRead;
+        Box::new(
+            std::io::BufReader::new(std::io::stdin())
+                .lines()
+                .map_while(|line| line.ok()),
+        )
     };
+    #[cfg(test)]
+    let console_input: Box<dyn Iterator<Item = String> + Send> =
+        match CONSOLE_INPUT.with(|ci| ci.borrow_mut().take()) {
+            Some(input) => Box::new(input.into_iter()),
+            None => Box::new(std::iter::empty()),
+        };
+    let console_sender = sender.clone();
+    std::thread::Builder::new()
+        .name("kernel console".to_owned())
+        .spawn(move || {
+            for line in console_input {
+                if console_sender.send(ThreadMessage::Console(line)).is_err() {
+                    break;
+                }
+            }
+        })
+        .expect("couldn't spawn console thread");
+
+    let (address_sender, address_receiver) = channel();
+    let listen_thread_handle = std::thread::Builder::new()
+        .name("kernel network listener".to_owned())
+        .spawn(move || {
+            listen_thread(
+                listen_addr,
+                sender,
+                address_sender,
+                new_pid_receiver,
+                exit_receiver,
+            )
+        })
+        .expect("couldn't spawn listen thread");
+
+    // Programs the kernel starts connect back to where it's listening.
+    let address = address_receiver.recv().unwrap();
+    xous_kernel::arch::set_xous_address(address.local());
+    if let Some(sa) = SEND_ADDR.with(|sa| sa.borrow_mut().take()) {
+        sa.send(address.clone()).unwrap();
+    }
 
-    let listen_thread_handle = SEND_ADDR.with(|sa| {
-        let sa = sa.borrow_mut().take();
+    // Stop the same way the `Shutdown` syscall does when the host sends
+    // SIGINT or SIGTERM, so that every process is terminated and every
+    // socket is closed properly.  Tests run many kernels in one process and
+    // leave signals alone.
+    #[cfg(not(test))]
+    {
+        xous_kernel::arch::catch_shutdown_signals();
         std::thread::Builder::new()
-            .name("kernel network listener".to_owned())
-            .spawn(move || listen_thread(listen_addr, sender, sa, new_pid_receiver, exit_receiver))
-            .expect("couldn't spawn listen thread")
-    });
+            .name("kernel signal listener".to_owned())
+            .spawn(move || {
+                while !xous_kernel::arch::shutdown_signalled() {
+                    std::thread::sleep(std::time::Duration::from_millis(100));
+                }
+                signal_sender.send(ThreadMessage::Shutdown).ok();
+            })
+            .expect("couldn't spawn signal thread");
+    }
 
     #[cfg(not(test))]
     {
-        let address = address_receiver.recv().unwrap();
-        xous_kernel::arch::set_xous_address(address);
         println!("KERNEL: Xous server listening on {}", address);
         println!("KERNEL: Starting initial processes:");
         let mut args = std::env::args();
@@ -441,22 +976,100 @@ pub fn idle() -> bool {
             let process_key = generate_pid_key();
             let init = xous_kernel::ProcessInit {
                 key: ProcessKey::new(process_key),
+                capabilities: Capabilities::all(),
+                quotas: Quotas::unlimited(),
+                command: None,
             };
             let new_pid = SystemServices::with_mut(|ss| ss.create_process(init)).unwrap();
+            replay::event(&format!("SPAWN {}", new_pid));
             println!(" {:^5} |  {}", new_pid, arg);
-            let process_args = xous_kernel::ProcessArgs::new("program", arg);
-            xous_kernel::arch::create_process_post(process_args, init, new_pid)
+            crate::arch::process::start_program(new_pid, &Program::shell("program", &arg))
                 .expect("couldn't spawn");
         }
     }
 
-    while let Ok(msg) = message_receiver.recv() {
+    loop {
+        // A pending shutdown finishes as soon as every subscriber is ready,
+        // and then there's nothing left to run.
+        if crate::shutdown::deadline().is_some() {
+            crate::arch::process::set_current_pid(pid1);
+            crate::shutdown::check();
+        }
+        if crate::shutdown::finished() {
+            exit_sender
+                .send(ExitMessage::Exit)
+                .expect("couldn't send shutdown signal");
+            break;
+        }
+
+        // Every process waiting is as quiet as a hosted system gets, so a
+        // pending suspend goes ahead.
+        if crate::suspend::pending() && crate::arch::process::all_waiting() {
+            replay::event("SUSPEND");
+            crate::arch::process::set_current_pid(pid1);
+            crate::suspend::enter();
+        }
+
+        let msg = if VIRTUAL_CLOCK.with(|vc| vc.get()).is_some() {
+            // Once the kernel has caught up with everything processes have
+            // asked for, and every process is waiting, nothing more can
+            // happen until the next deadline, so the clock skips ahead to it.
+            match message_receiver.try_recv() {
+                Ok(msg) => msg,
+                Err(TryRecvError::Disconnected) => break,
+                Err(TryRecvError::Empty) => match crate::timers::next_deadline() {
+                    Some(deadline) if crate::arch::process::all_waiting() => {
+                        set_clock(deadline);
+                        replay::event("TIMERS");
+                        crate::arch::process::set_current_pid(pid1);
+                        crate::timers::check();
+                        continue;
+                    }
+                    _ => match message_receiver.recv() {
+                        Ok(msg) => msg,
+                        Err(_) => break,
+                    },
+                },
+            }
+        } else {
+            // There's no tick timer to expire kernel timers, so wake up for
+            // the next one instead.
+            match crate::timers::next_deadline() {
+                Some(deadline) => {
+                    let wait =
+                        std::time::Duration::from_nanos(deadline.saturating_sub(host_timestamp()));
+                    match message_receiver.recv_timeout(wait) {
+                        Ok(msg) => msg,
+                        Err(RecvTimeoutError::Timeout) => {
+                            replay::event("TIMERS");
+                            crate::arch::process::set_current_pid(pid1);
+                            crate::timers::check();
+                            continue;
+                        }
+                        Err(RecvTimeoutError::Disconnected) => break,
+                    }
+                }
+                None => match message_receiver.recv() {
+                    Ok(msg) => msg,
+                    Err(_) => break,
+                },
+            }
+        };
         match msg {
-            ThreadMessage::NewConnection(conn, access_key) => {
+            ThreadMessage::NewConnection(conn, access_key, compress, shared) => {
                 // The new process should already have a PID registered. Convert its access key
                 // into a PID, and register the connection with the server.
-                let new_pid =
-                    crate::arch::process::register_connection_for_key(conn, access_key).unwrap();
+                let new_pid = match crate::arch::process::register_connection_for_key(
+                    conn, access_key, compress, shared,
+                ) {
+                    Ok(new_pid) => new_pid,
+                    Err(_) => {
+                        new_pid_sender
+                            .send(NewPidMessage::Refused)
+                            .expect("couldn't refuse new connection");
+                        continue;
+                    }
+                };
                 // println!(
                 //     "KERNEL: Access key {:?} mapped to PID {}",
                 //     access_key, new_pid
@@ -470,61 +1083,64 @@ pub fn idle() -> bool {
                 // conn.write_all(&new_pid.get().to_le_bytes())
                 //     .expect("couldn't send pid to new process");
 
-                // Switch to this process immediately, which moves it from `Setup(_)` to `Running(0)`.
-                // Note that in this system, multiple processes can be active at once. This is
-                // similar to having one core for each process
-                SystemServices::with_mut(|ss| {
-                    ss.create_thread(new_pid, ThreadInit {})?;
-                    ss.switch_to_thread(new_pid, None)
-                })
-                .unwrap();
+                replay::event(&format!("CONNECT {}", new_pid));
+                start_process(new_pid);
+            }
+            ThreadMessage::Shutdown => {
+                println!("KERNEL: Shutting down");
+                replay::event("SHUTDOWN");
+                crate::arch::process::set_current_pid(pid1);
+                crate::shutdown::begin(pid1, 1, ScrubLevel::None, None)
+                    .expect("couldn't shut down");
+            }
+            ThreadMessage::Console(line) => {
+                replay::event(&format!("CONSOLE {}", line));
+                crate::arch::process::set_current_pid(pid1);
+                crate::console::run(&line);
+            }
+            // A finished thread is as good as waiting forever.
+            ThreadMessage::ThreadFinished(pid, tid) => {
+                crate::arch::process::set_waiting(pid, tid, true)
             }
             ThreadMessage::SysCall(pid, thread_id, call) => {
                 // println!("KERNEL({}): Received syscall {:?}", pid, call);
+                // A process that terminated itself still has a thread reading its
+                // connection, which asks for the process to be terminated once the
+                // kernel hangs up.  By then there's nothing left to terminate.
+                if call == SysCall::TerminateProcess
+                    && SystemServices::with(|ss| {
+                        ss.get_process(pid).map(|p| p.free()).unwrap_or(true)
+                    })
+                {
+                    continue;
+                }
+                replay::syscall(pid, thread_id, &call);
                 crate::arch::process::set_current_pid(pid);
+                crate::arch::process::set_waiting(pid, thread_id, true);
                 // println!("KERNEL({}): Now running as the new process", pid);
 
                 // If the call being made is to terminate the current process, we need to know
                 // because we won't be able to send a response.
                 let is_terminate = call == SysCall::TerminateProcess;
-                let is_shutdown = call == SysCall::Shutdown;
 
-                // For a "Shutdown" command, send the response before we issue the shutdown.
-                // This is because the "process" will be "terminated" (the network socket will be closed),
-                // and we won't be able to send the response after we're done.
-                if is_shutdown {
-                    // println!("KERNEL: Detected shutdown -- sending final \"Ok\" to the client");
-                    let mut process = Process::current();
-                    let mut response_vec = Vec::new();
-                    response_vec.extend_from_slice(&thread_id.to_le_bytes());
-                    for word in Result::Ok.to_args().iter_mut() {
-                        response_vec.extend_from_slice(&word.to_le_bytes());
-                    }
-                    process.send(&response_vec).unwrap_or_else(|_e| {
-                        // If we're unable to send data to the process, assume it's dead and terminate it.
-                        println!(
-                            "Unable to send response to process: {:?} -- terminating",
-                            _e
-                        );
-                        crate::syscall::handle(pid, thread_id, SysCall::TerminateProcess).ok();
-                    });
-                    // println!("KERNEL: Done sending");
-                }
-
-                // Handle the syscall within the Xous kernel
-                let response =
-                    crate::syscall::handle(pid, thread_id, call).unwrap_or_else(Result::Error);
+                // Handle the syscall within the Xous kernel.  A `Shutdown` or a
+                // `Reboot` only gets things started, and the system goes down at
+                // the top of the loop, once the caller has its response.
+                let response = handle_syscall(pid, thread_id, call);
 
                 // println!("KERNEL({}): Syscall response {:?}", pid, response);
                 // There's a response if it wasn't a blocked process and we're not terminating.
-                // Send the response back to the target.
-                if response != Result::BlockedProcess && !is_terminate && !is_shutdown {
+                // Send the response back to the target.  A blocked thread gets its
+                // response later on, once whatever it's waiting for sets its result.
+                if let (SysCallOutcome::Return(response), false) = (response, is_terminate) {
                     // The syscall may change what the current process is, but we always
                     // want to send a response to the process where the request came from.
                     // For this block, switch to the original PID, send the message, then
                     // switch back.
                     let existing_pid = crate::arch::process::current_pid();
                     crate::arch::process::set_current_pid(pid);
+                    crate::arch::process::set_waiting(pid, thread_id, false);
+                    replay::result(pid, thread_id, &response);
 
                     let mut process = Process::current();
                     let mut response_vec = Vec::new();
@@ -538,19 +1154,12 @@ pub fn idle() -> bool {
                             "KERNEL({}): Unable to send response to process: {:?} -- terminating",
                             pid, _e
                         );
-                        crate::syscall::handle(pid, thread_id, SysCall::TerminateProcess).ok();
+                        terminate_unreachable(pid, thread_id);
                     });
                     crate::arch::process::set_current_pid(existing_pid);
                     // SystemServices::with_mut(|ss| {
                     // ss.switch_from(pid, 1, true)}).unwrap();
                 }
-
-                if is_shutdown {
-                    exit_sender
-                        .send(ExitMessage::Exit)
-                        .expect("couldn't send shutdown signal");
-                    break;
-                }
             }
         }
     }
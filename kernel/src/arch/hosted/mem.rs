@@ -1,4 +1,5 @@
 pub const PAGE_SIZE: usize = 4096;
+pub const MEGAPAGE_SIZE: usize = 4 * 1024 * 1024;
 use crate::mem::MemoryManager;
 use xous_kernel::{Error, MemoryFlags, PID};
 
@@ -16,6 +17,15 @@ pub struct MemoryMapping {
 pub const DEFAULT_MEMORY_MAPPING: MemoryMapping = MemoryMapping { pid: 0 };
 
 impl MemoryMapping {
+    /// Create an empty address space for `pid`.  This only matters when RAM
+    /// is being simulated, since otherwise the host maps its memory.
+    pub fn new(pid: PID) -> MemoryMapping {
+        super::mmu::create_space(pid);
+        MemoryMapping {
+            pid: pid.get() as usize,
+        }
+    }
+
     /// Get the currently active memory mapping.  Note that the actual root pages
     /// may be found at virtual address `PAGE_TABLE_ROOT_OFFSET`.
     pub fn current() -> MemoryMapping {
@@ -24,7 +34,7 @@ impl MemoryMapping {
 
     /// Get the "PID" (actually, ASID) from the current mapping
     pub fn get_pid(self) -> PID {
-        unimplemented!()
+        PID::new(self.pid as _).unwrap()
     }
 
     /// Set this mapping as the systemwide mapping.
@@ -33,33 +43,91 @@ impl MemoryMapping {
     /// As such, this will only have an observable effect once code returns
     /// to userspace.
     pub fn activate(self) -> Result<(), xous_kernel::Error> {
-        // This is a no-op on hosted environments
+        // This is a no-op on hosted environments, unless RAM is being
+        // simulated
+        if let Some(pid) = PID::new(self.pid as _) {
+            super::mmu::activate(pid);
+        }
         Ok(())
     }
 
+    /// Memory in hosted mode belongs to the host operating system, so there
+    /// are no page tables to print.
+    #[allow(dead_code)]
+    pub fn print_map(&self) {
+        println!("Memory Maps are managed by the host");
+    }
+
     pub fn reserve_address(
         &mut self,
-        _mm: &mut MemoryManager,
-        _addr: usize,
-        _flags: MemoryFlags,
+        mm: &mut MemoryManager,
+        addr: usize,
+        flags: MemoryFlags,
     ) -> Result<(), Error> {
+        if super::mmu::enabled() {
+            return super::mmu::reserve_address(mm, addr, flags);
+        }
         Ok(())
     }
 }
 
+/// Whether processes' memory is mapped by the kernel, which is only the case
+/// when a test is simulating RAM.  Otherwise each process gets its memory
+/// from the host, and sends it over its connection.
+pub fn has_page_tables() -> bool {
+    super::mmu::enabled()
+}
+
+/// Copy `buf.len()` bytes out of the current process, starting at `virt`.
+/// The kernel can only see a hosted process's memory when its pagetables
+/// are being simulated.
+pub fn read_user(virt: usize, buf: &mut [u8]) -> Result<(), Error> {
+    if super::mmu::enabled() {
+        return super::mmu::read_user(virt, buf);
+    }
+    Err(Error::BadAddress)
+}
+
 /// Determine whether a virtual address has been mapped
-pub fn address_available(_virt: usize) -> bool {
+pub fn address_available(virt: usize) -> bool {
+    if super::mmu::enabled() {
+        return super::mmu::virt_to_phys(virt).is_err();
+    }
     true
 }
 
 pub fn map_page_inner(
+    mm: &mut MemoryManager,
+    pid: PID,
+    phys: usize,
+    virt: usize,
+    req_flags: MemoryFlags,
+    map_user: bool,
+) -> Result<(), xous_kernel::Error> {
+    if super::mmu::enabled() {
+        return super::mmu::map_page(mm, pid, phys, virt, req_flags, map_user);
+    }
+    unimplemented!()
+}
+
+pub fn map_megapage_inner(
     _mm: &mut MemoryManager,
     _pid: PID,
-    _phys: usize,
-    _virt: usize,
-    _req_flags: MemoryFlags,
-    _map_user: bool,
+    phys: usize,
+    virt: usize,
+    req_flags: MemoryFlags,
+    map_user: bool,
 ) -> Result<(), xous_kernel::Error> {
+    if super::mmu::enabled() {
+        return super::mmu::map_megapage(phys, virt, req_flags, map_user);
+    }
+    unimplemented!()
+}
+
+pub fn unmap_megapage_inner(_mm: &mut MemoryManager, virt: usize) -> Result<usize, Error> {
+    if super::mmu::enabled() {
+        return super::mmu::unmap_megapage(virt);
+    }
     unimplemented!()
 }
 
@@ -86,25 +154,117 @@ pub fn lend_page_inner(
     unimplemented!()
 }
 
+pub fn move_range_inner(
+    mm: &mut MemoryManager,
+    src_space: &MemoryMapping,
+    src_addr: usize,
+    dest_pid: PID,
+    _dest_space: &MemoryMapping,
+    dest_addr: usize,
+    len: usize,
+) -> Result<(), Error> {
+    if super::mmu::enabled() {
+        let src_pid = src_space.get_pid();
+        return super::mmu::move_range(mm, src_pid, src_addr, dest_pid, dest_addr, len);
+    }
+    unimplemented!()
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn lend_range_inner(
+    mm: &mut MemoryManager,
+    src_space: &MemoryMapping,
+    src_addr: usize,
+    dest_pid: PID,
+    _dest_space: &MemoryMapping,
+    dest_addr: usize,
+    len: usize,
+    mutable: bool,
+) -> Result<(), Error> {
+    if super::mmu::enabled() {
+        let src_pid = src_space.get_pid();
+        return super::mmu::lend_range(mm, src_pid, src_addr, dest_pid, dest_addr, len, mutable);
+    }
+    unimplemented!()
+}
+
 pub fn return_page_inner(
     _mm: &mut MemoryManager,
-    _src_space: &MemoryMapping,
-    _src_addr: *mut u8,
+    src_space: &MemoryMapping,
+    src_addr: *mut u8,
     _dest_pid: PID,
-    _dest_space: &MemoryMapping,
-    _dest_addr: *mut u8,
+    dest_space: &MemoryMapping,
+    dest_addr: *mut u8,
 ) -> Result<usize, Error> {
+    if super::mmu::enabled() {
+        let (src_pid, dest_pid) = (src_space.get_pid(), dest_space.get_pid());
+        return super::mmu::return_page(src_pid, src_addr as usize, dest_pid, dest_addr as usize);
+    }
+    unimplemented!()
+}
+
+pub fn page_is_cow(virt: usize) -> bool {
+    if super::mmu::enabled() {
+        return super::mmu::page_is_cow(virt);
+    }
+    false
+}
+
+pub fn share_page_cow_inner(
+    mm: &mut MemoryManager,
+    src_space: &MemoryMapping,
+    src_addr: *mut u8,
+    dest_pid: PID,
+    _dest_space: &MemoryMapping,
+    dest_addr: *mut u8,
+) -> Result<bool, Error> {
+    if super::mmu::enabled() {
+        let src_pid = src_space.get_pid();
+        return super::mmu::share_page_cow(
+            mm,
+            src_pid,
+            src_addr as usize,
+            dest_pid,
+            dest_addr as usize,
+        );
+    }
+    unimplemented!()
+}
+
+pub fn finish_cow_inner(
+    _mm: &mut MemoryManager,
+    _pid: PID,
+    virt: usize,
+    new_phys: Option<usize>,
+) -> Result<(), Error> {
+    if super::mmu::enabled() {
+        return super::mmu::finish_cow(virt, new_phys);
+    }
     unimplemented!()
 }
 
-pub fn unmap_page_inner(_mm: &mut MemoryManager, virt: usize) -> Result<usize, Error> {
+/// Hosted processes manage their own memory, so there's nothing to protect.
+pub fn finalize_code_page(_mm: &mut MemoryManager, _virt: usize) -> Result<(), Error> {
+    Ok(())
+}
+
+pub fn unmap_page_inner(mm: &mut MemoryManager, virt: usize) -> Result<usize, Error> {
+    if super::mmu::enabled() {
+        return super::mmu::unmap_page(mm, virt);
+    }
     Ok(virt)
 }
 
-pub fn hand_page_to_user(_virt: *mut u8) -> Result<(), Error> {
+pub fn hand_page_to_user(virt: *mut u8) -> Result<(), Error> {
+    if super::mmu::enabled() {
+        return super::mmu::hand_page_to_user(virt as usize);
+    }
     unimplemented!()
 }
 
 pub fn virt_to_phys(virt: usize) -> Result<usize, Error> {
+    if super::mmu::enabled() {
+        return super::mmu::virt_to_phys(virt);
+    }
     Ok(virt)
 }
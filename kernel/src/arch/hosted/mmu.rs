@@ -0,0 +1,696 @@
+//! A simulated MMU, so that tests can exercise the kernel's memory code on
+//! the hosted arch layer.  Hosted processes normally get their memory from
+//! the host, and the kernel never sees it.  Once `simulate_ram()` has been
+//! called, each process instead gets Sv32 pagetables that map pages of a
+//! block of simulated RAM, and the functions in `mem` act on them the way
+//! the RISC-V ones act on real pagetables.  Entries use the same flags, and
+//! second-level pagetables are allocated from RAM by the memory manager, so
+//! running out of memory happens in the same places.  Root pagetables are
+//! kept outside of RAM, since on hardware the loader sets those up.
+//!
+//! Like the rest of the kernel's state, the simulation belongs to the host
+//! thread that set it up.
+
+#![cfg_attr(not(any(test, feature = "fuzz")), allow(dead_code))]
+
+use super::process::MAX_PROCESS_COUNT;
+use crate::mem::{MemoryManager, MEGAPAGE_SIZE, PAGE_SIZE};
+use core::cell::RefCell;
+use core::convert::TryInto;
+use xous_kernel::{Error, MemoryFlags, PID};
+
+bitflags::bitflags! {
+    struct MMUFlags: usize {
+        const NONE      = 0b00_0000_0000;
+        const VALID     = 0b00_0000_0001;
+        const R         = 0b00_0000_0010;
+        const W         = 0b00_0000_0100;
+        const X         = 0b00_0000_1000;
+        const USER      = 0b00_0001_0000;
+        const GLOBAL    = 0b00_0010_0000;
+        const A         = 0b00_0100_0000;
+        const D         = 0b00_1000_0000;
+        const S         = 0b01_0000_0000; // Shared page
+        const P         = 0b10_0000_0000; // Previously writable
+    }
+}
+
+/// The number of entries in a pagetable
+const ENTRIES: usize = 1024;
+
+struct Mmu {
+    /// The physical address of the first byte of `ram`
+    ram_start: usize,
+
+    /// The contents of simulated RAM
+    ram: Vec<u8>,
+
+    /// Each process's root pagetable, indexed by PID - 1
+    roots: Vec<[usize; ENTRIES]>,
+
+    /// The process whose address space pagetable walks use, as `satp`
+    /// would say on hardware
+    active: PID,
+}
+
+std::thread_local!(static MMU: RefCell<Option<Mmu>> = const { RefCell::new(None) });
+
+fn with<F, R>(f: F) -> R
+where
+    F: FnOnce(&mut Mmu) -> R,
+{
+    MMU.with(|mmu| {
+        f(mmu
+            .borrow_mut()
+            .as_mut()
+            .expect("RAM isn't being simulated"))
+    })
+}
+
+fn vpn1(virt: usize) -> usize {
+    (virt >> 22) & (ENTRIES - 1)
+}
+
+fn vpn0(virt: usize) -> usize {
+    (virt >> 12) & (ENTRIES - 1)
+}
+
+fn translate_flags(req_flags: MemoryFlags) -> MMUFlags {
+    let mut flags = MMUFlags::NONE;
+    if req_flags.contains(MemoryFlags::R) {
+        flags |= MMUFlags::R;
+    }
+    if req_flags.contains(MemoryFlags::W) {
+        flags |= MMUFlags::W;
+    }
+    if req_flags.contains(MemoryFlags::X) {
+        flags |= MMUFlags::X;
+    }
+    flags
+}
+
+fn untranslate_flags(entry: usize) -> MemoryFlags {
+    let entry = MMUFlags::from_bits_truncate(entry);
+    let mut flags = MemoryFlags::FREE;
+    if entry.contains(MMUFlags::R) {
+        flags |= MemoryFlags::R;
+    }
+    if entry.contains(MMUFlags::W) {
+        flags |= MemoryFlags::W;
+    }
+    if entry.contains(MMUFlags::X) {
+        flags |= MemoryFlags::X;
+    }
+    flags
+}
+
+/// A root pagetable entry is a megapage if it is valid and has any of the
+/// `RWX` bits set.  Otherwise it points to a second-level pagetable.
+fn is_megapage(l1_entry: usize) -> bool {
+    l1_entry & MMUFlags::VALID.bits() != 0
+        && l1_entry & (MMUFlags::R | MMUFlags::W | MMUFlags::X).bits() != 0
+}
+
+impl Mmu {
+    fn offset(&self, phys: usize) -> usize {
+        assert!(
+            phys >= self.ram_start && phys < self.ram_start + self.ram.len(),
+            "{:08x} isn't in simulated RAM",
+            phys
+        );
+        phys - self.ram_start
+    }
+
+    fn read(&self, phys: usize) -> usize {
+        let offset = self.offset(phys);
+        u32::from_le_bytes(self.ram[offset..offset + 4].try_into().unwrap()) as usize
+    }
+
+    fn write(&mut self, phys: usize, value: usize) {
+        let offset = self.offset(phys);
+        self.ram[offset..offset + 4].copy_from_slice(&(value as u32).to_le_bytes());
+    }
+
+    fn root(&mut self) -> &mut [usize; ENTRIES] {
+        &mut self.roots[self.active.get() as usize - 1]
+    }
+
+    /// The physical address of the second-level entry for `virt` in the
+    /// active address space, or `BadAddress` if there is no second-level
+    /// pagetable for it.
+    fn entry(&mut self, virt: usize) -> Result<usize, Error> {
+        let l1_entry = self.root()[vpn1(virt)];
+        if l1_entry & MMUFlags::VALID.bits() == 0 || is_megapage(l1_entry) {
+            return Err(Error::BadAddress);
+        }
+        Ok(((l1_entry >> 10) << 12) + vpn0(virt) * 4)
+    }
+
+    /// The second-level entry that maps the page containing `virt` in the
+    /// active address space.  Pages that are part of a megapage get the entry
+    /// they would have if the megapage were split.
+    fn leaf(&mut self, virt: usize) -> Result<usize, Error> {
+        let l1_entry = self.root()[vpn1(virt)];
+        if is_megapage(l1_entry) {
+            return Ok(l1_entry + (vpn0(virt) << 10));
+        }
+        let entry = self.entry(virt)?;
+        Ok(self.read(entry))
+    }
+
+    /// If `virt` lies inside a megapage, replace the megapage with a new
+    /// second-level pagetable of equivalent entries, as the RISC-V
+    /// `split_megapage()` does.
+    fn split_megapage(&mut self, mm: &mut MemoryManager, virt: usize) -> Result<(), Error> {
+        let megapage = self.root()[vpn1(virt)];
+        if !is_megapage(megapage) {
+            return Ok(());
+        }
+        let pid = self.active;
+        let l0pt_phys = mm.alloc_page(pid)?;
+        for index in 0..ENTRIES {
+            self.write(l0pt_phys + index * 4, megapage + (index << 10));
+        }
+        self.root()[vpn1(virt)] = ((l0pt_phys >> 12) << 10) | MMUFlags::VALID.bits();
+        Ok(())
+    }
+
+    /// Make sure there's a second-level pagetable for `virt`, allocating one
+    /// to `pid` if there isn't.
+    fn ensure_table(&mut self, mm: &mut MemoryManager, pid: PID, virt: usize) -> Result<(), Error> {
+        let l1_entry = self.root()[vpn1(virt)];
+        if is_megapage(l1_entry) {
+            return Err(Error::MemoryInUse);
+        }
+        if l1_entry & MMUFlags::VALID.bits() == 0 {
+            let l0pt_phys = mm.alloc_page(pid)?;
+            let offset = self.offset(l0pt_phys);
+            self.ram[offset..offset + PAGE_SIZE].fill(0);
+            self.root()[vpn1(virt)] = ((l0pt_phys >> 12) << 10) | MMUFlags::VALID.bits();
+        }
+        Ok(())
+    }
+
+    fn map_page(
+        &mut self,
+        mm: &mut MemoryManager,
+        pid: PID,
+        phys: usize,
+        virt: usize,
+        flags: MMUFlags,
+    ) -> Result<(), Error> {
+        self.ensure_table(mm, pid, virt)?;
+        let entry = self.entry(virt)?;
+        if self.read(entry) & MMUFlags::VALID.bits() != 0 {
+            panic!("Page {:08x} already allocated!", virt);
+        }
+        self.write(
+            entry,
+            ((phys >> 12) << 10) | (flags | MMUFlags::VALID | MMUFlags::D | MMUFlags::A).bits(),
+        );
+        Ok(())
+    }
+
+    fn virt_to_phys(&mut self, virt: usize) -> Result<usize, Error> {
+        let entry = self.leaf(virt)?;
+        if entry & MMUFlags::VALID.bits() == 0 {
+            return Err(Error::BadAddress);
+        }
+        Ok((entry >> 10) << 12)
+    }
+
+    /// Ensure every page in the range is mapped in the active address space,
+    /// and that none of them are already lent.  Megapages in the range are
+    /// split so that each page can be handled on its own.
+    fn check_range(
+        &mut self,
+        mm: &mut MemoryManager,
+        virt: usize,
+        len: usize,
+    ) -> Result<(), Error> {
+        for page in (virt..virt + len).step_by(PAGE_SIZE) {
+            self.split_megapage(mm, page)?;
+            let entry = self.entry(page)?;
+            let entry = self.read(entry);
+            if entry & MMUFlags::VALID.bits() == 0 {
+                return Err(Error::BadAddress);
+            }
+            if entry & MMUFlags::S.bits() != 0 {
+                return Err(Error::ShareViolation);
+            }
+        }
+        Ok(())
+    }
+
+    /// Allocate any pagetables `pid` needs to map the range in its address
+    /// space, and ensure none of its pages are in use, as the RISC-V
+    /// `prepare_range()` does.
+    fn prepare_range(
+        &mut self,
+        mm: &mut MemoryManager,
+        pid: PID,
+        virt: usize,
+        len: usize,
+    ) -> Result<(), Error> {
+        let previous = self.active;
+        self.active = pid;
+        let mut result = Ok(());
+        for page in (virt..virt + len).step_by(PAGE_SIZE) {
+            result = self.ensure_table(mm, pid, page).and_then(|_| {
+                let entry = self.entry(page)?;
+                if self.read(entry) & (MMUFlags::VALID | MMUFlags::S).bits() != 0 {
+                    return Err(Error::MemoryInUse);
+                }
+                Ok(())
+            });
+            if result.is_err() {
+                break;
+            }
+        }
+        self.active = previous;
+        result
+    }
+}
+
+/// Back every process with an address space of its own, mapping pages of
+/// `size` bytes of simulated RAM starting at physical address `start`.
+/// The memory manager needs to be told about the RAM as well, which
+/// `MemoryManager::simulate_ram()` takes care of.
+pub fn simulate_ram(start: usize, size: usize) {
+    MMU.with(|mmu| {
+        *mmu.borrow_mut() = Some(Mmu {
+            ram_start: start,
+            ram: vec![0; size],
+            roots: vec![[0; ENTRIES]; MAX_PROCESS_COUNT],
+            active: crate::arch::process::current_pid(),
+        })
+    });
+}
+
+/// Whether RAM is being simulated.
+pub fn enabled() -> bool {
+    MMU.with(|mmu| mmu.borrow().is_some())
+}
+
+/// Give `pid` a fresh, empty address space.
+pub fn create_space(pid: PID) {
+    if enabled() {
+        with(|mmu| mmu.roots[pid.get() as usize - 1] = [0; ENTRIES]);
+    }
+}
+
+/// Make `pid`'s address space the active one.
+pub fn activate(pid: PID) {
+    if enabled() {
+        with(|mmu| mmu.active = pid);
+    }
+}
+
+/// Read the word at `virt` in the active address space, as its process
+/// would.
+///
+/// # Errors
+///
+/// * **BadAddress**: The page isn't mapped readable
+pub fn load(virt: usize) -> Result<u32, Error> {
+    with(|mmu| {
+        let entry = mmu.leaf(virt)?;
+        let readable = (MMUFlags::VALID | MMUFlags::R).bits();
+        if entry & readable != readable {
+            return Err(Error::BadAddress);
+        }
+        Ok(mmu.read(((entry >> 10) << 12) + (virt & (PAGE_SIZE - 1))) as u32)
+    })
+}
+
+/// Write `value` to `virt` in the active address space, as its process
+/// would.
+///
+/// # Errors
+///
+/// * **BadAddress**: The page isn't mapped
+/// * **AccessDenied**: The page isn't writable, which on hardware would
+///   be a store page fault
+pub fn store(virt: usize, value: u32) -> Result<(), Error> {
+    with(|mmu| {
+        let entry = mmu.leaf(virt)?;
+        if entry & MMUFlags::VALID.bits() == 0 {
+            return Err(Error::BadAddress);
+        }
+        if entry & MMUFlags::W.bits() == 0 {
+            return Err(Error::AccessDenied);
+        }
+        mmu.write(
+            ((entry >> 10) << 12) + (virt & (PAGE_SIZE - 1)),
+            value as usize,
+        );
+        Ok(())
+    })
+}
+
+pub fn reserve_address(
+    mm: &mut MemoryManager,
+    addr: usize,
+    flags: MemoryFlags,
+) -> Result<(), Error> {
+    with(|mmu| {
+        let pid = mmu.active;
+        mmu.ensure_table(mm, pid, addr)?;
+        let entry = mmu.entry(addr)?;
+        if mmu.read(entry) & MMUFlags::VALID.bits() == 0 {
+            mmu.write(entry, translate_flags(flags).bits());
+        }
+        Ok(())
+    })
+}
+
+pub fn map_page(
+    mm: &mut MemoryManager,
+    pid: PID,
+    phys: usize,
+    virt: usize,
+    req_flags: MemoryFlags,
+    map_user: bool,
+) -> Result<(), Error> {
+    let flags = translate_flags(req_flags)
+        | if map_user {
+            MMUFlags::USER
+        } else {
+            MMUFlags::NONE
+        };
+    with(|mmu| mmu.map_page(mm, pid, phys, virt, flags))
+}
+
+/// Map the megapage at `phys` to `virt` in the active address space, as
+/// the RISC-V `map_megapage_inner()` does.
+pub fn map_megapage(
+    phys: usize,
+    virt: usize,
+    req_flags: MemoryFlags,
+    map_user: bool,
+) -> Result<(), Error> {
+    if phys & (MEGAPAGE_SIZE - 1) != 0 || virt & (MEGAPAGE_SIZE - 1) != 0 {
+        return Err(Error::BadAlignment);
+    }
+    let flags = translate_flags(req_flags);
+    if flags.is_empty() {
+        return Err(Error::BadAddress);
+    }
+    let flags = flags
+        | if map_user {
+            MMUFlags::USER
+        } else {
+            MMUFlags::NONE
+        };
+    with(|mmu| {
+        if mmu.root()[vpn1(virt)] & MMUFlags::VALID.bits() != 0 {
+            return Err(Error::MemoryInUse);
+        }
+        mmu.root()[vpn1(virt)] =
+            ((phys >> 12) << 10) | (flags | MMUFlags::VALID | MMUFlags::D | MMUFlags::A).bits();
+        Ok(())
+    })
+}
+
+/// Unmap the whole megapage covering `virt`, as the RISC-V
+/// `unmap_megapage_inner()` does.
+pub fn unmap_megapage(virt: usize) -> Result<usize, Error> {
+    with(|mmu| {
+        let megapage = mmu.root()[vpn1(virt)];
+        if !is_megapage(megapage) {
+            return Err(Error::BadAddress);
+        }
+        mmu.root()[vpn1(virt)] = 0;
+        Ok((megapage >> 10) << 12)
+    })
+}
+
+pub fn unmap_page(mm: &mut MemoryManager, virt: usize) -> Result<usize, Error> {
+    with(|mmu| {
+        mmu.split_megapage(mm, virt)?;
+        let entry = mmu.entry(virt)?;
+        let previous = mmu.read(entry);
+        if previous & MMUFlags::VALID.bits() == 0 {
+            return Err(Error::BadAddress);
+        }
+        mmu.write(entry, 0);
+        Ok((previous >> 10) << 12)
+    })
+}
+
+pub fn virt_to_phys(virt: usize) -> Result<usize, Error> {
+    with(|mmu| mmu.virt_to_phys(virt))
+}
+
+/// Copy `buf.len()` bytes out of the active address space, starting at
+/// `virt`, as the RISC-V `read_user()` does.  Every page they come from must
+/// be mapped and readable by userspace.
+pub fn read_user(virt: usize, buf: &mut [u8]) -> Result<(), Error> {
+    with(|mmu| {
+        let required = (MMUFlags::VALID | MMUFlags::USER | MMUFlags::R).bits();
+        for (offset, byte) in buf.iter_mut().enumerate() {
+            let addr = virt.checked_add(offset).ok_or(Error::BadAddress)?;
+            let entry = mmu.leaf(addr)?;
+            if entry & required != required {
+                return Err(Error::BadAddress);
+            }
+            let phys = ((entry >> 10) << 12) + (addr & (PAGE_SIZE - 1));
+            *byte = mmu.ram[mmu.offset(phys)];
+        }
+        Ok(())
+    })
+}
+
+pub fn hand_page_to_user(virt: usize) -> Result<(), Error> {
+    with(|mmu| {
+        let entry = mmu.entry(virt)?;
+        let previous = mmu.read(entry);
+        if previous & MMUFlags::VALID.bits() == 0 {
+            return Err(Error::BadAddress);
+        }
+        mmu.write(entry, previous | MMUFlags::USER.bits());
+        Ok(())
+    })
+}
+
+/// Whether the page containing `virt` in the active address space is shared
+/// copy-on-write, as the RISC-V `page_is_cow()` decides.
+pub fn page_is_cow(virt: usize) -> bool {
+    with(|mmu| match mmu.entry(virt) {
+        Ok(entry) => {
+            mmu.read(entry) & (MMUFlags::VALID | MMUFlags::S | MMUFlags::P).bits()
+                == (MMUFlags::VALID | MMUFlags::P).bits()
+        }
+        Err(_) => false,
+    })
+}
+
+/// Share a page from `src_pid` with `dest_pid`, making it copy-on-write in
+/// both if it's writable, as the RISC-V `share_page_cow_inner()` does.
+pub fn share_page_cow(
+    mm: &mut MemoryManager,
+    src_pid: PID,
+    src_addr: usize,
+    dest_pid: PID,
+    dest_addr: usize,
+) -> Result<bool, Error> {
+    with(|mmu| {
+        mmu.active = src_pid;
+        mmu.split_megapage(mm, src_addr)?;
+        let entry = mmu.entry(src_addr)?;
+        let mut shared = mmu.read(entry);
+        if shared & MMUFlags::VALID.bits() == 0 {
+            return Err(Error::BadAddress);
+        }
+        if shared & MMUFlags::S.bits() != 0 {
+            return Err(Error::ShareViolation);
+        }
+        if shared & MMUFlags::W.bits() != 0 {
+            shared = (shared & !MMUFlags::W.bits()) | MMUFlags::P.bits();
+            mmu.write(entry, shared);
+        }
+        let is_cow = shared & MMUFlags::P.bits() != 0;
+        let flags = translate_flags(untranslate_flags(shared))
+            | if dest_pid.get() != 1 {
+                MMUFlags::USER
+            } else {
+                MMUFlags::NONE
+            }
+            | if is_cow { MMUFlags::P } else { MMUFlags::NONE };
+
+        mmu.active = dest_pid;
+        let result = mmu.map_page(mm, dest_pid, (shared >> 10) << 12, dest_addr, flags);
+        mmu.active = src_pid;
+        result.map(|_| is_cow)
+    })
+}
+
+/// Resolve a write to the copy-on-write page at `virt` in the active address
+/// space, copying it into `new_phys` if that's given, as the RISC-V
+/// `finish_cow_inner()` does.
+pub fn finish_cow(virt: usize, new_phys: Option<usize>) -> Result<(), Error> {
+    if !page_is_cow(virt) {
+        return Err(Error::BadAddress);
+    }
+    with(|mmu| {
+        let entry = mmu.entry(virt)?;
+        let mut cow = mmu.read(entry);
+        if let Some(new_phys) = new_phys {
+            let old = mmu.offset((cow >> 10) << 12);
+            let new = mmu.offset(new_phys);
+            mmu.ram.copy_within(old..old + PAGE_SIZE, new);
+            cow = ((new_phys >> 12) << 10) | (cow & 0x3ff);
+        }
+        mmu.write(entry, (cow & !MMUFlags::P.bits()) | MMUFlags::W.bits());
+        Ok(())
+    })
+}
+
+/// Lend the pages in a range from `src_pid` to `dest_pid`, using the same
+/// encoding as the RISC-V `lend_range_inner()`.
+#[allow(clippy::too_many_arguments)]
+pub fn lend_range(
+    mm: &mut MemoryManager,
+    src_pid: PID,
+    src_addr: usize,
+    dest_pid: PID,
+    dest_addr: usize,
+    len: usize,
+    mutable: bool,
+) -> Result<(), Error> {
+    with(|mmu| {
+        mmu.active = src_pid;
+        mmu.check_range(mm, src_addr, len)?;
+        mmu.prepare_range(mm, dest_pid, dest_addr, len)?;
+        let dest_flags = if mutable {
+            MMUFlags::R | MMUFlags::W
+        } else {
+            MMUFlags::R
+        } | if dest_pid.get() != 1 {
+            MMUFlags::USER
+        } else {
+            MMUFlags::NONE
+        };
+        for offset in (0..len).step_by(PAGE_SIZE) {
+            mmu.active = src_pid;
+            let entry = mmu.entry(src_addr + offset)?;
+            let previous = mmu.read(entry);
+            mmu.write(
+                entry,
+                if mutable {
+                    (previous & !MMUFlags::VALID.bits()) | MMUFlags::S.bits()
+                } else if previous & MMUFlags::W.bits() != 0 {
+                    (previous & !MMUFlags::W.bits()) | (MMUFlags::P | MMUFlags::S).bits()
+                } else {
+                    previous | MMUFlags::S.bits()
+                },
+            );
+
+            mmu.active = dest_pid;
+            mmu.map_page(
+                mm,
+                dest_pid,
+                (previous >> 10) << 12,
+                dest_addr + offset,
+                dest_flags,
+            )
+            .expect("destination range wasn't prepared");
+        }
+        mmu.active = src_pid;
+        Ok(())
+    })
+}
+
+/// Move the pages in a range from `src_pid` to `dest_pid`, handing each one
+/// over to `dest_pid` as the RISC-V `move_range_inner()` does.
+pub fn move_range(
+    mm: &mut MemoryManager,
+    src_pid: PID,
+    src_addr: usize,
+    dest_pid: PID,
+    dest_addr: usize,
+    len: usize,
+) -> Result<(), Error> {
+    with(|mmu| {
+        mmu.active = src_pid;
+        mmu.check_range(mm, src_addr, len)?;
+        mmu.prepare_range(mm, dest_pid, dest_addr, len)?;
+
+        // Hand every page over before any mappings change, so that a page
+        // the source doesn't own leaves both processes as they were.
+        for offset in (0..len).step_by(PAGE_SIZE) {
+            let phys = mmu.virt_to_phys(src_addr + offset)?;
+            if let Err(e) = mm.give_page(phys, src_pid, dest_pid) {
+                for given in (0..offset).step_by(PAGE_SIZE) {
+                    let phys = mmu.virt_to_phys(src_addr + given)?;
+                    mm.give_page(phys, dest_pid, src_pid).unwrap();
+                }
+                return Err(e);
+            }
+        }
+
+        let user = if dest_pid.get() != 1 {
+            MMUFlags::USER
+        } else {
+            MMUFlags::NONE
+        };
+        for offset in (0..len).step_by(PAGE_SIZE) {
+            mmu.active = src_pid;
+            let entry = mmu.entry(src_addr + offset)?;
+            let previous = mmu.read(entry);
+            mmu.write(entry, 0);
+
+            let phys = (previous >> 10) << 12;
+            let flags = translate_flags(untranslate_flags(previous)) | user;
+            mmu.active = dest_pid;
+            mmu.map_page(mm, dest_pid, phys, dest_addr + offset, flags)
+                .expect("destination range wasn't prepared");
+        }
+        mmu.active = src_pid;
+        Ok(())
+    })
+}
+
+/// Return a page lent by `dest_pid` from `src_pid`, as the RISC-V
+/// `return_page_inner()` does.
+pub fn return_page(
+    src_pid: PID,
+    src_addr: usize,
+    dest_pid: PID,
+    dest_addr: usize,
+) -> Result<usize, Error> {
+    with(|mmu| {
+        mmu.active = src_pid;
+        let src_entry = mmu.entry(src_addr)?;
+        let previous = mmu.read(src_entry);
+        if previous & MMUFlags::VALID.bits() == 0 {
+            return Err(Error::ShareViolation);
+        }
+        mmu.write(src_entry, 0);
+
+        mmu.active = dest_pid;
+        let dest_entry = mmu
+            .entry(dest_addr)
+            .expect("page wasn't lent in destination space");
+        let lent = mmu.read(dest_entry);
+        if lent & MMUFlags::S.bits() == 0 {
+            panic!("page wasn't shared in destination space");
+        }
+        let restored = if lent & MMUFlags::VALID.bits() == 0 {
+            // This page was mutably borrowed.
+            (lent & !MMUFlags::S.bits()) | MMUFlags::VALID.bits()
+        } else {
+            // This page was immutably borrowed, and as such had its "W" flag
+            // clobbered.
+            let previous_flag = if lent & MMUFlags::P.bits() != 0 {
+                MMUFlags::W
+            } else {
+                MMUFlags::NONE
+            };
+            (lent & !(MMUFlags::S | MMUFlags::P).bits()) | previous_flag.bits()
+        };
+        mmu.write(dest_entry, restored);
+        mmu.active = src_pid;
+        Ok((previous >> 10) << 12)
+    })
+}
@@ -2,8 +2,10 @@ pub const MAX_THREAD: TID = 31;
 use crate::services::ProcessInner;
 use core::cell::RefCell;
 use std::io::Write;
-use std::net::TcpStream;
 use std::thread_local;
+use xous_kernel::arch::program::Program;
+use xous_kernel::arch::shared::SharedMemory;
+use xous_kernel::arch::transport::Stream;
 use xous_kernel::{ProcessInit, ProcessKey, ThreadInit, PID, TID};
 
 pub const INITIAL_TID: usize = 1;
@@ -22,7 +24,17 @@ struct ProcessImpl {
     key: ProcessKey,
 
     /// The network connection to the client process.
-    conn: Option<TcpStream>,
+    conn: Option<Stream>,
+
+    /// Whether memory sent over `conn` may be compressed
+    compress: bool,
+
+    /// Memory the process shares with the kernel, as it's mapped here.
+    /// Lends from it are made in place, so there's nothing to return.
+    shared: Option<SharedMemory>,
+
+    /// The host program the kernel started for this process, if any
+    program: Option<HostProgram>,
 
     /// Memory that may need to be returned to the caller for each thread
     memory_to_return: [Option<Vec<u8>>; MAX_THREAD + 1],
@@ -34,6 +46,10 @@ struct ProcessImpl {
 
     /// The currently-active thread for this process
     current_thread: TID,
+
+    /// A bit for each thread that has made a syscall and is waiting for its
+    /// result, or that has finished and will never make another
+    waiting: u64,
 }
 
 impl PartialEq for Process {
@@ -51,16 +67,42 @@ struct ProcessTable {
 
     /// The actual table contents
     table: Vec<Option<ProcessImpl>>,
+
+    /// Memory that was shared by processes that have gone away, which is
+    /// kept mapped since a server may still be holding a lend from it
+    retired: Vec<SharedMemory>,
 }
 
 thread_local!(
-    static PROCESS_TABLE: RefCell<ProcessTable> = RefCell::new(ProcessTable {
-        current: unsafe { PID::new_unchecked(1) },
-        total: 0,
-        table: Vec::new(),
-    })
+    static PROCESS_TABLE: RefCell<ProcessTable> = const {
+        RefCell::new(ProcessTable {
+            current: PID::new(1).unwrap(),
+            total: 0,
+            table: Vec::new(),
+            retired: Vec::new(),
+        })
+    }
 );
 
+/// Results handed to the threads of processes with no connection, which are
+/// the ones a test drives by calling into the kernel directly, along with
+/// any memory that was returned to them.
+#[cfg(any(test, feature = "fuzz"))]
+type DirectResult = (PID, TID, xous_kernel::Result, Option<Vec<u8>>);
+#[cfg(any(test, feature = "fuzz"))]
+thread_local!(static DIRECT_RESULTS: RefCell<Vec<DirectResult>> = const { RefCell::new(Vec::new()) });
+
+/// Take the oldest result handed to `pid:tid` that hasn't been taken yet.
+#[cfg(any(test, feature = "fuzz"))]
+pub fn take_direct_result(pid: PID, tid: TID) -> Option<(xous_kernel::Result, Option<Vec<u8>>)> {
+    DIRECT_RESULTS.with(|results| {
+        let mut results = results.borrow_mut();
+        let index = results.iter().position(|r| r.0 == pid && r.1 == tid)?;
+        let (_, _, result, returned) = results.remove(index);
+        Some((result, returned))
+    })
+}
+
 pub fn current_pid() -> PID {
     PROCESS_TABLE.with(|pt| pt.borrow().current)
 }
@@ -85,11 +127,16 @@ pub fn set_current_pid(pid: PID) {
         }
         pt.current = pid
     });
+
+    // Switching processes switches address spaces, as it would on hardware.
+    super::mmu::activate(pid);
 }
 
 pub fn register_connection_for_key(
-    conn: TcpStream,
+    conn: Stream,
     key: ProcessKey,
+    compress: bool,
+    shared: Option<SharedMemory>,
 ) -> Result<PID, xous_kernel::Error> {
     PROCESS_TABLE.with(|pt| {
         let mut process_table = pt.borrow_mut();
@@ -97,6 +144,8 @@ pub fn register_connection_for_key(
             if let Some(process) = process.as_mut() {
                 if process.key == key && process.conn.is_none() {
                     process.conn = Some(conn);
+                    process.compress = compress;
+                    process.shared = shared;
                     return Ok(PID::new(pid_minus_1 as u8 + 1).unwrap());
                 }
             }
@@ -105,6 +154,79 @@ pub fn register_connection_for_key(
     })
 }
 
+/// A program the kernel started on the host, which is killed if it's still
+/// running when its process goes away
+#[derive(Debug)]
+struct HostProgram(std::process::Child);
+
+impl Drop for HostProgram {
+    fn drop(&mut self) {
+        self.0.kill().ok();
+        self.0.wait().ok();
+    }
+}
+
+/// Start `program` on the host as process `pid`, which hasn't connected
+/// yet.  The program is told where the kernel is, and given a new key to
+/// connect with.
+pub fn start_program(pid: PID, program: &Program) -> Result<(), xous_kernel::Error> {
+    let key = super::generate_pid_key();
+    let child = std::process::Command::new(&program.path)
+        .args(&program.args)
+        .env("XOUS_SERVER", xous_kernel::arch::xous_address().to_string())
+        .env("XOUS_PID", pid.to_string())
+        .env("XOUS_PROCESS_NAME", &program.name)
+        .env("XOUS_PROCESS_KEY", hex::encode(key))
+        .spawn()
+        .map_err(|e| {
+            eprintln!("KERNEL: couldn't start {}: {}", program.path, e);
+            xous_kernel::Error::InternalError
+        })?;
+    PROCESS_TABLE.with(|pt| {
+        let mut process_table = pt.borrow_mut();
+        let process = process_table
+            .table
+            .get_mut(pid.get() as usize - 1)
+            .and_then(|process| process.as_mut())
+            .ok_or(xous_kernel::Error::ProcessNotFound)?;
+        process.key = ProcessKey::new(key);
+        process.program = Some(HostProgram(child));
+        Ok(())
+    })
+}
+
+/// Note whether thread `tid` of `pid` is waiting for the result of a
+/// syscall.
+pub fn set_waiting(pid: PID, tid: TID, waiting: bool) {
+    PROCESS_TABLE.with(|pt| {
+        if let Some(Some(process)) = pt.borrow_mut().table.get_mut(pid.get() as usize - 1) {
+            if waiting {
+                process.waiting |= 1u64 << tid;
+            } else {
+                process.waiting &= !(1u64 << tid);
+            }
+        }
+    })
+}
+
+/// Whether every thread of every connected process is waiting for the
+/// result of a syscall or has finished, so that nothing more can happen
+/// until a message or a deadline arrives.
+pub fn all_waiting() -> bool {
+    PROCESS_TABLE.with(|pt| {
+        pt.borrow()
+            .table
+            .iter()
+            .flatten()
+            .filter(|process| process.conn.is_some())
+            .all(|process| {
+                process.threads.iter().enumerate().all(|(index, thread)| {
+                    !thread.allocated || process.waiting & (1u64 << (index + 1)) != 0
+                })
+            })
+    })
+}
+
 #[repr(C)]
 #[derive(Copy, Clone, Debug)]
 /// Everything required to keep track of a single thread of execution.
@@ -250,6 +372,20 @@ impl Process {
         })
     }
 
+    /// The number of threads that exist in this process.
+    pub fn thread_count(&self) -> usize {
+        PROCESS_TABLE.with(|pt| {
+            let process_table = pt.borrow();
+            let current_pid_idx = process_table.current.get() as usize - 1;
+            let process = process_table.table[current_pid_idx].as_ref().unwrap();
+            process
+                .threads
+                .iter()
+                .filter(|thread| thread.allocated)
+                .count()
+        })
+    }
+
     pub fn set_thread_result(&mut self, tid: TID, result: xous_kernel::Result) {
         assert!(tid > 0);
         PROCESS_TABLE.with(|pt| {
@@ -262,6 +398,8 @@ impl Process {
                 tid,
             );
 
+            process.waiting &= !(1u64 << tid);
+
             let mut response = vec![];
             // Add the destination thread ID to the start of the packet.
             response.extend_from_slice(&tid.to_le_bytes());
@@ -272,14 +410,15 @@ impl Process {
             }
 
             // If there is memory to return for this thread, also return that.
-            if let Some(buf) = process.memory_to_return[tid - 1].take() {
+            let returned = process.memory_to_return[tid - 1].take();
+            if let Some(buf) = returned.as_ref() {
                 // if let xous_kernel::Result::Message(_) = result {
                 // } else {
                 //     panic!(
                 //         "memory was waiting to be returned, but message was not a result message"
                 //     );
                 // }
-                response.extend_from_slice(&buf);
+                xous_kernel::arch::compress::write_payload(&mut response, buf, process.compress);
             }
 
             // eprintln!(
@@ -287,6 +426,18 @@ impl Process {
             //     current_pid_idx + 1,
             //     response
             // );
+            super::replay::result(process_table.current, tid, &result);
+            if super::replay::replaying() {
+                return;
+            }
+            let process = &mut process_table.table[current_pid_idx].as_mut().unwrap();
+            #[cfg(any(test, feature = "fuzz"))]
+            if process.conn.is_none() {
+                let pid = process_table.current;
+                DIRECT_RESULTS
+                    .with(|results| results.borrow_mut().push((pid, tid, result, returned)));
+                return;
+            }
             process
                 .conn
                 .as_mut()
@@ -301,6 +452,11 @@ impl Process {
             let mut process_table = pt.borrow_mut();
             let current_pid_idx = process_table.current.get() as usize - 1;
             let process = &mut process_table.table[current_pid_idx].as_mut().unwrap();
+            if let Some(shared) = process.shared.as_ref() {
+                if shared.contains(buf.as_ptr(), buf.len()) {
+                    return;
+                }
+            }
             assert!(process.memory_to_return[tid - 1].is_none());
             process.memory_to_return[tid - 1] = Some(buf.to_vec());
         });
@@ -317,10 +473,14 @@ impl Process {
             let process = ProcessImpl {
                 inner: Default::default(),
                 conn: None,
+                compress: false,
+                shared: None,
+                program: None,
                 key: init_data.key,
                 memory_to_return: filled_array![None; 32 /* MAX_THREAD */],
                 current_thread: INITIAL_TID,
                 threads: [Thread { allocated: false }; MAX_THREAD + 1],
+                waiting: 0,
             };
 
             process_table.total += 1;
@@ -343,12 +503,14 @@ impl Process {
                 panic!("attempted to destroy PID that exceeds table index: {}", pid);
             }
             let process = process_table.table[pid_idx].as_mut().unwrap();
-            process
-                .conn
-                .as_mut()
-                .unwrap()
-                .shutdown(std::net::Shutdown::Both)
-                .unwrap();
+            // PID 1 has no connection unless a test harness is acting as it,
+            // and a process that already hung up can't be shut down again.
+            if let Some(conn) = process.conn.as_mut() {
+                conn.shutdown().ok();
+            }
+            if let Some(shared) = process.shared.take() {
+                process_table.retired.push(shared);
+            }
             process_table.table[pid_idx] = None;
             process_table.total -= 1;
             Ok(())
@@ -0,0 +1,429 @@
+//! Recording a hosted kernel, and replaying what it did.
+//!
+//! With `XOUS_RECORD=<file>` set, the kernel writes down everything that
+//! happens to it, one line at a time: processes connecting, syscalls and
+//! their arguments, timers being checked, and lines typed at the console.
+//! Every timestamp and random number it reads goes in as well, along with
+//! every result it hands back to a process.
+//!
+//! Starting the kernel with `XOUS_REPLAY=<file>` instead feeds the same
+//! events through `syscall::handle()` against fresh kernel state, with no
+//! processes attached, and answers reads of the clock and the TRNG from the
+//! recording.  Each result is checked against the recorded one, and the
+//! replay stops at the first that differs, so that a bug in the IPC layer
+//! that only shows up under one particular interleaving can be run again
+//! and again while it's bisected.
+//!
+//! Message payloads aren't recorded, only their length and a hash of what
+//! was in them, so that a recording can be passed around without leaking
+//! anything.  The kernel never looks inside a payload, so a replayed message
+//! carries zeroes instead.
+
+use std::cell::RefCell;
+use std::fs::File;
+use std::io::{BufRead, BufReader, LineWriter, Lines, Write};
+
+use crate::services::SystemServices;
+use crate::syscall::SysCallOutcome;
+use xous_kernel::{
+    Capabilities, Message, ProcessInit, ProcessKey, Quotas, Result, ScrubLevel, SysCall, PID, TID,
+};
+
+enum Mode {
+    Off,
+    Recording(LineWriter<File>),
+    Replaying(Replay),
+}
+
+struct Replay {
+    lines: Lines<BufReader<File>>,
+
+    /// The number of the line that was read last
+    line_number: usize,
+
+    /// The address each payload had when it was recorded, and the address
+    /// of the buffer standing in for it in the replay
+    addresses: Vec<(usize, usize)>,
+
+    /// Where the kernel first did something other than what was recorded
+    divergence: Option<String>,
+}
+
+/// Something that happened to the kernel, other than a read of the clock or
+/// the TRNG
+enum Event {
+    Spawn(PID),
+    Connect(PID),
+    Call(PID, TID, SysCall),
+    Timers,
+    Console(String),
+    Shutdown,
+    Suspend,
+}
+
+thread_local!(static MODE: RefCell<Mode> = const { RefCell::new(Mode::Off) });
+
+/// Record to or replay from the files named by `XOUS_RECORD` or
+/// `XOUS_REPLAY`, unless this kernel was already told what to do.
+pub fn init() {
+    if replaying() || MODE.with(|mode| matches!(*mode.borrow(), Mode::Recording(_))) {
+        return;
+    }
+    if let Ok(path) = std::env::var("XOUS_REPLAY") {
+        replay_from(&path);
+    } else if let Ok(path) = std::env::var("XOUS_RECORD") {
+        record_to(&path);
+    }
+}
+
+/// Write down everything this kernel does in `path`.
+pub fn record_to(path: &str) {
+    let file = File::create(path).expect("couldn't create recording");
+    MODE.with(|mode| *mode.borrow_mut() = Mode::Recording(LineWriter::new(file)));
+}
+
+/// Have this kernel do what was recorded in `path`, rather than listen for
+/// processes.
+pub fn replay_from(path: &str) {
+    let file = File::open(path).expect("couldn't open recording");
+    MODE.with(|mode| {
+        *mode.borrow_mut() = Mode::Replaying(Replay {
+            lines: BufReader::new(file).lines(),
+            line_number: 0,
+            addresses: Vec::new(),
+            divergence: None,
+        })
+    });
+}
+
+/// Whether this kernel is replaying a recording.
+pub fn replaying() -> bool {
+    MODE.with(|mode| matches!(*mode.borrow(), Mode::Replaying(_)))
+}
+
+fn write(line: &str) {
+    MODE.with(|mode| {
+        if let Mode::Recording(out) = &mut *mode.borrow_mut() {
+            writeln!(out, "{}", line).expect("couldn't write to recording");
+        }
+    })
+}
+
+impl Replay {
+    /// Read the next line of the recording, which must start with `kind`,
+    /// and return the rest of its fields.
+    fn expect(&mut self, kind: &str) -> Option<Vec<String>> {
+        if self.divergence.is_some() {
+            return None;
+        }
+        let line = self.next_line();
+        let mut fields = line.split(' ').map(|field| field.to_owned());
+        if fields.next().as_deref() != Some(kind) {
+            self.diverge(&line, kind);
+            return None;
+        }
+        Some(fields.collect())
+    }
+
+    fn next_line(&mut self) -> String {
+        self.line_number += 1;
+        match self.lines.next() {
+            Some(line) => line.expect("couldn't read recording"),
+            None => String::new(),
+        }
+    }
+
+    /// Note where the kernel stopped doing what was recorded.  The kernel
+    /// can't be stopped partway through a syscall, so the replay carries on
+    /// with the host's clock and TRNG until the end of the current event.
+    fn diverge(&mut self, recorded: &str, replayed: &str) {
+        if self.divergence.is_none() {
+            self.divergence = Some(format!(
+                "line {}: recorded {:?}, but the kernel did {:?}",
+                self.line_number, recorded, replayed
+            ));
+        }
+    }
+
+    /// Put the buffers standing in for payloads in place of the addresses
+    /// they had when they were recorded, or, with `to_replay` false, the
+    /// other way around.  Addresses get reused, so the newest payload wins.
+    fn translate(&self, words: &mut [usize], to_replay: bool) {
+        for word in words.iter_mut().filter(|word| **word != 0) {
+            if let Some(&(recorded, replayed)) = self.addresses.iter().rev().find(|pair| {
+                if to_replay {
+                    pair.0 == *word
+                } else {
+                    pair.1 == *word
+                }
+            }) {
+                *word = if to_replay { replayed } else { recorded };
+            }
+        }
+    }
+
+    fn next_event(&mut self) -> Option<Event> {
+        if self.divergence.is_some() {
+            return None;
+        }
+        let line = self.next_line();
+        let fields: Vec<&str> = line.split(' ').collect();
+        let pid = |index: usize| {
+            fields
+                .get(index)
+                .and_then(|f| f.parse().ok())
+                .and_then(PID::new)
+        };
+        let event = match fields[0] {
+            "" => return None,
+            "SPAWN" => pid(1).map(Event::Spawn),
+            "CONNECT" => pid(1).map(Event::Connect),
+            "TIMERS" => Some(Event::Timers),
+            "CONSOLE" => Some(Event::Console(
+                line.get("CONSOLE ".len()..).unwrap_or("").to_owned(),
+            )),
+            "SHUTDOWN" => Some(Event::Shutdown),
+            "SUSPEND" => Some(Event::Suspend),
+            "CALL" => self.parse_call(&fields),
+            _ => None,
+        };
+        if event.is_none() {
+            self.diverge(&line, "the next event");
+        }
+        event
+    }
+
+    /// Turn a `CALL` line back into the syscall it records, with a buffer
+    /// of zeroes standing in for its payload, if it had one.
+    fn parse_call(&mut self, fields: &[&str]) -> Option<Event> {
+        let pid = fields.get(1)?.parse().ok().and_then(PID::new)?;
+        let tid = fields.get(2)?.parse().ok()?;
+        let mut words = [0usize; 8];
+        for (word, field) in words.iter_mut().zip(fields.get(3..11)?) {
+            *word = usize::from_str_radix(field, 16).ok()?;
+        }
+        let payload_len: Option<usize> = fields.get(11).and_then(|f| f.parse().ok());
+        if payload_len.is_none() {
+            self.translate(&mut words, true);
+        }
+        let mut call = SysCall::from_args(
+            words[0], words[1], words[2], words[3], words[4], words[5], words[6], words[7],
+        )
+        .ok()?;
+        if let Some(len) = payload_len {
+            let buf = payload_mut(&mut call)?;
+            if buf.len() != len {
+                return None;
+            }
+            let stand_in = Box::into_raw(vec![0u8; len].into_boxed_slice()) as *mut u8 as usize;
+            self.addresses.push((buf.addr.get(), stand_in));
+            buf.addr = xous_kernel::MemoryAddress::new(stand_in)?;
+        }
+        Some(Event::Call(pid, tid, call))
+    }
+}
+
+fn payload(call: &SysCall) -> Option<&xous_kernel::MemoryRange> {
+    match call {
+        SysCall::SendMessage(_, message)
+        | SysCall::TrySendMessage(_, message)
+        | SysCall::CallMessage(_, message) => match message {
+            Message::MutableBorrow(msg) | Message::Borrow(msg) | Message::Move(msg) => {
+                Some(&msg.buf)
+            }
+            Message::Scalar(_) | Message::BlockingScalar(_) => None,
+        },
+        _ => None,
+    }
+}
+
+fn payload_mut(call: &mut SysCall) -> Option<&mut xous_kernel::MemoryRange> {
+    match call {
+        SysCall::SendMessage(_, message)
+        | SysCall::TrySendMessage(_, message)
+        | SysCall::CallMessage(_, message) => match message {
+            Message::MutableBorrow(msg) | Message::Borrow(msg) | Message::Move(msg) => {
+                Some(&mut msg.buf)
+            }
+            Message::Scalar(_) | Message::BlockingScalar(_) => None,
+        },
+        _ => None,
+    }
+}
+
+/// A 64-bit FNV-1a hash, which is plenty to tell payloads apart
+fn hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// Read the clock with `now`, or take the reading from the recording.
+pub fn timestamp(now: fn() -> u64) -> u64 {
+    MODE.with(|mode| match &mut *mode.borrow_mut() {
+        Mode::Off => now(),
+        Mode::Recording(out) => {
+            let time = now();
+            writeln!(out, "TIME {}", time).expect("couldn't write to recording");
+            time
+        }
+        Mode::Replaying(replay) => {
+            let fields = replay.expect("TIME").unwrap_or_default();
+            match fields.first().and_then(|field| field.parse().ok()) {
+                Some(time) => time,
+                None => {
+                    replay.diverge(&fields.join(" "), "read the time");
+                    now()
+                }
+            }
+        }
+    })
+}
+
+/// Fill `buf` from the TRNG with `fill`, or from the recording.
+pub fn random(buf: &mut [u8], fill: fn(&mut [u8])) {
+    MODE.with(|mode| match &mut *mode.borrow_mut() {
+        Mode::Off => fill(buf),
+        Mode::Recording(out) => {
+            fill(buf);
+            let hex: Vec<String> = buf.iter().map(|byte| format!("{:02x}", byte)).collect();
+            writeln!(out, "RANDOM {}", hex.concat()).expect("couldn't write to recording");
+        }
+        Mode::Replaying(replay) => {
+            let fields = replay.expect("RANDOM").unwrap_or_default();
+            let hex = fields.first().map(|field| field.as_str()).unwrap_or("");
+            if hex.len() != buf.len() * 2 || !hex.is_ascii() {
+                replay.diverge(hex, &format!("read {} random bytes", buf.len()));
+                return fill(buf);
+            }
+            for (index, byte) in buf.iter_mut().enumerate() {
+                *byte = u8::from_str_radix(&hex[index * 2..index * 2 + 2], 16).unwrap_or_default();
+            }
+        }
+    })
+}
+
+/// Note that `event` happened, if this kernel is being recorded.
+pub fn event(event: &str) {
+    write(event);
+}
+
+/// Note that `pid:tid` made syscall `call`, if this kernel is being
+/// recorded.
+pub fn syscall(pid: PID, tid: TID, call: &SysCall) {
+    if MODE.with(|mode| !matches!(*mode.borrow(), Mode::Recording(_))) {
+        return;
+    }
+    let words: Vec<String> = call
+        .as_args()
+        .iter()
+        .map(|word| format!("{:x}", word))
+        .collect();
+    let mut line = format!("CALL {} {} {}", pid, tid, words.join(" "));
+    if let Some(buf) = payload(call) {
+        let bytes = unsafe { core::slice::from_raw_parts(buf.as_ptr(), buf.len()) };
+        line.push_str(&format!(" {} {:016x}", buf.len(), hash(bytes)));
+    }
+    write(&line);
+}
+
+/// Note that `result` was handed back to `pid:tid`, or check that it's what
+/// was recorded.
+pub fn result(pid: PID, tid: TID, result: &Result) {
+    MODE.with(|mode| match &mut *mode.borrow_mut() {
+        Mode::Off => (),
+        Mode::Recording(out) => {
+            writeln!(out, "{}", result_line(pid, tid, result.to_args()))
+                .expect("couldn't write to recording");
+        }
+        Mode::Replaying(replay) if replay.divergence.is_none() => {
+            let mut words = result.to_args();
+            replay.translate(&mut words, false);
+            let replayed = result_line(pid, tid, words);
+            let recorded = replay.next_line();
+            if recorded != replayed {
+                replay.diverge(&recorded, &replayed);
+            }
+        }
+        Mode::Replaying(_) => (),
+    })
+}
+
+fn result_line(pid: PID, tid: TID, words: [usize; 8]) -> String {
+    let words: Vec<String> = words.iter().map(|word| format!("{:x}", word)).collect();
+    format!("RETURN {} {} {}", pid, tid, words.join(" "))
+}
+
+/// Feed every event in the recording to the kernel, the same way that the
+/// idle loop does when they come from processes.
+pub fn run(pid1: PID) {
+    let mut events = 0;
+    while let Some(event) = MODE.with(|mode| match &mut *mode.borrow_mut() {
+        Mode::Replaying(replay) => replay.next_event(),
+        _ => None,
+    }) {
+        events += 1;
+        match event {
+            Event::Spawn(pid) => {
+                crate::arch::process::set_current_pid(pid1);
+                let init = ProcessInit {
+                    key: ProcessKey::new([0; 16]),
+                    capabilities: Capabilities::all(),
+                    quotas: Quotas::unlimited(),
+                    command: None,
+                };
+                let new_pid = SystemServices::with_mut(|ss| ss.create_process(init));
+                if new_pid != Ok(pid) {
+                    MODE.with(|mode| {
+                        if let Mode::Replaying(replay) = &mut *mode.borrow_mut() {
+                            let spawned = format!("spawn {:?}", new_pid);
+                            replay.diverge(&format!("SPAWN {}", pid), &spawned);
+                        }
+                    });
+                }
+            }
+            Event::Connect(pid) => super::start_process(pid),
+            Event::Timers => {
+                crate::arch::process::set_current_pid(pid1);
+                crate::timers::check();
+            }
+            Event::Console(line) => {
+                crate::arch::process::set_current_pid(pid1);
+                crate::console::run(&line);
+            }
+            Event::Shutdown => {
+                crate::arch::process::set_current_pid(pid1);
+                crate::shutdown::begin(pid1, 1, ScrubLevel::None, None)
+                    .expect("couldn't shut down");
+            }
+            Event::Suspend => {
+                crate::arch::process::set_current_pid(pid1);
+                crate::suspend::enter();
+            }
+            Event::Call(pid, tid, call) => {
+                crate::arch::process::set_current_pid(pid);
+                let is_terminate = call == SysCall::TerminateProcess;
+                let response = super::handle_syscall(pid, tid, call);
+                if let (SysCallOutcome::Return(response), false) = (response, is_terminate) {
+                    result(pid, tid, &response);
+                }
+            }
+        }
+        crate::shutdown::check();
+        if crate::shutdown::finished() {
+            break;
+        }
+    }
+    match divergence() {
+        Some(divergence) => println!("REPLAY: diverged after {} events at {}", events, divergence),
+        None => println!("REPLAY: {} events replayed without diverging", events),
+    }
+}
+
+/// Where a replay first did something other than what was recorded.
+pub fn divergence() -> Option<String> {
+    MODE.with(|mode| match &*mode.borrow() {
+        Mode::Replaying(replay) => replay.divergence.clone(),
+        _ => None,
+    })
+}
@@ -3,6 +3,12 @@ mod hosted;
 #[cfg(any(windows,unix))]
 pub use hosted::*;
 
+// Instruction decoding doesn't touch the hardware, so the hosted tests can
+// check it too.
+#[cfg(any(test, target_arch = "riscv32", target_arch = "riscv64"))]
+#[path = "riscv/decode.rs"]
+pub mod riscv_decode;
+
 #[cfg(target_arch = "riscv32")]
 mod riscv;
 #[cfg(target_arch = "riscv32")]
@@ -1,16 +1,22 @@
-use riscv::register::{satp, sie, sstatus};
+use riscv::register::{sie, sstatus};
 use xous_kernel::PID;
 
+pub mod clocks;
+pub mod emulate;
 pub mod exception;
 pub mod irq;
 pub mod mem;
 pub mod process;
+pub mod reboot;
+pub mod suspend;
 pub mod syscall;
+pub mod timer;
+pub mod trng;
 
 pub use process::Thread;
 
 pub fn current_pid() -> PID {
-    PID::new(satp::read().asid() as _).unwrap()
+    process::current_pid()
 }
 
 pub fn init() {
@@ -19,11 +25,236 @@ pub fn init() {
         sie::set_ssoft();
         sie::set_sext();
     }
+    timer::init();
+    trng::init();
+    reboot::init();
+    suspend::init();
+    clocks::init();
+}
+
+/// Whether the system is able to reboot the way `mode` asks.
+///
+/// # Errors
+///
+/// * **UnhandledSyscall**: The kernel wasn't given a reboot peripheral
+pub fn check_reboot(_mode: xous_kernel::RebootMode) -> Result<(), xous_kernel::Error> {
+    if reboot::present() {
+        Ok(())
+    } else {
+        Err(xous_kernel::Error::UnhandledSyscall)
+    }
+}
+
+/// Reset the SoC as `mode` asks, once everything has been torn down.  If the
+/// reset somehow doesn't happen, the system stays down.
+pub fn reboot(mode: xous_kernel::RebootMode) {
+    reboot::reset(mode);
+}
+
+/// The interrupts that can wake the system from a suspend: every one that's
+/// unmasked, other than the tick timer's, which would only wake the system
+/// straight away.
+fn wake_mask() -> usize {
+    let mask = riscv::register::vexriscv::sim::read();
+    match timer::irq() {
+        Some(irq) => mask & !(1 << irq),
+        None => mask,
+    }
+}
+
+/// Whether the system is able to suspend to RAM, and to wake up again.
+///
+/// # Errors
+///
+/// * **UnhandledSyscall**: The kernel wasn't given a power controller
+/// * **InterruptNotFound**: No process has claimed an interrupt to wake it
+pub fn check_suspend() -> Result<(), xous_kernel::Error> {
+    if !suspend::present() {
+        return Err(xous_kernel::Error::UnhandledSyscall);
+    }
+    if wake_mask() == 0 {
+        return Err(xous_kernel::Error::InterruptNotFound);
+    }
+    Ok(())
+}
+
+/// Suspend to RAM until an interrupt that a process claimed is raised, and
+/// return `true` once the system is awake again.  This is entered with
+/// interrupts disabled, the same as `idle()`.  The tick timer is stopped
+/// and its interrupt masked, and the interrupt mask is put back as it was
+/// afterwards.  If every wake interrupt was freed since the suspend was
+/// asked for, the system doesn't sleep at all, since it would never wake.
+pub fn suspend() -> bool {
+    use riscv::register::vexriscv::sim;
+    let mask = sim::read();
+    let wake = wake_mask();
+    if wake == 0 {
+        return true;
+    }
+    timer::suspend();
+    sim::write(wake);
+    unsafe {
+        sstatus::clear_sie();
+        sie::set_sext();
+    }
+    suspend::sleep(wake);
+    sim::write(mask);
+    unsafe { sstatus::set_sie() };
+    timer::resume();
+    true
+}
+
+/// How many clock domains there are to gate, or `0` if the kernel wasn't
+/// given a clock controller.
+pub fn clock_domains() -> usize {
+    clocks::domains()
+}
+
+/// Gate clock `domain`, or let it run.
+pub fn set_clock_domain(domain: usize, running: bool) {
+    clocks::set(domain, running);
+}
+
+/// Run the CPU at least `hz` Hz, or as slowly as it can if `hz` is `0`,
+/// and return how fast it runs now.  The `time` CSR doesn't follow the
+/// CPU's clock, so timestamps are unaffected.
+pub fn set_cpu_frequency(hz: usize) -> usize {
+    clocks::set_cpu_frequency(hz)
+}
+
+/// Get the frequency of the `time` CSR, as given to the kernel in a `Freq`
+/// argument.
+pub fn timebase() -> Option<usize> {
+    crate::args::KernelArguments::get()
+        .iter()
+        .find(|arg| arg.name == make_type!("Freq") && !arg.data.is_empty())
+        .map(|arg| arg.data[0] as usize)
+}
+
+/// Read the `time` CSR.  The upper half is read again to make sure the lower
+/// half didn't wrap in between.
+pub fn timestamp() -> u64 {
+    use riscv::register::{time, timeh};
+    loop {
+        let high = timeh::read();
+        let low = time::read();
+        if timeh::read() == high {
+            return ((high as u64) << 32) | low as u64;
+        }
+    }
+}
+
+/// Read the `cycle` CSR, the same way as `timestamp()`.  This only works if
+/// the firmware has let supervisor mode read it through `mcounteren`.
+#[cfg(feature = "syscall-latency")]
+pub fn cycles() -> u64 {
+    use riscv::register::{cycle, cycleh};
+    loop {
+        let high = cycleh::read();
+        let low = cycle::read();
+        if cycleh::read() == high {
+            return ((high as u64) << 32) | low as u64;
+        }
+    }
+}
+
+/// The number of times the tick timer has fired since boot, or `None` if
+/// the kernel wasn't given one.
+pub fn ticks() -> Option<u64> {
+    timer::ticks()
+}
+
+/// Fill `buf` from the hardware TRNG.  Returns `false` if the kernel wasn't
+/// given one, or it stopped producing output.
+pub fn trng_read(buf: &mut [u8]) -> bool {
+    trng::read(buf)
+}
+
+extern "C" {
+    fn _xous_frame_pointer() -> usize;
+}
+
+/// The most frames `backtrace()` will walk, in case the chain loops.
+const MAX_BACKTRACE_FRAMES: usize = 32;
+
+/// Call `f` with the return address of each frame on the kernel stack,
+/// starting with the caller of this function.  This follows the chain of
+/// frame pointers, so the kernel has to be built with
+/// `-C force-frame-pointers=yes`, and it stops as soon as the chain leads
+/// somewhere that isn't mapped or doesn't move up the stack.
+pub fn backtrace<F: FnMut(usize)>(mut f: F) {
+    const WORD: usize = core::mem::size_of::<usize>();
+    let mut fp = unsafe { _xous_frame_pointer() };
+    for _ in 0..MAX_BACKTRACE_FRAMES {
+        // The return address is stored just below the frame pointer, and
+        // the caller's frame pointer just below that.
+        if fp & (WORD - 1) != 0 || fp < 2 * WORD || mem::virt_to_phys(fp - 2 * WORD).is_err() {
+            return;
+        }
+        let (ra, next) = unsafe {
+            (
+                ((fp - WORD) as *const usize).read_volatile(),
+                ((fp - 2 * WORD) as *const usize).read_volatile(),
+            )
+        };
+        if ra == 0 {
+            return;
+        }
+        f(ra);
+        if next <= fp {
+            return;
+        }
+        fp = next;
+    }
+}
+
+/// Print the CSRs describing the last trap, along with the registers of the
+/// thread that was interrupted by it.
+pub fn print_trap_frame() {
+    use riscv::register::{scause, sepc, stval};
+    println!(
+        "scause:{:08x}   sepc:{:08x}   stval:{:08x}",
+        scause::read().bits(),
+        sepc::read(),
+        stval::read()
+    );
+    process::Process::with_current(|process| {
+        println!("Thread {} of PID {}:", process.current_tid(), current_pid());
+        process.print_thread();
+    });
+}
+
+/// The number of harts the scheduler keeps state for.
+pub const MAX_HARTS: usize = 1;
+
+/// The hart the kernel is currently running on.  The loader only starts the
+/// boot hart, so this is always `0` for now.
+pub fn current_hart() -> usize {
+    0
 }
 
 /// Put the core to sleep until an interrupt hits. Returns `true`
 /// to indicate the kernel should not exit.
+///
+/// This is entered with external interrupts disabled in `sie`.  However,
+/// `wfi` only wakes up for interrupts that are enabled there, so instead
+/// they're masked with `sstatus.SIE` while the core sleeps, and taken as
+/// soon as it is set again.  The tick timer is stopped in the meantime,
+/// since there's nothing for it to preempt, unless a kernel timer still
+/// needs it to expire.
 pub fn idle() -> bool {
-    unsafe { riscv::asm::wfi() };
+    let suspend = crate::timers::next_deadline().is_none();
+    if suspend {
+        timer::suspend();
+    }
+    unsafe {
+        sstatus::clear_sie();
+        sie::set_sext();
+        riscv::asm::wfi();
+        sstatus::set_sie();
+    }
+    if suspend {
+        timer::resume();
+    }
     true
 }
@@ -0,0 +1,102 @@
+//! The clock controller that gates peripheral clocks and divides down the
+//! CPU's.  Its address, how many clock domains it has, and the fastest the
+//! CPU can run are given to the kernel in a `Clks` argument.  Each bit of
+//! its `GATE` register lets one domain run while it's set, and the CPU runs
+//! at the fastest frequency divided by one more than `CPU_DIVIDER`.  If
+//! there is no such argument, the kernel has no clocks to manage.
+
+use crate::mem::MemoryManager;
+use xous_kernel::{MemoryFlags, MemoryType, PID};
+
+/// Where the controller is mapped in the kernel's address space
+const CLOCKS_VIRT: usize = 0xffca_0000;
+
+// Register offsets, in words
+const GATE: usize = 0;
+const CPU_DIVIDER: usize = 1;
+
+/// The most the CPU's clock can be divided by
+const MAX_DIVIDER: usize = 256;
+
+/// How many domains the controller has, or `0` if there isn't one
+static mut DOMAINS: usize = 0;
+
+/// The fastest the CPU can run, in Hz
+static mut MAX_HZ: usize = 0;
+
+fn read(register: usize) -> usize {
+    unsafe { (CLOCKS_VIRT as *const usize).add(register).read_volatile() }
+}
+
+fn write(register: usize, value: usize) {
+    unsafe {
+        (CLOCKS_VIRT as *mut usize)
+            .add(register)
+            .write_volatile(value)
+    };
+}
+
+/// Map the clock controller, if the kernel was given one.
+pub fn init() {
+    let (base, domains, max_hz) = match crate::args::KernelArguments::get()
+        .iter()
+        .find(|arg| arg.name == make_type!("Clks") && arg.data.len() >= 3)
+    {
+        Some(arg) => (
+            arg.data[0] as usize,
+            arg.data[1] as usize,
+            arg.data[2] as usize,
+        ),
+        None => return,
+    };
+
+    MemoryManager::with_mut(|mm| {
+        mm.map_range(
+            base as *mut u8,
+            CLOCKS_VIRT as *mut u8,
+            4096,
+            PID::new(1).unwrap(),
+            MemoryFlags::R | MemoryFlags::W,
+            MemoryType::Default,
+        )
+        .expect("unable to map clock controller")
+    });
+    unsafe {
+        DOMAINS = domains;
+        MAX_HZ = max_hz;
+    }
+}
+
+/// How many clock domains there are, or `0` if there's no controller.
+pub fn domains() -> usize {
+    unsafe { DOMAINS }
+}
+
+/// Gate `domain`, or let it run.
+pub fn set(domain: usize, running: bool) {
+    if domain >= domains() {
+        return;
+    }
+    let gate = read(GATE);
+    if running {
+        write(GATE, gate | (1 << domain));
+    } else {
+        write(GATE, gate & !(1 << domain));
+    }
+}
+
+/// Run the CPU as slowly as it can while still running at least `hz` Hz,
+/// or as fast as it can if that isn't fast enough.  Returns how fast it
+/// runs now.
+pub fn set_cpu_frequency(hz: usize) -> usize {
+    let max_hz = unsafe { MAX_HZ };
+    if domains() == 0 || max_hz == 0 {
+        return 0;
+    }
+    let divider = match hz {
+        0 => MAX_DIVIDER,
+        hz => (max_hz / hz).clamp(1, MAX_DIVIDER),
+    };
+    write(CPU_DIVIDER, divider - 1);
+    max_hz / divider
+}
@@ -0,0 +1,170 @@
+//! Instruction decoding for `emulate`.  Nothing here touches the machine, so
+//! these are also built for the hosted tests.
+
+/// The unprivileged counters that userspace may read.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Counter {
+    Cycle,
+    Time,
+    Instret,
+    CycleH,
+    TimeH,
+    InstretH,
+}
+
+/// A load or store that `emulate` can split into byte accesses.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct MemoryAccess {
+    /// `true` for a store, `false` for a load
+    pub store: bool,
+
+    /// Number of bytes accessed
+    pub width: usize,
+
+    /// Whether a load is sign-extended
+    pub signed: bool,
+
+    /// Register holding the base address
+    pub base: usize,
+
+    /// Offset added to the base address
+    pub offset: isize,
+
+    /// Register that is loaded into or stored from
+    pub reg: usize,
+}
+
+/// An `LR.W`, `SC.W`, or `AMO*.W` instruction.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Atomic {
+    pub funct5: u32,
+    pub rd: usize,
+    pub rs1: usize,
+    pub rs2: usize,
+}
+
+pub const FUNCT5_LR: u32 = 0b00010;
+pub const FUNCT5_SC: u32 = 0b00011;
+
+/// Decode a read of one of the unprivileged counters, returning the
+/// destination register and the counter.  Only `CSRRS rd, csr, x0` is
+/// accepted, which is how `rdcycle`, `rdtime`, and `rdinstret` are encoded.
+pub fn counter(insn: u32, len: usize) -> Option<(usize, Counter)> {
+    if len != 4
+        || insn & 0x7f != 0b111_0011
+        || (insn >> 12) & 0b111 != 0b010
+        || (insn >> 15) & 0x1f != 0
+    {
+        return None;
+    }
+    let counter = match insn >> 20 {
+        0xc00 => Counter::Cycle,
+        0xc01 => Counter::Time,
+        0xc02 => Counter::Instret,
+        0xc80 => Counter::CycleH,
+        0xc81 => Counter::TimeH,
+        0xc82 => Counter::InstretH,
+        _ => return None,
+    };
+    Some((((insn >> 7) & 0x1f) as usize, counter))
+}
+
+/// Decode a 32-bit wide atomic instruction.
+pub fn atomic(insn: u32, len: usize) -> Option<Atomic> {
+    if len != 4 || insn & 0x7f != 0b010_1111 || (insn >> 12) & 0b111 != 0b010 {
+        return None;
+    }
+    Some(Atomic {
+        funct5: insn >> 27,
+        rd: ((insn >> 7) & 0x1f) as usize,
+        rs1: ((insn >> 15) & 0x1f) as usize,
+        rs2: ((insn >> 20) & 0x1f) as usize,
+    })
+}
+
+/// Compute the value an `AMO*.W` instruction stores, given the value that was
+/// in memory and the source register.  `LR.W` and `SC.W` aren't handled here.
+pub fn amo(funct5: u32, old: u32, src: u32) -> Option<u32> {
+    Some(match funct5 {
+        0b00001 => src,
+        0b00000 => old.wrapping_add(src),
+        0b00100 => old ^ src,
+        0b01100 => old & src,
+        0b01000 => old | src,
+        0b10000 => (old as i32).min(src as i32) as u32,
+        0b10100 => (old as i32).max(src as i32) as u32,
+        0b11000 => old.min(src),
+        0b11100 => old.max(src),
+        _ => return None,
+    })
+}
+
+/// Decode a halfword or word load or store, in either its full-size or
+/// compressed form.
+pub fn memory_access(insn: u32, len: usize) -> Option<MemoryAccess> {
+    let (store, width, signed, base, offset, reg) = if len == 4 {
+        let funct3 = (insn >> 12) & 0b111;
+        let rs1 = ((insn >> 15) & 0x1f) as usize;
+        match insn & 0x7f {
+            // LH, LW, LHU
+            0b000_0011 => {
+                let offset = (insn as i32 >> 20) as isize;
+                let rd = ((insn >> 7) & 0x1f) as usize;
+                match funct3 {
+                    0b001 => (false, 2, true, rs1, offset, rd),
+                    0b010 => (false, 4, true, rs1, offset, rd),
+                    0b101 => (false, 2, false, rs1, offset, rd),
+                    _ => return None,
+                }
+            }
+            // SH, SW
+            0b010_0011 => {
+                let offset = (((insn as i32 >> 25) << 5) | ((insn >> 7) & 0x1f) as i32) as isize;
+                let rs2 = ((insn >> 20) & 0x1f) as usize;
+                match funct3 {
+                    0b001 => (true, 2, false, rs1, offset, rs2),
+                    0b010 => (true, 4, false, rs1, offset, rs2),
+                    _ => return None,
+                }
+            }
+            _ => return None,
+        }
+    } else {
+        let funct3 = (insn >> 13) & 0b111;
+        match (insn & 0b11, funct3) {
+            // C.LW and C.SW
+            (0b00, 0b010) | (0b00, 0b110) => {
+                let offset = (((insn >> 6) & 1) << 2)
+                    | (((insn >> 10) & 0b111) << 3)
+                    | (((insn >> 5) & 1) << 6);
+                let rs1 = 8 + ((insn >> 7) & 0b111) as usize;
+                let reg = 8 + ((insn >> 2) & 0b111) as usize;
+                let store = funct3 == 0b110;
+                (store, 4, !store, rs1, offset as isize, reg)
+            }
+            // C.LWSP
+            (0b10, 0b010) => {
+                let offset = (((insn >> 4) & 0b111) << 2)
+                    | (((insn >> 12) & 1) << 5)
+                    | (((insn >> 2) & 0b11) << 6);
+                let rd = ((insn >> 7) & 0x1f) as usize;
+                (false, 4, true, 2, offset as isize, rd)
+            }
+            // C.SWSP
+            (0b10, 0b110) => {
+                let offset = (((insn >> 9) & 0b1111) << 2) | (((insn >> 7) & 0b11) << 6);
+                let rs2 = ((insn >> 2) & 0x1f) as usize;
+                (true, 4, false, 2, offset as isize, rs2)
+            }
+            _ => return None,
+        }
+    };
+    Some(MemoryAccess {
+        store,
+        width,
+        signed,
+        base,
+        offset,
+        reg,
+    })
+}
@@ -0,0 +1,273 @@
+//! Trap-and-emulate support for cores that are missing parts of the ISA.
+//!
+//! Reads of the `cycle`, `time`, and `instret` counters are always emulated,
+//! since userspace may not be permitted to read them directly.  With the
+//! `emulate-atomics` feature, `LR.W`, `SC.W`, and the `AMO*.W`
+//! instructions raise an illegal instruction exception on cores without the
+//! `A` extension, and are carried out here instead.  With the
+//! `emulate-misaligned` feature, loads and stores that aren't naturally
+//! aligned are split into byte accesses.
+//!
+//! Only instructions executed in user mode are emulated, and only when every
+//! byte they touch is already mapped into the process with the required
+//! permissions, aside from copy-on-write pages, which are copied first just
+//! as they would be for a store.  Anything else falls through to the regular
+//! exception path.
+
+use crate::arch::mem::{pagetable_entry, MMUFlags, PAGE_SIZE, USER_AREA_END};
+use crate::arch::process::Thread;
+use crate::arch::riscv_decode::{self as decode, Counter};
+use riscv::register::sstatus;
+
+use riscv::register::{cycle, cycleh, instret, instreth, time, timeh};
+use xous_kernel::{PID, TID};
+
+/// The reservation made by the last emulated `LR.W`, as the thread that made
+/// it, the address, and the value that was loaded.  `SC.W` only succeeds if
+/// it comes from the same thread, targets the same address, and memory still
+/// holds the same value.  Comparing the value catches other threads that
+/// stored to the address in between, since there's no way to watch for
+/// ordinary stores.
+#[cfg(feature = "emulate-atomics")]
+static mut RESERVATION: Option<(PID, TID, usize, u32)> = None;
+
+/// Read the value of register `x<reg>` from a saved thread.
+fn get_reg(thread: &Thread, reg: usize) -> usize {
+    if reg == 0 {
+        0
+    } else {
+        thread.registers[reg - 1]
+    }
+}
+
+/// Write register `x<reg>` in a saved thread.  Writes to `x0` are ignored.
+fn set_reg(thread: &mut Thread, reg: usize, value: usize) {
+    if reg != 0 {
+        thread.registers[reg - 1] = value;
+    }
+}
+
+/// Determine whether `len` bytes starting at `addr` are mapped into userspace
+/// and may be read, and also written if `write` is set.
+fn user_accessible(addr: usize, len: usize, write: bool) -> bool {
+    let end = match addr.checked_add(len) {
+        Some(end) if end <= USER_AREA_END => end,
+        _ => return false,
+    };
+    let mut required = MMUFlags::VALID | MMUFlags::USER | MMUFlags::R;
+    if write {
+        required |= MMUFlags::W;
+    }
+    for page in ((addr & !(PAGE_SIZE - 1))..end).step_by(PAGE_SIZE) {
+        match pagetable_entry(page) {
+            Ok(entry) => {
+                if !MMUFlags::from_bits_truncate(*entry).contains(required) {
+                    return false;
+                }
+            }
+            Err(_) => return false,
+        }
+    }
+    true
+}
+
+/// Give the process its own copy of any copy-on-write pages in the `len`
+/// bytes starting at `addr`, so that they can be written to.  Returns `false`
+/// if a copy couldn't be made.
+#[cfg(any(feature = "emulate-atomics", feature = "emulate-misaligned"))]
+fn break_cow(pid: PID, addr: usize, len: usize) -> bool {
+    let end = match addr.checked_add(len) {
+        Some(end) if end <= USER_AREA_END => end,
+        _ => return false,
+    };
+    for page in ((addr & !(PAGE_SIZE - 1))..end).step_by(PAGE_SIZE) {
+        if crate::arch::mem::page_is_cow(page)
+            && crate::mem::MemoryManager::with_mut(|mm| mm.copy_on_write(pid, page)).is_err()
+        {
+            return false;
+        }
+    }
+    true
+}
+
+/// Read `len` bytes of user memory, little-endian.  The range must already
+/// have been checked with `user_accessible()`.
+fn read_user(addr: usize, len: usize) -> usize {
+    let mut value = 0;
+    unsafe {
+        sstatus::set_sum();
+        for offset in (0..len).rev() {
+            value = (value << 8) | ((addr + offset) as *const u8).read_volatile() as usize;
+        }
+        sstatus::clear_sum();
+    }
+    value
+}
+
+/// Write `len` bytes of user memory, little-endian.  The range must already
+/// have been checked with `user_accessible()`.
+#[cfg(any(feature = "emulate-atomics", feature = "emulate-misaligned"))]
+fn write_user(addr: usize, len: usize, value: usize) {
+    unsafe {
+        sstatus::set_sum();
+        for offset in 0..len {
+            ((addr + offset) as *mut u8).write_volatile((value >> (offset * 8)) as u8);
+        }
+        sstatus::clear_sum();
+    }
+}
+
+/// Fetch the instruction at `pc`, returning it along with its length in bytes.
+fn fetch(pc: usize) -> Option<(u32, usize)> {
+    if !user_accessible(pc, 2, false) {
+        return None;
+    }
+    let low = read_user(pc, 2) as u32;
+    if low & 0b11 != 0b11 {
+        return Some((low, 2));
+    }
+    if !user_accessible(pc + 2, 2, false) {
+        return None;
+    }
+    Some((low | ((read_user(pc + 2, 2) as u32) << 16), 4))
+}
+
+/// Emulate the instruction that raised an illegal instruction exception in
+/// `thread`.  Returns `true` if the instruction was emulated, in which case the
+/// thread has been advanced past it and may be resumed.
+pub fn emulate_illegal_instruction(_pid: PID, _tid: TID, thread: &mut Thread) -> bool {
+    if emulate_counter(thread) {
+        return true;
+    }
+    #[cfg(feature = "emulate-atomics")]
+    {
+        if emulate_atomic(_pid, _tid, thread) {
+            return true;
+        }
+    }
+    false
+}
+
+/// Emulate a read of one of the unprivileged counters, which is how
+/// `rdcycle`, `rdtime`, and `rdinstret` are encoded.  Only `CSRRS` with `x0`
+/// as the source is accepted, since the counters are read-only.
+fn emulate_counter(thread: &mut Thread) -> bool {
+    let (insn, len) = match fetch(thread.sepc) {
+        Some(x) => x,
+        None => return false,
+    };
+
+    let (rd, counter) = match decode::counter(insn, len) {
+        Some(x) => x,
+        None => return false,
+    };
+    let value = match counter {
+        Counter::Cycle => cycle::read(),
+        Counter::Time => time::read(),
+        Counter::Instret => instret::read(),
+        Counter::CycleH => cycleh::read(),
+        Counter::TimeH => timeh::read(),
+        Counter::InstretH => instreth::read(),
+    };
+    set_reg(thread, rd, value);
+    thread.sepc += len;
+    true
+}
+
+/// Emulate an `LR.W`, `SC.W`, or `AMO*.W` instruction at the faulting PC of
+/// `thread`.  Returns `true` if the instruction was emulated, in which case the
+/// thread has been advanced past it and may be resumed.
+#[cfg(feature = "emulate-atomics")]
+fn emulate_atomic(pid: PID, tid: TID, thread: &mut Thread) -> bool {
+    let (insn, len) = match fetch(thread.sepc) {
+        Some(x) => x,
+        None => return false,
+    };
+
+    let decode::Atomic {
+        funct5,
+        rd,
+        rs1,
+        rs2,
+    } = match decode::atomic(insn, len) {
+        Some(x) => x,
+        None => return false,
+    };
+
+    let addr = get_reg(thread, rs1);
+    let write = funct5 != decode::FUNCT5_LR;
+    if addr & 3 != 0 || (write && !break_cow(pid, addr, 4)) || !user_accessible(addr, 4, write) {
+        return false;
+    }
+    let src = get_reg(thread, rs2) as u32;
+    let old = read_user(addr, 4) as u32;
+
+    let new = match funct5 {
+        decode::FUNCT5_LR => {
+            unsafe { RESERVATION = Some((pid, tid, addr, old)) };
+            set_reg(thread, rd, old as i32 as usize);
+            thread.sepc += len;
+            return true;
+        }
+        decode::FUNCT5_SC => {
+            let reservation = unsafe { RESERVATION.take() };
+            if reservation == Some((pid, tid, addr, old)) {
+                write_user(addr, 4, src as usize);
+                set_reg(thread, rd, 0);
+            } else {
+                set_reg(thread, rd, 1);
+            }
+            thread.sepc += len;
+            return true;
+        }
+        _ => match decode::amo(funct5, old, src) {
+            Some(new) => new,
+            None => return false,
+        },
+    };
+    write_user(addr, 4, new as usize);
+    set_reg(thread, rd, old as i32 as usize);
+    thread.sepc += len;
+    true
+}
+
+/// Emulate a misaligned load or store at the faulting PC of `thread` by
+/// splitting it into byte accesses.  Returns `true` if the instruction was
+/// emulated, in which case the thread has been advanced past it and may be
+/// resumed.
+#[cfg(feature = "emulate-misaligned")]
+pub fn emulate_misaligned(pid: PID, thread: &mut Thread) -> bool {
+    let (insn, len) = match fetch(thread.sepc) {
+        Some(x) => x,
+        None => return false,
+    };
+
+    let decode::MemoryAccess {
+        store,
+        width,
+        signed,
+        base,
+        offset,
+        reg,
+    } = match decode::memory_access(insn, len) {
+        Some(x) => x,
+        None => return false,
+    };
+
+    let addr = (get_reg(thread, base) as isize).wrapping_add(offset) as usize;
+    if (store && !break_cow(pid, addr, width)) || !user_accessible(addr, width, store) {
+        return false;
+    }
+    if store {
+        write_user(addr, width, get_reg(thread, reg));
+    } else {
+        let mut value = read_user(addr, width);
+        if signed && width < core::mem::size_of::<usize>() {
+            let shift = (core::mem::size_of::<usize>() - width) * 8;
+            value = (((value << shift) as isize) >> shift) as usize;
+        }
+        set_reg(thread, reg, value);
+    }
+    thread.sepc += len;
+    true
+}
@@ -1,9 +1,10 @@
 use crate::arch::current_pid;
 use crate::arch::mem::MemoryMapping;
 use crate::arch::process::Process as ArchProcess;
-use crate::arch::process::{Thread, RETURN_FROM_ISR};
-use crate::mem::{MemoryManager, PAGE_SIZE};
+use crate::arch::process::{Thread, EXIT_THREAD, RETURN_FROM_ISR};
+use crate::mem::MemoryManager;
 use crate::services::SystemServices;
+use crate::syscall::SysCallOutcome;
 use riscv::register::{scause, sepc, sie, sstatus, stval, vexriscv::sim, vexriscv::sip};
 use xous_kernel::{SysCall, PID, TID};
 
@@ -12,7 +13,8 @@ extern "Rust" {
 }
 
 extern "C" {
-    fn flush_mmu();
+    fn _xous_raise_soft_irq();
+    fn _xous_clear_soft_irq();
 }
 
 /// Disable external interrupts
@@ -36,14 +38,83 @@ pub fn disable_irq(irq_no: usize) -> Result<(), xous_kernel::Error> {
     Ok(())
 }
 
+/// Make a supervisor software interrupt pending, which is taken as soon as
+/// interrupts are enabled again.  The trap handler picks up whatever
+/// `crate::irq::raise()` asked for at that point.
+pub fn raise_soft_irq() {
+    unsafe { _xous_raise_soft_irq() };
+}
+
 static mut PREVIOUS_PAIR: Option<(PID, TID)> = None;
 
 pub unsafe fn set_isr_return_pair(pid: PID, tid: TID) {
     PREVIOUS_PAIR = Some((pid, tid));
 }
 
-pub unsafe fn take_isr_return_pair() -> Option<(PID, TID)> {
-    PREVIOUS_PAIR.take()
+/// The process and thread that were running when the current interrupt hit.
+pub unsafe fn isr_return_pair() -> Option<(PID, TID)> {
+    PREVIOUS_PAIR
+}
+
+/// Fill `words` from the top of an interrupted thread's stack, leaving it
+/// zeroed if the stack isn't currently mapped.
+fn read_stack(sp: usize, words: &mut [usize]) {
+    let last = sp.wrapping_add((words.len() - 1) * core::mem::size_of::<usize>());
+    if sp & 3 != 0
+        || crate::arch::mem::virt_to_phys(sp).is_err()
+        || crate::arch::mem::virt_to_phys(last).is_err()
+    {
+        return;
+    }
+    unsafe {
+        // The stack belongs to userspace, so allow the kernel to read it.
+        sstatus::set_sum();
+        for (index, word) in words.iter_mut().enumerate() {
+            *word = (sp as *const usize).add(index).read_volatile();
+        }
+        sstatus::clear_sum();
+    }
+}
+
+/// Read the words at the top of an interrupted thread's stack for the
+/// profiler.
+#[cfg(feature = "profile")]
+fn stack_words(sp: usize) -> [usize; crate::profile::STACK_WORDS] {
+    let mut words = [0; crate::profile::STACK_WORDS];
+    read_stack(sp, &mut words);
+    words
+}
+
+/// Terminate the current process, which faulted with no way to recover,
+/// keeping a report of the crash and telling the supervisor about it.  The
+/// parent is resumed in its place, as if the process had terminated itself.
+fn crash_current_process(pid: PID, cause: usize, pc: usize, addr: usize) -> ! {
+    let tid = crate::arch::process::current_tid();
+    let mut words = [0; crate::fault::CRASH_WORDS];
+    ArchProcess::with_current(|process| {
+        let registers = &process.current_thread().registers;
+        words[..registers.len()].copy_from_slice(registers);
+        read_stack(registers[1], &mut words[registers.len()..]);
+    });
+    SystemServices::with_mut(|ss| {
+        let ppid = ss
+            .get_process(pid)
+            .expect("crashed process doesn't exist")
+            .ppid;
+        let supervisor = crate::fault::record(pid, ppid, tid, cause, pc, addr, &words);
+        ss.switch_from_thread(pid, tid)
+            .expect("couldn't switch away from crashed thread");
+        ss.terminate_process(pid)
+            .expect("couldn't terminate crashed process");
+        if let Some(id) = supervisor {
+            ss.signal_notification(id, 1).ok();
+        }
+        ss.switch_to_thread(ppid, None)
+            .expect("couldn't resume parent of crashed process");
+    });
+    ArchProcess::with_current_mut(|process| {
+        crate::arch::syscall::resume(current_pid().get() == 1, process.current_thread())
+    })
 }
 
 /// Trap entry point rust (_start_trap_rust)
@@ -95,8 +166,8 @@ pub extern "C" fn trap_handler(
             })
         });
 
-        let response =
-            crate::syscall::handle(pid, tid, call).unwrap_or_else(|e| xous_kernel::Result::Error(e));
+        let response = crate::syscall::handle(pid, tid, call)
+            .unwrap_or_else(|e| SysCallOutcome::Return(xous_kernel::Result::Error(e)));
 
         // println!("Syscall Result: {:?}", response);
         ArchProcess::with_current_mut(|p| {
@@ -104,11 +175,15 @@ pub extern "C" fn trap_handler(
             // If we're resuming a process that was previously sleeping, restore the
             // context. Otherwise, keep the context the same but pass the return
             // values in 8 return registers.
-            if response == xous_kernel::Result::ResumeProcess {
-                crate::arch::syscall::resume(current_pid().get() == 1, thread);
-            } else {
-                // println!("Returning to address {:08x}", thread.sepc);
-                unsafe { _xous_syscall_return_result(&response, thread) };
+            match response {
+                SysCallOutcome::Return(result) => {
+                    // println!("Returning to address {:08x}", thread.sepc);
+                    crate::arch::mem::flush_pending();
+                    unsafe { _xous_syscall_return_result(&result, thread) };
+                }
+                SysCallOutcome::Resume | SysCallOutcome::Blocked => {
+                    crate::arch::syscall::resume(current_pid().get() == 1, thread);
+                }
             }
         });
     }
@@ -121,49 +196,76 @@ pub extern "C" fn trap_handler(
         match ex {
             RiscvException::StorePageFault(pc, addr) | RiscvException::LoadPageFault(pc, addr) => {
                 println!("Fault {} @ {:08x}, addr {:08x}", ex, pc, addr);
-                let entry = crate::arch::mem::pagetable_entry(addr).unwrap_or_else(|x| {
-                    // MemoryManagerHandle::get().print_ownership();
-                    MemoryMapping::current().print_map();
-                    panic!(
+                match crate::arch::mem::pagetable_entry(addr) {
+                    Err(x) => println!(
                         "error {:?} at {:08x}: memory not mapped or reserved for addr {:08x}",
                         x, pc, addr
-                    );
-                });
-                let flags = *entry & 0x1ff;
-
-                // If the flags are nonzero, but the "Valid" bit is not 1 and
-                // the page isn't shared, then this is a reserved page. Allocate
-                // a real page to back it and resume execution.
-                if flags & 1 == 0 && flags != 0 && flags & (1 << 8) == 0 {
-                    let new_page = MemoryManager::with_mut(|mm| {
-                        mm.alloc_page(pid).expect("Couldn't allocate new page")
-                    });
-                    let ppn1 = (new_page >> 22) & ((1 << 12) - 1);
-                    let ppn0 = (new_page >> 12) & ((1 << 10) - 1);
-                    unsafe {
-                        // Map the page to our process
-                        *entry = (ppn1 << 20)
-                            | (ppn0 << 10)
-                            | (flags | (1 << 0) /* valid */ | (1 << 6) /* D */ | (1 << 7)/* A */);
-                        flush_mmu();
-
-                        // Zero-out the page
-                        let virt = addr & !0xfff;
-                        (virt as *mut usize)
-                            .write_bytes(0, PAGE_SIZE / core::mem::size_of::<usize>());
-
-                        // Move the page into userspace
-                        *entry = (ppn1 << 20)
-                            | (ppn0 << 10)
-                            | (flags | (1 << 0) /* valid */ | (1 << 4) /* USER */ | (1 << 6) /* D */ | (1 << 7)/* A */);
-                        flush_mmu();
-                    };
+                    ),
+                    Ok(_) => {
+                        // Writing to a copy-on-write page gives this process its own
+                        // copy of the page, after which the write can be retried.
+                        // If there's no memory for the copy, the fault is left to
+                        // the process' exception handler, or else crashes it.
+                        if let RiscvException::StorePageFault(_, _) = ex {
+                            if crate::arch::mem::page_is_cow(addr) {
+                                match MemoryManager::with_mut(|mm| {
+                                    mm.copy_on_write(pid, addr & !0xfff)
+                                }) {
+                                    Ok(()) => ArchProcess::with_current_mut(|process| {
+                                        crate::arch::syscall::resume(
+                                            current_pid().get() == 1,
+                                            process.current_thread(),
+                                        )
+                                    }),
+                                    Err(e) => println!(
+                                        "PID {} couldn't copy copy-on-write page {:08x}: {:?}",
+                                        pid, addr, e
+                                    ),
+                                }
+                            }
+                        }
 
+                        // If this is a reserved page, allocate a real page to back it
+                        // and resume execution.
+                        if MemoryManager::with_mut(|mm| {
+                            crate::arch::mem::commit_reserved_page(mm, pid, addr)
+                                .expect("Couldn't allocate new page")
+                        }) {
+                            ArchProcess::with_current_mut(|process| {
+                                crate::arch::syscall::resume(
+                                    current_pid().get() == 1,
+                                    process.current_thread(),
+                                )
+                            });
+                        }
+                    }
+                }
+            }
+            RiscvException::IllegalInstruction(_pc, _insn) => {
+                if sstatus::read().spp() == sstatus::SPP::User {
+                    let tid = crate::arch::process::current_tid();
+                    ArchProcess::with_current_mut(|process| {
+                        if crate::arch::emulate::emulate_illegal_instruction(
+                            pid,
+                            tid,
+                            process.current_thread_mut(),
+                        ) {
+                            crate::arch::syscall::resume(false, process.current_thread());
+                        }
+                    });
+                }
+            }
+            #[cfg(feature = "emulate-misaligned")]
+            RiscvException::LoadAddressMisaligned(_pc, _addr)
+            | RiscvException::StoreAddressMisaligned(_pc, _addr) => {
+                if sstatus::read().spp() == sstatus::SPP::User {
                     ArchProcess::with_current_mut(|process| {
-                        crate::arch::syscall::resume(
-                            current_pid().get() == 1,
-                            process.current_thread(),
-                        )
+                        if crate::arch::emulate::emulate_misaligned(
+                            pid,
+                            process.current_thread_mut(),
+                        ) {
+                            crate::arch::syscall::resume(false, process.current_thread());
+                        }
                     });
                 }
             }
@@ -195,6 +297,37 @@ pub extern "C" fn trap_handler(
             }
             _ => (),
         }
+
+        // Let the process deal with the exception itself, if it has asked to.
+        // The faulting thread is replaced by a call to its handler, which runs
+        // on the thread's alternate exception stack if one was registered.
+        if let sstatus::SPP::User = sstatus::read().spp() {
+            let tid = crate::arch::process::current_tid();
+            let sp = ArchProcess::with_current(|process| process.current_thread().registers[1]);
+            if let Some((handler, sp)) =
+                SystemServices::with_mut(|ss| ss.begin_exception(pid, tid, sp))
+            {
+                ArchProcess::with_current_mut(|process| {
+                    crate::arch::syscall::invoke(
+                        process.current_thread_mut(),
+                        false,
+                        handler.get(),
+                        sp & !0xf,
+                        EXIT_THREAD,
+                        &[sc.bits(), sepc::read(), stval::read()],
+                    );
+                    crate::arch::syscall::resume(false, process.current_thread())
+                });
+            }
+        }
+
+        // Otherwise, a fault in userspace only takes down the process that
+        // caused it.
+        if let sstatus::SPP::User = sstatus::read().spp() {
+            println!("PID {} crashed: {}", pid, ex);
+            crash_current_process(pid, sc.bits(), sepc::read(), stval::read());
+        }
+
         println!("SYSTEM HALT: CPU Exception on PID {}: {}", pid, ex);
         ArchProcess::with_current(|process| {
             println!("Current thread {}:", process.current_tid());
@@ -203,7 +336,10 @@ pub extern "C" fn trap_handler(
         MemoryMapping::current().print_map();
         loop {}
     } else {
-        let irqs_pending = sip::read();
+        // A software interrupt stays pending until it's cleared, so clear it
+        // before handling whatever was raised.
+        unsafe { _xous_clear_soft_irq() };
+        let irqs_pending = sip::read() | crate::irq::take_raised();
         // Safe to access globals since interrupts are disabled
         // when this function runs.
         unsafe {
@@ -215,6 +351,20 @@ pub extern "C" fn trap_handler(
             //     println!("ISR: Previous pair is not None");
             }
         }
+        #[cfg(feature = "profile")]
+        {
+            if crate::profile::should_sample(crate::arch::timer::ticked(irqs_pending)) {
+                ArchProcess::with_current(|process| {
+                    let thread = process.current_thread();
+                    crate::profile::record(
+                        pid,
+                        process.current_tid(),
+                        thread.sepc,
+                        stack_words(thread.registers[1]),
+                    );
+                });
+            }
+        }
         crate::irq::handle(irqs_pending).expect("Couldn't handle IRQ");
         ArchProcess::with_current_mut(|process| {
             crate::arch::syscall::resume(current_pid().get() == 1, process.current_thread())
@@ -1,6 +1,6 @@
 use crate::mem::MemoryManager;
 use core::fmt;
-use riscv::register::satp;
+use riscv::register::{satp, sstatus};
 use xous_kernel::{MemoryFlags, PID};
 
 // pub const DEFAULT_STACK_TOP: usize = 0x8000_0000;
@@ -10,6 +10,7 @@ pub const DEFAULT_BASE: usize = 0x6000_0000;
 
 pub const USER_AREA_END: usize = 0xff00_0000;
 pub const PAGE_SIZE: usize = 4096;
+pub const MEGAPAGE_SIZE: usize = 4 * 1024 * 1024;
 const PAGE_TABLE_OFFSET: usize = 0xff40_0000;
 const PAGE_TABLE_ROOT_OFFSET: usize = 0xff80_0000;
 
@@ -17,6 +18,60 @@ extern "C" {
     fn flush_mmu();
 }
 
+/// How many individual pages may be waiting to be flushed before it's cheaper
+/// to flush the entire TLB instead.
+const MAX_PENDING_FLUSHES: usize = 16;
+
+/// Pages whose entries have changed since the TLB was last flushed, stored as
+/// `(asid, virt)` pairs.  If `PENDING_FLUSH_COUNT` exceeds
+/// `MAX_PENDING_FLUSHES`, then the whole TLB needs to be flushed.
+static mut PENDING_FLUSHES: [(usize, usize); MAX_PENDING_FLUSHES] = [(0, 0); MAX_PENDING_FLUSHES];
+static mut PENDING_FLUSH_COUNT: usize = 0;
+
+/// Immediately flush the TLB entry for a single page in the current address
+/// space.  Use this when the kernel itself is about to access the page, and
+/// `flush_mmu()` when a root pagetable entry has changed.
+pub fn flush_page(virt: usize) {
+    unsafe { riscv::asm::sfence_vma(satp::read().asid(), virt & !(PAGE_SIZE - 1)) };
+}
+
+/// Note that the entry for `virt` in the current address space has changed,
+/// but that only userspace will observe the change.  The flush is put off
+/// until `flush_pending()` is called before returning to userspace, so that a
+/// syscall that modifies many pages only needs to fence once.
+///
+/// Flushes are tagged with the ASID, so it's fine to switch to another
+/// address space in the meantime.  CPUs that don't implement ASIDs treat
+/// these as ordinary flushes.
+fn defer_flush(virt: usize) {
+    unsafe {
+        if PENDING_FLUSH_COUNT < MAX_PENDING_FLUSHES {
+            PENDING_FLUSHES[PENDING_FLUSH_COUNT] = (satp::read().asid(), virt & !(PAGE_SIZE - 1));
+        }
+        PENDING_FLUSH_COUNT = PENDING_FLUSH_COUNT.saturating_add(1);
+    }
+}
+
+/// Flush every entry in the TLB, regardless of ASID.
+pub fn flush_all() {
+    unsafe { flush_mmu() };
+}
+
+/// Perform any flushes put off by `defer_flush()`.  This must be called before
+/// returning to userspace.
+pub fn flush_pending() {
+    unsafe {
+        if PENDING_FLUSH_COUNT > MAX_PENDING_FLUSHES {
+            flush_mmu();
+        } else {
+            for (asid, virt) in PENDING_FLUSHES[..PENDING_FLUSH_COUNT].iter() {
+                riscv::asm::sfence_vma(*asid, *virt);
+            }
+        }
+        PENDING_FLUSH_COUNT = 0;
+    }
+}
+
 bitflags! {
     pub struct MMUFlags: usize {
         const NONE      = 0b00_0000_0000;
@@ -42,7 +97,7 @@ impl core::fmt::Debug for MemoryMapping {
     fn fmt(&self, fmt: &mut core::fmt::Formatter) -> core::result::Result<(), core::fmt::Error> {
         write!(
             fmt,
-            "(satp: 0x{:08x}, mode: {}, ASID: {}, PPN: {:08x})",
+            "(satp: 0x{:08x}, mode: {}, PID: {}, PPN: {:08x})",
             self.satp,
             self.satp >> 31,
             self.satp >> 22 & ((1 << 9) - 1),
@@ -96,11 +151,17 @@ impl MemoryMapping {
     /// may be found at virtual address `PAGE_TABLE_ROOT_OFFSET`.
     pub fn current() -> MemoryMapping {
         MemoryMapping {
-            satp: satp::read().bits(),
+            satp: crate::arch::process::satp_with_asid(
+                satp::read().bits(),
+                crate::arch::process::current_pid().get() as usize,
+            ),
         }
     }
 
-    /// Get the "PID" (actually, ASID) from the current mapping
+    /// Get the PID from the current mapping.  The loader stores the PID in the
+    /// ASID field of `satp`, and that's where it stays in the kernel's copy.
+    /// The ASID that actually gets loaded into `satp` is assigned separately
+    /// by `activate()`.
     pub fn get_pid(&self) -> PID {
         PID::new((self.satp >> 22 & ((1 << 9) - 1)) as _).unwrap()
     }
@@ -110,8 +171,19 @@ impl MemoryMapping {
     /// kernel, which should be mapped into every possible address space.
     /// As such, this will only have an observable effect once code returns
     /// to userspace.
+    ///
+    /// Switching to a different address space is tagged with the ASID of the
+    /// process that owns it, so the TLB doesn't need to be flushed.  If there
+    /// aren't enough ASIDs to go around, the TLB is flushed instead.
     pub fn activate(self) -> Result<(), xous_kernel::Error> {
-        satp::write(self.satp);
+        let asid = crate::arch::process::asid(self.get_pid());
+        let new_satp = crate::arch::process::satp_with_asid(self.satp, asid);
+        if satp::read().bits() != new_satp {
+            satp::write(new_satp);
+            if asid == 0 {
+                flush_all();
+            }
+        }
         Ok(())
     }
 
@@ -131,6 +203,12 @@ impl MemoryMapping {
                 MMUFlags::from_bits(l1_entry & 0xff).unwrap()
             );
 
+            // Megapages are leaves in the root table, so there's no second
+            // level to print.
+            if is_megapage(*l1_entry) {
+                continue;
+            }
+
             // Page 1023 is only available to PID1
             if i == 1023 {
                 if self.get_pid().get() != 1 {
@@ -157,6 +235,36 @@ impl MemoryMapping {
         println!("End of map");
     }
 
+    /// Print every page mapped into this process as a `MAP` line, which the
+    /// `memory-map` tool can turn into a picture of the address space.
+    #[cfg(feature = "print-mappings")]
+    pub fn dump_map(&self) {
+        let pid = self.get_pid();
+        let l1_pt = unsafe { &mut (*(PAGE_TABLE_ROOT_OFFSET as *mut RootPageTable)) };
+        for (i, l1_entry) in l1_pt.entries.iter().enumerate() {
+            if *l1_entry == 0 {
+                continue;
+            }
+            let superpage_addr = i * MEGAPAGE_SIZE;
+            if is_megapage(*l1_entry) {
+                dump_entry(pid, superpage_addr, *l1_entry, MEGAPAGE_SIZE);
+                continue;
+            }
+
+            // Page 1023 is only available to PID1
+            if i == 1023 && pid.get() != 1 {
+                continue;
+            }
+            let l0_pt = unsafe { &mut (*((PAGE_TABLE_OFFSET + i * 4096) as *mut LeafPageTable)) };
+            for (j, l0_entry) in l0_pt.entries.iter().enumerate() {
+                if *l0_entry & 0x7 == 0 {
+                    continue;
+                }
+                dump_entry(pid, superpage_addr + j * PAGE_SIZE, *l0_entry, PAGE_SIZE);
+            }
+        }
+    }
+
     pub fn reserve_address(
         &mut self,
         mm: &mut MemoryManager,
@@ -254,6 +362,100 @@ impl fmt::Display for LeafPageTable {
     }
 }
 
+/// What the region of the address space that `addr` falls in is used for
+#[cfg(feature = "print-mappings")]
+fn region_tag(pid: PID, addr: usize) -> &'static str {
+    if crate::image::reserved(pid, addr, PAGE_SIZE) {
+        "image"
+    } else if addr >= USER_AREA_END {
+        "kernel"
+    } else if addr >= DEFAULT_BASE {
+        "default"
+    } else if addr >= DEFAULT_MESSAGE_BASE {
+        "message"
+    } else if addr >= DEFAULT_HEAP_BASE {
+        "heap"
+    } else {
+        "program"
+    }
+}
+
+/// Print a page table entry as `MAP <pid> <virt> <phys> <size> <flags> <tag>`,
+/// with the flags written as `rwxusp` and a `-` for each one that is clear.
+#[cfg(feature = "print-mappings")]
+fn dump_entry(pid: PID, virt: usize, entry: usize, size: usize) {
+    let flags = MMUFlags::from_bits_truncate(entry);
+    let flag = |bit, c| if flags.contains(bit) { c } else { '-' };
+    println!(
+        "MAP {} {:08x} {:08x} {:x} {}{}{}{}{}{} {}",
+        pid,
+        virt,
+        (entry >> 10) << 12,
+        size,
+        flag(MMUFlags::R, 'r'),
+        flag(MMUFlags::W, 'w'),
+        flag(MMUFlags::X, 'x'),
+        flag(MMUFlags::USER, 'u'),
+        flag(MMUFlags::S, 's'),
+        flag(MMUFlags::P, 'p'),
+        region_tag(pid, virt)
+    );
+}
+
+/// A root pagetable entry is a megapage if it is valid and has any of the
+/// `RWX` bits set.  Otherwise it points to a second-level pagetable.
+fn is_megapage(l1_entry: usize) -> bool {
+    l1_entry & MMUFlags::VALID.bits() != 0
+        && l1_entry & (MMUFlags::R | MMUFlags::W | MMUFlags::X).bits() != 0
+}
+
+/// If `virt` lies inside a megapage, replace the megapage with a freshly
+/// allocated second-level pagetable containing 1024 equivalent 4 KiB
+/// entries.  This is a no-op if `virt` isn't part of a megapage.
+///
+/// # Errors
+///
+/// * OutOfMemory - Couldn't allocate the new pagetable
+fn split_megapage(mm: &mut MemoryManager, virt: usize) -> Result<(), xous_kernel::Error> {
+    let vpn1 = (virt >> 22) & ((1 << 10) - 1);
+
+    let l1_pt = unsafe { &mut (*(PAGE_TABLE_ROOT_OFFSET as *mut RootPageTable)) };
+    let megapage = l1_pt.entries[vpn1];
+    if !is_megapage(megapage) {
+        return Ok(());
+    }
+
+    // Allocate the new pagetable and map it in so we can fill it in.  The
+    // root entry keeps pointing at the megapage until the new table is
+    // complete.
+    let pid = crate::arch::current_pid();
+    let l0pt_phys = mm.alloc_page(pid)?;
+    let l0pt_virt = PAGE_TABLE_OFFSET + vpn1 * PAGE_SIZE;
+    if let Err(e) = map_page_inner(
+        mm,
+        pid,
+        l0pt_phys,
+        l0pt_virt,
+        MemoryFlags::W | MemoryFlags::R,
+        false,
+    ) {
+        mm.release_page(l0pt_phys as *mut usize, pid).ok();
+        return Err(e);
+    }
+
+    let phys_base = (megapage >> 10) << 12;
+    let flags = megapage & 0x3ff;
+    let l0_pt = unsafe { &mut (*(l0pt_virt as *mut LeafPageTable)) };
+    for (i, entry) in l0_pt.entries.iter_mut().enumerate() {
+        *entry = (((phys_base + i * PAGE_SIZE) >> 12) << 10) | flags;
+    }
+
+    // Swap the megapage for the new table.
+    l1_pt.entries[vpn1] = ((l0pt_phys >> 12) << 10) | MMUFlags::VALID.bits();
+    unsafe { flush_mmu() };
+    Ok(())
+}
+
 /// When we allocate pages, they are owned by the kernel so we can zero
 /// them out.  After that is done, hand the page to the user.
 pub fn hand_page_to_user(virt: *mut u8) -> Result<(), xous_kernel::Error> {
@@ -281,6 +483,13 @@ pub fn hand_page_to_user(virt: *mut u8) -> Result<(), xous_kernel::Error> {
         return Err(xous_kernel::Error::BadAddress);
     }
 
+    // Megapages are handed over all at once.
+    if is_megapage(l1_pt[vpn1]) {
+        l1_pt[vpn1] |= MMUFlags::USER.bits();
+        unsafe { flush_mmu() };
+        return Ok(());
+    }
+
     // Ensure the entry hasn't already been mapped.
     if l0_pt.entries[vpn0] & 1 == 0 {
         return Err(xous_kernel::Error::BadAddress);
@@ -288,7 +497,7 @@ pub fn hand_page_to_user(virt: *mut u8) -> Result<(), xous_kernel::Error> {
 
     // Add the USER flag to the entry
     l0_pt.entries[vpn0] |= MMUFlags::USER.bits();
-    unsafe { flush_mmu() };
+    defer_flush(virt);
 
     Ok(())
 }
@@ -306,6 +515,21 @@ pub fn map_page_inner(
     virt: usize,
     req_flags: MemoryFlags,
     map_user: bool,
+) -> Result<(), xous_kernel::Error> {
+    map_page_noflush(mm, pid, phys, virt, req_flags, map_user)?;
+    flush_page(virt);
+    Ok(())
+}
+
+/// Map the given page as `map_page_inner()` does, but leave it to the caller
+/// to flush the TLB entry for it.
+fn map_page_noflush(
+    mm: &mut MemoryManager,
+    pid: PID,
+    phys: usize,
+    virt: usize,
+    req_flags: MemoryFlags,
+    map_user: bool,
 ) -> Result<(), xous_kernel::Error> {
     let ppn1 = (phys >> 22) & ((1 << 12) - 1);
     let ppn0 = (phys >> 12) & ((1 << 10) - 1);
@@ -329,6 +553,37 @@ pub fn map_page_inner(
     assert!(vpn0 < 1024);
     assert!(vpo < 4096);
 
+    ensure_leaf_table(mm, pid, virt)?;
+
+    // Subsequent pagetables are defined as being mapped starting at
+    // offset 0x0020_0004, so 4 must be added to the ppn1 value.
+    let l0pt_virt = PAGE_TABLE_OFFSET + vpn1 * PAGE_SIZE;
+    let ref mut l0_pt = unsafe { &mut (*(l0pt_virt as *mut LeafPageTable)) };
+
+    // Ensure the entry hasn't already been mapped.
+    if l0_pt.entries[vpn0 as usize] & 1 != 0 {
+        panic!("Page {:08x} already allocated!", virt);
+    }
+    l0_pt.entries[vpn0 as usize] =
+        (ppn1 << 20) | (ppn0 << 10) | (flags | MMUFlags::VALID | MMUFlags::D | MMUFlags::A).bits();
+
+    Ok(())
+}
+
+/// Make sure there is a second-level pagetable covering `virt` in the current
+/// address space, allocating one to `pid` if there isn't.
+///
+/// # Errors
+///
+/// * MemoryInUse - The address is already covered by a megapage
+/// * OutOfMemory - A new pagetable couldn't be allocated
+fn ensure_leaf_table(
+    mm: &mut MemoryManager,
+    pid: PID,
+    virt: usize,
+) -> Result<(), xous_kernel::Error> {
+    let vpn1 = (virt >> 22) & ((1 << 10) - 1);
+
     // The root (l1) pagetable is defined to be mapped into our virtual
     // address space at this address.
     let l1_pt = unsafe { &mut (*(PAGE_TABLE_ROOT_OFFSET as *mut RootPageTable)) };
@@ -337,7 +592,11 @@ pub fn map_page_inner(
     // Subsequent pagetables are defined as being mapped starting at
     // offset 0x0020_0004, so 4 must be added to the ppn1 value.
     let l0pt_virt = PAGE_TABLE_OFFSET + vpn1 * PAGE_SIZE;
-    let ref mut l0_pt = unsafe { &mut (*(l0pt_virt as *mut LeafPageTable)) };
+
+    // This address is already covered by a megapage.
+    if is_megapage(l1_pt[vpn1 as usize]) {
+        return Err(xous_kernel::Error::MemoryInUse);
+    }
 
     // Allocate a new level 1 pagetable entry if one doesn't exist.
     if l1_pt[vpn1 as usize] & MMUFlags::VALID.bits() == 0 {
@@ -363,18 +622,83 @@ pub fn map_page_inner(
         let page_addr = l0pt_virt as *mut usize;
         unsafe { page_addr.write_bytes(0, PAGE_SIZE / core::mem::size_of::<usize>()) };
     }
+    Ok(())
+}
 
-    // Ensure the entry hasn't already been mapped.
-    if l0_pt.entries[vpn0 as usize] & 1 != 0 {
-        panic!("Page {:08x} already allocated!", virt);
+/// Map a single 4 MiB megapage into the specified process table.  Both
+/// `phys` and `virt` must be aligned to `MEGAPAGE_SIZE`, and the root
+/// pagetable entry for `virt` must currently be unused.
+///
+/// # Errors
+///
+/// * BadAlignment - Either address isn't aligned to a megapage
+/// * BadAddress - No `RWX` permissions were requested
+/// * MemoryInUse - Part of the range is already mapped
+pub fn map_megapage_inner(
+    _mm: &mut MemoryManager,
+    _pid: PID,
+    phys: usize,
+    virt: usize,
+    req_flags: MemoryFlags,
+    map_user: bool,
+) -> Result<(), xous_kernel::Error> {
+    if phys & (MEGAPAGE_SIZE - 1) != 0 || virt & (MEGAPAGE_SIZE - 1) != 0 {
+        return Err(xous_kernel::Error::BadAlignment);
     }
-    l0_pt.entries[vpn0 as usize] =
-        (ppn1 << 20) | (ppn0 << 10) | (flags | MMUFlags::VALID | MMUFlags::D | MMUFlags::A).bits();
+    let vpn1 = (virt >> 22) & ((1 << 10) - 1);
+
+    // A leaf entry with no `RWX` bits would instead be a pointer to
+    // another pagetable.
+    let flags = translate_flags(req_flags);
+    if flags.is_empty() {
+        return Err(xous_kernel::Error::BadAddress);
+    }
+    let flags = flags
+        | if map_user {
+            MMUFlags::USER
+        } else {
+            MMUFlags::NONE
+        };
+
+    let l1_pt = unsafe { &mut (*(PAGE_TABLE_ROOT_OFFSET as *mut RootPageTable)) };
+    if l1_pt.entries[vpn1] & MMUFlags::VALID.bits() != 0 {
+        return Err(xous_kernel::Error::MemoryInUse);
+    }
+    l1_pt.entries[vpn1] =
+        ((phys >> 12) << 10) | (flags | MMUFlags::VALID | MMUFlags::D | MMUFlags::A).bits();
     unsafe { flush_mmu() };
 
     Ok(())
 }
 
+/// Unmap the whole megapage covering `virt` from the current process table.
+/// Unlike `unmap_page_inner()`, this never splits the megapage, so it never
+/// needs to allocate.
+///
+/// # Returns
+///
+/// The physical address of the start of the megapage
+///
+/// # Errors
+///
+/// * BadAddress - `virt` isn't covered by a megapage
+pub fn unmap_megapage_inner(
+    _mm: &mut MemoryManager,
+    virt: usize,
+) -> Result<usize, xous_kernel::Error> {
+    let vpn1 = (virt >> 22) & ((1 << 10) - 1);
+
+    let l1_pt = unsafe { &mut (*(PAGE_TABLE_ROOT_OFFSET as *mut RootPageTable)) };
+    let megapage = l1_pt.entries[vpn1];
+    if !is_megapage(megapage) {
+        return Err(xous_kernel::Error::BadAddress);
+    }
+    l1_pt.entries[vpn1] = 0;
+    unsafe { flush_mmu() };
+
+    Ok((megapage >> 10) << 12)
+}
+
 /// Get the pagetable entry for a given address, or `Err()` if the address is invalid
 pub fn pagetable_entry(addr: usize) -> Result<&'static mut usize, xous_kernel::Error> {
     if addr & 3 != 0 {
@@ -387,7 +711,7 @@ pub fn pagetable_entry(addr: usize) -> Result<&'static mut usize, xous_kernel::E
 
     let l1_pt = unsafe { &(*(PAGE_TABLE_ROOT_OFFSET as *mut RootPageTable)) };
     let l1_pte = l1_pt.entries[vpn1];
-    if l1_pte & 1 == 0 {
+    if l1_pte & 1 == 0 || is_megapage(l1_pte) {
         return Err(xous_kernel::Error::BadAddress);
     }
     let l0_pt_virt = PAGE_TABLE_OFFSET + vpn1 * PAGE_SIZE;
@@ -405,7 +729,9 @@ pub fn pagetable_entry(addr: usize) -> Result<&'static mut usize, xous_kernel::E
 /// # Errors
 ///
 /// * BadAddress - Address was not already mapped.
-pub fn unmap_page_inner(_mm: &mut MemoryManager, virt: usize) -> Result<usize, xous_kernel::Error> {
+/// * OutOfMemory - The page was part of a megapage that couldn't be split.
+pub fn unmap_page_inner(mm: &mut MemoryManager, virt: usize) -> Result<usize, xous_kernel::Error> {
+    split_megapage(mm, virt)?;
     let entry = pagetable_entry(virt)?;
 
     // Ensure the entry hasn't already been mapped.
@@ -414,7 +740,7 @@ pub fn unmap_page_inner(_mm: &mut MemoryManager, virt: usize) -> Result<usize, x
     }
     let phys = (*entry >> 10) << 12;
     *entry = 0;
-    unsafe { flush_mmu() };
+    defer_flush(virt);
 
     Ok(phys)
 }
@@ -428,6 +754,7 @@ pub fn move_page_inner(
     dest_space: &MemoryMapping,
     dest_addr: *mut u8,
 ) -> Result<(), xous_kernel::Error> {
+    split_megapage(mm, src_addr as usize)?;
     let entry = pagetable_entry(src_addr as usize)?;
     if *entry & MMUFlags::VALID.bits() == 0 {
         return Err(xous_kernel::Error::BadAddress);
@@ -435,7 +762,7 @@ pub fn move_page_inner(
     let previous_entry = *entry;
     // Invalidate the old entry
     *entry = 0;
-    unsafe { flush_mmu() };
+    defer_flush(src_addr as usize);
 
     dest_space.activate()?;
     let phys = previous_entry >> 10 << 12;
@@ -472,6 +799,7 @@ pub fn lend_page_inner(
     dest_addr: *mut u8,
     mutable: bool,
 ) -> Result<usize, xous_kernel::Error> {
+    split_megapage(mm, src_addr as usize)?;
     let entry = pagetable_entry(src_addr as usize)?;
     let phys = (*entry >> 10) << 12;
 
@@ -486,7 +814,7 @@ pub fn lend_page_inner(
         // unavailable here.  Set the "Shared" bit and clear the "VALID" bit.
         // Keep all other bits the same.
         *entry = (*entry & !MMUFlags::VALID.bits()) | MMUFlags::S.bits();
-        unsafe { flush_mmu() };
+        defer_flush(src_addr as usize);
 
         dest_space.activate()?;
         map_page_inner(
@@ -513,7 +841,7 @@ pub fn lend_page_inner(
             "Additionally, mapping {:08x} into PID {:08x} @ {:08x}",
             phys, dest_pid, dest_addr as usize
         );
-        unsafe { flush_mmu() };
+        defer_flush(src_addr as usize);
 
         dest_space.activate()?;
         map_page_inner(
@@ -525,12 +853,190 @@ pub fn lend_page_inner(
             dest_pid.get() != 1,
         )
     };
-    unsafe { flush_mmu() };
 
     src_space.activate().unwrap();
     result.map(|_| phys)
 }
 
+/// The number of pages handled each time `lend_range_inner()` or
+/// `move_range_inner()` switches between address spaces.
+const BATCH_PAGES: usize = 64;
+
+/// Ensure every page in the range is mapped in the current address space, and
+/// that none of them are already lent.  This is done before anything is
+/// modified so that a bad range leaves the page tables untouched.
+fn check_range(mm: &mut MemoryManager, virt: usize, len: usize) -> Result<(), xous_kernel::Error> {
+    for page in (virt..virt + len).step_by(PAGE_SIZE) {
+        split_megapage(mm, page)?;
+        let entry = pagetable_entry(page)?;
+        if *entry & MMUFlags::VALID.bits() == 0 {
+            return Err(xous_kernel::Error::BadAddress);
+        }
+        if *entry & MMUFlags::S.bits() != 0 {
+            return Err(xous_kernel::Error::ShareViolation);
+        }
+    }
+    Ok(())
+}
+
+/// Allocate any pagetables `pid` needs to map the range in `space`, and
+/// ensure none of its pages are in use.  This is done before the source
+/// space is modified so that mapping the range can't fail part of the way
+/// through, which would leave pages that belong to neither process.
+fn prepare_range(
+    mm: &mut MemoryManager,
+    pid: PID,
+    space: &MemoryMapping,
+    virt: usize,
+    len: usize,
+) -> Result<(), xous_kernel::Error> {
+    let previous = MemoryMapping::current();
+    space.activate()?;
+    let mut result = Ok(());
+    for page in (virt..virt + len).step_by(PAGE_SIZE) {
+        result = ensure_leaf_table(mm, pid, page).and_then(|_| {
+            if *pagetable_entry(page)? & (MMUFlags::VALID | MMUFlags::S).bits() != 0 {
+                return Err(xous_kernel::Error::MemoryInUse);
+            }
+            Ok(())
+        });
+        if result.is_err() {
+            break;
+        }
+    }
+    previous.activate().unwrap();
+    result
+}
+
+/// Lend an entire range of pages from one address space to another.  This
+/// behaves like calling `lend_page_inner()` on each page, but switches
+/// address spaces once per batch of pages rather than twice per page.
+///
+/// # Errors
+///
+/// * **BadAddress**: A page in the range isn't allocated
+/// * **ShareViolation**: A page in the range is already lent
+/// * **MemoryInUse**: A page in the destination range is already mapped
+/// * **OutOfMemory**: A pagetable couldn't be allocated in the destination
+#[allow(clippy::too_many_arguments)]
+pub fn lend_range_inner(
+    mm: &mut MemoryManager,
+    src_space: &MemoryMapping,
+    src_addr: usize,
+    dest_pid: PID,
+    dest_space: &MemoryMapping,
+    dest_addr: usize,
+    len: usize,
+    mutable: bool,
+) -> Result<(), xous_kernel::Error> {
+    check_range(mm, src_addr, len)?;
+    prepare_range(mm, dest_pid, dest_space, dest_addr, len)?;
+
+    let dest_flags = if mutable {
+        MemoryFlags::R | MemoryFlags::W
+    } else {
+        MemoryFlags::R
+    };
+    let mut phys = [0usize; BATCH_PAGES];
+    for batch in (0..len).step_by(BATCH_PAGES * PAGE_SIZE) {
+        let count = core::cmp::min(BATCH_PAGES, (len - batch) / PAGE_SIZE);
+
+        // Mark each page as lent in the source space, using the same
+        // encoding as `lend_page_inner()`.
+        for (index, phys) in phys[..count].iter_mut().enumerate() {
+            let entry = pagetable_entry(src_addr + batch + index * PAGE_SIZE)?;
+            *phys = (*entry >> 10) << 12;
+            *entry = if mutable {
+                (*entry & !MMUFlags::VALID.bits()) | MMUFlags::S.bits()
+            } else if *entry & MMUFlags::W.bits() != 0 {
+                (*entry & !MMUFlags::W.bits()) | (MMUFlags::P | MMUFlags::S).bits()
+            } else {
+                *entry | MMUFlags::S.bits()
+            };
+            defer_flush(src_addr + batch + index * PAGE_SIZE);
+        }
+
+        dest_space.activate().unwrap();
+        for (index, phys) in phys[..count].iter().enumerate() {
+            let virt = dest_addr + batch + index * PAGE_SIZE;
+            map_page_noflush(mm, dest_pid, *phys, virt, dest_flags, dest_pid.get() != 1)
+                .expect("destination range wasn't prepared");
+            defer_flush(virt);
+        }
+        src_space.activate().unwrap();
+    }
+    Ok(())
+}
+
+/// Move an entire range of pages from one address space to another, handing
+/// ownership of each page to `dest_pid`.  Like `lend_range_inner()`, address
+/// spaces are switched once per batch of pages.
+///
+/// # Errors
+///
+/// * **BadAddress**: A page in the range isn't allocated
+/// * **ShareViolation**: A page in the range is currently lent
+/// * **MemoryInUse**: A page in the range doesn't belong to the source, or a
+///   page in the destination range is already mapped
+/// * **OutOfMemory**: A pagetable couldn't be allocated in the destination
+pub fn move_range_inner(
+    mm: &mut MemoryManager,
+    src_space: &MemoryMapping,
+    src_addr: usize,
+    dest_pid: PID,
+    dest_space: &MemoryMapping,
+    dest_addr: usize,
+    len: usize,
+) -> Result<(), xous_kernel::Error> {
+    check_range(mm, src_addr, len)?;
+    prepare_range(mm, dest_pid, dest_space, dest_addr, len)?;
+
+    // Hand every page over before any mappings change, so that a page the
+    // source doesn't own leaves both processes as they were.
+    let src_pid = src_space.get_pid();
+    for page in (src_addr..src_addr + len).step_by(PAGE_SIZE) {
+        let phys = (*pagetable_entry(page)? >> 10) << 12;
+        if let Err(e) = mm.give_page(phys, src_pid, dest_pid) {
+            for given in (src_addr..page).step_by(PAGE_SIZE) {
+                let phys = (*pagetable_entry(given)? >> 10) << 12;
+                mm.give_page(phys, dest_pid, src_pid).unwrap();
+            }
+            return Err(e);
+        }
+    }
+
+    let mut entries = [0usize; BATCH_PAGES];
+    for batch in (0..len).step_by(BATCH_PAGES * PAGE_SIZE) {
+        let count = core::cmp::min(BATCH_PAGES, (len - batch) / PAGE_SIZE);
+
+        // Invalidate the old entries, remembering them so they can be
+        // recreated in the destination.
+        for (index, previous) in entries[..count].iter_mut().enumerate() {
+            let entry = pagetable_entry(src_addr + batch + index * PAGE_SIZE)?;
+            *previous = *entry;
+            *entry = 0;
+            defer_flush(src_addr + batch + index * PAGE_SIZE);
+        }
+
+        dest_space.activate().unwrap();
+        for (index, previous) in entries[..count].iter().enumerate() {
+            let virt = dest_addr + batch + index * PAGE_SIZE;
+            map_page_noflush(
+                mm,
+                dest_pid,
+                previous >> 10 << 12,
+                virt,
+                untranslate_flags(*previous),
+                dest_pid.get() != 1,
+            )
+            .expect("destination range wasn't prepared");
+            defer_flush(virt);
+        }
+        src_space.activate().unwrap();
+    }
+    Ok(())
+}
+
 /// Return a page from `src_space` back to `dest_space`.
 pub fn return_page_inner(
     _mm: &mut MemoryManager,
@@ -548,7 +1054,7 @@ pub fn return_page_inner(
     }
 
     *src_entry = 0;
-    unsafe { flush_mmu() };
+    defer_flush(src_addr as usize);
 
     dest_space.activate()?;
     let dest_entry =
@@ -570,12 +1076,170 @@ pub fn return_page_inner(
         };
         *dest_entry = *dest_entry & !(MMUFlags::S | MMUFlags::P).bits() | previous_flag.bits();
     }
-    unsafe { flush_mmu() };
+    defer_flush(dest_addr as usize);
 
     src_space.activate().unwrap();
     Ok(phys)
 }
 
+/// Determine whether the page containing `virt` is shared copy-on-write.  Such
+/// pages have `P` set without `S`, and are mapped read-only until written to.
+pub fn page_is_cow(virt: usize) -> bool {
+    match pagetable_entry(virt & !(PAGE_SIZE - 1)) {
+        Ok(entry) => {
+            *entry & (MMUFlags::VALID | MMUFlags::S | MMUFlags::P).bits()
+                == (MMUFlags::VALID | MMUFlags::P).bits()
+        }
+        Err(_) => false,
+    }
+}
+
+/// Determine whether the page containing `virt` is mapped writable in the
+/// current address space.
+pub fn page_is_writable(virt: usize) -> bool {
+    match pagetable_entry(virt & !(PAGE_SIZE - 1)) {
+        Ok(entry) => {
+            *entry & (MMUFlags::VALID | MMUFlags::W).bits()
+                == (MMUFlags::VALID | MMUFlags::W).bits()
+        }
+        Err(_) => false,
+    }
+}
+
+/// Share a page from one address space with another.  If the page is
+/// writable, then it is made read-only in both spaces and marked
+/// copy-on-write by setting `P`.  Read-only pages are simply mapped into
+/// both spaces.
+///
+/// # Returns
+///
+/// `true` if the page is now copy-on-write, or `false` if it was read-only
+///
+/// # Errors
+///
+/// * **BadAddress**: The source page isn't allocated
+/// * **ShareViolation**: The source page is currently lent
+pub fn share_page_cow_inner(
+    mm: &mut MemoryManager,
+    src_space: &MemoryMapping,
+    src_addr: *mut u8,
+    dest_pid: PID,
+    dest_space: &MemoryMapping,
+    dest_addr: *mut u8,
+) -> Result<bool, xous_kernel::Error> {
+    split_megapage(mm, src_addr as usize)?;
+    let entry = pagetable_entry(src_addr as usize)?;
+    if *entry & MMUFlags::VALID.bits() == 0 {
+        return Err(xous_kernel::Error::BadAddress);
+    }
+    if *entry & MMUFlags::S.bits() != 0 {
+        return Err(xous_kernel::Error::ShareViolation);
+    }
+    let phys = (*entry >> 10) << 12;
+
+    // Writable pages lose their `W` bit and gain `P` so that the first write
+    // from either side results in a fault.  Pages that are already
+    // copy-on-write have `P` set and `W` clear, and so are unchanged.
+    if *entry & MMUFlags::W.bits() != 0 {
+        *entry = (*entry & !MMUFlags::W.bits()) | MMUFlags::P.bits();
+        defer_flush(src_addr as usize);
+    }
+    let is_cow = *entry & MMUFlags::P.bits() != 0;
+    let flags = untranslate_flags(*entry);
+
+    dest_space.activate()?;
+    let result = map_page_inner(mm, dest_pid, phys, dest_addr as usize, flags, dest_pid.get() != 1)
+        .and_then(|_| {
+            if is_cow {
+                *pagetable_entry(dest_addr as usize)? |= MMUFlags::P.bits();
+                defer_flush(dest_addr as usize);
+            }
+            Ok(())
+        });
+
+    src_space.activate().unwrap();
+    result.map(|_| is_cow)
+}
+
+/// Resolve a write to a copy-on-write page in the current process.  If
+/// `new_phys` is specified, the contents of the page are copied into it and
+/// it replaces the shared page.  Otherwise, the current process was the last
+/// one sharing the page, and it simply becomes writable again.
+///
+/// # Errors
+///
+/// * **BadAddress**: The page isn't copy-on-write
+/// * **OutOfMemory**: No scratch mapping could be made to perform the copy
+pub fn finish_cow_inner(
+    mm: &mut MemoryManager,
+    pid: PID,
+    virt: usize,
+    new_phys: Option<usize>,
+) -> Result<(), xous_kernel::Error> {
+    let virt = virt & !(PAGE_SIZE - 1);
+    if !page_is_cow(virt) {
+        return Err(xous_kernel::Error::BadAddress);
+    }
+    let entry = pagetable_entry(virt)?;
+
+    if let Some(new_phys) = new_phys {
+        // Map the new page somewhere in the kernel's view of this process so
+        // that it can be filled in.
+        let scratch = mm.find_virtual_address(
+            core::ptr::null_mut(),
+            PAGE_SIZE,
+            xous_kernel::MemoryType::Default,
+        )? as usize;
+        map_page_inner(mm, pid, new_phys, scratch, MemoryFlags::R | MemoryFlags::W, false)?;
+
+        // The kernel can't read userspace pages, so temporarily take the
+        // original page away from the user while copying it.
+        let user = *entry & MMUFlags::USER.bits();
+        *entry &= !MMUFlags::USER.bits();
+        flush_page(virt);
+        unsafe {
+            core::ptr::copy_nonoverlapping(
+                virt as *const usize,
+                scratch as *mut usize,
+                PAGE_SIZE / core::mem::size_of::<usize>(),
+            );
+        }
+        *entry |= user;
+        unmap_page_inner(mm, scratch)?;
+
+        let ppn1 = (new_phys >> 22) & ((1 << 12) - 1);
+        let ppn0 = (new_phys >> 12) & ((1 << 10) - 1);
+        *entry = (ppn1 << 20) | (ppn0 << 10) | (*entry & 0x3ff);
+    }
+
+    *entry = (*entry & !MMUFlags::P.bits()) | MMUFlags::W.bits();
+    defer_flush(virt);
+    Ok(())
+}
+
+/// Make the page at `virt` in the current process read-only and
+/// executable.  Pages that are lent or shared copy-on-write are refused,
+/// since another mapping of them could still be writable.
+///
+/// # Errors
+///
+/// * **BadAddress**: The page isn't present, or isn't a user page
+/// * **ShareViolation**: The page is lent out or shared
+pub fn finalize_code_page(mm: &mut MemoryManager, virt: usize) -> Result<(), xous_kernel::Error> {
+    split_megapage(mm, virt)?;
+    let entry = pagetable_entry(virt)?;
+    let present = (MMUFlags::VALID | MMUFlags::USER).bits();
+    if *entry & present != present {
+        return Err(xous_kernel::Error::BadAddress);
+    }
+    if *entry & (MMUFlags::S | MMUFlags::P).bits() != 0 {
+        return Err(xous_kernel::Error::ShareViolation);
+    }
+    *entry = (*entry & !MMUFlags::W.bits()) | (MMUFlags::R | MMUFlags::X).bits();
+    defer_flush(virt);
+    Ok(())
+}
+
 pub fn virt_to_phys(virt: usize) -> Result<usize, xous_kernel::Error> {
     let vpn1 = (virt >> 22) & ((1 << 10) - 1);
     let vpn0 = (virt >> 12) & ((1 << 10) - 1);
@@ -595,6 +1259,11 @@ pub fn virt_to_phys(virt: usize) -> Result<usize, xous_kernel::Error> {
         return Err(xous_kernel::Error::BadAddress);
     }
 
+    // Addresses within a megapage are offset from its base
+    if is_megapage(l1_pt[vpn1]) {
+        return Ok(((l1_pt[vpn1] >> 10) << 12) + (virt & (MEGAPAGE_SIZE - 1) & !(PAGE_SIZE - 1)));
+    }
+
     // Ensure the entry hasn't already been mapped.
     if l0_pt.entries[vpn0] & 1 == 0 {
         return Err(xous_kernel::Error::BadAddress);
@@ -602,6 +1271,183 @@ pub fn virt_to_phys(virt: usize) -> Result<usize, xous_kernel::Error> {
     Ok((l0_pt.entries[vpn0] >> 10) << 12)
 }
 
+/// Copy `buf.len()` bytes out of the current process, starting at `virt`.
+/// Every page they come from must be mapped and readable by userspace.
+pub fn read_user(virt: usize, buf: &mut [u8]) -> Result<(), xous_kernel::Error> {
+    let end = match virt.checked_add(buf.len()) {
+        Some(end) if end <= USER_AREA_END => end,
+        _ => return Err(xous_kernel::Error::BadAddress),
+    };
+    let required = MMUFlags::VALID | MMUFlags::USER | MMUFlags::R;
+    for page in ((virt & !(PAGE_SIZE - 1))..end).step_by(PAGE_SIZE) {
+        if !MMUFlags::from_bits_truncate(*pagetable_entry(page)?).contains(required) {
+            return Err(xous_kernel::Error::BadAddress);
+        }
+    }
+    unsafe {
+        sstatus::set_sum();
+        for (offset, byte) in buf.iter_mut().enumerate() {
+            *byte = ((virt + offset) as *const u8).read_volatile();
+        }
+        sstatus::clear_sum();
+    }
+    Ok(())
+}
+
+/// Back the reserved page containing `virt` with a fresh, zeroed page, as
+/// happens when the current process first touches it.  Returns `false` if
+/// the page isn't reserved, such as because it is already backed.
+pub fn commit_reserved_page(
+    mm: &mut MemoryManager,
+    pid: PID,
+    virt: usize,
+) -> Result<bool, xous_kernel::Error> {
+    let virt = virt & !(PAGE_SIZE - 1);
+    let entry = pagetable_entry(virt)?;
+    let flags = *entry & 0x1ff;
+
+    // If the flags are nonzero, but the "Valid" bit is not 1 and the page
+    // isn't shared, then this is a reserved page.
+    if flags & MMUFlags::VALID.bits() != 0 || flags == 0 || flags & MMUFlags::S.bits() != 0 {
+        return Ok(false);
+    }
+
+    let new_page = mm.alloc_page(pid)?;
+    let ppn1 = (new_page >> 22) & ((1 << 12) - 1);
+    let ppn0 = (new_page >> 12) & ((1 << 10) - 1);
+    let valid = MMUFlags::VALID | MMUFlags::D | MMUFlags::A;
+    unsafe {
+        // Map the page to our process
+        *entry = (ppn1 << 20) | (ppn0 << 10) | flags | valid.bits();
+        flush_page(virt);
+
+        // Zero-out the page
+        (virt as *mut usize).write_bytes(0, PAGE_SIZE / core::mem::size_of::<usize>());
+
+        // Move the page into userspace
+        *entry = (ppn1 << 20) | (ppn0 << 10) | flags | (valid | MMUFlags::USER).bits();
+        flush_page(virt);
+    }
+    Ok(true)
+}
+
+/// Write `value` into the current process at `virt`, which must be
+/// word-aligned.  The page it lands in must be mapped writable by userspace,
+/// or be reserved, in which case it is backed first.
+pub fn write_user_word(
+    mm: &mut MemoryManager,
+    pid: PID,
+    virt: usize,
+    value: usize,
+) -> Result<(), xous_kernel::Error> {
+    if virt >= USER_AREA_END {
+        return Err(xous_kernel::Error::BadAddress);
+    }
+    commit_reserved_page(mm, pid, virt)?;
+    let required = MMUFlags::VALID | MMUFlags::USER | MMUFlags::W;
+    if !MMUFlags::from_bits_truncate(*pagetable_entry(virt)?).contains(required) {
+        return Err(xous_kernel::Error::BadAddress);
+    }
+    unsafe {
+        sstatus::set_sum();
+        (virt as *mut usize).write_volatile(value);
+        sstatus::clear_sum();
+    }
+    Ok(())
+}
+
+/// Zero the physical page `phys`, which nothing may have mapped, by mapping
+/// it at `scratch` in process `pid` for as long as that takes.  `scratch`
+/// must be unmapped, and already have a pagetable.
+pub fn wipe_page(
+    mm: &mut MemoryManager,
+    pid: PID,
+    phys: usize,
+    scratch: usize,
+) -> Result<(), xous_kernel::Error> {
+    map_page_inner(
+        mm,
+        pid,
+        phys,
+        scratch,
+        MemoryFlags::R | MemoryFlags::W,
+        false,
+    )?;
+    unsafe {
+        (scratch as *mut usize).write_bytes(0, PAGE_SIZE / core::mem::size_of::<usize>());
+    }
+    unmap_page_inner(mm, scratch)?;
+    flush_page(scratch);
+    Ok(())
+}
+
+/// Zero every page of main RAM that the current process has mapped into
+/// its user area, including ones that are read-only or lent to it.  Device
+/// registers are left alone.  The pages are left writable afterwards, so
+/// this is only for when the system is going down.
+pub fn wipe_user_pages(mm: &MemoryManager) {
+    let l1_pt = unsafe { &mut (*(PAGE_TABLE_ROOT_OFFSET as *mut RootPageTable)) };
+    let present = (MMUFlags::VALID | MMUFlags::USER).bits();
+    let writable = (MMUFlags::R | MMUFlags::W | MMUFlags::D).bits();
+    let wipe = |entry: &mut usize, virt: usize, size: usize| {
+        if *entry & present != present || !mm.is_main_memory(((*entry >> 10) << 12) as *mut u8) {
+            return;
+        }
+        *entry |= writable;
+        if size == MEGAPAGE_SIZE {
+            unsafe { flush_mmu() };
+        } else {
+            flush_page(virt);
+        }
+        unsafe {
+            sstatus::set_sum();
+            (virt as *mut usize).write_bytes(0, size / core::mem::size_of::<usize>());
+            sstatus::clear_sum();
+        }
+    };
+    for (vpn1, l1_pte) in l1_pt.entries.iter_mut().enumerate() {
+        if vpn1 * MEGAPAGE_SIZE >= USER_AREA_END {
+            break;
+        }
+        if is_megapage(*l1_pte) {
+            wipe(l1_pte, vpn1 * MEGAPAGE_SIZE, MEGAPAGE_SIZE);
+            continue;
+        }
+        if *l1_pte & MMUFlags::VALID.bits() == 0 {
+            continue;
+        }
+        let l0_pt_virt = PAGE_TABLE_OFFSET + vpn1 * PAGE_SIZE;
+        let l0_pt = unsafe { &mut (*(l0_pt_virt as *mut LeafPageTable)) };
+        for (vpn0, entry) in l0_pt.entries.iter_mut().enumerate() {
+            wipe(entry, vpn1 * MEGAPAGE_SIZE + vpn0 * PAGE_SIZE, PAGE_SIZE);
+        }
+    }
+}
+
+/// Call `f` with the virtual and physical address of every page that the
+/// current process has mapped writable into its user area.  Megapages are
+/// skipped, to keep a core dump from being mostly device registers.
+pub fn writable_user_pages<F: FnMut(usize, usize)>(mut f: F) {
+    let l1_pt = unsafe { &(*(PAGE_TABLE_ROOT_OFFSET as *const RootPageTable)) };
+    let writable = (MMUFlags::VALID | MMUFlags::USER | MMUFlags::W).bits();
+    for (vpn1, l1_pte) in l1_pt.entries.iter().enumerate() {
+        if vpn1 * MEGAPAGE_SIZE >= USER_AREA_END {
+            break;
+        }
+        if is_megapage(*l1_pte) || *l1_pte & MMUFlags::VALID.bits() == 0 {
+            continue;
+        }
+        let l0_pt_virt = PAGE_TABLE_OFFSET + vpn1 * PAGE_SIZE;
+        let l0_pt = unsafe { &(*(l0_pt_virt as *const LeafPageTable)) };
+        for (vpn0, entry) in l0_pt.entries.iter().enumerate() {
+            if *entry & writable == writable {
+                let virt = vpn1 * MEGAPAGE_SIZE + vpn0 * PAGE_SIZE;
+                f(virt, (*entry >> 10) << 12);
+            }
+        }
+    }
+}
+
 /// Determine whether a virtual address has been mapped
 pub fn address_available(virt: usize) -> bool {
     virt_to_phys(virt).is_err()
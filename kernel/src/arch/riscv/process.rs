@@ -16,7 +16,7 @@ pub const MAX_PROCESS_COUNT: usize = 32;
 pub const RETURN_FROM_ISR: usize = 0xff80_2000;
 
 /// This is the address a thread will return to when it exits.
-const EXIT_THREAD: usize = 0xff80_3000;
+pub const EXIT_THREAD: usize = 0xff80_3000;
 
 // Thread IDs have three possible meaning:
 // Logical Thread ID: What the user sees
@@ -54,7 +54,7 @@ struct ProcessImpl {
 
     /// Pad everything to 128 bytes, so the Thread slice starts at
     /// offset 128.
-    _padding: [u32; 14],
+    _padding: [u32; 6],
 
     /// This enables the kernel to keep track of threads in the
     /// target process, and know which threads are ready to
@@ -80,6 +80,105 @@ static mut PROCESS_TABLE: ProcessTable = ProcessTable {
     table: [false; MAX_PROCESS_COUNT],
 };
 
+/// Mask of the ASID field within `satp`.
+const SATP_ASID_MASK: usize = ((1 << 9) - 1) << 22;
+
+/// Address Space IDs handed out to processes.  Each process gets its own
+/// ASID the first time its address space is activated, which lets TLB
+/// entries from several processes coexist so switching between them doesn't
+/// need a flush.  ASID 0 is never handed out.  If the MMU doesn't have enough
+/// ASIDs to go around, every process shares ASID 0 and switching address
+/// spaces flushes the TLB instead.
+struct AsidTable {
+    /// The largest ASID the MMU implements, or `None` if it hasn't been
+    /// probed yet.
+    max: Option<usize>,
+
+    /// The next ASID to try handing out.
+    next: usize,
+
+    /// The ASID assigned to each process, indexed by PID - 1, or 0 if the
+    /// process doesn't have one.
+    assigned: [usize; MAX_PROCESS_COUNT],
+}
+
+static mut ASID_TABLE: AsidTable = AsidTable {
+    max: None,
+    next: 1,
+    assigned: [0; MAX_PROCESS_COUNT],
+};
+
+/// Find out how many ASID bits this MMU implements, then hand the kernel,
+/// which is the only thing that has run so far, its ASID.  The ASID field of
+/// `satp` is WARL, so any bits that aren't implemented read back as zero.
+fn init_asids(table: &mut AsidTable) -> usize {
+    let satp = riscv::register::satp::read().bits();
+    riscv::register::satp::write(satp | SATP_ASID_MASK);
+    let max = (riscv::register::satp::read().bits() & SATP_ASID_MASK) >> 22;
+
+    let kernel_asid = if max < MAX_PROCESS_COUNT {
+        0
+    } else {
+        table.assigned[0] = 1;
+        table.next = 2;
+        1
+    };
+    riscv::register::satp::write(satp_with_asid(satp, kernel_asid));
+    crate::arch::mem::flush_all();
+    max
+}
+
+/// Return the ASID belonging to `pid`, assigning one if it doesn't have one
+/// yet.  A return value of 0 means ASIDs aren't in use and the TLB must be
+/// flushed whenever the address space changes.
+pub fn asid(pid: PID) -> usize {
+    let table = unsafe { &mut ASID_TABLE };
+    let max = match table.max {
+        Some(max) => max,
+        None => {
+            let max = init_asids(table);
+            table.max = Some(max);
+            max
+        }
+    };
+    if max < MAX_PROCESS_COUNT {
+        return 0;
+    }
+
+    let pid_idx = pid.get() as usize - 1;
+    if table.assigned[pid_idx] != 0 {
+        return table.assigned[pid_idx];
+    }
+
+    // Hand out ASIDs in order, skipping any that are still in use.  An ASID
+    // that was released may still have entries in the TLB, so flush
+    // everything each time the counter wraps around.  Entries for ASIDs
+    // handed out since the last wrap belong to live processes, or were
+    // released and won't be handed out again until after the next flush.
+    loop {
+        if table.next > max {
+            table.next = 1;
+            crate::arch::mem::flush_all();
+        }
+        let candidate = table.next;
+        table.next += 1;
+        if !table.assigned.contains(&candidate) {
+            table.assigned[pid_idx] = candidate;
+            return candidate;
+        }
+    }
+}
+
+/// Give up the ASID belonging to `pid`, if it has one.
+fn release_asid(pid: PID) {
+    unsafe { ASID_TABLE.assigned[pid.get() as usize - 1] = 0 };
+}
+
+/// Replace the ASID field of `satp` with `asid`.
+pub fn satp_with_asid(satp: usize, asid: usize) -> usize {
+    (satp & !SATP_ASID_MASK) | ((asid << 22) & SATP_ASID_MASK)
+}
+
 #[repr(C)]
 #[cfg(baremetal)]
 /// The stage1 bootloader sets up some initial processes.  These are reported
@@ -120,8 +219,8 @@ pub struct Thread {
 impl Process {
     pub fn current() -> Process {
         let pid = unsafe { PROCESS_TABLE.current };
-        let hardware_pid = (riscv::register::satp::read().bits() >> 22) & ((1 << 9) - 1);
-        assert!((pid.get() as usize) == hardware_pid);
+        let hardware_asid = riscv::register::satp::read().asid();
+        assert!(hardware_asid == asid(pid));
         Process {
             pid,
         }
@@ -227,6 +326,17 @@ impl Process {
         None
     }
 
+    /// The number of threads that exist in this process.
+    pub fn thread_count(&self) -> usize {
+        let process = unsafe { &*PROCESS };
+        process
+            .threads
+            .iter()
+            .enumerate()
+            .filter(|(index, thread)| *index != IRQ_TID && thread.sepc != 0)
+            .count()
+    }
+
     pub fn set_thread_result(&mut self, thread_nr: TID, result: xous_kernel::Result) {
         let vals = unsafe { mem::transmute::<_, [usize; 8]>(result) };
         let thread = self.thread_mut(thread_nr);
@@ -377,17 +487,16 @@ impl Process {
         todo!();
     }
 
-    pub fn destroy(_pid: PID) -> Result<(), xous_kernel::Error> {
-        todo!();
-        // let mut process_table = unsafe { &mut *PROCESS };
-        // let pid_idx = pid.get() as usize - 1;
-        // if pid_idx >= process_table.table.len() {
-        //     panic!("attempted to destroy PID that exceeds table index: {}", pid);
-        // }
-        // let process = process_table.table[pid_idx].as_mut().unwrap();
-        // process_table.table[pid_idx] = None;
-        // process_table.total -= 1;
-        // Ok(())
+    pub fn destroy(pid: PID) -> Result<(), xous_kernel::Error> {
+        let pid_idx = pid.get() as usize - 1;
+        unsafe {
+            if pid_idx >= PROCESS_TABLE.table.len() {
+                panic!("attempted to destroy PID that exceeds table index: {}", pid);
+            }
+            PROCESS_TABLE.table[pid_idx] = false;
+        }
+        release_asid(pid);
+        Ok(())
     }
 }
 
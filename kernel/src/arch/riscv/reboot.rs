@@ -0,0 +1,85 @@
+//! The peripheral that resets the SoC.  Its address is given to the kernel
+//! in a `Rebt` argument.  Writing `CPU_RESET_KEY` to its `CPU_RESET`
+//! register restarts the CPU at the loader, and writing `SOC_RESET_KEY` to
+//! `SOC_RESET` resets everything.  `SCRATCH` keeps its value across a
+//! reset, and is where the loader looks to see why it was started.  If there
+//! is no such argument, the kernel can't reboot.
+
+use crate::mem::MemoryManager;
+use xous_kernel::{MemoryFlags, MemoryType, RebootMode, PID};
+
+/// Where the peripheral is mapped in the kernel's address space
+const REBOOT_VIRT: usize = 0xffcc_0000;
+
+// Register offsets, in words
+const SOC_RESET: usize = 0;
+const CPU_RESET: usize = 1;
+const SCRATCH: usize = 2;
+
+// Only these values reset anything, so a stray write can't
+const SOC_RESET_KEY: usize = 0xac;
+const CPU_RESET_KEY: usize = 0x5a;
+
+/// Left in `SCRATCH` to have the loader wait for a firmware update
+const SCRATCH_UPDATE: usize = 0x5550_4454;
+
+/// Whether `init()` found a peripheral to reset with
+static mut PRESENT: bool = false;
+
+fn write(register: usize, value: usize) {
+    unsafe {
+        (REBOOT_VIRT as *mut usize)
+            .add(register)
+            .write_volatile(value)
+    };
+}
+
+/// Map the reboot peripheral, if the kernel was given one.
+pub fn init() {
+    let base = match crate::args::KernelArguments::get()
+        .iter()
+        .find(|arg| arg.name == make_type!("Rebt") && !arg.data.is_empty())
+    {
+        Some(arg) => arg.data[0] as usize,
+        None => return,
+    };
+
+    MemoryManager::with_mut(|mm| {
+        mm.map_range(
+            base as *mut u8,
+            REBOOT_VIRT as *mut u8,
+            4096,
+            PID::new(1).unwrap(),
+            MemoryFlags::R | MemoryFlags::W,
+            MemoryType::Default,
+        )
+        .expect("unable to map reboot peripheral")
+    });
+    unsafe { PRESENT = true };
+}
+
+/// Whether there's a peripheral to reboot with.
+pub fn present() -> bool {
+    unsafe { PRESENT }
+}
+
+/// Reset as `mode` asks.  This only returns if the reset didn't happen.
+pub fn reset(mode: RebootMode) {
+    if !present() {
+        return;
+    }
+    match mode {
+        RebootMode::Warm => {
+            write(SCRATCH, 0);
+            write(CPU_RESET, CPU_RESET_KEY);
+        }
+        RebootMode::Cold => {
+            write(SCRATCH, 0);
+            write(SOC_RESET, SOC_RESET_KEY);
+        }
+        RebootMode::Update => {
+            write(SCRATCH, SCRATCH_UPDATE);
+            write(SOC_RESET, SOC_RESET_KEY);
+        }
+    }
+}
@@ -0,0 +1,72 @@
+//! The power controller that puts RAM into self-refresh.  Its address is
+//! given to the kernel in a `Susp` argument.  Writing `SLEEP_KEY` to its
+//! `SLEEP` register has it put RAM into self-refresh and gate the core's
+//! clock as soon as the core waits for an interrupt, and bring both back
+//! once one of the interrupts set in `WAKE` is pending.  The core keeps its
+//! registers, and RAM keeps everything else.  If there is no such argument,
+//! the kernel can't suspend.
+
+use crate::mem::MemoryManager;
+use xous_kernel::{MemoryFlags, MemoryType, PID};
+
+/// Where the controller is mapped in the kernel's address space
+const SUSPEND_VIRT: usize = 0xffcb_0000;
+
+// Register offsets, in words
+const SLEEP: usize = 0;
+const WAKE: usize = 1;
+
+/// Only this value puts RAM to sleep, so a stray write can't
+const SLEEP_KEY: usize = 0x5e;
+
+/// Whether `init()` found a controller to suspend with
+static mut PRESENT: bool = false;
+
+fn write(register: usize, value: usize) {
+    unsafe {
+        (SUSPEND_VIRT as *mut usize)
+            .add(register)
+            .write_volatile(value)
+    };
+}
+
+/// Map the power controller, if the kernel was given one.
+pub fn init() {
+    let base = match crate::args::KernelArguments::get()
+        .iter()
+        .find(|arg| arg.name == make_type!("Susp") && !arg.data.is_empty())
+    {
+        Some(arg) => arg.data[0] as usize,
+        None => return,
+    };
+
+    MemoryManager::with_mut(|mm| {
+        mm.map_range(
+            base as *mut u8,
+            SUSPEND_VIRT as *mut u8,
+            4096,
+            PID::new(1).unwrap(),
+            MemoryFlags::R | MemoryFlags::W,
+            MemoryType::Default,
+        )
+        .expect("unable to map power controller")
+    });
+    unsafe { PRESENT = true };
+}
+
+/// Whether there's a power controller to suspend with.
+pub fn present() -> bool {
+    unsafe { PRESENT }
+}
+
+/// Put RAM into self-refresh and sleep until one of the interrupts in the
+/// `wake` mask is pending.  The caller must have masked interrupts in
+/// `sstatus`, so that the one that wakes the core is taken afterwards.
+pub fn sleep(wake: usize) {
+    if !present() {
+        return;
+    }
+    write(WAKE, wake);
+    write(SLEEP, SLEEP_KEY);
+    unsafe { riscv::asm::wfi() };
+}
@@ -32,6 +32,9 @@ fn set_supervisor(supervisor: bool) {
 }
 
 pub fn resume(supervisor: bool, thread: &Thread) -> ! {
+    // Make any pagetable changes visible before running the thread.
+    crate::arch::mem::flush_pending();
+
     sepc::write(thread.sepc);
 
     // Return to the appropriate CPU mode
@@ -0,0 +1,167 @@
+//! The timer that drives preemption.  This is a LiteX timer whose address,
+//! interrupt, and period are given to the kernel in a `Tick` argument.  If
+//! there is no such argument, processes are never preempted.
+
+use crate::mem::MemoryManager;
+use xous_kernel::{MemoryFlags, MemoryType, PID};
+
+/// Where the timer is mapped in the kernel's address space
+const TIMER_VIRT: usize = 0xffce_0000;
+
+// Register offsets, in words
+const LOAD: usize = 0;
+const RELOAD: usize = 1;
+const EN: usize = 2;
+const EV_PENDING: usize = 6;
+const EV_ENABLE: usize = 7;
+
+/// Whether `init()` found a timer to drive
+static mut PRESENT: bool = false;
+
+/// The interrupt the timer raises
+static mut IRQ: usize = 0;
+
+/// How many periods of the timer have passed since it was started,
+/// including ones that passed while it was suspended
+static mut TICKS: u64 = 0;
+
+/// The timestamp of the last tick, or of when the timer was last started
+static mut LAST_TICK: u64 = 0;
+
+/// How far apart ticks are in timestamp units, or `0` until the first tick
+/// has been timed
+static mut PERIOD: u64 = 0;
+
+fn write(register: usize, value: usize) {
+    unsafe {
+        (TIMER_VIRT as *mut usize)
+            .add(register)
+            .write_volatile(value)
+    };
+}
+
+fn tick(_irq_no: usize, _arg: *mut usize) {
+    // Acknowledge the interrupt
+    write(EV_PENDING, 1);
+
+    let now = crate::arch::timestamp();
+    unsafe {
+        if PERIOD == 0 {
+            PERIOD = now.wrapping_sub(LAST_TICK);
+        }
+        LAST_TICK = now;
+        TICKS += 1;
+    }
+
+    crate::lends::check();
+    crate::watchdog::check();
+    crate::timers::check();
+    crate::entropy::tick(unsafe { TICKS });
+
+    let (pid, tid) = match unsafe { crate::arch::irq::isr_return_pair() } {
+        Some(pair) => pair,
+        None => return,
+    };
+
+    // The policy sees every tick so that it can keep time, but the kernel
+    // itself is never preempted.
+    if crate::sched::tick(pid, tid) && pid.get() != 1 {
+        xous_kernel::rsyscall(xous_kernel::SysCall::ReturnToParentI(pid, 0))
+            .expect("couldn't preempt process");
+    }
+}
+
+/// Stop the timer while the core is idle, so that it doesn't keep waking up
+/// for no reason.
+pub fn suspend() {
+    if unsafe { PRESENT } {
+        write(EN, 0);
+    }
+}
+
+/// Restart the timer after `suspend()`, giving it a full period.  Every
+/// whole period that passed in the meantime is counted as a tick.
+pub fn resume() {
+    if unsafe { PRESENT } {
+        write(EN, 1);
+        let now = crate::arch::timestamp();
+        unsafe {
+            if PERIOD != 0 {
+                TICKS += now.wrapping_sub(LAST_TICK) / PERIOD;
+            }
+            LAST_TICK = now;
+        }
+    }
+}
+
+/// The number of ticks since the timer was started, or `None` if there is
+/// no timer.
+pub fn ticks() -> Option<u64> {
+    if unsafe { PRESENT } {
+        Some(unsafe { TICKS })
+    } else {
+        None
+    }
+}
+
+/// The interrupt the timer raises, or `None` if there is no timer.
+pub fn irq() -> Option<usize> {
+    if unsafe { PRESENT } {
+        Some(unsafe { IRQ })
+    } else {
+        None
+    }
+}
+
+/// Whether the timer is among `irqs_pending`, or `None` if there is no
+/// timer.
+#[cfg(feature = "profile")]
+pub fn ticked(irqs_pending: usize) -> Option<bool> {
+    if unsafe { PRESENT } {
+        Some(irqs_pending & (1 << unsafe { IRQ }) != 0)
+    } else {
+        None
+    }
+}
+
+/// Start the tick timer, if the kernel was given one.
+pub fn init() {
+    let (base, irq, period) = match crate::args::KernelArguments::get()
+        .iter()
+        .find(|arg| arg.name == make_type!("Tick") && arg.data.len() >= 3)
+    {
+        Some(arg) => (
+            arg.data[0] as usize,
+            arg.data[1] as usize,
+            arg.data[2] as usize,
+        ),
+        None => return,
+    };
+
+    MemoryManager::with_mut(|mm| {
+        mm.map_range(
+            base as *mut u8,
+            TIMER_VIRT as *mut u8,
+            4096,
+            PID::new(1).unwrap(),
+            MemoryFlags::R | MemoryFlags::W,
+            MemoryType::Default,
+        )
+        .expect("unable to map tick timer")
+    });
+
+    write(EN, 0);
+    write(LOAD, period);
+    write(RELOAD, period);
+    write(EN, 1);
+    write(EV_PENDING, 1);
+    write(EV_ENABLE, 1);
+    unsafe { LAST_TICK = crate::arch::timestamp() };
+
+    xous_kernel::claim_interrupt(irq, tick, core::ptr::null_mut())
+        .expect("couldn't claim tick timer interrupt");
+    unsafe {
+        IRQ = irq;
+        PRESENT = true;
+    }
+}
@@ -0,0 +1,65 @@
+//! The hardware TRNG, which seeds the kernel's random number generator.
+//! Its address is given to the kernel in a `Trng` argument.  It has a
+//! `DATA` register holding 32 random bits, and a `STATUS` register whose
+//! lowest bit is set while `DATA` holds a word that hasn't been read yet.
+//! If there is no such argument, the kernel has no source of randomness.
+
+use crate::mem::MemoryManager;
+use xous_kernel::{MemoryFlags, MemoryType, PID};
+
+/// Where the TRNG is mapped in the kernel's address space
+const TRNG_VIRT: usize = 0xffcd_0000;
+
+// Register offsets, in words
+const DATA: usize = 0;
+const STATUS: usize = 1;
+
+/// How many times to poll for a fresh word before giving up
+const MAX_POLLS: usize = 100_000;
+
+/// Whether `init()` found a TRNG to read
+static mut PRESENT: bool = false;
+
+fn read_register(register: usize) -> usize {
+    unsafe { (TRNG_VIRT as *const usize).add(register).read_volatile() }
+}
+
+/// Map the TRNG, if the kernel was given one.
+pub fn init() {
+    let base = match crate::args::KernelArguments::get()
+        .iter()
+        .find(|arg| arg.name == make_type!("Trng") && !arg.data.is_empty())
+    {
+        Some(arg) => arg.data[0] as usize,
+        None => return,
+    };
+
+    MemoryManager::with_mut(|mm| {
+        mm.map_range(
+            base as *mut u8,
+            TRNG_VIRT as *mut u8,
+            4096,
+            PID::new(1).unwrap(),
+            MemoryFlags::R | MemoryFlags::W,
+            MemoryType::Default,
+        )
+        .expect("unable to map TRNG")
+    });
+    unsafe { PRESENT = true };
+}
+
+/// Fill `buf` from the TRNG.  Returns `false` if there is no TRNG, or it
+/// stopped producing words.
+pub fn read(buf: &mut [u8]) -> bool {
+    if !unsafe { PRESENT } {
+        return false;
+    }
+    for chunk in buf.chunks_mut(4) {
+        if !(0..MAX_POLLS).any(|_| read_register(STATUS) & 1 != 0) {
+            return false;
+        }
+        let word = read_register(DATA) as u32;
+        chunk.copy_from_slice(&word.to_le_bytes()[..chunk.len()]);
+    }
+    true
+}
@@ -0,0 +1,72 @@
+//! The audit log.  The kernel records security-relevant events, such as
+//! processes coming and going or being turned away for lack of a
+//! capability, in a small ring that a process holding
+//! `Capabilities::AUDIT` reads with `ReadAuditLog`.  The ring overwrites
+//! its oldest entries when it fills up, and every entry carries a sequence
+//! number, so an auditor that falls behind can tell how much it missed.
+
+use xous_kernel::{AuditEvent, AuditKind, PID};
+
+/// How many events are kept before the oldest is overwritten
+const MAX_AUDIT_EVENTS: usize = 64;
+
+struct AuditLog {
+    events: [Option<AuditEvent>; MAX_AUDIT_EVENTS],
+
+    /// The sequence number the next event will get
+    next_sequence: usize,
+}
+
+const EMPTY: AuditLog = AuditLog {
+    events: [None; MAX_AUDIT_EVENTS],
+    next_sequence: 0,
+};
+
+#[cfg(baremetal)]
+static mut AUDIT_LOG: AuditLog = EMPTY;
+
+#[cfg(not(baremetal))]
+std::thread_local!(static AUDIT_LOG: core::cell::RefCell<AuditLog> = const { core::cell::RefCell::new(EMPTY) });
+
+fn with_mut<F, R>(f: F) -> R
+where
+    F: FnOnce(&mut AuditLog) -> R,
+{
+    #[cfg(baremetal)]
+    unsafe {
+        f(&mut AUDIT_LOG)
+    }
+
+    #[cfg(not(baremetal))]
+    AUDIT_LOG.with(|log| f(&mut log.borrow_mut()))
+}
+
+/// Add an event to the log, overwriting the oldest one if it's full.
+pub fn record(kind: AuditKind, pid: PID, arg: usize, arg2: usize) {
+    let timestamp = crate::arch::timestamp();
+    with_mut(|log| {
+        let sequence = log.next_sequence;
+        log.events[sequence % MAX_AUDIT_EVENTS] = Some(AuditEvent {
+            sequence,
+            kind,
+            pid,
+            arg,
+            arg2,
+            timestamp,
+        });
+        log.next_sequence = sequence.wrapping_add(1);
+    })
+}
+
+/// The oldest event still in the log whose sequence number is at least
+/// `sequence`, or `None` if there is no such event yet.
+pub fn read(sequence: usize) -> Option<AuditEvent> {
+    with_mut(|log| {
+        let oldest = log.next_sequence.saturating_sub(MAX_AUDIT_EVENTS);
+        let sequence = sequence.max(oldest);
+        if sequence >= log.next_sequence {
+            return None;
+        }
+        log.events[sequence % MAX_AUDIT_EVENTS]
+    })
+}
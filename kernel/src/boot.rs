@@ -0,0 +1,63 @@
+//! Timestamps taken while the kernel starts up, so that the time spent in
+//! each stage of booting can be measured afterwards.
+
+use xous_kernel::BootStage;
+
+const STAGE_COUNT: usize = BootStage::Scheduler as usize + 1;
+
+#[cfg(baremetal)]
+static mut BOOT_TIMES: [u64; STAGE_COUNT] = [0; STAGE_COUNT];
+
+#[cfg(not(baremetal))]
+std::thread_local!(static BOOT_TIMES: core::cell::RefCell<[u64; STAGE_COUNT]> = const { core::cell::RefCell::new([0; STAGE_COUNT]) });
+
+/// Record that the kernel has reached `stage`.  Only the first time a stage
+/// is reached is kept.
+pub fn mark(stage: BootStage) {
+    let now = crate::arch::timestamp();
+
+    #[cfg(baremetal)]
+    unsafe {
+        if BOOT_TIMES[stage as usize] == 0 {
+            BOOT_TIMES[stage as usize] = now;
+        }
+    }
+
+    #[cfg(not(baremetal))]
+    BOOT_TIMES.with(|times| {
+        let mut times = times.borrow_mut();
+        if times[stage as usize] == 0 {
+            times[stage as usize] = now;
+        }
+    });
+}
+
+/// Get the time at which the kernel reached `stage`, or `0` if it never did.
+pub fn time(stage: BootStage) -> u64 {
+    #[cfg(baremetal)]
+    unsafe {
+        BOOT_TIMES[stage as usize]
+    }
+
+    #[cfg(not(baremetal))]
+    BOOT_TIMES.with(|times| times.borrow()[stage as usize])
+}
+
+/// Print how long each stage of booting took.
+#[cfg(feature = "debug-print")]
+pub fn print() {
+    let stages = [
+        BootStage::KernelEntry,
+        BootStage::MemoryManager,
+        BootStage::Processes,
+        BootStage::ArchInit,
+        BootStage::Scheduler,
+    ];
+    println!("Boot stages:");
+    let mut previous = 0;
+    for stage in stages.iter() {
+        let time = time(*stage);
+        println!("    {:?}: {} (+{})", stage, time, time.wrapping_sub(previous));
+        previous = time;
+    }
+}
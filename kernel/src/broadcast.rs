@@ -0,0 +1,215 @@
+//! Broadcast lists, for servers that need to tell many clients the same
+//! thing at once.  A broadcast list belongs to the process that created it,
+//! which is the only one that may broadcast on it.  Any process that knows
+//! its ID may subscribe one of the servers it's connected to, along with the
+//! opcode that broadcasts should arrive as.  Each broadcast is then
+//! delivered to every subscribed server as a `Scalar` message.
+//!
+//! The kernel has lists of its own, such as `SHUTDOWN_BROADCAST`, which
+//! always exist and which only the kernel broadcasts on.  Their IDs count
+//! down from the top, so that they never clash with a process's.
+
+use xous_kernel::{PID, SID};
+
+/// The most broadcast lists that may exist at once
+const MAX_BROADCAST_LISTS: usize = 8;
+
+/// The number of lists that belong to the kernel, which come after the
+/// ones processes create
+const KERNEL_LISTS: usize = 2;
+
+/// The most servers that may subscribe to one broadcast list
+pub const MAX_SUBSCRIBERS: usize = 32;
+
+/// A server that broadcasts are delivered to
+#[derive(Copy, Clone)]
+pub struct Subscriber {
+    /// The process that subscribed the server
+    pub pid: PID,
+
+    /// The server that broadcasts are sent to
+    pub sid: SID,
+
+    /// The message ID that broadcasts arrive with
+    pub opcode: usize,
+}
+
+#[derive(Copy, Clone)]
+struct BroadcastList {
+    /// The process that created the list, or `None` for the kernel's own
+    owner: Option<PID>,
+
+    /// The servers subscribed to the list, in the order they subscribed
+    subscribers: [Option<Subscriber>; MAX_SUBSCRIBERS],
+}
+
+type Lists = [Option<BroadcastList>; MAX_BROADCAST_LISTS + KERNEL_LISTS];
+
+const EMPTY: Lists = [None; MAX_BROADCAST_LISTS + KERNEL_LISTS];
+
+#[cfg(baremetal)]
+static mut BROADCAST_LISTS: Lists = EMPTY;
+
+#[cfg(not(baremetal))]
+std::thread_local!(static BROADCAST_LISTS: core::cell::RefCell<Lists> = const { core::cell::RefCell::new(EMPTY) });
+
+fn with_mut<F, R>(f: F) -> R
+where
+    F: FnOnce(&mut Lists) -> R,
+{
+    #[cfg(baremetal)]
+    unsafe {
+        f(&mut BROADCAST_LISTS)
+    }
+
+    #[cfg(not(baremetal))]
+    BROADCAST_LISTS.with(|lists| f(&mut lists.borrow_mut()))
+}
+
+/// Look up broadcast list `id`.  IDs of lists that processes create are one
+/// more than the slot they live in, so that `0` is never a valid ID.  The
+/// kernel's lists are made the first time they're looked up.
+fn get(lists: &mut Lists, id: usize) -> Result<&mut BroadcastList, xous_kernel::Error> {
+    if id > usize::MAX - KERNEL_LISTS {
+        return Ok(
+            lists[MAX_BROADCAST_LISTS + (usize::MAX - id)].get_or_insert(BroadcastList {
+                owner: None,
+                subscribers: [None; MAX_SUBSCRIBERS],
+            }),
+        );
+    }
+    if id == 0 || id > MAX_BROADCAST_LISTS {
+        return Err(xous_kernel::Error::InvalidSyscall);
+    }
+    lists[id - 1]
+        .as_mut()
+        .ok_or(xous_kernel::Error::InvalidSyscall)
+}
+
+/// Create a new broadcast list owned by `owner`, and return its ID.
+///
+/// # Errors
+///
+/// * **OutOfMemory**: Every broadcast list is in use
+pub fn create(owner: PID) -> Result<usize, xous_kernel::Error> {
+    with_mut(|lists| {
+        let (index, slot) = lists[..MAX_BROADCAST_LISTS]
+            .iter_mut()
+            .enumerate()
+            .find(|(_, slot)| slot.is_none())
+            .ok_or(xous_kernel::Error::OutOfMemory)?;
+        *slot = Some(BroadcastList {
+            owner: Some(owner),
+            subscribers: [None; MAX_SUBSCRIBERS],
+        });
+        Ok(index + 1)
+    })
+}
+
+/// Have `pid` subscribe server `sid` to broadcast list `id`, so that
+/// broadcasts arrive there as `opcode`.  It's up to the caller to make sure
+/// that `pid` is connected to the server.  Subscribing a server again just
+/// changes its opcode.
+///
+/// # Errors
+///
+/// * **InvalidSyscall**: The broadcast list doesn't exist
+/// * **OutOfMemory**: The broadcast list is full
+pub fn subscribe(id: usize, pid: PID, sid: SID, opcode: usize) -> Result<(), xous_kernel::Error> {
+    with_mut(|lists| {
+        let list = get(lists, id)?;
+        if let Some(subscriber) = list
+            .subscribers
+            .iter_mut()
+            .flatten()
+            .find(|subscriber| subscriber.pid == pid && subscriber.sid == sid)
+        {
+            subscriber.opcode = opcode;
+            return Ok(());
+        }
+        let slot = list
+            .subscribers
+            .iter_mut()
+            .find(|slot| slot.is_none())
+            .ok_or(xous_kernel::Error::OutOfMemory)?;
+        *slot = Some(Subscriber { pid, sid, opcode });
+        Ok(())
+    })
+}
+
+/// Stop delivering broadcasts on list `id` to server `sid` on behalf of
+/// `pid`.  Unsubscribing a server that isn't subscribed does nothing.
+///
+/// # Errors
+///
+/// * **InvalidSyscall**: The broadcast list doesn't exist
+pub fn unsubscribe(id: usize, pid: PID, sid: SID) -> Result<(), xous_kernel::Error> {
+    with_mut(|lists| {
+        for slot in get(lists, id)?.subscribers.iter_mut() {
+            if matches!(slot, Some(subscriber) if subscriber.pid == pid && subscriber.sid == sid) {
+                *slot = None;
+            }
+        }
+        Ok(())
+    })
+}
+
+/// The servers subscribed to broadcast list `id`.
+///
+/// # Errors
+///
+/// * **InvalidSyscall**: The broadcast list doesn't exist
+/// * **AccessDenied**: `pid` doesn't own the broadcast list
+pub fn subscribers(
+    id: usize,
+    pid: PID,
+) -> Result<[Option<Subscriber>; MAX_SUBSCRIBERS], xous_kernel::Error> {
+    with_mut(|lists| {
+        let list = get(lists, id)?;
+        if list.owner != Some(pid) {
+            return Err(xous_kernel::Error::AccessDenied);
+        }
+        Ok(list.subscribers)
+    })
+}
+
+/// The servers subscribed to `id`, which is one of the kernel's own lists.
+pub fn kernel_subscribers(id: usize) -> [Option<Subscriber>; MAX_SUBSCRIBERS] {
+    with_mut(|lists| {
+        get(lists, id)
+            .map(|list| list.subscribers)
+            .unwrap_or([None; MAX_SUBSCRIBERS])
+    })
+}
+
+/// Drop server `sid`, which no longer exists, from every broadcast list.
+pub fn forget_server(sid: SID) {
+    with_mut(|lists| {
+        for list in lists.iter_mut().flatten() {
+            for slot in list.subscribers.iter_mut() {
+                if matches!(slot, Some(subscriber) if subscriber.sid == sid) {
+                    *slot = None;
+                }
+            }
+        }
+    })
+}
+
+/// Destroy every broadcast list owned by `pid`, which is going away, and
+/// drop every subscription it made.
+pub fn forget_process(pid: PID) {
+    with_mut(|lists| {
+        for slot in lists.iter_mut() {
+            if matches!(slot, Some(list) if list.owner == Some(pid)) {
+                *slot = None;
+            }
+        }
+        for list in lists.iter_mut().flatten() {
+            for slot in list.subscribers.iter_mut() {
+                if matches!(slot, Some(subscriber) if subscriber.pid == pid) {
+                    *slot = None;
+                }
+            }
+        }
+    })
+}
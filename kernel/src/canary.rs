@@ -0,0 +1,95 @@
+//! Stack canaries.  When a thread is created, the kernel writes a random
+//! word at the very bottom of its stack, and checks that it's still there
+//! whenever the thread is switched to or away from.  A thread that runs off
+//! the end of its stack almost always overwrites it, so the overflow is
+//! caught right away and blamed on the thread that caused it, rather than
+//! turning up later as corruption in whatever lies below the stack.
+
+use crate::arch::process::{MAX_PROCESS_COUNT, MAX_THREAD};
+use crate::mem::MemoryManager;
+use xous_kernel::{MemoryRange, PID, TID};
+
+/// The canary to use if the random number generator has never been seeded.
+/// It's mixed with the canary's address, so it at least differs per stack.
+const FALLBACK_CANARY: usize = 0x5afe_57ac;
+
+#[derive(Copy, Clone)]
+struct Canary {
+    /// Where the canary lives in the thread's process
+    address: usize,
+
+    /// What should be found there
+    value: usize,
+}
+
+static mut CANARIES: [[Option<Canary>; MAX_THREAD + 1]; MAX_PROCESS_COUNT] =
+    [[None; MAX_THREAD + 1]; MAX_PROCESS_COUNT];
+
+fn slot(pid: PID, tid: TID) -> Option<&'static mut Option<Canary>> {
+    unsafe {
+        CANARIES
+            .get_mut(pid.get() as usize - 1)
+            .and_then(|threads| threads.get_mut(tid))
+    }
+}
+
+/// Write a canary at the bottom of `stack`, which belongs to thread `tid`
+/// of `pid`.  `pid` must be the current process.  If the canary can't be
+/// written, such as because the stack isn't mapped yet, the thread simply
+/// goes without one.
+pub fn place(pid: PID, tid: TID, stack: &MemoryRange) {
+    let slot = match slot(pid, tid) {
+        Some(slot) => slot,
+        None => return,
+    };
+    let address = stack.as_ptr() as usize;
+    let mut random = [0u8; core::mem::size_of::<usize>()];
+    let value = match crate::entropy::fill(&mut random) {
+        Ok(()) => usize::from_le_bytes(random),
+        Err(_) => FALLBACK_CANARY ^ address,
+    };
+    *slot = MemoryManager::with_mut(|mm| {
+        crate::arch::mem::write_user_word(mm, pid, address, value).ok()
+    })
+    .map(|_| Canary { address, value });
+}
+
+/// Make sure the canary of thread `tid` of `pid` is intact.  This can only
+/// be done while `pid` is the current process, so it's skipped otherwise.
+///
+/// # Panics
+///
+/// If the thread has overwritten its canary, by overflowing its stack.
+pub fn check(pid: PID, tid: TID) {
+    if crate::arch::process::current_pid() != pid {
+        return;
+    }
+    let slot = match slot(pid, tid) {
+        Some(slot) => slot,
+        None => return,
+    };
+    let canary = match slot {
+        Some(canary) => *canary,
+        None => return,
+    };
+    let mut word = [0u8; core::mem::size_of::<usize>()];
+    if crate::arch::mem::read_user(canary.address, &mut word).is_err() {
+        // The process unmapped the bottom of its own stack, so there's
+        // nothing left to check.
+        *slot = None;
+        return;
+    }
+    if usize::from_le_bytes(word) != canary.value {
+        panic!(
+            "PID {} TID {} overflowed its stack, which ends at {:08x}",
+            pid, tid, canary.address
+        );
+    }
+}
+
+/// Forget the canaries of every thread in a process that is exiting.
+pub fn forget_process(pid: PID) {
+    if let Some(threads) = unsafe { CANARIES.get_mut(pid.get() as usize - 1) } {
+        *threads = [None; MAX_THREAD + 1];
+    }
+}
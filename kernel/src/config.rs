@@ -0,0 +1,64 @@
+//! A description of how this kernel was built, so that programs can check
+//! that they're compatible with it and size things to fit its limits.
+
+use xous_kernel::{KernelArch, KernelConfig, KernelFeatures};
+
+/// Each optional feature, and whether this kernel was built with it
+const FEATURES: &[(KernelFeatures, bool)] = &[
+    (KernelFeatures::DEBUG_PRINT, cfg!(feature = "debug-print")),
+    (KernelFeatures::PRINT_PANICS, cfg!(feature = "print-panics")),
+    (
+        KernelFeatures::REPORT_MEMORY,
+        cfg!(feature = "report-memory"),
+    ),
+    (KernelFeatures::PROFILE, cfg!(feature = "profile")),
+    (KernelFeatures::COVERAGE, cfg!(feature = "coverage")),
+    (
+        KernelFeatures::EMULATE_ATOMICS,
+        cfg!(feature = "emulate-atomics"),
+    ),
+    (
+        KernelFeatures::EMULATE_MISALIGNED,
+        cfg!(feature = "emulate-misaligned"),
+    ),
+    (
+        KernelFeatures::PRINT_MAPPINGS,
+        cfg!(feature = "print-mappings"),
+    ),
+    (KernelFeatures::TRACE_LENDS, cfg!(feature = "trace-lends")),
+    (
+        KernelFeatures::SCHED_ROUND_ROBIN,
+        cfg!(feature = "sched-round-robin"),
+    ),
+    (
+        KernelFeatures::SCHED_PRIORITY,
+        cfg!(feature = "sched-priority"),
+    ),
+    (KernelFeatures::TRACE, cfg!(feature = "trace")),
+    (
+        KernelFeatures::SYSCALL_LATENCY,
+        cfg!(feature = "syscall-latency"),
+    ),
+];
+
+/// The configuration this kernel was built with.
+pub fn get() -> KernelConfig {
+    KernelConfig {
+        arch: if cfg!(baremetal) {
+            KernelArch::Riscv32
+        } else {
+            KernelArch::Hosted
+        },
+        page_size: crate::mem::PAGE_SIZE,
+        features: FEATURES
+            .iter()
+            .filter(|(_, enabled)| *enabled)
+            .fold(KernelFeatures::empty(), |features, (feature, _)| {
+                features | *feature
+            }),
+        max_processes: crate::services::MAX_PROCESS_COUNT,
+        max_threads: crate::arch::process::MAX_THREAD,
+        max_servers: crate::services::MAX_SERVER_COUNT,
+        max_queue_length: crate::server::MAX_QUEUE_LENGTH,
+    }
+}
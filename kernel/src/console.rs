@@ -0,0 +1,237 @@
+//! A console for looking at and prodding a running kernel, for when the
+//! processes that would normally be used to do that are what's broken.
+//! Commands are typed one per line on the debug UART, or on the terminal
+//! that a hosted kernel was started from.  Type `help` for a list.
+
+use crate::services::SystemServices;
+use xous_kernel::{LogLevel, LogSubsystem, PID};
+
+/// The longest line the UART collects before running it
+#[cfg(baremetal)]
+const LINE_LENGTH: usize = 64;
+
+#[cfg(baremetal)]
+static mut LINE: [u8; LINE_LENGTH] = [0; LINE_LENGTH];
+
+/// How many characters of `LINE` have been typed so far
+#[cfg(baremetal)]
+static mut LINE_END: usize = 0;
+
+/// Add a character typed on the debug UART to the current line, echoing it
+/// back, and run the line once it's finished.  This must only be called
+/// from an interrupt context.
+#[cfg(baremetal)]
+pub fn feed(c: u8) {
+    match c {
+        b'\r' | b'\n' => {
+            println!();
+            let (line, length) = unsafe { (LINE, core::mem::replace(&mut LINE_END, 0)) };
+            // Only printable ASCII is added to the line, so it's always valid.
+            if let Ok(line) = core::str::from_utf8(&line[..length]) {
+                run(line);
+            }
+            print!("}} ");
+        }
+        8 | 127 => unsafe {
+            if LINE_END > 0 {
+                LINE_END -= 1;
+                print!("\x08 \x08");
+            }
+        },
+        b' '..=b'~' => unsafe {
+            if LINE_END < LINE_LENGTH {
+                LINE[LINE_END] = c;
+                LINE_END += 1;
+                print!("{}", c as char);
+            }
+        },
+        _ => (),
+    }
+}
+
+/// Run one line typed at the console.
+pub fn run(line: &str) {
+    let mut words = line.split_whitespace();
+    let command = match words.next() {
+        Some(command) => command,
+        None => return,
+    };
+    let arg = words.next();
+    let result = match command {
+        "help" => {
+            help();
+            Ok(())
+        }
+        "ps" => {
+            SystemServices::with(|ss| ss.print_processes());
+            Ok(())
+        }
+        "servers" => {
+            SystemServices::with(|ss| ss.print_servers());
+            Ok(())
+        }
+        "queue" => match arg.and_then(|arg| arg.parse().ok()) {
+            Some(sidx) => SystemServices::with(|ss| ss.print_queue(sidx)),
+            None => return println!("usage: queue <server index>"),
+        },
+        "map" => match parse_pid(arg) {
+            Some(pid) => SystemServices::with(|ss| ss.print_mappings(pid)),
+            None => return println!("usage: map <pid>"),
+        },
+        "sched" => {
+            reschedule();
+            Ok(())
+        }
+        "kill" => match parse_pid(arg) {
+            Some(pid) => kill(pid),
+            None => return println!("usage: kill <pid>"),
+        },
+        "log" => match (arg, words.next()) {
+            (None, _) => {
+                print_log_levels();
+                Ok(())
+            }
+            (Some(subsystem), level) => {
+                match (parse_subsystem(subsystem), level.and_then(parse_level)) {
+                    (Some(subsystem), Some(level)) => {
+                        crate::log::set_level(subsystem, level);
+                        Ok(())
+                    }
+                    _ => return println!("usage: log [<subsystem> <level>]"),
+                }
+            }
+        },
+        #[cfg(not(baremetal))]
+        "advance" => match arg.and_then(|arg| arg.parse().ok()) {
+            Some(ms) => crate::arch::advance_clock(ms),
+            None => return println!("usage: advance <ms>"),
+        },
+        #[cfg(not(baremetal))]
+        "wake" => crate::arch::wake(),
+        #[cfg(feature = "profile")]
+        "prof" => {
+            crate::profile::dump();
+            Ok(())
+        }
+        #[cfg(all(baremetal, feature = "print-mappings"))]
+        "maps" => {
+            SystemServices::with(|ss| ss.dump_all_mappings());
+            Ok(())
+        }
+        _ => return println!("unknown command {:?} -- type `help` for a list", command),
+    };
+    if let Err(e) = result {
+        println!("{}: {:?}", command, e);
+    }
+}
+
+fn help() {
+    println!("ps                         list processes and their threads");
+    println!("servers                    list servers and their indices");
+    println!("queue <server index>       dump the message queue of a server");
+    println!("map <pid>                  dump the page tables of a process");
+    println!("sched                      preempt the running process");
+    println!("kill <pid>                 terminate a process");
+    println!("log [<subsystem> <level>]  show or set how much a subsystem logs");
+    #[cfg(not(baremetal))]
+    println!("advance <ms>               move the virtual clock forward");
+    #[cfg(not(baremetal))]
+    println!("wake                       wake the system from a suspend");
+    #[cfg(feature = "profile")]
+    println!("prof                       dump and clear the profiler samples");
+    #[cfg(all(baremetal, feature = "print-mappings"))]
+    println!("maps                       dump the mappings of every process");
+}
+
+fn parse_pid(arg: Option<&str>) -> Option<PID> {
+    arg?.parse().ok().and_then(PID::new)
+}
+
+const SUBSYSTEMS: [(&str, LogSubsystem); 4] = [
+    ("syscalls", LogSubsystem::Syscalls),
+    ("memory", LogSubsystem::Memory),
+    ("interrupts", LogSubsystem::Interrupts),
+    ("scheduler", LogSubsystem::Scheduler),
+];
+
+const LEVELS: [(&str, LogLevel); 4] = [
+    ("off", LogLevel::Off),
+    ("errors", LogLevel::Errors),
+    ("info", LogLevel::Info),
+    ("debug", LogLevel::Debug),
+];
+
+fn parse_subsystem(name: &str) -> Option<LogSubsystem> {
+    SUBSYSTEMS
+        .iter()
+        .find(|(subsystem_name, _)| *subsystem_name == name)
+        .map(|(_, subsystem)| *subsystem)
+}
+
+fn parse_level(name: &str) -> Option<LogLevel> {
+    LEVELS
+        .iter()
+        .find(|(level_name, _)| *level_name == name)
+        .map(|(_, level)| *level)
+}
+
+fn print_log_levels() {
+    for (name, subsystem) in SUBSYSTEMS.iter() {
+        let level = crate::log::level(*subsystem);
+        let level_name = LEVELS
+            .iter()
+            .find(|(_, l)| *l == level)
+            .map(|(level_name, _)| *level_name)
+            .unwrap_or("?");
+        println!("{}: {}", name, level_name);
+    }
+}
+
+/// Preempt the process that the console interrupted, the same way that the
+/// tick timer does.
+#[cfg(baremetal)]
+fn reschedule() {
+    match unsafe { crate::arch::irq::isr_return_pair() } {
+        Some((pid, _)) if pid.get() != 1 => {
+            println!("Preempting PID {}", pid);
+            xous_kernel::rsyscall(xous_kernel::SysCall::ReturnToParentI(pid, 0))
+                .expect("couldn't preempt process");
+        }
+        _ => println!("The kernel was running, so there's nothing to preempt"),
+    }
+}
+
+/// Every hosted process runs on a host thread of its own, so there's never
+/// anything waiting for the CPU.
+#[cfg(not(baremetal))]
+fn reschedule() {
+    println!("Every process has a host thread of its own, so there's nothing to preempt");
+}
+
+/// Terminate `pid` as though it had terminated itself.
+fn kill(pid: PID) -> Result<(), xous_kernel::Error> {
+    if pid.get() == 1 {
+        return Err(xous_kernel::Error::AccessDenied);
+    }
+    SystemServices::with_mut(|ss| {
+        let current_pid = ss.current_pid();
+
+        // The console runs on top of whatever it interrupted.  If that's the
+        // process being killed, return to whoever switched to it instead.
+        #[cfg(baremetal)]
+        if let Some((interrupted, tid)) = unsafe { crate::arch::irq::isr_return_pair() } {
+            if interrupted == pid {
+                let (parent_pid, parent_tid) = ss
+                    .take_switched_from(pid, tid)
+                    .ok_or(xous_kernel::Error::ThreadNotAvailable)?;
+                unsafe { crate::arch::irq::set_isr_return_pair(parent_pid, parent_tid) };
+                ss.account_switch_in(parent_pid, parent_tid);
+            }
+        }
+
+        ss.terminate_process(pid)?;
+        ss.get_process(current_pid)?.activate()
+    })?;
+    println!("PID {} killed", pid);
+    Ok(())
+}
@@ -0,0 +1,103 @@
+//! Working out what went wrong after the kernel panics.  Every syscall is
+//! noted on its way in, and the last few are kept in a small ring, so the
+//! panic handler can say which call it was in the middle of and what led up
+//! to it, as well as printing the trap frame and a backtrace.
+
+use xous_kernel::{PID, TID};
+
+/// How many of the most recent syscalls are kept
+const RECENT_SYSCALLS: usize = 8;
+
+#[derive(Copy, Clone)]
+struct SyscallRecord {
+    pid: PID,
+    tid: TID,
+    args: [usize; 8],
+}
+
+struct SyscallTrace {
+    recent: [Option<SyscallRecord>; RECENT_SYSCALLS],
+
+    /// The slot the next syscall will be written to
+    next: usize,
+
+    /// Whether the newest syscall hasn't returned yet
+    in_progress: bool,
+}
+
+static mut TRACE: SyscallTrace = SyscallTrace {
+    recent: [None; RECENT_SYSCALLS],
+    next: 0,
+    in_progress: false,
+};
+
+/// Set once the panic handler has started reporting, so that a panic while
+/// reporting doesn't try again.
+static mut REPORTING: bool = false;
+
+/// Note that `pid:tid` made the syscall described by `args`.
+pub fn syscall_entry(pid: PID, tid: TID, args: &[usize; 8]) {
+    unsafe {
+        TRACE.recent[TRACE.next] = Some(SyscallRecord {
+            pid,
+            tid,
+            args: *args,
+        });
+        TRACE.next = (TRACE.next + 1) % RECENT_SYSCALLS;
+        TRACE.in_progress = true;
+    }
+}
+
+/// Note that the newest syscall has returned.
+pub fn syscall_exit() {
+    unsafe { TRACE.in_progress = false };
+}
+
+fn print_syscall(record: &SyscallRecord) {
+    println!(
+        "  PID {} TID {}: {} ({:08x} {:08x} {:08x} {:08x} {:08x} {:08x} {:08x})",
+        record.pid,
+        record.tid,
+        record.args[0],
+        record.args[1],
+        record.args[2],
+        record.args[3],
+        record.args[4],
+        record.args[5],
+        record.args[6],
+        record.args[7]
+    );
+}
+
+/// Print everything the kernel knows about where it was.  This is only done
+/// for the first panic, since a second one most likely came from in here.
+pub fn report() {
+    unsafe {
+        if REPORTING {
+            return;
+        }
+        REPORTING = true;
+    }
+
+    crate::arch::print_trap_frame();
+
+    let trace = unsafe { &TRACE };
+    let newest = (trace.next + RECENT_SYSCALLS - 1) % RECENT_SYSCALLS;
+    match trace.recent[newest] {
+        Some(record) if trace.in_progress => {
+            println!("Syscall in progress:");
+            print_syscall(&record);
+        }
+        _ => println!("No syscall in progress"),
+    }
+
+    println!("Backtrace:");
+    crate::arch::backtrace(|ra| println!("  {:08x}", ra));
+
+    println!("Recent syscalls, oldest first:");
+    for offset in 0..RECENT_SYSCALLS {
+        if let Some(record) = trace.recent[(trace.next + offset) % RECENT_SYSCALLS] {
+            print_syscall(&record);
+        }
+    }
+}
@@ -0,0 +1,232 @@
+//! A deadline scheduling class for threads that have to run on time, such as
+//! audio and radio servers.  A thread reserves a budget of timer ticks out of
+//! every period, and threads with a reservation are run earliest deadline
+//! first, ahead of every process in the normal priority classes.  A thread
+//! that uses up its budget falls back to being scheduled normally until its
+//! next period begins.
+//!
+//! Time is counted in timer ticks, so periods are stretched by any time the
+//! core spends idle with the timer stopped.
+
+use xous_kernel::{PID, TID};
+
+/// The most threads that may hold a reservation at once
+const MAX_RESERVATIONS: usize = 8;
+
+/// Utilization is measured in units of `1 / UTILIZATION_SCALE` of the CPU.
+const UTILIZATION_SCALE: usize = 1024;
+
+/// The most of the CPU that reservations may add up to.  Earliest deadline
+/// first can't meet every deadline past the whole CPU, and the rest is kept
+/// for the normal classes, so that a thread that holds on to its
+/// reservation can't starve everything else.
+const MAX_UTILIZATION: usize = UTILIZATION_SCALE * 9 / 10;
+
+// Hosted builds have no timer, so they only keep track of reservations.
+#[cfg_attr(not(baremetal), allow(dead_code))]
+#[derive(Copy, Clone)]
+struct Reservation {
+    pid: PID,
+    tid: TID,
+
+    /// How many ticks long each period is
+    period: usize,
+
+    /// How many ticks the thread may run for in each period
+    budget: usize,
+
+    /// How many ticks the thread has left in the current period
+    remaining: usize,
+
+    /// The tick at which the current period ends
+    deadline: u64,
+}
+
+impl Reservation {
+    fn utilization(&self) -> usize {
+        self.budget * UTILIZATION_SCALE / self.period
+    }
+}
+
+struct Deadlines {
+    reservations: [Option<Reservation>; MAX_RESERVATIONS],
+
+    /// The number of ticks counted since boot
+    now: u64,
+}
+
+impl Deadlines {
+    fn find_mut(&mut self, pid: PID, tid: TID) -> Option<&mut Reservation> {
+        self.reservations
+            .iter_mut()
+            .flatten()
+            .find(|r| r.pid == pid && r.tid == tid)
+    }
+
+    /// Start a new period for every reservation whose deadline has passed,
+    /// giving it its full budget again.
+    #[cfg(baremetal)]
+    fn replenish(&mut self) {
+        let now = self.now;
+        for r in self.reservations.iter_mut().flatten() {
+            if r.deadline <= now {
+                let missed = (now - r.deadline) / r.period as u64 + 1;
+                r.deadline += missed * r.period as u64;
+                r.remaining = r.budget;
+            }
+        }
+    }
+
+    /// Find the ready thread with budget left whose deadline is soonest,
+    /// ignoring `skip`.
+    #[cfg(baremetal)]
+    fn earliest(&self, skip: Option<(PID, TID)>) -> Option<&Reservation> {
+        crate::services::SystemServices::with(|ss| {
+            self.reservations
+                .iter()
+                .flatten()
+                .filter(|r| r.remaining > 0 && Some((r.pid, r.tid)) != skip)
+                .filter(|r| ss.thread_is_ready(r.pid, r.tid))
+                .min_by_key(|r| r.deadline)
+        })
+    }
+}
+
+const EMPTY: Deadlines = Deadlines {
+    reservations: [None; MAX_RESERVATIONS],
+    now: 0,
+};
+
+#[cfg(baremetal)]
+static mut DEADLINES: Deadlines = EMPTY;
+
+#[cfg(not(baremetal))]
+std::thread_local!(static DEADLINES: core::cell::RefCell<Deadlines> = const { core::cell::RefCell::new(EMPTY) });
+
+fn with_mut<F, R>(f: F) -> R
+where
+    F: FnOnce(&mut Deadlines) -> R,
+{
+    #[cfg(baremetal)]
+    unsafe {
+        f(&mut DEADLINES)
+    }
+
+    #[cfg(not(baremetal))]
+    DEADLINES.with(|deadlines| f(&mut deadlines.borrow_mut()))
+}
+
+/// Give thread `tid` of `pid` `budget` ticks out of every `period`, starting
+/// now.  A `period` and `budget` of `0` remove its reservation.
+///
+/// # Errors
+///
+/// * **InvalidSyscall**: The budget is `0` or longer than the period
+/// * **OutOfMemory**: The reservation table is full, or the reservation
+///                    would take the total utilization past `MAX_UTILIZATION`
+pub fn set(pid: PID, tid: TID, period: usize, budget: usize) -> Result<(), xous_kernel::Error> {
+    if period == 0 && budget == 0 {
+        clear(pid, tid);
+        return Ok(());
+    }
+    if budget == 0 || budget > period {
+        return Err(xous_kernel::Error::InvalidSyscall);
+    }
+
+    with_mut(|deadlines| {
+        let reservation = Reservation {
+            pid,
+            tid,
+            period,
+            budget,
+            remaining: budget,
+            deadline: deadlines.now + period as u64,
+        };
+
+        let others: usize = deadlines
+            .reservations
+            .iter()
+            .flatten()
+            .filter(|r| r.pid != pid || r.tid != tid)
+            .map(|r| r.utilization())
+            .sum();
+        if others + reservation.utilization() > MAX_UTILIZATION {
+            return Err(xous_kernel::Error::OutOfMemory);
+        }
+
+        if let Some(existing) = deadlines.find_mut(pid, tid) {
+            *existing = reservation;
+            return Ok(());
+        }
+        let slot = deadlines
+            .reservations
+            .iter_mut()
+            .find(|slot| slot.is_none())
+            .ok_or(xous_kernel::Error::OutOfMemory)?;
+        *slot = Some(reservation);
+        Ok(())
+    })
+}
+
+/// Return thread `tid` of `pid` to the normal scheduling class.
+pub fn clear(pid: PID, tid: TID) {
+    with_mut(|deadlines| {
+        for slot in deadlines.reservations.iter_mut() {
+            if slot.map(|r| r.pid == pid && r.tid == tid).unwrap_or(false) {
+                *slot = None;
+            }
+        }
+    })
+}
+
+/// Drop every reservation held by a process that is exiting.
+pub fn forget_process(pid: PID) {
+    with_mut(|deadlines| {
+        for slot in deadlines.reservations.iter_mut() {
+            if slot.map(|r| r.pid == pid).unwrap_or(false) {
+                *slot = None;
+            }
+        }
+    })
+}
+
+/// Pick the thread in the deadline class that should run next, if any is
+/// ready and has budget left.
+#[cfg(baremetal)]
+pub fn next() -> Option<(PID, TID)> {
+    with_mut(|deadlines| {
+        deadlines.replenish();
+        deadlines.earliest(None).map(|r| (r.pid, r.tid))
+    })
+}
+
+/// Count a timer tick while thread `tid` of `pid` is running.  Returns
+/// whether it should be preempted, or `None` if neither it nor any ready
+/// thread is in the deadline class, in which case the normal quantum
+/// applies.  This must only be called from an interrupt context.
+#[cfg(baremetal)]
+pub fn tick(pid: PID, tid: TID) -> Option<bool> {
+    with_mut(|deadlines| {
+        deadlines.now += 1;
+        deadlines.replenish();
+
+        let current = match deadlines.find_mut(pid, tid) {
+            Some(r) if r.remaining > 0 => {
+                r.remaining -= 1;
+                if r.remaining == 0 {
+                    return Some(true);
+                }
+                Some(r.deadline)
+            }
+            _ => None,
+        };
+        let waiting = deadlines.earliest(Some((pid, tid))).map(|r| r.deadline);
+
+        match (current, waiting) {
+            (Some(current), Some(waiting)) => Some(waiting < current),
+            (Some(_), None) => Some(false),
+            (None, Some(_)) => Some(true),
+            (None, None) => None,
+        }
+    })
+}
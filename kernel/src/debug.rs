@@ -84,13 +84,10 @@ impl Uart {
 
 #[cfg(all(not(test), any(feature = "debug-print", feature = "print-panics")))]
 pub fn irq(_irq_number: usize, _arg: *mut usize) {
-    println!(
-        "Interrupt {}: Key pressed: {}",
-        _irq_number,
-        SUPERVISOR_UART
-            .getc()
-            .expect("no character queued despite interrupt") as char
-    );
+    let c = SUPERVISOR_UART
+        .getc()
+        .expect("no character queued despite interrupt");
+    crate::console::feed(c);
 }
 
 impl Write for Uart {
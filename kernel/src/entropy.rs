@@ -0,0 +1,150 @@
+//! A small random number generator for the kernel, and for processes that
+//! need randomness before the TRNG server is running.  It is seeded from
+//! the hardware TRNG early in boot, or from the host in a hosted
+//! environment, and stirs in fresh material from the TRNG every
+//! `RESEED_TICKS` after that.  Processes may stir in material of their own
+//! with `AddEntropy`, but that never counts as a seed, since the kernel
+//! can't tell how random it is.
+//!
+//! Each block of output is a SHA3-256 hash of the key and a counter, and
+//! the key is replaced with a hash of itself after every request, so output
+//! that has already been handed out can't be worked out from a later state.
+
+use sha3::{Digest, Sha3_256};
+
+/// How many bytes of TRNG output go into each seed
+const SEED_LEN: usize = 32;
+
+/// How many timer ticks go by between reseeds from the TRNG
+const RESEED_TICKS: u64 = 1000;
+
+struct Generator {
+    key: [u8; 32],
+
+    /// How many blocks have been generated, which makes each block different
+    counter: u64,
+
+    /// Whether the key has been mixed with a seed from the hardware
+    seeded: bool,
+
+    /// The tick of the last reseed from the TRNG
+    last_reseed: u64,
+}
+
+const EMPTY: Generator = Generator {
+    key: [0; 32],
+    counter: 0,
+    seeded: false,
+    last_reseed: 0,
+};
+
+#[cfg(baremetal)]
+static mut GENERATOR: Generator = EMPTY;
+
+#[cfg(not(baremetal))]
+std::thread_local!(static GENERATOR: core::cell::RefCell<Generator> = const { core::cell::RefCell::new(EMPTY) });
+
+fn with_mut<F, R>(f: F) -> R
+where
+    F: FnOnce(&mut Generator) -> R,
+{
+    #[cfg(baremetal)]
+    unsafe {
+        f(&mut GENERATOR)
+    }
+
+    #[cfg(not(baremetal))]
+    GENERATOR.with(|generator| f(&mut generator.borrow_mut()))
+}
+
+/// Replace the key with a hash of the old key, `label`, and `material`.
+fn mix(generator: &mut Generator, label: &[u8], material: &[u8]) {
+    let key = Sha3_256::new()
+        .chain(label)
+        .chain(generator.key)
+        .chain(generator.counter.to_le_bytes())
+        .chain(material)
+        .result();
+    generator.key.copy_from_slice(&key);
+}
+
+/// Stir a fresh seed from the hardware TRNG into the generator.  Returns
+/// `false` if there is no TRNG, or it didn't produce a seed in time.
+pub fn reseed() -> bool {
+    let mut seed = [0u8; SEED_LEN];
+    if !crate::arch::trng_read(&mut seed) {
+        return false;
+    }
+    with_mut(|generator| {
+        mix(generator, b"reseed", &seed);
+        generator.seeded = true;
+    });
+    true
+}
+
+/// Seed the generator for the first time.  This should happen as early in
+/// boot as the TRNG can be read.
+pub fn init() {
+    if !reseed() {
+        xous_kernel::cover!("entropy: no seed at boot");
+    }
+}
+
+/// Reseed from the TRNG if it has been long enough since the last time.
+/// This is called on every timer tick.
+#[cfg_attr(not(baremetal), allow(dead_code))]
+pub fn tick(ticks: u64) {
+    let due = with_mut(|generator| {
+        if ticks.wrapping_sub(generator.last_reseed) < RESEED_TICKS {
+            return false;
+        }
+        generator.last_reseed = ticks;
+        true
+    });
+    if due && !reseed() {
+        xous_kernel::cover!("entropy: periodic reseed failed");
+    }
+}
+
+/// Stir `material` from a process into the generator.  This can't make the
+/// output any easier to guess, but doesn't count as a seed either.
+pub fn add(material: &[u8]) {
+    with_mut(|generator| mix(generator, b"add", material))
+}
+
+/// Fill `buf` with random bytes.
+///
+/// # Errors
+///
+/// * **UnhandledSyscall**: The generator has never been seeded, because
+///   there's no TRNG
+pub fn fill(buf: &mut [u8]) -> Result<(), xous_kernel::Error> {
+    with_mut(|generator| {
+        if !generator.seeded {
+            return Err(xous_kernel::Error::UnhandledSyscall);
+        }
+        for chunk in buf.chunks_mut(32) {
+            let block = Sha3_256::new()
+                .chain(b"output")
+                .chain(generator.key)
+                .chain(generator.counter.to_le_bytes())
+                .result();
+            generator.counter = generator.counter.wrapping_add(1);
+            chunk.copy_from_slice(&block[..chunk.len()]);
+        }
+        mix(generator, b"rekey", &[]);
+        Ok(())
+    })
+}
+
+/// Forget the generator's state, as the system goes down, so that nothing
+/// it has handed out can be worked out from what's left in RAM.  The
+/// generator counts as unseeded afterwards.
+pub fn wipe() {
+    with_mut(|generator| {
+        for byte in generator.key.iter_mut() {
+            unsafe { core::ptr::write_volatile(byte, 0) };
+        }
+        *generator = EMPTY;
+    })
+}
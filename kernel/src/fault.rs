@@ -0,0 +1,305 @@
+//! Crash reports for processes that fault.  A process that faults without
+//! an exception handler to deal with it is terminated rather than taking
+//! the system down with it, and what's left of it is kept here: where it
+//! faulted, its registers, and the top of its stack.  The crash supervisor,
+//! if there is one, has its notification signalled so it can read the
+//! report and restart the process, and the parent may read the reports of
+//! its own children.
+//!
+//! Only the last few reports are kept, so that a process that keeps
+//! crashing can't use up kernel memory.
+//!
+//! When there is a supervisor, the crashed process' writable pages are kept
+//! as well, as a core that the supervisor takes one page at a time to save
+//! or send off the device.  Only one core is kept at a time, and it stays
+//! until the supervisor has taken all of it.
+
+use xous_kernel::{CrashReport, MemoryRange, CRASH_REGISTERS, CRASH_STACK_WORDS, PID};
+
+/// How many crash reports are kept before the oldest is overwritten
+const MAX_CRASH_REPORTS: usize = 4;
+
+/// The most pages kept for a core
+#[cfg(baremetal)]
+const MAX_CORE_PAGES: usize = 64;
+
+/// The number of words saved with each report
+pub const CRASH_WORDS: usize = CRASH_REGISTERS + CRASH_STACK_WORDS;
+
+#[derive(Copy, Clone)]
+struct Crash {
+    report: CrashReport,
+
+    /// The registers, followed by the top of the stack
+    words: [usize; CRASH_WORDS],
+}
+
+/// The pages of a crashed process, which the kernel owns until the
+/// supervisor takes them
+#[cfg(baremetal)]
+struct Core {
+    /// The report of the crash this is the core of
+    sequence: usize,
+
+    /// The address each page was at in the crashed process, and the
+    /// physical page
+    pages: [Option<(usize, usize)>; MAX_CORE_PAGES],
+}
+
+struct Crashes {
+    reports: [Option<Crash>; MAX_CRASH_REPORTS],
+
+    /// The sequence number the next report will get
+    next_sequence: usize,
+
+    /// The process that is told about crashes, and the notification it
+    /// wants signalled
+    supervisor: Option<(PID, usize)>,
+
+    /// The core the supervisor hasn't finished taking yet
+    #[cfg(baremetal)]
+    core: Option<Core>,
+}
+
+const EMPTY: Crashes = Crashes {
+    reports: [None; MAX_CRASH_REPORTS],
+    next_sequence: 0,
+    supervisor: None,
+    #[cfg(baremetal)]
+    core: None,
+};
+
+/// The kernel's own PID, which owns the pages of a core
+#[cfg(baremetal)]
+const KERNEL_PID: PID = unsafe { PID::new_unchecked(1) };
+
+#[cfg(baremetal)]
+static mut CRASHES: Crashes = EMPTY;
+
+#[cfg(not(baremetal))]
+std::thread_local!(static CRASHES: core::cell::RefCell<Crashes> = const { core::cell::RefCell::new(EMPTY) });
+
+fn with_mut<F, R>(f: F) -> R
+where
+    F: FnOnce(&mut Crashes) -> R,
+{
+    #[cfg(baremetal)]
+    unsafe {
+        f(&mut CRASHES)
+    }
+
+    #[cfg(not(baremetal))]
+    CRASHES.with(|crashes| f(&mut crashes.borrow_mut()))
+}
+
+impl Crashes {
+    /// Whether `pid` may read `report`
+    fn may_read(&self, pid: PID, report: &CrashReport) -> bool {
+        report.ppid == pid || self.is_supervisor(pid)
+    }
+
+    /// The report numbered `sequence`, if it's still kept
+    fn get(&self, sequence: usize) -> Option<&Crash> {
+        self.reports[sequence % MAX_CRASH_REPORTS]
+            .as_ref()
+            .filter(|crash| crash.report.sequence == sequence)
+    }
+
+    /// Whether `pid` is the supervisor
+    fn is_supervisor(&self, pid: PID) -> bool {
+        matches!(self.supervisor, Some((supervisor, _)) if supervisor == pid)
+    }
+
+    /// Take the writable pages of `pid`, which is the current process, away
+    /// from it, so that they're still around once it has been terminated.
+    #[cfg(baremetal)]
+    fn keep_core(&mut self, pid: PID, sequence: usize) {
+        if self.core.is_some() || self.supervisor.is_none() || self.is_supervisor(pid) {
+            return;
+        }
+        let mut pages = [None; MAX_CORE_PAGES];
+        let mut count = 0;
+        crate::mem::MemoryManager::with_mut(|mm| {
+            crate::arch::mem::writable_user_pages(|virt, phys| {
+                // Pages borrowed from other processes aren't the crashed
+                // process' to give away.
+                if count < MAX_CORE_PAGES
+                    && mm.is_main_memory(phys as *mut u8)
+                    && mm.give_page(phys, pid, KERNEL_PID).is_ok()
+                {
+                    pages[count] = Some((virt, phys));
+                    count += 1;
+                }
+            })
+        });
+        self.core = Some(Core { sequence, pages });
+    }
+
+    /// Give back every page of the core that hasn't been taken.
+    #[cfg(baremetal)]
+    fn discard_core(&mut self) {
+        if let Some(core) = self.core.take() {
+            crate::mem::MemoryManager::with_mut(|mm| {
+                for (_, phys) in core.pages.iter().flatten() {
+                    mm.release_page(*phys as *mut usize, KERNEL_PID).ok();
+                }
+            });
+        }
+    }
+}
+
+/// Keep a report of a crash of `pid`, which is the current process,
+/// overwriting the oldest one if there's no room, along with a core if
+/// there's a supervisor to take it.  Returns the notification of the
+/// supervisor, which the caller should signal once the process has been
+/// dealt with.
+#[cfg(baremetal)]
+pub fn record(
+    pid: PID,
+    ppid: PID,
+    tid: xous_kernel::TID,
+    cause: usize,
+    pc: usize,
+    addr: usize,
+    words: &[usize; CRASH_WORDS],
+) -> Option<usize> {
+    with_mut(|crashes| {
+        let sequence = crashes.next_sequence;
+        crashes.reports[sequence % MAX_CRASH_REPORTS] = Some(Crash {
+            report: CrashReport {
+                sequence,
+                pid,
+                ppid,
+                tid,
+                cause,
+                pc,
+                addr,
+            },
+            words: *words,
+        });
+        crashes.next_sequence = sequence.wrapping_add(1);
+        crashes.keep_core(pid, sequence);
+        crashes.supervisor.map(|(_, id)| id)
+    })
+}
+
+/// Make `pid` the supervisor, to be told about crashes by having
+/// notification `id` signalled, or stop having a supervisor if `id` is `0`.
+/// It's up to the caller to make sure that `pid` owns the notification.
+/// Any core that the previous supervisor didn't finish taking is discarded.
+pub fn set_supervisor(pid: PID, id: usize) {
+    with_mut(|crashes| {
+        if !crashes.is_supervisor(pid) {
+            #[cfg(baremetal)]
+            crashes.discard_core();
+        }
+        crashes.supervisor = if id == 0 { None } else { Some((pid, id)) };
+    })
+}
+
+/// The oldest report that `pid` may read whose sequence number is at least
+/// `sequence`, or `None` if there is no such report yet.
+pub fn read(pid: PID, sequence: usize) -> Option<CrashReport> {
+    with_mut(|crashes| {
+        let oldest = crashes.next_sequence.saturating_sub(MAX_CRASH_REPORTS);
+        (sequence.max(oldest)..crashes.next_sequence)
+            .filter_map(|sequence| crashes.get(sequence))
+            .map(|crash| crash.report)
+            .find(|report| crashes.may_read(pid, report))
+    })
+}
+
+/// Word `index` of the registers and stack saved with report `sequence`.
+///
+/// # Errors
+///
+/// * **AccessDenied**: `pid` may not read the report
+/// * **InvalidSyscall**: The report is no longer kept, or `index` is past
+///                       the end
+pub fn word(pid: PID, sequence: usize, index: usize) -> Result<usize, xous_kernel::Error> {
+    with_mut(|crashes| {
+        let crash = crashes
+            .get(sequence)
+            .ok_or(xous_kernel::Error::InvalidSyscall)?;
+        if !crashes.may_read(pid, &crash.report) {
+            return Err(xous_kernel::Error::AccessDenied);
+        }
+        crash
+            .words
+            .get(index)
+            .copied()
+            .ok_or(xous_kernel::Error::InvalidSyscall)
+    })
+}
+
+/// Map the next page of the core of crash `sequence` into `pid`, which is
+/// the current process.  Returns where the page now is, and where it was
+/// in the crashed process, or `None` once every page has been taken.
+///
+/// # Errors
+///
+/// * **AccessDenied**: `pid` isn't the supervisor
+#[cfg(baremetal)]
+pub fn take_core_page(
+    pid: PID,
+    sequence: usize,
+) -> Result<Option<(MemoryRange, usize)>, xous_kernel::Error> {
+    with_mut(|crashes| {
+        if !crashes.is_supervisor(pid) {
+            return Err(xous_kernel::Error::AccessDenied);
+        }
+        let (virt, phys) = match crashes.core.as_mut() {
+            Some(core) if core.sequence == sequence => {
+                match core.pages.iter_mut().find_map(|page| page.take()) {
+                    Some(page) => page,
+                    None => {
+                        crashes.core = None;
+                        return Ok(None);
+                    }
+                }
+            }
+            _ => return Ok(None),
+        };
+        crate::mem::MemoryManager::with_mut(|mm| {
+            mm.give_page(phys, KERNEL_PID, pid)?;
+            mm.map_range(
+                phys as *mut u8,
+                core::ptr::null_mut(),
+                crate::mem::PAGE_SIZE,
+                pid,
+                xous_kernel::MemoryFlags::R | xous_kernel::MemoryFlags::W,
+                xous_kernel::MemoryType::Default,
+            )
+            .map_err(|e| {
+                mm.release_page(phys as *mut usize, pid).ok();
+                e
+            })
+        })
+        .map(|range| Some((range, virt)))
+    })
+}
+
+/// Processes can't crash when hosted, so there's never a core to take.
+#[cfg(not(baremetal))]
+pub fn take_core_page(
+    pid: PID,
+    _sequence: usize,
+) -> Result<Option<(MemoryRange, usize)>, xous_kernel::Error> {
+    with_mut(|crashes| {
+        if !crashes.is_supervisor(pid) {
+            return Err(xous_kernel::Error::AccessDenied);
+        }
+        Ok(None)
+    })
+}
+
+/// Drop `pid` as the supervisor if it was one, since it's going away.
+pub fn forget_process(pid: PID) {
+    with_mut(|crashes| {
+        if crashes.is_supervisor(pid) {
+            #[cfg(baremetal)]
+            crashes.discard_core();
+            crashes.supervisor = None;
+        }
+    })
+}
@@ -0,0 +1,182 @@
+//! An entry point for fuzzing the syscall interface.  `fuzz_syscall()`
+//! turns arbitrary bytes into a sequence of syscalls and hands each one to
+//! `syscall::handle_inner()`, the same as a process trapping into the
+//! kernel would, so that a fuzzer can look for arguments that panic the
+//! kernel before an attacker does.
+//!
+//! The input is split into records of `RECORD_LENGTH` bytes.  The first
+//! byte of each record picks which thread makes the call, and the rest are
+//! the eight words of the call, each a little-endian `u32`, which
+//! `SysCall::from_args()` decodes exactly as it would for a real process.
+//! Calls that don't decode are dropped, as are calls from threads that are
+//! blocked, since those can't make syscalls.
+//!
+//! The kernel runs on the hosted arch layer with simulated RAM, so that
+//! processes have pagetables, and memory syscalls and message payloads are
+//! checked against them the way they are on hardware.  There's PID 1, and
+//! two processes to make calls from, with a server at `SERVER` in the first
+//! of them.  Each of the two has `MAPPED_PAGES` pages of RAM at `MAPPED`
+//! for payloads to point into.  A few calls still can't be made on the
+//! host, and are dropped as well:
+//!
+//! * `MapMemory` with a physical address, since the kernel zeroes the new
+//!   pages through the process's own mapping, which isn't host memory
+//! * `SwitchTo`, `ReturnToParentI` and `WaitEvent`, since every process
+//!   runs on a host thread of its own
+//!
+//! New processes are never given a host program to start, since the
+//! command that describes it is in host memory.
+//!
+//! This is built for tests, and with the `fuzz` feature for the `syscalls`
+//! target in `fuzz/`, which `cargo fuzz run syscalls` runs.
+
+use crate::arch::process::{set_current_pid, take_direct_result};
+use crate::mem::{MemoryManager, PAGE_SIZE};
+use crate::services::SystemServices;
+use crate::syscall::SysCallOutcome;
+use xous_kernel::{
+    Capabilities, Error, MemoryFlags, MemoryType, ProcessInit, ProcessKey, Quotas, Result, SysCall,
+    ThreadInit, PID, SID, TID,
+};
+
+/// The number of bytes of input turned into each syscall
+pub const RECORD_LENGTH: usize = 1 + 8 * 4;
+
+/// Where simulated RAM starts, and how many pages of it there are
+const RAM_START: usize = 0x4000_0000;
+const RAM_PAGES: usize = 64;
+
+/// Where each process that makes calls has RAM mapped, and how much
+pub const MAPPED: usize = 0x1000_0000;
+const MAPPED_PAGES: usize = 4;
+
+/// The server that's running in the first process, which a fuzzer finds by
+/// sending to SID words 1, 2, 3 and 4
+pub const SERVER: (u32, u32, u32, u32) = (1, 2, 3, 4);
+
+/// Run every syscall in `bytes` against a fresh kernel.  Any panic is a
+/// bug, and is passed on to the caller.
+pub fn fuzz_syscall(bytes: &[u8]) {
+    let bytes = bytes.to_vec();
+    // Kernel state is kept per host thread, so a new thread starts from
+    // nothing and leaves nothing behind.
+    if let Err(panic) = std::thread::spawn(move || run(&bytes)).join() {
+        std::panic::resume_unwind(panic);
+    }
+}
+
+fn run(bytes: &[u8]) {
+    MemoryManager::with_mut(|mm| mm.simulate_ram(RAM_START, RAM_PAGES * PAGE_SIZE));
+    let pid1 = spawn(None);
+    let server = spawn(Some(pid1));
+    let client = spawn(Some(pid1));
+
+    // Map pages from the top of RAM, since pagetables come from the bottom.
+    for (index, &pid) in [server, client].iter().enumerate() {
+        let phys = RAM_START + (RAM_PAGES - (index + 1) * MAPPED_PAGES) * PAGE_SIZE;
+        set_current_pid(pid);
+        MemoryManager::with_mut(|mm| {
+            mm.map_range(
+                phys as *mut u8,
+                MAPPED as *mut u8,
+                MAPPED_PAGES * PAGE_SIZE,
+                pid,
+                MemoryFlags::R | MemoryFlags::W,
+                MemoryType::Default,
+            )
+        })
+        .expect("couldn't map pages");
+    }
+
+    set_current_pid(server);
+    let sid = SID::from_u32(SERVER.0, SERVER.1, SERVER.2, SERVER.3);
+    crate::syscall::handle_inner(server, 1, SysCall::CreateServer(sid))
+        .expect("couldn't create server");
+
+    let mut threads = vec![(server, 1), (client, 1)];
+    let mut blocked: Vec<(PID, TID)> = vec![];
+
+    for record in bytes.chunks_exact(RECORD_LENGTH) {
+        // Threads whose results have come in may run again.
+        blocked.retain(|&(pid, tid)| take_direct_result(pid, tid).is_none());
+
+        // Forget processes that have gone away, before their PIDs are reused.
+        threads.retain(|&(pid, _)| {
+            SystemServices::with(|ss| ss.get_process(pid).map(|p| !p.free()).unwrap_or(false))
+        });
+        if threads.is_empty() {
+            break;
+        }
+        let (pid, tid) = threads[record[0] as usize % threads.len()];
+        if blocked.contains(&(pid, tid)) {
+            continue;
+        }
+
+        let mut words = [0usize; 8];
+        for (word, bytes) in words.iter_mut().zip(record[1..].chunks_exact(4)) {
+            *word = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize;
+        }
+        let mut call = match SysCall::from_args(
+            words[0], words[1], words[2], words[3], words[4], words[5], words[6], words[7],
+        ) {
+            Ok(call) => call,
+            Err(_) => continue,
+        };
+        if !hosted(&call) {
+            continue;
+        }
+        if let SysCall::CreateProcess(init) = &mut call {
+            init.command = None;
+        }
+
+        set_current_pid(pid);
+        match crate::syscall::handle_inner(pid, tid, call) {
+            Ok(SysCallOutcome::Blocked) => blocked.push((pid, tid)),
+            Ok(SysCallOutcome::Return(Result::ThreadID(new_tid))) => threads.push((pid, new_tid)),
+            Ok(SysCallOutcome::Return(Result::ProcessID(new_pid))) => {
+                let started = SystemServices::with_mut(|ss| {
+                    ss.create_thread(new_pid, ThreadInit {})?;
+                    ss.switch_to_thread(new_pid, None)
+                });
+                if started.is_ok() {
+                    threads.push((new_pid, 1));
+                }
+            }
+            Ok(_) | Err(_) => (),
+        }
+    }
+}
+
+/// Create a process with a single thread, as though it had connected.
+/// PID 1 has no parent, and doesn't get a thread.
+pub fn spawn(parent: Option<PID>) -> PID {
+    let init = ProcessInit {
+        key: ProcessKey::new([0; 16]),
+        capabilities: Capabilities::all(),
+        quotas: Quotas::unlimited(),
+        command: None,
+    };
+    SystemServices::with_mut(|ss| {
+        if let Some(parent) = parent {
+            set_current_pid(parent);
+        }
+        let pid = ss.create_process(init)?;
+        if parent.is_some() {
+            ss.create_thread(pid, ThreadInit {})?;
+            ss.switch_to_thread(pid, None)?;
+        }
+        Ok::<_, Error>(pid)
+    })
+    .expect("couldn't spawn process")
+}
+
+/// Whether the hosted arch layer is able to do what `call` asks.
+fn hosted(call: &SysCall) -> bool {
+    !matches!(
+        call,
+        SysCall::MapMemory(Some(_), ..)
+            | SysCall::SwitchTo(..)
+            | SysCall::ReturnToParentI(..)
+            | SysCall::WaitEvent
+    )
+}
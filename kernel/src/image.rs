@@ -0,0 +1,239 @@
+//! Address space reservations for a userspace dynamic loader.  A loader
+//! reserves a range of virtual addresses big enough for a whole image, then
+//! maps each of the image's segments at its offset inside the reservation.
+//! The kernel remembers where every segment went, so the layout of a process
+//! can be reported without any help from the loader.
+//!
+//! Reserved addresses are never handed out to anything else, and stay
+//! reserved until the process exits.
+
+use xous_kernel::{MemoryFlags, MemoryRange, PID};
+
+/// The most address space reservations that may exist at once
+const MAX_RESERVATIONS: usize = 16;
+
+/// The most segments that may be mapped into reservations at once
+const MAX_SEGMENTS: usize = 64;
+
+#[derive(Copy, Clone)]
+struct Reservation {
+    pid: PID,
+    range: MemoryRange,
+}
+
+#[derive(Copy, Clone)]
+struct Segment {
+    pid: PID,
+
+    /// The start of the reservation the segment was mapped into
+    base: usize,
+
+    /// Where the segment starts, relative to `base`
+    offset: usize,
+
+    size: usize,
+    flags: MemoryFlags,
+}
+
+impl Segment {
+    fn start(&self) -> usize {
+        self.base + self.offset
+    }
+}
+
+struct Images {
+    reservations: [Option<Reservation>; MAX_RESERVATIONS],
+    segments: [Option<Segment>; MAX_SEGMENTS],
+}
+
+const EMPTY: Images = Images {
+    reservations: [None; MAX_RESERVATIONS],
+    segments: [None; MAX_SEGMENTS],
+};
+
+#[cfg(baremetal)]
+static mut IMAGES: Images = EMPTY;
+
+#[cfg(not(baremetal))]
+std::thread_local!(static IMAGES: core::cell::RefCell<Images> = const { core::cell::RefCell::new(EMPTY) });
+
+fn with_mut<F, R>(f: F) -> R
+where
+    F: FnOnce(&mut Images) -> R,
+{
+    #[cfg(baremetal)]
+    unsafe {
+        f(&mut IMAGES)
+    }
+
+    #[cfg(not(baremetal))]
+    IMAGES.with(|images| f(&mut images.borrow_mut()))
+}
+
+/// Whether the `size` bytes at `a` share any addresses with the `other_size`
+/// bytes at `b`.
+fn overlaps(a: usize, size: usize, b: usize, other_size: usize) -> bool {
+    a < b + other_size && b < a + size
+}
+
+/// Whether any of the `size` bytes at `addr` are reserved by `pid`.
+pub fn reserved(pid: PID, addr: usize, size: usize) -> bool {
+    with_mut(|images| {
+        images
+            .reservations
+            .iter()
+            .flatten()
+            .any(|r| r.pid == pid && overlaps(addr, size, r.range.addr.get(), r.range.size.get()))
+    })
+}
+
+/// Keep `range` of `pid`'s address space for segments that are mapped
+/// with `add_segment()`.
+///
+/// # Errors
+///
+/// * **MemoryInUse**: Part of the range is already reserved
+/// * **OutOfMemory**: The reservation table is full
+pub fn reserve(pid: PID, range: MemoryRange) -> Result<(), xous_kernel::Error> {
+    if reserved(pid, range.addr.get(), range.size.get()) {
+        return Err(xous_kernel::Error::MemoryInUse);
+    }
+    with_mut(|images| {
+        let slot = images
+            .reservations
+            .iter_mut()
+            .find(|slot| slot.is_none())
+            .ok_or(xous_kernel::Error::OutOfMemory)?;
+        *slot = Some(Reservation { pid, range });
+        Ok(())
+    })
+}
+
+/// Record a segment of `size` bytes at `offset` into the reservation that
+/// starts at `base`, returning the addresses it should be mapped at.
+///
+/// # Errors
+///
+/// * **BadAddress**: There is no reservation at `base`, or the segment
+///                   doesn't fit inside it
+/// * **MemoryInUse**: The segment overlaps one that was already added
+/// * **OutOfMemory**: The segment table is full
+pub fn add_segment(
+    pid: PID,
+    base: usize,
+    offset: usize,
+    size: usize,
+    flags: MemoryFlags,
+) -> Result<MemoryRange, xous_kernel::Error> {
+    with_mut(|images| {
+        let reservation = images
+            .reservations
+            .iter()
+            .flatten()
+            .find(|r| r.pid == pid && r.range.addr.get() == base)
+            .ok_or(xous_kernel::Error::BadAddress)?;
+        if offset > reservation.range.size.get() || size > reservation.range.size.get() - offset {
+            return Err(xous_kernel::Error::BadAddress);
+        }
+
+        let segment = Segment {
+            pid,
+            base,
+            offset,
+            size,
+            flags,
+        };
+        if images
+            .segments
+            .iter()
+            .flatten()
+            .any(|s| s.pid == pid && overlaps(segment.start(), size, s.start(), s.size))
+        {
+            return Err(xous_kernel::Error::MemoryInUse);
+        }
+        let slot = images
+            .segments
+            .iter_mut()
+            .find(|slot| slot.is_none())
+            .ok_or(xous_kernel::Error::OutOfMemory)?;
+        *slot = Some(segment);
+        MemoryRange::new(segment.start(), size)
+    })
+}
+
+/// Find the segment of `pid` that starts at `addr`, returning its offset
+/// into its reservation, its size, and the flags it was mapped with.
+pub fn find_segment(pid: PID, addr: usize) -> Option<(usize, usize, MemoryFlags)> {
+    with_mut(|images| {
+        images
+            .segments
+            .iter()
+            .flatten()
+            .find(|s| s.pid == pid && s.start() == addr)
+            .map(|s| (s.offset, s.size, s.flags))
+    })
+}
+
+/// Forget the segment of `pid` that starts at `addr`, such as when it
+/// couldn't be mapped after all.
+pub fn remove_segment(pid: PID, addr: usize) {
+    with_mut(|images| {
+        for slot in images.segments.iter_mut() {
+            if slot
+                .map(|s| s.pid == pid && s.start() == addr)
+                .unwrap_or(false)
+            {
+                *slot = None;
+            }
+        }
+    })
+}
+
+/// Drop every reservation and segment of a process that is exiting.
+pub fn forget_process(pid: PID) {
+    with_mut(|images| {
+        for slot in images.reservations.iter_mut() {
+            if slot.map(|r| r.pid == pid).unwrap_or(false) {
+                *slot = None;
+            }
+        }
+        for slot in images.segments.iter_mut() {
+            if slot.map(|s| s.pid == pid).unwrap_or(false) {
+                *slot = None;
+            }
+        }
+    })
+}
+
+/// Print the reservations of `pid` and the segments mapped into them.
+#[cfg(feature = "print-mappings")]
+pub fn print(pid: PID) {
+    with_mut(|images| {
+        for r in images
+            .reservations
+            .iter()
+            .flatten()
+            .filter(|r| r.pid == pid)
+        {
+            println!(
+                "Reserved {:08x} - {:08x}",
+                r.range.addr.get(),
+                r.range.addr.get() + r.range.size.get()
+            );
+            for s in images
+                .segments
+                .iter()
+                .flatten()
+                .filter(|s| s.pid == pid && s.base == r.range.addr.get())
+            {
+                println!(
+                    "    +{:08x}: {:08x} - {:08x} {:?}",
+                    s.offset,
+                    s.start(),
+                    s.start() + s.size,
+                    s.flags
+                );
+            }
+        }
+    })
+}
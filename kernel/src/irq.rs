@@ -1,10 +1,68 @@
 use crate::arch;
-use xous_kernel::{MemoryAddress, PID};
+use xous_kernel::{MemoryAddress, PID, SID, TID};
 
-static mut IRQ_HANDLERS: [Option<(PID, MemoryAddress, Option<MemoryAddress>)>; 32] = [None; 32];
+/// How many interrupts there are to claim
+const IRQ_COUNT: usize = 32;
 
+/// The most processes that may share one interrupt line
+const MAX_SHARERS: usize = 4;
+
+/// What happens when a claimed interrupt fires
+#[derive(Copy, Clone)]
+#[cfg_attr(not(baremetal), allow(dead_code))]
+enum Handler {
+    /// Call a function in the owner, passing it an argument
+    Callback(MemoryAddress, Option<MemoryAddress>),
+
+    /// Send a `Scalar` message with ID `opcode` to server `sid`, as though
+    /// thread `tid` of the owner had sent it
+    Message { sid: SID, tid: TID, opcode: usize },
+}
+
+/// One process's claim on an interrupt line
+#[derive(Copy, Clone)]
+#[cfg_attr(not(baremetal), allow(dead_code))]
+struct Claim {
+    pid: PID,
+    handler: Handler,
+
+    /// Whether the process has asked not to be told about the interrupt
+    /// for now.  The line itself is only masked once every sharer is.
+    masked: bool,
+}
+
+static mut IRQ_HANDLERS: [[Option<Claim>; MAX_SHARERS]; IRQ_COUNT] =
+    [[None; MAX_SHARERS]; IRQ_COUNT];
+
+/// The sharer of each line that was called back most recently.  Only one
+/// callback can run at a time, so sharers take turns, and a level-triggered
+/// line that is still asserted fires again for the next one.
 #[cfg(baremetal)]
-pub fn handle(irqs_pending: usize) -> Result<xous_kernel::Result, xous_kernel::Error> {
+static mut LAST_CALLBACK: [usize; IRQ_COUNT] = [0; IRQ_COUNT];
+
+/// Interrupts raised with `raise()` that haven't been handled yet
+#[cfg(baremetal)]
+static mut RAISED: usize = 0;
+
+/// Send a message to every process sharing `irq_no` that asked for one and
+/// hasn't masked it.
+fn deliver_messages(irq_no: usize, claims: &[Option<Claim>; MAX_SHARERS]) {
+    use crate::services::SystemServices;
+    for claim in claims.iter().flatten().filter(|claim| !claim.masked) {
+        if let Handler::Message { sid, tid, opcode } = claim.handler {
+            if SystemServices::with_mut(|ss| {
+                crate::syscall::deliver_interrupt(ss, claim.pid, tid, sid, opcode, irq_no)
+            })
+            .is_err()
+            {
+                xous_kernel::cover!("irq: interrupt message couldn't be delivered");
+            }
+        }
+    }
+}
+
+#[cfg(baremetal)]
+pub fn handle(irqs_pending: usize) -> crate::syscall::SysCallResult {
     use crate::services::SystemServices;
     // Unsafe is required here because we're accessing a static
     // mutable value, and it could be modified from various threads.
@@ -13,9 +71,45 @@ pub fn handle(irqs_pending: usize) -> Result<xous_kernel::Result, xous_kernel::E
     // NOTE: This will become an issue when running with multiple cores,
     // so this should be protected by a mutex.
     unsafe {
-        for irq_no in 0..IRQ_HANDLERS.len() {
-            if irqs_pending & (1 << irq_no) != 0 {
-                if let Some((pid, f, arg)) = IRQ_HANDLERS[irq_no] {
+        for irq_no in 0..IRQ_COUNT {
+            if irqs_pending & (1 << irq_no) == 0 {
+                continue;
+            }
+            let claims = IRQ_HANDLERS[irq_no];
+            if !claims.iter().flatten().any(|claim| !claim.masked) {
+                // If there is no handler, mask this interrupt
+                // to prevent an IRQ storm.  This is considered
+                // an error.
+                klog!(
+                    Interrupts,
+                    Errors,
+                    "KERNEL: Masking IRQ {}, which has no handler",
+                    irq_no
+                );
+                arch::irq::disable_irq(irq_no)?;
+                continue;
+            }
+
+            // Messages are queued without leaving the kernel, so every
+            // sharer that wants one gets one.
+            deliver_messages(irq_no, &claims);
+
+            for offset in 1..=MAX_SHARERS {
+                let idx = (LAST_CALLBACK[irq_no] + offset) % MAX_SHARERS;
+                if let Some(Claim {
+                    pid,
+                    handler: Handler::Callback(f, arg),
+                    masked: false,
+                }) = claims[idx]
+                {
+                    LAST_CALLBACK[irq_no] = idx;
+                    klog!(
+                        Interrupts,
+                        Debug,
+                        "KERNEL: IRQ {} calling back PID {}",
+                        irq_no,
+                        pid
+                    );
                     return SystemServices::with_mut(|ss| {
                         // Disable all other IRQs and redirect into userspace
                         arch::irq::disable_all_irqs();
@@ -27,40 +121,186 @@ pub fn handle(irqs_pending: usize) -> Result<xous_kernel::Result, xous_kernel::E
                             arg.map(|x| x.get() as *mut usize)
                                 .unwrap_or(core::ptr::null_mut::<usize>()),
                         )
-                        .map(|_| xous_kernel::Result::ResumeProcess)
+                        .map(|_| crate::syscall::SysCallOutcome::Resume)
                     });
-                } else {
-                    // If there is no handler, mask this interrupt
-                    // to prevent an IRQ storm.  This is considered
-                    // an error.
-                    arch::irq::disable_irq(irq_no)?;
                 }
             }
         }
     }
-    Ok(xous_kernel::Result::ResumeProcess)
+    Ok(crate::syscall::SysCallOutcome::Resume)
 }
 
+/// Hand interrupt `irq` to `pid`, which will have `f` called with `arg`
+/// whenever it fires.  Claiming an interrupt that `pid` already owns
+/// replaces its handler, so a driver that restarts can claim it again.
 pub fn interrupt_claim(
     irq: usize,
     pid: PID,
     f: MemoryAddress,
     arg: Option<MemoryAddress>,
 ) -> Result<(), xous_kernel::Error> {
+    claim(irq, pid, Handler::Callback(f, arg))
+}
+
+/// Hand interrupt `irq` to `pid`, having it delivered to server `sid` as a
+/// `Scalar` message with ID `opcode` whenever it fires.  The message is sent
+/// as though it came from thread `tid` of `pid`.
+pub fn interrupt_claim_message(
+    irq: usize,
+    pid: PID,
+    tid: TID,
+    sid: SID,
+    opcode: usize,
+) -> Result<(), xous_kernel::Error> {
+    claim(irq, pid, Handler::Message { sid, tid, opcode })
+}
+
+/// Add `pid` to the processes sharing interrupt `irq`, or replace its
+/// handler if it's already one of them.  A new handler starts out unmasked.
+fn claim(irq: usize, pid: PID, handler: Handler) -> Result<(), xous_kernel::Error> {
+    klog!(Interrupts, Info, "KERNEL: PID {} claiming IRQ {}", pid, irq);
     // Unsafe is required since we're accessing a static mut array.
     // However, we disable interrupts to prevent contention on this array.
     unsafe {
         arch::irq::disable_all_irqs();
-        let result = if irq > IRQ_HANDLERS.len() {
+        let result = if irq >= IRQ_COUNT {
             Err(xous_kernel::Error::InterruptNotFound)
-        } else if IRQ_HANDLERS[irq].is_some() {
-            Err(xous_kernel::Error::InterruptInUse)
         } else {
-            IRQ_HANDLERS[irq] = Some((pid, f, arg));
-            arch::irq::enable_irq(irq);
-            Ok(())
+            let claims = &mut IRQ_HANDLERS[irq];
+            match claims
+                .iter()
+                .position(|claim| matches!(claim, Some(claim) if claim.pid == pid))
+                .or_else(|| claims.iter().position(|claim| claim.is_none()))
+            {
+                Some(idx) => {
+                    claims[idx] = Some(Claim {
+                        pid,
+                        handler,
+                        masked: false,
+                    });
+                    arch::irq::enable_irq(irq);
+                    Ok(())
+                }
+                None => Err(xous_kernel::Error::InterruptInUse),
+            }
         };
         arch::irq::enable_all_irqs();
         result
     }
 }
+
+/// Run `f` on the claim `pid` has on interrupt `irq`, or remove the claim
+/// if `f` returns `None`.  Afterwards the line is masked if nobody wants to
+/// hear about it, and unmasked otherwise.
+///
+/// # Errors
+///
+/// * **InterruptNotFound**: The interrupt doesn't exist, or `pid` hasn't
+///   claimed it
+fn update_claim<F>(irq: usize, pid: PID, f: F) -> Result<(), xous_kernel::Error>
+where
+    F: FnOnce(Claim) -> Option<Claim>,
+{
+    // Unsafe is required since we're accessing a static mut array.
+    // However, we disable interrupts to prevent contention on this array.
+    unsafe {
+        arch::irq::disable_all_irqs();
+        let result = if irq >= IRQ_COUNT {
+            Err(xous_kernel::Error::InterruptNotFound)
+        } else {
+            let claims = &mut IRQ_HANDLERS[irq];
+            match claims
+                .iter_mut()
+                .find(|claim| matches!(claim, Some(claim) if claim.pid == pid))
+            {
+                Some(slot) => {
+                    *slot = slot.and_then(f);
+                    if claims.iter().flatten().any(|claim| !claim.masked) {
+                        arch::irq::enable_irq(irq);
+                        Ok(())
+                    } else {
+                        arch::irq::disable_irq(irq)
+                    }
+                }
+                None => Err(xous_kernel::Error::InterruptNotFound),
+            }
+        };
+        arch::irq::enable_all_irqs();
+        result
+    }
+}
+
+/// Stop `pid` sharing interrupt `irq`, masking the line again if nobody
+/// else wants it.
+///
+/// # Errors
+///
+/// * **InterruptNotFound**: The interrupt doesn't exist, or isn't claimed by
+///   `pid`
+pub fn interrupt_free(irq: usize, pid: PID) -> Result<(), xous_kernel::Error> {
+    klog!(Interrupts, Info, "KERNEL: PID {} freeing IRQ {}", pid, irq);
+    update_claim(irq, pid, |_| None)
+}
+
+/// Stop (or resume) telling `pid` about interrupt `irq`, without affecting
+/// any other process sharing the line.
+///
+/// # Errors
+///
+/// * **InterruptNotFound**: The interrupt doesn't exist, or isn't claimed by
+///   `pid`
+pub fn interrupt_mask(irq: usize, pid: PID, masked: bool) -> Result<(), xous_kernel::Error> {
+    update_claim(irq, pid, |claim| Some(Claim { masked, ..claim }))
+}
+
+/// Fire interrupt `irq` from software, as though the hardware had raised
+/// it.  The interrupt is taken as soon as the caller returns to userspace.
+///
+/// # Errors
+///
+/// * **InterruptNotFound**: The interrupt doesn't exist
+#[cfg(baremetal)]
+pub fn raise(irq: usize) -> Result<(), xous_kernel::Error> {
+    if irq >= IRQ_COUNT {
+        return Err(xous_kernel::Error::InterruptNotFound);
+    }
+    // Unsafe is required since we're accessing a static mut.  However,
+    // syscalls are handled with interrupts disabled.
+    unsafe { RAISED |= 1 << irq };
+    arch::irq::raise_soft_irq();
+    Ok(())
+}
+
+/// Fire interrupt `irq` from software, as though the hardware had raised
+/// it.  There are no interrupts in a hosted environment, so its messages
+/// are delivered straight away, and callbacks are never made.
+///
+/// # Errors
+///
+/// * **InterruptNotFound**: The interrupt doesn't exist
+#[cfg(not(baremetal))]
+pub fn raise(irq: usize) -> Result<(), xous_kernel::Error> {
+    if irq >= IRQ_COUNT {
+        return Err(xous_kernel::Error::InterruptNotFound);
+    }
+    // Unsafe is required since we're accessing a static mut array.
+    let claims = unsafe { IRQ_HANDLERS[irq] };
+    deliver_messages(irq, &claims);
+    Ok(())
+}
+
+/// Take the interrupts raised with `raise()` since the last call, as a
+/// bitmask like the one the hardware reports.
+#[cfg(baremetal)]
+pub fn take_raised() -> usize {
+    // Unsafe is required since we're accessing a static mut.  However, this
+    // is run from an IRQ context with interrupts disabled.
+    unsafe { core::mem::replace(&mut RAISED, 0) }
+}
+
+/// Free every interrupt claimed by `pid`, which is going away.
+pub fn forget_process(pid: PID) {
+    for irq in 0..IRQ_COUNT {
+        interrupt_free(irq, pid).ok();
+    }
+}
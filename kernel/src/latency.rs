@@ -0,0 +1,161 @@
+//! Message latency statistics for each server.  Every message records how
+//! long it waited in the queue before the server received it, and blocking
+//! messages also record how long the server took to respond.  A fixed-size
+//! random sample of each is kept, so percentiles can be reported for a
+//! server that has handled any number of messages without instrumenting its
+//! clients.
+//!
+//! Latencies are measured in the units of `arch::timestamp()`, which can be
+//! converted to real time using the timebase.
+
+use xous_kernel::LatencyStage;
+
+/// How many samples of each latency are kept for every server
+const RESERVOIR_SIZE: usize = 32;
+
+/// A uniform random sample of every latency that has been recorded
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct Reservoir {
+    samples: [u64; RESERVOIR_SIZE],
+
+    /// The number of latencies that have been recorded, including those
+    /// that were not kept
+    seen: u64,
+}
+
+impl Reservoir {
+    /// Record `latency`.  Once the reservoir is full, this replaces a random
+    /// sample with a probability that keeps every latency equally likely to
+    /// be in the reservoir.
+    fn add(&mut self, latency: u64, random: u32) {
+        if self.seen < RESERVOIR_SIZE as u64 {
+            self.samples[self.seen as usize] = latency;
+        } else {
+            let slot = random as u64 % (self.seen + 1);
+            if slot < RESERVOIR_SIZE as u64 {
+                self.samples[slot as usize] = latency;
+            }
+        }
+        self.seen += 1;
+    }
+
+    /// Estimate the latency below which `percentile` percent of the recorded
+    /// latencies fall, or `0` if nothing has been recorded.
+    fn percentile(&self, percentile: usize) -> u64 {
+        let count = core::cmp::min(self.seen, RESERVOIR_SIZE as u64) as usize;
+        if count == 0 {
+            return 0;
+        }
+        let mut sorted = self.samples;
+        sorted[..count].sort_unstable();
+        sorted[(count - 1) * percentile / 100]
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Latency {
+    /// When the message in each queue slot was sent, or, once the server
+    /// has received it, when it was received.  This lives alongside the
+    /// server's queue, so there is one stamp for every slot.
+    #[cfg(baremetal)]
+    stamps: &'static mut [u64],
+    #[cfg(not(baremetal))]
+    stamps: Vec<u64>,
+
+    queue_wait: Reservoir,
+    service: Reservoir,
+
+    /// The state of the generator used to pick which samples to replace
+    random: u32,
+}
+
+impl Latency {
+    /// Start recording latencies for a queue with one slot for every entry
+    /// in `stamps`.
+    pub fn new(
+        #[cfg(baremetal)] stamps: &'static mut [u64],
+        #[cfg(not(baremetal))] stamps: Vec<u64>,
+    ) -> Latency {
+        let empty = Reservoir {
+            samples: [0; RESERVOIR_SIZE],
+            seen: 0,
+        };
+        Latency {
+            stamps,
+            queue_wait: empty,
+            service: empty,
+            random: 0x2545_f491,
+        }
+    }
+
+    /// Produce the next value of a xorshift generator.  This only needs to
+    /// be good enough to keep the samples unbiased.
+    fn next_random(&mut self) -> u32 {
+        let mut x = self.random;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.random = x;
+        x
+    }
+
+    /// Note that a message was placed in queue slot `idx`.
+    pub fn queued(&mut self, idx: usize) {
+        if let Some(stamp) = self.stamps.get_mut(idx) {
+            *stamp = crate::arch::timestamp();
+        }
+    }
+
+    /// Note that the server received the message in queue slot `idx`,
+    /// recording how long it waited.  Returns when the message was queued.
+    pub fn received(&mut self, idx: usize) -> u64 {
+        let now = crate::arch::timestamp();
+        if let Some(stamp) = self.stamps.get_mut(idx) {
+            let queued = core::mem::replace(stamp, now);
+            let random = self.next_random();
+            self.queue_wait.add(now.saturating_sub(queued), random);
+            queued
+        } else {
+            now
+        }
+    }
+
+    /// Note that the message in queue slot `idx` was handed straight to a
+    /// server thread that was already waiting for it.
+    pub fn delivered(&mut self, idx: usize) {
+        self.queued(idx);
+        self.received(idx);
+    }
+
+    /// Note that the server responded to the message in queue slot `idx`,
+    /// recording how long it took.
+    pub fn responded(&mut self, idx: usize) {
+        let now = crate::arch::timestamp();
+        if let Some(stamp) = self.stamps.get(idx) {
+            let took = now.saturating_sub(*stamp);
+            let random = self.next_random();
+            self.service.add(took, random);
+        }
+    }
+
+    /// Return the estimated `percentile` latency of `stage`, along with the
+    /// number of messages it was estimated from.
+    ///
+    /// # Errors
+    ///
+    /// * **InvalidSyscall**: The percentile is greater than 100
+    pub fn percentile(
+        &self,
+        stage: LatencyStage,
+        percentile: usize,
+    ) -> Result<(u64, u64), xous_kernel::Error> {
+        if percentile > 100 {
+            return Err(xous_kernel::Error::InvalidSyscall);
+        }
+        let reservoir = match stage {
+            LatencyStage::QueueWait => &self.queue_wait,
+            LatencyStage::Service => &self.service,
+        };
+        Ok((reservoir.percentile(percentile), reservoir.seen))
+    }
+}
@@ -0,0 +1,203 @@
+//! Tracking of memory lent to servers.  Every lend is recorded with a
+//! timestamp when the server receives it, and forgotten when the memory is
+//! returned.  A periodic check reports lends that have been outstanding for
+//! too long, which usually means the server forgot to return the memory and
+//! its client is stuck waiting forever.
+//!
+//! A client may also put a limit on how long servers may hold the memory it
+//! lends them.  A server that holds on for longer has the memory taken back,
+//! and the client is woken with a `Timeout` error.
+//!
+//! With the `trace-lends` feature, every lend and return is also printed as
+//! it happens.
+
+use crate::arch::process::MAX_PROCESS_COUNT;
+use xous_kernel::{PID, TID};
+
+/// The most lends that may be tracked at once.  Lends past this are not
+/// checked for leaks.
+const MAX_LENDS: usize = 32;
+
+/// How long, in seconds, memory may be lent before the lend is reported
+#[cfg(baremetal)]
+const LEAK_SECONDS: u64 = 5;
+
+// Hosted builds have no timer to check for leaks with, so they only keep
+// track of lends.
+#[cfg_attr(not(baremetal), allow(dead_code))]
+#[derive(Copy, Clone)]
+struct Lend {
+    /// The process that is holding the memory
+    server: PID,
+
+    /// The thread that lent the memory, which is blocked until it's returned
+    client: PID,
+    client_tid: TID,
+
+    /// The ID of the message the memory was lent with
+    id: usize,
+
+    /// When the server received the memory
+    lent_at: u64,
+
+    /// When the server has to return the memory by, or `0` if the client
+    /// didn't set a lend timeout
+    deadline: u64,
+
+    /// Whether this lend has already been reported as a leak
+    reported: bool,
+}
+
+struct Lends {
+    lends: [Option<Lend>; MAX_LENDS],
+
+    /// How long each process lets servers hold memory it lends them,
+    /// indexed by PID - 1, or `0` if there is no limit
+    timeouts: [u64; MAX_PROCESS_COUNT],
+}
+
+const EMPTY: Lends = Lends {
+    lends: [None; MAX_LENDS],
+    timeouts: [0; MAX_PROCESS_COUNT],
+};
+
+#[cfg(baremetal)]
+static mut LENDS: Lends = EMPTY;
+
+#[cfg(not(baremetal))]
+std::thread_local!(static LENDS: core::cell::RefCell<Lends> = const { core::cell::RefCell::new(EMPTY) });
+
+fn with_mut<F, R>(f: F) -> R
+where
+    F: FnOnce(&mut Lends) -> R,
+{
+    #[cfg(baremetal)]
+    unsafe {
+        f(&mut LENDS)
+    }
+
+    #[cfg(not(baremetal))]
+    LENDS.with(|lends| f(&mut lends.borrow_mut()))
+}
+
+/// Let servers hold memory that `pid` lends them for at most `ticks`, or for
+/// as long as they like if `ticks` is `0`.  This only applies to memory
+/// lent after it's set.  Returns the previous limit.
+pub fn set_timeout(pid: PID, ticks: u64) -> u64 {
+    with_mut(|lends| core::mem::replace(&mut lends.timeouts[pid.get() as usize - 1], ticks))
+}
+
+/// Note that `server` has received memory lent by thread `client_tid` of
+/// `client` with message `id`.
+pub fn lent(server: PID, client: PID, client_tid: TID, id: usize) {
+    with_mut(|lends| {
+        let lent_at = crate::arch::timestamp();
+        let deadline = match lends.timeouts[client.get() as usize - 1] {
+            0 => 0,
+            timeout => lent_at.saturating_add(timeout),
+        };
+        let lend = Lend {
+            server,
+            client,
+            client_tid,
+            id,
+            lent_at,
+            deadline,
+            reported: false,
+        };
+        #[cfg(feature = "trace-lends")]
+        println!(
+            "lend: PID {} thread {} lent memory to PID {} with message ID {} at {}",
+            client, client_tid, server, id, lend.lent_at
+        );
+        if let Some(slot) = lends.lends.iter_mut().find(|slot| slot.is_none()) {
+            *slot = Some(lend);
+        }
+    })
+}
+
+/// Note that the memory lent by thread `client_tid` of `client` has been
+/// returned.
+pub fn returned(client: PID, client_tid: TID) {
+    with_mut(|lends| {
+        for slot in lends.lends.iter_mut() {
+            if let Some(lend) = slot {
+                if lend.client == client && lend.client_tid == client_tid {
+                    #[cfg(feature = "trace-lends")]
+                    println!(
+                        "lend: PID {} returned memory to PID {} thread {} (message ID {}) after {}",
+                        lend.server,
+                        client,
+                        client_tid,
+                        lend.id,
+                        crate::arch::timestamp().saturating_sub(lend.lent_at)
+                    );
+                    *slot = None;
+                }
+            }
+        }
+    })
+}
+
+/// Drop every lend that a process that is exiting was holding or waiting
+/// on, along with its lend timeout.
+pub fn forget_process(pid: PID) {
+    with_mut(|lends| {
+        lends.timeouts[pid.get() as usize - 1] = 0;
+        for slot in lends.lends.iter_mut() {
+            if slot
+                .map(|lend| lend.server == pid || lend.client == pid)
+                .unwrap_or(false)
+            {
+                *slot = None;
+            }
+        }
+    })
+}
+
+/// Take the first lend whose server has held the memory past its client's
+/// deadline off the list.
+#[cfg(baremetal)]
+fn take_expired(now: u64) -> Option<Lend> {
+    with_mut(|lends| {
+        lends
+            .lends
+            .iter_mut()
+            .find(|slot| matches!(slot, Some(lend) if lend.deadline != 0 && now > lend.deadline))?
+            .take()
+    })
+}
+
+/// Take memory back from every server that has held it for longer than its
+/// client allows, and report every lend that has been outstanding for more
+/// than `LEAK_SECONDS`.  Each lend is only reported once.
+#[cfg(baremetal)]
+pub fn check() {
+    let now = crate::arch::timestamp();
+    while let Some(lend) = take_expired(now) {
+        println!(
+            "lend: PID {} held memory from PID {} thread {} (message ID {}) past its deadline, so it was taken back",
+            lend.server, lend.client, lend.client_tid, lend.id
+        );
+        crate::services::SystemServices::with_mut(|ss| {
+            ss.revoke_lend(lend.server, lend.client, lend.client_tid)
+        })
+        .ok();
+    }
+
+    let hz = match crate::arch::timebase() {
+        Some(hz) => hz as u64,
+        None => return,
+    };
+    with_mut(|lends| {
+        for lend in lends.lends.iter_mut().flatten() {
+            if !lend.reported && now.saturating_sub(lend.lent_at) > LEAK_SECONDS * hz {
+                lend.reported = true;
+                println!(
+                    "lend: PID {} has held memory from PID {} thread {} (message ID {}) for over {} seconds",
+                    lend.server, lend.client, lend.client_tid, lend.id, LEAK_SECONDS
+                );
+            }
+        }
+    })
+}
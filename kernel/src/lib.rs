@@ -0,0 +1,230 @@
+//! The Xous kernel.  `main.rs` only links this in, so that the kernel can
+//! also be built into tools that run it on the host, such as the fuzz
+//! target in `fuzz/`.
+
+#![cfg_attr(baremetal, no_std)]
+
+#[cfg(baremetal)]
+#[macro_use]
+extern crate bitflags;
+
+#[cfg(baremetal)]
+#[macro_use]
+mod debug;
+
+#[cfg(all(test, not(baremetal)))]
+mod test;
+
+#[macro_use]
+mod log;
+
+mod arch;
+
+#[macro_use]
+mod args;
+mod audit;
+mod boot;
+mod broadcast;
+#[cfg(baremetal)]
+mod canary;
+mod config;
+#[cfg(any(not(baremetal), feature = "debug-print", feature = "print-panics"))]
+mod console;
+#[cfg(baremetal)]
+mod crash;
+mod deadline;
+mod entropy;
+mod fault;
+#[cfg(all(not(baremetal), any(test, feature = "fuzz")))]
+pub mod fuzz;
+mod image;
+mod irq;
+mod latency;
+mod lends;
+mod macros;
+mod measure;
+mod mem;
+mod mmio;
+mod notify;
+mod poll;
+mod power;
+mod preempt;
+#[cfg(feature = "profile")]
+mod profile;
+mod scatter;
+mod sched;
+mod server;
+mod services;
+mod shutdown;
+mod suspend;
+mod syscall;
+#[cfg(feature = "syscall-latency")]
+mod syscall_latency;
+mod timers;
+#[cfg(feature = "trace")]
+mod trace;
+mod validate;
+mod watchdog;
+
+#[cfg(baremetal)]
+use services::SystemServices;
+use xous_kernel::*;
+
+#[cfg(baremetal)]
+use core::panic::PanicInfo;
+#[cfg(baremetal)]
+#[panic_handler]
+fn handle_panic(_arg: &PanicInfo) -> ! {
+    println!("PANIC in PID {}: {}", crate::arch::current_pid(), _arg);
+    crash::report();
+
+    // Don't leave the kernel's secrets, or whatever processes left behind in
+    // freed pages, for somebody who finds the device in this state.  If
+    // wiping them panics as well, give up on it.
+    static mut WIPING: bool = false;
+    unsafe {
+        if !WIPING {
+            WIPING = true;
+            entropy::wipe();
+            let pid = crate::arch::current_pid();
+            mem::MemoryManager::with_mut(|mm| mm.wipe_free_pages(pid)).ok();
+        }
+    }
+
+    loop {
+        arch::idle();
+    }
+}
+
+#[cfg(baremetal)]
+#[no_mangle]
+/// This function is called from baremetal startup code to initialize various kernel structures
+/// based on arguments passed by the bootloader. It is unused when running under an operating system.
+pub extern "C" fn init(arg_offset: *const u32, init_offset: *const u32, rpt_offset: *mut u32) {
+    boot::mark(BootStage::KernelEntry);
+    unsafe { args::KernelArguments::init(arg_offset) };
+    let args = args::KernelArguments::get();
+    // Everything needs memory, so the first thing we should do is initialize the memory manager.
+    crate::mem::MemoryManager::with_mut(|mm| {
+        mm.init_from_memory(rpt_offset, &args)
+            .expect("couldn't initialize memory manager")
+    });
+    boot::mark(BootStage::MemoryManager);
+    SystemServices::with_mut(|system_services| {
+        system_services.init_from_memory(init_offset, &args)
+    });
+    boot::mark(BootStage::Processes);
+
+    sched::init();
+
+    // Now that the memory manager is set up, perform any arch-specific initializations.
+    arch::init();
+    boot::mark(BootStage::ArchInit);
+
+    // The TRNG is mapped now, so the random number generator can be seeded.
+    entropy::init();
+
+    // Either map memory using a syscall, or if we're debugging the syscall
+    // handler then directly map it.
+    #[cfg(any(feature = "debug-print", feature = "print-panics"))]
+    {
+        // Map the serial port so println!() works as expected.
+        mem::MemoryManager::with_mut(|memory_manager| {
+            memory_manager
+                .map_range(
+                    0xF0002000 as *mut u8,
+                    ((debug::SUPERVISOR_UART.base as u32) & !4095) as *mut u8,
+                    4096,
+                    PID::new(1).unwrap(),
+                    MemoryFlags::R | MemoryFlags::W,
+                    MemoryType::Default,
+                )
+                .expect("unable to map serial port")
+        });
+        println!("KMAIN: Supervisor mode started...");
+        debug::SUPERVISOR_UART.enable_rx();
+        println!("Claiming IRQ 3 via syscall...");
+        xous_kernel::claim_interrupt(3, debug::irq, 0 as *mut usize).expect("Couldn't claim interrupt 3");
+        print!("}} ");
+
+        // Print the processed kernel arguments
+        let args = args::KernelArguments::get();
+        println!("Kernel arguments:");
+        for arg in args.iter() {
+            println!("    {}", arg);
+        }
+    }
+}
+
+/// Common main function for baremetal and hosted environments.
+#[no_mangle]
+pub extern "C" fn kmain() {
+    boot::mark(BootStage::Scheduler);
+    #[cfg(feature = "debug-print")]
+    boot::print();
+
+    // Start scheduling all child processes.
+    // Note that at this point, no new direct children of INIT may be created.
+    loop {
+        arch::irq::disable_all_irqs();
+
+        match sched::next() {
+            Some((pid, tid)) => {
+                arch::irq::enable_all_irqs();
+                klog!(
+                    Scheduler,
+                    Debug,
+                    "Attempting to switch to PID {} thread {}",
+                    pid,
+                    tid
+                );
+                sched::start(pid, tid);
+                xous_kernel::rsyscall(xous_kernel::SysCall::SwitchTo(pid, tid)).expect("couldn't switch to pid");
+            }
+            None => {
+                // Nothing is left to run, which is what a pending suspend
+                // was waiting for.
+                if suspend::pending() {
+                    suspend::enter();
+                    continue;
+                }
+                klog!(
+                    Scheduler,
+                    Debug,
+                    "No runnable tasks found.  Entering idle state..."
+                );
+                // Interrupts are still disabled here, so one that makes a
+                // process runnable can't slip in before the core goes to
+                // sleep.  `idle()` enables them again.
+                // Special case for testing: idle can return `false` to indicate exit
+                if !arch::idle() {
+                    return;
+                }
+            }
+        }
+    }
+}
+
+/// The main entrypoint when run in hosted mode. When running in embedded mode,
+/// this function does not exist.
+#[cfg(not(baremetal))]
+pub fn hosted_main() {
+    // All of the kernel's state is kept per thread, so a warm reboot only
+    // has to start it again on a fresh one.
+    loop {
+        let reboot = std::thread::Builder::new()
+            .name("kernel main".to_owned())
+            .spawn(|| {
+                kmain();
+                arch::reboot_mode()
+            })
+            .expect("couldn't start kernel thread")
+            .join()
+            .expect("kernel panicked");
+        match reboot {
+            Some(RebootMode::Warm) => println!("KERNEL: Rebooting"),
+            Some(_) => arch::restart(),
+            None => break,
+        }
+    }
+}
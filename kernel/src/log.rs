@@ -0,0 +1,71 @@
+//! Log messages that can be turned up or down while the kernel is running.
+//! Each subsystem has its own level, which a process holding
+//! `Capabilities::TRACE` sets with `SetLogLevel`, and which may also be
+//! changed from the debug console.  This makes it possible to watch IPC on
+//! a deployed image without building a new one with `debug-print`, which
+//! now only sets the level every subsystem starts at.
+//!
+//! Messages are logged with `klog!()`, which checks the level before doing
+//! any formatting, so that a message that isn't wanted costs very little.
+
+use xous_kernel::{LogLevel, LogSubsystem};
+
+/// The number of subsystems with a level of their own
+const SUBSYSTEMS: usize = 4;
+
+/// The level each subsystem starts at
+#[cfg(feature = "debug-print")]
+const INITIAL_LEVEL: LogLevel = LogLevel::Info;
+#[cfg(not(feature = "debug-print"))]
+const INITIAL_LEVEL: LogLevel = LogLevel::Off;
+
+#[cfg(baremetal)]
+static mut LEVELS: [LogLevel; SUBSYSTEMS] = [INITIAL_LEVEL; SUBSYSTEMS];
+
+#[cfg(not(baremetal))]
+std::thread_local!(static LEVELS: core::cell::RefCell<[LogLevel; SUBSYSTEMS]> =
+    const { core::cell::RefCell::new([INITIAL_LEVEL; SUBSYSTEMS]) });
+
+fn with_mut<F, R>(f: F) -> R
+where
+    F: FnOnce(&mut [LogLevel; SUBSYSTEMS]) -> R,
+{
+    #[cfg(baremetal)]
+    unsafe {
+        f(&mut LEVELS)
+    }
+
+    #[cfg(not(baremetal))]
+    LEVELS.with(|levels| f(&mut levels.borrow_mut()))
+}
+
+/// Print a message to the debug console if `$subsystem` is logging at
+/// `$level` or above, as in `klog!(Syscalls, Info, "...", ...)`.
+#[macro_export]
+macro_rules! klog {
+    ($subsystem:ident, $level:ident, $($args:tt)+) => {
+        if $crate::log::enabled(
+            xous_kernel::LogSubsystem::$subsystem,
+            xous_kernel::LogLevel::$level,
+        ) {
+            println!($($args)+);
+        }
+    };
+}
+
+/// Whether `subsystem` is logging messages at `level`.
+pub fn enabled(subsystem: LogSubsystem, level: LogLevel) -> bool {
+    with_mut(|levels| levels[subsystem as usize] >= level)
+}
+
+/// Have `subsystem` log messages at `level` and below, returning the level
+/// it was at.
+pub fn set_level(subsystem: LogSubsystem, level: LogLevel) -> LogLevel {
+    with_mut(|levels| core::mem::replace(&mut levels[subsystem as usize], level))
+}
+
+/// The level `subsystem` is logging at.
+#[cfg(any(not(baremetal), feature = "debug-print", feature = "print-panics"))]
+pub fn level(subsystem: LogSubsystem) -> LogLevel {
+    with_mut(|levels| levels[subsystem as usize])
+}
@@ -0,0 +1,77 @@
+//! Measured boot.  The loader hashes the kernel and each initial process as
+//! they appear in the boot image, and the kernel folds those hashes into a
+//! single measurement register, the same way a TPM extends a PCR.  Anything
+//! that wants to tie a secret to a known-good software state can then check
+//! the register, which cannot be changed once the system is running.
+
+/// The size of a single measurement, in bytes
+pub const MEASUREMENT_SIZE: usize = 32;
+
+pub type Measurement = [u8; MEASUREMENT_SIZE];
+
+const MAX_IMAGES: usize = crate::services::MAX_PROCESS_COUNT;
+
+struct Measurements {
+    /// The result of extending an all-zero register with each image, in order
+    register: Measurement,
+
+    /// The hash of each image, indexed by the PID it was loaded as, minus one
+    images: [Measurement; MAX_IMAGES],
+
+    /// The number of valid entries in `images`
+    count: usize,
+}
+
+const EMPTY: Measurements = Measurements {
+    register: [0; MEASUREMENT_SIZE],
+    images: [[0; MEASUREMENT_SIZE]; MAX_IMAGES],
+    count: 0,
+};
+
+#[cfg(baremetal)]
+static mut MEASUREMENTS: Measurements = EMPTY;
+
+#[cfg(not(baremetal))]
+std::thread_local!(static MEASUREMENTS: core::cell::RefCell<Measurements> = const { core::cell::RefCell::new(EMPTY) });
+
+/// Extend the register with each of `images`, which were measured by the
+/// loader.  The first image is the kernel, and the rest are the initial
+/// processes in PID order.  This must only be called once, during boot.
+#[cfg(baremetal)]
+pub fn init(images: &[Measurement]) {
+    use sha3::{Digest, Sha3_256};
+
+    let measurements = unsafe { &mut MEASUREMENTS };
+    for (slot, image) in measurements.images.iter_mut().zip(images) {
+        let mut hasher = Sha3_256::new();
+        hasher.input(&measurements.register);
+        hasher.input(image);
+        measurements
+            .register
+            .copy_from_slice(hasher.result().as_slice());
+        *slot = *image;
+        measurements.count += 1;
+    }
+}
+
+/// Get a measurement.  Index `0` is the measurement register itself, and any
+/// other index is the hash of the image that was loaded as that PID.
+///
+/// # Errors
+///
+/// * **InvalidSyscall**: No image was loaded as that PID
+pub fn get(index: usize) -> Result<Measurement, xous_kernel::Error> {
+    let lookup = |measurements: &Measurements| match index {
+        0 => Ok(measurements.register),
+        pid if pid <= measurements.count => Ok(measurements.images[pid - 1]),
+        _ => Err(xous_kernel::Error::InvalidSyscall),
+    };
+
+    #[cfg(baremetal)]
+    unsafe {
+        lookup(&MEASUREMENTS)
+    }
+
+    #[cfg(not(baremetal))]
+    MEASUREMENTS.with(|measurements| lookup(&measurements.borrow()))
+}
@@ -2,7 +2,7 @@ use core::fmt;
 use core::slice;
 use core::str;
 
-pub use crate::arch::mem::{MemoryMapping, PAGE_SIZE};
+pub use crate::arch::mem::{MemoryMapping, MEGAPAGE_SIZE, PAGE_SIZE};
 use crate::arch::process::Process;
 
 use xous_kernel::{MemoryFlags, MemoryRange, PID};
@@ -11,8 +11,14 @@ use xous_kernel::{MemoryFlags, MemoryRange, PID};
 enum ClaimOrRelease {
     Claim,
     Release,
+    Transfer,
+    Give(PID),
 }
 
+/// The largest number of physical pages that may be shared copy-on-write
+/// at any one time.
+const MAX_COW_FRAMES: usize = 256;
+
 #[repr(C)]
 pub struct MemoryRangeExtra {
     mem_start: u32,
@@ -50,6 +56,13 @@ pub struct MemoryManager {
     ram_name: u32,
     #[allow(dead_code)]
     last_ram_page: usize,
+    /// Physical pages that are shared copy-on-write, along with the number
+    /// of mappings that still refer to each one.
+    cow_frames: [Option<(usize, usize)>; MAX_COW_FRAMES],
+    /// The process that owns each page of simulated RAM.  Hosted processes
+    /// otherwise get their memory from the host, so this is usually empty.
+    #[cfg(not(baremetal))]
+    allocations: Vec<Option<PID>>,
 }
 
 impl Default for MemoryManager {
@@ -68,6 +81,18 @@ static mut MEMORY_ALLOCATIONS: &mut [Option<PID>] = &mut [];
 #[cfg(baremetal)]
 static mut EXTRA_REGIONS: &[MemoryRangeExtra] = &[];
 
+/// Regions of memory besides main RAM, whose pages are tracked after the
+/// ones in main RAM.
+fn extra_regions() -> &'static [MemoryRangeExtra] {
+    #[cfg(baremetal)]
+    unsafe {
+        EXTRA_REGIONS
+    }
+
+    #[cfg(not(baremetal))]
+    &[]
+}
+
 /// Initialize the memory map.
 /// This will go through memory and map anything that the kernel is
 /// using to process 1, then allocate a pagetable for this process
@@ -80,6 +105,9 @@ impl MemoryManager {
             ram_size: 0,
             ram_name: 0,
             last_ram_page: 0,
+            cow_frames: [None; MAX_COW_FRAMES],
+            #[cfg(not(baremetal))]
+            allocations: Vec::new(),
         }
     }
 
@@ -164,6 +192,39 @@ impl MemoryManager {
         Ok(())
     }
 
+    /// Manage `size` bytes of simulated RAM starting at physical address
+    /// `start`, and have the arch layer map processes' memory out of it.
+    #[cfg(all(not(baremetal), any(test, feature = "fuzz")))]
+    pub fn simulate_ram(&mut self, start: usize, size: usize) {
+        self.ram_start = start;
+        self.ram_size = size;
+        self.last_ram_page = 0;
+        self.allocations = vec![None; size / PAGE_SIZE];
+        crate::arch::mmu::simulate_ram(start, size);
+    }
+
+    /// The process that owns each page, in the order given by
+    /// `allocation_index()`.
+    fn allocations(&self) -> &[Option<PID>] {
+        #[cfg(baremetal)]
+        unsafe {
+            &*MEMORY_ALLOCATIONS
+        }
+
+        #[cfg(not(baremetal))]
+        &self.allocations
+    }
+
+    fn allocations_mut(&mut self) -> &mut [Option<PID>] {
+        #[cfg(baremetal)]
+        unsafe {
+            &mut *MEMORY_ALLOCATIONS
+        }
+
+        #[cfg(not(baremetal))]
+        &mut self.allocations
+    }
+
     #[cfg(all(baremetal, feature = "print-debug"))]
     pub fn print_ownership(&self) {
         println!("Ownership ({} bytes in all):", unsafe {
@@ -220,31 +281,29 @@ impl MemoryManager {
 
     /// Allocate a single page to the given process. DOES NOT ZERO THE PAGE!!!
     /// This function CANNOT zero the page, as it hasn't been mapped yet.
-    #[cfg(baremetal)]
     pub fn alloc_page(&mut self, pid: PID) -> Result<usize, xous_kernel::Error> {
         // Go through all RAM pages looking for a free page.
         // Optimization: start from the previous address.
         // println!("Allocating page for PID {}", pid);
-        unsafe {
-            for index in self.last_ram_page..((self.ram_size as usize) / PAGE_SIZE) {
-                // println!("    Checking {:08x}...", index * PAGE_SIZE + self.ram_start as usize);
-                if MEMORY_ALLOCATIONS[index].is_none() {
-                    MEMORY_ALLOCATIONS[index] = Some(pid);
-                    self.last_ram_page = index + 1;
-                    let page = index * PAGE_SIZE + self.ram_start;
-                    return Ok(page);
-                }
+        for index in self.last_ram_page..((self.ram_size as usize) / PAGE_SIZE) {
+            // println!("    Checking {:08x}...", index * PAGE_SIZE + self.ram_start as usize);
+            if self.allocations()[index].is_none() {
+                self.allocations_mut()[index] = Some(pid);
+                self.last_ram_page = index + 1;
+                let page = index * PAGE_SIZE + self.ram_start;
+                return Ok(page);
             }
-            for index in 0..self.last_ram_page {
-                // println!("    Checking {:08x}...", index * PAGE_SIZE + self.ram_start as usize);
-                if MEMORY_ALLOCATIONS[index].is_none() {
-                    MEMORY_ALLOCATIONS[index] = Some(pid);
-                    self.last_ram_page = index + 1;
-                    let page = index * PAGE_SIZE + self.ram_start;
-                    return Ok(page);
-                }
+        }
+        for index in 0..self.last_ram_page {
+            // println!("    Checking {:08x}...", index * PAGE_SIZE + self.ram_start as usize);
+            if self.allocations()[index].is_none() {
+                self.allocations_mut()[index] = Some(pid);
+                self.last_ram_page = index + 1;
+                let page = index * PAGE_SIZE + self.ram_start;
+                return Ok(page);
             }
         }
+        klog!(Memory, Errors, "KERNEL: No free page for PID {}", pid);
         Err(xous_kernel::Error::OutOfMemory)
     }
 
@@ -262,16 +321,19 @@ impl MemoryManager {
         }
 
         // let process = Process::current();
+        // Addresses a dynamic loader has set aside are never handed out.
+        let pid = crate::arch::process::current_pid();
         Process::with_inner_mut(|process_inner| {
             let (start, end, initial) = match kind {
                 xous_kernel::MemoryType::Stack => return Err(xous_kernel::Error::BadAddress),
                 xous_kernel::MemoryType::Heap => {
                     let new_virt =
                         process_inner.mem_heap_base + process_inner.mem_heap_size + PAGE_SIZE;
-                    if new_virt + size > process_inner.mem_heap_base + process_inner.mem_heap_max {
-                        return Err(xous_kernel::Error::OutOfMemory);
-                    }
-                    return Ok(new_virt as *mut u8);
+                    let heap_end = process_inner.mem_heap_base + process_inner.mem_heap_max;
+                    return match new_virt.checked_add(size) {
+                        Some(end) if end <= heap_end => Ok(new_virt as *mut u8),
+                        _ => Err(xous_kernel::Error::OutOfMemory),
+                    };
                 }
                 xous_kernel::MemoryType::Default => (
                     process_inner.mem_default_base,
@@ -286,7 +348,10 @@ impl MemoryManager {
             };
 
             // Look for a sequence of `size` pages that are free.
-            for potential_start in (initial..end - size).step_by(PAGE_SIZE) {
+            let last = end
+                .checked_sub(size)
+                .ok_or(xous_kernel::Error::OutOfMemory)?;
+            for potential_start in (initial..last).step_by(PAGE_SIZE) {
                 // println!("    Checking {:08x}...", potential_start);
                 let mut all_free = true;
                 for check_page in (potential_start..potential_start + size).step_by(PAGE_SIZE) {
@@ -295,7 +360,7 @@ impl MemoryManager {
                         break;
                     }
                 }
-                if all_free {
+                if all_free && !crate::image::reserved(pid, potential_start, size) {
                     match kind {
                         xous_kernel::MemoryType::Default => {
                             process_inner.mem_default_last = potential_start
@@ -318,7 +383,7 @@ impl MemoryManager {
                         break;
                     }
                 }
-                if all_free {
+                if all_free && !crate::image::reserved(pid, potential_start, size) {
                     match kind {
                         xous_kernel::MemoryType::Default => {
                             process_inner.mem_default_last = potential_start
@@ -365,20 +430,41 @@ impl MemoryManager {
         Ok(xous_kernel::MemoryRange::new(virt_ptr as usize, size)?)
     }
 
-    /// Attempt to allocate a single page from the default section.
-    /// Note that this will be backed by a real page.
+    /// Attempt to allocate `size` bytes of contiguous pages from the default
+    /// section.  Note that this will be backed by real pages.
     #[cfg(baremetal)]
-    pub fn map_zeroed_page(
+    pub fn map_zeroed_pages(
         &mut self,
         pid: PID,
+        size: usize,
         is_user: bool,
     ) -> Result<*mut usize, xous_kernel::Error> {
-        let virt = self.find_virtual_address(
+        let start = self.find_virtual_address(
             core::ptr::null_mut(),
-            PAGE_SIZE,
+            size,
             xous_kernel::MemoryType::Default,
         )? as usize;
 
+        for virt in (start..start + size).step_by(PAGE_SIZE) {
+            if let Err(e) = self.map_zeroed_page_at(pid, virt, is_user) {
+                // Give back whatever was mapped before running out.
+                for mapped in (start..virt).step_by(PAGE_SIZE) {
+                    self.unmap_page(mapped as *mut usize).ok();
+                }
+                return Err(e);
+            }
+        }
+        Ok(start as *mut usize)
+    }
+
+    /// Back the page at `virt` with a fresh, zeroed page.
+    #[cfg(baremetal)]
+    fn map_zeroed_page_at(
+        &mut self,
+        pid: PID,
+        virt: usize,
+        is_user: bool,
+    ) -> Result<(), xous_kernel::Error> {
         // Grab the next available page.  This claims it for this process.
         let phys = self.alloc_page(pid)?;
 
@@ -387,7 +473,7 @@ impl MemoryManager {
             self,
             pid,
             phys as usize,
-            virt as usize,
+            virt,
             xous_kernel::MemoryFlags::R | xous_kernel::MemoryFlags::W,
             false,
         ) {
@@ -406,13 +492,113 @@ impl MemoryManager {
             "Mapped {:08x} -> {:08x} (user? {})",
             phys as usize, virt as usize, is_user
         );
-        Ok(virt)
+        Ok(())
     }
 
     pub fn is_main_memory(&self, phys: *mut u8) -> bool {
         (phys as usize) >= self.ram_start && (phys as usize) < self.ram_start + self.ram_size
     }
 
+    /// Determine whether any page in the given physical range already belongs
+    /// to a process.  This covers main RAM as well as every additional region,
+    /// so a process that passes this check can't end up with two mappings of
+    /// the same page.
+    pub fn memory_in_use(&self, phys: usize, size: usize) -> bool {
+        (phys..(phys + size))
+            .step_by(PAGE_SIZE)
+            .filter_map(|page| self.allocation_index(page))
+            .any(|index| self.allocations()[index].is_some())
+    }
+
+    /// Zero every page of main RAM that no process owns, returning how many
+    /// were wiped.  Each one is mapped into `pid`, which must be the current
+    /// process, just long enough to clear it.
+    #[cfg(baremetal)]
+    pub fn wipe_free_pages(&mut self, pid: PID) -> Result<usize, xous_kernel::Error> {
+        // Take a page of our own and give it back, so the scratch address
+        // has a pagetable before the loop starts.  Otherwise that pagetable
+        // could be allocated from a free page halfway through, and then be
+        // wiped out from under the mapping that's clearing it.
+        let scratch = self.map_zeroed_pages(pid, PAGE_SIZE, false)? as usize;
+        self.unmap_page(scratch as *mut usize)?;
+
+        let mut wiped = 0;
+        for index in 0..self.ram_size / PAGE_SIZE {
+            if unsafe { MEMORY_ALLOCATIONS[index].is_none() } {
+                let phys = self.ram_start + index * PAGE_SIZE;
+                crate::arch::mem::wipe_page(self, pid, phys, scratch)?;
+                wiped += 1;
+            }
+        }
+        Ok(wiped)
+    }
+
+    #[cfg(not(baremetal))]
+    pub fn wipe_free_pages(&mut self, _pid: PID) -> Result<usize, xous_kernel::Error> {
+        Ok(0)
+    }
+
+    /// Determine whether any page in the given physical range is outside
+    /// main RAM, such as a peripheral's registers.
+    pub fn is_io(&self, phys: usize, size: usize) -> bool {
+        (phys..(phys + size))
+            .step_by(PAGE_SIZE)
+            .any(|page| !self.is_main_memory(page as *mut u8))
+    }
+
+    /// Find the slot in `MEMORY_ALLOCATIONS` that tracks the physical page at
+    /// `addr`, or `None` if the page isn't in any known region.
+    fn allocation_index(&self, addr: usize) -> Option<usize> {
+        if addr >= self.ram_start && addr < self.ram_start + self.ram_size {
+            return Some((addr - self.ram_start) / PAGE_SIZE);
+        }
+
+        let mut offset = self.ram_size / PAGE_SIZE;
+        for region in extra_regions() {
+            if addr >= (region.mem_start as usize)
+                && addr < (region.mem_start + region.mem_size) as usize
+            {
+                return Some(offset + (addr - (region.mem_start as usize)) / PAGE_SIZE);
+            }
+            offset += region.mem_size as usize / PAGE_SIZE;
+        }
+        None
+    }
+
+    /// Make the code that has been written to `range` of the current process
+    /// read-only and executable.  Only main RAM may be finalized, since
+    /// anything else could have another writable mapping that the kernel
+    /// doesn't know about.
+    ///
+    /// # Errors
+    ///
+    /// * **BadAlignment**: The range isn't page-aligned
+    /// * **BadAddress**: A page in the range isn't present
+    /// * **ShareViolation**: A page is lent out or shared
+    /// * **AccessDenied**: A page isn't in main RAM
+    pub fn finalize_code(&mut self, range: MemoryRange) -> Result<(), xous_kernel::Error> {
+        if range.addr.get() & (PAGE_SIZE - 1) != 0 || range.size.get() & (PAGE_SIZE - 1) != 0 {
+            return Err(xous_kernel::Error::BadAlignment);
+        }
+
+        // Check every page before changing any of them, so that a failure
+        // doesn't leave the range half finalized.
+        let pages = (range.addr.get()..(range.addr.get() + range.size.get())).step_by(PAGE_SIZE);
+        for virt in pages.clone() {
+            let phys = crate::arch::mem::virt_to_phys(virt)?;
+            if cfg!(baremetal) && !self.is_main_memory(phys as *mut u8) {
+                return Err(xous_kernel::Error::AccessDenied);
+            }
+            if crate::arch::mem::page_is_cow(virt) {
+                return Err(xous_kernel::Error::ShareViolation);
+            }
+        }
+        for virt in pages {
+            crate::arch::mem::finalize_code_page(self, virt)?;
+        }
+        Ok(())
+    }
+
     /// Attempt to map the given physical address into the virtual address space
     /// of this process.
     ///
@@ -430,6 +616,16 @@ impl MemoryManager {
     ) -> Result<xous_kernel::MemoryRange, xous_kernel::Error> {
         let phys = phys_ptr as usize;
         let virt = self.find_virtual_address(virt_ptr, size, kind)?;
+        klog!(
+            Memory,
+            Info,
+            "KERNEL: Mapping {:08x} at {:08x} in PID {} ({} bytes, flags: {:?})",
+            phys,
+            virt as usize,
+            pid,
+            size,
+            flags
+        );
 
         // If no physical address is specified, give the user the next available pages
         if phys == 0 {
@@ -448,22 +644,47 @@ impl MemoryManager {
         }
 
         // Actually perform the map.  At this stage, every physical page should be owned by us.
-        for offset in (0..size).step_by(PAGE_SIZE) {
-            if let Err(e) = crate::arch::mem::map_page_inner(
-                self,
-                pid,
-                offset + phys as usize,
-                offset + virt as usize,
-                flags,
-                false,
-            ) {
-                for unmap_offset in (0..offset).step_by(PAGE_SIZE) {
-                    crate::arch::mem::unmap_page_inner(self, unmap_offset + virt as usize).ok();
-                    self.release_page((unmap_offset + phys) as *mut usize, pid)
-                        .ok();
+        // Use megapages wherever both addresses are suitably aligned and there's enough
+        // of the range left, and fall back to regular pages otherwise.
+        let mut offset = 0;
+        while offset < size {
+            let this_phys = offset + phys as usize;
+            let this_virt = offset + virt as usize;
+            if this_phys & (MEGAPAGE_SIZE - 1) == 0
+                && this_virt & (MEGAPAGE_SIZE - 1) == 0
+                && size - offset >= MEGAPAGE_SIZE
+                && crate::arch::mem::map_megapage_inner(
+                    self, pid, this_phys, this_virt, flags, false,
+                )
+                .is_ok()
+            {
+                offset += MEGAPAGE_SIZE;
+                continue;
+            }
+            if let Err(e) =
+                crate::arch::mem::map_page_inner(self, pid, this_phys, this_virt, flags, false)
+            {
+                // Megapages are unmapped whole, since splitting one would
+                // need a new pagetable, and memory may be what ran out.
+                let mut unmap_offset = 0;
+                while unmap_offset < offset {
+                    let unmap_virt = unmap_offset + virt as usize;
+                    if unmap_virt & (MEGAPAGE_SIZE - 1) == 0
+                        && offset - unmap_offset >= MEGAPAGE_SIZE
+                        && crate::arch::mem::unmap_megapage_inner(self, unmap_virt).is_ok()
+                    {
+                        unmap_offset += MEGAPAGE_SIZE;
+                        continue;
+                    }
+                    crate::arch::mem::unmap_page_inner(self, unmap_virt).ok();
+                    unmap_offset += PAGE_SIZE;
+                }
+                for rel_phys in (phys..(phys + size)).step_by(PAGE_SIZE) {
+                    self.release_page(rel_phys as *mut usize, pid).ok();
                 }
                 return Err(e);
             }
+            offset += PAGE_SIZE;
         }
 
         Ok(MemoryRange::new(virt as usize, size)?)
@@ -478,6 +699,22 @@ impl MemoryManager {
     pub fn unmap_page(&mut self, virt: *mut usize) -> Result<usize, xous_kernel::Error> {
         let pid = crate::arch::process::current_pid();
         let phys = crate::arch::mem::virt_to_phys(virt as usize)?;
+        klog!(
+            Memory,
+            Debug,
+            "KERNEL: Unmapping {:08x} from PID {}",
+            virt as usize,
+            pid
+        );
+        if crate::arch::mem::page_is_cow(virt as usize) || self.is_shared(phys) {
+            // If other processes still share this page then it must stay
+            // allocated.  Otherwise it now belongs to this process, regardless
+            // of which process originally allocated it.
+            if self.cow_release(phys) > 0 {
+                return crate::arch::mem::unmap_page_inner(self, virt as usize);
+            }
+            self.claim_or_release(phys as *mut usize, pid, ClaimOrRelease::Transfer)?;
+        }
         self.release_page(phys as *mut usize, pid)?;
         crate::arch::mem::unmap_page_inner(self, virt as usize)
     }
@@ -492,6 +729,15 @@ impl MemoryManager {
         dest_mapping: &MemoryMapping,
         dest_addr: *mut u8,
     ) -> Result<(), xous_kernel::Error> {
+        // The destination must not end up sharing the page with anyone else.
+        let pid = crate::arch::process::current_pid();
+        if crate::arch::mem::page_is_cow(src_addr as usize) {
+            self.copy_on_write(pid, src_addr as usize)?;
+        }
+        let phys = crate::arch::mem::virt_to_phys(src_addr as usize)?;
+        if self.is_shared(phys) {
+            return Err(xous_kernel::Error::ShareViolation);
+        }
         crate::arch::mem::move_page_inner(
             self,
             &src_mapping,
@@ -499,9 +745,86 @@ impl MemoryManager {
             dest_pid,
             &dest_mapping,
             dest_addr,
+        )?;
+
+        // The page now belongs to the process it was moved into.
+        self.give_page(phys, pid, dest_pid)
+    }
+
+    /// Move every page in a range from one process into another, keeping
+    /// their permissions.  This is equivalent to calling `move_page()` on each
+    /// page, but updates the page tables in batches.
+    pub fn move_range(
+        &mut self,
+        src_mapping: &MemoryMapping,
+        src_addr: *mut u8,
+        dest_pid: PID,
+        dest_mapping: &MemoryMapping,
+        dest_addr: *mut u8,
+        len: usize,
+    ) -> Result<(), xous_kernel::Error> {
+        klog!(
+            Memory,
+            Info,
+            "KERNEL: Moving {} bytes at {:08x} to {:08x} in PID {}",
+            len,
+            src_addr as usize,
+            dest_addr as usize,
+            dest_pid
+        );
+        self.break_cow_range(src_addr as usize, len)?;
+        self.check_unshared_range(src_addr as usize, len)?;
+        crate::arch::mem::move_range_inner(
+            self,
+            &src_mapping,
+            src_addr as usize,
+            dest_pid,
+            &dest_mapping,
+            dest_addr as usize,
+            len,
         )
     }
 
+    /// Hand the physical page `phys` from `pid` over to `new_pid`.
+    pub fn give_page(&mut self, phys: usize, pid: PID, new_pid: PID) -> Result<(), xous_kernel::Error> {
+        self.claim_or_release(phys as *mut usize, pid, ClaimOrRelease::Give(new_pid))
+    }
+
+    /// Hand every page in the given range of the current address space from
+    /// `pid` over to `new_pid`.  This is used when `pid` terminates while
+    /// `new_pid` is still borrowing memory from it.  Pages that don't belong to
+    /// `pid` are skipped.
+    pub fn give_range(&mut self, virt: usize, len: usize, pid: PID, new_pid: PID) {
+        for page in (virt..virt + len).step_by(PAGE_SIZE) {
+            if let Ok(phys) = crate::arch::mem::virt_to_phys(page) {
+                self.give_page(phys, pid, new_pid).ok();
+            }
+        }
+    }
+
+    /// Return every page owned by `pid` to the free pool, returning how many
+    /// pages were released.  Pages that are still shared copy-on-write are
+    /// kept, since another process may still have them mapped.
+    pub fn release_all(&mut self, pid: PID) -> usize {
+        let mut released = 0;
+        let mut index = 0;
+        let regions = core::iter::once((self.ram_start, self.ram_size)).chain(
+            extra_regions()
+                .iter()
+                .map(|r| (r.mem_start as usize, r.mem_size as usize)),
+        );
+        for (start, size) in regions {
+            for phys in (start..start + size).step_by(PAGE_SIZE) {
+                if self.allocations()[index] == Some(pid) && !self.is_shared(phys) {
+                    self.allocations_mut()[index] = None;
+                    released += 1;
+                }
+                index += 1;
+            }
+        }
+        released
+    }
+
     /// Mark the page in the current process as being lent.  If the borrow is
     /// read-only, then additionally remove the "write" bit on it.  If the page
     /// is writable, then remove it from the current process until the borrow is
@@ -518,7 +841,14 @@ impl MemoryManager {
     ) -> Result<usize, xous_kernel::Error> {
         // If this page is to be writable, detach it from this process.
         // Otherwise, mark it as read-only to prevent a process from modifying
-        // the page while it's borrowed.
+        // the page while it's borrowed.  Copy-on-write pages get a private copy
+        // first, since lending reuses the `P` bit.
+        if crate::arch::mem::page_is_cow(src_addr as usize) {
+            self.copy_on_write(crate::arch::process::current_pid(), src_addr as usize)?;
+        }
+        if mutable {
+            self.check_unshared_range(src_addr as usize, PAGE_SIZE)?;
+        }
         crate::arch::mem::lend_page_inner(
             self,
             &src_mapping,
@@ -530,6 +860,170 @@ impl MemoryManager {
         )
     }
 
+    /// Lend every page in a range to another process.  This is equivalent to
+    /// calling `lend_page()` on each page, but updates the page tables in
+    /// batches.
+    #[allow(clippy::too_many_arguments)]
+    pub fn lend_range(
+        &mut self,
+        src_mapping: &MemoryMapping,
+        src_addr: *mut u8,
+        dest_pid: PID,
+        dest_mapping: &MemoryMapping,
+        dest_addr: *mut u8,
+        len: usize,
+        mutable: bool,
+    ) -> Result<(), xous_kernel::Error> {
+        klog!(
+            Memory,
+            Info,
+            "KERNEL: Lending {} bytes at {:08x} to {:08x} in PID {}{}",
+            len,
+            src_addr as usize,
+            dest_addr as usize,
+            dest_pid,
+            if mutable { " mutably" } else { "" }
+        );
+        self.break_cow_range(src_addr as usize, len)?;
+        if mutable {
+            self.check_unshared_range(src_addr as usize, len)?;
+        }
+        crate::arch::mem::lend_range_inner(
+            self,
+            &src_mapping,
+            src_addr as usize,
+            dest_pid,
+            &dest_mapping,
+            dest_addr as usize,
+            len,
+            mutable,
+        )
+    }
+
+    /// Give the current process a private copy of any copy-on-write pages in
+    /// the given range, since lending and moving reuse the `P` bit.
+    fn break_cow_range(&mut self, virt: usize, len: usize) -> Result<(), xous_kernel::Error> {
+        let pid = crate::arch::process::current_pid();
+        for page in (virt..virt + len).step_by(PAGE_SIZE) {
+            if crate::arch::mem::page_is_cow(page) {
+                self.copy_on_write(pid, page)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Make sure no page in the given range is shared read-only with another
+    /// process, since those can't be handed over or written to by a borrower.
+    fn check_unshared_range(&self, virt: usize, len: usize) -> Result<(), xous_kernel::Error> {
+        for page in (virt..virt + len).step_by(PAGE_SIZE) {
+            if let Ok(phys) = crate::arch::mem::virt_to_phys(page) {
+                if self.is_shared(phys) {
+                    return Err(xous_kernel::Error::ShareViolation);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Share a page from the current process with `dest_pid` without copying
+    /// it.  Read-only pages such as program text are simply mapped into both
+    /// processes.  Writable pages are marked copy-on-write in both processes,
+    /// and a private copy is made the first time either side writes to it.
+    /// Either way the page is counted as shared, so it outlives whichever
+    /// process lets go of it first.
+    ///
+    /// # Errors
+    ///
+    /// * **BadAddress**: The source page isn't allocated
+    /// * **ShareViolation**: The source page is currently lent
+    /// * **OutOfMemory**: Too many pages are already shared
+    pub fn share_page_cow(
+        &mut self,
+        src_mapping: &MemoryMapping,
+        src_addr: *mut u8,
+        dest_pid: PID,
+        dest_mapping: &MemoryMapping,
+        dest_addr: *mut u8,
+    ) -> Result<(), xous_kernel::Error> {
+        let phys = crate::arch::mem::virt_to_phys(src_addr as usize)?;
+
+        // Reserve a slot before touching the page tables so that running
+        // out of room leaves both processes untouched.
+        self.cow_retain(phys)?;
+        let result = crate::arch::mem::share_page_cow_inner(
+            self,
+            src_mapping,
+            src_addr,
+            dest_pid,
+            dest_mapping,
+            dest_addr,
+        );
+        if result.is_err() {
+            self.cow_release(phys);
+        }
+        result.map(|_| ())
+    }
+
+    /// Handle a write to the copy-on-write page at `virt` in the current
+    /// process by giving it a private copy.  If no other process shares the
+    /// page any more, the page is simply handed over and made writable.
+    pub fn copy_on_write(&mut self, pid: PID, virt: usize) -> Result<(), xous_kernel::Error> {
+        let phys = crate::arch::mem::virt_to_phys(virt)?;
+        if self.cow_release(phys) == 0 {
+            self.claim_or_release(phys as *mut usize, pid, ClaimOrRelease::Transfer)?;
+            return crate::arch::mem::finish_cow_inner(self, pid, virt, None);
+        }
+
+        let new_phys = self.alloc_page(pid)?;
+        if let Err(e) = crate::arch::mem::finish_cow_inner(self, pid, virt, Some(new_phys)) {
+            self.release_page(new_phys as *mut usize, pid).ok();
+            self.cow_retain(phys).ok();
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    /// Note that one more mapping refers to the copy-on-write page `phys`.
+    /// A page that wasn't previously shared starts out with two mappings.
+    fn cow_retain(&mut self, phys: usize) -> Result<(), xous_kernel::Error> {
+        if let Some((_, count)) = self.cow_frames.iter_mut().flatten().find(|(p, _)| *p == phys) {
+            *count += 1;
+            return Ok(());
+        }
+        let slot = self
+            .cow_frames
+            .iter_mut()
+            .find(|f| f.is_none())
+            .ok_or(xous_kernel::Error::OutOfMemory)?;
+        *slot = Some((phys, 2));
+        Ok(())
+    }
+
+    /// Whether `phys` is mapped into more than one process by
+    /// `share_page_cow()`.
+    fn is_shared(&self, phys: usize) -> bool {
+        self.cow_frames.iter().flatten().any(|(p, _)| *p == phys)
+    }
+
+    /// Drop one mapping of the copy-on-write page `phys`, returning the number
+    /// of other mappings that still share it.  Once only one mapping is left
+    /// the page is no longer tracked, and that mapping owns it outright.
+    fn cow_release(&mut self, phys: usize) -> usize {
+        for frame in self.cow_frames.iter_mut() {
+            if let Some((p, count)) = frame {
+                if *p == phys {
+                    *count -= 1;
+                    let remaining = *count;
+                    if remaining <= 1 {
+                        *frame = None;
+                    }
+                    return remaining;
+                }
+            }
+        }
+        0
+    }
+
     /// Return the range from `src_mapping` back to `dest_mapping`
     #[allow(dead_code)]
     pub fn unlend_page(
@@ -555,17 +1049,6 @@ impl MemoryManager {
 
     /// Claim the given memory for the given process, or release the memory
     /// back to the free pool.
-    #[cfg(not(baremetal))]
-    fn claim_or_release(
-        &mut self,
-        _addr: *mut usize,
-        _pid: PID,
-        _action: ClaimOrRelease,
-    ) -> Result<(), xous_kernel::Error> {
-        Ok(())
-    }
-
-    #[cfg(baremetal)]
     fn claim_or_release(
         &mut self,
         addr: *mut usize,
@@ -579,6 +1062,12 @@ impl MemoryManager {
             pid: PID,
             action: ClaimOrRelease,
         ) -> Result<(), xous_kernel::Error> {
+            // Ownership of a page that was shared copy-on-write passes to
+            // whichever process ends up with it.
+            if let ClaimOrRelease::Transfer = action {
+                *addr = Some(pid);
+                return Ok(());
+            }
             if let Some(current_pid) = *addr {
                 if current_pid != pid {
                     return Err(xous_kernel::Error::MemoryInUse);
@@ -591,36 +1080,28 @@ impl MemoryManager {
                 ClaimOrRelease::Release => {
                     *addr = None;
                 }
+                ClaimOrRelease::Give(new_pid) => {
+                    *addr = Some(new_pid);
+                }
+                ClaimOrRelease::Transfer => unreachable!(),
             }
             Ok(())
         }
         let addr = addr as usize;
 
+        // Hosted processes get their memory from the host unless RAM is being
+        // simulated, so there's nothing to keep track of.
+        if !cfg!(baremetal) && self.ram_size == 0 {
+            return Ok(());
+        }
+
         // Ensure the address lies on a page boundary
         if addr & 0xfff != 0 {
             return Err(xous_kernel::Error::BadAlignment);
         }
 
-        let mut offset = 0;
-        // Happy path: The address is in main RAM
-        if addr >= self.ram_start && addr < self.ram_start + self.ram_size {
-            offset += (addr - self.ram_start) / PAGE_SIZE;
-            return unsafe { action_inner(&mut MEMORY_ALLOCATIONS[offset], pid, action) };
-        }
-
-        offset += self.ram_size / PAGE_SIZE;
-        // Go through additional regions looking for this address, and claim it
-        // if it's not in use.
-        unsafe {
-            for region in EXTRA_REGIONS {
-                if addr >= (region.mem_start as usize)
-                    && addr < (region.mem_start + region.mem_size) as usize
-                {
-                    offset += (addr - (region.mem_start as usize)) / PAGE_SIZE;
-                    return action_inner(&mut MEMORY_ALLOCATIONS[offset], pid, action);
-                }
-                offset += region.mem_size as usize / PAGE_SIZE;
-            }
+        if let Some(index) = self.allocation_index(addr) {
+            return action_inner(&mut self.allocations_mut()[index], pid, action);
         }
         println!(
             "mem: unable to claim or release physical address {:08x}",
@@ -635,7 +1116,7 @@ impl MemoryManager {
     }
 
     /// Mark a given address as no longer being owned by the specified process ID
-    fn release_page(&mut self, addr: *mut usize, pid: PID) -> Result<(), xous_kernel::Error> {
+    pub fn release_page(&mut self, addr: *mut usize, pid: PID) -> Result<(), xous_kernel::Error> {
         self.claim_or_release(addr, pid, ClaimOrRelease::Release)
     }
 }
@@ -0,0 +1,112 @@
+//! Ownership of physical I/O regions, such as a peripheral's registers.
+//! The memory manager only tracks who has each page mapped right now, so
+//! two drivers could each map part of the same peripheral, or one could
+//! pick it up while another has it unmapped.  Instead, the first process to
+//! map an I/O region owns the whole region until it exits, and nobody else
+//! may map any part of it.  PID 1 can also hand a region to a driver ahead
+//! of time, before anyone else gets to it.
+
+use xous_kernel::PID;
+
+/// The most I/O regions that may be owned at once
+const MAX_REGIONS: usize = 32;
+
+#[derive(Copy, Clone)]
+struct Region {
+    owner: PID,
+
+    /// The physical address the region starts at
+    start: usize,
+
+    /// How many bytes the region covers
+    size: usize,
+}
+
+impl Region {
+    fn end(&self) -> usize {
+        self.start.saturating_add(self.size)
+    }
+
+    fn overlaps(&self, start: usize, size: usize) -> bool {
+        start < self.end() && self.start < start.saturating_add(size)
+    }
+
+    fn contains(&self, start: usize, size: usize) -> bool {
+        start >= self.start && start.saturating_add(size) <= self.end()
+    }
+}
+
+struct Registry {
+    regions: [Option<Region>; MAX_REGIONS],
+}
+
+const EMPTY: Registry = Registry {
+    regions: [None; MAX_REGIONS],
+};
+
+#[cfg(baremetal)]
+static mut REGISTRY: Registry = EMPTY;
+
+#[cfg(not(baremetal))]
+std::thread_local!(static REGISTRY: core::cell::RefCell<Registry> = const { core::cell::RefCell::new(EMPTY) });
+
+fn with_mut<F, R>(f: F) -> R
+where
+    F: FnOnce(&mut Registry) -> R,
+{
+    #[cfg(baremetal)]
+    unsafe {
+        f(&mut REGISTRY)
+    }
+
+    #[cfg(not(baremetal))]
+    REGISTRY.with(|registry| f(&mut registry.borrow_mut()))
+}
+
+/// Give the `size` bytes at physical address `start` to `pid`, unless some
+/// of them already belong to another process.  Claiming a region that `pid`
+/// already owns does nothing.
+///
+/// # Errors
+///
+/// * **MemoryInUse**: Part of the region belongs to another process
+/// * **OutOfMemory**: Too many regions are already owned
+pub fn claim(pid: PID, start: usize, size: usize) -> Result<(), xous_kernel::Error> {
+    with_mut(|registry| {
+        let mut owned = false;
+        for region in registry.regions.iter().flatten() {
+            if !region.overlaps(start, size) {
+                continue;
+            }
+            if region.owner != pid {
+                return Err(xous_kernel::Error::MemoryInUse);
+            }
+            owned |= region.contains(start, size);
+        }
+        if owned {
+            return Ok(());
+        }
+        let slot = registry
+            .regions
+            .iter_mut()
+            .find(|slot| slot.is_none())
+            .ok_or(xous_kernel::Error::OutOfMemory)?;
+        *slot = Some(Region {
+            owner: pid,
+            start,
+            size,
+        });
+        Ok(())
+    })
+}
+
+/// Give every region owned by `pid`, which is going away, back.
+pub fn forget_process(pid: PID) {
+    with_mut(|registry| {
+        for slot in registry.regions.iter_mut() {
+            if matches!(slot, Some(region) if region.owner == pid) {
+                *slot = None;
+            }
+        }
+    })
+}
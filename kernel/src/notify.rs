@@ -0,0 +1,201 @@
+//! Notification objects, for when a thread only needs to be told that
+//! something happened.  A notification is a counting semaphore owned by the
+//! process that created it.  Any process that knows its ID may signal it,
+//! while only threads of the owner may wait on it or poll it.  Signalling
+//! wakes up waiting threads oldest first, and signals nobody was waiting
+//! for are counted so that later waits return right away.
+//!
+//! This is much cheaper than sending a message, since there is no queue slot
+//! to allocate and no message to copy.
+
+use xous_kernel::{PID, TID};
+
+/// The most notifications that may exist at once
+const MAX_NOTIFICATIONS: usize = 32;
+
+/// The most threads that may wait on one notification at once
+pub const MAX_WAITERS: usize = 8;
+
+#[derive(Copy, Clone)]
+struct Notification {
+    /// The process that created the notification
+    owner: PID,
+
+    /// How many signals have arrived that no thread has consumed yet
+    count: usize,
+
+    /// Threads of the owner waiting for a signal, oldest first
+    waiters: [Option<TID>; MAX_WAITERS],
+}
+
+const EMPTY: [Option<Notification>; MAX_NOTIFICATIONS] = [None; MAX_NOTIFICATIONS];
+
+#[cfg(baremetal)]
+static mut NOTIFICATIONS: [Option<Notification>; MAX_NOTIFICATIONS] = EMPTY;
+
+#[cfg(not(baremetal))]
+std::thread_local!(static NOTIFICATIONS: core::cell::RefCell<[Option<Notification>; MAX_NOTIFICATIONS]> = const { core::cell::RefCell::new(EMPTY) });
+
+fn with_mut<F, R>(f: F) -> R
+where
+    F: FnOnce(&mut [Option<Notification>; MAX_NOTIFICATIONS]) -> R,
+{
+    #[cfg(baremetal)]
+    unsafe {
+        f(&mut NOTIFICATIONS)
+    }
+
+    #[cfg(not(baremetal))]
+    NOTIFICATIONS.with(|notifications| f(&mut notifications.borrow_mut()))
+}
+
+/// Look up notification `id`.  IDs are one more than the slot they live in,
+/// so that `0` is never a valid ID.
+fn get(
+    notifications: &mut [Option<Notification>; MAX_NOTIFICATIONS],
+    id: usize,
+) -> Result<&mut Notification, xous_kernel::Error> {
+    if id == 0 || id > MAX_NOTIFICATIONS {
+        return Err(xous_kernel::Error::InvalidSyscall);
+    }
+    notifications[id - 1]
+        .as_mut()
+        .ok_or(xous_kernel::Error::InvalidSyscall)
+}
+
+/// Create a new notification owned by `owner`, and return its ID.
+///
+/// # Errors
+///
+/// * **OutOfMemory**: Every notification is in use
+pub fn create(owner: PID) -> Result<usize, xous_kernel::Error> {
+    with_mut(|notifications| {
+        let (index, slot) = notifications
+            .iter_mut()
+            .enumerate()
+            .find(|(_, slot)| slot.is_none())
+            .ok_or(xous_kernel::Error::OutOfMemory)?;
+        *slot = Some(Notification {
+            owner,
+            count: 0,
+            waiters: [None; MAX_WAITERS],
+        });
+        Ok(index + 1)
+    })
+}
+
+/// Signal notification `id` `count` times.  Up to `count` waiting threads are
+/// removed from the notification and returned along with the PID they belong
+/// to, and it's up to the caller to wake them up.  Any signals left over are
+/// added to the count.
+///
+/// # Errors
+///
+/// * **InvalidSyscall**: The notification doesn't exist
+pub fn signal(
+    id: usize,
+    count: usize,
+) -> Result<(PID, [Option<TID>; MAX_WAITERS]), xous_kernel::Error> {
+    with_mut(|notifications| {
+        let notification = get(notifications, id)?;
+        let mut woken = [None; MAX_WAITERS];
+        let mut remaining = count;
+        for slot in woken.iter_mut() {
+            if remaining == 0 || notification.waiters[0].is_none() {
+                break;
+            }
+            *slot = notification.waiters[0].take();
+            notification.waiters.rotate_left(1);
+            remaining -= 1;
+        }
+        notification.count = notification.count.saturating_add(remaining);
+        Ok((notification.owner, woken))
+    })
+}
+
+/// Give a signal back to notification `id`, because the thread it woke up
+/// couldn't be made ready.
+pub fn credit(id: usize) {
+    with_mut(|notifications| {
+        if let Ok(notification) = get(notifications, id) {
+            notification.count = notification.count.saturating_add(1);
+        }
+    })
+}
+
+/// Consume a signal from notification `id` on behalf of thread `tid` of
+/// `pid`.  Returns `true` if there was one, or `false` if the thread has been
+/// added to the waiters and must be blocked.
+///
+/// # Errors
+///
+/// * **InvalidSyscall**: The notification doesn't exist
+/// * **AccessDenied**: `pid` doesn't own the notification
+/// * **OutOfMemory**: Too many threads are already waiting on it
+pub fn wait(id: usize, pid: PID, tid: TID) -> Result<bool, xous_kernel::Error> {
+    with_mut(|notifications| {
+        let notification = get(notifications, id)?;
+        if notification.owner != pid {
+            return Err(xous_kernel::Error::AccessDenied);
+        }
+        if notification.count > 0 {
+            notification.count -= 1;
+            return Ok(true);
+        }
+        let slot = notification
+            .waiters
+            .iter_mut()
+            .find(|slot| slot.is_none())
+            .ok_or(xous_kernel::Error::OutOfMemory)?;
+        *slot = Some(tid);
+        Ok(false)
+    })
+}
+
+/// Consume a signal from notification `id` if there is one, without waiting.
+/// Returns whether a signal was consumed.
+///
+/// # Errors
+///
+/// * **InvalidSyscall**: The notification doesn't exist
+/// * **AccessDenied**: `pid` doesn't own the notification
+pub fn poll(id: usize, pid: PID) -> Result<bool, xous_kernel::Error> {
+    with_mut(|notifications| {
+        let notification = get(notifications, id)?;
+        if notification.owner != pid {
+            return Err(xous_kernel::Error::AccessDenied);
+        }
+        if notification.count > 0 {
+            notification.count -= 1;
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    })
+}
+
+/// Make sure that notification `id` exists and is owned by `pid`.
+///
+/// # Errors
+///
+/// * **InvalidSyscall**: The notification doesn't exist
+/// * **AccessDenied**: `pid` doesn't own the notification
+pub fn check_owner(id: usize, pid: PID) -> Result<(), xous_kernel::Error> {
+    with_mut(|notifications| {
+        if get(notifications, id)?.owner != pid {
+            return Err(xous_kernel::Error::AccessDenied);
+        }
+        Ok(())
+    })
+}
+
+/// Destroy every notification owned by `pid`, which is going away.
+pub fn forget_process(pid: PID) {
+    with_mut(|notifications| {
+        for slot in notifications.iter_mut() {
+            if matches!(slot, Some(notification) if notification.owner == pid) {
+                *slot = None;
+            }
+        }
+    })
+}
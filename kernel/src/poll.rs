@@ -0,0 +1,183 @@
+//! Poll sets, which let a single thread wait for a message on any of several
+//! servers.  A poll set belongs to the process that created it, and holds
+//! servers owned by that process.  A thread waiting on a poll set is parked
+//! on every server in it, and once one of them hands it a message it is
+//! unparked from the rest.
+
+use xous_kernel::{PID, SID, TID};
+
+/// The most poll sets that may exist at once
+const MAX_POLL_SETS: usize = 16;
+
+/// The most servers that a poll set may hold
+pub const MAX_POLL_SERVERS: usize = 8;
+
+/// The most threads that may wait on poll sets at once
+const MAX_POLLERS: usize = 16;
+
+#[derive(Copy, Clone)]
+struct PollSet {
+    /// The process that created the poll set
+    owner: PID,
+
+    /// The servers in the set, in the order they were added
+    servers: [Option<SID>; MAX_POLL_SERVERS],
+}
+
+/// A thread that is parked on every server of a poll set
+#[derive(Copy, Clone)]
+struct Poller {
+    pid: PID,
+    tid: TID,
+    set: usize,
+}
+
+struct PollSets {
+    sets: [Option<PollSet>; MAX_POLL_SETS],
+    pollers: [Option<Poller>; MAX_POLLERS],
+}
+
+const EMPTY: PollSets = PollSets {
+    sets: [None; MAX_POLL_SETS],
+    pollers: [None; MAX_POLLERS],
+};
+
+#[cfg(baremetal)]
+static mut POLL_SETS: PollSets = EMPTY;
+
+#[cfg(not(baremetal))]
+std::thread_local!(static POLL_SETS: core::cell::RefCell<PollSets> = const { core::cell::RefCell::new(EMPTY) });
+
+fn with_mut<F, R>(f: F) -> R
+where
+    F: FnOnce(&mut PollSets) -> R,
+{
+    #[cfg(baremetal)]
+    unsafe {
+        f(&mut POLL_SETS)
+    }
+
+    #[cfg(not(baremetal))]
+    POLL_SETS.with(|poll_sets| f(&mut poll_sets.borrow_mut()))
+}
+
+impl PollSets {
+    /// Look up poll set `id` on behalf of `pid`.  IDs are one more than the
+    /// slot they live in, so that `0` is never a valid ID.
+    fn get(&mut self, id: usize, pid: PID) -> Result<&mut PollSet, xous_kernel::Error> {
+        if id == 0 || id > MAX_POLL_SETS {
+            return Err(xous_kernel::Error::InvalidSyscall);
+        }
+        let set = self.sets[id - 1]
+            .as_mut()
+            .ok_or(xous_kernel::Error::InvalidSyscall)?;
+        if set.owner != pid {
+            return Err(xous_kernel::Error::AccessDenied);
+        }
+        Ok(set)
+    }
+}
+
+/// Create a new, empty poll set owned by `owner`, and return its ID.
+///
+/// # Errors
+///
+/// * **OutOfMemory**: Every poll set is in use
+pub fn create(owner: PID) -> Result<usize, xous_kernel::Error> {
+    with_mut(|poll_sets| {
+        let (index, slot) = poll_sets
+            .sets
+            .iter_mut()
+            .enumerate()
+            .find(|(_, slot)| slot.is_none())
+            .ok_or(xous_kernel::Error::OutOfMemory)?;
+        *slot = Some(PollSet {
+            owner,
+            servers: [None; MAX_POLL_SERVERS],
+        });
+        Ok(index + 1)
+    })
+}
+
+/// Add server `sid` to poll set `id`.  It's up to the caller to make sure
+/// that `pid` owns the server.  Adding a server that is already in the set
+/// does nothing.
+///
+/// # Errors
+///
+/// * **InvalidSyscall**: The poll set doesn't exist
+/// * **AccessDenied**: `pid` doesn't own the poll set
+/// * **OutOfMemory**: The poll set is full
+pub fn add(id: usize, pid: PID, sid: SID) -> Result<(), xous_kernel::Error> {
+    with_mut(|poll_sets| {
+        let set = poll_sets.get(id, pid)?;
+        if set.servers.contains(&Some(sid)) {
+            return Ok(());
+        }
+        let slot = set
+            .servers
+            .iter_mut()
+            .find(|slot| slot.is_none())
+            .ok_or(xous_kernel::Error::OutOfMemory)?;
+        *slot = Some(sid);
+        Ok(())
+    })
+}
+
+/// The servers in poll set `id`.
+///
+/// # Errors
+///
+/// * **InvalidSyscall**: The poll set doesn't exist
+/// * **AccessDenied**: `pid` doesn't own the poll set
+pub fn servers(id: usize, pid: PID) -> Result<[Option<SID>; MAX_POLL_SERVERS], xous_kernel::Error> {
+    with_mut(|poll_sets| poll_sets.get(id, pid).map(|set| set.servers))
+}
+
+/// Note that thread `tid` of `pid` has been parked on every server of poll
+/// set `id`.
+///
+/// # Errors
+///
+/// * **OutOfMemory**: Too many threads are already waiting on poll sets
+pub fn wait(id: usize, pid: PID, tid: TID) -> Result<(), xous_kernel::Error> {
+    with_mut(|poll_sets| {
+        let slot = poll_sets
+            .pollers
+            .iter_mut()
+            .find(|slot| slot.is_none())
+            .ok_or(xous_kernel::Error::OutOfMemory)?;
+        *slot = Some(Poller { pid, tid, set: id });
+        Ok(())
+    })
+}
+
+/// A server has handed a message to thread `tid` of `pid`.  If the thread
+/// was waiting on a poll set, stop tracking it and return the servers it
+/// should now be unparked from.
+pub fn woken(pid: PID, tid: TID) -> Option<[Option<SID>; MAX_POLL_SERVERS]> {
+    with_mut(|poll_sets| {
+        let slot = poll_sets
+            .pollers
+            .iter_mut()
+            .find(|slot| matches!(slot, Some(poller) if poller.pid == pid && poller.tid == tid))?;
+        let poller = slot.take()?;
+        poll_sets.get(poller.set, pid).ok().map(|set| set.servers)
+    })
+}
+
+/// Destroy every poll set owned by `pid`, which is going away.
+pub fn forget_process(pid: PID) {
+    with_mut(|poll_sets| {
+        for slot in poll_sets.sets.iter_mut() {
+            if matches!(slot, Some(set) if set.owner == pid) {
+                *slot = None;
+            }
+        }
+        for slot in poll_sets.pollers.iter_mut() {
+            if matches!(slot, Some(poller) if poller.pid == pid) {
+                *slot = None;
+            }
+        }
+    })
+}
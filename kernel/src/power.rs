@@ -0,0 +1,137 @@
+//! Clock gating and CPU frequency scaling, shared between drivers.  Rather
+//! than each driver writing the SoC's clock registers itself, and turning
+//! off a clock that another driver still needs, each one says which clock
+//! domains it needs running and how fast it needs the CPU to be.  A domain
+//! runs while any process needs it, and the CPU runs fast enough for the
+//! process that needs it fastest.  A process's claims go away when it
+//! exits, so one that crashes can't keep a clock running forever.
+//!
+//! The arch layer does the actual gating and scaling.  Domains that nobody
+//! has said anything about are left the way the loader set them up.
+
+use crate::arch::process::MAX_PROCESS_COUNT;
+use xous_kernel::{ClockState, PID};
+
+/// The most clock domains the kernel keeps track of
+const MAX_CLOCK_DOMAINS: usize = 32;
+
+struct Claims {
+    /// The domains each process needs running, as a mask indexed by
+    /// PID - 1
+    domains: [u32; MAX_PROCESS_COUNT],
+
+    /// How fast each process needs the CPU to run in Hz, indexed by
+    /// PID - 1, or `0` if it doesn't care
+    frequencies: [usize; MAX_PROCESS_COUNT],
+}
+
+const EMPTY: Claims = Claims {
+    domains: [0; MAX_PROCESS_COUNT],
+    frequencies: [0; MAX_PROCESS_COUNT],
+};
+
+#[cfg(baremetal)]
+static mut CLAIMS: Claims = EMPTY;
+
+#[cfg(not(baremetal))]
+std::thread_local!(static CLAIMS: core::cell::RefCell<Claims> = const { core::cell::RefCell::new(EMPTY) });
+
+fn with_mut<F, R>(f: F) -> R
+where
+    F: FnOnce(&mut Claims) -> R,
+{
+    #[cfg(baremetal)]
+    unsafe {
+        f(&mut CLAIMS)
+    }
+
+    #[cfg(not(baremetal))]
+    CLAIMS.with(|claims| f(&mut claims.borrow_mut()))
+}
+
+impl Claims {
+    /// Whether any process needs `domain` running.
+    fn needed(&self, domain: usize) -> bool {
+        self.domains.iter().any(|mask| mask & (1 << domain) != 0)
+    }
+
+    /// The fastest any process needs the CPU to run, or `0` if none care.
+    fn frequency(&self) -> usize {
+        self.frequencies.iter().copied().max().unwrap_or(0)
+    }
+}
+
+/// Record whether `pid` needs clock `domain` running, and then gate the
+/// domain or let it run depending on whether anybody does.  Returns whether
+/// it runs now.
+///
+/// # Errors
+///
+/// * **UnhandledSyscall**: The arch layer has no clocks to manage
+/// * **InvalidSyscall**: The domain doesn't exist
+pub fn set_clock_state(
+    pid: PID,
+    domain: usize,
+    state: ClockState,
+) -> Result<bool, xous_kernel::Error> {
+    let domains = crate::arch::clock_domains().min(MAX_CLOCK_DOMAINS);
+    if domains == 0 {
+        return Err(xous_kernel::Error::UnhandledSyscall);
+    }
+    if domain >= domains {
+        return Err(xous_kernel::Error::InvalidSyscall);
+    }
+    let running = with_mut(|claims| {
+        let mask = &mut claims.domains[pid.get() as usize - 1];
+        match state {
+            ClockState::On => *mask |= 1 << domain,
+            ClockState::Off => *mask &= !(1 << domain),
+        }
+        claims.needed(domain)
+    });
+    crate::arch::set_clock_domain(domain, running);
+    Ok(running)
+}
+
+/// Record that `pid` needs the CPU to run at least `hz` Hz, and then set it
+/// to run as fast as whichever process needs it fastest.  Returns the
+/// frequency it runs at now.
+///
+/// # Errors
+///
+/// * **UnhandledSyscall**: The arch layer can't change the CPU's frequency
+pub fn request_cpu_frequency(pid: PID, hz: usize) -> Result<usize, xous_kernel::Error> {
+    if crate::arch::clock_domains() == 0 {
+        return Err(xous_kernel::Error::UnhandledSyscall);
+    }
+    let frequency = with_mut(|claims| {
+        claims.frequencies[pid.get() as usize - 1] = hz;
+        claims.frequency()
+    });
+    Ok(crate::arch::set_cpu_frequency(frequency))
+}
+
+/// Drop every claim held by `pid`, which is going away, gating the domains
+/// that nobody else needs and slowing the CPU down if it can be.
+pub fn forget_process(pid: PID) {
+    let (released, frequency) = with_mut(|claims| {
+        let index = pid.get() as usize - 1;
+        let mut released = core::mem::replace(&mut claims.domains[index], 0);
+        for domain in 0..MAX_CLOCK_DOMAINS {
+            if claims.needed(domain) {
+                released &= !(1 << domain);
+            }
+        }
+        let frequency = match core::mem::replace(&mut claims.frequencies[index], 0) {
+            0 => None,
+            _ => Some(claims.frequency()),
+        };
+        (released, frequency)
+    });
+    for domain in (0..MAX_CLOCK_DOMAINS).filter(|domain| released & (1 << domain) != 0) {
+        crate::arch::set_clock_domain(domain, false);
+    }
+    if let Some(frequency) = frequency {
+        crate::arch::set_cpu_frequency(frequency);
+    }
+}
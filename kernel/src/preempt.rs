@@ -0,0 +1,46 @@
+//! Preemptive time slicing.  Each priority class has a quantum, which is how
+//! many timer ticks a process in that class may run for before the
+//! scheduling policy sends it back to the scheduler so that another process
+//! can run.
+
+/// Priorities are grouped into this many classes, each of which has its own
+/// quantum.
+pub const PRIORITY_CLASSES: usize = 4;
+
+/// The number of ticks a process may run for before being preempted, until
+/// its class is given a different quantum.
+const DEFAULT_QUANTUM: usize = 10;
+
+#[cfg(baremetal)]
+static mut QUANTA: [usize; PRIORITY_CLASSES] = [DEFAULT_QUANTUM; PRIORITY_CLASSES];
+
+#[cfg(not(baremetal))]
+std::thread_local!(static QUANTA: core::cell::RefCell<[usize; PRIORITY_CLASSES]> = const { core::cell::RefCell::new([DEFAULT_QUANTUM; PRIORITY_CLASSES]) });
+
+/// Get the class that `priority` falls into.
+#[cfg(baremetal)]
+fn class(priority: u8) -> usize {
+    priority as usize * PRIORITY_CLASSES / 256
+}
+
+/// Set the quantum of priority `class` to `ticks`, returning the previous
+/// quantum.
+pub fn set_quantum(class: usize, ticks: usize) -> Result<usize, xous_kernel::Error> {
+    if class >= PRIORITY_CLASSES || ticks == 0 {
+        return Err(xous_kernel::Error::InvalidSyscall);
+    }
+
+    #[cfg(baremetal)]
+    unsafe {
+        Ok(core::mem::replace(&mut QUANTA[class], ticks))
+    }
+
+    #[cfg(not(baremetal))]
+    QUANTA.with(|quanta| Ok(core::mem::replace(&mut quanta.borrow_mut()[class], ticks)))
+}
+
+/// Get the number of ticks a process running at `priority` may run for.
+#[cfg(baremetal)]
+pub fn quantum(priority: u8) -> usize {
+    unsafe { QUANTA[class(priority)] }
+}
@@ -0,0 +1,162 @@
+//! A sampling profiler driven by the tick timer.
+//!
+//! On every tick, the architecture code records which thread was
+//! interrupted, where it was, and the words at the top of its stack.  A
+//! kernel without a tick timer samples every `SAMPLE_INTERVAL` interrupts
+//! instead.  Samples are kept in a ring buffer, which a process holding
+//! `Capabilities::TRACE` drains with `ReadProfileSample`, or which may be
+//! dumped to the debug console.  Either way they end up as `PROF` lines that
+//! the `fold-samples` tool turns into folded stacks suitable for generating
+//! a flamegraph.
+//!
+//! Hosted builds have no interrupts to sample on, so the ring stays empty.
+
+use xous_kernel::{ProfileSample, PID, PROFILE_STACK_WORDS, TID};
+
+/// Record one sample out of this many interrupts when there's no tick
+/// timer.  The timer interrupt fires far more often than anything else, so
+/// this approximates sampling on a subset of timer ticks.
+const SAMPLE_INTERVAL: usize = 4;
+
+/// How many samples to keep before the oldest ones get overwritten
+const SAMPLE_COUNT: usize = 512;
+
+/// The number of words from the top of the stack to record with each sample
+pub const STACK_WORDS: usize = PROFILE_STACK_WORDS;
+
+#[derive(Copy, Clone)]
+struct Sample {
+    pid: Option<PID>,
+    tid: TID,
+    pc: usize,
+    stack: [usize; STACK_WORDS],
+}
+
+struct Profiler {
+    samples: [Sample; SAMPLE_COUNT],
+
+    /// The number of samples that have ever been recorded
+    written: usize,
+
+    /// The number of samples that have been taken or overwritten
+    read: usize,
+
+    /// Whether samples are being recorded
+    running: bool,
+
+    /// Interrupts counted towards the next sample when there's no tick timer
+    interrupts: usize,
+}
+
+const EMPTY: Profiler = Profiler {
+    samples: [Sample {
+        pid: None,
+        tid: 0,
+        pc: 0,
+        stack: [0; STACK_WORDS],
+    }; SAMPLE_COUNT],
+    written: 0,
+    read: 0,
+    running: true,
+    interrupts: 0,
+};
+
+#[cfg(baremetal)]
+static mut PROFILER: Profiler = EMPTY;
+
+#[cfg(not(baremetal))]
+std::thread_local!(static PROFILER: core::cell::RefCell<Profiler> = core::cell::RefCell::new(EMPTY));
+
+fn with_mut<F, R>(f: F) -> R
+where
+    F: FnOnce(&mut Profiler) -> R,
+{
+    #[cfg(baremetal)]
+    unsafe {
+        f(&mut PROFILER)
+    }
+
+    #[cfg(not(baremetal))]
+    PROFILER.with(|profiler| f(&mut profiler.borrow_mut()))
+}
+
+/// Count an interrupt, and return `true` if this one should be sampled.
+/// `ticked` says whether the tick timer fired, or is `None` if there is no
+/// tick timer.  This must only be called from an interrupt context.
+#[cfg_attr(not(baremetal), allow(dead_code))]
+pub fn should_sample(ticked: Option<bool>) -> bool {
+    with_mut(|profiler| {
+        if !profiler.running {
+            return false;
+        }
+        match ticked {
+            Some(ticked) => ticked,
+            None => {
+                profiler.interrupts = profiler.interrupts.wrapping_add(1);
+                profiler.interrupts % SAMPLE_INTERVAL == 0
+            }
+        }
+    })
+}
+
+/// Add a sample to the ring buffer, overwriting the oldest one if it is full.
+/// This must only be called from an interrupt context.
+#[cfg_attr(not(baremetal), allow(dead_code))]
+pub fn record(pid: PID, tid: TID, pc: usize, stack: [usize; STACK_WORDS]) {
+    with_mut(|profiler| {
+        if profiler.written.wrapping_sub(profiler.read) == SAMPLE_COUNT {
+            profiler.read = profiler.read.wrapping_add(1);
+        }
+        profiler.samples[profiler.written % SAMPLE_COUNT] = Sample {
+            pid: Some(pid),
+            tid,
+            pc,
+            stack,
+        };
+        profiler.written = profiler.written.wrapping_add(1);
+    })
+}
+
+/// Start recording samples, throwing away any that are left over, or stop.
+pub fn set_running(running: bool) {
+    with_mut(|profiler| {
+        if running {
+            profiler.read = profiler.written;
+        }
+        profiler.running = running;
+    })
+}
+
+/// Take the oldest sample out of the ring buffer, or `None` if it's empty.
+pub fn take() -> Option<ProfileSample> {
+    with_mut(|profiler| {
+        if profiler.written == profiler.read {
+            return None;
+        }
+        let sample = profiler.samples[profiler.read % SAMPLE_COUNT];
+        profiler.read = profiler.read.wrapping_add(1);
+        Some(ProfileSample {
+            pid: sample.pid?,
+            tid: sample.tid,
+            pc: sample.pc,
+            stack: sample.stack,
+        })
+    })
+}
+
+/// Print every sample in the ring buffer, oldest first, and then empty it.
+/// Each sample is printed on its own line as:
+///
+/// `PROF <pid> <tid> <pc> <stack0> <stack1> ...`
+///
+/// with all addresses in hex.
+#[allow(dead_code)]
+pub fn dump() {
+    while let Some(sample) = take() {
+        print!("PROF {} {} {:08x}", sample.pid, sample.tid, sample.pc);
+        for word in sample.stack.iter() {
+            print!(" {:08x}", word);
+        }
+        println!();
+    }
+}
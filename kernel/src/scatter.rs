@@ -0,0 +1,130 @@
+//! Tracking of scattered lends.  A scattered lend is mapped into the server
+//! as a single buffer, but its pages have to go back to several places in
+//! the client when it's returned, so the pieces are remembered here until
+//! then.  A client thread blocks until its lend comes back, so each thread
+//! has at most one scattered lend outstanding.
+
+use xous_kernel::{Error, ScatterList, PID, TID};
+
+/// The most scattered lends that may be outstanding at once
+const MAX_SCATTERED_LENDS: usize = 16;
+
+#[derive(Copy, Clone)]
+struct ScatteredLend {
+    /// The thread that lent the memory
+    client: PID,
+    client_tid: TID,
+
+    /// Where each piece of the lend lives in the client
+    scatter: ScatterList,
+}
+
+const EMPTY: [Option<ScatteredLend>; MAX_SCATTERED_LENDS] = [None; MAX_SCATTERED_LENDS];
+
+#[cfg(baremetal)]
+static mut SCATTERED_LENDS: [Option<ScatteredLend>; MAX_SCATTERED_LENDS] = EMPTY;
+
+#[cfg(not(baremetal))]
+std::thread_local!(static SCATTERED_LENDS: core::cell::RefCell<[Option<ScatteredLend>; MAX_SCATTERED_LENDS]> = const { core::cell::RefCell::new(EMPTY) });
+
+fn with_mut<F, R>(f: F) -> R
+where
+    F: FnOnce(&mut [Option<ScatteredLend>; MAX_SCATTERED_LENDS]) -> R,
+{
+    #[cfg(baremetal)]
+    unsafe {
+        f(&mut SCATTERED_LENDS)
+    }
+
+    #[cfg(not(baremetal))]
+    SCATTERED_LENDS.with(|lends| f(&mut lends.borrow_mut()))
+}
+
+/// Read the `ScatterList` at `list` out of the current process, and make
+/// sure it's well-formed and that none of its pieces overlap.
+///
+/// # Errors
+///
+/// * **BadAddress**: The list couldn't be read, or a piece is empty or runs
+///   past the end of the address space
+/// * **BadAlignment**: A piece wasn't page-aligned
+/// * **OutOfMemory**: The list has too many pieces
+/// * **ShareViolation**: Two pieces overlap
+pub fn read(list: *const ScatterList) -> Result<ScatterList, Error> {
+    let mut scatter = ScatterList::default();
+    crate::arch::mem::read_user(list as usize, unsafe {
+        core::slice::from_raw_parts_mut(
+            &mut scatter as *mut ScatterList as *mut u8,
+            core::mem::size_of::<ScatterList>(),
+        )
+    })?;
+    scatter.check()?;
+    for range in scatter.iter() {
+        (range.as_ptr() as usize)
+            .checked_add(range.len())
+            .ok_or(Error::BadAddress)?;
+    }
+    for (index, range) in scatter.iter().enumerate() {
+        let start = range.as_ptr() as usize;
+        if scatter.iter().skip(index + 1).any(|other| {
+            let other_start = other.as_ptr() as usize;
+            start < other_start + other.len() && other_start < start + range.len()
+        }) {
+            return Err(Error::ShareViolation);
+        }
+    }
+    Ok(scatter)
+}
+
+/// Note that thread `client_tid` of `client` is lending the pieces in
+/// `scatter`.
+///
+/// # Errors
+///
+/// * **OutOfMemory**: Too many scattered lends are already outstanding
+pub fn lent(client: PID, client_tid: TID, scatter: ScatterList) -> Result<(), Error> {
+    with_mut(|lends| {
+        let slot = lends
+            .iter_mut()
+            .find(|slot| slot.is_none())
+            .ok_or(Error::OutOfMemory)?;
+        *slot = Some(ScatteredLend {
+            client,
+            client_tid,
+            scatter,
+        });
+        Ok(())
+    })
+}
+
+/// Whether thread `client_tid` of `client` has a scattered lend outstanding
+#[cfg_attr(not(baremetal), allow(dead_code))]
+pub fn is_scattered(client: PID, client_tid: TID) -> bool {
+    with_mut(|lends| {
+        lends.iter().any(|slot| {
+            matches!(slot, Some(lend) if lend.client == client && lend.client_tid == client_tid)
+        })
+    })
+}
+
+/// The memory lent by thread `client_tid` of `client` is being returned.  If
+/// it was a scattered lend, stop tracking it and return its pieces.
+pub fn returned(client: PID, client_tid: TID) -> Option<ScatterList> {
+    with_mut(|lends| {
+        let slot = lends.iter_mut().find(|slot| {
+            matches!(slot, Some(lend) if lend.client == client && lend.client_tid == client_tid)
+        })?;
+        slot.take().map(|lend| lend.scatter)
+    })
+}
+
+/// Drop every scattered lend made by a process that is exiting.
+pub fn forget_process(pid: PID) {
+    with_mut(|lends| {
+        for slot in lends.iter_mut() {
+            if matches!(slot, Some(lend) if lend.client == pid) {
+                *slot = None;
+            }
+        }
+    })
+}
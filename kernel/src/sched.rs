@@ -0,0 +1,259 @@
+//! Scheduling policies.  The kernel's main loop asks the policy which thread
+//! to run next, and the tick timer asks it whether the running thread should
+//! be preempted, so different targets can make different trade-offs without
+//! changing either of them.
+//!
+//! The policy is chosen when the kernel is built, using the
+//! `sched-round-robin` or `sched-priority` features, and defaults to
+//! earliest deadline first.  A `Schd` kernel argument overrides it at boot.
+//! Each hart keeps its own policy state.
+
+use crate::services::SystemServices;
+use xous_kernel::{PID, TID};
+
+/// A way of deciding which thread runs next, and for how long.
+pub trait Policy {
+    /// Choose the next thread to run, or `None` if nothing is runnable.  A
+    /// TID of `0` lets the process pick one of its ready threads.
+    fn next(&mut self) -> Option<(PID, TID)>;
+
+    /// Note that thread `tid` of `pid` is about to be switched to.
+    fn start(&mut self, pid: PID, tid: TID);
+
+    /// Count a timer tick while thread `tid` of `pid` is running, returning
+    /// `true` if it should be preempted.  This must only be called from an
+    /// interrupt context.
+    #[cfg(baremetal)]
+    fn tick(&mut self, pid: PID, tid: TID) -> bool;
+}
+
+/// Pick the next child of PID 1 after `last` that is runnable, taking turns
+/// in PID order.  If `by_priority` is set, only the most important runnable
+/// processes are considered.
+fn next_process(last: Option<PID>, by_priority: bool) -> Option<PID> {
+    // PIDs are 1-indexed but arrays are 0-indexed.  By not subtracting
+    // 1 from the PID when we use it as an array index, we automatically
+    // pick the next process in the list.
+    let current_pid = last.map(|pid| pid.get() as usize).unwrap_or(1);
+
+    SystemServices::with(|system_services| {
+        let processes = &system_services.processes;
+        let candidate = |idx: &usize| processes[*idx].ppid.get() == 1 && processes[*idx].runnable();
+
+        let priority = if by_priority {
+            (0..processes.len())
+                .filter(candidate)
+                .map(|idx| processes[idx].priority())
+                .max()?
+        } else {
+            0
+        };
+
+        (current_pid..processes.len())
+            .chain(0..current_pid)
+            .filter(candidate)
+            .find(|idx| !by_priority || processes[*idx].priority() == priority)
+            .and_then(|idx| xous_kernel::pid_from_usize(idx + 1).ok())
+    })
+}
+
+/// Whether thread `tid` of `pid` has run for its whole quantum, counting
+/// this tick.
+#[cfg(baremetal)]
+fn quantum_expired(ticks: &mut usize, pid: PID, by_priority: bool) -> bool {
+    let priority = if by_priority {
+        SystemServices::with(|ss| ss.get_process(pid).map(|p| p.priority()).unwrap_or(0))
+    } else {
+        0
+    };
+    *ticks += 1;
+    *ticks >= crate::preempt::quantum(priority)
+}
+
+/// Every process takes turns, regardless of priority, using the quantum of
+/// the lowest priority class.
+#[derive(Copy, Clone)]
+pub struct RoundRobin {
+    last: Option<PID>,
+    ticks: usize,
+}
+
+impl Policy for RoundRobin {
+    fn next(&mut self) -> Option<(PID, TID)> {
+        self.last = next_process(self.last, false);
+        self.last.map(|pid| (pid, 0))
+    }
+
+    fn start(&mut self, _pid: PID, _tid: TID) {
+        self.ticks = 0;
+    }
+
+    #[cfg(baremetal)]
+    fn tick(&mut self, pid: PID, _tid: TID) -> bool {
+        quantum_expired(&mut self.ticks, pid, false)
+    }
+}
+
+/// Only the most important runnable processes get to run.  Processes that
+/// share that priority take turns, each using the quantum of its class.
+#[derive(Copy, Clone)]
+pub struct Priority {
+    last: Option<PID>,
+    ticks: usize,
+}
+
+impl Policy for Priority {
+    fn next(&mut self) -> Option<(PID, TID)> {
+        self.last = next_process(self.last, true);
+        self.last.map(|pid| (pid, 0))
+    }
+
+    fn start(&mut self, _pid: PID, _tid: TID) {
+        self.ticks = 0;
+    }
+
+    #[cfg(baremetal)]
+    fn tick(&mut self, pid: PID, _tid: TID) -> bool {
+        quantum_expired(&mut self.ticks, pid, true)
+    }
+}
+
+/// Threads in the deadline class are run earliest deadline first, ahead of
+/// everything else.  Other processes are scheduled by priority, and the
+/// deadline class doesn't disturb their order.
+#[derive(Copy, Clone)]
+pub struct EarliestDeadline {
+    normal: Priority,
+}
+
+impl Policy for EarliestDeadline {
+    fn next(&mut self) -> Option<(PID, TID)> {
+        #[cfg(baremetal)]
+        let deadline_thread = crate::deadline::next();
+        #[cfg(not(baremetal))]
+        let deadline_thread = None;
+        deadline_thread.or_else(|| self.normal.next())
+    }
+
+    fn start(&mut self, pid: PID, tid: TID) {
+        self.normal.start(pid, tid);
+    }
+
+    #[cfg(baremetal)]
+    fn tick(&mut self, pid: PID, tid: TID) -> bool {
+        crate::deadline::tick(pid, tid).unwrap_or_else(|| self.normal.tick(pid, tid))
+    }
+}
+
+/// The state of whichever policy a hart is using
+#[derive(Copy, Clone)]
+enum Scheduler {
+    RoundRobin(RoundRobin),
+    Priority(Priority),
+    EarliestDeadline(EarliestDeadline),
+}
+
+impl Scheduler {
+    /// Get a fresh scheduler using the policy numbered `policy` in a `Schd`
+    /// argument, or `None` if there is no such policy.
+    #[cfg_attr(not(baremetal), allow(dead_code))]
+    fn from_usize(policy: usize) -> Option<Scheduler> {
+        let priority = Priority {
+            last: None,
+            ticks: 0,
+        };
+        match policy {
+            0 => Some(Scheduler::RoundRobin(RoundRobin {
+                last: None,
+                ticks: 0,
+            })),
+            1 => Some(Scheduler::Priority(priority)),
+            2 => Some(Scheduler::EarliestDeadline(EarliestDeadline {
+                normal: priority,
+            })),
+            _ => None,
+        }
+    }
+
+    fn policy(&mut self) -> &mut dyn Policy {
+        match self {
+            Scheduler::RoundRobin(policy) => policy,
+            Scheduler::Priority(policy) => policy,
+            Scheduler::EarliestDeadline(policy) => policy,
+        }
+    }
+}
+
+#[cfg(feature = "sched-round-robin")]
+const DEFAULT: Scheduler = Scheduler::RoundRobin(RoundRobin {
+    last: None,
+    ticks: 0,
+});
+
+#[cfg(all(feature = "sched-priority", not(feature = "sched-round-robin")))]
+const DEFAULT: Scheduler = Scheduler::Priority(Priority {
+    last: None,
+    ticks: 0,
+});
+
+#[cfg(not(any(feature = "sched-round-robin", feature = "sched-priority")))]
+const DEFAULT: Scheduler = Scheduler::EarliestDeadline(EarliestDeadline {
+    normal: Priority {
+        last: None,
+        ticks: 0,
+    },
+});
+
+#[cfg(baremetal)]
+static mut SCHEDULERS: [Scheduler; crate::arch::MAX_HARTS] = [DEFAULT; crate::arch::MAX_HARTS];
+
+#[cfg(not(baremetal))]
+std::thread_local!(static SCHEDULERS: core::cell::RefCell<[Scheduler; crate::arch::MAX_HARTS]> = const { core::cell::RefCell::new([DEFAULT; crate::arch::MAX_HARTS]) });
+
+fn with_mut<F, R>(f: F) -> R
+where
+    F: FnOnce(&mut dyn Policy) -> R,
+{
+    let hart = crate::arch::current_hart();
+
+    #[cfg(baremetal)]
+    unsafe {
+        f(SCHEDULERS[hart].policy())
+    }
+
+    #[cfg(not(baremetal))]
+    SCHEDULERS.with(|schedulers| f(schedulers.borrow_mut()[hart].policy()))
+}
+
+/// Switch every hart to the policy given in the `Schd` kernel argument, if
+/// there is one.  This must only be called once, during boot.
+#[cfg(baremetal)]
+pub fn init() {
+    let policy = match crate::args::KernelArguments::get()
+        .iter()
+        .find(|arg| arg.name == make_type!("Schd") && !arg.data.is_empty())
+    {
+        Some(arg) => arg.data[0] as usize,
+        None => return,
+    };
+    match Scheduler::from_usize(policy) {
+        Some(scheduler) => unsafe { SCHEDULERS = [scheduler; crate::arch::MAX_HARTS] },
+        None => println!("sched: unknown policy {}, using the default", policy),
+    }
+}
+
+/// Choose the next thread for this hart to run.  See `Policy::next()`.
+pub fn next() -> Option<(PID, TID)> {
+    with_mut(|policy| policy.next())
+}
+
+/// Note that this hart is about to run thread `tid` of `pid`.
+pub fn start(pid: PID, tid: TID) {
+    with_mut(|policy| policy.start(pid, tid))
+}
+
+/// Count a timer tick on this hart.  See `Policy::tick()`.
+#[cfg(baremetal)]
+pub fn tick(pid: PID, tid: TID) -> bool {
+    with_mut(|policy| policy.tick(pid, tid))
+}
@@ -1,26 +1,35 @@
 pub use crate::arch::process::Thread;
+use crate::latency::Latency;
 use core::mem;
-use xous_kernel::{MemoryAddress, MemoryRange, MemorySize, Message, PID, SID, TID};
+use xous_kernel::{LatencyStage, MemoryAddress, MemoryRange, MemorySize, Message, PID, SID, TID};
 
+/// Identifies a message to the server that received it.  This is packed into
+/// a `MessageSender` as `pid << 24 | cid << 16 | idx`, which is the layout
+/// that `MessageEnvelope::sender_pid()` unpacks.
 pub struct SenderID {
     /// The connection ID inside the server
     pub cid: usize,
     /// The index into the queue array
     pub idx: usize,
+    /// The process that sent the message
+    pub pid: Option<PID>,
 }
 
 impl From<usize> for SenderID {
     fn from(item: usize) -> SenderID {
         SenderID {
-            cid: item >> 16,
+            cid: (item >> 16) & 0xff,
             idx: item & 0xffff,
+            pid: PID::new((item >> 24) as u8),
         }
     }
 }
 
 impl Into<usize> for SenderID {
     fn into(self) -> usize {
-        (self.cid << 16) | (self.idx & 0xffff)
+        (self.pid.map(|p| p.get() as usize).unwrap_or(0) << 24)
+            | ((self.cid & 0xff) << 16)
+            | (self.idx & 0xffff)
     }
 }
 
@@ -39,10 +48,34 @@ pub enum WaitingMessage {
 
     /// This memory should be returned to the system.
     ForgetMemory(MemoryRange),
+
+    /// The process waiting for this scalar terminated, so the response
+    /// should be dropped.
+    ForgetScalar,
 }
 
+/// The number of messages a server can queue if it doesn't ask for more or
+/// fewer, which is one page of messages
+pub const DEFAULT_QUEUE_LENGTH: usize =
+    crate::arch::mem::PAGE_SIZE / mem::size_of::<QueuedMessage>();
+
+/// The most messages a server may ask to be able to queue
+pub const MAX_QUEUE_LENGTH: usize = 1024;
+
+/// The most connections a server may attach data to at once
+pub const MAX_CONNECTION_DATA: usize = 16;
+
+/// The most clients that may watch a server for it going away
+pub const MAX_DISCONNECT_WATCHERS: usize = 16;
+
+/// The most processes a private server may allow to connect to it
+pub const MAX_ALLOWED_CLIENTS: usize = 16;
+
+/// Memory lent to a server, as `(server address, client address, length)`
+pub type LentMemory = (usize, usize, usize);
+
 /// Internal representation of a queued message for a server. This should be
-/// exactly 8 words / 32 bytes, yielding 128 queued messages per server
+/// exactly 8 words / 32 bytes, yielding 128 queued messages per page
 #[repr(usize)]
 #[derive(PartialEq, Debug)]
 enum QueuedMessage {
@@ -166,6 +199,14 @@ enum QueuedMessage {
         u16,   /* client CTX */
         usize, /* server return address */
     ),
+
+    /// The process waiting for the response terminated after we
+    /// received the message, so there is nobody to respond to.
+    WaitingReturnScalarTerminated(
+        u16,   /* client PID */
+        u16,   /* client CTX */
+        usize, /* server return address */
+    ),
 }
 
 /// A pointer to resolve a server ID to a particular process
@@ -192,11 +233,52 @@ pub struct Server {
     /// this message. If there are no available contexts, then messages will
     /// need to be queued.
     ready_threads: usize,
+
+    /// How long messages to this server take to be received and handled
+    latency: Latency,
+
+    /// Data attached to the connection from each client process, which is
+    /// returned with every message it sends.  A process only ever has one
+    /// connection to a given server, so its PID identifies the connection.
+    connection_data: [Option<(PID, usize)>; MAX_CONNECTION_DATA],
+
+    /// The notification each client process wants signalled when this
+    /// server goes away.
+    disconnect_watchers: [Option<(PID, usize)>; MAX_DISCONNECT_WATCHERS],
+
+    /// Whether only the owner and the processes in `allowed_clients` may
+    /// connect to this server
+    private: bool,
+
+    /// The processes that may connect to this server if it is private
+    allowed_clients: [Option<PID>; MAX_ALLOWED_CLIENTS],
 }
 
 impl Server {
+    /// The number of bytes of backing memory a server needs in order to
+    /// queue `queue_length` messages, rounded up to a whole number of pages.
+    /// Each slot has a message followed, after the last message, by the
+    /// time it was stamped with.
+    ///
+    /// # Errors
+    ///
+    /// * **InvalidSyscall**: The queue would have no slots
+    /// * **OutOfMemory**: The queue would be longer than `MAX_QUEUE_LENGTH`
+    pub fn backing_size(queue_length: usize) -> Result<usize, xous_kernel::Error> {
+        if queue_length == 0 {
+            return Err(xous_kernel::Error::InvalidSyscall);
+        }
+        if queue_length > MAX_QUEUE_LENGTH {
+            return Err(xous_kernel::Error::OutOfMemory);
+        }
+        let page_size = crate::arch::mem::PAGE_SIZE;
+        let bytes = queue_length * (mem::size_of::<QueuedMessage>() + mem::size_of::<u64>());
+        Ok((bytes + page_size - 1) & !(page_size - 1))
+    }
+
     /// Initialize a server in the given option array. This function is
     /// designed to be called with `new` pointing to an entry in a vec.
+    /// `backing` must be at least `backing_size(queue_length)` bytes.
     ///
     /// # Errors
     ///
@@ -206,28 +288,33 @@ impl Server {
         pid: PID,
         sid: SID,
         _backing: MemoryRange,
+        queue_length: usize,
     ) -> Result<(), xous_kernel::Error> {
         if new != &None {
             return Err(xous_kernel::Error::MemoryInUse);
         }
 
         #[cfg(baremetal)]
-        let queue = unsafe {
-            core::slice::from_raw_parts_mut(
-                _backing.as_mut_ptr() as *mut QueuedMessage,
-                _backing.len() / mem::size_of::<QueuedMessage>(),
+        let (queue, stamps) = unsafe {
+            let queue_bytes = queue_length * mem::size_of::<QueuedMessage>();
+            (
+                core::slice::from_raw_parts_mut(
+                    _backing.as_mut_ptr() as *mut QueuedMessage,
+                    queue_length,
+                ),
+                core::slice::from_raw_parts_mut(
+                    _backing.as_mut_ptr().add(queue_bytes) as *mut u64,
+                    queue_length,
+                ),
             )
         };
 
         #[cfg(not(baremetal))]
-        let queue = {
+        let (queue, stamps) = {
             let mut queue = vec![];
             // TODO: Replace this with a direct operation on a passed-in page
-            queue.resize_with(
-                crate::arch::mem::PAGE_SIZE / mem::size_of::<QueuedMessage>(),
-                || QueuedMessage::Empty,
-            );
-            queue
+            queue.resize_with(queue_length, || QueuedMessage::Empty);
+            (queue, vec![0; queue_length])
         };
 
         *new = Some(Server {
@@ -237,6 +324,11 @@ impl Server {
             queue_tail: 0,
             queue,
             ready_threads: 0,
+            latency: Latency::new(stamps),
+            connection_data: [None; MAX_CONNECTION_DATA],
+            disconnect_watchers: [None; MAX_DISCONNECT_WATCHERS],
+            private: false,
+            allowed_clients: [None; MAX_ALLOWED_CLIENTS],
         });
         Ok(())
     }
@@ -251,20 +343,27 @@ impl Server {
         Ok(())
     }
 
-    // pub fn print_queue(&self) {
-    //     println!("    Q Queue Head: {}", self.queue_head);
-    //     println!("    Q Queue Tail: {}", self.queue_tail);
-    //     for (_idx, _entry) in self.queue.iter().enumerate() {
-    //         if _entry != &QueuedMessage::Empty {
-    //             println!("    Q  entry[{}]: {:?}", _idx, _entry);
-    //         }
-    //     }
-    // }
+    /// Print the head and tail of the queue, and every slot in it that's in
+    /// use, to the console.
+    #[cfg(any(not(baremetal), feature = "debug-print", feature = "print-panics"))]
+    pub fn print_queue(&self) {
+        println!("    Q Queue Head: {}", self.queue_head);
+        println!("    Q Queue Tail: {}", self.queue_tail);
+        for (idx, entry) in self.queue.iter().enumerate() {
+            if entry != &QueuedMessage::Empty {
+                println!("    Q  entry[{}]: {:?}", idx, entry);
+            }
+        }
+    }
 
     /// When a process terminates, there may be memory that is lent to us.
     /// Mark all of that memory to be discarded when it is returned, rather than
     /// giving it back to the previous process space.
-    pub fn discard_messages_for_pid(&mut self, pid: PID) {
+    ///
+    /// `lent` is called with the address and length of every buffer this
+    /// server is holding on behalf of `pid`.  These will be forgotten rather
+    /// than returned, so the server becomes responsible for them.
+    pub fn discard_messages_for_pid<F: FnMut(usize, usize)>(&mut self, pid: PID, mut lent: F) {
         for entry in self.queue.iter_mut() {
             match *entry {
                 QueuedMessage::MemoryMessageROLend(
@@ -278,6 +377,7 @@ impl Server {
                     arg6,
                 ) => {
                     if msg_pid == pid.get() as _ {
+                        lent(arg3, arg4);
                         *entry = QueuedMessage::MemoryMessageROLendTerminated(
                             msg_pid, ctx, arg1, arg2, arg3, arg4, arg5, arg6,
                         );
@@ -294,6 +394,7 @@ impl Server {
                     arg6,
                 ) => {
                     if msg_pid == pid.get() as _ {
+                        lent(arg3, arg4);
                         *entry = QueuedMessage::MemoryMessageRWLendTerminated(
                             msg_pid, ctx, arg1, arg2, arg3, arg4, arg5, arg6,
                         );
@@ -315,6 +416,26 @@ impl Server {
                         );
                     }
                 }
+                // Memory that the server has already received can no longer
+                // be returned, so it must be forgotten instead.
+                QueuedMessage::WaitingReturnMemory(msg_pid, ctx, server_addr, client_addr, len) => {
+                    if msg_pid == pid.get() as _ {
+                        lent(server_addr, len);
+                        *entry =
+                            QueuedMessage::WaitingForget(msg_pid, ctx, server_addr, client_addr, len);
+                    }
+                }
+                // Responses to a client that is gone must not wake up
+                // whatever thread ends up with its PID and TID.
+                QueuedMessage::WaitingReturnScalar(msg_pid, ctx, return_address) => {
+                    if msg_pid == pid.get() as _ {
+                        *entry = QueuedMessage::WaitingReturnScalarTerminated(
+                            msg_pid,
+                            ctx,
+                            return_address,
+                        );
+                    }
+                }
                 // For "Scalar" and "Move" messages, this memory has already
                 // been moved into this process, so memory will be reclaimed
                 // when the process terminates.
@@ -323,16 +444,41 @@ impl Server {
         }
     }
 
+    /// Call `f` with the PID of every client that is blocked waiting for this
+    /// server, either because its message is still queued or because the
+    /// server has received it but not yet responded.
+    pub fn for_each_blocked_client<F: FnMut(PID)>(&self, mut f: F) {
+        for entry in self.queue.iter() {
+            let client_pid = match *entry {
+                QueuedMessage::BlockingScalarMessage(pid, _, _, _, _, _, _, _)
+                | QueuedMessage::MemoryMessageROLend(pid, _, _, _, _, _, _, _)
+                | QueuedMessage::MemoryMessageRWLend(pid, _, _, _, _, _, _, _)
+                | QueuedMessage::WaitingReturnMemory(pid, _, _, _, _)
+                | QueuedMessage::WaitingReturnScalar(pid, _, _) => pid,
+                _ => continue,
+            };
+            if let Some(pid) = PID::new(client_pid as _) {
+                f(pid);
+            }
+        }
+    }
+
     /// Convert a `QueuedMesage::WaitingReturnMemory` into `QueuedMessage::Empty`
     /// and return the pair.  Advance the tail.  Note that the `idx` could be
-    /// somewhere other than the tail, but as long as it points to a valid
-    /// message that's waiting a response, that's acceptable.
+    /// somewhere other than the tail, since servers may defer responses and
+    /// answer them in any order, but as long as it points to a valid message
+    /// that's waiting a response, that's acceptable.
+    ///
+    /// `client` is the process the server believes sent the message.  If the
+    /// slot is waiting on some other process, then the sender is stale and
+    /// the message is treated as though it doesn't exist.
     pub fn take_waiting_message(
         &mut self,
         idx: usize,
+        client: Option<PID>,
         buf: Option<&MemoryRange>,
     ) -> Result<WaitingMessage, xous_kernel::Error> {
-        if idx > self.queue.len() {
+        if idx >= self.queue.len() {
             return Err(xous_kernel::Error::BadAddress);
         }
         let (pid, ctx, server_addr, client_addr, len, forget, is_memory) = match self.queue[idx] {
@@ -343,10 +489,16 @@ impl Server {
                 (pid, ctx, server_addr, client_addr, len, true, true)
             }
             QueuedMessage::WaitingReturnScalar(pid, ctx, return_address) => {
+                (pid, ctx, return_address, 0, 0, false, false)
+            }
+            QueuedMessage::WaitingReturnScalarTerminated(pid, ctx, return_address) => {
                 (pid, ctx, return_address, 0, 0, true, false)
             }
             _ => return Ok(WaitingMessage::None),
         };
+        if PID::new(pid as _) != client {
+            return Ok(WaitingMessage::None);
+        }
 
         // Sanity check the specified address was correct, and matches what we
         // had cached.
@@ -357,15 +509,21 @@ impl Server {
             }
         }
         self.queue[idx] = QueuedMessage::Empty;
-        self.queue_tail += 1;
-        if self.queue_tail >= self.queue.len() {
-            self.queue_tail = 0;
+        self.advance_tail();
+        self.latency.responded(idx);
+        if is_memory {
+            if let Some(client) = PID::new(pid as _) {
+                crate::lends::returned(client, ctx as _);
+            }
         }
 
         // Destructure the PID and context ID from the `pid_ctx` field
         // println!("Taking waiting message -- pid: {} ctx: {}", pid, ctx);
 
         if !is_memory {
+            if forget {
+                return Ok(WaitingMessage::ForgetScalar);
+            }
             return Ok(WaitingMessage::ScalarMessage(
                 PID::new(pid as _).unwrap(),
                 ctx as _,
@@ -397,6 +555,67 @@ impl Server {
         ))
     }
 
+    /// Describe the message in slot `idx` that is waiting for a response,
+    /// without taking it off the queue.  Only messages that are waiting for
+    /// lent memory or a scalar to be returned to `client` are described;
+    /// anything else is `WaitingMessage::None`.
+    pub fn peek_waiting_message(&self, idx: usize, client: Option<PID>) -> WaitingMessage {
+        let waiting = match self.queue.get(idx) {
+            Some(QueuedMessage::WaitingReturnMemory(pid, ctx, server_addr, client_addr, len)) => {
+                match (
+                    PID::new(*pid as _),
+                    MemoryAddress::new(*server_addr),
+                    MemoryAddress::new(*client_addr),
+                    MemorySize::new(*len),
+                ) {
+                    (Some(pid), Some(server_addr), Some(client_addr), Some(len)) => {
+                        WaitingMessage::BorrowedMemory(
+                            pid,
+                            *ctx as _,
+                            server_addr,
+                            client_addr,
+                            len,
+                        )
+                    }
+                    _ => WaitingMessage::None,
+                }
+            }
+            Some(QueuedMessage::WaitingReturnScalar(pid, ctx, _)) => match PID::new(*pid as _) {
+                Some(pid) => WaitingMessage::ScalarMessage(pid, *ctx as _),
+                None => WaitingMessage::None,
+            },
+            _ => WaitingMessage::None,
+        };
+        match waiting {
+            WaitingMessage::BorrowedMemory(pid, ..) | WaitingMessage::ScalarMessage(pid, _)
+                if Some(pid) != client =>
+            {
+                WaitingMessage::None
+            }
+            waiting => waiting,
+        }
+    }
+
+    /// Find the first slot at or after `start` holding a message that the
+    /// server has received but not yet responded to, and return the slot
+    /// along with the process that is waiting on it.  Servers use this to
+    /// find responses they have deferred, such as when cleaning up.
+    pub fn next_outstanding(&self, start: usize) -> Option<(usize, PID)> {
+        self.queue
+            .iter()
+            .enumerate()
+            .skip(start)
+            .find_map(|(idx, entry)| match *entry {
+                QueuedMessage::WaitingReturnMemory(pid, ..)
+                | QueuedMessage::WaitingForget(pid, ..)
+                | QueuedMessage::WaitingReturnScalar(pid, ..)
+                | QueuedMessage::WaitingReturnScalarTerminated(pid, ..) => {
+                    PID::new(pid as _).map(|pid| (idx, pid))
+                }
+                _ => None,
+            })
+    }
+
     /// Remove a message from the server's queue and replace it with either a QueuedMessage::WaitingReturnMemory
     /// or, for Scalar messages, QueuedMessage::Empty.
     ///
@@ -406,21 +625,285 @@ impl Server {
     /// # Returns
     ///
     /// * **None**: There are no waiting messages
-    /// ***Some(MessageEnvelope): This message is queued.
-    pub fn take_next_message(&mut self, cid: xous_kernel::CID) -> Option<xous_kernel::MessageEnvelope> {
+    /// ***Some((MessageEnvelope, u64)): This message is queued, and was queued at this time.
+    pub fn take_next_message(
+        &mut self,
+        cid: xous_kernel::CID,
+    ) -> Option<(xous_kernel::MessageEnvelope, u64)> {
+        let idx = self.next_queued_index()?;
+        let message = self.take_queued_message(idx, cid)?;
+        let queued = self.latency.received(idx);
+        self.note_lend(idx, &message.body);
+        Some((message, queued))
+    }
+
+    /// The data attached to the connection from process `pid`, or `0` if
+    /// there isn't any.
+    pub fn connection_data(&self, pid: PID) -> usize {
+        self.connection_data
+            .iter()
+            .flatten()
+            .find(|(client, _)| *client == pid)
+            .map(|(_, data)| *data)
+            .unwrap_or(0)
+    }
+
+    /// Attach `data` to the connection from process `pid`.  Attaching `0`
+    /// frees up the slot the connection was using.
+    ///
+    /// # Errors
+    ///
+    /// * **OutOfMemory**: Data is already attached to `MAX_CONNECTION_DATA`
+    ///                    other connections
+    pub fn set_connection_data(&mut self, pid: PID, data: usize) -> Result<(), xous_kernel::Error> {
+        self.forget_connection_data(pid);
+        if data == 0 {
+            return Ok(());
+        }
+        let slot = self
+            .connection_data
+            .iter_mut()
+            .find(|slot| slot.is_none())
+            .ok_or(xous_kernel::Error::OutOfMemory)?;
+        *slot = Some((pid, data));
+        Ok(())
+    }
+
+    /// Drop the data attached to the connection from process `pid`, such
+    /// as when it disconnects.
+    pub fn forget_connection_data(&mut self, pid: PID) {
+        for slot in self.connection_data.iter_mut() {
+            if slot.map(|(client, _)| client == pid).unwrap_or(false) {
+                *slot = None;
+            }
+        }
+    }
+
+    /// Signal `notification` on behalf of process `pid` when this server
+    /// goes away.  Watching with `0` stops process `pid` from watching.
+    ///
+    /// # Errors
+    ///
+    /// * **OutOfMemory**: `MAX_DISCONNECT_WATCHERS` other processes are
+    ///                    already watching
+    pub fn watch_disconnect(
+        &mut self,
+        pid: PID,
+        notification: usize,
+    ) -> Result<(), xous_kernel::Error> {
+        self.forget_disconnect_watcher(pid);
+        if notification == 0 {
+            return Ok(());
+        }
+        let slot = self
+            .disconnect_watchers
+            .iter_mut()
+            .find(|slot| slot.is_none())
+            .ok_or(xous_kernel::Error::OutOfMemory)?;
+        *slot = Some((pid, notification));
+        Ok(())
+    }
+
+    /// Stop process `pid` from watching this server, such as when it
+    /// disconnects.
+    pub fn forget_disconnect_watcher(&mut self, pid: PID) {
+        for slot in self.disconnect_watchers.iter_mut() {
+            if slot.map(|(client, _)| client == pid).unwrap_or(false) {
+                *slot = None;
+            }
+        }
+    }
+
+    /// Only let the owner, and processes it allows, connect from now on.
+    /// Connections that have already been made are left alone.
+    pub fn make_private(&mut self) {
+        self.private = true;
+    }
+
+    /// Let process `pid` connect to this server once it is private.
+    /// Allowing a process twice does nothing.
+    ///
+    /// # Errors
+    ///
+    /// * **OutOfMemory**: `MAX_ALLOWED_CLIENTS` other processes are already
+    ///                    allowed
+    pub fn allow_client(&mut self, pid: PID) -> Result<(), xous_kernel::Error> {
+        if self.allowed_clients.contains(&Some(pid)) {
+            return Ok(());
+        }
+        let slot = self
+            .allowed_clients
+            .iter_mut()
+            .find(|slot| slot.is_none())
+            .ok_or(xous_kernel::Error::OutOfMemory)?;
+        *slot = Some(pid);
+        Ok(())
+    }
+
+    /// Stop allowing process `pid` to connect, such as when it exits and
+    /// its PID may be handed to somebody else.
+    pub fn forget_allowed_client(&mut self, pid: PID) {
+        for slot in self.allowed_clients.iter_mut() {
+            if *slot == Some(pid) {
+                *slot = None;
+            }
+        }
+    }
+
+    /// Whether process `pid` may make a new connection to this server.
+    pub fn may_connect(&self, pid: PID) -> bool {
+        !self.private || pid == self.pid || self.allowed_clients.contains(&Some(pid))
+    }
+
+    /// Remove the next process watching this server, along with the
+    /// notification it wants signalled, for when the server goes away.
+    pub fn take_disconnect_watcher(&mut self) -> Option<(PID, usize)> {
+        self.disconnect_watchers
+            .iter_mut()
+            .find(|slot| slot.is_some())
+            .and_then(|slot| slot.take())
+    }
+
+    /// Remove the next message whose client is blocked waiting on this
+    /// server, for when the server goes away.  Returns the client thread,
+    /// along with the memory it lent if there is any to give back.
+    pub fn take_blocked_client(&mut self) -> Option<(PID, TID, Option<LentMemory>)> {
+        for entry in self.queue.iter_mut() {
+            let (pid, ctx, lent) = match *entry {
+                QueuedMessage::BlockingScalarMessage(pid, ctx, ..)
+                | QueuedMessage::WaitingReturnScalar(pid, ctx, _) => (pid, ctx, None),
+                QueuedMessage::MemoryMessageROLend(pid, ctx, client_addr, _, buf, buf_size, ..)
+                | QueuedMessage::MemoryMessageRWLend(pid, ctx, client_addr, _, buf, buf_size, ..) => {
+                    (pid, ctx, Some((buf, client_addr, buf_size)))
+                }
+                QueuedMessage::WaitingReturnMemory(pid, ctx, server_addr, client_addr, len) => {
+                    if let Some(client) = PID::new(pid as _) {
+                        crate::lends::returned(client, ctx as _);
+                    }
+                    (pid, ctx, Some((server_addr, client_addr, len)))
+                }
+                _ => continue,
+            };
+            *entry = QueuedMessage::Empty;
+            if let Some(client) = PID::new(pid as _) {
+                return Some((client, ctx as _, lent));
+            }
+        }
+        None
+    }
+
+    /// Remove the memory that thread `client_tid` of `client` lent to this
+    /// server from the queue, so that it can be taken back before the server
+    /// returns it.  Returns `None` if the server isn't holding any.
+    #[cfg(baremetal)]
+    pub fn take_lend(&mut self, client: PID, client_tid: TID) -> Option<LentMemory> {
+        let idx = self.queue.iter().position(|entry| {
+            matches!(*entry, QueuedMessage::WaitingReturnMemory(pid, ctx, ..)
+                if PID::new(pid as _) == Some(client) && ctx as TID == client_tid)
+        })?;
+        let lent = match self.queue[idx] {
+            QueuedMessage::WaitingReturnMemory(_, _, server_addr, client_addr, len) => {
+                (server_addr, client_addr, len)
+            }
+            _ => return None,
+        };
+        self.queue[idx] = QueuedMessage::Empty;
+        self.advance_tail();
+        Some(lent)
+    }
+
+    /// Add one to the second argument of the `Scalar` message with ID `id`
+    /// and first argument `arg1` that `client` queued, if the server hasn't
+    /// received it yet.  Returns `false` if there is no such message.
+    pub fn coalesce_scalar(&mut self, client: PID, id: usize, arg1: usize) -> bool {
+        for entry in self.queue.iter_mut() {
+            if let QueuedMessage::ScalarMessage(pid, _, _, msg_id, msg_arg1, count, _, _) = entry {
+                if PID::new(*pid as _) == Some(client) && *msg_id == id && *msg_arg1 == arg1 {
+                    *count = count.saturating_add(1);
+                    return true;
+                }
+            }
+        }
+        false
+    }
+
+    /// Start tracking the memory in `message` if it's now on loan to this
+    /// server, waiting in queue slot `idx` to be returned.
+    fn note_lend(&self, idx: usize, message: &Message) {
+        let id = match message {
+            Message::MutableBorrow(msg) | Message::Borrow(msg) => msg.id,
+            _ => return,
+        };
+        if let QueuedMessage::WaitingReturnMemory(pid, ctx, _, _, _) = self.queue[idx] {
+            if let Some(client) = PID::new(pid as _) {
+                crate::lends::lent(self.pid, client, ctx as _, id);
+            }
+        }
+    }
+
+    /// The slot of the oldest message that hasn't been received yet.  Slots
+    /// waiting for a response are skipped over, so that a server which defers
+    /// its responses can keep receiving.  Messages are only ever added at the
+    /// head, so walking forward from the tail visits them in the order they
+    /// arrived.
+    fn next_queued_index(&self) -> Option<usize> {
+        (0..self.queue.len())
+            .map(|offset| (self.queue_tail + offset) % self.queue.len())
+            .find(|&idx| {
+                !matches!(
+                    self.queue[idx],
+                    QueuedMessage::Empty
+                        | QueuedMessage::WaitingReturnMemory(..)
+                        | QueuedMessage::WaitingForget(..)
+                        | QueuedMessage::WaitingReturnScalar(..)
+                        | QueuedMessage::WaitingReturnScalarTerminated(..)
+                )
+            })
+    }
+
+    /// Move the tail past any slots that have been emptied, which may have
+    /// been responded to out of order.  If the whole queue is empty, the
+    /// tail catches up with the head.
+    fn advance_tail(&mut self) {
+        for _ in 0..self.queue.len() {
+            if self.queue[self.queue_tail] != QueuedMessage::Empty {
+                return;
+            }
+            self.queue_tail += 1;
+            if self.queue_tail >= self.queue.len() {
+                self.queue_tail = 0;
+            }
+        }
+        self.queue_tail = self.queue_head;
+    }
+
+    fn take_queued_message(
+        &mut self,
+        idx: usize,
+        cid: xous_kernel::CID,
+    ) -> Option<xous_kernel::MessageEnvelope> {
         // println!(
         //     "queue_head: ((({})))  queue_tail: ((({}))): {:?}  CID: ((({})))",
-        //     self.queue_head, self.queue_tail, self.queue[self.queue_tail], cid
+        //     self.queue_head, self.queue_tail, self.queue[idx], cid
         // );
-        let sender = SenderID {
-            idx: self.queue_tail,
-            cid,
-        }.into();
-        let (result, response) = match self.queue[self.queue_tail] {
+        let pid = match self.queue[idx] {
+            QueuedMessage::MemoryMessageROLend(pid, ..)
+            | QueuedMessage::MemoryMessageRWLend(pid, ..)
+            | QueuedMessage::MemoryMessageROLendTerminated(pid, ..)
+            | QueuedMessage::MemoryMessageRWLendTerminated(pid, ..)
+            | QueuedMessage::MemoryMessageSend(pid, ..)
+            | QueuedMessage::BlockingScalarMessage(pid, ..)
+            | QueuedMessage::ScalarMessage(pid, ..) => PID::new(pid as _),
+            _ => None,
+        };
+        let connection_data = pid.map(|pid| self.connection_data(pid)).unwrap_or(0);
+        let sender = SenderID { idx, cid, pid }.into();
+        let (result, response) = match self.queue[idx] {
             QueuedMessage::Empty => return None,
             QueuedMessage::WaitingReturnMemory(_, _, _, _, _) => return None,
             QueuedMessage::WaitingForget(_, _, _, _, _) => return None,
             QueuedMessage::WaitingReturnScalar(_, _, _) => return None,
+            QueuedMessage::WaitingReturnScalarTerminated(_, _, _) => return None,
             QueuedMessage::MemoryMessageROLend(
                 pid,
                 ctx,
@@ -433,6 +916,7 @@ impl Server {
             ) => (
                 xous_kernel::MessageEnvelope {
                     sender,
+                    connection_data,
                     body: xous_kernel::Message::Borrow(xous_kernel::MemoryMessage {
                         id,
                         buf: MemoryRange::new(buf, buf_size).ok()?,
@@ -440,7 +924,7 @@ impl Server {
                         valid: MemorySize::new(valid),
                     }),
                 },
-                QueuedMessage::WaitingForget(pid, ctx, buf, client_addr, buf_size),
+                QueuedMessage::WaitingReturnMemory(pid, ctx, buf, client_addr, buf_size),
             ),
             QueuedMessage::MemoryMessageRWLend(
                 pid,
@@ -454,6 +938,7 @@ impl Server {
             ) => (
                 xous_kernel::MessageEnvelope {
                     sender,
+                    connection_data,
                     body: xous_kernel::Message::MutableBorrow(xous_kernel::MemoryMessage {
                         id,
                         buf: MemoryRange::new(buf, buf_size).ok()?,
@@ -461,7 +946,7 @@ impl Server {
                         valid: MemorySize::new(valid),
                     }),
                 },
-                QueuedMessage::WaitingForget(pid, ctx, buf, client_addr, buf_size),
+                QueuedMessage::WaitingReturnMemory(pid, ctx, buf, client_addr, buf_size),
             ),
             QueuedMessage::MemoryMessageROLendTerminated(
                 pid,
@@ -475,6 +960,7 @@ impl Server {
             ) => (
                 xous_kernel::MessageEnvelope {
                     sender,
+                    connection_data,
                     body: xous_kernel::Message::Borrow(xous_kernel::MemoryMessage {
                         id,
                         buf: MemoryRange::new(buf, buf_size).ok()?,
@@ -482,7 +968,7 @@ impl Server {
                         valid: MemorySize::new(valid),
                     }),
                 },
-                QueuedMessage::WaitingReturnMemory(pid, ctx, buf, client_addr, buf_size),
+                QueuedMessage::WaitingForget(pid, ctx, buf, client_addr, buf_size),
             ),
             QueuedMessage::MemoryMessageRWLendTerminated(
                 pid,
@@ -496,6 +982,7 @@ impl Server {
             ) => (
                 xous_kernel::MessageEnvelope {
                     sender,
+                    connection_data,
                     body: xous_kernel::Message::MutableBorrow(xous_kernel::MemoryMessage {
                         id,
                         buf: MemoryRange::new(buf, buf_size).ok()?,
@@ -503,7 +990,7 @@ impl Server {
                         valid: MemorySize::new(valid),
                     }),
                 },
-                QueuedMessage::WaitingReturnMemory(pid, ctx, buf, client_addr, buf_size),
+                QueuedMessage::WaitingForget(pid, ctx, buf, client_addr, buf_size),
             ),
 
             QueuedMessage::BlockingScalarMessage(
@@ -518,6 +1005,7 @@ impl Server {
             ) => (
                 xous_kernel::MessageEnvelope {
                     sender,
+                    connection_data,
                     body: xous_kernel::Message::BlockingScalar(xous_kernel::ScalarMessage {
                         id,
                         arg1,
@@ -540,6 +1028,7 @@ impl Server {
             ) => {
                 let msg = xous_kernel::MessageEnvelope {
                     sender,
+                    connection_data,
                     body: xous_kernel::Message::Move(xous_kernel::MemoryMessage {
                         id,
                         buf: MemoryRange::new(buf, buf_size).ok()?,
@@ -547,11 +1036,8 @@ impl Server {
                         valid: MemorySize::new(valid),
                     }),
                 };
-                self.queue[self.queue_tail] = QueuedMessage::Empty;
-                self.queue_tail += 1;
-                if self.queue_tail >= self.queue.len() {
-                    self.queue_tail = 0;
-                }
+                self.queue[idx] = QueuedMessage::Empty;
+                self.advance_tail();
                 return Some(msg);
             }
 
@@ -559,6 +1045,7 @@ impl Server {
             QueuedMessage::ScalarMessage(_pid, _ctx, _reserved, id, arg1, arg2, arg3, arg4) => {
                 let msg = xous_kernel::MessageEnvelope {
                     sender,
+                    connection_data,
                     body: xous_kernel::Message::Scalar(xous_kernel::ScalarMessage {
                         id,
                         arg1,
@@ -567,11 +1054,8 @@ impl Server {
                         arg4,
                     }),
                 };
-                self.queue[self.queue_tail] = QueuedMessage::Empty;
-                self.queue_tail += 1;
-                if self.queue_tail >= self.queue.len() {
-                    self.queue_tail = 0;
-                }
+                self.queue[idx] = QueuedMessage::Empty;
+                self.advance_tail();
                 return Some(msg);
             }
             QueuedMessage::BlockingScalarTerminated(
@@ -586,6 +1070,7 @@ impl Server {
             ) => {
                 let msg = xous_kernel::MessageEnvelope {
                     sender,
+                    connection_data,
                     body: xous_kernel::Message::Scalar(xous_kernel::ScalarMessage {
                         id,
                         arg1,
@@ -594,16 +1079,13 @@ impl Server {
                         arg4,
                     }),
                 };
-                self.queue[self.queue_tail] = QueuedMessage::Empty;
-                self.queue_tail += 1;
-                if self.queue_tail >= self.queue.len() {
-                    self.queue_tail = 0;
-                }
+                self.queue[idx] = QueuedMessage::Empty;
+                self.advance_tail();
                 return Some(msg);
             }
         };
 
-        self.queue[self.queue_tail] = response;
+        self.queue[idx] = response;
         Some(result)
     }
 
@@ -682,6 +1164,7 @@ impl Server {
         if self.queue_head >= self.queue.len() {
             self.queue_head = 0;
         }
+        self.latency.queued(idx);
         Ok(idx)
     }
 
@@ -732,8 +1215,54 @@ impl Server {
         if self.queue_head >= self.queue.len() {
             self.queue_head = 0;
         }
+        self.latency.delivered(idx);
+        self.note_lend(idx, message);
         Ok(idx)
     }
+
+    /// Describe the state of this server, given the number of processes
+    /// connected to it.
+    pub fn info(&self, connections: usize) -> xous_kernel::ServerInfo {
+        let mut queued = 0;
+        let mut outstanding = 0;
+        for entry in self.queue.iter() {
+            match entry {
+                QueuedMessage::Empty => (),
+                QueuedMessage::WaitingReturnMemory(..)
+                | QueuedMessage::WaitingForget(..)
+                | QueuedMessage::WaitingReturnScalar(..)
+                | QueuedMessage::WaitingReturnScalarTerminated(..) => outstanding += 1,
+                _ => queued += 1,
+            }
+        }
+        xous_kernel::ServerInfo {
+            pid: self.pid,
+            queue_length: self.queue.len(),
+            queued,
+            outstanding,
+            connections,
+            parked_threads: self.ready_threads.count_ones() as usize,
+        }
+    }
+
+    /// Whether there is room in the queue for another message.
+    pub fn has_room(&self) -> bool {
+        self.queue[self.queue_head] == QueuedMessage::Empty
+    }
+
+    /// Return the estimated `percentile` latency of `stage` for messages to
+    /// this server, along with the number of messages it was estimated from.
+    ///
+    /// # Errors
+    ///
+    /// * **InvalidSyscall**: The percentile is greater than 100
+    pub fn latency(
+        &self,
+        stage: LatencyStage,
+        percentile: usize,
+    ) -> Result<(u64, u64), xous_kernel::Error> {
+        self.latency.percentile(stage, percentile)
+    }
     // assert!(
     //     mem::size_of::<QueuedMessage>() == 32,
     //     "QueuedMessage was supposed to be 32 bytes, but instead was {} bytes",
@@ -789,4 +1318,11 @@ impl Server {
         assert!(self.ready_threads & (1 << tid) == 0);
         self.ready_threads |= 1 << tid;
     }
+
+    /// Remove the given context from the list of ready and waiting contexts,
+    /// if it's there, such as when it was parked on several servers and one
+    /// of the others has handed it a message.
+    pub fn unpark_thread(&mut self, tid: TID) {
+        self.ready_threads &= !(1 << tid);
+    }
 }
@@ -10,10 +10,11 @@ use crate::filled_array;
 use crate::server::Server;
 // use core::mem;
 use xous_kernel::{
-    pid_from_usize, Error, MemoryAddress, Message, ProcessInit, ThreadInit, CID, PID, SID, TID,
+    pid_from_usize, AuditKind, Capabilities, Error, MemoryAddress, MemoryFlags, Message,
+    ProcessInit, Quotas, ScrubLevel, SyscallFilter, ThreadInit, ThreadStats, CID, PID, SID, TID,
 };
 
-const MAX_SERVER_COUNT: usize = 32;
+pub const MAX_SERVER_COUNT: usize = 32;
 
 pub use crate::arch::process::{INITIAL_TID, MAX_PROCESS_COUNT};
 
@@ -88,6 +89,59 @@ pub struct Process {
     /// The context number that was active before this process was switched
     /// away.
     previous_thread: TID,
+
+    /// Whether this process may create mappings that are both writable and
+    /// executable.  This is the "JIT" capability.
+    pub jit_allowed: bool,
+
+    /// The privileged operations this process may perform, which its parent
+    /// granted it when it was created.
+    capabilities: Capabilities,
+
+    /// The syscalls this process may make.
+    syscall_filter: SyscallFilter,
+
+    /// How many servers, connections, and threads this process may have.
+    quotas: Quotas,
+
+    /// The function to call when a thread in this process raises a CPU
+    /// exception that the kernel can't resolve.
+    exception_handler: Option<MemoryAddress>,
+
+    /// Alternate stacks to run the exception handler on, indexed by thread.
+    exception_stacks: [Option<MemoryRange>; arch::process::MAX_THREAD + 1],
+
+    /// A bitmask of threads that are currently running the exception handler.
+    /// A thread that faults again before it exits won't get another call.
+    exception_threads: usize,
+
+    /// The priority this process asked to run at.
+    base_priority: u8,
+
+    /// The highest base priority this process may ask for without
+    /// `Capabilities::SCHEDULING`, which is the base priority its parent had
+    /// when it was created.
+    priority_ceiling: u8,
+
+    /// The priority this process is actually scheduled at.  This is the
+    /// higher of `base_priority` and the priority of any client that is
+    /// blocked waiting on one of this process' servers.
+    priority: u8,
+
+    /// How much each thread has been scheduled.
+    thread_stats: [ThreadStats; arch::process::MAX_THREAD + 1],
+
+    /// The timestamp at which each thread was last switched to, or `0` if
+    /// it isn't running.
+    thread_started: [u64; arch::process::MAX_THREAD + 1],
+
+    /// The thread that used `SwitchTo` to run each thread, which is where
+    /// that thread goes back to when it yields or is interrupted.
+    switched_from: [Option<(PID, TID)>; arch::process::MAX_THREAD + 1],
+
+    /// When the message that each thread most recently received was queued
+    /// for its server, or `0` if it hasn't received one.
+    message_stamps: [u64; arch::process::MAX_THREAD + 1],
 }
 
 impl Default for Process {
@@ -130,6 +184,10 @@ pub struct ProcessInner {
     /// A mapping of connection IDs to server indexes
     pub connection_map: [Option<NonZeroU8>; 32],
 
+    /// The number of times each connection has been made and not yet
+    /// disconnected.  Connections at `u8::MAX` are never freed.
+    pub connection_refs: [u8; 32],
+
     /// A copy of this process' ID
     pub pid: PID,
 
@@ -148,6 +206,7 @@ impl Default for ProcessInner {
             mem_heap_size: 0,
             mem_heap_max: 524_288,
             connection_map: [None; 32],
+            connection_refs: [0; 32],
             pid: unsafe { PID::new_unchecked(1) },
             _reserved: [0; 1],
         }
@@ -171,6 +230,12 @@ impl Process {
         }
     }
 
+    /// The priority this process should be scheduled at, including any
+    /// priority it has inherited from its clients.
+    pub fn priority(&self) -> u8 {
+        self.priority
+    }
+
     pub fn activate(&self) -> Result<(), xous_kernel::Error> {
         crate::arch::process::set_current_pid(self.pid);
         self.mapping.activate()?;
@@ -183,7 +248,8 @@ impl Process {
             return Err(xous_kernel::Error::ProcessNotFound);
         }
 
-        // TODO: Free all pages
+        // Every page still owned by this process is now unreachable.
+        crate::mem::MemoryManager::with_mut(|mm| mm.release_all(self.pid));
 
         // TODO: Free all IRQs
 
@@ -195,21 +261,40 @@ impl Process {
 }
 
 #[cfg(not(baremetal))]
-std::thread_local!(static SYSTEM_SERVICES: core::cell::RefCell<SystemServices> = core::cell::RefCell::new(SystemServices {
+std::thread_local!(static SYSTEM_SERVICES: core::cell::RefCell<SystemServices> = const { core::cell::RefCell::new(SystemServices {
     processes: [Process {
         state: ProcessState::Free,
-        ppid: unsafe { PID::new_unchecked(1) },
-        pid: unsafe { PID::new_unchecked(1) },
+        ppid: PID::new(1).unwrap(),
+        pid: PID::new(1).unwrap(),
         mapping: arch::mem::DEFAULT_MEMORY_MAPPING,
         current_thread: 0 as TID,
         previous_thread: INITIAL_TID as TID,
+        jit_allowed: false,
+        capabilities: Capabilities::empty(),
+        syscall_filter: SyscallFilter::all(),
+        quotas: Quotas::unlimited(),
+        exception_handler: None,
+        exception_stacks: [None; arch::process::MAX_THREAD + 1],
+        exception_threads: 0,
+        base_priority: 0,
+        priority_ceiling: 0,
+        priority: 0,
+        thread_stats: [ThreadStats {
+            run_time: 0,
+            schedules: 0,
+            voluntary_switches: 0,
+            involuntary_switches: 0,
+        }; arch::process::MAX_THREAD + 1],
+        thread_started: [0; arch::process::MAX_THREAD + 1],
+        switched_from: [None; arch::process::MAX_THREAD + 1],
+        message_stamps: [0; arch::process::MAX_THREAD + 1],
     }; MAX_PROCESS_COUNT],
     // Note we can't use MAX_SERVER_COUNT here because of how Rust's
     // macro tokenization works
     servers: filled_array![None; 32],
     _syscall_stack: [(0, 0), (0, 0), (0, 0)],
     _syscall_depth: 0,
-}));
+}) });
 
 #[cfg(baremetal)]
 static mut SYSTEM_SERVICES: SystemServices = SystemServices {
@@ -220,6 +305,25 @@ static mut SYSTEM_SERVICES: SystemServices = SystemServices {
         mapping: arch::mem::DEFAULT_MEMORY_MAPPING,
         current_thread: 0 as TID,
         previous_thread: INITIAL_TID as TID,
+        jit_allowed: false,
+        capabilities: Capabilities::empty(),
+        syscall_filter: SyscallFilter::all(),
+        quotas: Quotas::unlimited(),
+        exception_handler: None,
+        exception_stacks: [None; arch::process::MAX_THREAD + 1],
+        exception_threads: 0,
+        base_priority: 0,
+        priority_ceiling: 0,
+        priority: 0,
+        thread_stats: [ThreadStats {
+            run_time: 0,
+            schedules: 0,
+            voluntary_switches: 0,
+            involuntary_switches: 0,
+        }; arch::process::MAX_THREAD + 1],
+        thread_started: [0; arch::process::MAX_THREAD + 1],
+        switched_from: [None; arch::process::MAX_THREAD + 1],
+        message_stamps: [0; arch::process::MAX_THREAD + 1],
     }; MAX_PROCESS_COUNT],
     // Note we can't use MAX_SERVER_COUNT here because of how Rust's
     // macro tokenization works
@@ -308,6 +412,12 @@ impl SystemServices {
                 process.ppid = PID::new_unchecked(1);
                 process.pid = PID::new(pid as _).unwrap();
             };
+            // Not even the kernel starts out with the JIT capability, so
+            // code always has to go through `FinalizeCode`.
+            process.jit_allowed = false;
+            process.capabilities = Capabilities::all();
+            process.syscall_filter = SyscallFilter::all();
+            process.quotas = Quotas::unlimited();
             if pid == 1 {
                 process.state = ProcessState::Running(0);
             } else {
@@ -320,6 +430,15 @@ impl SystemServices {
             }
         }
 
+        // The loader places a hash of each image directly after the process
+        // table, in the same order.
+        crate::measure::init(unsafe {
+            core::slice::from_raw_parts(
+                init_offsets.as_ptr().add(init_offsets.len()) as *const crate::measure::Measurement,
+                init_offsets.len(),
+            )
+        });
+
         // Set up our handle with a bogus sp and pc.  These will get updated
         // once a context switch _away_ from the kernel occurs, however we need
         // to make sure other fields such as "thread number" are all valid.
@@ -328,7 +447,18 @@ impl SystemServices {
 
     /// Add a new entry to the process table. This results in a new address space
     /// and a new PID, though the process is in the state `Setup()`.
+    /// The new process is only given the capabilities in `init_process` that
+    /// its parent holds, may only make the syscalls its parent may make, and
+    /// gets no higher quotas than its parent has.
     pub fn create_process(&mut self, init_process: ProcessInit) -> Result<PID, xous_kernel::Error> {
+        let parent = crate::arch::process::current_pid();
+        let capabilities = init_process.capabilities & self.capabilities(parent);
+        let syscall_filter = self.syscall_filter(parent);
+        let quotas = init_process.quotas.min(&self.quotas(parent));
+        let priority_ceiling = self
+            .get_process(parent)
+            .map(|process| process.base_priority)
+            .unwrap_or(0);
         for (idx, mut entry) in self.processes.iter_mut().enumerate() {
             if entry.state != ProcessState::Free {
                 continue;
@@ -340,6 +470,32 @@ impl SystemServices {
             entry.state = ProcessState::Allocated;
             entry.ppid = ppid;
             entry.pid = new_pid;
+            entry.jit_allowed = false;
+            entry.capabilities = capabilities;
+            entry.syscall_filter = syscall_filter;
+            entry.quotas = quotas;
+            entry.exception_handler = None;
+            entry.exception_stacks = [None; arch::process::MAX_THREAD + 1];
+            entry.exception_threads = 0;
+            entry.base_priority = 0;
+            entry.priority_ceiling = priority_ceiling;
+            entry.priority = 0;
+            entry.thread_stats = [ThreadStats::default(); arch::process::MAX_THREAD + 1];
+            entry.thread_started = [0; arch::process::MAX_THREAD + 1];
+            entry.switched_from = [None; arch::process::MAX_THREAD + 1];
+            entry.message_stamps = [0; arch::process::MAX_THREAD + 1];
+            #[cfg(not(baremetal))]
+            {
+                entry.mapping = arch::mem::MemoryMapping::new(new_pid);
+            }
+            crate::audit::record(AuditKind::ProcessCreated, new_pid, ppid.get() as usize, 0);
+            klog!(
+                Scheduler,
+                Info,
+                "KERNEL: PID {} created PID {}",
+                ppid,
+                new_pid
+            );
             return Ok(new_pid);
         }
         Err(xous_kernel::Error::ProcessNotFound)
@@ -374,6 +530,428 @@ impl SystemServices {
         Ok(&mut self.processes[pid_idx])
     }
 
+    /// Grant the JIT capability to the given process, so that it may map
+    /// memory that is both writable and executable.
+    pub fn allow_jit(&mut self, pid: PID) -> Result<(), xous_kernel::Error> {
+        let process = self
+            .processes
+            .get_mut(pid.get() as usize - 1)
+            .ok_or(xous_kernel::Error::ProcessNotFound)?;
+        if process.free() {
+            return Err(xous_kernel::Error::ProcessNotFound);
+        }
+        process.jit_allowed = true;
+        Ok(())
+    }
+
+    /// Set the base priority of `pid`, and return the priority it is now
+    /// running at once inheritance is taken into account.
+    ///
+    /// # Errors
+    ///
+    /// * **ProcessNotFound**: `pid` doesn't exist
+    /// * **AccessDenied**: The priority is above the ceiling `pid` was
+    ///   created with, and `pid` doesn't hold `Capabilities::SCHEDULING`
+    pub fn set_priority(&mut self, pid: PID, priority: u8) -> Result<u8, xous_kernel::Error> {
+        let process = self.get_process(pid)?;
+        if process.free() {
+            return Err(xous_kernel::Error::ProcessNotFound);
+        }
+        if priority > process.priority_ceiling {
+            self.check_capability(pid, Capabilities::SCHEDULING)?;
+        }
+        self.get_process_mut(pid)?.base_priority = priority;
+        self.update_priorities();
+        Ok(self.get_process(pid)?.priority)
+    }
+
+    /// Recalculate the priority of every process.  A process that owns a
+    /// server runs at the priority of the most important client blocked on
+    /// that server, if that is higher than its own.  This must be called
+    /// whenever a client starts or stops waiting on a server.
+    pub fn update_priorities(&mut self) {
+        for process in self.processes.iter_mut() {
+            process.priority = process.base_priority;
+        }
+
+        // A server may itself be blocked on another server, so keep passing
+        // priorities down the chain until nothing changes.  Each pass settles
+        // at least one more link, so this can't take more passes than there
+        // are processes.
+        for _ in 0..self.processes.len() {
+            let mut changed = false;
+            for server in self.servers.iter().flatten() {
+                let server_idx = server.pid.get() as usize - 1;
+                let processes = &mut self.processes;
+                server.for_each_blocked_client(|client_pid| {
+                    let client_priority = processes[client_pid.get() as usize - 1].priority;
+                    if client_priority > processes[server_idx].priority {
+                        processes[server_idx].priority = client_priority;
+                        changed = true;
+                    }
+                });
+            }
+            if !changed {
+                break;
+            }
+        }
+    }
+
+    /// Whether `client` starting or stopping waiting on a server owned by
+    /// `server` could change anybody's priority.  If not, there's no need to
+    /// call `update_priorities()`.
+    pub fn affects_priority(&self, server: PID, client: PID) -> bool {
+        let (server, client) = match (self.get_process(server), self.get_process(client)) {
+            (Ok(server), Ok(client)) => (server, client),
+            _ => return true,
+        };
+        client.priority >= server.priority && client.priority > server.base_priority
+    }
+
+    /// Recalculate priorities after `client` starts or stops waiting on a
+    /// server owned by `server`, unless that can't change anything.  Most
+    /// messages go from a busy client to a server at the same or a higher
+    /// priority, so this saves walking every process on each call.
+    pub fn update_priorities_for(&mut self, server: PID, client: PID) {
+        if self.affects_priority(server, client) {
+            self.update_priorities();
+        }
+    }
+
+    /// Ensure the requested `flags` do not describe executable memory,
+    /// unless `pid` holds the JIT capability.  Everybody else has to map code
+    /// writable and then finalize it, so that it is never writable and
+    /// executable at once.
+    ///
+    /// # Errors
+    ///
+    /// * **AccessDenied**: The flags include X and the process may not JIT
+    pub fn check_wx(&self, pid: PID, flags: MemoryFlags) -> Result<(), xous_kernel::Error> {
+        if !flags.contains(MemoryFlags::X) {
+            return Ok(());
+        }
+        if self.get_process(pid)?.jit_allowed {
+            Ok(())
+        } else {
+            Err(xous_kernel::Error::AccessDenied)
+        }
+    }
+
+    /// The capabilities that `pid` holds.  PID 1 holds all of them.
+    fn capabilities(&self, pid: PID) -> Capabilities {
+        if pid.get() == 1 {
+            return Capabilities::all();
+        }
+        self.get_process(pid)
+            .map(|process| process.capabilities)
+            .unwrap_or_else(|_| Capabilities::empty())
+    }
+
+    /// Whether `pid` holds `capability`.
+    pub fn has_capability(&self, pid: PID, capability: Capabilities) -> bool {
+        self.capabilities(pid).contains(capability)
+    }
+
+    /// Make sure that `pid` holds `capability`, and record it in the audit
+    /// log if it doesn't.
+    ///
+    /// # Errors
+    ///
+    /// * **AccessDenied**: `pid` wasn't granted `capability`
+    pub fn check_capability(
+        &self,
+        pid: PID,
+        capability: Capabilities,
+    ) -> Result<(), xous_kernel::Error> {
+        if self.has_capability(pid, capability) {
+            Ok(())
+        } else {
+            crate::audit::record(AuditKind::CapabilityDenied, pid, capability.bits(), 0);
+            Err(xous_kernel::Error::AccessDenied)
+        }
+    }
+
+    /// How many kernel objects `pid` may hold.  PID 1 has no limits.
+    fn quotas(&self, pid: PID) -> Quotas {
+        if pid.get() == 1 {
+            return Quotas::unlimited();
+        }
+        self.get_process(pid)
+            .map(|process| process.quotas)
+            .unwrap_or_else(|_| Quotas::unlimited())
+    }
+
+    /// The syscalls that `pid` may make.  PID 1 may make all of them.
+    fn syscall_filter(&self, pid: PID) -> SyscallFilter {
+        if pid.get() == 1 {
+            return SyscallFilter::all();
+        }
+        self.get_process(pid)
+            .map(|process| process.syscall_filter)
+            .unwrap_or_else(|_| SyscallFilter::empty())
+    }
+
+    /// Make sure that `pid` may make syscall number `call`, and record it in
+    /// the audit log if it may not.
+    ///
+    /// # Errors
+    ///
+    /// * **AccessDenied**: The syscall isn't in the process' filter
+    pub fn check_syscall(&self, pid: PID, call: usize) -> Result<(), xous_kernel::Error> {
+        if self.syscall_filter(pid).allows(call) {
+            Ok(())
+        } else {
+            crate::audit::record(AuditKind::SyscallDenied, pid, call, 0);
+            Err(xous_kernel::Error::AccessDenied)
+        }
+    }
+
+    /// Limit child process `target` of `pid` to the syscalls in `filter`, on
+    /// top of the ones it is already limited to.
+    ///
+    /// # Errors
+    ///
+    /// * **ProcessNotChild**: `target` isn't a child of `pid`
+    /// * **AccessDenied**: `target` has already started running
+    pub fn set_syscall_filter(
+        &mut self,
+        pid: PID,
+        target: PID,
+        filter: SyscallFilter,
+    ) -> Result<(), xous_kernel::Error> {
+        let process = self
+            .processes
+            .get_mut(target.get() as usize - 1)
+            .ok_or(xous_kernel::Error::ProcessNotChild)?;
+        if process.free() || process.ppid != pid || target == pid {
+            return Err(xous_kernel::Error::ProcessNotChild);
+        }
+        match process.state {
+            ProcessState::Allocated | ProcessState::Setup(_) => {
+                process.syscall_filter = process.syscall_filter.intersect(&filter);
+                Ok(())
+            }
+            _ => Err(xous_kernel::Error::AccessDenied),
+        }
+    }
+
+    /// Set or clear the exception handler for the given process.
+    pub fn set_exception_handler(
+        &mut self,
+        pid: PID,
+        handler: Option<MemoryAddress>,
+    ) -> Result<(), xous_kernel::Error> {
+        self.get_process_mut(pid)?.exception_handler = handler;
+        Ok(())
+    }
+
+    /// Set or clear the alternate exception stack for the given thread.
+    ///
+    /// # Errors
+    ///
+    /// * **ThreadNotAvailable**: The thread doesn't exist
+    pub fn set_exception_stack(
+        &mut self,
+        pid: PID,
+        tid: TID,
+        stack: Option<MemoryRange>,
+    ) -> Result<(), xous_kernel::Error> {
+        let process = self.get_process_mut(pid)?;
+        let slot = process
+            .exception_stacks
+            .get_mut(tid)
+            .ok_or(xous_kernel::Error::ThreadNotAvailable)?;
+        *slot = stack;
+        Ok(())
+    }
+
+    /// Decide whether an exception raised by the given thread should be
+    /// passed to the process.  If so, this returns the handler along with the
+    /// stack pointer it should run with, and the thread is marked as being in
+    /// its exception handler.  `sp` is the thread's stack pointer at the time
+    /// of the fault, which is used if there's no alternate stack.
+    #[allow(dead_code)]
+    pub fn begin_exception(&mut self, pid: PID, tid: TID, sp: usize) -> Option<(MemoryAddress, usize)> {
+        let process = self.get_process_mut(pid).ok()?;
+        let handler = process.exception_handler?;
+        if process.exception_threads & (1 << tid) != 0 {
+            return None;
+        }
+        let stack = process.exception_stacks.get(tid).copied().flatten();
+        process.exception_threads |= 1 << tid;
+        Some((
+            handler,
+            stack.map(|s| s.as_ptr() as usize + s.len()).unwrap_or(sp),
+        ))
+    }
+
+    /// Print the memory mappings of the given process to the console.  The
+    /// process' address space is switched in so its page tables can be
+    /// walked, and the caller's address space is restored afterwards.
+    #[cfg_attr(
+        not(any(
+            feature = "print-mappings",
+            feature = "debug-print",
+            feature = "print-panics"
+        )),
+        allow(dead_code)
+    )]
+    pub fn print_mappings(&self, pid: PID) -> Result<(), xous_kernel::Error> {
+        let current_mapping = self.get_process(self.current_pid())?.mapping;
+        let mapping = self.get_process(pid)?.mapping;
+        mapping.activate()?;
+        mapping.print_map();
+        #[cfg(feature = "print-mappings")]
+        crate::image::print(pid);
+        current_mapping.activate()
+    }
+
+    /// Print the mappings of every process as `MAP` lines.  This is called
+    /// from the debug console, so processes whose address space can't be
+    /// switched in are skipped rather than reported.
+    #[cfg(all(baremetal, feature = "print-mappings"))]
+    pub fn dump_all_mappings(&self) {
+        let current_mapping = match self.get_process(self.current_pid()) {
+            Ok(process) => process.mapping,
+            Err(_) => return,
+        };
+        for process in self.processes.iter().filter(|p| !p.free()) {
+            if process.mapping.activate().is_ok() {
+                process.mapping.dump_map();
+            }
+        }
+        current_mapping.activate().ok();
+    }
+
+    /// Print every process to the console, along with each of its threads
+    /// that has run or is waiting to.
+    #[cfg(any(not(baremetal), feature = "debug-print", feature = "print-panics"))]
+    pub fn print_processes(&self) {
+        for process in self.processes.iter().filter(|p| !p.free()) {
+            println!(
+                "PID {} (parent {}, priority {}): {:?}",
+                process.pid, process.ppid, process.priority, process.state
+            );
+            for tid in 1..=arch::process::MAX_THREAD {
+                let stats = match self.thread_stats(process.pid, tid) {
+                    Ok(stats) => stats,
+                    Err(_) => continue,
+                };
+                let ready = self.thread_is_ready(process.pid, tid);
+                if stats.schedules == 0 && !ready {
+                    continue;
+                }
+                let state = if ready {
+                    "ready"
+                } else if process.thread_started[tid] != 0 {
+                    "running"
+                } else {
+                    "waiting"
+                };
+                println!(
+                    "    TID {}: {}, scheduled {} times, run time {}",
+                    tid, state, stats.schedules, stats.run_time
+                );
+            }
+        }
+    }
+
+    /// Print every server to the console, along with the index that
+    /// `print_queue()` takes.
+    #[cfg(any(not(baremetal), feature = "debug-print", feature = "print-panics"))]
+    pub fn print_servers(&self) {
+        for (sidx, server) in self.servers.iter().enumerate() {
+            if let Some(server) = server {
+                println!("Server {}: PID {}, SID {:?}", sidx, server.pid, server.sid);
+            }
+        }
+    }
+
+    /// Print the message queue of the server in slot `sidx` to the console.
+    ///
+    /// # Errors
+    ///
+    /// * **ServerNotFound**: There is no server in that slot
+    #[cfg(any(not(baremetal), feature = "debug-print", feature = "print-panics"))]
+    pub fn print_queue(&self, sidx: usize) -> Result<(), xous_kernel::Error> {
+        let server = self
+            .servers
+            .get(sidx)
+            .and_then(|server| server.as_ref())
+            .ok_or(xous_kernel::Error::ServerNotFound)?;
+        println!("Server {}: PID {}, SID {:?}", sidx, server.pid, server.sid);
+        server.print_queue();
+        Ok(())
+    }
+
+    /// Share the image segment that starts at `src` in process `pid` with
+    /// its child `dest_pid`, placing it at the same offset into the
+    /// reservation that starts at `dest_base`.  Read-only pages such as
+    /// library text are mapped into both processes, while writable pages are
+    /// copy-on-write so that each process ends up with its own.
+    ///
+    /// # Errors
+    ///
+    /// * **BadAddress**: There is no segment at `src`, part of it hasn't been
+    ///                   touched yet, or there is no room for it in the
+    ///                   destination reservation
+    /// * **ProcessNotFound**: The destination process doesn't exist
+    /// * **AccessDenied**: The destination isn't a child of `pid`
+    /// * **MemoryInUse**: The segment would overlap one in the destination
+    /// * **ShareViolation**: Part of the segment is currently lent
+    /// * **OutOfMemory**: Too many segments or pages are already shared
+    pub fn share_segment(
+        &mut self,
+        pid: PID,
+        src: usize,
+        dest_pid: PID,
+        dest_base: usize,
+    ) -> Result<MemoryRange, xous_kernel::Error> {
+        let (offset, size, flags) =
+            crate::image::find_segment(pid, src).ok_or(xous_kernel::Error::BadAddress)?;
+        let dest = self
+            .processes
+            .get(dest_pid.get() as usize - 1)
+            .filter(|process| !process.free())
+            .ok_or(xous_kernel::Error::ProcessNotFound)?;
+        if dest_pid == pid || dest.ppid != pid {
+            return Err(xous_kernel::Error::AccessDenied);
+        }
+
+        // Hosted processes manage their own memory, so there's only the
+        // record of the segment to keep.
+        #[cfg(not(baremetal))]
+        if !arch::mem::has_page_tables() {
+            return crate::image::add_segment(dest_pid, dest_base, offset, size, flags);
+        }
+
+        // Pages are only allocated when they are first touched, and there
+        // is nothing to share until then.
+        for page in (src..src + size).step_by(arch::mem::PAGE_SIZE) {
+            arch::mem::virt_to_phys(page)?;
+        }
+
+        let range = crate::image::add_segment(dest_pid, dest_base, offset, size, flags)?;
+
+        let src_mapping = self.get_process(pid)?.mapping;
+        let dest_mapping = dest.mapping;
+        // If this runs out of room partway through, the pages shared so
+        // far stay mapped and the segment stays recorded to match.
+        crate::mem::MemoryManager::with_mut(|mm| {
+            for page in (0..size).step_by(arch::mem::PAGE_SIZE) {
+                mm.share_page_cow(
+                    &src_mapping,
+                    (src + page) as *mut u8,
+                    dest_pid,
+                    &dest_mapping,
+                    (range.addr.get() + page) as *mut u8,
+                )?;
+            }
+            Ok(())
+        })?;
+        Ok(range)
+    }
+
     // pub fn current_thread(&self, pid: PID) -> usize {
     //     self.processes[pid.get() as usize - 1].current_thread as usize
     // }
@@ -576,6 +1154,9 @@ impl SystemServices {
         //     pid, tid, process.state
         // );
 
+        // The thread that ends up running, if this actually switches to one
+        let mut switched_to = None;
+
         // Determine which context number to switch to
         process.state = match process.state {
             ProcessState::Free => return Err(xous_kernel::Error::ProcessNotFound),
@@ -596,6 +1177,7 @@ impl SystemServices {
                 let mut p = crate::arch::process::Process::current();
                 p.setup_thread(INITIAL_TID, setup)?;
                 p.set_thread(INITIAL_TID)?;
+                switched_to = Some(INITIAL_TID);
                 // process.current_thread = INITIAL_TID as u8;
 
                 // Mark the current proces state as "running, and no waiting contexts"
@@ -631,6 +1213,7 @@ impl SystemServices {
                 // FIXME: What happens if this fails? We're currently in the new process
                 // but without a context to switch to.
                 p.set_thread(new_thread)?;
+                switched_to = Some(new_thread);
                 // process.current_thread = new_context as u8;
 
                 // Remove the new context from the available context list
@@ -676,6 +1259,7 @@ impl SystemServices {
                 // Activate this process on this CPU
                 process.activate()?;
                 p.set_thread(new_thread)?;
+                switched_to = Some(new_thread);
                 ProcessState::Running(new_mask)
             }
         };
@@ -683,6 +1267,18 @@ impl SystemServices {
         //     "switch_to_thread({}:{:?}): New state is {:?}",
         //     pid, tid, process.state
         // );
+        if let Some(tid) = switched_to {
+            self.account_switch_in(pid, tid);
+            klog!(
+                Scheduler,
+                Debug,
+                "KERNEL: Switching to PID {} thread {}",
+                pid,
+                tid
+            );
+            #[cfg(feature = "trace")]
+            crate::trace::record(xous_kernel::TraceKind::ContextSwitch, pid, tid, 0);
+        }
         Ok(())
     }
 
@@ -731,9 +1327,178 @@ impl SystemServices {
         //     "switch_from_thread({}:{}): New state is {:?}",
         //     pid, tid, process.state
         // );
+        self.account_switch_out(pid, tid, false);
+        Ok(())
+    }
+
+    /// Note that thread `tid` of process `pid` has just been given the CPU.
+    pub fn account_switch_in(&mut self, pid: PID, tid: TID) {
+        #[cfg(baremetal)]
+        crate::canary::check(pid, tid);
+        let now = crate::arch::timestamp();
+        if let Ok(process) = self.get_process_mut(pid) {
+            if let Some(started) = process.thread_started.get_mut(tid) {
+                // Threads that are switched to while they're already
+                // running haven't really been scheduled again.
+                if *started == 0 {
+                    *started = now;
+                    process.thread_stats[tid].schedules += 1;
+                }
+            }
+        }
+    }
+
+    /// Note that thread `tid` of process `pid` has given up the CPU, and
+    /// charge it for the time it has run since it was switched to.
+    /// `involuntary` is `true` if it was preempted.
+    pub fn account_switch_out(&mut self, pid: PID, tid: TID, involuntary: bool) {
+        #[cfg(baremetal)]
+        crate::canary::check(pid, tid);
+        let now = crate::arch::timestamp();
+        if let Ok(process) = self.get_process_mut(pid) {
+            if let Some(started) = process.thread_started.get_mut(tid) {
+                let stats = &mut process.thread_stats[tid];
+                if *started != 0 {
+                    stats.run_time += now.saturating_sub(*started);
+                    *started = 0;
+                }
+                if involuntary {
+                    stats.involuntary_switches += 1;
+                } else {
+                    stats.voluntary_switches += 1;
+                }
+            }
+        }
+    }
+
+    /// Remember that thread `tid` of process `pid` was run by `caller` using
+    /// `SwitchTo`.  A thread that is switched to again goes back to whoever
+    /// switched to it most recently.
+    pub fn set_switched_from(&mut self, pid: PID, tid: TID, caller: (PID, TID)) {
+        if let Ok(process) = self.get_process_mut(pid) {
+            if let Some(link) = process.switched_from.get_mut(tid) {
+                *link = Some(caller);
+            }
+        }
+    }
+
+    /// Take the thread that used `SwitchTo` to run thread `tid` of process
+    /// `pid`, or `None` if it was scheduled some other way.
+    pub fn take_switched_from(&mut self, pid: PID, tid: TID) -> Option<(PID, TID)> {
+        self.get_process_mut(pid)
+            .ok()?
+            .switched_from
+            .get_mut(tid)?
+            .take()
+    }
+
+    /// Note that thread `tid` of process `pid` has been given a message that
+    /// was queued for its server at `stamp`.
+    pub fn note_message_received(&mut self, pid: PID, tid: TID, stamp: u64) {
+        if let Ok(process) = self.get_process_mut(pid) {
+            if let Some(slot) = process.message_stamps.get_mut(tid) {
+                *slot = stamp;
+            }
+        }
+    }
+
+    /// When the message that thread `tid` of process `pid` most recently
+    /// received was queued, or `0` if it hasn't received one.
+    pub fn message_timestamp(&self, pid: PID, tid: TID) -> Result<u64, xous_kernel::Error> {
+        self.get_process(pid)?
+            .message_stamps
+            .get(tid)
+            .copied()
+            .ok_or(xous_kernel::Error::InvalidThread)
+    }
+
+    /// Get the scheduler statistics for thread `tid` of process `pid`,
+    /// including the time it has spent in its current run.
+    pub fn thread_stats(&self, pid: PID, tid: TID) -> Result<ThreadStats, xous_kernel::Error> {
+        let process = self
+            .processes
+            .get(pid.get() as usize - 1)
+            .filter(|process| !process.free())
+            .ok_or(xous_kernel::Error::ProcessNotFound)?;
+        let mut stats = *process
+            .thread_stats
+            .get(tid)
+            .ok_or(xous_kernel::Error::InvalidThread)?;
+        let started = process.thread_started[tid];
+        if started != 0 {
+            stats.run_time += crate::arch::timestamp().saturating_sub(started);
+        }
+        Ok(stats)
+    }
+
+    /// Describe the server in slot `sidx` on behalf of `pid`, which must hold
+    /// `Capabilities::SUPERVISE`.  Connections are counted by looking
+    /// through every process' connection map.
+    ///
+    /// # Errors
+    ///
+    /// * **AccessDenied**: `pid` doesn't hold `Capabilities::SUPERVISE`
+    /// * **ServerNotFound**: There is no server in that slot
+    /// * **InvalidSyscall**: The slot is past the end of the table
+    pub fn server_info(
+        &self,
+        pid: PID,
+        sidx: usize,
+    ) -> Result<xous_kernel::ServerInfo, xous_kernel::Error> {
+        self.check_capability(pid, Capabilities::SUPERVISE)?;
+        let server = self
+            .servers
+            .get(sidx)
+            .ok_or(xous_kernel::Error::InvalidSyscall)?
+            .as_ref()
+            .ok_or(xous_kernel::Error::ServerNotFound)?;
+
+        // Connection map entries are offset by two, because 0 == free and
+        // 1 == "tombstone".
+        let current_pid = self.current_pid();
+        let mut connections = 0;
+        for process in self.processes.iter().filter(|process| !process.free()) {
+            process.activate()?;
+            if ArchProcess::with_inner(|process_inner| {
+                process_inner
+                    .connection_map
+                    .iter()
+                    .flatten()
+                    .any(|mapping| mapping.get() == sidx as u8 + 2)
+            }) {
+                connections += 1;
+            }
+        }
+        self.get_process(current_pid)?.activate()?;
+        Ok(server.info(connections))
+    }
+
+    /// Make sure process `pid` exists and `tid` could be one of its threads.
+    ///
+    /// # Errors
+    ///
+    /// * **ProcessNotFound**: The process doesn't exist
+    /// * **InvalidThread**: The thread ID is out of range
+    pub fn check_thread(&self, pid: PID, tid: TID) -> Result<(), xous_kernel::Error> {
+        self.processes
+            .get(pid.get() as usize - 1)
+            .filter(|process| !process.free())
+            .ok_or(xous_kernel::Error::ProcessNotFound)?;
+        if tid == 0 || tid > arch::process::MAX_THREAD {
+            return Err(xous_kernel::Error::InvalidThread);
+        }
         Ok(())
     }
 
+    /// Whether thread `tid` of process `pid` is waiting to be run.
+    pub fn thread_is_ready(&self, pid: PID, tid: TID) -> bool {
+        match self.processes.get(pid.get() as usize - 1).map(|p| p.state) {
+            Some(ProcessState::Setup(_)) => tid == INITIAL_TID,
+            Some(ProcessState::Ready(x)) | Some(ProcessState::Running(x)) => x & (1 << tid) != 0,
+            _ => false,
+        }
+    }
+
     pub fn thread_is_running(&self, pid: PID, tid: TID) -> bool {
         let process = self.get_process(pid).unwrap();
         if let ProcessState::Running(thread_ids) = process.state {
@@ -758,6 +1523,16 @@ impl SystemServices {
         tid: TID,
         result: xous_kernel::Result,
     ) -> Result<(), xous_kernel::Error> {
+        #[cfg(feature = "trace")]
+        if let xous_kernel::Result::Message(envelope) = &result {
+            crate::trace::record(
+                xous_kernel::TraceKind::MessageReceived,
+                pid,
+                tid,
+                envelope.sender,
+            );
+        }
+
         // Temporarily switch into the target process memory space
         // in order to pass the return value.
         let current_pid = self.current_pid();
@@ -953,6 +1728,8 @@ impl SystemServices {
         // self.processes[new_pid.get() as usize - 1].current_thread = new_tid as u8;
         // let _ctx = process.current_context();
 
+        self.account_switch_out(previous_pid, previous_tid, false);
+        self.account_switch_in(new_pid, new_tid);
         Ok(new_tid)
     }
 
@@ -968,7 +1745,8 @@ impl SystemServices {
     /// once.
     ///
     /// The given memory range is guaranteed to be unavailable in the src process
-    /// after this function returns.
+    /// after this function returns successfully.  If it returns an error, then
+    /// neither process has changed.
     ///
     /// # Returns
     ///
@@ -980,12 +1758,9 @@ impl SystemServices {
     ///   shared
     /// * **BadAddress**: The provided address was not valid
     /// * **BadAlignment**: The provided address or length was not page-aligned
-    ///
-    /// # Panics
-    ///
-    /// If the memory should have been able to go into the destination process
-    /// but failed, then the system panics.
-    #[cfg(baremetal)]
+    /// * **MemoryInUse**: Part of the region doesn't belong to the src process
+    /// * **OutOfMemory**: The destination process's pagetables couldn't be
+    ///   allocated
     pub fn send_memory(
         &mut self,
         src_virt: *mut u8,
@@ -993,6 +1768,12 @@ impl SystemServices {
         dest_virt: *mut u8,
         len: usize,
     ) -> Result<*mut u8, xous_kernel::Error> {
+        // Hosted processes send the memory itself over their connection.
+        #[cfg(not(baremetal))]
+        if !arch::mem::has_page_tables() {
+            return Ok(src_virt);
+        }
+
         if len == 0 {
             return Err(xous_kernel::Error::BadAddress);
         }
@@ -1022,36 +1803,12 @@ impl SystemServices {
                 .activate()
                 .expect("Couldn't switch back to source mapping");
 
-            let mut error = None;
-
-            // Lend each subsequent page.
-            for offset in (0..(len / core::mem::size_of::<usize>()))
-                .step_by(crate::mem::PAGE_SIZE / core::mem::size_of::<usize>())
-            {
-                mm.move_page(
-                    &src_mapping,
-                    src_virt.wrapping_add(offset),
-                    dest_pid,
-                    &dest_mapping,
-                    dest_virt.wrapping_add(offset),
-                )
-                .unwrap_or_else(|e| error = Some(e));
-            }
-            error.map_or_else(|| Ok(dest_virt), |e| panic!("unable to send: {:?}", e))
+            // Move the whole range at once.
+            mm.move_range(&src_mapping, src_virt, dest_pid, &dest_mapping, dest_virt, len)
+                .map(|_| dest_virt)
         })
     }
 
-    #[cfg(not(baremetal))]
-    pub fn send_memory(
-        &mut self,
-        src_virt: *mut u8,
-        _dest_pid: PID,
-        _dest_virt: *mut u8,
-        _len: usize,
-    ) -> Result<*mut u8, xous_kernel::Error> {
-        Ok(src_virt)
-    }
-
     /// Lend memory from one process to another.
     ///
     /// During this process, memory is marked as `Shared` in the source process.
@@ -1077,7 +1834,8 @@ impl SystemServices {
     ///   shared
     /// * **BadAddress**: The provided address was not valid
     /// * **BadAlignment**: The provided address or length was not page-aligned
-    #[cfg(baremetal)]
+    /// * **OutOfMemory**: The destination process's pagetables couldn't be
+    ///   allocated, in which case neither process has changed
     pub fn lend_memory(
         &mut self,
         src_virt: *mut u8,
@@ -1086,6 +1844,12 @@ impl SystemServices {
         len: usize,
         mutable: bool,
     ) -> Result<*mut u8, xous_kernel::Error> {
+        // Hosted processes send the memory itself over their connection.
+        #[cfg(not(baremetal))]
+        if !arch::mem::has_page_tables() {
+            return Ok(src_virt);
+        }
+
         if len == 0 {
             return Err(xous_kernel::Error::BadAddress);
         }
@@ -1114,41 +1878,118 @@ impl SystemServices {
                 })?;
             src_mapping.activate().unwrap();
 
-            let mut error = None;
+            // Lend the whole range at once.
+            mm.lend_range(
+                &src_mapping,
+                src_virt,
+                dest_pid,
+                &dest_mapping,
+                dest_virt,
+                len,
+                mutable,
+            )
+            .map(|_| dest_virt)
+        })
+    }
 
-            // Lend each subsequent page.
-            for offset in (0..(len / core::mem::size_of::<usize>()))
-                .step_by(crate::mem::PAGE_SIZE / core::mem::size_of::<usize>())
-            {
-                mm.lend_page(
+    /// Lend the pieces of a scattered message from thread `src_tid` of the
+    /// current process to `dest_pid`, mapped one after another as a single
+    /// buffer of `len` bytes.  The message's `ScatterList` is read from
+    /// `list` in the current process, and its pieces are remembered so that
+    /// `return_memory()` can put each one back where it came from.
+    ///
+    /// # Returns
+    ///
+    /// Returns the virtual address of the memory region in the target process.
+    ///
+    /// # Errors
+    ///
+    /// * **BadAddress**: The list couldn't be read, its pieces don't add up
+    ///   to `len`, or a piece isn't mapped
+    /// * **BadAlignment**: A piece wasn't page-aligned
+    /// * **OutOfMemory**: The list had too many pieces, or too many scattered
+    ///   lends are already outstanding
+    /// * **ShareViolation**: Two pieces overlap, or a piece is already lent
+    /// * **InvalidSyscall**: Hosted processes gather the pieces together
+    ///   before sending them, so the kernel doesn't take scattered lends from
+    ///   them unless it's simulating their pagetables
+    pub fn lend_scattered_memory(
+        &mut self,
+        list: *const u8,
+        src_tid: TID,
+        dest_pid: PID,
+        len: usize,
+        mutable: bool,
+    ) -> Result<*mut u8, xous_kernel::Error> {
+        #[cfg(not(baremetal))]
+        if !arch::mem::has_page_tables() {
+            return Err(xous_kernel::Error::InvalidSyscall);
+        }
+
+        let scatter = crate::scatter::read(list as *const xous_kernel::ScatterList)?;
+        if scatter.len() != len {
+            return Err(xous_kernel::Error::BadAddress);
+        }
+
+        let current_pid = self.current_pid();
+        let src_mapping = self.get_process(current_pid)?.mapping;
+        let dest_mapping = self.get_process(dest_pid)?.mapping;
+        use crate::mem::MemoryManager;
+        MemoryManager::with_mut(|mm| {
+            // Locate an address to fit all of the pieces.
+            dest_mapping.activate()?;
+            let dest_virt = mm
+                .find_virtual_address(
+                    core::ptr::null_mut(),
+                    len,
+                    xous_kernel::MemoryType::Messages,
+                )
+                .inspect_err(|_| src_mapping.activate().unwrap())?;
+            src_mapping.activate().unwrap();
+
+            crate::scatter::lent(current_pid, src_tid, scatter)?;
+            let mut offset = 0;
+            for range in scatter.iter() {
+                let result = mm.lend_range(
                     &src_mapping,
-                    src_virt.wrapping_add(offset),
+                    range.as_mut_ptr(),
                     dest_pid,
                     &dest_mapping,
                     dest_virt.wrapping_add(offset),
+                    range.len(),
                     mutable,
-                )
-                .unwrap_or_else(|e| {
-                    error = Some(e);
-                    0
-                });
+                );
+                if let Err(e) = result {
+                    // `lend_range()` leaves a piece it can't lend untouched,
+                    // so only the pieces before it need to be put back.
+                    crate::scatter::returned(current_pid, src_tid);
+                    let mut returned = 0;
+                    for range in scatter.iter() {
+                        if returned >= offset {
+                            break;
+                        }
+                        for page in (0..range.len()).step_by(crate::mem::PAGE_SIZE) {
+                            dest_mapping.activate().unwrap();
+                            mm.unlend_page(
+                                &dest_mapping,
+                                dest_virt.wrapping_add(returned + page),
+                                current_pid,
+                                &src_mapping,
+                                range.as_mut_ptr().wrapping_add(page),
+                            )
+                            .expect("lent piece wasn't mapped in the server");
+                        }
+                        returned += range.len();
+                    }
+                    src_mapping.activate().unwrap();
+                    return Err(e);
+                }
+                offset += range.len();
             }
-            error.map_or_else(|| Ok(dest_virt), |e| panic!("unable to lend: {:?}", e))
+            Ok(dest_virt)
         })
     }
 
-    #[cfg(not(baremetal))]
-    pub fn lend_memory(
-        &mut self,
-        src_virt: *mut u8,
-        _dest_pid: PID,
-        _dest_virt: *mut u8,
-        _len: usize,
-        _mutable: bool,
-    ) -> Result<*mut u8, xous_kernel::Error> {
-        Ok(src_virt)
-    }
-
     /// Return memory from one process back to another
     ///
     /// During this process, memory is unmapped from the source process.
@@ -1160,16 +2001,53 @@ impl SystemServices {
     /// # Errors
     ///
     /// * **ShareViolation**: Tried to mutably share a region that was already shared
-    #[cfg(baremetal)]
     pub fn return_memory(
         &mut self,
         src_virt: *mut u8,
         _src_tid: TID,
         dest_pid: PID,
-        _dest_tid: TID,
+        dest_tid: TID,
         dest_virt: *mut u8,
         len: usize,
     ) -> Result<*mut u8, xous_kernel::Error> {
+        // Hosted processes are sent back a copy of the memory, since it
+        // belongs to them rather than the kernel.
+        #[cfg(not(baremetal))]
+        if !arch::mem::has_page_tables() {
+            let buf = unsafe { core::slice::from_raw_parts(src_virt, len) };
+            let current_pid = self.current_pid();
+            {
+                let target_process = self.get_process(dest_pid)?;
+                target_process.activate()?;
+                let mut arch_process = crate::arch::process::Process::current();
+                arch_process.return_memory(dest_tid, buf);
+            }
+            let target_process = self.get_process(current_pid)?;
+            target_process.activate()?;
+
+            return Ok(src_virt);
+        }
+
+        // A scattered lend goes back to each of its pieces in turn.
+        if let Some(scatter) = crate::scatter::returned(dest_pid, dest_tid) {
+            let mut offset = 0;
+            let mut result = Ok(dest_virt);
+            for range in scatter.iter() {
+                if let Err(e) = self.return_memory(
+                    src_virt.wrapping_add(offset),
+                    0,
+                    dest_pid,
+                    dest_tid,
+                    range.as_mut_ptr(),
+                    range.len(),
+                ) {
+                    result = Err(e);
+                }
+                offset += range.len();
+            }
+            return result;
+        }
+
         if len == 0 {
             return Err(xous_kernel::Error::BadAddress);
         }
@@ -1210,27 +2088,80 @@ impl SystemServices {
         })
     }
 
-    #[cfg(not(baremetal))]
-    pub fn return_memory(
+    /// Pass memory that the current process was lent by thread `client_tid`
+    /// of `client_pid` on to `dest_pid`, as though the client had lent it
+    /// there directly.  The memory is handed back to the client and then lent
+    /// from the client to the new process, so the current process no longer
+    /// has access to it.  If the new process can't take it, the memory is
+    /// lent back to the current process where it was before.
+    ///
+    /// # Returns
+    ///
+    /// Returns the virtual address of the memory region in the target process.
+    ///
+    /// # Errors
+    ///
+    /// * **ShareViolation**: Tried to pass on memory mutably that the current
+    ///   process was only lent immutably
+    /// * **BadAddress**: The provided address was not valid
+    /// * **InvalidSyscall**: The memory was a scattered lend, which can't be
+    ///   forwarded
+    #[cfg(baremetal)]
+    #[allow(clippy::too_many_arguments)]
+    pub fn forward_memory(
         &mut self,
         src_virt: *mut u8,
-        _src_tid: TID,
+        client_pid: PID,
+        client_tid: TID,
+        client_virt: *mut u8,
         dest_pid: PID,
-        dest_tid: TID,
-        _dest_virt: *mut u8,
         len: usize,
+        mutable: bool,
     ) -> Result<*mut u8, xous_kernel::Error> {
-        let buf = unsafe { core::slice::from_raw_parts(src_virt, len) };
-        let current_pid = self.current_pid();
-        {
-            let target_process = self.get_process(dest_pid)?;
-            target_process.activate()?;
-            let mut arch_process = crate::arch::process::Process::current();
-            arch_process.return_memory(dest_tid, buf);
+        if crate::scatter::is_scattered(client_pid, client_tid) {
+            return Err(xous_kernel::Error::InvalidSyscall);
         }
-        let target_process = self.get_process(current_pid)?;
-        target_process.activate()?;
+        let was_mutable = (src_virt as usize..src_virt as usize + len)
+            .step_by(crate::mem::PAGE_SIZE)
+            .all(crate::arch::mem::page_is_writable);
+        if mutable && !was_mutable {
+            return Err(xous_kernel::Error::ShareViolation);
+        }
+        let current_pid = self.current_pid();
+        self.return_memory(src_virt, 0, client_pid, client_tid, client_virt, len)?;
+
+        // Memory is always lent from the current address space.
+        self.get_process(client_pid)?.activate()?;
+        let result = self
+            .lend_memory(
+                client_virt,
+                dest_pid,
+                core::ptr::null_mut(),
+                len,
+                mutable,
+            )
+            .or_else(|e| {
+                // Put the memory back so the current process still holds
+                // the message it was trying to pass on.
+                self.lend_memory(client_virt, current_pid, src_virt, len, was_mutable)?;
+                Err(e)
+            });
+        self.get_process(current_pid)?.activate()?;
+        result
+    }
 
+    #[cfg(not(baremetal))]
+    #[allow(clippy::too_many_arguments)]
+    pub fn forward_memory(
+        &mut self,
+        src_virt: *mut u8,
+        _client_pid: PID,
+        _client_tid: TID,
+        _client_virt: *mut u8,
+        _dest_pid: PID,
+        _len: usize,
+        _mutable: bool,
+    ) -> Result<*mut u8, xous_kernel::Error> {
         Ok(src_virt)
     }
 
@@ -1245,6 +2176,8 @@ impl SystemServices {
     ///
     /// * **ThreadNotAvailable**: The process has used all of its context
     ///   slots.
+    /// * **LimitReached**: The process already has as many threads as its
+    ///   quota allows.
     pub fn create_thread(
         &mut self,
         pid: PID,
@@ -1254,11 +2187,32 @@ impl SystemServices {
         process.activate()?;
 
         let mut arch_process = crate::arch::process::Process::current();
+        if arch_process.thread_count() >= process.quotas.threads {
+            return Err(xous_kernel::Error::LimitReached);
+        }
         let new_tid = arch_process
             .find_free_thread()
             .ok_or(xous_kernel::Error::ThreadNotAvailable)?;
 
+        #[cfg(baremetal)]
+        let stack = thread_init.stack;
         arch_process.setup_thread(new_tid, thread_init)?;
+        #[cfg(baremetal)]
+        crate::canary::place(pid, new_tid, &stack);
+        if let Some(stack) = process.exception_stacks.get_mut(new_tid) {
+            *stack = None;
+        }
+        process.exception_threads &= !(1 << new_tid);
+        if let Some(stats) = process.thread_stats.get_mut(new_tid) {
+            *stats = ThreadStats::default();
+        }
+        if let Some(started) = process.thread_started.get_mut(new_tid) {
+            *started = 0;
+        }
+        if let Some(caller) = process.switched_from.get_mut(new_tid) {
+            *caller = None;
+        }
+        crate::deadline::clear(pid, new_tid);
 
         // println!("KERNEL({}): Created new thread {}", pid, new_tid);
 
@@ -1280,42 +2234,68 @@ impl SystemServices {
 
     /// Allocate a new server ID for this process and return the address. If the
     /// server table is full, or if there is not enough memory to map the server queue,
-    /// return an error.
+    /// return an error.  A `sid` of all zeroes asks for an unguessable ID from the
+    /// kernel's random number generator instead.
     ///
     /// # Errors
     ///
-    /// * **OutOfMemory**: A new page could not be assigned to store the server
-    ///   queue.
+    /// * **OutOfMemory**: New pages could not be assigned to store the server
+    ///   queue, or the queue would be longer than `MAX_QUEUE_LENGTH`.
+    /// * **InvalidSyscall**: The queue would have no slots.
     /// * **ServerNotFound**: The server queue was full and a free slot could not
     ///   be found.
-    pub fn create_server(&mut self, pid: PID, sid: SID) -> Result<(SID, CID), xous_kernel::Error> {
+    /// * **UnhandledSyscall**: A random ID was asked for, but the random number
+    ///   generator has never been seeded.
+    /// * **LimitReached**: The process already has as many servers as its
+    ///   quota allows.
+    /// * **AccessDenied**: The process wasn't started by PID 1, and only those
+    ///   processes may start servers for now.
+    pub fn create_server(
+        &mut self,
+        pid: PID,
+        sid: SID,
+        queue_length: usize,
+    ) -> Result<(SID, CID), xous_kernel::Error> {
         // println!(
         //     "KERNEL({}): Looking through server list for free server",
         //     self.pid.get()
         // );
 
-        // TODO: Come up with a way to randomize the server ID
         let ppid = self.get_process(pid)?.ppid.get();
         if ppid != 1 {
-            panic!(
-                "KERNEL({}): Non-PID1 processes cannot start servers yet",
-                pid.get()
-            );
+            return Err(xous_kernel::Error::AccessDenied);
+        }
+
+        let owned = self
+            .servers
+            .iter()
+            .flatten()
+            .filter(|server| server.pid == pid)
+            .count();
+        if owned >= self.quotas(pid).servers {
+            return Err(xous_kernel::Error::LimitReached);
         }
 
+        let sid = if sid == SID::from_u32(0, 0, 0, 0) {
+            self.random_sid()?
+        } else {
+            sid
+        };
+
+        let backing_size = Server::backing_size(queue_length)?;
         for entry in self.servers.iter_mut() {
             if entry == &None {
                 #[cfg(baremetal)]
-                // Allocate a single page for the server queue
+                // Allocate enough pages for the server queue
                 let backing = crate::mem::MemoryManager::with_mut(|mm| {
                     MemoryRange::new(
-                        mm.map_zeroed_page(pid, false)? as _,
-                        crate::arch::mem::PAGE_SIZE,
+                        mm.map_zeroed_pages(pid, backing_size, false)? as _,
+                        backing_size,
                     )
                 })?;
 
                 #[cfg(not(baremetal))]
-                let backing = MemoryRange::new(4096, 4096).unwrap();
+                let backing = MemoryRange::new(4096, backing_size).unwrap();
                 // println!(
                 //     "KERNEL({}): Found a free slot for server {:?} @ {} -- allocating an entry",
                 //     pid.get(),
@@ -1323,25 +2303,109 @@ impl SystemServices {
                 //     _idx,
                 // );
 
-                // Initialize the server with the given memory page.
-                Server::init(entry, pid, sid, backing).map_err(|x| x)?;
+                // Initialize the server with the given memory.
+                Server::init(entry, pid, sid, backing, queue_length).map_err(|x| x)?;
 
-                let cid = self.connect_to_server(sid)?;
+                let cid = self.connect_to_own_server(sid)?;
                 return Ok((sid, cid));
             }
         }
         Err(xous_kernel::Error::ServerNotFound)
     }
 
+    /// Pick a server ID that nobody could guess, and that isn't in use.
+    fn random_sid(&mut self) -> Result<SID, xous_kernel::Error> {
+        loop {
+            let mut bytes = [0u8; 16];
+            crate::entropy::fill(&mut bytes)?;
+            let sid = SID::from_bytes(&bytes).ok_or(xous_kernel::Error::InternalError)?;
+            if sid != SID::from_u32(0, 0, 0, 0) && self.server_sidx(sid).is_none() {
+                return Ok(sid);
+            }
+        }
+    }
+
     /// Allocate a new server ID for this process and return the address. If the
     /// server table is full, return an error.
+    ///
+    /// # Errors
+    ///
+    /// * **AccessDenied**: The server is private, and hasn't allowed the
+    ///   current process to connect
+    /// * **LimitReached**: The process already has as many connections as
+    ///   its quota allows
     pub fn connect_to_server(&mut self, sid: SID) -> Result<CID, xous_kernel::Error> {
+        let pid = crate::arch::process::current_pid();
+        let sidx = self.server_sidx(sid);
+        if let Some(server) = sidx.and_then(|sidx| self.servers[sidx].as_ref()) {
+            if !server.may_connect(pid) {
+                return Err(xous_kernel::Error::AccessDenied);
+            }
+        }
+        self.connect_with_refs(sid, 1)
+    }
+
+    /// Make server `sid`, which belongs to process `pid`, private, so that
+    /// only processes it allows may connect to it from now on.
+    ///
+    /// # Errors
+    ///
+    /// * **ServerNotFound**: `pid` doesn't have a server called `sid`
+    pub fn make_server_private(&mut self, pid: PID, sid: SID) -> Result<(), xous_kernel::Error> {
+        self.owned_server_mut(pid, sid)?.make_private();
+        Ok(())
+    }
+
+    /// Let process `client` connect to server `sid`, which belongs to
+    /// process `pid`, even once it is private.
+    ///
+    /// # Errors
+    ///
+    /// * **ServerNotFound**: `pid` doesn't have a server called `sid`
+    /// * **ProcessNotFound**: `client` isn't running
+    /// * **OutOfMemory**: The server already allows as many processes as it can
+    pub fn allow_connect(
+        &mut self,
+        pid: PID,
+        sid: SID,
+        client: PID,
+    ) -> Result<(), xous_kernel::Error> {
+        if self
+            .processes
+            .get(client.get() as usize - 1)
+            .map(|process| process.free())
+            .unwrap_or(true)
+        {
+            return Err(xous_kernel::Error::ProcessNotFound);
+        }
+        self.owned_server_mut(pid, sid)?.allow_client(client)
+    }
+
+    fn owned_server_mut(&mut self, pid: PID, sid: SID) -> Result<&mut Server, xous_kernel::Error> {
+        let sidx = self
+            .server_sidx(sid)
+            .ok_or(xous_kernel::Error::ServerNotFound)?;
+        self.servers[sidx]
+            .as_mut()
+            .filter(|server| server.pid == pid)
+            .ok_or(xous_kernel::Error::ServerNotFound)
+    }
+
+    /// Connect the current process to a server that it owns.  Replies to the
+    /// server's messages are routed through this connection, so it is pinned
+    /// and can never be disconnected.
+    pub fn connect_to_own_server(&mut self, sid: SID) -> Result<CID, xous_kernel::Error> {
+        self.connect_with_refs(sid, u8::MAX)
+    }
+
+    fn connect_with_refs(&mut self, sid: SID, refs: u8) -> Result<CID, xous_kernel::Error> {
         // Check to see if we've already connected to this server.
         // While doing this, find a free slot in case we haven't
         // yet connected.
 
         // let _pid = crate::arch::process::current_pid();
         // println!("KERNEL({}): Server table: {:?}", _pid.get(), self.servers);
+        let quota = self.quotas(crate::arch::process::current_pid()).connections;
         ArchProcess::with_inner_mut(|process_inner| {
             let mut slot_idx = None;
             // Look through the connection map for (1) a free slot, and (2) an
@@ -1355,8 +2419,14 @@ impl SystemServices {
                     continue;
                 }
 
+                // Tombstones can't match, since their server is gone.
+                let server_idx = server_idx.unwrap().get() as usize;
+                if server_idx < 2 {
+                    continue;
+                }
+
                 // If a connection to this server ID exists already, return it.
-                let server_idx = (server_idx.unwrap().get() as usize) - 2;
+                let server_idx = server_idx - 2;
                 if let Some(allocated_server) = &self.servers[server_idx] {
                     if allocated_server.sid == sid {
                         // println!("KERNEL({}): Existing connection to SID {:?} found in this process @ {}, process connection map is: {:?}",
@@ -1365,18 +2435,27 @@ impl SystemServices {
                         //     (connection_idx as CID) + 2,
                         //     process_inner.connection_map,
                         // );
+                        let count = &mut process_inner.connection_refs[connection_idx];
+                        *count = count.saturating_add(refs);
                         return Ok((connection_idx as CID) + 2);
                     }
                 }
             }
             let slot_idx = slot_idx.ok_or_else(|| Error::OutOfMemory)?;
 
+            // Only a new connection counts against the quota.
+            let open = process_inner.connection_map.iter().flatten().count();
+            if open >= quota {
+                return Err(Error::LimitReached);
+            }
+
             // Look through all servers for one whose SID matches.
             for (server_idx, server) in self.servers.iter().enumerate() {
                 if let Some(allocated_server) = server {
                     if allocated_server.sid == sid {
                         process_inner.connection_map[slot_idx] =
                             Some(NonZeroU8::new((server_idx as u8) + 2).unwrap());
+                        process_inner.connection_refs[slot_idx] = refs;
                         // println!(
                         //     "KERNEL({}): New connection to {:?}. After connection, process connection map is: {:?}",
                         //     _pid.get(),
@@ -1391,6 +2470,43 @@ impl SystemServices {
         })
     }
 
+    /// Drop a reference to connection `cid` of the current process, freeing
+    /// the Connection ID once nothing refers to it any more.
+    pub fn disconnect_from_server(&mut self, cid: CID) -> Result<(), xous_kernel::Error> {
+        let pid = crate::arch::process::current_pid();
+        let closed = ArchProcess::with_inner_mut(|process_inner| {
+            let idx = cid
+                .checked_sub(2)
+                .filter(|idx| *idx < process_inner.connection_map.len())
+                .ok_or(xous_kernel::Error::ServerNotFound)?;
+            if process_inner.connection_map[idx].is_none() {
+                return Err(xous_kernel::Error::ServerNotFound);
+            }
+
+            let count = &mut process_inner.connection_refs[idx];
+            if *count == u8::MAX {
+                return Ok(None);
+            }
+            *count = count.saturating_sub(1);
+            if *count == 0 {
+                return Ok(process_inner.connection_map[idx].take());
+            }
+            Ok(None)
+        })?;
+
+        // Once the connection is gone, the server has nothing to attach data
+        // to.  Tombstones have no server to tell.
+        if let Some(server) = closed
+            .and_then(|server_idx| (server_idx.get() as usize).checked_sub(2))
+            .and_then(|sidx| self.servers.get_mut(sidx))
+            .and_then(|server| server.as_mut())
+        {
+            server.forget_connection_data(pid);
+            server.forget_disconnect_watcher(pid);
+        }
+        Ok(())
+    }
+
     /// Return a server based on the connection id and the current process
     pub fn server_from_sidx(&self, sidx: usize) -> Option<&Server> {
         if sidx > self.servers.len() {
@@ -1426,8 +2542,9 @@ impl SystemServices {
                 return None;
             }
             let mut server_idx = process_inner.connection_map[cid]?.get() as usize;
+            // A tombstone, left behind when the server's process terminated.
             if server_idx < 2 {
-                panic!("KERNEL: CID {} is invalid", cid + 2);
+                return None;
             }
             server_idx -= 2;
             if server_idx >= self.servers.len() {
@@ -1481,16 +2598,20 @@ impl SystemServices {
                 .ok_or(xous_kernel::Error::ServerNotFound)?;
             let server_pid = server.pid;
             let sid = server.sid;
+            // Hosted processes don't have their own address spaces, so the
+            // connection table that gets used depends on the current PID.
             {
                 let server_process = self.get_process(server_pid)?;
                 server_process.mapping.activate().unwrap();
+                crate::arch::process::set_current_pid(server_pid);
             }
-            self.connect_to_server(sid)
+            self.connect_to_own_server(sid)
         };
         let current_process = self
             .get_process(current_pid)
             .expect("couldn't restore previous process");
         current_process.mapping.activate()?;
+        crate::arch::process::set_current_pid(current_pid);
         result
     }
 
@@ -1531,6 +2652,135 @@ impl SystemServices {
         None
     }
 
+    /// Signal notification `id` `count` times, waking up the threads that
+    /// were waiting on it.
+    ///
+    /// # Errors
+    ///
+    /// * **InvalidSyscall**: The notification doesn't exist
+    pub fn signal_notification(
+        &mut self,
+        id: usize,
+        count: usize,
+    ) -> Result<(), xous_kernel::Error> {
+        let (owner, woken) = crate::notify::signal(id, count)?;
+        for &waiter in woken.iter().flatten() {
+            // If the waiter can't be woken, keep its signal around for the
+            // next thread to wait instead of losing it.
+            let woke = self
+                .ready_thread(owner, waiter)
+                .and_then(|_| self.switch_to_thread(owner, Some(waiter)))
+                .and_then(|_| self.set_thread_result(owner, waiter, xous_kernel::Result::Ok));
+            if woke.is_err() {
+                crate::notify::credit(id);
+            }
+        }
+        Ok(())
+    }
+
+    /// Tear down server `sidx`, whose process is terminating.  Clients that
+    /// are blocked on it are given back any memory they lent and woken up
+    /// with `ServerNotFound`, clients watching it are signalled, and its
+    /// broadcast subscriptions and timers are dropped.  The server's process
+    /// must be the current one.
+    fn destroy_server(&mut self, sidx: usize) -> Result<(), xous_kernel::Error> {
+        let (server_pid, sid) = match &self.servers[sidx] {
+            Some(server) => (server.pid, server.sid),
+            None => return Ok(()),
+        };
+        crate::broadcast::forget_server(sid);
+        crate::timers::forget_server(sid);
+        while let Some((client_pid, client_tid, lent)) = self.servers[sidx]
+            .as_mut()
+            .and_then(|server| server.take_blocked_client())
+        {
+            // The server's own threads are going away along with it.
+            if client_pid == server_pid {
+                continue;
+            }
+            if let Some((server_addr, client_addr, len)) = lent {
+                self.return_memory(
+                    server_addr as *mut u8,
+                    0,
+                    client_pid,
+                    client_tid,
+                    client_addr as *mut u8,
+                    len,
+                )
+                .ok();
+            }
+            self.ready_thread(client_pid, client_tid)
+                .and_then(|_| self.switch_to_thread(client_pid, Some(client_tid)))
+                .and_then(|_| {
+                    self.set_thread_result(
+                        client_pid,
+                        client_tid,
+                        xous_kernel::Result::Error(xous_kernel::Error::ServerNotFound),
+                    )
+                })
+                .ok();
+            self.get_process(server_pid)?.activate()?;
+        }
+        while let Some((_, notification)) = self.servers[sidx]
+            .as_mut()
+            .and_then(|server| server.take_disconnect_watcher())
+        {
+            self.signal_notification(notification, 1).ok();
+        }
+        self.get_process(server_pid)?.activate()?;
+        Server::destroy(&mut self.servers[sidx])
+    }
+
+    /// Take back the memory that thread `client_tid` of `client_pid` lent to
+    /// `server_pid`, because the server has held it for longer than the
+    /// client allows, and wake the client with a `Timeout` error.  The memory
+    /// is unmapped from the server, so the server will fault if it touches it
+    /// again.
+    ///
+    /// # Errors
+    ///
+    /// * **ServerNotFound**: The server isn't holding memory from that thread
+    #[cfg(baremetal)]
+    pub fn revoke_lend(
+        &mut self,
+        server_pid: PID,
+        client_pid: PID,
+        client_tid: TID,
+    ) -> Result<(), xous_kernel::Error> {
+        let (server_addr, client_addr, len) = self
+            .servers
+            .iter_mut()
+            .flatten()
+            .filter(|server| server.pid == server_pid)
+            .find_map(|server| server.take_lend(client_pid, client_tid))
+            .ok_or(xous_kernel::Error::ServerNotFound)?;
+
+        // The memory has to be taken back from within the server's address
+        // space, which may not be the one that was interrupted.
+        let current_pid = self.current_pid();
+        self.get_process(server_pid)?.activate()?;
+        let result = self
+            .return_memory(
+                server_addr as *mut u8,
+                0,
+                client_pid,
+                client_tid,
+                client_addr as *mut u8,
+                len,
+            )
+            .and_then(|_| self.ready_thread(client_pid, client_tid))
+            .and_then(|_| {
+                self.set_thread_result(
+                    client_pid,
+                    client_tid,
+                    xous_kernel::Result::Error(xous_kernel::Error::Timeout),
+                )
+            });
+        self.update_priorities();
+        self.get_process(current_pid)?.activate()?;
+        result
+    }
+
     /// Terminate the given process. Returns the process' parent PID.
     pub fn terminate_process(&mut self, target_pid: PID) -> Result<PID, xous_kernel::Error> {
         // To terminate a process, we must perform the following:
@@ -1540,15 +2790,18 @@ impl SystemServices {
         // 3. If there are any incoming server requests queued, dequeue them and return an error
         // 4. Mark all "Borrowed" memory as "Free-when-returned". That way, if we've shared
         //    memory to a Server, it will be reclaimed by the system when it comes back
+        // 5. Wake up any clients blocked on our servers, and destroy the servers so
+        //    that a restarted process can create them again
 
         // 1. Find all servers associated with this PID and remove them.
+        let processes = &self.processes;
         for (idx, server) in self.servers.iter_mut().enumerate() {
             if let Some(server) = server {
                 if server.pid == target_pid {
                     // This is our server, so look through the connection map of each
                     // process to determine if this connection needs to be replaced
                     // with a tombstone.
-                    for process in self.processes.iter() {
+                    for process in processes.iter() {
                         if process.free() {
                             continue;
                         }
@@ -1569,14 +2822,83 @@ impl SystemServices {
                 }
 
                 // Look through this server's memory space to determine if this process
-                // is mentioned there as having some memory lent out.
-                server.discard_messages_for_pid(target_pid);
+                // is mentioned there as having some memory lent out.  Any such memory
+                // now belongs to the server, and is freed when the server returns it.
+                let server_pid = server.pid;
+                let server_mapping = processes[server_pid.get() as usize - 1].mapping;
+                server.discard_messages_for_pid(target_pid, |addr, len| {
+                    if server_mapping.activate().is_ok() {
+                        crate::mem::MemoryManager::with_mut(|mm| {
+                            mm.give_range(addr, len, target_pid, server_pid)
+                        });
+                    }
+                });
             }
         }
+
+        // 5. Destroy our servers.  This must happen while our memory is still
+        //    mapped, so that lent memory can be returned.
+        self.get_process(target_pid)?.activate()?;
+        for sidx in 0..self.servers.len() {
+            if self.servers[sidx]
+                .as_ref()
+                .map(|server| server.pid == target_pid)
+                .unwrap_or(false)
+            {
+                self.destroy_server(sidx)?;
+            }
+        }
+
         let process = self.get_process_mut(target_pid)?;
         process.activate()?;
         let parent_pid = process.ppid;
         process.terminate()?;
+        self.update_priorities();
+        crate::audit::record(
+            AuditKind::ProcessTerminated,
+            target_pid,
+            parent_pid.get() as usize,
+            0,
+        );
+        klog!(Scheduler, Info, "KERNEL: PID {} terminated", target_pid);
+        crate::deadline::forget_process(target_pid);
+        crate::image::forget_process(target_pid);
+        crate::lends::forget_process(target_pid);
+        crate::scatter::forget_process(target_pid);
+        #[cfg(baremetal)]
+        crate::canary::forget_process(target_pid);
+        crate::notify::forget_process(target_pid);
+        crate::poll::forget_process(target_pid);
+        crate::broadcast::forget_process(target_pid);
+        crate::watchdog::forget_process(target_pid);
+        crate::fault::forget_process(target_pid);
+        crate::timers::forget_process(target_pid);
+        crate::irq::forget_process(target_pid);
+        crate::mmio::forget_process(target_pid);
+        crate::power::forget_process(target_pid);
+        for server in self.servers.iter_mut().flatten() {
+            server.forget_connection_data(target_pid);
+            server.forget_disconnect_watcher(target_pid);
+            server.forget_allowed_client(target_pid);
+        }
+
+        // Children of this process are adopted by PID 1, so that nothing
+        // refers to this PID once it's handed out again.
+        for process in self.processes.iter_mut() {
+            if !process.free() && process.ppid == target_pid {
+                process.ppid = unsafe { PID::new_unchecked(1) };
+            }
+        }
+
+        // Threads that were switched to by this process have nothing to go
+        // back to anymore.
+        for process in self.processes.iter_mut() {
+            for link in process.switched_from.iter_mut() {
+                if link.map(|(pid, _)| pid == target_pid).unwrap_or(false) {
+                    *link = None;
+                }
+            }
+        }
         // println!("KERNEL({}): Terminated", target_pid);
 
         let process = self.get_process(parent_pid)?;
@@ -1585,8 +2907,23 @@ impl SystemServices {
         Ok(parent_pid)
     }
 
-    /// Calls the provided function with the current inner process state.
-    pub fn shutdown(&mut self) -> Result<(), xous_kernel::Error> {
+    /// Tear down every server and process, first wiping as much memory as
+    /// `scrub` asks for.
+    pub fn shutdown(&mut self, scrub: ScrubLevel) -> Result<(), xous_kernel::Error> {
+        // Wipe process memory while every address space is still intact.
+        #[cfg(baremetal)]
+        if scrub == ScrubLevel::All {
+            for process in self.processes.iter().filter(|process| !process.free()) {
+                process.activate()?;
+                crate::mem::MemoryManager::with_mut(|mm| arch::mem::wipe_user_pages(mm));
+            }
+        }
+        if scrub != ScrubLevel::None {
+            crate::entropy::wipe();
+            let pid = arch::process::current_pid();
+            crate::mem::MemoryManager::with_mut(|mm| mm.wipe_free_pages(pid))?;
+        }
+
         // Destroy all servers. This will cause all queued messages to be lost.
         for server in &mut self.servers {
             if server.is_some() {
@@ -0,0 +1,175 @@
+//! An orderly shutdown, so that processes holding state that must outlive
+//! them get a chance to save it.  The `Shutdown` syscall announces the
+//! shutdown to every server subscribed to `SHUTDOWN_BROADCAST`, along with
+//! how long they have to get ready.  The system only goes down once every
+//! one of them has unsubscribed or exited, or once that grace period is
+//! over, whichever comes first.  With nobody subscribed, it goes down right
+//! away.  A `Reboot` goes down the same way, and then has the arch layer
+//! start the system again.
+//!
+//! The grace period is counted by `timers`, which reports its deadline
+//! alongside its own and calls `expire()` whenever it checks them.
+
+use xous_kernel::{RebootMode, ScrubLevel, PID, SHUTDOWN_BROADCAST, TID};
+
+/// How long subscribers have to get ready for a shutdown
+pub const GRACE_MS: usize = 5000;
+
+#[derive(Copy, Clone, PartialEq)]
+enum State {
+    Running,
+
+    /// Subscribers have been told, and the system goes down at `deadline`
+    /// if they aren't all ready before then, coming back up again if
+    /// `reboot` says how
+    Pending {
+        scrub: ScrubLevel,
+        reboot: Option<RebootMode>,
+        deadline: u64,
+    },
+
+    /// Every server and process has been torn down
+    Finished,
+}
+
+#[cfg(baremetal)]
+static mut STATE: State = State::Running;
+
+#[cfg(not(baremetal))]
+std::thread_local!(static STATE: core::cell::Cell<State> = const { core::cell::Cell::new(State::Running) });
+
+fn state() -> State {
+    #[cfg(baremetal)]
+    unsafe {
+        STATE
+    }
+
+    #[cfg(not(baremetal))]
+    STATE.with(|state| state.get())
+}
+
+fn set_state(new_state: State) {
+    #[cfg(baremetal)]
+    unsafe {
+        STATE = new_state
+    }
+
+    #[cfg(not(baremetal))]
+    STATE.with(|state| state.set(new_state))
+}
+
+/// Start shutting down on behalf of thread `tid` of `pid`, which the
+/// announcement is sent from, and then reboot if `reboot` says how.  Asking
+/// to shut down while a shutdown is pending keeps the deadline, and only
+/// raises how much memory is wiped.  Nothing goes down until the next
+/// `check()`, even if nobody needs to be waited for, so that the caller can
+/// be answered first.
+///
+/// # Errors
+///
+/// * **UnhandledSyscall**: The arch layer can't reboot that way
+/// * **ShareViolation**: A reboot is pending, or a shutdown is pending and
+///                       this is a reboot
+pub fn begin(
+    pid: PID,
+    tid: TID,
+    scrub: ScrubLevel,
+    reboot: Option<RebootMode>,
+) -> Result<(), xous_kernel::Error> {
+    match state() {
+        State::Running => (),
+        State::Pending {
+            scrub: pending,
+            reboot: None,
+            deadline,
+        } if reboot.is_none() => {
+            let scrub = if scrub as usize > pending as usize {
+                scrub
+            } else {
+                pending
+            };
+            set_state(State::Pending {
+                scrub,
+                reboot,
+                deadline,
+            });
+            return Ok(());
+        }
+        State::Pending { .. } => return Err(xous_kernel::Error::ShareViolation),
+        State::Finished => return Ok(()),
+    }
+    if let Some(mode) = reboot {
+        crate::arch::check_reboot(mode)?;
+    }
+
+    let grace = crate::arch::timebase()
+        .map(|hz| (GRACE_MS as u64).saturating_mul(hz as u64) / 1000)
+        .unwrap_or(0);
+    let subscribers = crate::broadcast::kernel_subscribers(SHUTDOWN_BROADCAST);
+    let mode = reboot.map(|mode| mode as usize).unwrap_or(0);
+    crate::services::SystemServices::with_mut(|ss| {
+        crate::syscall::deliver_broadcast(ss, pid, tid, &subscribers, [GRACE_MS, mode, 0, 0])
+    });
+    set_state(State::Pending {
+        scrub,
+        reboot,
+        deadline: crate::arch::timestamp().saturating_add(grace),
+    });
+    Ok(())
+}
+
+/// When the grace period of a pending shutdown ends.
+pub fn deadline() -> Option<u64> {
+    match state() {
+        State::Pending { deadline, .. } => Some(deadline),
+        _ => None,
+    }
+}
+
+/// Finish a pending shutdown if every subscriber is ready for it.
+pub fn check() {
+    if let State::Pending { scrub, reboot, .. } = state() {
+        if ready() {
+            finish(scrub, reboot).expect("couldn't shut down");
+        }
+    }
+}
+
+/// Finish a pending shutdown if every subscriber is ready for it, or if its
+/// grace period ended by `now`.
+pub fn expire(now: u64) {
+    if let State::Pending {
+        scrub,
+        reboot,
+        deadline,
+    } = state()
+    {
+        if ready() {
+            finish(scrub, reboot).expect("couldn't shut down");
+        } else if now >= deadline {
+            xous_kernel::cover!("shutdown: grace period ran out");
+            finish(scrub, reboot).expect("couldn't shut down");
+        }
+    }
+}
+
+/// Whether every server and process has been torn down.
+pub fn finished() -> bool {
+    state() == State::Finished
+}
+
+/// Whether nobody is left subscribed to the announcement.
+fn ready() -> bool {
+    crate::broadcast::kernel_subscribers(SHUTDOWN_BROADCAST)
+        .iter()
+        .all(Option::is_none)
+}
+
+fn finish(scrub: ScrubLevel, reboot: Option<RebootMode>) -> Result<(), xous_kernel::Error> {
+    set_state(State::Finished);
+    crate::services::SystemServices::with_mut(|ss| ss.shutdown(scrub))?;
+    if let Some(mode) = reboot {
+        crate::arch::reboot(mode);
+    }
+    Ok(())
+}
@@ -0,0 +1,121 @@
+//! Suspending to RAM, so that a battery-powered device can sleep with
+//! everything it was doing left where it was.  The `Suspend` syscall tells
+//! every server subscribed to `SUSPEND_BROADCAST` to stop its device, and
+//! the system suspends the next time the scheduler finds nothing to run.
+//! The arch layer saves whatever it would lose and sleeps until a wake
+//! interrupt, and then subscribers are told that the system is back and the
+//! caller is woken up.  Processes are never told anything themselves: their
+//! threads are left in RAM, waiting for whatever they were waiting for.
+
+use xous_kernel::{SuspendEvent, PID, SUSPEND_BROADCAST, TID};
+
+#[derive(Copy, Clone, PartialEq)]
+enum State {
+    Running,
+
+    /// Subscribers have been told, and thread `tid` of `pid` is waiting for
+    /// the system to suspend and wake up again
+    Pending {
+        pid: PID,
+        tid: TID,
+    },
+
+    /// The system is asleep until the arch layer wakes it
+    Suspended {
+        pid: PID,
+        tid: TID,
+    },
+}
+
+#[cfg(baremetal)]
+static mut STATE: State = State::Running;
+
+#[cfg(not(baremetal))]
+std::thread_local!(static STATE: core::cell::Cell<State> = const { core::cell::Cell::new(State::Running) });
+
+fn state() -> State {
+    #[cfg(baremetal)]
+    unsafe {
+        STATE
+    }
+
+    #[cfg(not(baremetal))]
+    STATE.with(|state| state.get())
+}
+
+fn set_state(new_state: State) {
+    #[cfg(baremetal)]
+    unsafe {
+        STATE = new_state
+    }
+
+    #[cfg(not(baremetal))]
+    STATE.with(|state| state.set(new_state))
+}
+
+/// Start suspending on behalf of thread `tid` of `pid`, which the
+/// announcement is sent from.  It's up to the caller to block the thread
+/// until the system wakes up again.
+///
+/// # Errors
+///
+/// * **UnhandledSyscall**: The arch layer can't suspend
+/// * **InterruptNotFound**: Nothing could wake the system up again
+/// * **ShareViolation**: A suspend or a shutdown is already pending
+pub fn begin(pid: PID, tid: TID) -> Result<(), xous_kernel::Error> {
+    if state() != State::Running || crate::shutdown::deadline().is_some() {
+        return Err(xous_kernel::Error::ShareViolation);
+    }
+    crate::arch::check_suspend()?;
+    announce(pid, tid, SuspendEvent::Suspending);
+    set_state(State::Pending { pid, tid });
+    Ok(())
+}
+
+/// Whether the system is waiting for nothing to be left to run.
+pub fn pending() -> bool {
+    matches!(state(), State::Pending { .. })
+}
+
+/// Whether the system is asleep.
+pub fn suspended() -> bool {
+    matches!(state(), State::Suspended { .. })
+}
+
+/// Suspend now that nothing is left to run.  This returns once the system
+/// has woken up, unless the arch layer leaves it to call `resume()` later.
+/// Interrupts must be disabled, so that one can't make a thread runnable
+/// in the meantime.
+pub fn enter() {
+    if let State::Pending { pid, tid } = state() {
+        set_state(State::Suspended { pid, tid });
+        if crate::arch::suspend() {
+            resume();
+        }
+    }
+}
+
+/// Tell subscribers that the system is awake again, and wake the thread
+/// that asked for it to be suspended.
+pub fn resume() {
+    if let State::Suspended { pid, tid } = state() {
+        set_state(State::Running);
+        announce(pid, tid, SuspendEvent::Resumed);
+        if crate::services::SystemServices::with_mut(|ss| {
+            ss.ready_thread(pid, tid)
+                .and_then(|_| ss.switch_to_thread(pid, Some(tid)))
+                .and_then(|_| ss.set_thread_result(pid, tid, xous_kernel::Result::Ok))
+        })
+        .is_err()
+        {
+            xous_kernel::cover!("suspend: caller went away while the system slept");
+        }
+    }
+}
+
+fn announce(pid: PID, tid: TID, event: SuspendEvent) {
+    let subscribers = crate::broadcast::kernel_subscribers(SUSPEND_BROADCAST);
+    crate::services::SystemServices::with_mut(|ss| {
+        crate::syscall::deliver_broadcast(ss, pid, tid, &subscribers, [event as usize, 0, 0, 0])
+    });
+}
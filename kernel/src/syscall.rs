@@ -1,14 +1,39 @@
 use crate::arch;
 use crate::arch::process::Process as ArchProcess;
-use crate::irq::interrupt_claim;
+use crate::irq::{interrupt_claim, interrupt_free};
 use crate::mem::{MemoryManager, PAGE_SIZE};
 use crate::server::{SenderID, WaitingMessage};
 use crate::services::SystemServices;
 use core::mem;
 use xous_kernel::*;
 
-/// This is the context that called SwitchTo
-static mut SWITCHTO_CALLER: Option<(PID, TID)> = None;
+/// What the kernel should do with the calling thread once a syscall has
+/// been handled.  Only `Return` values are ever passed back to userspace;
+/// the other two are scheduling decisions that stay inside the kernel.
+#[derive(Debug, PartialEq)]
+pub enum SysCallOutcome {
+    /// Hand this result back to the calling thread.
+    Return(xous_kernel::Result),
+
+    /// A different context was activated, so resume that one instead of the
+    /// caller.  The caller's result, if any, has already been stored in its
+    /// context.
+    Resume,
+
+    /// The calling thread is blocked.  Whoever wakes it up will set its
+    /// result at that time.
+    Blocked,
+}
+
+impl From<xous_kernel::Result> for SysCallOutcome {
+    fn from(result: xous_kernel::Result) -> Self {
+        SysCallOutcome::Return(result)
+    }
+}
+
+/// The kernel's view of a syscall result.  This shadows the userspace
+/// `SysCallResult`, which can only ever hold logical results.
+pub type SysCallResult = core::result::Result<SysCallOutcome, xous_kernel::Error>;
 
 fn send_message(pid: PID, thread: TID, cid: CID, message: Message) -> SysCallResult {
     SystemServices::with_mut(|ss| {
@@ -38,6 +63,10 @@ fn send_message(pid: PID, thread: TID, cid: CID, message: Message) -> SysCallRes
         let message = match message {
             Message::Scalar(_) | Message::BlockingScalar(_) => message,
             Message::Move(msg) => {
+                // Only lends may be scattered.
+                if msg.scatter_list().is_some() {
+                    return Err(xous_kernel::Error::InvalidSyscall);
+                }
                 let new_virt = ss.send_memory(
                     msg.buf.as_mut_ptr(),
                     server_pid,
@@ -52,13 +81,23 @@ fn send_message(pid: PID, thread: TID, cid: CID, message: Message) -> SysCallRes
                 })
             }
             Message::MutableBorrow(msg) => {
-                let new_virt = ss.lend_memory(
-                    msg.buf.as_mut_ptr(),
-                    server_pid,
-                    core::ptr::null_mut(),
-                    msg.buf.len(),
-                    true,
-                )?;
+                let new_virt = if let Some(list) = msg.scatter_list() {
+                    ss.lend_scattered_memory(
+                        list as *const u8,
+                        thread,
+                        server_pid,
+                        msg.buf.len(),
+                        true,
+                    )?
+                } else {
+                    ss.lend_memory(
+                        msg.buf.as_mut_ptr(),
+                        server_pid,
+                        core::ptr::null_mut(),
+                        msg.buf.len(),
+                        true,
+                    )?
+                };
                 Message::MutableBorrow(MemoryMessage {
                     id: msg.id,
                     buf: MemoryRange::new(new_virt as usize, msg.buf.len())?,
@@ -67,13 +106,23 @@ fn send_message(pid: PID, thread: TID, cid: CID, message: Message) -> SysCallRes
                 })
             }
             Message::Borrow(msg) => {
-                let new_virt = ss.lend_memory(
-                    msg.buf.as_mut_ptr(),
-                    server_pid,
-                    core::ptr::null_mut(),
-                    msg.buf.len(),
-                    false,
-                )?;
+                let new_virt = if let Some(list) = msg.scatter_list() {
+                    ss.lend_scattered_memory(
+                        list as *const u8,
+                        thread,
+                        server_pid,
+                        msg.buf.len(),
+                        false,
+                    )?
+                } else {
+                    ss.lend_memory(
+                        msg.buf.as_mut_ptr(),
+                        server_pid,
+                        core::ptr::null_mut(),
+                        msg.buf.len(),
+                        false,
+                    )?
+                };
                 // println!(
                 //     "Lending {} bytes from {:08x} in PID {} to {:08x} in PID {}",
                 //     msg.buf.len(),
@@ -98,6 +147,7 @@ fn send_message(pid: PID, thread: TID, cid: CID, message: Message) -> SysCallRes
             .expect("server couldn't be located")
             .take_available_thread()
         {
+            cover!("send: server thread available");
             // println!(
             //     "There are contexts available to handle this message.  Marking PID {} as Ready",
             //     server_pid
@@ -105,11 +155,10 @@ fn send_message(pid: PID, thread: TID, cid: CID, message: Message) -> SysCallRes
             let server_cid = ss.server_cid(sidx)?;
             let sender_idx = if message.is_blocking() {
                 ss.remember_server_message(sidx, pid, thread, &message, client_address)
-                    .map_err(|e| {
+                    .inspect_err(|_| {
                         ss.server_from_sidx_mut(sidx)
                             .expect("server couldn't be located")
                             .return_available_thread(thread);
-                        e
                     })?
             } else {
                 0
@@ -117,25 +166,48 @@ fn send_message(pid: PID, thread: TID, cid: CID, message: Message) -> SysCallRes
             let sender = SenderID {
                 cid: server_cid,
                 idx: sender_idx,
+                pid: Some(pid),
             };
             let envelope = MessageEnvelope {
                 sender: sender.into(),
+                connection_data: ss
+                    .server_from_sidx(sidx)
+                    .expect("server couldn't be located")
+                    .connection_data(pid),
                 body: message,
             };
 
             // Mark the server's context as "Ready". If this fails, return the context
             // to the blocking list.
-            ss.ready_thread(server_pid, server_tid).map_err(|e| {
+            ss.ready_thread(server_pid, server_tid).inspect_err(|_| {
                 ss.server_from_sidx_mut(sidx)
                     .expect("server couldn't be located")
                     .return_available_thread(thread);
-                e
             })?;
+            ss.note_message_received(server_pid, server_tid, crate::arch::timestamp());
+
+            // A thread waiting on a poll set is parked on every server in
+            // it, so take it back off the others.
+            if let Some(servers) = crate::poll::woken(server_pid, server_tid) {
+                for sid in servers.iter().flatten() {
+                    if let Some(other) = ss.server_sidx(*sid) {
+                        if let Some(server) = ss.server_from_sidx_mut(other) {
+                            server.unpark_thread(server_tid);
+                        }
+                    }
+                }
+            }
+
+            // The client is now waiting on the server, so the server may
+            // need to inherit its priority.
+            if blocking {
+                ss.update_priorities_for(server_pid, pid);
+            }
 
             if blocking && cfg!(baremetal) {
                 // println!("Activating Server context and switching away from Client");
                 ss.activate_process_thread(thread, server_pid, server_tid, !blocking)
-                    .map(|_| Ok(xous_kernel::Result::Message(envelope)))
+                    .map(|_| Ok(xous_kernel::Result::Message(envelope).into()))
                     .unwrap_or(Err(xous_kernel::Error::ProcessNotFound))
             } else if blocking && !cfg!(baremetal) {
                 // println!("Blocking client, since it sent a blocking message");
@@ -146,7 +218,7 @@ fn send_message(pid: PID, thread: TID, cid: CID, message: Message) -> SysCallRes
                     server_tid,
                     xous_kernel::Result::Message(envelope),
                 )
-                .map(|_| xous_kernel::Result::BlockedProcess)
+                .map(|_| SysCallOutcome::Blocked)
             } else if cfg!(baremetal) {
                 // println!("Setting the return value of the Server and returning to Client");
                 ss.set_thread_result(
@@ -154,7 +226,7 @@ fn send_message(pid: PID, thread: TID, cid: CID, message: Message) -> SysCallRes
                     server_tid,
                     xous_kernel::Result::Message(envelope),
                 )
-                .map(|_| xous_kernel::Result::Ok)
+                .map(|_| xous_kernel::Result::Ok.into())
             } else {
                 // println!("Setting the return value of the Server and returning to Client");
                 // "Switch to" the server PID when not running on bare metal. This ensures
@@ -165,36 +237,65 @@ fn send_message(pid: PID, thread: TID, cid: CID, message: Message) -> SysCallRes
                     server_tid,
                     xous_kernel::Result::Message(envelope),
                 )
-                .map(|_| xous_kernel::Result::Ok)
+                .map(|_| xous_kernel::Result::Ok.into())
             }
         } else {
             // Add this message to the queue.  If the queue is full, this
             // returns an error.
-            ss.queue_server_message(sidx, pid, thread, message, client_address)?;
+            ss.queue_server_message(sidx, pid, thread, message, client_address)
+                .inspect_err(|_| cover!("send: server queue full"))?;
+            cover!("send: message queued");
 
             // Park this context if it's blocking.  This is roughly
             // equivalent to a "Yield".
             if blocking {
-                if cfg!(baremetal) {
-                    // println!("Returning to parent");
-                    let process = ss.get_process(pid).expect("Can't get current process");
-                    let ppid = process.ppid;
-                    unsafe { SWITCHTO_CALLER = None };
-                    ss.activate_process_thread(thread, ppid, 0, !blocking)
-                        .map(|_| Ok(xous_kernel::Result::ResumeProcess))
-                        .unwrap_or(Err(xous_kernel::Error::ProcessNotFound))
-                } else {
-                    ss.switch_from_thread(pid, thread)?;
-                    Ok(xous_kernel::Result::BlockedProcess)
-                }
+                cover!("send: blocking message queued");
+                ss.update_priorities_for(server_pid, pid);
+                block_caller(ss, pid, thread)
             } else {
                 // println!("Returning to Client with Ok result");
-                Ok(xous_kernel::Result::Ok)
+                Ok(xous_kernel::Result::Ok.into())
             }
         }
     })
 }
 
+/// Send a message that the client will wait on until the server replies.
+/// Every kind but `Move` already blocks until it's answered, except that a
+/// `Scalar` isn't answered at all, so it's sent as a `BlockingScalar`.
+fn call_message(pid: PID, thread: TID, cid: CID, message: Message) -> SysCallResult {
+    let message = match message {
+        Message::Scalar(scalar) => Message::BlockingScalar(scalar),
+        Message::Move(_) => return Err(xous_kernel::Error::InvalidSyscall),
+        other => other,
+    };
+    send_message(pid, thread, cid, message)
+}
+
+/// Wake the client of a blocking scalar message by switching straight to it
+/// with `result`, rather than setting the result in its context from afar
+/// and leaving it for the scheduler.  The server thread gets `Ok` once it's
+/// next run.  This may only be used if the client is in another process,
+/// and the server wasn't running at the client's priority.
+#[cfg(baremetal)]
+fn return_scalar_fast(
+    ss: &mut SystemServices,
+    pid: PID,
+    tid: TID,
+    client_pid: PID,
+    client_tid: TID,
+    result: xous_kernel::Result,
+) -> SysCallResult {
+    cover!("return: scalar fast path");
+    ss.ready_thread(client_pid, client_tid)?;
+    // The server is still running, so its context can be written without
+    // switching address spaces.
+    ArchProcess::current().set_thread_result(tid, xous_kernel::Result::Ok);
+    ss.ready_thread(pid, tid)?;
+    ss.activate_process_thread(tid, client_pid, client_tid, true)?;
+    Ok(result.into())
+}
+
 fn return_memory(pid: PID, tid: TID, sender: MessageSender, buf: MemoryRange) -> SysCallResult {
     SystemServices::with_mut(|ss| {
         let sender = SenderID::from(sender);
@@ -206,7 +307,7 @@ fn return_memory(pid: PID, tid: TID, sender: MessageSender, buf: MemoryRange) ->
         if server.pid != pid {
             return Err(xous_kernel::Error::ServerNotFound);
         }
-        let result = server.take_waiting_message(sender.idx, Some(&buf))?;
+        let result = server.take_waiting_message(sender.idx, sender.pid, Some(&buf))?;
         let (client_pid, client_tid, server_addr, client_addr, len) = match result {
             WaitingMessage::BorrowedMemory(
                 client_pid,
@@ -216,11 +317,13 @@ fn return_memory(pid: PID, tid: TID, sender: MessageSender, buf: MemoryRange) ->
                 len,
             ) => (client_pid, client_ctx, server_addr, client_addr, len),
             WaitingMessage::MovedMemory => {
-                return Ok(xous_kernel::Result::Ok);
+                cover!("return: moved memory");
+                return Ok(xous_kernel::Result::Ok.into());
             }
             WaitingMessage::ForgetMemory(range) => {
+                cover!("return: forgotten memory");
                 return MemoryManager::with_mut(|mm| {
-                    let mut result = Ok(xous_kernel::Result::Ok);
+                    let mut result = Ok(xous_kernel::Result::Ok.into());
                     let virt = range.addr.get();
                     let size = range.size.get();
                     if virt & 0xfff != 0 {
@@ -236,15 +339,18 @@ fn return_memory(pid: PID, tid: TID, sender: MessageSender, buf: MemoryRange) ->
                     result
                 })
             }
-            WaitingMessage::ScalarMessage(_pid, _tid) => {
+            WaitingMessage::ScalarMessage(_, _) | WaitingMessage::ForgetScalar => {
+                cover!("return: memory to a scalar message");
                 println!("WARNING: Tried to wait on a message that was a scalar");
                 return Err(xous_kernel::Error::InternalError);
             }
             WaitingMessage::None => {
+                cover!("return: memory to a missing message");
                 println!("WARNING: Tried to wait on a message that didn't exist");
                 return Err(xous_kernel::Error::ProcessNotFound);
             }
         };
+        cover!("return: borrowed memory");
         // println!(
         //     "Returning {} bytes from {:08x} in PID {} to {:08x} in PID {} in context {}",
         //     len,
@@ -265,6 +371,10 @@ fn return_memory(pid: PID, tid: TID, sender: MessageSender, buf: MemoryRange) ->
             len.get(),
         )?;
 
+        // The client is no longer waiting on this server, so the server gives
+        // up any priority it inherited from it.
+        ss.update_priorities_for(pid, client_pid);
+
         // Unblock the client context to allow it to continue.
         // println!(
         //     "KERNEL({}): Unblocking PID {} CTX {}",
@@ -273,7 +383,7 @@ fn return_memory(pid: PID, tid: TID, sender: MessageSender, buf: MemoryRange) ->
         ss.ready_thread(client_pid, client_tid)?;
         ss.switch_to_thread(client_pid, Some(client_tid))?;
         ss.set_thread_result(client_pid, client_tid, xous_kernel::Result::Ok)?;
-        Ok(xous_kernel::Result::Ok)
+        Ok(xous_kernel::Result::Ok.into())
     })
 }
 
@@ -288,9 +398,13 @@ fn return_scalar(pid: PID, _tid: TID, sender: MessageSender, arg: usize) -> SysC
         if server.pid != pid {
             return Err(xous_kernel::Error::ServerNotFound);
         }
-        let result = server.take_waiting_message(sender.idx, None)?;
+        let result = server.take_waiting_message(sender.idx, sender.pid, None)?;
         let (client_pid, client_tid) = match result {
             WaitingMessage::ScalarMessage(pid, tid) => (pid, tid),
+            WaitingMessage::ForgetScalar => {
+                cover!("return: scalar to a terminated client");
+                return Ok(xous_kernel::Result::Ok.into());
+            }
             WaitingMessage::ForgetMemory(_) => {
                 println!("WARNING: Tried to wait on a scalar message that was actually forgettingmemory");
                 return Err(xous_kernel::Error::ProcessNotFound);
@@ -308,10 +422,22 @@ fn return_scalar(pid: PID, _tid: TID, sender: MessageSender, arg: usize) -> SysC
                 return Err(xous_kernel::Error::ProcessNotFound);
             }
         };
+        #[cfg(baremetal)]
+        if client_pid != pid && !ss.affects_priority(pid, client_pid) {
+            return return_scalar_fast(
+                ss,
+                pid,
+                _tid,
+                client_pid,
+                client_tid,
+                xous_kernel::Result::Scalar1(arg),
+            );
+        }
+        ss.update_priorities_for(pid, client_pid);
         ss.ready_thread(client_pid, client_tid)?;
         ss.switch_to_thread(client_pid, Some(client_tid))?;
         ss.set_thread_result(client_pid, client_tid, xous_kernel::Result::Scalar1(arg))?;
-        Ok(xous_kernel::Result::Ok)
+        Ok(xous_kernel::Result::Ok.into())
     })
 }
 
@@ -326,9 +452,13 @@ fn return_scalar2(pid: PID, _tid: TID, sender: MessageSender, arg1: usize, arg2:
         if server.pid != pid {
             return Err(xous_kernel::Error::ServerNotFound);
         }
-        let result = server.take_waiting_message(sender.idx, None)?;
+        let result = server.take_waiting_message(sender.idx, sender.pid, None)?;
         let (client_pid, client_tid) = match result {
             WaitingMessage::ScalarMessage(pid, tid) => (pid, tid),
+            WaitingMessage::ForgetScalar => {
+                cover!("return: scalar to a terminated client");
+                return Ok(xous_kernel::Result::Ok.into());
+            }
             WaitingMessage::ForgetMemory(_) => {
                 println!("WARNING: Tried to wait on a scalar message that was actually forgetting memory");
                 return Err(xous_kernel::Error::ProcessNotFound);
@@ -346,13 +476,215 @@ fn return_scalar2(pid: PID, _tid: TID, sender: MessageSender, arg1: usize, arg2:
                 return Err(xous_kernel::Error::ProcessNotFound);
             }
         };
+        #[cfg(baremetal)]
+        if client_pid != pid && !ss.affects_priority(pid, client_pid) {
+            return return_scalar_fast(
+                ss,
+                pid,
+                _tid,
+                client_pid,
+                client_tid,
+                xous_kernel::Result::Scalar2(arg1, arg2),
+            );
+        }
+        ss.update_priorities_for(pid, client_pid);
         ss.ready_thread(client_pid, client_tid)?;
         ss.switch_to_thread(client_pid, Some(client_tid))?;
         ss.set_thread_result(client_pid, client_tid, xous_kernel::Result::Scalar2(arg1, arg2))?;
-        Ok(xous_kernel::Result::Ok)
+        Ok(xous_kernel::Result::Ok.into())
+    })
+}
+
+fn forward_message(pid: PID, sender: MessageSender, cid: CID, message: Message) -> SysCallResult {
+    SystemServices::with_mut(|ss| {
+        let sender = SenderID::from(sender);
+
+        let sidx = ss
+            .sidx_from_cid(sender.cid)
+            .ok_or(xous_kernel::Error::ServerNotFound)?;
+        let server = ss
+            .server_from_sidx(sidx)
+            .ok_or(xous_kernel::Error::ServerNotFound)?;
+        if server.pid != pid {
+            return Err(xous_kernel::Error::ServerNotFound);
+        }
+        let waiting = server.peek_waiting_message(sender.idx, sender.pid);
+
+        let dest_sidx = ss
+            .sidx_from_cid(cid)
+            .ok_or(xous_kernel::Error::ServerNotFound)?;
+        let dest = ss
+            .server_from_sidx(dest_sidx)
+            .ok_or(xous_kernel::Error::ServerNotFound)?;
+        let dest_pid = dest.pid;
+        if !dest.has_room() {
+            return Err(xous_kernel::Error::ServerQueueFull);
+        }
+        // The server's connection to itself is made the first time a message
+        // is delivered, so make it now while failing still leaves the message
+        // with this server.
+        ss.server_cid(dest_sidx)?;
+
+        // Make sure the message is the same kind as the one that was
+        // received, and uses the buffer that was lent, before changing
+        // anything.
+        let mutable = matches!(message, Message::MutableBorrow(_));
+        let (client_pid, client_tid, client_address, received_buf, message) =
+            match (waiting, message) {
+                (
+                    WaitingMessage::BorrowedMemory(
+                        client_pid,
+                        client_tid,
+                        server_addr,
+                        client_addr,
+                        len,
+                    ),
+                    Message::Borrow(msg),
+                )
+                | (
+                    WaitingMessage::BorrowedMemory(
+                        client_pid,
+                        client_tid,
+                        server_addr,
+                        client_addr,
+                        len,
+                    ),
+                    Message::MutableBorrow(msg),
+                ) => {
+                    if msg.buf.addr != server_addr || msg.buf.size != len {
+                        return Err(xous_kernel::Error::BadAddress);
+                    }
+                    let new_virt = ss.forward_memory(
+                        msg.buf.as_mut_ptr(),
+                        client_pid,
+                        client_tid,
+                        client_addr.get() as _,
+                        dest_pid,
+                        len.get(),
+                        mutable,
+                    )?;
+                    let forwarded = MemoryMessage {
+                        buf: MemoryRange::new(new_virt as usize, len.get())?,
+                        ..msg
+                    };
+                    let message = if mutable {
+                        Message::MutableBorrow(forwarded)
+                    } else {
+                        Message::Borrow(forwarded)
+                    };
+                    (
+                        client_pid,
+                        client_tid,
+                        Some(client_addr),
+                        Some(msg.buf),
+                        message,
+                    )
+                }
+                (
+                    WaitingMessage::ScalarMessage(client_pid, client_tid),
+                    Message::BlockingScalar(msg),
+                ) => (
+                    client_pid,
+                    client_tid,
+                    None,
+                    None,
+                    Message::BlockingScalar(msg),
+                ),
+                _ => return Err(xous_kernel::Error::InvalidSyscall),
+            };
+
+        // As far as this server is concerned the message has been responded
+        // to, and the client is now waiting on the new server instead.
+        ss.server_from_sidx_mut(sidx)
+            .expect("server couldn't be located")
+            .take_waiting_message(sender.idx, sender.pid, received_buf.as_ref())?;
+
+        // Deliver the message as though the client had sent it, without
+        // blocking the caller.
+        deliver_message(
+            ss,
+            dest_sidx,
+            client_pid,
+            client_tid,
+            message,
+            client_address,
+        )?;
+        Ok(xous_kernel::Result::Ok.into())
     })
 }
 
+/// Deliver `message` to server `sidx` as though thread `tid` of `pid` had
+/// sent it, without blocking the caller.  The message goes straight to a
+/// server thread if one is waiting, and into the server's queue otherwise.
+/// Returns `true` if a server thread was waiting.
+fn deliver_message(
+    ss: &mut SystemServices,
+    sidx: usize,
+    pid: PID,
+    tid: TID,
+    message: Message,
+    client_address: Option<MemoryAddress>,
+) -> core::result::Result<bool, xous_kernel::Error> {
+    let server = ss
+        .server_from_sidx_mut(sidx)
+        .ok_or(xous_kernel::Error::ServerNotFound)?;
+    let server_pid = server.pid;
+    let blocking = message.is_blocking();
+    let server_tid = match server.take_available_thread() {
+        Some(server_tid) => server_tid,
+        None => {
+            ss.queue_server_message(sidx, pid, tid, message, client_address)?;
+            if blocking {
+                ss.update_priorities_for(server_pid, pid);
+            }
+            return Ok(false);
+        }
+    };
+
+    let server_cid = ss.server_cid(sidx)?;
+    let sender_idx = if blocking {
+        ss.remember_server_message(sidx, pid, tid, &message, client_address)?
+    } else {
+        0
+    };
+    let envelope = MessageEnvelope {
+        sender: SenderID {
+            cid: server_cid,
+            idx: sender_idx,
+            pid: Some(pid),
+        }
+        .into(),
+        connection_data: ss
+            .server_from_sidx(sidx)
+            .expect("server couldn't be located")
+            .connection_data(pid),
+        body: message,
+    };
+    ss.ready_thread(server_pid, server_tid)?;
+    ss.note_message_received(server_pid, server_tid, crate::arch::timestamp());
+    if let Some(servers) = crate::poll::woken(server_pid, server_tid) {
+        for sid in servers.iter().flatten() {
+            if let Some(other) = ss.server_sidx(*sid) {
+                if let Some(server) = ss.server_from_sidx_mut(other) {
+                    server.unpark_thread(server_tid);
+                }
+            }
+        }
+    }
+    if blocking {
+        ss.update_priorities_for(server_pid, pid);
+    }
+    if !cfg!(baremetal) {
+        ss.switch_to_thread(server_pid, Some(server_tid))?;
+    }
+    ss.set_thread_result(
+        server_pid,
+        server_tid,
+        xous_kernel::Result::Message(envelope),
+    )?;
+    Ok(true)
+}
+
 fn receive_message(pid: PID, tid: TID, sid: SID) -> SysCallResult {
     SystemServices::with_mut(|ss| {
         assert!(
@@ -360,7 +692,7 @@ fn receive_message(pid: PID, tid: TID, sid: SID) -> SysCallResult {
             "current thread is not running"
         );
         // See if there is a pending message.  If so, return immediately.
-        let cid = ss.connect_to_server(sid)?;
+        let cid = ss.connect_to_own_server(sid)?;
         let sidx = ss
             .server_sidx(sid)
             .ok_or(xous_kernel::Error::ServerNotFound)?;
@@ -375,8 +707,9 @@ fn receive_message(pid: PID, tid: TID, sid: SID) -> SysCallResult {
         }
 
         // If there is a pending message, return it immediately.
-        if let Some(msg) = server.take_next_message(cid) {
-            return Ok(xous_kernel::Result::Message(msg));
+        if let Some((msg, queued)) = server.take_next_message(cid) {
+            ss.note_message_received(pid, tid, queued);
+            return Ok(xous_kernel::Result::Message(msg).into());
         }
 
         // There is no pending message, so return control to the parent
@@ -389,38 +722,313 @@ fn receive_message(pid: PID, tid: TID, sid: SID) -> SysCallResult {
         // );
         server.park_thread(tid);
 
-        // For baremetal targets, switch away from this process.
-        if cfg!(baremetal) {
-            unsafe { SWITCHTO_CALLER = None };
-            let ppid = ss.get_process(pid).expect("Can't get current process").ppid;
-            // TODO: Advance thread
-            ss.activate_process_thread(tid, ppid, 0, false)
-                .map(|_| Ok(xous_kernel::Result::ResumeProcess))
-                .unwrap_or(Err(xous_kernel::Error::ProcessNotFound))
+        block_caller(ss, pid, tid)
+    })
+}
+
+/// Block thread `tid` of `pid` until something readies it again.  For
+/// baremetal targets this switches back to the parent process, and for hosted
+/// targets it returns `Blocked` so that the thread is called back on its
+/// socket at a later time.
+fn block_caller(ss: &mut SystemServices, pid: PID, tid: TID) -> SysCallResult {
+    if cfg!(baremetal) {
+        ss.take_switched_from(pid, tid);
+        let ppid = ss.get_process(pid)?.ppid;
+        ss.activate_process_thread(tid, ppid, 0, false)
+            .map(|_| Ok(SysCallOutcome::Resume))
+            .unwrap_or(Err(xous_kernel::Error::ProcessNotFound))
+    } else {
+        ss.switch_from_thread(pid, tid)
+            .map(|_| SysCallOutcome::Blocked)
+    }
+}
+
+fn signal_notification(id: usize, count: usize) -> SysCallResult {
+    SystemServices::with_mut(|ss| ss.signal_notification(id, count))
+        .map(|_| xous_kernel::Result::Ok.into())
+}
+
+fn wait_notification(pid: PID, tid: TID, id: usize) -> SysCallResult {
+    if crate::notify::wait(id, pid, tid)? {
+        return Ok(xous_kernel::Result::Ok.into());
+    }
+
+    // There is no signal yet, so block until `signal_notification()` readies
+    // this thread again.
+    SystemServices::with_mut(|ss| block_caller(ss, pid, tid))
+}
+
+fn sleep_thread(pid: PID, tid: TID, ms: usize) -> SysCallResult {
+    if ms == 0 {
+        return Ok(xous_kernel::Result::Ok.into());
+    }
+    crate::timers::sleep(pid, tid, ms)?;
+
+    // Block until `timers::check()` readies this thread again.
+    SystemServices::with_mut(|ss| block_caller(ss, pid, tid))
+}
+
+fn suspend(pid: PID, tid: TID) -> SysCallResult {
+    crate::suspend::begin(pid, tid)?;
+
+    // Block until `suspend::resume()` readies this thread again.
+    SystemServices::with_mut(|ss| block_caller(ss, pid, tid))
+}
+
+fn receive_any(pid: PID, tid: TID, id: usize) -> SysCallResult {
+    let servers = crate::poll::servers(id, pid)?;
+    SystemServices::with_mut(|ss| {
+        // Return the first waiting message, checking servers in the order
+        // they were added.  Servers that have gone away are skipped.
+        let mut live = [None; crate::poll::MAX_POLL_SERVERS];
+        for (slot, sid) in live.iter_mut().zip(servers.iter()) {
+            let sid = match sid {
+                Some(sid) => *sid,
+                None => continue,
+            };
+            let sidx = match ss.server_sidx(sid) {
+                Some(sidx) => sidx,
+                None => continue,
+            };
+            let cid = ss.connect_to_own_server(sid)?;
+            let server = ss
+                .server_from_sidx_mut(sidx)
+                .ok_or(xous_kernel::Error::ServerNotFound)?;
+            if server.pid != pid {
+                continue;
+            }
+            if let Some((msg, queued)) = server.take_next_message(cid) {
+                ss.note_message_received(pid, tid, queued);
+                return Ok(xous_kernel::Result::Message(msg).into());
+            }
+            *slot = Some(sidx);
+        }
+        if live.iter().all(|sidx| sidx.is_none()) {
+            return Err(xous_kernel::Error::ServerNotFound);
+        }
+
+        // Nothing is waiting, so park on every server until one of them
+        // hands this thread a message.
+        crate::poll::wait(id, pid, tid)?;
+        for sidx in live.iter().flatten() {
+            ss.server_from_sidx_mut(*sidx)
+                .expect("server couldn't be located")
+                .park_thread(tid);
+        }
+
+        block_caller(ss, pid, tid)
+    })
+}
+
+/// Send a `Scalar` message with `args` to every server subscribed to
+/// broadcast list `id`, returning how many of them it reached.  Servers
+/// whose queues are full miss out, rather than holding up the rest.
+fn broadcast(pid: PID, tid: TID, id: usize, args: [usize; 4]) -> SysCallResult {
+    let subscribers = crate::broadcast::subscribers(id, pid)?;
+    SystemServices::with_mut(|ss| {
+        let delivered = deliver_broadcast(ss, pid, tid, &subscribers, args);
+        Ok(xous_kernel::Result::Scalar1(delivered).into())
+    })
+}
+
+/// Send a `Scalar` message with `args` to each of `subscribers` as though
+/// thread `tid` of `pid` had sent it, and return how many it reached.
+pub fn deliver_broadcast(
+    ss: &mut SystemServices,
+    pid: PID,
+    tid: TID,
+    subscribers: &[Option<crate::broadcast::Subscriber>],
+    args: [usize; 4],
+) -> usize {
+    let mut delivered = 0;
+    for subscriber in subscribers.iter().flatten() {
+        let sidx = match ss.server_sidx(subscriber.sid) {
+            Some(sidx) => sidx,
+            None => continue,
+        };
+        let message = Message::Scalar(ScalarMessage {
+            id: subscriber.opcode,
+            arg1: args[0],
+            arg2: args[1],
+            arg3: args[2],
+            arg4: args[3],
+        });
+        if deliver_message(ss, sidx, pid, tid, message, None).is_ok() {
+            delivered += 1;
+        } else {
+            cover!("broadcast: subscriber missed a message");
         }
-        // For hosted targets, simply return `BlockedProcess` indicating we'll make
-        // a callback to their socket at a later time.
-        else {
-            ss.switch_from_thread(pid, tid)
-                .map(|_| xous_kernel::Result::BlockedProcess)
+    }
+    delivered
+}
+
+/// Deliver interrupt `irq_no` to server `sid` as a `Scalar` message with ID
+/// `opcode`, as though thread `tid` of `pid` had sent it.  The second
+/// argument counts how many times the interrupt fired, so if the last
+/// message for it hasn't been received yet it's counted there instead.
+pub fn deliver_interrupt(
+    ss: &mut SystemServices,
+    pid: PID,
+    tid: TID,
+    sid: SID,
+    opcode: usize,
+    irq_no: usize,
+) -> core::result::Result<(), xous_kernel::Error> {
+    let sidx = ss
+        .server_sidx(sid)
+        .ok_or(xous_kernel::Error::ServerNotFound)?;
+    let server = ss
+        .server_from_sidx_mut(sidx)
+        .ok_or(xous_kernel::Error::ServerNotFound)?;
+    if server.coalesce_scalar(pid, opcode, irq_no) {
+        return Ok(());
+    }
+    let message = Message::Scalar(ScalarMessage {
+        id: opcode,
+        arg1: irq_no,
+        arg2: 1,
+        arg3: 0,
+        arg4: 0,
+    });
+    deliver_message(ss, sidx, pid, tid, message, None).map(|_| ())
+}
+
+/// Deliver timer `id` to its server as a `Scalar` message whose first
+/// argument is the timer's ID, as though the thread that created the timer
+/// had sent it.
+pub fn deliver_timer(
+    ss: &mut SystemServices,
+    id: usize,
+    timer: &crate::timers::Timer,
+) -> core::result::Result<(), xous_kernel::Error> {
+    let sidx = ss
+        .server_sidx(timer.sid)
+        .ok_or(xous_kernel::Error::ServerNotFound)?;
+    let message = Message::Scalar(ScalarMessage {
+        id: timer.opcode,
+        arg1: id,
+        arg2: 0,
+        arg3: 0,
+        arg4: 0,
+    });
+    deliver_message(ss, sidx, timer.owner, timer.tid, message, None).map(|_| ())
+}
+
+fn yield_slice(pid: PID, tid: TID) -> SysCallResult {
+    // If we're not running on bare metal, treat this as a no-op.
+    if !cfg!(baremetal) {
+        return Ok(xous_kernel::Result::Ok.into());
+    }
+
+    SystemServices::with_mut(|ss| {
+        // Go back to whoever switched to this thread.  A thread that
+        // was scheduled some other way yields to its parent process,
+        // unless it has no parent to yield to.
+        let (parent_pid, parent_ctx) = match ss.take_switched_from(pid, tid) {
+            Some(caller) => caller,
+            None => match ss.get_process(pid)?.ppid {
+                ppid if ppid == pid => return Ok(xous_kernel::Result::Ok.into()),
+                ppid => (ppid, 0),
+            },
+        };
+        // TODO: Advance thread
+        ss.activate_process_thread(tid, parent_pid, parent_ctx, true)
+            .map(|_| Ok(SysCallOutcome::Resume))
+            .unwrap_or(Err(xous_kernel::Error::ProcessNotFound))
+    })
+}
+
+fn yield_to(pid: PID, tid: TID, target_pid: PID, target_tid: TID) -> SysCallResult {
+    let ready = SystemServices::with(|ss| {
+        ss.check_thread(target_pid, target_tid)?;
+        Ok(ss.thread_is_ready(target_pid, target_tid))
+    })?;
+    if !cfg!(baremetal) || !ready || (target_pid, target_tid) == (pid, tid) {
+        return yield_slice(pid, tid);
+    }
+
+    SystemServices::with_mut(|ss| {
+        // The target takes this thread's place, so when it yields it goes
+        // back to whoever this thread would have.  The rest of the quantum
+        // is left alone so that the target only gets what was donated.
+        let caller = ss.take_switched_from(pid, tid);
+        match ss.activate_process_thread(tid, target_pid, target_tid, true) {
+            Ok(new_tid) => {
+                if let Some(caller) = caller {
+                    ss.set_switched_from(target_pid, new_tid, caller);
+                }
+                Ok(SysCallOutcome::Resume)
+            }
+            Err(e) => {
+                if let Some(caller) = caller {
+                    ss.set_switched_from(pid, tid, caller);
+                }
+                Err(e)
+            }
         }
     })
 }
 
 pub fn handle(pid: PID, tid: TID, call: SysCall) -> SysCallResult {
-    #[cfg(feature = "debug-print")]
-    print!("KERNEL({}:{}): Syscall {:?}", pid, tid, call);
-    let result = handle_inner(pid, tid, call);
-    #[cfg(feature = "debug-print")]
-    println!(" -> {:?}", result);
+    klog!(
+        Syscalls,
+        Info,
+        "KERNEL({}:{}): Syscall {:?}",
+        pid,
+        tid,
+        call
+    );
+    let args = call.as_args();
+    #[cfg(baremetal)]
+    crate::crash::syscall_entry(pid, tid, &args);
+    #[cfg(feature = "trace")]
+    let traced = crate::trace::syscall_entry(pid, tid, &call);
+    #[cfg(feature = "syscall-latency")]
+    let start = crate::arch::cycles();
+    let result = SystemServices::with(|ss| ss.check_syscall(pid, args[0]))
+        .and_then(|_| handle_inner(pid, tid, call));
+    #[cfg(feature = "syscall-latency")]
+    crate::syscall_latency::record(args[0], crate::arch::cycles().wrapping_sub(start));
+    #[cfg(baremetal)]
+    crate::crash::syscall_exit();
+    #[cfg(feature = "trace")]
+    if traced {
+        crate::trace::syscall_exit(pid, tid, &result);
+    }
+    match &result {
+        Err(e) => klog!(
+            Syscalls,
+            Errors,
+            "KERNEL({}:{}): Syscall {} ({:08x} {:08x} {:08x} {:08x} {:08x} {:08x} {:08x}) failed: {:?}",
+            pid,
+            tid,
+            args[0],
+            args[1],
+            args[2],
+            args[3],
+            args[4],
+            args[5],
+            args[6],
+            args[7],
+            e
+        ),
+        Ok(outcome) => klog!(Syscalls, Info, "KERNEL({}:{}): -> {:?}", pid, tid, outcome),
+    }
     result
 }
 
 pub fn handle_inner(pid: PID, tid: TID, call: SysCall) -> SysCallResult {
     // let pid = arch::current_pid();
+    crate::validate::syscall(pid, &call)?;
 
     match call {
         SysCall::MapMemory(phys, virt, size, req_flags) => {
+            SystemServices::with(|ss| {
+                if phys.is_some() {
+                    ss.check_capability(pid, Capabilities::PHYSICAL_MEMORY)?;
+                }
+                ss.check_wx(pid, req_flags)
+            })?;
             MemoryManager::with_mut(|mm| {
                 let phys_ptr = phys
                     .map(|x| x.get() as *mut u8)
@@ -429,18 +1037,21 @@ pub fn handle_inner(pid: PID, tid: TID, call: SysCall) -> SysCallResult {
                     .map(|x| x.get() as *mut u8)
                     .unwrap_or(core::ptr::null_mut());
 
-                // Don't let the address exceed the user area (unless it's PID 1)
-                if pid.get() != 1
-                    && virt
-                        .map(|x| x.get() >= arch::mem::USER_AREA_END)
-                        .unwrap_or(false)
+                // Don't allow a second mapping of memory that's already in
+                // use, even by this process, since finalized code or a page
+                // that's been borrowed could then be rewritten through it.
+                if phys.is_some() && mm.memory_in_use(phys_ptr as usize, size.get()) {
+                    return Err(xous_kernel::Error::MemoryInUse);
+
+                // Reserved addresses may only be filled in with `MapSegment`.
+                } else if virt.is_some()
+                    && crate::image::reserved(pid, virt_ptr as usize, size.get())
                 {
-                    return Err(xous_kernel::Error::BadAddress);
+                    return Err(xous_kernel::Error::MemoryInUse);
 
-                // Don't allow mapping non-page values
-                } else if size.get() & (PAGE_SIZE - 1) != 0 {
-                    // println!("map: bad alignment of size {:08x}", size);
-                    return Err(xous_kernel::Error::BadAlignment);
+                // Peripherals belong to the first process that maps them.
+                } else if phys.is_some() && mm.is_io(phys_ptr as usize, size.get()) {
+                    crate::mmio::claim(pid, phys_ptr as usize, size.get())?;
                 }
                 // println!(
                 //     "Mapping {:08x} -> {:08x} ({} bytes, flags: {:?})",
@@ -480,17 +1091,52 @@ pub fn handle_inner(pid: PID, tid: TID, call: SysCall) -> SysCallResult {
                             .expect("couldn't hand page to user");
                     }
                 }
+                if let Some(phys) = phys {
+                    crate::audit::record(AuditKind::PhysicalMapping, pid, phys.get(), size.get());
+                }
 
-                Ok(xous_kernel::Result::MemoryRange(range))
+                Ok(xous_kernel::Result::MemoryRange(range).into())
             })
         }
+        SysCall::ReserveAddressSpace(virt, size) => MemoryManager::with_mut(|mm| {
+            let start = virt.map(|x| x.get()).unwrap_or(0);
+            if virt.is_some()
+                && (start..start + size.get())
+                    .step_by(PAGE_SIZE)
+                    .any(|page| !crate::arch::mem::address_available(page))
+            {
+                return Err(xous_kernel::Error::MemoryInUse);
+            }
+            let start =
+                mm.find_virtual_address(start as *mut u8, size.get(), MemoryType::Default)?;
+            let range = MemoryRange::new(start as usize, size.get())?;
+            crate::image::reserve(pid, range)?;
+            Ok(xous_kernel::Result::MemoryRange(range).into())
+        }),
+        SysCall::MapSegment(base, offset, size, flags) => {
+            SystemServices::with(|ss| ss.check_wx(pid, flags))?;
+            let segment = crate::image::add_segment(pid, base.get(), offset, size.get(), flags)?;
+            MemoryManager::with_mut(|mm| {
+                mm.map_range(
+                    core::ptr::null_mut(),
+                    segment.as_mut_ptr(),
+                    segment.len(),
+                    pid,
+                    flags,
+                    MemoryType::Default,
+                )
+            })
+            .map(|range| xous_kernel::Result::MemoryRange(range).into())
+            .inspect_err(|_| crate::image::remove_segment(pid, segment.addr.get()))
+        }
+        SysCall::ShareSegment(src, dest_pid, dest_base) => SystemServices::with_mut(|ss| {
+            ss.share_segment(pid, src.get(), dest_pid, dest_base.get())
+                .map(|range| xous_kernel::Result::MemoryRange(range).into())
+        }),
         SysCall::UnmapMemory(range) => MemoryManager::with_mut(|mm| {
-            let mut result = Ok(xous_kernel::Result::Ok);
+            let mut result = Ok(xous_kernel::Result::Ok.into());
             let virt = range.as_ptr() as usize;
             let size = range.len();
-            if virt & 0xfff != 0 {
-                return Err(xous_kernel::Error::BadAlignment);
-            }
             for addr in (virt..(virt + size)).step_by(PAGE_SIZE) {
                 if let Err(e) = mm.unmap_page(addr as *mut usize) {
                     if result.is_ok() {
@@ -504,10 +1150,12 @@ pub fn handle_inner(pid: PID, tid: TID, call: SysCall) -> SysCallResult {
             if delta & 0xfff != 0 {
                 return Err(xous_kernel::Error::BadAlignment);
             }
+            SystemServices::with(|ss| ss.check_wx(pid, flags))?;
             let start = {
                 ArchProcess::with_inner_mut(|process_inner| {
-                    if process_inner.mem_heap_size + delta > process_inner.mem_heap_max {
-                        return Err(xous_kernel::Error::OutOfMemory);
+                    match process_inner.mem_heap_size.checked_add(delta) {
+                        Some(size) if size <= process_inner.mem_heap_max => (),
+                        _ => return Err(xous_kernel::Error::OutOfMemory),
                     }
 
                     let start = process_inner.mem_heap_base + process_inner.mem_heap_size;
@@ -518,7 +1166,8 @@ pub fn handle_inner(pid: PID, tid: TID, call: SysCall) -> SysCallResult {
             MemoryManager::with_mut(|mm| {
                 Ok(xous_kernel::Result::MemoryRange(
                     mm.reserve_range(start, delta, flags)?,
-                ))
+                )
+                .into())
             })
         }
         SysCall::DecreaseHeap(delta) => {
@@ -526,8 +1175,8 @@ pub fn handle_inner(pid: PID, tid: TID, call: SysCall) -> SysCallResult {
                 return Err(xous_kernel::Error::BadAlignment);
             }
             let start = ArchProcess::with_inner_mut(|process_inner| {
-                if process_inner.mem_heap_size + delta > process_inner.mem_heap_max {
-                    return Err(xous_kernel::Error::OutOfMemory);
+                if delta > process_inner.mem_heap_size {
+                    return Err(xous_kernel::Error::BadAddress);
                 }
 
                 let start = process_inner.mem_heap_base + process_inner.mem_heap_size;
@@ -540,66 +1189,261 @@ pub fn handle_inner(pid: PID, tid: TID, call: SysCall) -> SysCallResult {
                         .expect("unable to unmap page");
                 }
             });
-            Ok(xous_kernel::Result::Ok)
-        }
-        SysCall::SwitchTo(new_pid, new_context) => {
-            SystemServices::with_mut(|ss| {
-                unsafe {
-                    assert!(
-                        SWITCHTO_CALLER.is_none(),
-                        "SWITCHTO_CALLER was not None, indicating SwitchTo was called twice"
-                    );
-                    SWITCHTO_CALLER = Some((pid, tid));
-                }
-                ss.activate_process_thread(tid, new_pid, new_context, true)
-                    .map(|_ctx| {
-                        // println!("switchto ({}, {})", pid, _ctx);
-                        xous_kernel::Result::ResumeProcess
-                    })
-            })
+            Ok(xous_kernel::Result::Ok.into())
+        }
+        SysCall::UpdateMemoryFlags(_virt, _count, flags) => {
+            // Changing flags is not yet supported, but make sure nobody can
+            // use it to sneak a W+X mapping past the check in `MapMemory`.
+            SystemServices::with(|ss| ss.check_wx(pid, flags))?;
+            Err(xous_kernel::Error::UnhandledSyscall)
         }
+        SysCall::SwitchTo(new_pid, new_context) => SystemServices::with_mut(|ss| {
+            let new_tid = ss.activate_process_thread(tid, new_pid, new_context, true)?;
+            // println!("switchto ({}, {})", new_pid, new_tid);
+            ss.set_switched_from(new_pid, new_tid, (pid, tid));
+            Ok(SysCallOutcome::Resume)
+        }),
         SysCall::ClaimInterrupt(no, callback, arg) => {
-            interrupt_claim(no, pid as definitions::PID, callback, arg)
-                .map(|_| xous_kernel::Result::Ok)
+            SystemServices::with(|ss| ss.check_capability(pid, Capabilities::INTERRUPTS))?;
+            interrupt_claim(no, pid as definitions::PID, callback, arg)?;
+            crate::audit::record(AuditKind::InterruptClaimed, pid, no, 0);
+            Ok(xous_kernel::Result::Ok.into())
         }
-        SysCall::Yield => {
-            // If we're not running on bare metal, treat this as a no-op.
-            if !cfg!(baremetal) {
-                return Ok(xous_kernel::Result::Ok);
+        SysCall::ClaimInterruptMessage(no, cid, opcode) => SystemServices::with_mut(|ss| {
+            ss.check_capability(pid, Capabilities::INTERRUPTS)?;
+            let sidx = ss
+                .sidx_from_cid(cid)
+                .ok_or(xous_kernel::Error::ServerNotFound)?;
+            let sid = ss
+                .server_from_sidx(sidx)
+                .ok_or(xous_kernel::Error::ServerNotFound)?
+                .sid;
+            crate::irq::interrupt_claim_message(no, pid, tid, sid, opcode)?;
+            crate::audit::record(AuditKind::InterruptClaimed, pid, no, 0);
+            Ok(xous_kernel::Result::Ok.into())
+        }),
+        SysCall::CreateTimer(cid, opcode, delay, period) => SystemServices::with_mut(|ss| {
+            let sidx = ss
+                .sidx_from_cid(cid)
+                .ok_or(xous_kernel::Error::ServerNotFound)?;
+            let sid = ss
+                .server_from_sidx(sidx)
+                .ok_or(xous_kernel::Error::ServerNotFound)?
+                .sid;
+            crate::timers::create(pid, tid, sid, opcode, delay, period)
+                .map(|id| xous_kernel::Result::Scalar1(id).into())
+        }),
+        SysCall::SleepThread(ms) => sleep_thread(pid, tid, ms),
+        SysCall::CancelTimer(id) => {
+            crate::timers::cancel(id, pid).map(|_| xous_kernel::Result::Ok.into())
+        }
+        SysCall::RaiseIrq(no) => {
+            SystemServices::with(|ss| ss.check_capability(pid, Capabilities::INTERRUPTS))
+                .and_then(|_| crate::irq::raise(no))
+                .map(|_| xous_kernel::Result::Ok.into())
+        }
+        SysCall::SetSyscallFilter(target, filter) => SystemServices::with_mut(|ss| {
+            ss.set_syscall_filter(pid, target, filter)
+                .map(|_| xous_kernel::Result::Ok.into())
+        }),
+        SysCall::AssignIoRegion(target, phys, size) => SystemServices::with(|ss| {
+            ss.check_capability(pid, Capabilities::IO_REGIONS)?;
+            if phys.get() & (PAGE_SIZE - 1) != 0 || size.get() & (PAGE_SIZE - 1) != 0 {
+                return Err(xous_kernel::Error::BadAlignment);
             }
-
-            let (parent_pid, parent_ctx) = unsafe {
-                SWITCHTO_CALLER
-                    .take()
-                    .expect("yielded when no parent context was present")
-            };
-            SystemServices::with_mut(|ss| {
-                // TODO: Advance thread
-                ss.activate_process_thread(tid, parent_pid, parent_ctx, true)
-                    .map(|_| Ok(xous_kernel::Result::ResumeProcess))
-                    .unwrap_or(Err(xous_kernel::Error::ProcessNotFound))
+            if ss
+                .processes
+                .get(target.get() as usize - 1)
+                .map(|process| process.free())
+                .unwrap_or(true)
+            {
+                return Err(xous_kernel::Error::ProcessNotFound);
+            }
+            crate::mmio::claim(target, phys.get(), size.get())
+                .map(|_| xous_kernel::Result::Ok.into())
+        }),
+        SysCall::GetRandom => {
+            let mut a = [0u8; mem::size_of::<usize>()];
+            let mut b = [0u8; mem::size_of::<usize>()];
+            crate::entropy::fill(&mut a)
+                .and_then(|_| crate::entropy::fill(&mut b))
+                .map(|_| {
+                    xous_kernel::Result::Scalar2(usize::from_le_bytes(a), usize::from_le_bytes(b))
+                        .into()
+                })
+        }
+        SysCall::AddEntropy(a1, a2, a3, a4) => {
+            for word in [a1, a2, a3, a4].iter() {
+                crate::entropy::add(&word.to_le_bytes());
+            }
+            Ok(xous_kernel::Result::Ok.into())
+        }
+        SysCall::MakeServerPrivate(sid) => SystemServices::with_mut(|ss| {
+            ss.make_server_private(pid, sid)
+                .map(|_| xous_kernel::Result::Ok.into())
+        }),
+        SysCall::AllowConnect(sid, client) => SystemServices::with_mut(|ss| {
+            ss.allow_connect(pid, sid, client)
+                .map(|_| xous_kernel::Result::Ok.into())
+        }),
+        SysCall::ReadAuditLog(sequence) => {
+            SystemServices::with(|ss| ss.check_capability(pid, Capabilities::AUDIT))?;
+            Ok(match crate::audit::read(sequence) {
+                Some(event) => xous_kernel::Result::AuditEvent(event).into(),
+                None => xous_kernel::Result::Ok.into(),
             })
         }
-        SysCall::ReturnToParentI(_pid, _cpuid) => {
-            unsafe {
-                let (_current_pid, _current_ctx) = crate::arch::irq::take_isr_return_pair()
-                    .expect("couldn't get the isr return pair");
-                // ss.ready_context(current_pid, current_ctx).unwrap();
-                let (parent_pid, parent_ctx) = SWITCHTO_CALLER
-                    .take()
-                    .expect("ReturnToParentI called with no existing parent present");
-                crate::arch::irq::set_isr_return_pair(parent_pid, parent_ctx);
-            };
-            Ok(xous_kernel::Result::ResumeProcess)
+        SysCall::SetCrashSupervisor(id) => SystemServices::with(|ss| {
+            ss.check_capability(pid, Capabilities::SUPERVISE)?;
+            if id != 0 {
+                crate::notify::check_owner(id, pid)?;
+            }
+            crate::fault::set_supervisor(pid, id);
+            Ok(xous_kernel::Result::Ok.into())
+        }),
+        SysCall::ReadCrashReport(sequence) => Ok(match crate::fault::read(pid, sequence) {
+            Some(report) => xous_kernel::Result::CrashReport(report).into(),
+            None => xous_kernel::Result::Ok.into(),
+        }),
+        SysCall::ReadCrashWord(sequence, index) => crate::fault::word(pid, sequence, index)
+            .map(|word| xous_kernel::Result::Scalar1(word).into()),
+        SysCall::TakeCorePage(sequence) => Ok(match crate::fault::take_core_page(pid, sequence)? {
+            Some((range, addr)) => xous_kernel::Result::CorePage(range, addr).into(),
+            None => xous_kernel::Result::Ok.into(),
+        }),
+        #[cfg(feature = "trace")]
+        SysCall::ReadTrace => {
+            SystemServices::with(|ss| ss.check_capability(pid, Capabilities::TRACE))?;
+            Ok(match crate::trace::take() {
+                Some(event) => xous_kernel::Result::TraceEvent(event).into(),
+                None => xous_kernel::Result::Ok.into(),
+            })
+        }
+        #[cfg(not(feature = "trace"))]
+        SysCall::ReadTrace => Err(xous_kernel::Error::UnhandledSyscall),
+        #[cfg(feature = "syscall-latency")]
+        SysCall::ReadSyscallLatency(number) => {
+            SystemServices::with(|ss| ss.check_capability(pid, Capabilities::TRACE))?;
+            crate::syscall_latency::summary(number)
+                .map(|latency| xous_kernel::Result::SyscallLatency(latency).into())
+        }
+        #[cfg(feature = "syscall-latency")]
+        SysCall::ReadLatencyBucket(number, bucket) => {
+            SystemServices::with(|ss| ss.check_capability(pid, Capabilities::TRACE))?;
+            crate::syscall_latency::bucket(number, bucket)
+                .map(|count| xous_kernel::Result::Scalar1(count).into())
+        }
+        #[cfg(not(feature = "syscall-latency"))]
+        SysCall::ReadSyscallLatency(_) | SysCall::ReadLatencyBucket(_, _) => {
+            Err(xous_kernel::Error::UnhandledSyscall)
+        }
+        #[cfg(feature = "profile")]
+        SysCall::SetProfiling(running) => {
+            SystemServices::with(|ss| ss.check_capability(pid, Capabilities::TRACE))?;
+            crate::profile::set_running(running);
+            Ok(xous_kernel::Result::Ok.into())
+        }
+        #[cfg(feature = "profile")]
+        SysCall::ReadProfileSample => {
+            SystemServices::with(|ss| ss.check_capability(pid, Capabilities::TRACE))?;
+            Ok(match crate::profile::take() {
+                Some(sample) => xous_kernel::Result::ProfileSample(sample).into(),
+                None => xous_kernel::Result::Ok.into(),
+            })
+        }
+        #[cfg(not(feature = "profile"))]
+        SysCall::SetProfiling(_) | SysCall::ReadProfileSample => {
+            Err(xous_kernel::Error::UnhandledSyscall)
+        }
+        SysCall::SetLogLevel(subsystem, level) => {
+            SystemServices::with(|ss| ss.check_capability(pid, Capabilities::TRACE))?;
+            let previous = crate::log::set_level(subsystem, level);
+            Ok(xous_kernel::Result::Scalar1(previous as usize).into())
+        }
+        SysCall::EnableIrq(no) => {
+            crate::irq::interrupt_mask(no, pid, false).map(|_| xous_kernel::Result::Ok.into())
+        }
+        SysCall::DisableIrq(no) => {
+            crate::irq::interrupt_mask(no, pid, true).map(|_| xous_kernel::Result::Ok.into())
+        }
+        SysCall::FreeInterrupt(no) => {
+            interrupt_free(no, pid).map(|_| xous_kernel::Result::Ok.into())
+        }
+        SysCall::Yield => yield_slice(pid, tid),
+        SysCall::YieldTo(target_pid, target_tid) => yield_to(pid, tid, target_pid, target_tid),
+        SysCall::SetConnectionData(sender, data) => SystemServices::with_mut(|ss| {
+            let sender = SenderID::from(sender);
+            let sidx = ss
+                .sidx_from_cid(sender.cid)
+                .ok_or(xous_kernel::Error::ServerNotFound)?;
+            let server = ss
+                .server_from_sidx_mut(sidx)
+                .ok_or(xous_kernel::Error::ServerNotFound)?;
+            if server.pid != pid {
+                return Err(xous_kernel::Error::ServerNotFound);
+            }
+            let client = sender.pid.ok_or(xous_kernel::Error::ProcessNotFound)?;
+            server
+                .set_connection_data(client, data)
+                .map(|_| xous_kernel::Result::Ok.into())
+        }),
+        SysCall::CreateNotification => {
+            crate::notify::create(pid).map(|id| xous_kernel::Result::Scalar1(id).into())
+        }
+        SysCall::SignalNotification(id, count) => signal_notification(id, count),
+        SysCall::WaitNotification(id) => wait_notification(pid, tid, id),
+        SysCall::PollNotification(id) => crate::notify::poll(id, pid)
+            .map(|signalled| xous_kernel::Result::Scalar1(signalled as usize).into()),
+        SysCall::CreatePollSet => {
+            crate::poll::create(pid).map(|id| xous_kernel::Result::Scalar1(id).into())
+        }
+        SysCall::AddToPollSet(id, sid) => SystemServices::with_mut(|ss| {
+            let sidx = ss
+                .server_sidx(sid)
+                .ok_or(xous_kernel::Error::ServerNotFound)?;
+            let server = ss
+                .server_from_sidx(sidx)
+                .ok_or(xous_kernel::Error::ServerNotFound)?;
+            if server.pid != pid {
+                return Err(xous_kernel::Error::ServerNotFound);
+            }
+            crate::poll::add(id, pid, sid)?;
+            ss.connect_to_own_server(sid)
+                .map(|cid| xous_kernel::Result::Scalar1(cid).into())
+        }),
+        SysCall::ReceiveAny(id) => receive_any(pid, tid, id),
+        SysCall::GetKernelConfig => {
+            Ok(xous_kernel::Result::KernelConfig(crate::config::get()).into())
+        }
+        SysCall::ReturnToParentI(_pid, cpuid) => {
+            if cpuid >= arch::MAX_HARTS {
+                return Err(xous_kernel::Error::InvalidSyscall);
+            }
+            // If the interrupted thread wasn't switched to by a parent, there
+            // is nothing to return to and it simply keeps running.
+            let interrupted = unsafe { crate::arch::irq::isr_return_pair() };
+            if let Some((current_pid, current_ctx)) = interrupted {
+                SystemServices::with_mut(|ss| {
+                    if let Some((parent_pid, parent_ctx)) =
+                        ss.take_switched_from(current_pid, current_ctx)
+                    {
+                        // ss.ready_context(current_pid, current_ctx).unwrap();
+                        unsafe { crate::arch::irq::set_isr_return_pair(parent_pid, parent_ctx) };
+                        ss.account_switch_out(current_pid, current_ctx, true);
+                        ss.account_switch_in(parent_pid, parent_ctx);
+                    }
+                });
+            }
+            Ok(SysCallOutcome::Resume)
         }
         SysCall::ReceiveMessage(sid) => receive_message(pid, tid, sid),
         SysCall::WaitEvent => SystemServices::with_mut(|ss| {
             let process = ss.get_process(pid).expect("Can't get current process");
             let ppid = process.ppid;
-            unsafe { SWITCHTO_CALLER = None };
+            ss.take_switched_from(pid, tid);
             // TODO: Advance thread
             ss.activate_process_thread(tid, ppid, 0, false)
-                .map(|_| Ok(xous_kernel::Result::ResumeProcess))
+                .map(|_| Ok(SysCallOutcome::Resume))
                 .unwrap_or(Err(xous_kernel::Error::ProcessNotFound))
         }),
         SysCall::CreateThread(thread_init) => SystemServices::with_mut(|ss| {
@@ -608,44 +1452,275 @@ pub fn handle_inner(pid: PID, tid: TID, call: SysCall) -> SysCallResult {
                     ss.switch_to_thread(pid, Some(new_tid))
                         .expect("couldn't activate new thread");
                 }
-                xous_kernel::Result::ThreadID(new_tid)
+                xous_kernel::Result::ThreadID(new_tid).into()
             })
         }),
         SysCall::CreateProcess(process_init) => SystemServices::with_mut(|ss| {
-            ss.create_process(process_init)
-                .map(xous_kernel::Result::ProcessID)
+            let pid = ss.create_process(process_init)?;
+            // A hosted kernel starts the program for the new process itself.
+            #[cfg(not(baremetal))]
+            if let Some(command) = process_init.command {
+                if let Err(e) = crate::arch::start_command(pid, command) {
+                    ss.terminate_process(pid)?;
+                    return Err(e);
+                }
+            }
+            Ok(xous_kernel::Result::ProcessID(pid).into())
         }),
         SysCall::CreateServer(name) => SystemServices::with_mut(|ss| {
-            ss.create_server(pid, name)
-                .map(|(sid, cid)| xous_kernel::Result::NewServerID(sid, cid))
+            ss.create_server(pid, name, crate::server::DEFAULT_QUEUE_LENGTH)
+                .map(|(sid, cid)| xous_kernel::Result::NewServerID(sid, cid).into())
+        }),
+        SysCall::CreateServerWithQueue(name, queue_length) => SystemServices::with_mut(|ss| {
+            ss.create_server(pid, name, queue_length)
+                .map(|(sid, cid)| xous_kernel::Result::NewServerID(sid, cid).into())
         }),
         SysCall::TryConnect(sid) => SystemServices::with_mut(|ss| {
             ss.connect_to_server(sid)
-                .map(xous_kernel::Result::ConnectionID)
+                .map(|cid| xous_kernel::Result::ConnectionID(cid).into())
+        }),
+        SysCall::FinalizeCode(range) => MemoryManager::with_mut(|mm| {
+            mm.finalize_code(range)
+                .map(|_| xous_kernel::Result::Ok.into())
+        }),
+        SysCall::Disconnect(cid) => SystemServices::with_mut(|ss| {
+            ss.disconnect_from_server(cid)
+                .map(|_| xous_kernel::Result::Ok.into())
         }),
         SysCall::ReturnMemory(sender, buf) => return_memory(pid, tid, sender, buf),
         SysCall::ReturnScalar1(sender, arg) => return_scalar(pid, tid, sender, arg),
+        SysCall::ForwardMessage(sender, cid, message) => forward_message(pid, sender, cid, message),
+        SysCall::NextOutstandingSender(sid, slot) => SystemServices::with_mut(|ss| {
+            let sidx = ss
+                .server_sidx(sid)
+                .ok_or(xous_kernel::Error::ServerNotFound)?;
+            let server = ss
+                .server_from_sidx(sidx)
+                .ok_or(xous_kernel::Error::ServerNotFound)?;
+            if server.pid != pid {
+                return Err(xous_kernel::Error::ServerNotFound);
+            }
+            let outstanding = server.next_outstanding(slot);
+            let cid = ss.connect_to_own_server(sid)?;
+            let sender = outstanding
+                .map(|(idx, client)| {
+                    SenderID {
+                        cid,
+                        idx,
+                        pid: Some(client),
+                    }
+                    .into()
+                })
+                .unwrap_or(0);
+            Ok(xous_kernel::Result::Scalar1(sender).into())
+        }),
+        SysCall::WatchDisconnect(cid, notification) => SystemServices::with_mut(|ss| {
+            if notification != 0 {
+                crate::notify::check_owner(notification, pid)?;
+            }
+            let sidx = ss
+                .sidx_from_cid(cid)
+                .ok_or(xous_kernel::Error::ServerNotFound)?;
+            ss.server_from_sidx_mut(sidx)
+                .ok_or(xous_kernel::Error::ServerNotFound)?
+                .watch_disconnect(pid, notification)
+                .map(|_| xous_kernel::Result::Ok.into())
+        }),
         SysCall::ReturnScalar2(sender, arg1, arg2) => return_scalar2(pid, tid, sender, arg1, arg2),
         // SysCall::ReturnScalar2(sender, arg, arg2) => return_memory(pid, tid, sender, arg, arg2),
         SysCall::TrySendMessage(cid, message) => send_message(pid, tid, cid, message),
+        SysCall::CallMessage(cid, message) => call_message(pid, tid, cid, message),
+        SysCall::CreateBroadcast => {
+            crate::broadcast::create(pid).map(|id| xous_kernel::Result::Scalar1(id).into())
+        }
+        SysCall::Subscribe(id, cid, opcode) => SystemServices::with_mut(|ss| {
+            let sidx = ss
+                .sidx_from_cid(cid)
+                .ok_or(xous_kernel::Error::ServerNotFound)?;
+            let sid = ss
+                .server_from_sidx(sidx)
+                .ok_or(xous_kernel::Error::ServerNotFound)?
+                .sid;
+            crate::broadcast::subscribe(id, pid, sid, opcode)
+                .map(|_| xous_kernel::Result::Ok.into())
+        }),
+        SysCall::Unsubscribe(id, cid) => SystemServices::with_mut(|ss| {
+            let sidx = ss
+                .sidx_from_cid(cid)
+                .ok_or(xous_kernel::Error::ServerNotFound)?;
+            let sid = ss
+                .server_from_sidx(sidx)
+                .ok_or(xous_kernel::Error::ServerNotFound)?
+                .sid;
+            crate::broadcast::unsubscribe(id, pid, sid).map(|_| xous_kernel::Result::Ok.into())
+        }),
+        SysCall::Broadcast(id, arg1, arg2, arg3, arg4) => {
+            broadcast(pid, tid, id, [arg1, arg2, arg3, arg4])
+        }
         SysCall::TerminateProcess => SystemServices::with_mut(|ss| {
             ss.switch_from_thread(pid, tid)?;
             let ppid = ss.terminate_process(pid)?;
             if cfg!(baremetal) {
                 ss.switch_to_thread(ppid, None)
-                    .map(|_| xous_kernel::Result::ResumeProcess)
+                    .map(|_| SysCallOutcome::Resume)
             } else {
-                Ok(xous_kernel::Result::Ok)
+                Ok(xous_kernel::Result::Ok.into())
+            }
+        }),
+        SysCall::Shutdown(scrub) => {
+            SystemServices::with(|ss| ss.check_capability(pid, Capabilities::SHUTDOWN))?;
+            crate::shutdown::begin(pid, tid, scrub, None)?;
+            // A hosted kernel answers the caller first, since going down
+            // closes its connection, and checks again once it has.
+            if cfg!(baremetal) {
+                crate::shutdown::check();
+            }
+            Ok(xous_kernel::Result::Ok.into())
+        }
+        SysCall::Reboot(mode) => {
+            SystemServices::with(|ss| ss.check_capability(pid, Capabilities::SHUTDOWN))?;
+            crate::shutdown::begin(pid, tid, ScrubLevel::None, Some(mode))?;
+            if cfg!(baremetal) {
+                crate::shutdown::check();
+            }
+            Ok(xous_kernel::Result::Ok.into())
+        }
+        SysCall::Suspend => {
+            SystemServices::with(|ss| ss.check_capability(pid, Capabilities::SHUTDOWN))?;
+            suspend(pid, tid)
+        }
+        SysCall::SetClockState(domain, state) => {
+            SystemServices::with(|ss| ss.check_capability(pid, Capabilities::POWER))?;
+            crate::power::set_clock_state(pid, domain, state)
+                .map(|running| xous_kernel::Result::Scalar1(running as usize).into())
+        }
+        SysCall::RequestCpuFrequency(hz) => {
+            SystemServices::with(|ss| ss.check_capability(pid, Capabilities::POWER))?;
+            crate::power::request_cpu_frequency(pid, hz)
+                .map(|hz| xous_kernel::Result::Scalar1(hz).into())
+        }
+        SysCall::AllowJit(target) => SystemServices::with_mut(|ss| {
+            ss.check_capability(pid, Capabilities::ALLOW_JIT)?;
+            ss.allow_jit(target).map(|_| xous_kernel::Result::Ok.into())
+        }),
+        #[cfg(feature = "coverage")]
+        SysCall::DumpCoverage => {
+            for point in xous_kernel::coverage::points() {
+                println!("COV {} {}", point.hits(), point.name());
+                point.reset();
+            }
+            Ok(xous_kernel::Result::Ok.into())
+        }
+        #[cfg(not(feature = "coverage"))]
+        SysCall::DumpCoverage => Err(xous_kernel::Error::UnhandledSyscall),
+        SysCall::SetExceptionHandler(handler) => SystemServices::with_mut(|ss| {
+            ss.set_exception_handler(pid, handler)
+                .map(|_| xous_kernel::Result::Ok.into())
+        }),
+        SysCall::SetExceptionStack(stack) => SystemServices::with_mut(|ss| {
+            ss.set_exception_stack(pid, tid, stack)
+                .map(|_| xous_kernel::Result::Ok.into())
+        }),
+        #[cfg(feature = "print-mappings")]
+        SysCall::DumpMemoryMap(target) => SystemServices::with(|ss| {
+            ss.print_mappings(target)
+                .map(|_| xous_kernel::Result::Ok.into())
+        }),
+        #[cfg(not(feature = "print-mappings"))]
+        SysCall::DumpMemoryMap(_) => Err(xous_kernel::Error::UnhandledSyscall),
+        SysCall::BootStageTime(stage) => {
+            let time = crate::boot::time(stage);
+            Ok(xous_kernel::Result::Scalar2(time as u32 as usize, (time >> 32) as usize).into())
+        }
+        SysCall::MessageTimestamp => SystemServices::with(|ss| {
+            let time = ss.message_timestamp(pid, tid)?;
+            Ok(xous_kernel::Result::Scalar2(time as u32 as usize, (time >> 32) as usize).into())
+        }),
+        SysCall::SetLendTimeout(ticks) => Ok(xous_kernel::Result::Scalar1(
+            crate::lends::set_timeout(pid, ticks as u64) as usize,
+        )
+        .into()),
+        SysCall::GetTimebase => arch::timebase()
+            .map(|hz| xous_kernel::Result::Scalar1(hz).into())
+            .ok_or(xous_kernel::Error::UnhandledSyscall),
+        SysCall::GetTicks => arch::ticks()
+            .map(|ticks| {
+                xous_kernel::Result::Scalar2(ticks as u32 as usize, (ticks >> 32) as usize).into()
+            })
+            .ok_or(xous_kernel::Error::UnhandledSyscall),
+        SysCall::SetPriority(priority) => SystemServices::with_mut(|ss| {
+            ss.set_priority(pid, priority)
+                .map(|effective| xous_kernel::Result::Scalar1(effective as usize).into())
+        }),
+        SysCall::SetQuantum(class, ticks) => {
+            SystemServices::with(|ss| ss.check_capability(pid, Capabilities::SCHEDULING))?;
+            crate::preempt::set_quantum(class, ticks)
+                .map(|previous| xous_kernel::Result::Scalar1(previous).into())
+        }
+        SysCall::SetDeadline(period, budget) => {
+            // Anyone may give up a reservation.
+            if period != 0 || budget != 0 {
+                SystemServices::with(|ss| ss.check_capability(pid, Capabilities::SCHEDULING))?;
             }
+            crate::deadline::set(pid, tid, period, budget).map(|_| xous_kernel::Result::Ok.into())
+        }
+        SysCall::QueryLatency(cid, stage, percentile) => SystemServices::with(|ss| {
+            let sidx = ss
+                .sidx_from_cid(cid)
+                .ok_or(xous_kernel::Error::ServerNotFound)?;
+            let server = ss
+                .server_from_sidx(sidx)
+                .ok_or(xous_kernel::Error::ServerNotFound)?;
+            let cap = |value: u64| core::cmp::min(value, usize::MAX as u64) as usize;
+            server.latency(stage, percentile).map(|(latency, count)| {
+                xous_kernel::Result::Scalar2(cap(latency), cap(count)).into()
+            })
         }),
-        SysCall::Shutdown => {
-            SystemServices::with_mut(|ss| ss.shutdown().map(|_| xous_kernel::Result::Ok))
+        SysCall::QueryServices(index) => SystemServices::with(|ss| {
+            ss.server_info(pid, index)
+                .map(|info| xous_kernel::Result::ServerInfo(info).into())
+        }),
+        SysCall::SetHeartbeat(ticks) => {
+            crate::watchdog::set_interval(pid, ticks as u64).map(|_| xous_kernel::Result::Ok.into())
+        }
+        SysCall::Heartbeat => crate::watchdog::beat(pid).map(|_| xous_kernel::Result::Ok.into()),
+        SysCall::SetWatchdogSupervisor(id) => SystemServices::with(|ss| {
+            ss.check_capability(pid, Capabilities::SUPERVISE)?;
+            if id != 0 {
+                crate::notify::check_owner(id, pid)?;
+            }
+            crate::watchdog::set_supervisor(pid, id);
+            Ok(xous_kernel::Result::Ok.into())
+        }),
+        SysCall::TakeMissedHeartbeat => crate::watchdog::take_missed(pid)?
+            .map(|missed| xous_kernel::Result::ProcessID(missed).into())
+            .ok_or(xous_kernel::Error::ProcessNotFound),
+        SysCall::QueryScheduler(target_pid, target_tid) => SystemServices::with(|ss| {
+            ss.thread_stats(target_pid, target_tid)
+                .map(|stats| xous_kernel::Result::ThreadStats(stats).into())
+        }),
+        SysCall::GetMeasurement(index, chunk) => {
+            if chunk >= crate::measure::MEASUREMENT_SIZE / 8 {
+                return Err(xous_kernel::Error::InvalidSyscall);
+            }
+            crate::measure::get(index).map(|measurement| {
+                let word = |offset: usize| {
+                    let mut bytes = [0u8; 4];
+                    bytes.copy_from_slice(&measurement[offset..offset + 4]);
+                    u32::from_le_bytes(bytes) as usize
+                };
+                xous_kernel::Result::Scalar2(word(chunk * 8), word(chunk * 8 + 4)).into()
+            })
         }
 
         // SysCall::Connect(sid) => {
         //     SystemServices::with_mut(|ss| ss.connect_to_server(sid).map(xous_kernel::Result::ConnectionID))
         // }
         // SysCall::SendMessage(cid, message) => send_message(pid, tid, cid, message),
-        _ => panic!("Unhandled Syscall: {:?}", call), //Err(xous_kernel::Error::UnhandledSyscall),
+
+        // `Connect` and `SendMessage` are built out of `TryConnect` and
+        // `TrySendMessage` by the process itself, but anyone can make the
+        // raw call.
+        _ => Err(xous_kernel::Error::UnhandledSyscall),
     }
 }
@@ -0,0 +1,101 @@
+//! Latency histograms for each syscall.  With the `syscall-latency` feature,
+//! the kernel reads the cycle counter on the way in and out of every
+//! syscall and adds the difference to a histogram kept for that syscall
+//! number, so that a change that slows down IPC shows up as numbers
+//! measured on hardware.  A process holding `Capabilities::TRACE` reads
+//! them back with `ReadSyscallLatency` and `ReadLatencyBucket`.
+//!
+//! Buckets are powers of two, which is coarse but cheap enough to do on
+//! every call, and is plenty to see a syscall get twice as slow.
+
+use xous_kernel::{SyscallLatency, LATENCY_BUCKETS};
+
+/// Every syscall number is below this.  Calls with larger numbers are
+/// invalid, and aren't counted.
+const MAX_SYSCALLS: usize = 128;
+
+#[derive(Copy, Clone)]
+struct Histogram {
+    calls: u32,
+    total: u64,
+    max: u32,
+    buckets: [u32; LATENCY_BUCKETS],
+}
+
+const EMPTY: Histogram = Histogram {
+    calls: 0,
+    total: 0,
+    max: 0,
+    buckets: [0; LATENCY_BUCKETS],
+};
+
+#[cfg(baremetal)]
+static mut HISTOGRAMS: [Histogram; MAX_SYSCALLS] = [EMPTY; MAX_SYSCALLS];
+
+#[cfg(not(baremetal))]
+std::thread_local!(static HISTOGRAMS: core::cell::RefCell<[Histogram; MAX_SYSCALLS]> =
+    core::cell::RefCell::new([EMPTY; MAX_SYSCALLS]));
+
+fn with_mut<F, R>(f: F) -> R
+where
+    F: FnOnce(&mut [Histogram; MAX_SYSCALLS]) -> R,
+{
+    #[cfg(baremetal)]
+    unsafe {
+        f(&mut HISTOGRAMS)
+    }
+
+    #[cfg(not(baremetal))]
+    HISTOGRAMS.with(|histograms| f(&mut histograms.borrow_mut()))
+}
+
+/// Note that a call to syscall `number` took `cycles`.
+pub fn record(number: usize, cycles: u64) {
+    let cycles = cycles.min(u32::MAX as u64) as u32;
+    // Bucket `n` starts at `2^n`, so it's one less than the number of bits
+    // needed to hold `cycles`.
+    let bucket = (32 - cycles.leading_zeros()).saturating_sub(1) as usize;
+    with_mut(|histograms| {
+        if let Some(histogram) = histograms.get_mut(number) {
+            histogram.calls = histogram.calls.saturating_add(1);
+            histogram.total = histogram.total.saturating_add(cycles as u64);
+            histogram.max = histogram.max.max(cycles);
+            let count = &mut histogram.buckets[bucket.min(LATENCY_BUCKETS - 1)];
+            *count = count.saturating_add(1);
+        }
+    })
+}
+
+/// How long the kernel has spent on syscall `number`.
+///
+/// # Errors
+///
+/// * **InvalidSyscall**: `number` is too large to be a syscall number
+pub fn summary(number: usize) -> Result<SyscallLatency, xous_kernel::Error> {
+    with_mut(|histograms| {
+        let histogram = histograms
+            .get(number)
+            .ok_or(xous_kernel::Error::InvalidSyscall)?;
+        Ok(SyscallLatency {
+            calls: histogram.calls as usize,
+            total: histogram.total,
+            max: histogram.max as usize,
+        })
+    })
+}
+
+/// The number of calls to syscall `number` that fell into `bucket`.
+///
+/// # Errors
+///
+/// * **InvalidSyscall**: `number` is too large to be a syscall number, or
+///                       `bucket` is past the end
+pub fn bucket(number: usize, bucket: usize) -> Result<usize, xous_kernel::Error> {
+    with_mut(|histograms| {
+        histograms
+            .get(number)
+            .and_then(|histogram| histogram.buckets.get(bucket))
+            .map(|count| *count as usize)
+            .ok_or(xous_kernel::Error::InvalidSyscall)
+    })
+}
@@ -1,10 +1,11 @@
 use crate::kmain;
 use std::thread::JoinHandle;
 
-use std::net::ToSocketAddrs;
-use std::sync::mpsc::channel;
-use xous_kernel::{rsyscall, SysCall};
+use std::sync::mpsc::{channel, Sender};
+use xous_kernel::{rsyscall, ClockState, RebootMode, ScrubLevel, SysCall, CID, PID, SID};
 
+mod emulate;
+mod harness;
 mod shutdown;
 
 #[cfg(feature = "report-memory")]
@@ -15,7 +16,24 @@ static GLOBAL: &StatsAlloc<std::alloc::System> = &INSTRUMENTED_SYSTEM;
 
 const SERVER_SPEC: &str = "127.0.0.1:0";
 
-fn start_kernel(server_spec: &str) -> JoinHandle<()> {
+fn start_kernel(server_spec: &str) -> JoinHandle<Option<RebootMode>> {
+    start_kernel_with_console(server_spec).0
+}
+
+/// Start a kernel whose debug console runs the lines sent to the returned
+/// channel.
+fn start_kernel_with_console(
+    server_spec: &str,
+) -> (JoinHandle<Option<RebootMode>>, Sender<String>) {
+    start_kernel_with(server_spec, || ())
+}
+
+/// Start a kernel, calling `setup` on its thread before it boots.  The
+/// kernel's thread returns how it was asked to start again, if it was.
+fn start_kernel_with(
+    server_spec: &str,
+    setup: impl FnOnce() + Send + 'static,
+) -> (JoinHandle<Option<RebootMode>>, Sender<String>) {
     assert!(
         std::env::var("XOUS_LISTEN_ADDR").is_err(),
         "XOUS_LISTEN_ADDR environment variable must be unset to run tests"
@@ -33,17 +51,15 @@ fn start_kernel(server_spec: &str) -> JoinHandle<()> {
     }
     xous_kernel::arch::set_process_key(&pid1_key);
 
-    let server_addr = server_spec
-        .to_socket_addrs()
-        .expect("invalid server address")
-        .next()
-        .expect("unable to resolve server address");
+    let server_addr =
+        xous_kernel::arch::transport::Address::parse(server_spec).expect("invalid server address");
     // Attempt to bind. This will fail if the port is in use.
     // let temp_server = TcpListener::bind(server_addr).unwrap();
     // let server_addr = temp_server.local_addr().unwrap();
     // drop(temp_server);
 
     let (send_addr, recv_addr) = channel();
+    let (console_send, console_recv) = channel();
 
     // Launch the main thread. We pass a `send_addr` channel so that the
     // server can notify us when it's ready to listen.
@@ -54,7 +70,10 @@ fn start_kernel(server_spec: &str) -> JoinHandle<()> {
             crate::arch::set_pid1_key(pid1_key);
             crate::arch::set_send_addr(send_addr);
             crate::arch::set_listen_address(&server_spec_server);
-            kmain()
+            crate::arch::set_console_input(console_recv);
+            setup();
+            kmain();
+            crate::arch::reboot_mode()
         })
         .expect("couldn't start kernel thread");
     let server_addr = recv_addr.recv().unwrap();
@@ -73,23 +92,28 @@ fn start_kernel(server_spec: &str) -> JoinHandle<()> {
     // }
     // // Convert the Option<conn> into conn
     // assert!(connected, "unable to connect to server");
-    main_thread
+    (main_thread, console_send)
 }
 
 fn shutdown_kernel() {
-    // Any process ought to be able to shut down the system currently.
     xous_kernel::wait_process_as_thread(
-        xous_kernel::create_process_as_thread(xous_kernel::ProcessArgsAsThread::new(
-            "shutdown",
-            || {
-                rsyscall(SysCall::Shutdown).expect("unable to shutdown server");
-            },
-        ))
+        xous_kernel::create_process_as_thread(
+            xous_kernel::ProcessArgsAsThread::new("shutdown", || {
+                rsyscall(SysCall::Shutdown(ScrubLevel::None)).expect("unable to shutdown server");
+            })
+            .capabilities(xous_kernel::Capabilities::SHUTDOWN),
+        )
         .expect("couldn't shut down the kernel"),
     )
     .expect("couldn't wait for the shutdown process to end");
 }
 
+/// Describe a process that may claim interrupts.
+fn driver_process<F: FnOnce()>(name: &str, main: F) -> xous_kernel::ProcessArgsAsThread<F> {
+    xous_kernel::ProcessArgsAsThread::new(name, main)
+        .capabilities(xous_kernel::Capabilities::INTERRUPTS)
+}
+
 // /// Spawn a new "process" with the given server spec inside the given closure
 // /// and return a join handle
 // fn as_process<F, R>(f: F) -> JoinHandle<R>
@@ -609,8 +633,8 @@ fn server_client_same_process() {
     xous_kernel::wait_process_as_thread(internal_server)
         .expect("couldn't join internal_server process");
 
-    // Any process ought to be able to shut down the system currently.
-    rsyscall(SysCall::Shutdown).expect("unable to shutdown server");
+    // PID 1 may always shut down the system.
+    rsyscall(SysCall::Shutdown(ScrubLevel::None)).expect("unable to shutdown server");
 
     main_thread.join().expect("couldn't join kernel process");
 }
@@ -735,7 +759,7 @@ fn process_restart_server() {
         xous_kernel::wait_process_as_thread(xous_client).expect("couldn't join client process");
     }
 
-    // create_destroy_server(test_bytes);
+    create_destroy_server(test_bytes);
     create_destroy_server(test_bytes);
 
     // Any process ought to be able to shut down the system currently.
@@ -743,3 +767,4442 @@ fn process_restart_server() {
 
     main_thread.join().expect("couldn't join kernel process");
 }
+
+/// Test that a process without the JIT capability can't map executable
+/// memory, and has to finalize code instead
+#[test]
+fn map_memory_wx_denied() {
+    let main_thread = start_kernel(SERVER_SPEC);
+
+    let xous_process = xous_kernel::create_process_as_thread(
+        xous_kernel::ProcessArgsAsThread::new("map_memory_wx_denied process", || {
+            use xous_kernel::MemoryFlags;
+            assert_eq!(
+                xous_kernel::map_memory(
+                    None,
+                    None,
+                    4096,
+                    MemoryFlags::R | MemoryFlags::W | MemoryFlags::X,
+                ),
+                Err(xous_kernel::Error::AccessDenied)
+            );
+            assert_eq!(
+                rsyscall(SysCall::IncreaseHeap(4096, MemoryFlags::W | MemoryFlags::X)),
+                Err(xous_kernel::Error::AccessDenied)
+            );
+            assert_eq!(
+                xous_kernel::map_memory(None, None, 4096, MemoryFlags::R | MemoryFlags::X),
+                Err(xous_kernel::Error::AccessDenied)
+            );
+            assert_eq!(
+                xous_kernel::finalize_code(
+                    xous_kernel::MemoryRange::new(0x2000_0800, 4096).unwrap()
+                ),
+                Err(xous_kernel::Error::BadAlignment)
+            );
+        }),
+    )
+    .expect("couldn't start process");
+
+    xous_kernel::wait_process_as_thread(xous_process).expect("couldn't join process");
+    shutdown_kernel();
+
+    main_thread.join().expect("couldn't join kernel process");
+}
+
+/// Test that only a process holding `Capabilities::ALLOW_JIT` can grant the
+/// JIT capability, and that a process holding it may map memory that is
+/// writable and executable
+#[test]
+fn map_memory_jit() {
+    use xous_kernel::MemoryFlags;
+
+    let main_thread = start_kernel(SERVER_SPEC);
+
+    let args = xous_kernel::ProcessArgsAsThread::new("map_memory_jit process", || {
+        xous_kernel::map_memory(
+            None,
+            None,
+            4096,
+            MemoryFlags::R | MemoryFlags::W | MemoryFlags::X,
+        )
+        .expect("couldn't map writable, executable memory");
+        assert_eq!(
+            xous_kernel::allow_jit(xous_kernel::PID::new(1).unwrap()),
+            Err(xous_kernel::Error::AccessDenied)
+        );
+    });
+    let init =
+        xous_kernel::arch::create_process_pre_as_thread(&args).expect("couldn't prepare process");
+    let jit_pid = match rsyscall(SysCall::CreateProcess(init)) {
+        Ok(xous_kernel::Result::ProcessID(pid)) => pid,
+        other => panic!("couldn't create process: {:?}", other),
+    };
+    xous_kernel::allow_jit(jit_pid).expect("couldn't grant the JIT capability");
+    assert_eq!(
+        xous_kernel::allow_jit(xous_kernel::PID::new(60).unwrap()),
+        Err(xous_kernel::Error::ProcessNotFound)
+    );
+    let jit_process = xous_kernel::arch::create_process_post_as_thread(args, init, jit_pid)
+        .expect("couldn't start process");
+
+    xous_kernel::wait_process_as_thread(jit_process).expect("couldn't join process");
+    shutdown_kernel();
+
+    main_thread.join().expect("couldn't join kernel process");
+}
+
+/// Test that exception stacks must lie within the user area
+#[test]
+fn set_exception_stack() {
+    let main_thread = start_kernel(SERVER_SPEC);
+
+    let xous_process = xous_kernel::create_process_as_thread(
+        xous_kernel::ProcessArgsAsThread::new("set_exception_stack process", || {
+            let stack = xous_kernel::MemoryRange::new(0x4000_0000, 4096).unwrap();
+            xous_kernel::set_exception_stack(Some(stack)).expect("couldn't set exception stack");
+            xous_kernel::set_exception_stack(None).expect("couldn't clear exception stack");
+
+            let kernel_stack = xous_kernel::MemoryRange::new(0xffff_0000, 4096).unwrap();
+            assert_eq!(
+                xous_kernel::set_exception_stack(Some(kernel_stack)),
+                Err(xous_kernel::Error::BadAddress)
+            );
+        }),
+    )
+    .expect("couldn't start process");
+
+    xous_kernel::wait_process_as_thread(xous_process).expect("couldn't join process");
+    shutdown_kernel();
+
+    main_thread.join().expect("couldn't join kernel process");
+}
+
+/// Test that hosted timestamps are reported as nanoseconds, and that the
+/// scheduler's start time was recorded
+#[test]
+fn timebase() {
+    let main_thread = start_kernel(SERVER_SPEC);
+
+    let xous_process = xous_kernel::create_process_as_thread(
+        xous_kernel::ProcessArgsAsThread::new("timebase process", || {
+            let timebase =
+                xous_kernel::timestamp::Timebase::get().expect("couldn't get timebase");
+            assert_eq!(timebase.ticks_per_second(), 1_000_000_000);
+            assert_eq!(timebase.to_us(1_500_000), 1_500);
+
+            let start = xous_kernel::timestamp::now();
+            assert!(xous_kernel::timestamp::now() >= start);
+
+            // Hosted kernels only pass through the scheduler stage.
+            let scheduler = xous_kernel::boot_stage_time(xous_kernel::BootStage::Scheduler)
+                .expect("couldn't get boot time");
+            assert!(scheduler != 0 && scheduler <= start);
+            assert_eq!(
+                xous_kernel::boot_stage_time(xous_kernel::BootStage::KernelEntry),
+                Ok(0)
+            );
+        }),
+    )
+    .expect("couldn't start process");
+
+    xous_kernel::wait_process_as_thread(xous_process).expect("couldn't join process");
+    shutdown_kernel();
+
+    main_thread.join().expect("couldn't join kernel process");
+}
+
+#[test]
+fn priority_inheritance() {
+    let main_thread = start_kernel(SERVER_SPEC);
+
+    let (server_addr_send, server_addr_recv) = channel();
+
+    let xous_server = xous_kernel::create_process_as_thread(
+        xous_kernel::ProcessArgsAsThread::new("priority_inheritance server", move || {
+            let sid = xous_kernel::create_server(b"priority_inherit")
+                .expect("couldn't create test server");
+            server_addr_send.send(sid).unwrap();
+            assert_eq!(xous_kernel::set_priority(1), Ok(1));
+
+            // While the client is blocked on us we run at its priority.
+            let envelope = xous_kernel::receive_message(sid).expect("couldn't receive messages");
+            let inherited = xous_kernel::set_priority(1).expect("couldn't set priority");
+            xous_kernel::return_scalar(envelope.sender, inherited as usize)
+                .expect("couldn't return scalar");
+
+            // Once it has been answered, we drop back to our own priority.
+            assert_eq!(xous_kernel::set_priority(1), Ok(1));
+        })
+        .capabilities(xous_kernel::Capabilities::SCHEDULING),
+    )
+    .expect("couldn't spawn server process");
+
+    let xous_client = xous_kernel::create_process_as_thread(
+        xous_kernel::ProcessArgsAsThread::new("priority_inheritance client", move || {
+            let sid = server_addr_recv.recv().unwrap();
+            let conn = xous_kernel::try_connect(sid).expect("couldn't connect to server");
+            assert_eq!(xous_kernel::set_priority(5), Ok(5));
+            let result = xous_kernel::try_send_message(
+                conn,
+                xous_kernel::Message::BlockingScalar(xous_kernel::ScalarMessage {
+                    id: 1,
+                    arg1: 0,
+                    arg2: 0,
+                    arg3: 0,
+                    arg4: 0,
+                }),
+            )
+            .expect("couldn't send message");
+            assert_eq!(result, xous_kernel::Result::Scalar1(5));
+        })
+        .capabilities(xous_kernel::Capabilities::SCHEDULING),
+    )
+    .expect("couldn't spawn client process");
+
+    xous_kernel::wait_process_as_thread(xous_server).expect("couldn't join server process");
+    xous_kernel::wait_process_as_thread(xous_client).expect("couldn't join client process");
+    shutdown_kernel();
+
+    main_thread.join().expect("couldn't join kernel process");
+}
+
+#[test]
+fn set_quantum() {
+    let main_thread = start_kernel(SERVER_SPEC);
+
+    let xous_process = xous_kernel::create_process_as_thread(
+        xous_kernel::ProcessArgsAsThread::new("set_quantum process", || {
+            let previous = xous_kernel::set_quantum(3, 2).expect("couldn't set quantum");
+            assert_eq!(xous_kernel::set_quantum(3, previous), Ok(2));
+            assert_eq!(
+                xous_kernel::set_quantum(4, 2),
+                Err(xous_kernel::Error::InvalidSyscall)
+            );
+            assert_eq!(
+                xous_kernel::set_quantum(0, 0),
+                Err(xous_kernel::Error::InvalidSyscall)
+            );
+        })
+        .capabilities(xous_kernel::Capabilities::SCHEDULING),
+    )
+    .expect("couldn't start process");
+    xous_kernel::wait_process_as_thread(xous_process).expect("couldn't join process");
+
+    // The quanta apply to every process, so one without the capability
+    // can't change them.
+    let unprivileged = xous_kernel::create_process_as_thread(
+        xous_kernel::ProcessArgsAsThread::new("set_quantum unprivileged", || {
+            assert_eq!(
+                xous_kernel::set_quantum(3, 2),
+                Err(xous_kernel::Error::AccessDenied)
+            );
+        }),
+    )
+    .expect("couldn't start unprivileged process");
+    xous_kernel::wait_process_as_thread(unprivileged).expect("couldn't join process");
+    shutdown_kernel();
+
+    main_thread.join().expect("couldn't join kernel process");
+}
+
+#[test]
+fn set_deadline() {
+    let main_thread = start_kernel(SERVER_SPEC);
+
+    let xous_process = xous_kernel::create_process_as_thread(
+        xous_kernel::ProcessArgsAsThread::new("set_deadline process", || {
+            assert_eq!(
+                xous_kernel::set_deadline(10, 0),
+                Err(xous_kernel::Error::InvalidSyscall)
+            );
+            assert_eq!(
+                xous_kernel::set_deadline(10, 11),
+                Err(xous_kernel::Error::InvalidSyscall)
+            );
+
+            let set_from_other_thread = |period, budget| {
+                let (result_send, result_recv) = channel();
+                let thread = xous_kernel::create_thread(move || {
+                    result_send
+                        .send(xous_kernel::set_deadline(period, budget))
+                        .unwrap()
+                })
+                .expect("couldn't create thread");
+                xous_kernel::wait_thread(thread).expect("couldn't join thread");
+                result_recv.recv().unwrap()
+            };
+
+            // A thread may change its own reservation, up to as much of the
+            // CPU as the deadline class may have, but no further.
+            xous_kernel::set_deadline(10, 5).expect("couldn't set deadline");
+            xous_kernel::set_deadline(10, 9).expect("couldn't change deadline");
+            assert_eq!(
+                xous_kernel::set_deadline(10, 10),
+                Err(xous_kernel::Error::OutOfMemory)
+            );
+
+            // Nobody else can have any of it until it gives it up.
+            assert_eq!(
+                set_from_other_thread(10, 1),
+                Err(xous_kernel::Error::OutOfMemory)
+            );
+            xous_kernel::set_deadline(0, 0).expect("couldn't clear deadline");
+            assert_eq!(set_from_other_thread(10, 1), Ok(()));
+        })
+        .capabilities(xous_kernel::Capabilities::SCHEDULING),
+    )
+    .expect("couldn't start process");
+    xous_kernel::wait_process_as_thread(xous_process).expect("couldn't join process");
+
+    // A process without the capability can't reserve anything, but giving
+    // up a reservation is always allowed.
+    let unprivileged = xous_kernel::create_process_as_thread(
+        xous_kernel::ProcessArgsAsThread::new("set_deadline unprivileged", || {
+            assert_eq!(
+                xous_kernel::set_deadline(10, 1),
+                Err(xous_kernel::Error::AccessDenied)
+            );
+            xous_kernel::set_deadline(0, 0).expect("couldn't clear deadline");
+        }),
+    )
+    .expect("couldn't start unprivileged process");
+    xous_kernel::wait_process_as_thread(unprivileged).expect("couldn't join process");
+    shutdown_kernel();
+
+    main_thread.join().expect("couldn't join kernel process");
+}
+
+#[test]
+fn query_scheduler() {
+    let main_thread = start_kernel(SERVER_SPEC);
+
+    let (server_addr_send, server_addr_recv) = channel();
+
+    let xous_server = xous_kernel::create_process_as_thread(xous_kernel::ProcessArgsAsThread::new(
+        "query_scheduler server",
+        move || {
+            let sid = xous_kernel::create_server(b"query_scheduler!")
+                .expect("couldn't create test server");
+            server_addr_send.send(sid).unwrap();
+            for _ in 0..3 {
+                let envelope =
+                    xous_kernel::receive_message(sid).expect("couldn't receive messages");
+                xous_kernel::return_scalar(envelope.sender, 0).expect("couldn't return scalar");
+            }
+        },
+    ))
+    .expect("couldn't spawn server process");
+
+    let xous_client = xous_kernel::create_process_as_thread(xous_kernel::ProcessArgsAsThread::new(
+        "query_scheduler client",
+        move || {
+            let sid = server_addr_recv.recv().unwrap();
+            let conn = xous_kernel::try_connect(sid).expect("couldn't connect to server");
+            for _ in 0..3 {
+                xous_kernel::try_send_message(
+                    conn,
+                    xous_kernel::Message::BlockingScalar(xous_kernel::ScalarMessage {
+                        id: 1,
+                        arg1: 0,
+                        arg2: 0,
+                        arg3: 0,
+                        arg4: 0,
+                    }),
+                )
+                .expect("couldn't send message");
+            }
+
+            // Add up every thread in the system, the way `top` would.  Each
+            // blocking message made a thread give up the CPU.
+            let mut voluntary_switches = 0;
+            for pid in 1..=255 {
+                let pid = xous_kernel::PID::new(pid).unwrap();
+                for tid in 0.. {
+                    match xous_kernel::query_scheduler(pid, tid) {
+                        Ok(stats) => {
+                            assert_eq!(stats.involuntary_switches, 0);
+                            voluntary_switches += stats.voluntary_switches;
+                        }
+                        Err(xous_kernel::Error::InvalidThread) => break,
+                        Err(xous_kernel::Error::ProcessNotFound) => break,
+                        Err(e) => panic!("unexpected error: {:?}", e),
+                    }
+                }
+            }
+            assert!(voluntary_switches >= 3);
+        },
+    ))
+    .expect("couldn't spawn client process");
+
+    xous_kernel::wait_process_as_thread(xous_server).expect("couldn't join server process");
+    xous_kernel::wait_process_as_thread(xous_client).expect("couldn't join client process");
+    shutdown_kernel();
+
+    main_thread.join().expect("couldn't join kernel process");
+}
+
+#[test]
+fn measurement() {
+    let main_thread = start_kernel(SERVER_SPEC);
+
+    let xous_process = xous_kernel::create_process_as_thread(
+        xous_kernel::ProcessArgsAsThread::new("measurement process", || {
+            // Hosted mode has no boot images, so the register is never
+            // extended.
+            assert_eq!(xous_kernel::measurement(0), Ok([0u8; 32]));
+            assert_eq!(
+                xous_kernel::measurement(1),
+                Err(xous_kernel::Error::InvalidSyscall)
+            );
+            assert_eq!(
+                rsyscall(SysCall::GetMeasurement(0, 4)),
+                Err(xous_kernel::Error::InvalidSyscall)
+            );
+        }),
+    )
+    .expect("couldn't start process");
+
+    xous_kernel::wait_process_as_thread(xous_process).expect("couldn't join process");
+    shutdown_kernel();
+
+    main_thread.join().expect("couldn't join kernel process");
+}
+
+#[test]
+fn connection_refcount() {
+    let main_thread = start_kernel(SERVER_SPEC);
+
+    let (server_addr_send, server_addr_recv) = channel();
+    let (done_send, done_recv) = channel();
+
+    let xous_server = xous_kernel::create_process_as_thread(xous_kernel::ProcessArgsAsThread::new(
+        "connection_refcount server",
+        move || {
+            let sid = xous_kernel::create_server(b"connect_refcount")
+                .expect("couldn't create test server");
+            server_addr_send.send(sid).unwrap();
+            done_recv.recv().unwrap();
+        },
+    ))
+    .expect("couldn't spawn server process");
+
+    let xous_client = xous_kernel::create_process_as_thread(xous_kernel::ProcessArgsAsThread::new(
+        "connection_refcount client",
+        move || {
+            use xous_kernel::connection::Connection;
+            let sid = server_addr_recv.recv().unwrap();
+
+            // A clone shares the Connection ID, which stays open until both
+            // have been dropped.
+            let conn = Connection::try_connect(sid).expect("couldn't connect to server");
+            let cid = conn.cid();
+            let clone = conn.clone();
+            assert_eq!(clone.cid(), cid);
+            drop(conn);
+            drop(clone);
+            assert_eq!(
+                xous_kernel::disconnect(cid),
+                Err(xous_kernel::Error::ServerNotFound)
+            );
+
+            // A leaked connection has to be released by hand.
+            let cid = Connection::try_connect(sid)
+                .expect("couldn't connect to server")
+                .leak();
+            assert_eq!(xous_kernel::disconnect(cid), Ok(()));
+            assert_eq!(
+                xous_kernel::disconnect(cid),
+                Err(xous_kernel::Error::ServerNotFound)
+            );
+            done_send.send(()).unwrap();
+        },
+    ))
+    .expect("couldn't spawn client process");
+
+    xous_kernel::wait_process_as_thread(xous_client).expect("couldn't join client process");
+    xous_kernel::wait_process_as_thread(xous_server).expect("couldn't join server process");
+    shutdown_kernel();
+
+    main_thread.join().expect("couldn't join kernel process");
+}
+
+#[test]
+fn query_latency() {
+    use xous_kernel::LatencyStage;
+    const MESSAGES: usize = 3;
+
+    let main_thread = start_kernel(SERVER_SPEC);
+
+    let (server_addr_send, server_addr_recv) = channel();
+    let (done_send, done_recv) = channel();
+
+    let xous_server = xous_kernel::create_process_as_thread(xous_kernel::ProcessArgsAsThread::new(
+        "query_latency server",
+        move || {
+            let sid = xous_kernel::create_server(b"query_latency_sv")
+                .expect("couldn't create test server");
+            server_addr_send.send(sid).unwrap();
+            for _ in 0..MESSAGES {
+                let envelope =
+                    xous_kernel::receive_message(sid).expect("couldn't receive messages");
+                xous_kernel::return_scalar(envelope.sender, 0).expect("couldn't return scalar");
+            }
+            done_recv.recv().unwrap();
+        },
+    ))
+    .expect("couldn't spawn server process");
+
+    let xous_client = xous_kernel::create_process_as_thread(xous_kernel::ProcessArgsAsThread::new(
+        "query_latency client",
+        move || {
+            let sid = server_addr_recv.recv().unwrap();
+            let conn = xous_kernel::try_connect(sid).expect("couldn't connect to server");
+            assert_eq!(
+                xous_kernel::query_latency(conn, LatencyStage::QueueWait, 50),
+                Ok((0, 0))
+            );
+
+            for id in 0..MESSAGES {
+                xous_kernel::try_send_message(
+                    conn,
+                    xous_kernel::Message::BlockingScalar(xous_kernel::ScalarMessage {
+                        id,
+                        arg1: 0,
+                        arg2: 0,
+                        arg3: 0,
+                        arg4: 0,
+                    }),
+                )
+                .expect("couldn't send message");
+            }
+
+            // Every message waited in the queue and was responded to.
+            for stage in [LatencyStage::QueueWait, LatencyStage::Service].iter() {
+                let (median, count) = xous_kernel::query_latency(conn, *stage, 50)
+                    .expect("couldn't query latency");
+                let (max, _) = xous_kernel::query_latency(conn, *stage, 100)
+                    .expect("couldn't query latency");
+                assert_eq!(count, MESSAGES);
+                assert!(median <= max);
+            }
+
+            assert_eq!(
+                xous_kernel::query_latency(conn, LatencyStage::Service, 101),
+                Err(xous_kernel::Error::InvalidSyscall)
+            );
+            done_send.send(()).unwrap();
+        },
+    ))
+    .expect("couldn't spawn client process");
+
+    xous_kernel::wait_process_as_thread(xous_client).expect("couldn't join client process");
+    xous_kernel::wait_process_as_thread(xous_server).expect("couldn't join server process");
+    shutdown_kernel();
+
+    main_thread.join().expect("couldn't join kernel process");
+}
+
+#[test]
+fn yield_to() {
+    let main_thread = start_kernel(SERVER_SPEC);
+
+    let xous_process = xous_kernel::create_process_as_thread(
+        xous_kernel::ProcessArgsAsThread::new("yield_to process", || {
+            let kernel_pid = xous_kernel::pid_from_usize(1).unwrap();
+            let missing_pid = xous_kernel::pid_from_usize(200).unwrap();
+            assert_eq!(
+                xous_kernel::yield_to(missing_pid, 1),
+                Err(xous_kernel::Error::ProcessNotFound)
+            );
+            assert_eq!(
+                xous_kernel::yield_to(kernel_pid, 0),
+                Err(xous_kernel::Error::InvalidThread)
+            );
+            assert_eq!(
+                xous_kernel::yield_to(kernel_pid, 1000),
+                Err(xous_kernel::Error::InvalidThread)
+            );
+
+            // Yielding doesn't switch threads when hosted, so this just returns.
+            assert_eq!(xous_kernel::yield_to(kernel_pid, 1), Ok(()));
+        }),
+    )
+    .expect("couldn't start process");
+
+    xous_kernel::wait_process_as_thread(xous_process).expect("couldn't join process");
+    shutdown_kernel();
+
+    main_thread.join().expect("couldn't join kernel process");
+}
+
+#[test]
+fn server_queue_length() {
+    const QUEUE_LENGTH: usize = 2;
+
+    let main_thread = start_kernel(SERVER_SPEC);
+
+    let (server_addr_send, server_addr_recv) = channel();
+    let (done_send, done_recv) = channel();
+
+    let xous_server = xous_kernel::create_process_as_thread(xous_kernel::ProcessArgsAsThread::new(
+        "server_queue_length server",
+        move || {
+            assert_eq!(
+                xous_kernel::create_server_with_queue(b"empty queue!!!!!", 0),
+                Err(xous_kernel::Error::InvalidSyscall)
+            );
+            assert_eq!(
+                xous_kernel::create_server_with_queue(b"enormous queue!!", usize::MAX),
+                Err(xous_kernel::Error::OutOfMemory)
+            );
+            let sid = xous_kernel::create_server_with_queue(b"short queue!!!!!", QUEUE_LENGTH)
+                .expect("couldn't create test server");
+            server_addr_send.send(sid).unwrap();
+
+            // Don't receive anything, so that the queue fills up.
+            done_recv.recv().unwrap();
+        },
+    ))
+    .expect("couldn't spawn server process");
+
+    let xous_client = xous_kernel::create_process_as_thread(xous_kernel::ProcessArgsAsThread::new(
+        "server_queue_length client",
+        move || {
+            let sid = server_addr_recv.recv().unwrap();
+            let conn = xous_kernel::try_connect(sid).expect("couldn't connect to server");
+            let send = |id| {
+                xous_kernel::try_send_message(
+                    conn,
+                    xous_kernel::Message::Scalar(xous_kernel::ScalarMessage {
+                        id,
+                        arg1: 0,
+                        arg2: 0,
+                        arg3: 0,
+                        arg4: 0,
+                    }),
+                )
+            };
+            for id in 0..QUEUE_LENGTH {
+                send(id).expect("couldn't queue message");
+            }
+            assert_eq!(send(QUEUE_LENGTH), Err(xous_kernel::Error::ServerQueueFull));
+            done_send.send(()).unwrap();
+        },
+    ))
+    .expect("couldn't start client process");
+
+    xous_kernel::wait_process_as_thread(xous_client).expect("couldn't join client process");
+    xous_kernel::wait_process_as_thread(xous_server).expect("couldn't join server process");
+    shutdown_kernel();
+
+    main_thread.join().expect("couldn't join kernel process");
+}
+
+#[test]
+fn image_segments() {
+    let main_thread = start_kernel(SERVER_SPEC);
+
+    let xous_process = xous_kernel::create_process_as_thread(
+        xous_kernel::ProcessArgsAsThread::new("image_segments process", || {
+            use xous_kernel::{MemoryAddress, MemoryFlags};
+            let rw = MemoryFlags::R | MemoryFlags::W;
+
+            assert_eq!(
+                xous_kernel::reserve_address_space(None, 4097),
+                Err(xous_kernel::Error::BadAlignment)
+            );
+            let image = xous_kernel::reserve_address_space(None, 4 * 4096)
+                .expect("couldn't reserve address space");
+            let other = xous_kernel::reserve_address_space(None, 4096)
+                .expect("couldn't reserve more address space");
+            assert!(
+                other.addr.get() >= image.addr.get() + image.len()
+                    || other.addr.get() + other.len() <= image.addr.get()
+            );
+            assert_eq!(
+                xous_kernel::reserve_address_space(Some(image.addr), 4096),
+                Err(xous_kernel::Error::MemoryInUse)
+            );
+
+            // Nothing else may be mapped into a reservation.
+            assert_eq!(
+                xous_kernel::map_memory(None, Some(image.addr), 4096, rw),
+                Err(xous_kernel::Error::MemoryInUse)
+            );
+
+            let data = xous_kernel::map_segment(image.addr, 4096, 2 * 4096, rw)
+                .expect("couldn't map segment");
+            assert_eq!(data.addr.get(), image.addr.get() + 4096);
+            assert_eq!(data.len(), 2 * 4096);
+            assert_eq!(
+                xous_kernel::map_segment(image.addr, 2 * 4096, 4096, rw),
+                Err(xous_kernel::Error::MemoryInUse)
+            );
+            assert_eq!(
+                xous_kernel::map_segment(image.addr, 3 * 4096, 2 * 4096, rw),
+                Err(xous_kernel::Error::BadAddress)
+            );
+            assert_eq!(
+                xous_kernel::map_segment(image.addr, 100, 4096, rw),
+                Err(xous_kernel::Error::BadAlignment)
+            );
+            let unreserved = MemoryAddress::new(image.addr.get() + 4096).unwrap();
+            assert_eq!(
+                xous_kernel::map_segment(unreserved, 0, 4096, rw),
+                Err(xous_kernel::Error::BadAddress)
+            );
+        }),
+    )
+    .expect("couldn't start process");
+
+    xous_kernel::wait_process_as_thread(xous_process).expect("couldn't join process");
+    shutdown_kernel();
+
+    main_thread.join().expect("couldn't join kernel process");
+}
+
+#[test]
+fn share_segment() {
+    let main_thread = start_kernel(SERVER_SPEC);
+
+    let xous_process = xous_kernel::create_process_as_thread(
+        xous_kernel::ProcessArgsAsThread::new("share_segment process", || {
+            use xous_kernel::{MemoryAddress, MemoryFlags};
+
+            let image = xous_kernel::reserve_address_space(None, 2 * 4096)
+                .expect("couldn't reserve address space");
+            let text = xous_kernel::map_segment(image.addr, 0, 4096, MemoryFlags::R)
+                .expect("couldn't map segment");
+            let kernel_pid = xous_kernel::pid_from_usize(1).unwrap();
+            let missing_pid = xous_kernel::pid_from_usize(200).unwrap();
+
+            let not_a_segment = MemoryAddress::new(text.addr.get() + 4096).unwrap();
+            assert_eq!(
+                xous_kernel::share_segment(not_a_segment, kernel_pid, image.addr),
+                Err(xous_kernel::Error::BadAddress)
+            );
+            assert_eq!(
+                xous_kernel::share_segment(text.addr, missing_pid, image.addr),
+                Err(xous_kernel::Error::ProcessNotFound)
+            );
+
+            // Segments may only be shared with the caller's own children.
+            assert_eq!(
+                xous_kernel::share_segment(text.addr, kernel_pid, image.addr),
+                Err(xous_kernel::Error::AccessDenied)
+            );
+        }),
+    )
+    .expect("couldn't start process");
+
+    xous_kernel::wait_process_as_thread(xous_process).expect("couldn't join process");
+    shutdown_kernel();
+
+    main_thread.join().expect("couldn't join kernel process");
+}
+
+#[test]
+fn sender_pid() {
+    const CLIENTS: usize = 2;
+
+    let main_thread = start_kernel(SERVER_SPEC);
+
+    let (server_addr_send, server_addr_recv) = channel();
+    let (done_send, done_recv) = channel();
+
+    let xous_server = xous_kernel::create_process_as_thread(xous_kernel::ProcessArgsAsThread::new(
+        "sender_pid server",
+        move || {
+            let sid = xous_kernel::create_server(b"sender_pid_servr")
+                .expect("couldn't create test server");
+            for _ in 0..CLIENTS {
+                server_addr_send.send(sid).unwrap();
+            }
+
+            // Each client sends a scalar followed by a blocking scalar, both
+            // with the client's index as the message ID.
+            let mut senders = [None; CLIENTS];
+            for _ in 0..CLIENTS * 2 {
+                let envelope =
+                    xous_kernel::receive_message(sid).expect("couldn't receive messages");
+                let pid = envelope.sender_pid().expect("message had no sender");
+                let client = match &envelope.body {
+                    xous_kernel::Message::Scalar(msg) => msg.id,
+                    xous_kernel::Message::BlockingScalar(msg) => {
+                        xous_kernel::return_scalar(envelope.sender, 0)
+                            .expect("couldn't return scalar");
+                        msg.id
+                    }
+                    _ => panic!("unexpected message"),
+                };
+                assert_ne!(pid.get(), 1);
+                assert_eq!(*senders[client].get_or_insert(pid), pid);
+            }
+            assert_ne!(senders[0], senders[1]);
+            for _ in 0..CLIENTS {
+                done_recv.recv().unwrap();
+            }
+        },
+    ))
+    .expect("couldn't spawn server process");
+
+    let mut xous_clients = vec![];
+    for client in 0..CLIENTS {
+        let server_addr_recv = server_addr_recv.recv().unwrap();
+        let done_send = done_send.clone();
+        xous_clients.push(
+            xous_kernel::create_process_as_thread(xous_kernel::ProcessArgsAsThread::new(
+                "sender_pid client",
+                move || {
+                    let conn =
+                        xous_kernel::try_connect(server_addr_recv).expect("couldn't connect");
+                    let msg = xous_kernel::ScalarMessage {
+                        id: client,
+                        arg1: 0,
+                        arg2: 0,
+                        arg3: 0,
+                        arg4: 0,
+                    };
+                    xous_kernel::try_send_message(conn, xous_kernel::Message::Scalar(msg))
+                        .expect("couldn't send message");
+                    xous_kernel::try_send_message(conn, xous_kernel::Message::BlockingScalar(msg))
+                        .expect("couldn't send message");
+                    done_send.send(()).unwrap();
+                },
+            ))
+            .expect("couldn't spawn client process"),
+        );
+    }
+
+    for xous_client in xous_clients {
+        xous_kernel::wait_process_as_thread(xous_client).expect("couldn't join client process");
+    }
+    xous_kernel::wait_process_as_thread(xous_server).expect("couldn't join server process");
+    shutdown_kernel();
+
+    main_thread.join().expect("couldn't join kernel process");
+}
+
+#[test]
+fn connection_data() {
+    const MESSAGES: usize = 3;
+
+    let main_thread = start_kernel(SERVER_SPEC);
+
+    let (server_addr_send, server_addr_recv) = channel();
+    let (done_send, done_recv) = channel();
+
+    let xous_server = xous_kernel::create_process_as_thread(xous_kernel::ProcessArgsAsThread::new(
+        "connection_data server",
+        move || {
+            let sid = xous_kernel::create_server(b"conn_data_server")
+                .expect("couldn't create test server");
+            server_addr_send.send(sid).unwrap();
+
+            // Return the data each message arrived with, then attach a new
+            // value for the next one.
+            for id in 0..MESSAGES {
+                let envelope =
+                    xous_kernel::receive_message(sid).expect("couldn't receive messages");
+                xous_kernel::set_connection_data(envelope.sender, id + 100)
+                    .expect("couldn't set connection data");
+                xous_kernel::return_scalar(envelope.sender, envelope.connection_data)
+                    .expect("couldn't return scalar");
+            }
+            done_recv.recv().unwrap();
+        },
+    ))
+    .expect("couldn't spawn server process");
+
+    let xous_client = xous_kernel::create_process_as_thread(xous_kernel::ProcessArgsAsThread::new(
+        "connection_data client",
+        move || {
+            let sid = server_addr_recv.recv().unwrap();
+            let send = |conn| match xous_kernel::try_send_message(
+                conn,
+                xous_kernel::Message::BlockingScalar(xous_kernel::ScalarMessage {
+                    id: 0,
+                    arg1: 0,
+                    arg2: 0,
+                    arg3: 0,
+                    arg4: 0,
+                }),
+            ) {
+                Ok(xous_kernel::Result::Scalar1(data)) => data,
+                other => panic!("unexpected result: {:?}", other),
+            };
+
+            let conn = xous_kernel::try_connect(sid).expect("couldn't connect to server");
+            assert_eq!(send(conn), 0);
+            assert_eq!(send(conn), 100);
+
+            // Data doesn't outlive the connection it was attached to.
+            xous_kernel::disconnect(conn).expect("couldn't disconnect");
+            let conn = xous_kernel::try_connect(sid).expect("couldn't reconnect to server");
+            assert_eq!(send(conn), 0);
+            done_send.send(()).unwrap();
+        },
+    ))
+    .expect("couldn't spawn client process");
+
+    xous_kernel::wait_process_as_thread(xous_client).expect("couldn't join client process");
+    xous_kernel::wait_process_as_thread(xous_server).expect("couldn't join server process");
+    shutdown_kernel();
+
+    main_thread.join().expect("couldn't join kernel process");
+}
+
+#[test]
+fn notification() {
+    let main_thread = start_kernel(SERVER_SPEC);
+
+    let (id_send, id_recv) = channel();
+
+    let xous_owner = xous_kernel::create_process_as_thread(xous_kernel::ProcessArgsAsThread::new(
+        "notification owner",
+        move || {
+            let id = xous_kernel::create_notification().expect("couldn't create notification");
+            assert_eq!(xous_kernel::poll_notification(id), Ok(false));
+            id_send.send(id).unwrap();
+
+            // Three signals arrive, whether or not this thread is already
+            // waiting when they do, and each is consumed exactly once.
+            xous_kernel::wait_notification(id).expect("couldn't wait for notification");
+            assert_eq!(xous_kernel::poll_notification(id), Ok(true));
+            xous_kernel::wait_notification(id).expect("couldn't wait for notification");
+            assert_eq!(xous_kernel::poll_notification(id), Ok(false));
+        },
+    ))
+    .expect("couldn't spawn owner process");
+
+    let xous_client = xous_kernel::create_process_as_thread(xous_kernel::ProcessArgsAsThread::new(
+        "notification signaller",
+        move || {
+            let id = id_recv.recv().unwrap();
+
+            // Only the owner may consume signals.
+            assert_eq!(
+                xous_kernel::poll_notification(id),
+                Err(xous_kernel::Error::AccessDenied)
+            );
+            xous_kernel::signal_notification(id, 3).expect("couldn't signal notification");
+            assert_eq!(
+                xous_kernel::signal_notification(id + 1, 1),
+                Err(xous_kernel::Error::InvalidSyscall)
+            );
+        },
+    ))
+    .expect("couldn't spawn signaller process");
+
+    xous_kernel::wait_process_as_thread(xous_client).expect("couldn't join signaller process");
+    xous_kernel::wait_process_as_thread(xous_owner).expect("couldn't join owner process");
+    shutdown_kernel();
+
+    main_thread.join().expect("couldn't join kernel process");
+}
+
+#[test]
+fn send_large_mutableborrow_message() {
+    // Large enough to be compressed on the way to the server and back.
+    const LEN: usize = 1024 * 1024;
+
+    let main_thread = start_kernel(SERVER_SPEC);
+    let (server_addr_send, server_addr_recv) = channel();
+
+    let xous_server = xous_kernel::create_process_as_thread(xous_kernel::ProcessArgsAsThread::new(
+        "send_large_mutableborrow_message server",
+        move || {
+            let sid = xous_kernel::create_server(b"send_large_mutbo")
+                .expect("couldn't create test server");
+            server_addr_send.send(sid).unwrap();
+            let envelope = xous_kernel::receive_message(sid).expect("couldn't receive messages");
+            if let xous_kernel::Message::MutableBorrow(m) = envelope.body {
+                let buf = m.buf;
+                let bt = unsafe { core::slice::from_raw_parts_mut(buf.as_mut_ptr(), buf.len()) };
+                for (index, byte) in bt.iter().enumerate() {
+                    assert_eq!(*byte, (index % 251) as u8);
+                }
+                for byte in bt.iter_mut() {
+                    *byte = byte.wrapping_add(1);
+                }
+                xous_kernel::return_memory(envelope.sender, m.buf).unwrap();
+            } else {
+                panic!("unexpected message type");
+            }
+        },
+    ))
+    .expect("couldn't start server");
+
+    let xous_client = xous_kernel::create_process_as_thread(xous_kernel::ProcessArgsAsThread::new(
+        "send_large_mutableborrow_message client",
+        move || {
+            let sid = server_addr_recv.recv().unwrap();
+            let conn = xous_kernel::try_connect(sid).expect("couldn't connect to server");
+
+            let test_bytes: Vec<u8> = (0..LEN).map(|index| (index % 251) as u8).collect();
+            let mut carton = xous_kernel::carton::Carton::from_bytes(&test_bytes);
+            carton
+                .lend_mut(conn, 3)
+                .expect("couldn't mutably lend data");
+
+            let modified_bytes: &[u8] = carton.as_ref();
+            for (index, byte) in modified_bytes.iter().enumerate() {
+                assert_eq!(*byte, (index % 251) as u8 + 1);
+            }
+        },
+    ))
+    .expect("couldn't start client");
+
+    crate::wait_process_as_thread(xous_server).expect("couldn't join server process");
+    crate::wait_process_as_thread(xous_client).expect("couldn't join client process");
+    shutdown_kernel();
+
+    main_thread.join().expect("couldn't join kernel process");
+}
+
+#[test]
+fn receive_any() {
+    let main_thread = start_kernel(SERVER_SPEC);
+
+    let (server_addr_send, server_addr_recv) = channel();
+    let (received_send, received_recv) = channel();
+
+    let xous_server = xous_kernel::create_process_as_thread(xous_kernel::ProcessArgsAsThread::new(
+        "receive_any server",
+        move || {
+            let sid_a = xous_kernel::create_server(b"receive_any_srva")
+                .expect("couldn't create test server");
+            let sid_b = xous_kernel::create_server(b"receive_any_srvb")
+                .expect("couldn't create test server");
+            let set = xous_kernel::create_poll_set().expect("couldn't create poll set");
+            let cid_a = xous_kernel::add_to_poll_set(set, sid_a).expect("couldn't add server");
+            let cid_b = xous_kernel::add_to_poll_set(set, sid_b).expect("couldn't add server");
+            assert_ne!(cid_a, cid_b);
+            assert_eq!(xous_kernel::add_to_poll_set(set, sid_a), Ok(cid_a));
+            server_addr_send.send((sid_a, sid_b, set)).unwrap();
+
+            // The second message is only sent once the first has arrived, so
+            // this thread has to be taken off server B before it waits again.
+            let envelope = xous_kernel::receive_any(set).expect("couldn't receive message");
+            assert_eq!(envelope.server_cid(), cid_b);
+            received_send.send(()).unwrap();
+
+            let envelope = xous_kernel::receive_any(set).expect("couldn't receive message");
+            assert_eq!(envelope.server_cid(), cid_a);
+            if let xous_kernel::Message::BlockingScalar(msg) = envelope.body {
+                xous_kernel::return_scalar(envelope.sender, msg.id)
+                    .expect("couldn't return scalar");
+            } else {
+                panic!("unexpected message type");
+            }
+        },
+    ))
+    .expect("couldn't spawn server process");
+
+    let xous_client = xous_kernel::create_process_as_thread(xous_kernel::ProcessArgsAsThread::new(
+        "receive_any client",
+        move || {
+            let (sid_a, sid_b, set) = server_addr_recv.recv().unwrap();
+            assert_eq!(
+                xous_kernel::receive_any(set).map(|_| ()),
+                Err(xous_kernel::Error::AccessDenied)
+            );
+
+            let msg = xous_kernel::ScalarMessage {
+                id: 20,
+                arg1: 0,
+                arg2: 0,
+                arg3: 0,
+                arg4: 0,
+            };
+            let conn_b = xous_kernel::try_connect(sid_b).expect("couldn't connect to server");
+            xous_kernel::try_send_message(conn_b, xous_kernel::Message::Scalar(msg))
+                .expect("couldn't send message");
+            received_recv.recv().unwrap();
+
+            let conn_a = xous_kernel::try_connect(sid_a).expect("couldn't connect to server");
+            let result = xous_kernel::try_send_message(
+                conn_a,
+                xous_kernel::Message::BlockingScalar(xous_kernel::ScalarMessage { id: 10, ..msg }),
+            );
+            assert_eq!(result, Ok(xous_kernel::Result::Scalar1(10)));
+        },
+    ))
+    .expect("couldn't spawn client process");
+
+    xous_kernel::wait_process_as_thread(xous_client).expect("couldn't join client process");
+    xous_kernel::wait_process_as_thread(xous_server).expect("couldn't join server process");
+    shutdown_kernel();
+
+    main_thread.join().expect("couldn't join kernel process");
+}
+
+#[cfg(unix)]
+#[test]
+fn unix_transports() {
+    // Bigger than a shared memory ring, and hard to compress, so that the
+    // rings wrap around and fill up.
+    const LEN: usize = 3 * 1024 * 1024;
+    fn pattern(index: usize) -> u8 {
+        (index.wrapping_mul(2_654_435_761) >> 13) as u8
+    }
+
+    for transport in &["unix", "shm"] {
+        use rand::{thread_rng, Rng};
+        let path = std::env::temp_dir().join(format!(
+            "xous-test-{}-{:08x}.sock",
+            transport,
+            thread_rng().gen::<u32>()
+        ));
+        let main_thread = start_kernel(&format!("{}:{}", transport, path.display()));
+        let (server_addr_send, server_addr_recv) = channel();
+
+        let xous_server = xous_kernel::create_process_as_thread(
+            xous_kernel::ProcessArgsAsThread::new("unix_transports server", move || {
+                let sid = xous_kernel::create_server(b"unix_transp_serv")
+                    .expect("couldn't create test server");
+                server_addr_send.send(sid).unwrap();
+                let envelope =
+                    xous_kernel::receive_message(sid).expect("couldn't receive messages");
+                if let xous_kernel::Message::MutableBorrow(m) = envelope.body {
+                    let buf = m.buf;
+                    let bt =
+                        unsafe { core::slice::from_raw_parts_mut(buf.as_mut_ptr(), buf.len()) };
+                    for (index, byte) in bt.iter_mut().enumerate() {
+                        assert_eq!(*byte, pattern(index));
+                        *byte = byte.wrapping_add(1);
+                    }
+                    xous_kernel::return_memory(envelope.sender, m.buf).unwrap();
+                } else {
+                    panic!("unexpected message type");
+                }
+            }),
+        )
+        .expect("couldn't start server");
+
+        let xous_client = xous_kernel::create_process_as_thread(
+            xous_kernel::ProcessArgsAsThread::new("unix_transports client", move || {
+                let sid = server_addr_recv.recv().unwrap();
+                let conn = xous_kernel::try_connect(sid).expect("couldn't connect to server");
+
+                let test_bytes: Vec<u8> = (0..LEN).map(pattern).collect();
+                let mut carton = xous_kernel::carton::Carton::from_bytes(&test_bytes);
+                carton
+                    .lend_mut(conn, 3)
+                    .expect("couldn't mutably lend data");
+
+                let modified_bytes: &[u8] = carton.as_ref();
+                for (index, byte) in modified_bytes.iter().enumerate() {
+                    assert_eq!(*byte, pattern(index).wrapping_add(1));
+                }
+            }),
+        )
+        .expect("couldn't start client");
+
+        crate::wait_process_as_thread(xous_server).expect("couldn't join server process");
+        crate::wait_process_as_thread(xous_client).expect("couldn't join client process");
+        shutdown_kernel();
+
+        main_thread.join().expect("couldn't join kernel process");
+        assert!(!path.exists(), "socket wasn't cleaned up");
+    }
+}
+
+#[test]
+fn forward_message() {
+    const LEN: usize = 64 * 1024;
+
+    let main_thread = start_kernel(SERVER_SPEC);
+    let (backend_addr_send, backend_addr_recv) = channel();
+    let (router_addr_send, router_addr_recv) = channel();
+
+    let xous_backend = xous_kernel::create_process_as_thread(
+        xous_kernel::ProcessArgsAsThread::new("forward_message backend", move || {
+            let sid = xous_kernel::create_server(b"forward_backend_")
+                .expect("couldn't create backend server");
+            backend_addr_send.send(sid).unwrap();
+
+            // Forwarded messages look like they came straight from the client.
+            let envelope = xous_kernel::receive_message(sid).expect("couldn't receive messages");
+            if let xous_kernel::Message::MutableBorrow(m) = envelope.body {
+                assert_eq!(m.id, 3);
+                let buf = m.buf;
+                let bt = unsafe { core::slice::from_raw_parts_mut(buf.as_mut_ptr(), buf.len()) };
+                for (index, byte) in bt.iter_mut().enumerate() {
+                    assert_eq!(*byte, (index % 251) as u8);
+                    *byte = byte.wrapping_add(1);
+                }
+                xous_kernel::return_memory(envelope.sender, m.buf).unwrap();
+            } else {
+                panic!("unexpected message type");
+            }
+
+            let envelope = xous_kernel::receive_message(sid).expect("couldn't receive messages");
+            if let xous_kernel::Message::BlockingScalar(m) = envelope.body {
+                xous_kernel::return_scalar(envelope.sender, m.arg1 + 1).unwrap();
+            } else {
+                panic!("unexpected message type");
+            }
+        }),
+    )
+    .expect("couldn't start backend");
+
+    let xous_router = xous_kernel::create_process_as_thread(xous_kernel::ProcessArgsAsThread::new(
+        "forward_message router",
+        move || {
+            let backend = xous_kernel::try_connect(backend_addr_recv.recv().unwrap())
+                .expect("couldn't connect to backend");
+            let sid = xous_kernel::create_server(b"forward_router__")
+                .expect("couldn't create router server");
+            router_addr_send.send(sid).unwrap();
+
+            // Messages that nobody is waiting on can't be forwarded.
+            let envelope = xous_kernel::receive_message(sid).expect("couldn't receive messages");
+            assert_eq!(
+                xous_kernel::forward_message(envelope.sender, backend, envelope.body),
+                Err(xous_kernel::Error::InvalidSyscall)
+            );
+
+            for _ in 0..2 {
+                let envelope =
+                    xous_kernel::receive_message(sid).expect("couldn't receive messages");
+                let lent = match &envelope.body {
+                    xous_kernel::Message::MutableBorrow(m) => Some(m.buf),
+                    _ => None,
+                };
+                xous_kernel::forward_message(envelope.sender, backend, envelope.body)
+                    .expect("couldn't forward message");
+
+                // The router is done with the message once it's forwarded.
+                if let Some(buf) = lent {
+                    assert_eq!(
+                        xous_kernel::return_memory(envelope.sender, buf),
+                        Err(xous_kernel::Error::ProcessNotFound)
+                    );
+                }
+            }
+        },
+    ))
+    .expect("couldn't start router");
+
+    let xous_client = xous_kernel::create_process_as_thread(xous_kernel::ProcessArgsAsThread::new(
+        "forward_message client",
+        move || {
+            let conn = xous_kernel::try_connect(router_addr_recv.recv().unwrap())
+                .expect("couldn't connect to router");
+            let msg = xous_kernel::ScalarMessage {
+                id: 1,
+                arg1: 41,
+                arg2: 0,
+                arg3: 0,
+                arg4: 0,
+            };
+            xous_kernel::try_send_message(conn, xous_kernel::Message::Scalar(msg))
+                .expect("couldn't send message");
+
+            let test_bytes: Vec<u8> = (0..LEN).map(|index| (index % 251) as u8).collect();
+            let mut carton = xous_kernel::carton::Carton::from_bytes(&test_bytes);
+            carton
+                .lend_mut(conn, 3)
+                .expect("couldn't mutably lend data");
+            let modified_bytes: &[u8] = carton.as_ref();
+            for (index, byte) in modified_bytes.iter().enumerate() {
+                assert_eq!(*byte, (index % 251) as u8 + 1);
+            }
+
+            let result =
+                xous_kernel::try_send_message(conn, xous_kernel::Message::BlockingScalar(msg));
+            assert_eq!(result, Ok(xous_kernel::Result::Scalar1(42)));
+        },
+    ))
+    .expect("couldn't start client");
+
+    crate::wait_process_as_thread(xous_client).expect("couldn't join client process");
+    crate::wait_process_as_thread(xous_router).expect("couldn't join router process");
+    crate::wait_process_as_thread(xous_backend).expect("couldn't join backend process");
+    shutdown_kernel();
+
+    main_thread.join().expect("couldn't join kernel process");
+}
+
+/// Test that the kernel reports the configuration it was built with
+#[test]
+fn kernel_config() {
+    let main_thread = start_kernel(SERVER_SPEC);
+
+    let xous_process = xous_kernel::create_process_as_thread(
+        xous_kernel::ProcessArgsAsThread::new("kernel_config process", || {
+            let config = xous_kernel::kernel_config().expect("couldn't get kernel config");
+            assert_eq!(config.arch, xous_kernel::KernelArch::Hosted);
+            assert_eq!(config.page_size, crate::mem::PAGE_SIZE);
+            assert_eq!(config.max_processes, crate::services::MAX_PROCESS_COUNT);
+            assert_eq!(config.max_threads, crate::arch::process::MAX_THREAD);
+            assert_eq!(config.max_servers, crate::services::MAX_SERVER_COUNT);
+            assert_eq!(config.max_queue_length, crate::server::MAX_QUEUE_LENGTH);
+            assert_eq!(
+                config
+                    .features
+                    .contains(xous_kernel::KernelFeatures::PRINT_PANICS),
+                cfg!(feature = "print-panics")
+            );
+        }),
+    )
+    .expect("couldn't spawn kernel_config process");
+
+    xous_kernel::wait_process_as_thread(xous_process).expect("couldn't join kernel_config process");
+
+    shutdown_kernel();
+    main_thread.join().expect("couldn't join kernel process");
+}
+
+/// Test that a server can hold on to blocking messages, keep receiving, and
+/// respond to them out of order
+#[test]
+fn deferred_response() {
+    let main_thread = start_kernel(SERVER_SPEC);
+    let (server_addr_send, server_addr_recv) = channel();
+    let (received_send, received_recv) = channel();
+    let (sent_send, sent_recv) = channel();
+
+    let xous_server = xous_kernel::create_process_as_thread(
+        xous_kernel::ProcessArgsAsThread::new("deferred_response server", move || {
+            let sid = xous_kernel::create_server(b"deferred_respons")
+                .expect("couldn't create test server");
+            server_addr_send.send(sid).unwrap();
+
+            let first = xous_kernel::receive_message(sid).expect("couldn't receive messages");
+
+            // A message queued while the first is still waiting for a
+            // response can be received anyway.
+            received_send.send(()).unwrap();
+            sent_recv.recv().unwrap();
+            let envelope = xous_kernel::receive_message(sid).expect("couldn't receive messages");
+            if let xous_kernel::Message::Scalar(m) = envelope.body {
+                assert_eq!(m.arg1, 2);
+            } else {
+                panic!("unexpected message type");
+            }
+
+            let third = xous_kernel::receive_message(sid).expect("couldn't receive messages");
+
+            let mut outstanding = vec![];
+            let mut after = None;
+            while let Some(sender) =
+                xous_kernel::next_outstanding_sender(sid, after).expect("couldn't list senders")
+            {
+                outstanding.push(sender);
+                after = Some(sender);
+            }
+            assert_eq!(outstanding, vec![first.sender, third.sender]);
+
+            // A sender naming some other client is refused.
+            let stale = (third.sender & 0x00ff_ffff) | (0x7f << 24);
+            assert_eq!(
+                xous_kernel::return_scalar(stale, 0),
+                Err(xous_kernel::Error::ProcessNotFound)
+            );
+
+            for envelope in [&third, &first].iter() {
+                if let xous_kernel::Message::BlockingScalar(m) = &envelope.body {
+                    xous_kernel::return_scalar(envelope.sender, m.arg1 + 10).unwrap();
+                } else {
+                    panic!("unexpected message type");
+                }
+            }
+            assert_eq!(xous_kernel::next_outstanding_sender(sid, None), Ok(None));
+        }),
+    )
+    .expect("couldn't start server");
+
+    let xous_client = xous_kernel::create_process_as_thread(xous_kernel::ProcessArgsAsThread::new(
+        "deferred_response client",
+        move || {
+            let conn = xous_kernel::try_connect(server_addr_recv.recv().unwrap())
+                .expect("couldn't connect to server");
+            let send = move |message| {
+                xous_kernel::create_thread(move || {
+                    let result = xous_kernel::try_send_message(
+                        conn,
+                        xous_kernel::Message::BlockingScalar(message),
+                    );
+                    assert_eq!(result, Ok(xous_kernel::Result::Scalar1(message.arg1 + 10)));
+                })
+                .expect("couldn't create thread")
+            };
+            let msg = xous_kernel::ScalarMessage {
+                id: 1,
+                arg1: 1,
+                arg2: 0,
+                arg3: 0,
+                arg4: 0,
+            };
+
+            let first = send(msg);
+            received_recv.recv().unwrap();
+            xous_kernel::try_send_message(
+                conn,
+                xous_kernel::Message::Scalar(xous_kernel::ScalarMessage { arg1: 2, ..msg }),
+            )
+            .expect("couldn't send message");
+            sent_send.send(()).unwrap();
+            let third = send(xous_kernel::ScalarMessage { arg1: 3, ..msg });
+
+            xous_kernel::wait_thread(third).expect("couldn't join thread");
+            xous_kernel::wait_thread(first).expect("couldn't join thread");
+        },
+    ))
+    .expect("couldn't start client");
+
+    crate::wait_process_as_thread(xous_client).expect("couldn't join client process");
+    crate::wait_process_as_thread(xous_server).expect("couldn't join server process");
+    shutdown_kernel();
+
+    main_thread.join().expect("couldn't join kernel process");
+}
+
+/// Test that responding to a client that terminated while the server held
+/// its message does nothing
+#[test]
+fn respond_to_terminated_client() {
+    let main_thread = start_kernel(SERVER_SPEC);
+    let (server_addr_send, server_addr_recv) = channel();
+    let (received_send, received_recv) = channel();
+    let (terminated_send, terminated_recv) = channel();
+
+    let xous_server = xous_kernel::create_process_as_thread(xous_kernel::ProcessArgsAsThread::new(
+        "respond_to_terminated_client server",
+        move || {
+            let sid = xous_kernel::create_server(b"terminated_clien")
+                .expect("couldn't create test server");
+            server_addr_send.send(sid).unwrap();
+
+            let envelope = xous_kernel::receive_message(sid).expect("couldn't receive messages");
+            received_send.send(()).unwrap();
+            terminated_recv.recv().unwrap();
+
+            // The slot is still outstanding until it's responded to.
+            assert_eq!(
+                xous_kernel::next_outstanding_sender(sid, None),
+                Ok(Some(envelope.sender))
+            );
+            assert_eq!(xous_kernel::return_scalar(envelope.sender, 1), Ok(()));
+            assert_eq!(xous_kernel::next_outstanding_sender(sid, None), Ok(None));
+        },
+    ))
+    .expect("couldn't start server");
+
+    let xous_client = xous_kernel::create_process_as_thread(xous_kernel::ProcessArgsAsThread::new(
+        "respond_to_terminated_client client",
+        move || {
+            let conn = xous_kernel::try_connect(server_addr_recv.recv().unwrap())
+                .expect("couldn't connect to server");
+            xous_kernel::create_thread(move || {
+                let msg = xous_kernel::ScalarMessage {
+                    id: 1,
+                    arg1: 0,
+                    arg2: 0,
+                    arg3: 0,
+                    arg4: 0,
+                };
+                xous_kernel::try_send_message(conn, xous_kernel::Message::BlockingScalar(msg)).ok();
+            })
+            .expect("couldn't create thread");
+
+            // Terminating never returns, since the kernel hangs up on the
+            // process instead.
+            received_recv.recv().unwrap();
+            xous_kernel::rsyscall(xous_kernel::SysCall::TerminateProcess).ok();
+        },
+    ))
+    .expect("couldn't start client");
+
+    crate::wait_process_as_thread(xous_client).ok();
+    terminated_send.send(()).unwrap();
+    crate::wait_process_as_thread(xous_server).expect("couldn't join server process");
+    shutdown_kernel();
+
+    main_thread.join().expect("couldn't join kernel process");
+}
+
+/// Test that clients blocked on a server are woken up when its process
+/// terminates, and can connect to it again once it restarts
+#[test]
+fn server_terminated() {
+    const LEN: usize = 4096;
+
+    let main_thread = start_kernel(SERVER_SPEC);
+    let (server_addr_send, server_addr_recv) = channel();
+    let (scalar_addr_send, scalar_addr_recv) = channel();
+    let (restart_send, restart_recv) = channel();
+    let (restarted_send, restarted_recv) = channel();
+
+    let xous_server = xous_kernel::create_process_as_thread(xous_kernel::ProcessArgsAsThread::new(
+        "server_terminated server",
+        move || {
+            let sid = xous_kernel::create_server(b"server_terminate")
+                .expect("couldn't create test server");
+            server_addr_send.send(sid).unwrap();
+            scalar_addr_send.send(sid).unwrap();
+
+            // Hold on to both messages without responding, and then exit.
+            for _ in 0..2 {
+                xous_kernel::receive_message(sid).expect("couldn't receive messages");
+            }
+        },
+    ))
+    .expect("couldn't start server");
+
+    let scalar_client = xous_kernel::create_process_as_thread(
+        xous_kernel::ProcessArgsAsThread::new("server_terminated scalar client", move || {
+            let sid = scalar_addr_recv.recv().unwrap();
+            let conn = xous_kernel::try_connect(sid).expect("couldn't connect to server");
+            let msg = xous_kernel::ScalarMessage {
+                id: 1,
+                arg1: 0,
+                arg2: 0,
+                arg3: 0,
+                arg4: 0,
+            };
+            assert_eq!(
+                xous_kernel::try_send_message(conn, xous_kernel::Message::BlockingScalar(msg)),
+                Err(xous_kernel::Error::ServerNotFound)
+            );
+        }),
+    )
+    .expect("couldn't start scalar client");
+
+    let xous_client = xous_kernel::create_process_as_thread(xous_kernel::ProcessArgsAsThread::new(
+        "server_terminated client",
+        move || {
+            let sid = server_addr_recv.recv().unwrap();
+            let conn = xous_kernel::try_connect(sid).expect("couldn't connect to server");
+            let notification =
+                xous_kernel::create_notification().expect("couldn't create notification");
+            xous_kernel::watch_disconnect(conn, notification).expect("couldn't watch connection");
+
+            let test_bytes: Vec<u8> = (0..LEN).map(|index| (index % 251) as u8).collect();
+            let mut carton = xous_kernel::carton::Carton::from_bytes(&test_bytes);
+            assert_eq!(
+                carton.lend_mut(conn, 2),
+                Err(xous_kernel::Error::ServerNotFound)
+            );
+            let returned: &[u8] = carton.as_ref();
+            assert_eq!(returned, test_bytes.as_slice());
+
+            xous_kernel::wait_notification(notification).expect("couldn't wait for notification");
+            let msg = xous_kernel::ScalarMessage {
+                id: 1,
+                arg1: 0,
+                arg2: 0,
+                arg3: 0,
+                arg4: 0,
+            };
+            assert_eq!(
+                xous_kernel::try_send_message(conn, xous_kernel::Message::Scalar(msg)),
+                Err(xous_kernel::Error::ServerNotFound)
+            );
+            xous_kernel::disconnect(conn).expect("couldn't disconnect");
+
+            // Connect to the restarted server.
+            restart_send.send(()).unwrap();
+            restarted_recv.recv().unwrap();
+            let conn = xous_kernel::try_connect(sid).expect("couldn't reconnect to server");
+            assert_eq!(
+                xous_kernel::try_send_message(conn, xous_kernel::Message::BlockingScalar(msg)),
+                Ok(xous_kernel::Result::Scalar1(1))
+            );
+        },
+    ))
+    .expect("couldn't start client");
+
+    crate::wait_process_as_thread(xous_server).expect("couldn't join server process");
+    crate::wait_process_as_thread(scalar_client).expect("couldn't join scalar client process");
+    restart_recv.recv().unwrap();
+    let xous_server = xous_kernel::create_process_as_thread(xous_kernel::ProcessArgsAsThread::new(
+        "server_terminated restarted server",
+        move || {
+            let sid = xous_kernel::create_server(b"server_terminate")
+                .expect("couldn't create test server");
+            restarted_send.send(()).unwrap();
+            let envelope = xous_kernel::receive_message(sid).expect("couldn't receive messages");
+            xous_kernel::return_scalar(envelope.sender, 1).unwrap();
+        },
+    ))
+    .expect("couldn't restart server");
+
+    crate::wait_process_as_thread(xous_client).expect("couldn't join client process");
+    crate::wait_process_as_thread(xous_server).expect("couldn't join server process");
+    shutdown_kernel();
+
+    main_thread.join().expect("couldn't join kernel process");
+}
+
+#[test]
+fn send_scattered_mutableborrow_message() {
+    const PIECE_LEN: usize = 4096;
+
+    let main_thread = start_kernel(SERVER_SPEC);
+    let (server_addr_send, server_addr_recv) = channel();
+
+    let xous_server = xous_kernel::create_process_as_thread(xous_kernel::ProcessArgsAsThread::new(
+        "send_scattered_mutableborrow_message server",
+        move || {
+            let sid = xous_kernel::create_server(b"send_scattered_m")
+                .expect("couldn't create test server");
+            server_addr_send.send(sid).unwrap();
+            let envelope = xous_kernel::receive_message(sid).expect("couldn't receive messages");
+            if let xous_kernel::Message::MutableBorrow(m) = envelope.body {
+                // The pieces arrive as one buffer, in order.
+                let buf = m.buf;
+                assert_eq!(buf.len(), PIECE_LEN * 2);
+                let bt = unsafe { core::slice::from_raw_parts_mut(buf.as_mut_ptr(), buf.len()) };
+                for (index, byte) in bt.iter().enumerate() {
+                    assert_eq!(*byte, (index / PIECE_LEN) as u8 + 1);
+                }
+                for byte in bt.iter_mut() {
+                    *byte += 10;
+                }
+                xous_kernel::return_memory(envelope.sender, m.buf).unwrap();
+            } else {
+                panic!("unexpected message type");
+            }
+        },
+    ))
+    .expect("couldn't start server");
+
+    let xous_client = xous_kernel::create_process_as_thread(xous_kernel::ProcessArgsAsThread::new(
+        "send_scattered_mutableborrow_message client",
+        move || {
+            use xous_kernel::MemoryFlags;
+            let sid = server_addr_recv.recv().unwrap();
+            let conn = xous_kernel::try_connect(sid).expect("couldn't connect to server");
+
+            let pieces = [
+                xous_kernel::map_memory(None, None, PIECE_LEN, MemoryFlags::R | MemoryFlags::W)
+                    .expect("couldn't map memory"),
+                xous_kernel::map_memory(None, None, PIECE_LEN, MemoryFlags::R | MemoryFlags::W)
+                    .expect("couldn't map memory"),
+            ];
+            for (index, piece) in pieces.iter().enumerate() {
+                unsafe { core::slice::from_raw_parts_mut(piece.as_mut_ptr(), piece.len()) }
+                    .fill(index as u8 + 1);
+            }
+
+            let scatter =
+                xous_kernel::ScatterList::new(&pieces).expect("couldn't build scatter list");
+            let msg = xous_kernel::MemoryMessage::scattered(4, &scatter)
+                .expect("couldn't build scattered message");
+            xous_kernel::try_send_message(conn, xous_kernel::Message::MutableBorrow(msg))
+                .expect("couldn't lend scattered memory");
+
+            for (index, piece) in pieces.iter().enumerate() {
+                let bytes = unsafe { core::slice::from_raw_parts(piece.as_ptr(), piece.len()) };
+                assert!(bytes.iter().all(|&byte| byte == index as u8 + 11));
+            }
+        },
+    ))
+    .expect("couldn't start client");
+
+    crate::wait_process_as_thread(xous_server).expect("couldn't join server process");
+    crate::wait_process_as_thread(xous_client).expect("couldn't join client process");
+    shutdown_kernel();
+
+    main_thread.join().expect("couldn't join kernel process");
+}
+
+#[test]
+fn call_message() {
+    let main_thread = start_kernel(SERVER_SPEC);
+    let (server_addr_send, server_addr_recv) = channel();
+    let test_bytes = b"Hello, world!";
+
+    let xous_server = xous_kernel::create_process_as_thread(xous_kernel::ProcessArgsAsThread::new(
+        "call_message server",
+        move || {
+            let sid =
+                xous_kernel::create_server(b"call_message_srv").expect("couldn't create test server");
+            server_addr_send.send(sid).unwrap();
+
+            // A plain scalar sent as a call has to be answered.
+            let envelope = xous_kernel::receive_message(sid).expect("couldn't receive messages");
+            assert_eq!(
+                envelope.body,
+                xous_kernel::Message::BlockingScalar(xous_kernel::ScalarMessage {
+                    id: 1,
+                    arg1: 2,
+                    arg2: 3,
+                    arg3: 4,
+                    arg4: 5
+                })
+            );
+            xous_kernel::return_scalar2(envelope.sender, 6, 7).expect("couldn't return scalar");
+
+            let envelope = xous_kernel::receive_message(sid).expect("couldn't receive messages");
+            if let xous_kernel::Message::Borrow(m) = &envelope.body {
+                let bt = unsafe { core::slice::from_raw_parts(m.buf.as_ptr(), m.buf.len()) };
+                assert_eq!(bt, test_bytes);
+                xous_kernel::return_memory(envelope.sender, m.buf).expect("couldn't return memory");
+            } else {
+                panic!("unexpected message type");
+            }
+        },
+    ))
+    .expect("couldn't start server");
+
+    let xous_client = xous_kernel::create_process_as_thread(xous_kernel::ProcessArgsAsThread::new(
+        "call_message client",
+        move || {
+            let sid = server_addr_recv.recv().unwrap();
+            let conn = xous_kernel::try_connect(sid).expect("couldn't connect to server");
+
+            let reply = xous_kernel::call_message(
+                conn,
+                xous_kernel::Message::Scalar(xous_kernel::ScalarMessage {
+                    id: 1,
+                    arg1: 2,
+                    arg2: 3,
+                    arg3: 4,
+                    arg4: 5,
+                }),
+            )
+            .expect("couldn't call server");
+            assert_eq!(reply, xous_kernel::Result::Scalar2(6, 7));
+
+            let carton = xous_kernel::carton::Carton::from_bytes(test_bytes);
+            let msg = xous_kernel::MemoryMessage {
+                id: 2,
+                buf: *carton.as_ref(),
+                offset: None,
+                valid: None,
+            };
+            let reply = xous_kernel::call_message(conn, xous_kernel::Message::Borrow(msg))
+                .expect("couldn't call server");
+            assert_eq!(reply, xous_kernel::Result::Ok);
+
+            // There's nothing to reply to once memory has been moved away.
+            assert_eq!(
+                xous_kernel::call_message(conn, xous_kernel::Message::Move(carton.into_message(3))),
+                Err(xous_kernel::Error::InvalidSyscall)
+            );
+        },
+    ))
+    .expect("couldn't start client");
+
+    crate::wait_process_as_thread(xous_server).expect("couldn't join server process");
+    crate::wait_process_as_thread(xous_client).expect("couldn't join client process");
+
+    shutdown_kernel();
+    main_thread.join().expect("couldn't join kernel process");
+}
+
+#[test]
+fn broadcast_message() {
+    let main_thread = start_kernel(SERVER_SPEC);
+    let (ready_send, ready_recv) = channel();
+    let mut id_sends = vec![];
+    let mut subscribers = vec![];
+
+    for (opcode, name) in [(10usize, b"broadcast_sub_10"), (20, b"broadcast_sub_20")] {
+        let (id_send, id_recv) = channel();
+        let ready_send = ready_send.clone();
+        id_sends.push(id_send);
+        subscribers.push(
+            xous_kernel::create_process_as_thread(xous_kernel::ProcessArgsAsThread::new(
+                "broadcast subscriber",
+                move || {
+                    let id: usize = id_recv.recv().unwrap();
+                    let sid =
+                        xous_kernel::create_server(name).expect("couldn't create test server");
+                    let conn = xous_kernel::try_connect(sid).expect("couldn't connect to server");
+                    xous_kernel::subscribe(id, conn, opcode).expect("couldn't subscribe");
+
+                    // Only the process that created the list may broadcast on it.
+                    assert_eq!(
+                        xous_kernel::broadcast(id, [0, 0, 0, 0]),
+                        Err(xous_kernel::Error::AccessDenied)
+                    );
+                    ready_send.send(()).unwrap();
+
+                    let envelope =
+                        xous_kernel::receive_message(sid).expect("couldn't receive messages");
+                    assert_eq!(
+                        envelope.body,
+                        xous_kernel::Message::Scalar(xous_kernel::ScalarMessage {
+                            id: opcode,
+                            arg1: 1,
+                            arg2: 2,
+                            arg3: 3,
+                            arg4: 4
+                        })
+                    );
+                },
+            ))
+            .expect("couldn't start subscriber"),
+        );
+    }
+
+    let publisher = xous_kernel::create_process_as_thread(xous_kernel::ProcessArgsAsThread::new(
+        "broadcast publisher",
+        move || {
+            let id = xous_kernel::create_broadcast().expect("couldn't create broadcast list");
+            for id_send in id_sends {
+                id_send.send(id).unwrap();
+            }
+            ready_recv.recv().unwrap();
+            ready_recv.recv().unwrap();
+
+            assert_eq!(xous_kernel::broadcast(id, [1, 2, 3, 4]), Ok(2));
+        },
+    ))
+    .expect("couldn't start publisher");
+
+    crate::wait_process_as_thread(publisher).expect("couldn't join publisher process");
+    for subscriber in subscribers {
+        crate::wait_process_as_thread(subscriber).expect("couldn't join subscriber process");
+    }
+
+    shutdown_kernel();
+    main_thread.join().expect("couldn't join kernel process");
+}
+
+#[test]
+fn message_timestamp() {
+    let main_thread = start_kernel(SERVER_SPEC);
+    let (server_addr_send, server_addr_recv) = channel();
+    let (sent_send, sent_recv) = channel();
+
+    let xous_server = xous_kernel::create_process_as_thread(xous_kernel::ProcessArgsAsThread::new(
+        "message_timestamp server",
+        move || {
+            assert_eq!(xous_kernel::message_timestamp(), Ok(0));
+            let sid = xous_kernel::create_server(b"message_timestam")
+                .expect("couldn't create test server");
+            server_addr_send.send(sid).unwrap();
+
+            // A queued message is stamped when it was sent, not when it's
+            // received.
+            let (before, after) = sent_recv.recv().unwrap();
+            std::thread::sleep(std::time::Duration::from_millis(10));
+            xous_kernel::receive_message(sid).expect("couldn't receive messages");
+            let stamp = xous_kernel::message_timestamp().expect("couldn't get timestamp");
+            assert!(stamp >= before && stamp <= after);
+
+            // A message handed straight to a waiting thread is stamped as
+            // it's delivered.
+            xous_kernel::receive_message(sid).expect("couldn't receive messages");
+            let stamp = xous_kernel::message_timestamp().expect("couldn't get timestamp");
+            let (before, _) = sent_recv.recv().unwrap();
+            assert!(stamp >= before && stamp <= xous_kernel::timestamp::now());
+        },
+    ))
+    .expect("couldn't start server");
+
+    let xous_client = xous_kernel::create_process_as_thread(xous_kernel::ProcessArgsAsThread::new(
+        "message_timestamp client",
+        move || {
+            let sid = server_addr_recv.recv().unwrap();
+            let conn = xous_kernel::try_connect(sid).expect("couldn't connect to server");
+
+            for id in 0..2 {
+                // Give the server time to start waiting for the second one.
+                if id == 1 {
+                    std::thread::sleep(std::time::Duration::from_millis(50));
+                }
+                let message = xous_kernel::Message::Scalar(xous_kernel::ScalarMessage {
+                    id,
+                    arg1: 2,
+                    arg2: 3,
+                    arg3: 4,
+                    arg4: 5,
+                });
+                let before = xous_kernel::timestamp::now();
+                xous_kernel::try_send_message(conn, message).expect("couldn't send message");
+                sent_send
+                    .send((before, xous_kernel::timestamp::now()))
+                    .unwrap();
+            }
+        },
+    ))
+    .expect("couldn't start client");
+
+    crate::wait_process_as_thread(xous_server).expect("couldn't join server process");
+    crate::wait_process_as_thread(xous_client).expect("couldn't join client process");
+
+    shutdown_kernel();
+    main_thread.join().expect("couldn't join kernel process");
+}
+
+#[test]
+fn set_lend_timeout() {
+    let main_thread = start_kernel(SERVER_SPEC);
+
+    let xous_process = xous_kernel::create_process_as_thread(
+        xous_kernel::ProcessArgsAsThread::new("set_lend_timeout process", || {
+            assert_eq!(xous_kernel::set_lend_timeout(1000), Ok(0));
+            assert_eq!(xous_kernel::set_lend_timeout(0), Ok(1000));
+        }),
+    )
+    .expect("couldn't start process");
+
+    xous_kernel::wait_process_as_thread(xous_process).expect("couldn't join process");
+    shutdown_kernel();
+
+    main_thread.join().expect("couldn't join kernel process");
+}
+
+#[test]
+fn query_services() {
+    let main_thread = start_kernel(SERVER_SPEC);
+    let (server_addr_send, server_addr_recv) = channel();
+    let (done_send, done_recv) = channel();
+
+    let xous_server = xous_kernel::create_process_as_thread(xous_kernel::ProcessArgsAsThread::new(
+        "query_services server",
+        move || {
+            let sid = xous_kernel::create_server(b"query_services!!")
+                .expect("couldn't create test server");
+            assert_eq!(
+                xous_kernel::query_services(0),
+                Err(xous_kernel::Error::AccessDenied)
+            );
+            server_addr_send.send(sid).unwrap();
+
+            // Leave the messages queued until the client has looked.
+            done_recv.recv().unwrap();
+            for _ in 0..2 {
+                xous_kernel::receive_message(sid).expect("couldn't receive messages");
+            }
+        },
+    ))
+    .expect("couldn't start server");
+
+    let xous_client = xous_kernel::create_process_as_thread(
+        xous_kernel::ProcessArgsAsThread::new("query_services client", move || {
+            let sid = server_addr_recv.recv().unwrap();
+            let conn = xous_kernel::try_connect(sid).expect("couldn't connect to server");
+            for id in 0..2 {
+                xous_kernel::try_send_message(
+                    conn,
+                    xous_kernel::Message::Scalar(xous_kernel::ScalarMessage {
+                        id,
+                        arg1: 0,
+                        arg2: 0,
+                        arg3: 0,
+                        arg4: 0,
+                    }),
+                )
+                .expect("couldn't send message");
+            }
+
+            // Walk the whole table, the way `lsipc` would.
+            let mut servers = vec![];
+            for index in 0.. {
+                match xous_kernel::query_services(index) {
+                    Ok(info) => servers.push(info),
+                    Err(xous_kernel::Error::ServerNotFound) => continue,
+                    Err(xous_kernel::Error::InvalidSyscall) => break,
+                    Err(e) => panic!("couldn't query services: {:?}", e),
+                }
+            }
+            let info = servers
+                .iter()
+                .find(|info| info.queued == 2)
+                .expect("couldn't find test server");
+            assert_eq!(info.queue_length, crate::server::DEFAULT_QUEUE_LENGTH);
+            assert_eq!(info.outstanding, 0);
+            // Creating a server connects its own process to it, too.
+            assert_eq!(info.connections, 2);
+            assert_eq!(info.parked_threads, 0);
+            done_send.send(()).unwrap();
+        })
+        .capabilities(xous_kernel::Capabilities::SUPERVISE),
+    )
+    .expect("couldn't start client");
+
+    crate::wait_process_as_thread(xous_server).expect("couldn't join server process");
+    crate::wait_process_as_thread(xous_client).expect("couldn't join client process");
+
+    shutdown_kernel();
+    main_thread.join().expect("couldn't join kernel process");
+}
+
+#[test]
+fn watchdog() {
+    let main_thread = start_kernel(SERVER_SPEC);
+    let (stalled_send, stalled_recv) = channel();
+    let (done_send, done_recv) = channel();
+
+    let watched = xous_kernel::create_process_as_thread(xous_kernel::ProcessArgsAsThread::new(
+        "watchdog watched",
+        move || {
+            assert_eq!(
+                xous_kernel::heartbeat(),
+                Err(xous_kernel::Error::InvalidSyscall)
+            );
+            assert_eq!(
+                xous_kernel::take_missed_heartbeat(),
+                Err(xous_kernel::Error::AccessDenied)
+            );
+            assert_eq!(
+                xous_kernel::set_watchdog_supervisor(0),
+                Err(xous_kernel::Error::AccessDenied)
+            );
+
+            // Hosted timestamps count nanoseconds, so this is 10ms.
+            xous_kernel::set_heartbeat(10_000_000).expect("couldn't set heartbeat");
+            xous_kernel::heartbeat().expect("couldn't poke heartbeat");
+            std::thread::sleep(std::time::Duration::from_millis(30));
+            stalled_send.send(()).unwrap();
+
+            // Stay alive until the supervisor has looked, since a process
+            // that exits stops being watched.
+            done_recv.recv().unwrap();
+        },
+    ))
+    .expect("couldn't start watched process");
+
+    let supervisor = xous_kernel::create_process_as_thread(
+        xous_kernel::ProcessArgsAsThread::new("watchdog supervisor", move || {
+            let notification =
+                xous_kernel::create_notification().expect("couldn't create notification");
+            xous_kernel::set_watchdog_supervisor(notification).expect("couldn't supervise");
+
+            stalled_recv.recv().unwrap();
+            assert!(xous_kernel::take_missed_heartbeat()
+                .expect("couldn't check heartbeats")
+                .is_some());
+
+            // The stuck process got a fresh deadline when it was taken.
+            assert_eq!(xous_kernel::take_missed_heartbeat(), Ok(None));
+            done_send.send(()).unwrap();
+        })
+        .capabilities(xous_kernel::Capabilities::SUPERVISE),
+    )
+    .expect("couldn't start supervisor");
+
+    crate::wait_process_as_thread(watched).expect("couldn't join watched process");
+    crate::wait_process_as_thread(supervisor).expect("couldn't join supervisor");
+
+    shutdown_kernel();
+    main_thread.join().expect("couldn't join kernel process");
+}
+
+#[test]
+fn free_interrupt() {
+    // Interrupt numbers are shared between kernels, so pick one that no other
+    // test uses.
+    const IRQ: usize = 20;
+    fn handler(_irq_no: usize, _arg: *mut usize) {}
+
+    let main_thread = start_kernel(SERVER_SPEC);
+    let (claimed_send, claimed_recv) = channel();
+    let (checked_send, checked_recv) = channel();
+
+    let driver = xous_kernel::create_process_as_thread(driver_process("irq driver", move || {
+        xous_kernel::claim_interrupt(IRQ, handler, core::ptr::null_mut())
+            .expect("couldn't claim interrupt");
+        // Claiming it again replaces the handler.
+        xous_kernel::claim_interrupt(IRQ, handler, core::ptr::null_mut())
+            .expect("couldn't claim interrupt again");
+        claimed_send.send(()).unwrap();
+        checked_recv.recv().unwrap();
+
+        xous_kernel::free_interrupt(IRQ).expect("couldn't free interrupt");
+        assert_eq!(
+            xous_kernel::free_interrupt(IRQ),
+            Err(xous_kernel::Error::InterruptNotFound)
+        );
+        assert_eq!(
+            xous_kernel::free_interrupt(32),
+            Err(xous_kernel::Error::InterruptNotFound)
+        );
+
+        // Exiting gives the interrupt back.
+        xous_kernel::claim_interrupt(IRQ, handler, core::ptr::null_mut())
+            .expect("couldn't reclaim interrupt");
+    }))
+    .expect("couldn't start driver");
+
+    let other = xous_kernel::create_process_as_thread(driver_process("irq other", move || {
+        claimed_recv.recv().unwrap();
+        assert_eq!(
+            xous_kernel::free_interrupt(IRQ),
+            Err(xous_kernel::Error::InterruptNotFound)
+        );
+        // Other processes may share the line.
+        xous_kernel::claim_interrupt(IRQ, handler, core::ptr::null_mut())
+            .expect("couldn't share interrupt");
+        xous_kernel::free_interrupt(IRQ).expect("couldn't free shared interrupt");
+        checked_send.send(()).unwrap();
+    }))
+    .expect("couldn't start other process");
+
+    crate::wait_process_as_thread(driver).expect("couldn't join driver");
+    crate::wait_process_as_thread(other).expect("couldn't join other process");
+
+    let restarted =
+        xous_kernel::create_process_as_thread(driver_process("irq restarted driver", move || {
+            xous_kernel::claim_interrupt(IRQ, handler, core::ptr::null_mut())
+                .expect("couldn't claim interrupt after the driver exited");
+            xous_kernel::free_interrupt(IRQ).expect("couldn't free interrupt");
+        }))
+        .expect("couldn't start restarted driver");
+    crate::wait_process_as_thread(restarted).expect("couldn't join restarted driver");
+
+    shutdown_kernel();
+    main_thread.join().expect("couldn't join kernel process");
+}
+
+#[test]
+fn claim_interrupt_message() {
+    // Interrupt numbers are shared between kernels, so pick one that no other
+    // test uses.
+    const IRQ: usize = 21;
+    fn handler(_irq_no: usize, _arg: *mut usize) {}
+
+    let main_thread = start_kernel(SERVER_SPEC);
+    let (claimed_send, claimed_recv) = channel();
+    let (checked_send, checked_recv) = channel();
+
+    let driver =
+        xous_kernel::create_process_as_thread(driver_process("irq message driver", move || {
+            assert_eq!(
+                xous_kernel::claim_interrupt_message(IRQ, 99, 1),
+                Err(xous_kernel::Error::ServerNotFound)
+            );
+            let sid = xous_kernel::create_server(b"irq_message_serv")
+                .expect("couldn't create test server");
+            let conn = xous_kernel::try_connect(sid).expect("couldn't connect to server");
+            xous_kernel::claim_interrupt_message(IRQ, conn, 1).expect("couldn't claim interrupt");
+            assert_eq!(
+                xous_kernel::claim_interrupt_message(32, conn, 1),
+                Err(xous_kernel::Error::InterruptNotFound)
+            );
+            claimed_send.send(()).unwrap();
+            checked_recv.recv().unwrap();
+            xous_kernel::free_interrupt(IRQ).expect("couldn't free interrupt");
+        }))
+        .expect("couldn't start driver");
+
+    let other =
+        xous_kernel::create_process_as_thread(driver_process("irq message other", move || {
+            claimed_recv.recv().unwrap();
+            xous_kernel::claim_interrupt(IRQ, handler, core::ptr::null_mut())
+                .expect("couldn't share interrupt");
+            xous_kernel::free_interrupt(IRQ).expect("couldn't free shared interrupt");
+            checked_send.send(()).unwrap();
+        }))
+        .expect("couldn't start other process");
+
+    crate::wait_process_as_thread(driver).expect("couldn't join driver");
+    crate::wait_process_as_thread(other).expect("couldn't join other process");
+
+    shutdown_kernel();
+    main_thread.join().expect("couldn't join kernel process");
+}
+
+#[test]
+fn shared_interrupt() {
+    // Interrupt numbers are shared between kernels, so pick one that no other
+    // test uses.
+    const IRQ: usize = 22;
+    fn handler(_irq_no: usize, _arg: *mut usize) {}
+
+    let main_thread = start_kernel(SERVER_SPEC);
+    let (claimed_send, claimed_recv) = channel();
+    let mut done_sends = vec![];
+    let mut sharers = vec![];
+
+    for _ in 0..4 {
+        let claimed_send = claimed_send.clone();
+        let (done_send, done_recv) = channel::<()>();
+        done_sends.push(done_send);
+        sharers.push(
+            xous_kernel::create_process_as_thread(driver_process("irq sharer", move || {
+                xous_kernel::claim_interrupt(IRQ, handler, core::ptr::null_mut())
+                    .expect("couldn't share interrupt");
+                // Masking a share doesn't affect anyone else.
+                xous_kernel::disable_irq(IRQ).expect("couldn't disable interrupt");
+                xous_kernel::enable_irq(IRQ).expect("couldn't enable interrupt");
+                claimed_send.send(()).unwrap();
+                done_recv.recv().unwrap();
+            }))
+            .expect("couldn't start sharer"),
+        );
+    }
+    for _ in 0..4 {
+        claimed_recv.recv().unwrap();
+    }
+
+    let latecomer =
+        xous_kernel::create_process_as_thread(driver_process("irq latecomer", move || {
+            assert_eq!(
+                xous_kernel::claim_interrupt(IRQ, handler, core::ptr::null_mut()),
+                Err(xous_kernel::Error::InterruptInUse)
+            );
+            assert_eq!(
+                xous_kernel::disable_irq(IRQ),
+                Err(xous_kernel::Error::InterruptNotFound)
+            );
+            assert_eq!(
+                xous_kernel::enable_irq(32),
+                Err(xous_kernel::Error::InterruptNotFound)
+            );
+        }))
+        .expect("couldn't start latecomer");
+    crate::wait_process_as_thread(latecomer).expect("couldn't join latecomer");
+
+    for done_send in done_sends {
+        done_send.send(()).unwrap();
+    }
+    for sharer in sharers {
+        crate::wait_process_as_thread(sharer).expect("couldn't join sharer");
+    }
+
+    shutdown_kernel();
+    main_thread.join().expect("couldn't join kernel process");
+}
+
+#[test]
+fn get_ticks() {
+    let main_thread = start_kernel(SERVER_SPEC);
+
+    let xous_process = xous_kernel::create_process_as_thread(
+        xous_kernel::ProcessArgsAsThread::new("get ticks", || {
+            let before = xous_kernel::get_ticks().expect("couldn't get ticks");
+            // Hosted ticks are a millisecond long.
+            std::thread::sleep(std::time::Duration::from_millis(20));
+            let after = xous_kernel::get_ticks().expect("couldn't get ticks");
+            assert!(after >= before + 20);
+        }),
+    )
+    .expect("couldn't start process");
+    crate::wait_process_as_thread(xous_process).expect("couldn't join process");
+
+    shutdown_kernel();
+    main_thread.join().expect("couldn't join kernel process");
+}
+
+#[test]
+fn kernel_timers() {
+    let main_thread = start_kernel(SERVER_SPEC);
+
+    let xous_process = xous_kernel::create_process_as_thread(
+        xous_kernel::ProcessArgsAsThread::new("kernel timers", || {
+            assert_eq!(
+                xous_kernel::create_timer(99, 1, 10, 0),
+                Err(xous_kernel::Error::ServerNotFound)
+            );
+            let sid =
+                xous_kernel::create_server(b"kernel_timer_srv").expect("couldn't create server");
+            let conn = xous_kernel::try_connect(sid).expect("couldn't connect to server");
+
+            let once = xous_kernel::create_timer(conn, 1, 10, 0).expect("couldn't create timer");
+            let envelope = xous_kernel::receive_message(sid).expect("couldn't receive message");
+            assert_eq!(
+                envelope.body,
+                xous_kernel::Message::Scalar(xous_kernel::ScalarMessage {
+                    id: 1,
+                    arg1: once,
+                    arg2: 0,
+                    arg3: 0,
+                    arg4: 0
+                })
+            );
+            // A one-shot timer goes away once it expires.
+            assert_eq!(
+                xous_kernel::cancel_timer(once),
+                Err(xous_kernel::Error::InvalidSyscall)
+            );
+
+            let periodic = xous_kernel::create_timer(conn, 2, 5, 5).expect("couldn't create timer");
+            for _ in 0..3 {
+                let envelope = xous_kernel::receive_message(sid).expect("couldn't receive message");
+                match envelope.body {
+                    xous_kernel::Message::Scalar(scalar) => {
+                        assert_eq!((scalar.id, scalar.arg1), (2, periodic))
+                    }
+                    _ => panic!("unexpected message"),
+                }
+            }
+            xous_kernel::cancel_timer(periodic).expect("couldn't cancel timer");
+        }),
+    )
+    .expect("couldn't start process");
+    crate::wait_process_as_thread(xous_process).expect("couldn't join process");
+
+    shutdown_kernel();
+    main_thread.join().expect("couldn't join kernel process");
+}
+
+#[test]
+fn sleep_thread() {
+    let main_thread = start_kernel(SERVER_SPEC);
+
+    let xous_process = xous_kernel::create_process_as_thread(
+        xous_kernel::ProcessArgsAsThread::new("sleep thread", || {
+            xous_kernel::sleep_thread(0).expect("couldn't sleep");
+
+            let start = std::time::Instant::now();
+            xous_kernel::sleep_thread(20).expect("couldn't sleep");
+            assert!(start.elapsed() >= std::time::Duration::from_millis(20));
+        }),
+    )
+    .expect("couldn't start process");
+    crate::wait_process_as_thread(xous_process).expect("couldn't join process");
+
+    shutdown_kernel();
+    main_thread.join().expect("couldn't join kernel process");
+}
+
+#[test]
+fn raise_irq() {
+    // Interrupt numbers are shared between kernels, so pick one that no other
+    // test uses.
+    const IRQ: usize = 23;
+
+    let main_thread = start_kernel(SERVER_SPEC);
+
+    let xous_process = xous_kernel::create_process_as_thread(driver_process("raise irq", || {
+        let sid = xous_kernel::create_server(b"raise_irq_server").expect("couldn't create server");
+        let conn = xous_kernel::try_connect(sid).expect("couldn't connect to server");
+        xous_kernel::claim_interrupt_message(IRQ, conn, 7).expect("couldn't claim interrupt");
+
+        // Interrupts that fire before the last one was received are
+        // counted in the same message.
+        xous_kernel::raise_irq(IRQ).expect("couldn't raise interrupt");
+        xous_kernel::raise_irq(IRQ).expect("couldn't raise interrupt");
+        let envelope = xous_kernel::receive_message(sid).expect("couldn't receive message");
+        assert_eq!(
+            envelope.body,
+            xous_kernel::Message::Scalar(xous_kernel::ScalarMessage {
+                id: 7,
+                arg1: IRQ,
+                arg2: 2,
+                arg3: 0,
+                arg4: 0
+            })
+        );
+
+        // A masked interrupt isn't delivered.
+        xous_kernel::disable_irq(IRQ).expect("couldn't disable interrupt");
+        xous_kernel::raise_irq(IRQ).expect("couldn't raise interrupt");
+        xous_kernel::enable_irq(IRQ).expect("couldn't enable interrupt");
+        xous_kernel::raise_irq(IRQ).expect("couldn't raise interrupt");
+        let envelope = xous_kernel::receive_message(sid).expect("couldn't receive message");
+        match envelope.body {
+            xous_kernel::Message::Scalar(scalar) => assert_eq!(scalar.arg2, 1),
+            _ => panic!("unexpected message"),
+        }
+
+        assert_eq!(
+            xous_kernel::raise_irq(32),
+            Err(xous_kernel::Error::InterruptNotFound)
+        );
+        xous_kernel::free_interrupt(IRQ).expect("couldn't free interrupt");
+    }))
+    .expect("couldn't start process");
+    crate::wait_process_as_thread(xous_process).expect("couldn't join process");
+
+    shutdown_kernel();
+    main_thread.join().expect("couldn't join kernel process");
+}
+
+#[test]
+fn capabilities() {
+    // Interrupt numbers are shared between kernels, so pick one that no other
+    // test uses.
+    const IRQ: usize = 24;
+    fn handler(_irq_no: usize, _arg: *mut usize) {}
+
+    let main_thread = start_kernel(SERVER_SPEC);
+
+    let unprivileged = xous_kernel::create_process_as_thread(
+        xous_kernel::ProcessArgsAsThread::new("no capabilities", || {
+            assert_eq!(
+                xous_kernel::claim_interrupt(IRQ, handler, core::ptr::null_mut()),
+                Err(xous_kernel::Error::AccessDenied)
+            );
+            assert_eq!(
+                xous_kernel::map_memory(
+                    xous_kernel::MemoryAddress::new(0x8000_0000),
+                    None,
+                    4096,
+                    xous_kernel::MemoryFlags::R | xous_kernel::MemoryFlags::W,
+                ),
+                Err(xous_kernel::Error::AccessDenied)
+            );
+            assert_eq!(
+                rsyscall(SysCall::Shutdown(ScrubLevel::None)),
+                Err(xous_kernel::Error::AccessDenied)
+            );
+        }),
+    )
+    .expect("couldn't start unprivileged process");
+    crate::wait_process_as_thread(unprivileged).expect("couldn't join unprivileged process");
+
+    // A driver only holds the capabilities it was given.
+    let driver = xous_kernel::create_process_as_thread(driver_process("capable driver", || {
+        xous_kernel::claim_interrupt(IRQ, handler, core::ptr::null_mut())
+            .expect("couldn't claim interrupt");
+        xous_kernel::free_interrupt(IRQ).expect("couldn't free interrupt");
+        assert_eq!(
+            rsyscall(SysCall::Shutdown(ScrubLevel::None)),
+            Err(xous_kernel::Error::AccessDenied)
+        );
+    }))
+    .expect("couldn't start driver");
+    crate::wait_process_as_thread(driver).expect("couldn't join driver");
+
+    shutdown_kernel();
+    main_thread.join().expect("couldn't join kernel process");
+}
+
+#[test]
+fn syscall_filter() {
+    use xous_kernel::{SysCallNumber, SyscallFilter};
+
+    let main_thread = start_kernel(SERVER_SPEC);
+    let (started_send, started_recv) = channel();
+    let (checked_send, checked_recv) = channel();
+
+    // Set the filter up by hand, since it has to go in between creating the
+    // process and starting it.  Starting a process as a thread needs
+    // `CreateThread`.
+    let args = xous_kernel::ProcessArgsAsThread::new("filtered", move || {
+        xous_kernel::get_ticks().expect("couldn't get ticks");
+        assert_eq!(
+            xous_kernel::create_server(b"filtered_server!"),
+            Err(xous_kernel::Error::AccessDenied)
+        );
+        assert_eq!(
+            rsyscall(SysCall::Yield),
+            Err(xous_kernel::Error::AccessDenied)
+        );
+        started_send.send(()).unwrap();
+        checked_recv.recv().unwrap();
+    });
+    let init = xous_kernel::arch::create_process_pre_as_thread(&args)
+        .expect("couldn't prepare filtered process");
+    let pid = match rsyscall(SysCall::CreateProcess(init)) {
+        Ok(xous_kernel::Result::ProcessID(pid)) => pid,
+        other => panic!("couldn't create filtered process: {:?}", other),
+    };
+    xous_kernel::set_syscall_filter(
+        pid,
+        SyscallFilter::empty()
+            .allow(SysCallNumber::CreateThread)
+            .allow(SysCallNumber::GetTicks),
+    )
+    .expect("couldn't set syscall filter");
+    let filtered = xous_kernel::arch::create_process_post_as_thread(args, init, pid)
+        .expect("couldn't start filtered process");
+
+    // Once the process is running its filter can't be changed, and only a
+    // parent can set one at all.
+    started_recv.recv().unwrap();
+    assert_eq!(
+        xous_kernel::set_syscall_filter(pid, SyscallFilter::all()),
+        Err(xous_kernel::Error::AccessDenied)
+    );
+    assert_eq!(
+        xous_kernel::set_syscall_filter(xous_kernel::PID::new(1).unwrap(), SyscallFilter::all()),
+        Err(xous_kernel::Error::ProcessNotChild)
+    );
+    checked_send.send(()).unwrap();
+    crate::wait_process_as_thread(filtered).expect("couldn't join filtered process");
+
+    shutdown_kernel();
+    main_thread.join().expect("couldn't join kernel process");
+}
+
+#[test]
+fn io_regions() {
+    use xous_kernel::{Capabilities, MemoryAddress, MemoryFlags, MemorySize, PID};
+    const DEVICE: usize = 0x4000_0000;
+
+    let main_thread = start_kernel(SERVER_SPEC);
+    let (started_send, started_recv) = channel();
+    let (checked_send, checked_recv) = channel();
+
+    let map_device = |phys: usize| {
+        xous_kernel::map_memory(
+            MemoryAddress::new(phys),
+            None,
+            4096,
+            MemoryFlags::R | MemoryFlags::W,
+        )
+        .map(|_| ())
+    };
+    let assign = |pid: usize, phys: usize, size: usize| {
+        xous_kernel::assign_io_region(
+            PID::new(pid as u8).unwrap(),
+            MemoryAddress::new(phys).unwrap(),
+            MemorySize::new(size).unwrap(),
+        )
+    };
+
+    // Hand the device to a driver before it starts.  Physical pages can't
+    // actually be mapped in a hosted environment, so the driver never maps
+    // it itself.
+    let args = xous_kernel::ProcessArgsAsThread::new("device driver", move || {
+        started_send.send(()).unwrap();
+        checked_recv.recv().unwrap();
+    });
+    let init = xous_kernel::arch::create_process_pre_as_thread(&args)
+        .expect("couldn't prepare device driver");
+    let driver_pid = match rsyscall(SysCall::CreateProcess(init)) {
+        Ok(xous_kernel::Result::ProcessID(pid)) => pid,
+        other => panic!("couldn't create device driver: {:?}", other),
+    };
+    assign(driver_pid.get() as usize, DEVICE, 2 * 4096).expect("couldn't assign device");
+    let driver = xous_kernel::arch::create_process_post_as_thread(args, init, driver_pid)
+        .expect("couldn't start device driver");
+    started_recv.recv().unwrap();
+
+    // Nobody else may map any part of the device, and only a process holding
+    // `Capabilities::IO_REGIONS` can hand regions out.
+    let rival = xous_kernel::create_process_as_thread(
+        xous_kernel::ProcessArgsAsThread::new("device rival", move || {
+            assert_eq!(
+                map_device(DEVICE + 4096),
+                Err(xous_kernel::Error::MemoryInUse)
+            );
+            assert_eq!(
+                assign(1, DEVICE + 0x10_0000, 4096),
+                Err(xous_kernel::Error::AccessDenied)
+            );
+        })
+        .capabilities(Capabilities::PHYSICAL_MEMORY),
+    )
+    .expect("couldn't start device rival");
+    crate::wait_process_as_thread(rival).expect("couldn't join device rival");
+
+    assert_eq!(
+        assign(1, DEVICE, 4096),
+        Err(xous_kernel::Error::MemoryInUse)
+    );
+    assert_eq!(
+        assign(1, DEVICE + 0x800, 4096),
+        Err(xous_kernel::Error::BadAlignment)
+    );
+    assert_eq!(
+        assign(30, DEVICE + 0x10_0000, 4096),
+        Err(xous_kernel::Error::ProcessNotFound)
+    );
+
+    // Once the driver is gone, the device can be handed to somebody else.
+    checked_send.send(()).unwrap();
+    crate::wait_process_as_thread(driver).expect("couldn't join device driver");
+
+    // The kernel notices the exit shortly after the thread is joined.
+    let mut result = assign(1, DEVICE, 4096);
+    for _ in 0..100 {
+        if result != Err(xous_kernel::Error::MemoryInUse) {
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        result = assign(1, DEVICE, 4096);
+    }
+    result.expect("couldn't assign device after its driver exited");
+
+    shutdown_kernel();
+    main_thread.join().expect("couldn't join kernel process");
+}
+
+#[test]
+fn get_random() {
+    let main_thread = start_kernel(SERVER_SPEC);
+
+    let xous_process = xous_kernel::create_process_as_thread(
+        xous_kernel::ProcessArgsAsThread::new("get random", || {
+            let mut first = [0u8; 32];
+            let mut second = [0u8; 32];
+            xous_kernel::get_random(&mut first).expect("couldn't get random bytes");
+            xous_kernel::add_entropy([1, 2, 3, 4]).expect("couldn't add entropy");
+            xous_kernel::get_random(&mut second).expect("couldn't get random bytes");
+            assert_ne!(first, [0u8; 32]);
+            assert_ne!(first, second);
+
+            // Lengths that aren't a whole number of words are fine too.
+            let mut odd = [0u8; 13];
+            xous_kernel::get_random(&mut odd).expect("couldn't get random bytes");
+        }),
+    )
+    .expect("couldn't start process");
+    crate::wait_process_as_thread(xous_process).expect("couldn't join process");
+
+    shutdown_kernel();
+    main_thread.join().expect("couldn't join kernel process");
+}
+
+#[test]
+fn private_server() {
+    let main_thread = start_kernel(SERVER_SPEC);
+    let (server_addr_send, server_addr_recv) = channel();
+    let (client_pid_send, client_pid_recv) = channel();
+    let (allowed_send, allowed_recv) = channel();
+    let (done_send, done_recv) = channel();
+
+    let xous_server = xous_kernel::create_process_as_thread(xous_kernel::ProcessArgsAsThread::new(
+        "private server",
+        move || {
+            let sid = xous_kernel::create_unnamed_server().expect("couldn't create server");
+            let other = xous_kernel::create_unnamed_server().expect("couldn't create server");
+            assert_ne!(sid, other);
+            assert_ne!(sid, xous_kernel::SID::from_u32(0, 0, 0, 0));
+
+            xous_kernel::make_server_private(sid).expect("couldn't make server private");
+            server_addr_send.send(sid).unwrap();
+            let client = client_pid_recv.recv().unwrap();
+            xous_kernel::allow_connect(sid, client).expect("couldn't allow client");
+            allowed_send.send(()).unwrap();
+            done_recv.recv().unwrap();
+        },
+    ))
+    .expect("couldn't start server");
+    let sid = server_addr_recv.recv().unwrap();
+
+    // Only the server itself can make it private or allow clients.
+    assert_eq!(
+        xous_kernel::make_server_private(sid),
+        Err(xous_kernel::Error::ServerNotFound)
+    );
+    assert_eq!(
+        xous_kernel::allow_connect(sid, xous_kernel::PID::new(1).unwrap()),
+        Err(xous_kernel::Error::ServerNotFound)
+    );
+
+    let args = xous_kernel::ProcessArgsAsThread::new("allowed client", move || {
+        xous_kernel::try_connect(sid).expect("allowed client couldn't connect");
+    });
+    let init = xous_kernel::arch::create_process_pre_as_thread(&args)
+        .expect("couldn't prepare allowed client");
+    let client_pid = match rsyscall(SysCall::CreateProcess(init)) {
+        Ok(xous_kernel::Result::ProcessID(pid)) => pid,
+        other => panic!("couldn't create allowed client: {:?}", other),
+    };
+    client_pid_send.send(client_pid).unwrap();
+    allowed_recv.recv().unwrap();
+    let client = xous_kernel::arch::create_process_post_as_thread(args, init, client_pid)
+        .expect("couldn't start allowed client");
+    crate::wait_process_as_thread(client).expect("couldn't join allowed client");
+
+    let rival = xous_kernel::create_process_as_thread(xous_kernel::ProcessArgsAsThread::new(
+        "rival client",
+        move || {
+            assert_eq!(
+                xous_kernel::try_connect(sid),
+                Err(xous_kernel::Error::AccessDenied)
+            );
+        },
+    ))
+    .expect("couldn't start rival client");
+    crate::wait_process_as_thread(rival).expect("couldn't join rival client");
+
+    done_send.send(()).unwrap();
+    crate::wait_process_as_thread(xous_server).expect("couldn't join server");
+
+    shutdown_kernel();
+    main_thread.join().expect("couldn't join kernel process");
+}
+
+#[test]
+fn user_addresses() {
+    let main_thread = start_kernel(SERVER_SPEC);
+
+    let xous_process = xous_kernel::create_process_as_thread(
+        xous_kernel::ProcessArgsAsThread::new("user addresses process", || {
+            use xous_kernel::{MemoryAddress, MemoryFlags, MemoryRange, MemorySize};
+            let addr = |a: usize| MemoryAddress::new(a).unwrap();
+            let size = |s: usize| MemorySize::new(s).unwrap();
+            let rw = MemoryFlags::R | MemoryFlags::W;
+
+            // Ranges that start in the user area but run into the kernel
+            // are turned away, not just ones that start in the kernel.
+            assert_eq!(
+                rsyscall(SysCall::MapMemory(
+                    None,
+                    Some(addr(0xfeff_f000)),
+                    size(0x2000),
+                    rw
+                )),
+                Err(xous_kernel::Error::BadAddress)
+            );
+            assert_eq!(
+                rsyscall(SysCall::MapMemory(
+                    None,
+                    Some(addr(0x2000_0800)),
+                    size(0x1000),
+                    rw
+                )),
+                Err(xous_kernel::Error::BadAlignment)
+            );
+            assert_eq!(
+                rsyscall(SysCall::ReserveAddressSpace(
+                    Some(addr(0xfeff_f000)),
+                    size(0x2000)
+                )),
+                Err(xous_kernel::Error::BadAddress)
+            );
+            assert_eq!(
+                rsyscall(SysCall::UpdateMemoryFlags(
+                    addr(0xfeff_f000),
+                    2,
+                    MemoryFlags::R
+                )),
+                Err(xous_kernel::Error::BadAddress)
+            );
+            assert_eq!(
+                rsyscall(SysCall::UpdateMemoryFlags(
+                    addr(0x2000_0000),
+                    usize::MAX,
+                    MemoryFlags::R
+                )),
+                Err(xous_kernel::Error::BadAddress)
+            );
+            assert_eq!(
+                rsyscall(SysCall::UnmapMemory(
+                    MemoryRange::new(0x2000_0000, 0x800).unwrap()
+                )),
+                Err(xous_kernel::Error::BadAlignment)
+            );
+            assert_eq!(
+                rsyscall(SysCall::SetExceptionHandler(Some(addr(0xff00_1000)))),
+                Err(xous_kernel::Error::BadAddress)
+            );
+        }),
+    )
+    .expect("couldn't start process");
+
+    xous_kernel::wait_process_as_thread(xous_process).expect("couldn't join process");
+    shutdown_kernel();
+
+    main_thread.join().expect("couldn't join kernel process");
+}
+
+#[test]
+fn audit_log() {
+    use xous_kernel::{AuditKind, Capabilities};
+
+    let main_thread = start_kernel(SERVER_SPEC);
+
+    // A process without the capability may not read the log, and trying
+    // is itself recorded.
+    let snoop = xous_kernel::create_process_as_thread(xous_kernel::ProcessArgsAsThread::new(
+        "audit snoop",
+        || {
+            assert_eq!(
+                xous_kernel::read_audit_log(0),
+                Err(xous_kernel::Error::AccessDenied)
+            );
+        },
+    ))
+    .expect("couldn't start audit snoop");
+    crate::wait_process_as_thread(snoop).expect("couldn't join audit snoop");
+
+    let mut events = vec![];
+    let mut sequence = 0;
+    while let Some(event) = xous_kernel::read_audit_log(sequence).expect("couldn't read log") {
+        assert_eq!(event.sequence, sequence);
+        sequence = event.sequence + 1;
+        events.push(event);
+    }
+    let denied = events
+        .iter()
+        .find(|event| event.kind == AuditKind::CapabilityDenied)
+        .expect("denial wasn't recorded");
+    assert_eq!(denied.arg, Capabilities::AUDIT.bits());
+    let snoop_pid = denied.pid;
+    assert!(events.iter().any(|event| {
+        event.kind == AuditKind::ProcessCreated && event.pid == snoop_pid && event.arg == 1
+    }));
+
+    // The kernel may only notice that the snoop has gone after it has been
+    // joined, so wait for that to be recorded before looking for the end.
+    while !events
+        .iter()
+        .any(|event| event.kind == AuditKind::ProcessTerminated && event.pid == snoop_pid)
+    {
+        match xous_kernel::read_audit_log(sequence).expect("couldn't read log") {
+            Some(event) => {
+                sequence = event.sequence + 1;
+                events.push(event);
+            }
+            None => xous_kernel::yield_slice(),
+        }
+    }
+
+    // Reading past the end finds nothing until something else happens.
+    assert_eq!(xous_kernel::read_audit_log(sequence), Ok(None));
+
+    shutdown_kernel();
+    main_thread.join().expect("couldn't join kernel process");
+}
+
+#[test]
+fn shutdown_scrub() {
+    let main_thread = start_kernel(SERVER_SPEC);
+
+    // Wiping memory doesn't change who may shut down.
+    let unprivileged = xous_kernel::create_process_as_thread(
+        xous_kernel::ProcessArgsAsThread::new("scrub unprivileged", || {
+            assert_eq!(
+                rsyscall(SysCall::Shutdown(ScrubLevel::All)),
+                Err(xous_kernel::Error::AccessDenied)
+            );
+        }),
+    )
+    .expect("couldn't start unprivileged process");
+    crate::wait_process_as_thread(unprivileged).expect("couldn't join unprivileged process");
+
+    xous_kernel::wait_process_as_thread(
+        xous_kernel::create_process_as_thread(
+            xous_kernel::ProcessArgsAsThread::new("scrub shutdown", || {
+                let mut random = [0u8; 16];
+                xous_kernel::get_random(&mut random).expect("couldn't get random bytes");
+                rsyscall(SysCall::Shutdown(ScrubLevel::All)).expect("couldn't shut down");
+            })
+            .capabilities(xous_kernel::Capabilities::SHUTDOWN),
+        )
+        .expect("couldn't start shutdown process"),
+    )
+    .expect("couldn't wait for the shutdown process to end");
+    main_thread.join().expect("couldn't join kernel process");
+}
+
+#[test]
+fn quotas() {
+    use xous_kernel::Quotas;
+
+    let main_thread = start_kernel(SERVER_SPEC);
+    let (servers_send, servers_recv) = channel();
+    let (done_send, done_recv) = channel();
+
+    let owner = xous_kernel::create_process_as_thread(xous_kernel::ProcessArgsAsThread::new(
+        "quota owner",
+        move || {
+            let first =
+                xous_kernel::create_server(b"quota server one").expect("couldn't create server");
+            let second =
+                xous_kernel::create_server(b"quota server two").expect("couldn't create server");
+            servers_send.send((first, second)).unwrap();
+            done_recv.recv().unwrap();
+        },
+    ))
+    .expect("couldn't start server owner");
+    let (first, second) = servers_recv.recv().unwrap();
+
+    let limited = xous_kernel::create_process_as_thread(
+        xous_kernel::ProcessArgsAsThread::new("limited process", move || {
+            // The connection to its own server uses up one of the two.
+            xous_kernel::create_server(b"quota server own").expect("couldn't create server");
+            assert_eq!(
+                xous_kernel::create_server(b"quota server too"),
+                Err(xous_kernel::Error::LimitReached)
+            );
+
+            let cid = xous_kernel::try_connect(first).expect("couldn't connect");
+            assert_eq!(
+                xous_kernel::try_connect(second),
+                Err(xous_kernel::Error::LimitReached)
+            );
+            // Connecting to the same server again doesn't take another one.
+            assert_eq!(xous_kernel::try_connect(first), Ok(cid));
+
+            // The threads the process already has count too, so it runs out
+            // before it gets to start four more.
+            let mut threads = vec![];
+            let error = loop {
+                match xous_kernel::create_thread(|| {
+                    std::thread::sleep(std::time::Duration::from_millis(100))
+                }) {
+                    Ok(thread) => threads.push(thread),
+                    Err(e) => break e,
+                }
+            };
+            assert_eq!(error, xous_kernel::Error::LimitReached);
+            assert!(threads.len() < 4);
+            for thread in threads {
+                xous_kernel::wait_thread(thread).expect("couldn't join thread");
+            }
+        })
+        .quotas(Quotas {
+            servers: 1,
+            connections: 2,
+            threads: 4,
+        }),
+    )
+    .expect("couldn't start limited process");
+    crate::wait_process_as_thread(limited).expect("couldn't join limited process");
+
+    done_send.send(()).unwrap();
+    crate::wait_process_as_thread(owner).expect("couldn't join server owner");
+
+    shutdown_kernel();
+    main_thread.join().expect("couldn't join kernel process");
+}
+
+#[test]
+fn crash_supervisor() {
+    let main_thread = start_kernel(SERVER_SPEC);
+
+    let supervisor = xous_kernel::create_process_as_thread(
+        xous_kernel::ProcessArgsAsThread::new("crash supervisor", || {
+            // Hosted processes can't fault into the kernel, so nothing ever
+            // crashes here.
+            assert_eq!(xous_kernel::read_crash_report(0), Ok(None));
+            assert_eq!(
+                xous_kernel::read_crash_word(0, 0),
+                Err(xous_kernel::Error::InvalidSyscall)
+            );
+            assert_eq!(
+                xous_kernel::take_core_page(0),
+                Err(xous_kernel::Error::AccessDenied)
+            );
+
+            let notification =
+                xous_kernel::create_notification().expect("couldn't create notification");
+            assert!(xous_kernel::set_crash_supervisor(notification + 1).is_err());
+            xous_kernel::set_crash_supervisor(notification).expect("couldn't supervise");
+            assert_eq!(xous_kernel::read_crash_report(0), Ok(None));
+            assert_eq!(xous_kernel::take_core_page(0), Ok(None));
+            xous_kernel::set_crash_supervisor(0).expect("couldn't stop supervising");
+        })
+        .capabilities(xous_kernel::Capabilities::SUPERVISE),
+    )
+    .expect("couldn't start supervisor");
+    crate::wait_process_as_thread(supervisor).expect("couldn't join supervisor");
+
+    shutdown_kernel();
+    main_thread.join().expect("couldn't join kernel process");
+}
+
+#[test]
+fn trace_buffer() {
+    use xous_kernel::TraceKind;
+
+    let main_thread = start_kernel(SERVER_SPEC);
+
+    let traced = xous_kernel::create_process_as_thread(xous_kernel::ProcessArgsAsThread::new(
+        "traced process",
+        || {
+            if cfg!(feature = "trace") {
+                assert_eq!(
+                    xous_kernel::read_trace(),
+                    Err(xous_kernel::Error::AccessDenied)
+                );
+            }
+            xous_kernel::yield_slice();
+        },
+    ))
+    .expect("couldn't start traced process");
+    crate::wait_process_as_thread(traced).expect("couldn't join traced process");
+
+    if !cfg!(feature = "trace") {
+        assert_eq!(
+            xous_kernel::read_trace(),
+            Err(xous_kernel::Error::UnhandledSyscall)
+        );
+    } else {
+        let mut events = vec![];
+        while let Some(event) = xous_kernel::read_trace().expect("couldn't read trace") {
+            events.push(event);
+        }
+        assert_eq!(xous_kernel::read_trace(), Ok(None));
+
+        // The yield was recorded going in and coming back out, in order.
+        let entry = events
+            .iter()
+            .position(|event| {
+                event.kind == TraceKind::SyscallEntry && event.arg == SysCall::Yield.as_args()[0]
+            })
+            .expect("yield wasn't recorded");
+        let exit = events[entry..]
+            .iter()
+            .find(|event| {
+                event.kind == TraceKind::SyscallExit
+                    && event.pid == events[entry].pid
+                    && event.tid == events[entry].tid
+            })
+            .expect("yield didn't finish");
+        assert_eq!(exit.arg, 0);
+        assert!(exit.timestamp >= events[entry].timestamp);
+
+        // Draining the buffer isn't itself recorded.
+        let read_trace = SysCall::ReadTrace.as_args()[0];
+        assert!(!events
+            .iter()
+            .any(|event| event.kind == TraceKind::SyscallEntry && event.arg == read_trace));
+    }
+
+    shutdown_kernel();
+    main_thread.join().expect("couldn't join kernel process");
+}
+
+#[test]
+fn syscall_latency() {
+    use xous_kernel::LATENCY_BUCKETS;
+
+    let main_thread = start_kernel(SERVER_SPEC);
+    let yield_number = SysCall::Yield.as_args()[0];
+
+    let timed = xous_kernel::create_process_as_thread(xous_kernel::ProcessArgsAsThread::new(
+        "timed process",
+        move || {
+            if cfg!(feature = "syscall-latency") {
+                assert_eq!(
+                    xous_kernel::read_syscall_latency(yield_number),
+                    Err(xous_kernel::Error::AccessDenied)
+                );
+            }
+            for _ in 0..3 {
+                xous_kernel::yield_slice();
+            }
+        },
+    ))
+    .expect("couldn't start timed process");
+    crate::wait_process_as_thread(timed).expect("couldn't join timed process");
+
+    if !cfg!(feature = "syscall-latency") {
+        assert_eq!(
+            xous_kernel::read_syscall_latency(yield_number),
+            Err(xous_kernel::Error::UnhandledSyscall)
+        );
+    } else {
+        // Every yield landed in exactly one bucket.
+        let latency =
+            xous_kernel::read_syscall_latency(yield_number).expect("couldn't read latency");
+        assert!(latency.calls >= 3);
+        assert!(latency.total >= latency.max as u64);
+        let counted: usize = (0..LATENCY_BUCKETS)
+            .map(|bucket| {
+                xous_kernel::read_latency_bucket(yield_number, bucket)
+                    .expect("couldn't read bucket")
+            })
+            .sum();
+        assert_eq!(counted, latency.calls);
+
+        assert_eq!(
+            xous_kernel::read_latency_bucket(yield_number, LATENCY_BUCKETS),
+            Err(xous_kernel::Error::InvalidSyscall)
+        );
+        assert_eq!(
+            xous_kernel::read_syscall_latency(usize::MAX),
+            Err(xous_kernel::Error::InvalidSyscall)
+        );
+    }
+
+    shutdown_kernel();
+    main_thread.join().expect("couldn't join kernel process");
+}
+
+#[test]
+fn sampling_profiler() {
+    let main_thread = start_kernel(SERVER_SPEC);
+
+    let profiled = xous_kernel::create_process_as_thread(xous_kernel::ProcessArgsAsThread::new(
+        "profiled process",
+        || {
+            if cfg!(feature = "profile") {
+                assert_eq!(
+                    xous_kernel::set_profiling(false),
+                    Err(xous_kernel::Error::AccessDenied)
+                );
+                assert_eq!(
+                    xous_kernel::read_profile_sample(),
+                    Err(xous_kernel::Error::AccessDenied)
+                );
+            }
+        },
+    ))
+    .expect("couldn't start profiled process");
+    crate::wait_process_as_thread(profiled).expect("couldn't join profiled process");
+
+    if !cfg!(feature = "profile") {
+        assert_eq!(
+            xous_kernel::set_profiling(true),
+            Err(xous_kernel::Error::UnhandledSyscall)
+        );
+    } else {
+        // There are no interrupts to sample on when hosted, so the ring
+        // stays empty however the profiler is started and stopped.
+        xous_kernel::set_profiling(false).expect("couldn't stop profiler");
+        assert_eq!(xous_kernel::read_profile_sample(), Ok(None));
+        xous_kernel::set_profiling(true).expect("couldn't start profiler");
+        assert_eq!(xous_kernel::read_profile_sample(), Ok(None));
+    }
+
+    shutdown_kernel();
+    main_thread.join().expect("couldn't join kernel process");
+}
+
+#[test]
+fn log_levels() {
+    use xous_kernel::{LogLevel, LogSubsystem};
+
+    let main_thread = start_kernel(SERVER_SPEC);
+
+    let unprivileged = xous_kernel::create_process_as_thread(
+        xous_kernel::ProcessArgsAsThread::new("unprivileged process", || {
+            assert_eq!(
+                xous_kernel::set_log_level(LogSubsystem::Syscalls, LogLevel::Debug),
+                Err(xous_kernel::Error::AccessDenied)
+            );
+        }),
+    )
+    .expect("couldn't start unprivileged process");
+    crate::wait_process_as_thread(unprivileged).expect("couldn't join unprivileged process");
+
+    // Each subsystem has a level of its own, and setting one returns the
+    // level it was at.
+    let initial = if cfg!(feature = "debug-print") {
+        LogLevel::Info
+    } else {
+        LogLevel::Off
+    };
+    assert_eq!(
+        xous_kernel::set_log_level(LogSubsystem::Memory, LogLevel::Errors),
+        Ok(initial)
+    );
+    assert_eq!(
+        xous_kernel::set_log_level(LogSubsystem::Interrupts, LogLevel::Off),
+        Ok(initial)
+    );
+    assert_eq!(
+        xous_kernel::set_log_level(LogSubsystem::Memory, initial),
+        Ok(LogLevel::Errors)
+    );
+
+    // Levels that don't exist are turned away before reaching the kernel.
+    let mut args = SysCall::SetLogLevel(LogSubsystem::Memory, LogLevel::Off).as_args();
+    args[2] = 4;
+    assert_eq!(
+        SysCall::from_args(args[0], args[1], args[2], args[3], args[4], args[5], args[6], args[7]),
+        Err(xous_kernel::Error::InvalidSyscall)
+    );
+
+    shutdown_kernel();
+    main_thread.join().expect("couldn't join kernel process");
+}
+
+#[test]
+fn debug_console() {
+    let (main_thread, console) = start_kernel_with_console(SERVER_SPEC);
+    let (sid_send, sid_recv) = channel();
+
+    // This process never gets an answer, so it only ends if it's killed.
+    let stuck = xous_kernel::create_process_as_thread(xous_kernel::ProcessArgsAsThread::new(
+        "stuck process",
+        move || {
+            let sid =
+                xous_kernel::create_server(b"debug_console_st").expect("couldn't create server");
+            sid_send.send(sid).unwrap();
+            xous_kernel::receive_message(sid).ok();
+            xous_kernel::receive_message(sid).ok();
+        },
+    ))
+    .expect("couldn't start stuck process");
+    let sid = sid_recv.recv().unwrap();
+
+    // Looking around doesn't change anything, even when given nonsense.
+    for line in [
+        "",
+        "help",
+        "ps",
+        "servers",
+        "queue 0",
+        "queue 99",
+        "queue",
+        "map 2",
+        "map 0",
+        "sched",
+        "log",
+        "log memory debug",
+        "log memory off",
+        "log memory loud",
+        "kill",
+        "kill 1",
+        "bogus",
+    ]
+    .iter()
+    {
+        console.send(line.to_string()).unwrap();
+    }
+    assert!(xous_kernel::try_connect(sid).is_ok());
+
+    // Killing a process takes its servers down with it.
+    console.send("kill 2".to_owned()).unwrap();
+    assert!(crate::wait_process_as_thread(stuck).is_err());
+    assert!(xous_kernel::try_connect(sid).is_err());
+
+    drop(console);
+    shutdown_kernel();
+    main_thread.join().expect("couldn't join kernel process");
+}
+
+#[test]
+fn replay() {
+    use rand::{thread_rng, Rng};
+    let path =
+        std::env::temp_dir().join(format!("xous-replay-{:08x}.txt", thread_rng().gen::<u32>()));
+    let recording = path.to_str().unwrap().to_owned();
+
+    let record_to = recording.clone();
+    let (main_thread, _console) = start_kernel_with(SERVER_SPEC, move || {
+        crate::arch::replay::record_to(&record_to)
+    });
+    let (sid_send, sid_recv) = channel();
+
+    let xous_server = xous_kernel::create_process_as_thread(xous_kernel::ProcessArgsAsThread::new(
+        "replay server",
+        move || {
+            let sid =
+                xous_kernel::create_server(b"replay_server_00").expect("couldn't create server");
+            sid_send.send(sid).unwrap();
+            let envelope = xous_kernel::receive_message(sid).expect("couldn't receive messages");
+            if let xous_kernel::Message::Borrow(m) = envelope.body {
+                let bytes = unsafe { core::slice::from_raw_parts(m.buf.as_ptr(), m.buf.len()) };
+                assert_eq!(bytes, b"a secret payload");
+                xous_kernel::return_memory(envelope.sender, m.buf).unwrap();
+            } else {
+                panic!("unexpected message type");
+            }
+            let envelope = xous_kernel::receive_message(sid).expect("couldn't receive messages");
+            if let xous_kernel::Message::BlockingScalar(m) = envelope.body {
+                xous_kernel::return_scalar(envelope.sender, m.arg1 + 1).unwrap();
+            } else {
+                panic!("unexpected message type");
+            }
+        },
+    ))
+    .expect("couldn't start server");
+
+    let xous_client = xous_kernel::create_process_as_thread(xous_kernel::ProcessArgsAsThread::new(
+        "replay client",
+        move || {
+            let sid = sid_recv.recv().unwrap();
+            let conn = xous_kernel::try_connect(sid).expect("couldn't connect to server");
+            xous_kernel::carton::Carton::from_bytes(b"a secret payload")
+                .lend(conn, 1)
+                .expect("couldn't lend data");
+            let result = xous_kernel::try_send_message(
+                conn,
+                xous_kernel::Message::BlockingScalar(xous_kernel::ScalarMessage {
+                    id: 2,
+                    arg1: 41,
+                    arg2: 0,
+                    arg3: 0,
+                    arg4: 0,
+                }),
+            )
+            .expect("couldn't send message");
+            assert_eq!(result, xous_kernel::Result::Scalar1(42));
+        },
+    ))
+    .expect("couldn't start client");
+
+    crate::wait_process_as_thread(xous_server).expect("couldn't join server process");
+    crate::wait_process_as_thread(xous_client).expect("couldn't join client process");
+    shutdown_kernel();
+    main_thread.join().expect("couldn't join kernel process");
+
+    // Payloads are only recorded as a hash.
+    let text = std::fs::read_to_string(&path).expect("couldn't read recording");
+    assert!(!text.contains("secret"));
+    assert!(text
+        .lines()
+        .any(|line| line.starts_with("CALL") && line.split(' ').count() == 13));
+
+    let replay = |recording: String| {
+        std::thread::Builder::new()
+            .name("kernel replay".to_owned())
+            .spawn(move || {
+                crate::arch::replay::replay_from(&recording);
+                kmain();
+                crate::arch::replay::divergence()
+            })
+            .unwrap()
+            .join()
+            .expect("couldn't join replay")
+    };
+    assert_eq!(replay(recording.clone()), None);
+
+    // A kernel that hands back anything other than what was recorded stops.
+    let tampered: Vec<String> = text
+        .lines()
+        .map(|line| match line.strip_prefix("RETURN ") {
+            Some(rest) if line.ends_with(" 2a 0 0 0 0 0 0") => {
+                format!("RETURN {}", rest.replace(" 2a ", " 2b "))
+            }
+            _ => line.to_owned(),
+        })
+        .collect();
+    assert_ne!(tampered.join("\n"), text.trim_end());
+    std::fs::write(&path, tampered.join("\n")).unwrap();
+    let divergence = replay(recording).expect("tampered replay didn't diverge");
+    assert!(divergence.contains(" 2b "), "{}", divergence);
+    std::fs::remove_file(&path).ok();
+}
+
+/// Where RAM starts for tests that run against simulated RAM
+const SIMULATED_RAM: usize = 0x4000_0000;
+
+/// Run `f` against a fresh kernel whose processes have page tables and
+/// `pages` pages of simulated RAM, with PID 1 and a server and a client
+/// process from `fuzz::spawn()`.  The client is connected to the server.
+fn with_simulated_ram<F>(pages: usize, f: F)
+where
+    F: FnOnce(PID, PID, SID, CID) + Send + 'static,
+{
+    use crate::arch::process::set_current_pid;
+    use crate::fuzz::spawn;
+    use crate::syscall::{handle_inner, SysCallOutcome};
+    use xous_kernel::Result;
+
+    // Kernel state is kept per host thread, so a new thread starts from
+    // nothing and leaves nothing behind.
+    std::thread::spawn(move || {
+        crate::mem::MemoryManager::with_mut(|mm| {
+            mm.simulate_ram(SIMULATED_RAM, pages * crate::mem::PAGE_SIZE)
+        });
+        let pid1 = spawn(None);
+        let server = spawn(Some(pid1));
+        let client = spawn(Some(pid1));
+        let sid = SID::from_u32(1, 2, 3, 4);
+        set_current_pid(server);
+        handle_inner(server, 1, SysCall::CreateServer(sid)).expect("couldn't create server");
+        set_current_pid(client);
+        let cid = match handle_inner(client, 1, SysCall::TryConnect(sid)) {
+            Ok(SysCallOutcome::Return(Result::ConnectionID(cid))) => cid,
+            other => panic!("couldn't connect: {:?}", other),
+        };
+        f(server, client, sid, cid)
+    })
+    .join()
+    .expect("simulated kernel panicked");
+}
+
+#[test]
+fn terminate_reclaims_memory() {
+    use crate::arch::mmu;
+    use crate::arch::process::set_current_pid;
+    use crate::mem::{MemoryManager, PAGE_SIZE};
+    use crate::services::SystemServices;
+    use crate::syscall::{handle_inner, SysCallOutcome};
+    use xous_kernel::{MemoryFlags, MemoryMessage, MemoryRange, MemoryType, Message, Result};
+
+    with_simulated_ram(16, |server, client, sid, cid| {
+        // The client has three pages: one it moves to the server, one it
+        // lends to the server, and one it keeps.
+        let phys = SIMULATED_RAM + 8 * PAGE_SIZE;
+        let (moved, lent) = (0x2000_0000, 0x2000_1000);
+        MemoryManager::with_mut(|mm| {
+            mm.map_range(
+                phys as *mut u8,
+                moved as *mut u8,
+                3 * PAGE_SIZE,
+                client,
+                MemoryFlags::R | MemoryFlags::W,
+                MemoryType::Default,
+            )
+        })
+        .expect("couldn't map client pages");
+        mmu::store(moved, 0x1234_5678).unwrap();
+        mmu::store(lent, 0x9abc_def0).unwrap();
+
+        let message = |addr| MemoryMessage {
+            id: 0,
+            buf: MemoryRange::new(addr, PAGE_SIZE).unwrap(),
+            offset: None,
+            valid: None,
+        };
+        assert!(matches!(
+            handle_inner(
+                client,
+                1,
+                SysCall::TrySendMessage(cid, Message::Move(message(moved)))
+            ),
+            Ok(SysCallOutcome::Return(Result::Ok))
+        ));
+        assert!(matches!(
+            handle_inner(
+                client,
+                1,
+                SysCall::TrySendMessage(cid, Message::MutableBorrow(message(lent)))
+            ),
+            Ok(SysCallOutcome::Blocked)
+        ));
+
+        // The client dies while the server holds both pages.  Only the page
+        // it kept, and the memory behind its page tables, go back.
+        let in_use = |addr| MemoryManager::with_mut(|mm| mm.memory_in_use(addr, PAGE_SIZE));
+        SystemServices::with_mut(|ss| ss.terminate_process(client)).unwrap();
+        assert!(in_use(phys));
+        assert!(in_use(phys + PAGE_SIZE));
+        assert!(!in_use(phys + 2 * PAGE_SIZE));
+
+        // The server gets the moved page with its contents, and the lent page
+        // goes back to the free pool once the server is done with it, since
+        // there's no one to return it to.
+        set_current_pid(server);
+        let envelope = match handle_inner(server, 1, SysCall::ReceiveMessage(sid)) {
+            Ok(SysCallOutcome::Return(Result::Message(envelope))) => envelope,
+            other => panic!("couldn't receive moved page: {:?}", other),
+        };
+        let buf = match envelope.body {
+            Message::Move(message) => message.buf,
+            other => panic!("expected a move, got {:?}", other),
+        };
+        assert_eq!(mmu::load(buf.as_ptr() as usize), Ok(0x1234_5678));
+        let envelope = match handle_inner(server, 1, SysCall::ReceiveMessage(sid)) {
+            Ok(SysCallOutcome::Return(Result::Message(envelope))) => envelope,
+            other => panic!("couldn't receive lent page: {:?}", other),
+        };
+        let buf = match envelope.body {
+            Message::MutableBorrow(message) => message.buf,
+            other => panic!("expected a lend, got {:?}", other),
+        };
+        assert_eq!(mmu::load(buf.as_ptr() as usize), Ok(0x9abc_def0));
+        handle_inner(server, 1, SysCall::ReturnMemory(envelope.sender, buf))
+            .expect("couldn't return lent page");
+        assert!(in_use(phys));
+        assert!(!in_use(phys + PAGE_SIZE));
+
+        // Once the server is gone, nothing is left.
+        SystemServices::with_mut(|ss| ss.terminate_process(server)).unwrap();
+        assert!(!MemoryManager::with_mut(
+            |mm| mm.memory_in_use(SIMULATED_RAM, 16 * PAGE_SIZE)
+        ));
+    });
+}
+
+#[test]
+fn failed_range_transfer_changes_nothing() {
+    use crate::arch::mmu;
+    use crate::arch::process::set_current_pid;
+    use crate::mem::{MemoryManager, PAGE_SIZE};
+    use crate::syscall::{handle_inner, SysCallOutcome};
+    use xous_kernel::{
+        Error, MemoryFlags, MemoryMessage, MemoryRange, MemoryType, Message, Result,
+    };
+
+    with_simulated_ram(4, |server, client, sid, cid| {
+        // The client's three pages and the pagetable that maps them fill
+        // RAM, leaving nothing for the server's pagetables.
+        let phys = SIMULATED_RAM + PAGE_SIZE;
+        let virt = 0x2000_0000;
+        MemoryManager::with_mut(|mm| {
+            mm.map_range(
+                phys as *mut u8,
+                virt as *mut u8,
+                3 * PAGE_SIZE,
+                client,
+                MemoryFlags::R | MemoryFlags::W,
+                MemoryType::Default,
+            )
+        })
+        .expect("couldn't map client pages");
+        for page in 0..3 {
+            mmu::store(virt + page * PAGE_SIZE, page as u32).unwrap();
+        }
+
+        let message = |pages| MemoryMessage {
+            id: 0,
+            buf: MemoryRange::new(virt, pages * PAGE_SIZE).unwrap(),
+            offset: None,
+            valid: None,
+        };
+        for message in [
+            Message::MutableBorrow(message(3)),
+            Message::Borrow(message(3)),
+            Message::Move(message(3)),
+        ] {
+            assert_eq!(
+                handle_inner(client, 1, SysCall::TrySendMessage(cid, message)).err(),
+                Some(Error::OutOfMemory)
+            );
+        }
+        for page in 0..3 {
+            assert_eq!(mmu::load(virt + page * PAGE_SIZE), Ok(page as u32));
+            mmu::store(virt + page * PAGE_SIZE, page as u32).unwrap();
+        }
+
+        // With a page free for the server's pagetable, a range that includes
+        // a page the client doesn't own still can't be moved, and the pages
+        // before it stay with the client.
+        let pid1 = PID::new(1).unwrap();
+        MemoryManager::with_mut(|mm| {
+            mm.unmap_page((virt + 2 * PAGE_SIZE) as *mut usize)?;
+            mm.give_page(phys + PAGE_SIZE, client, pid1)
+        })
+        .unwrap();
+        assert_eq!(
+            handle_inner(
+                client,
+                1,
+                SysCall::TrySendMessage(cid, Message::Move(message(2)))
+            )
+            .err(),
+            Some(Error::MemoryInUse)
+        );
+        assert_eq!(mmu::load(virt), Ok(0));
+        assert_eq!(mmu::load(virt + PAGE_SIZE), Ok(1));
+
+        MemoryManager::with_mut(|mm| mm.give_page(phys + PAGE_SIZE, pid1, client)).unwrap();
+        assert!(matches!(
+            handle_inner(
+                client,
+                1,
+                SysCall::TrySendMessage(cid, Message::Move(message(2)))
+            ),
+            Ok(SysCallOutcome::Return(Result::Ok))
+        ));
+        assert_eq!(mmu::load(virt), Err(Error::BadAddress));
+
+        set_current_pid(server);
+        let buf = match handle_inner(server, 1, SysCall::ReceiveMessage(sid)) {
+            Ok(SysCallOutcome::Return(Result::Message(envelope))) => match envelope.body {
+                Message::Move(message) => message.buf,
+                other => panic!("expected a move, got {:?}", other),
+            },
+            other => panic!("couldn't receive moved pages: {:?}", other),
+        };
+        assert_eq!(mmu::load(buf.as_ptr() as usize), Ok(0));
+        assert_eq!(mmu::load(buf.as_ptr() as usize + PAGE_SIZE), Ok(1));
+
+        // Both pages went to the server, so they go when it does.
+        crate::services::SystemServices::with_mut(|ss| ss.terminate_process(server)).unwrap();
+        assert!(!MemoryManager::with_mut(
+            |mm| mm.memory_in_use(phys, 3 * PAGE_SIZE)
+        ));
+    });
+}
+
+#[test]
+fn failed_scattered_lend_changes_nothing() {
+    use crate::arch::mmu;
+    use crate::arch::process::set_current_pid;
+    use crate::mem::{MemoryManager, PAGE_SIZE};
+    use crate::services::SystemServices;
+    use crate::syscall::{handle_inner, SysCallOutcome};
+    use xous_kernel::{
+        Error, MemoryFlags, MemoryMessage, MemoryRange, MemoryType, Message, Result, ScatterList,
+    };
+
+    with_simulated_ram(16, |server, client, sid, cid| {
+        // The client keeps its scatter list in the first page, and lends
+        // pieces from the three after it.
+        let phys = SIMULATED_RAM + 8 * PAGE_SIZE;
+        let list = 0x2000_0000;
+        let pages = [list + PAGE_SIZE, list + 2 * PAGE_SIZE, list + 3 * PAGE_SIZE];
+        MemoryManager::with_mut(|mm| {
+            mm.map_range(
+                phys as *mut u8,
+                list as *mut u8,
+                4 * PAGE_SIZE,
+                client,
+                MemoryFlags::R | MemoryFlags::W,
+                MemoryType::Default,
+            )
+        })
+        .expect("couldn't map client pages");
+        for page in 0..4 {
+            mmu::hand_page_to_user(list + page * PAGE_SIZE).unwrap();
+        }
+        for (index, &page) in pages.iter().enumerate() {
+            mmu::store(page, index as u32).unwrap();
+        }
+
+        let lend = |pieces: &[usize]| {
+            let ranges: Vec<_> = pieces
+                .iter()
+                .map(|&piece| MemoryRange::new(piece, PAGE_SIZE).unwrap())
+                .collect();
+            let scatter = ScatterList::new(&ranges).unwrap();
+            let words = unsafe {
+                core::slice::from_raw_parts(
+                    &scatter as *const ScatterList as *const u32,
+                    core::mem::size_of::<ScatterList>() / 4,
+                )
+            };
+            for (index, &word) in words.iter().enumerate() {
+                mmu::store(list + index * 4, word).unwrap();
+            }
+            let message = MemoryMessage {
+                id: 0,
+                buf: MemoryRange::new(list | 1, scatter.len()).unwrap(),
+                offset: None,
+                valid: None,
+            };
+            handle_inner(
+                client,
+                1,
+                SysCall::TrySendMessage(cid, Message::MutableBorrow(message)),
+            )
+        };
+
+        // A piece that isn't mapped, or that lies outside of the user area,
+        // is refused, and the piece before it stays with the client.
+        assert_eq!(
+            lend(&[pages[0], 0x2010_0000]).err(),
+            Some(Error::BadAddress)
+        );
+        assert_eq!(
+            lend(&[pages[0], 0xff00_0000]).err(),
+            Some(Error::BadAddress)
+        );
+        assert_eq!(mmu::load(pages[0]), Ok(0));
+        mmu::store(pages[0], 0).unwrap();
+
+        // So is a piece that's already lent, even if only to be read.
+        let mapping = |pid| SystemServices::with(|ss| ss.get_process(pid).unwrap().mapping);
+        MemoryManager::with_mut(|mm| {
+            mm.lend_range(
+                &mapping(client),
+                pages[1] as *mut u8,
+                server,
+                &mapping(server),
+                0x3000_0000 as *mut u8,
+                PAGE_SIZE,
+                false,
+            )
+        })
+        .expect("couldn't lend page");
+        assert_eq!(
+            lend(&[pages[0], pages[1]]).err(),
+            Some(Error::ShareViolation)
+        );
+        assert_eq!(mmu::load(pages[0]), Ok(0));
+        mmu::store(pages[0], 0).unwrap();
+
+        // Nothing is left over from the failed lends, so a good one comes
+        // back to the right pages.
+        assert!(matches!(
+            lend(&[pages[0], pages[2]]),
+            Ok(SysCallOutcome::Blocked)
+        ));
+        assert_eq!(mmu::load(pages[0]), Err(Error::BadAddress));
+
+        set_current_pid(server);
+        let envelope = match handle_inner(server, 1, SysCall::ReceiveMessage(sid)) {
+            Ok(SysCallOutcome::Return(Result::Message(envelope))) => envelope,
+            other => panic!("couldn't receive scattered lend: {:?}", other),
+        };
+        let buf = match envelope.body {
+            Message::MutableBorrow(message) => message.buf,
+            other => panic!("expected a lend, got {:?}", other),
+        };
+        assert_eq!(mmu::load(buf.as_ptr() as usize), Ok(0));
+        assert_eq!(mmu::load(buf.as_ptr() as usize + PAGE_SIZE), Ok(2));
+        handle_inner(server, 1, SysCall::ReturnMemory(envelope.sender, buf))
+            .expect("couldn't return scattered lend");
+
+        set_current_pid(client);
+        assert_eq!(mmu::load(pages[0]), Ok(0));
+        assert_eq!(mmu::load(pages[2]), Ok(2));
+    });
+}
+
+#[test]
+fn megapages_map_and_split() {
+    use crate::arch::mmu;
+    use crate::mem::{MemoryManager, MEGAPAGE_SIZE, PAGE_SIZE};
+    use xous_kernel::{Error, MemoryFlags, MemoryType};
+
+    with_simulated_ram(
+        2 * MEGAPAGE_SIZE / PAGE_SIZE,
+        |_server, client, _sid, _cid| {
+            let in_use = |addr, size| MemoryManager::with_mut(|mm| mm.memory_in_use(addr, size));
+
+            // An aligned range is mapped as a megapage, which needs no
+            // second-level pagetable.
+            let phys = SIMULATED_RAM + MEGAPAGE_SIZE;
+            let virt = 0x2040_0000;
+            MemoryManager::with_mut(|mm| {
+                mm.map_range(
+                    phys as *mut u8,
+                    virt as *mut u8,
+                    MEGAPAGE_SIZE,
+                    client,
+                    MemoryFlags::R | MemoryFlags::W,
+                    MemoryType::Default,
+                )
+            })
+            .expect("couldn't map megapage");
+            assert!(!in_use(SIMULATED_RAM, MEGAPAGE_SIZE));
+            assert_eq!(
+                crate::arch::mem::virt_to_phys(virt + 5 * PAGE_SIZE),
+                Ok(phys + 5 * PAGE_SIZE)
+            );
+            mmu::store(virt + 5 * PAGE_SIZE + 8, 5).unwrap();
+            mmu::store(virt + 6 * PAGE_SIZE + 8, 6).unwrap();
+
+            // Unmapping one page of it splits it, which takes a pagetable, and
+            // leaves the rest of it as it was.
+            MemoryManager::with_mut(|mm| mm.unmap_page((virt + 5 * PAGE_SIZE) as *mut usize))
+                .expect("couldn't unmap page");
+            assert!(in_use(SIMULATED_RAM, PAGE_SIZE));
+            assert!(!in_use(phys + 5 * PAGE_SIZE, PAGE_SIZE));
+            assert!(in_use(phys + 6 * PAGE_SIZE, PAGE_SIZE));
+            assert_eq!(mmu::load(virt + 5 * PAGE_SIZE + 8), Err(Error::BadAddress));
+            assert_eq!(mmu::load(virt + 6 * PAGE_SIZE + 8), Ok(6));
+            assert_eq!(
+                crate::arch::mem::virt_to_phys(virt + 6 * PAGE_SIZE),
+                Ok(phys + 6 * PAGE_SIZE)
+            );
+        },
+    );
+}
+
+#[test]
+fn failed_map_unmaps_megapages_whole() {
+    use crate::arch::mmu;
+    use crate::mem::{MemoryManager, MEGAPAGE_SIZE, PAGE_SIZE};
+    use xous_kernel::{Error, MemoryFlags, MemoryType};
+
+    with_simulated_ram(
+        2 * MEGAPAGE_SIZE / PAGE_SIZE + 1,
+        |_server, client, _sid, _cid| {
+            // Everything but the range itself is in use, so the page past the
+            // megapage can't get a pagetable, and neither could the megapage
+            // if it had to be split to undo it.
+            let pid1 = PID::new(1).unwrap();
+            let phys = SIMULATED_RAM + MEGAPAGE_SIZE;
+            let virt = 0x2040_0000;
+            let map = || {
+                MemoryManager::with_mut(|mm| {
+                    mm.map_range(
+                        phys as *mut u8,
+                        virt as *mut u8,
+                        MEGAPAGE_SIZE + PAGE_SIZE,
+                        client,
+                        MemoryFlags::R | MemoryFlags::W,
+                        MemoryType::Default,
+                    )
+                })
+            };
+            MemoryManager::with_mut(|mm| {
+                for _ in 0..MEGAPAGE_SIZE / PAGE_SIZE {
+                    mm.alloc_page(pid1).unwrap();
+                }
+            });
+            assert_eq!(map().err(), Some(Error::OutOfMemory));
+            assert_eq!(crate::arch::mem::virt_to_phys(virt), Err(Error::BadAddress));
+            assert!(!MemoryManager::with_mut(
+                |mm| mm.memory_in_use(phys, MEGAPAGE_SIZE + PAGE_SIZE)
+            ));
+
+            // With a page free for the pagetable, the same range maps.
+            MemoryManager::with_mut(|mm| mm.release_page(SIMULATED_RAM as *mut usize, pid1))
+                .unwrap();
+            map().expect("couldn't map range");
+            mmu::store(virt + MEGAPAGE_SIZE, 1).unwrap();
+            assert_eq!(mmu::load(virt + MEGAPAGE_SIZE), Ok(1));
+        },
+    );
+}
+
+#[test]
+fn shared_segment_copy_on_write() {
+    use crate::arch::mmu;
+    use crate::arch::process::set_current_pid;
+    use crate::mem::{MemoryManager, PAGE_SIZE};
+    use crate::services::SystemServices;
+    use xous_kernel::{Error, MemoryFlags, MemoryRange, MemoryType};
+
+    with_simulated_ram(16, |server, _client, _sid, _cid| {
+        // The server has a writable segment, which it shares with a child
+        // of its own.
+        let child = crate::fuzz::spawn(Some(server));
+        set_current_pid(server);
+        let phys = SIMULATED_RAM + 8 * PAGE_SIZE;
+        let (base, child_base) = (0x2000_0000, 0x3000_0000);
+        crate::image::reserve(server, MemoryRange::new(base, 2 * PAGE_SIZE).unwrap()).unwrap();
+        crate::image::reserve(child, MemoryRange::new(child_base, 2 * PAGE_SIZE).unwrap()).unwrap();
+        let segment = crate::image::add_segment(
+            server,
+            base,
+            0,
+            2 * PAGE_SIZE,
+            MemoryFlags::R | MemoryFlags::W,
+        )
+        .unwrap();
+        MemoryManager::with_mut(|mm| {
+            mm.map_range(
+                phys as *mut u8,
+                segment.as_mut_ptr(),
+                segment.len(),
+                server,
+                MemoryFlags::R | MemoryFlags::W,
+                MemoryType::Default,
+            )
+        })
+        .expect("couldn't map segment");
+        mmu::store(base, 1).unwrap();
+        mmu::store(base + PAGE_SIZE, 2).unwrap();
+        let shared =
+            SystemServices::with_mut(|ss| ss.share_segment(server, base, child, child_base))
+                .expect("couldn't share segment");
+        assert_eq!(shared.as_ptr() as usize, child_base);
+
+        // Both processes see the same pages, and neither can write to them
+        // without faulting.
+        assert_eq!(mmu::store(base, 3), Err(Error::AccessDenied));
+        set_current_pid(child);
+        assert_eq!(mmu::load(child_base), Ok(1));
+        assert_eq!(mmu::load(child_base + PAGE_SIZE), Ok(2));
+        assert_eq!(mmu::store(child_base, 3), Err(Error::AccessDenied));
+
+        // The fault gives the child a copy of its own to write to, as the
+        // trap handler would, and the server's page is untouched.
+        assert!(crate::arch::mem::page_is_cow(child_base));
+        MemoryManager::with_mut(|mm| mm.copy_on_write(child, child_base)).unwrap();
+        mmu::store(child_base, 3).unwrap();
+        assert_eq!(mmu::load(child_base), Ok(3));
+        assert_ne!(crate::arch::mem::virt_to_phys(child_base), Ok(phys));
+        set_current_pid(server);
+        assert_eq!(mmu::load(base), Ok(1));
+
+        // The server is now the only one using its page, so its own fault
+        // makes the page writable again without copying it.
+        MemoryManager::with_mut(|mm| mm.copy_on_write(server, base)).unwrap();
+        mmu::store(base, 4).unwrap();
+        assert_eq!(crate::arch::mem::virt_to_phys(base), Ok(phys));
+        set_current_pid(child);
+        assert_eq!(mmu::load(child_base), Ok(3));
+        assert_eq!(mmu::load(child_base + PAGE_SIZE), Ok(2));
+    });
+}
+
+#[test]
+fn fuzz_syscalls() {
+    use crate::fuzz::{fuzz_syscall, MAPPED, RECORD_LENGTH, SERVER};
+
+    // Malformed arguments are usually small numbers, or just past the end
+    // of something, or as large as they can be.
+    let interesting: [u32; 17] = [
+        0,
+        1,
+        2,
+        3,
+        4,
+        5,
+        7,
+        0x10,
+        0xfff,
+        0x1000,
+        0x1001,
+        0x8000_0000,
+        0xffff_f000,
+        0xffff_ffff,
+        SERVER.0,
+        SERVER.3,
+        MAPPED as u32,
+    ];
+    let mut state = 0x2545_f491_4f6c_dd1du64;
+    let mut random = move || {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        state
+    };
+    for _ in 0..2000 {
+        let mut input = vec![];
+        for _ in 0..16 {
+            input.push(random() as u8);
+            // Most of the time, make a syscall that exists.
+            let number = random() as u32 % 100;
+            input.extend_from_slice(&number.to_le_bytes());
+            for _ in 1..8 {
+                let word = match random() % 4 {
+                    0 => random() as u32,
+                    _ => interesting[random() as usize % interesting.len()],
+                };
+                input.extend_from_slice(&word.to_le_bytes());
+            }
+        }
+        assert_eq!(input.len() % RECORD_LENGTH, 0);
+        fuzz_syscall(&input);
+    }
+}
+
+#[test]
+fn remote_process() {
+    // A process the kernel didn't start connects with a key it was given,
+    // as one running on another machine would.
+    let remote_key = [0x5a; 16];
+    let (main_thread, _console) = start_kernel_with(SERVER_SPEC, move || {
+        crate::arch::set_remote_keys(vec![remote_key]);
+    });
+    let server_addr = xous_kernel::arch::xous_address();
+
+    // A key that nothing was registered with is refused, and the kernel
+    // carries on.
+    let stranger_addr = server_addr.clone();
+    std::thread::spawn(move || {
+        xous_kernel::arch::set_xous_address(stranger_addr);
+        xous_kernel::arch::set_process_key(&[0xa5; 16]);
+        assert!(xous_kernel::arch::ensure_connection().is_err());
+    })
+    .join()
+    .expect("stranger panicked");
+
+    let (sid_send, sid_recv) = channel();
+    let remote = std::thread::spawn(move || {
+        xous_kernel::arch::set_xous_address(server_addr);
+        xous_kernel::arch::set_process_key(&remote_key);
+        xous_kernel::arch::ensure_connection().expect("couldn't connect remote process");
+        let sid = xous_kernel::create_server(b"remote_process00").expect("couldn't create server");
+        sid_send.send(sid).unwrap();
+        let envelope = xous_kernel::receive_message(sid).expect("couldn't receive message");
+        assert_eq!(
+            envelope.body,
+            xous_kernel::Message::Scalar(xous_kernel::ScalarMessage {
+                id: 7,
+                arg1: 1,
+                arg2: 2,
+                arg3: 3,
+                arg4: 4,
+            })
+        );
+    });
+
+    xous_kernel::arch::ensure_connection().expect("couldn't connect as PID 1");
+    let sid = sid_recv.recv().unwrap();
+    let conn = xous_kernel::try_connect(sid).expect("couldn't connect to remote server");
+    xous_kernel::try_send_message(
+        conn,
+        xous_kernel::Message::Scalar(xous_kernel::ScalarMessage {
+            id: 7,
+            arg1: 1,
+            arg2: 2,
+            arg3: 3,
+            arg4: 4,
+        }),
+    )
+    .expect("couldn't send message");
+    remote.join().expect("remote process panicked");
+
+    shutdown_kernel();
+    main_thread.join().expect("couldn't join kernel process");
+}
+
+#[test]
+fn shared_memory_lend() {
+    // Pages from `map_memory()` are shared with the kernel, so a server
+    // writes straight into the lender's memory rather than into a copy.
+    let main_thread = start_kernel(SERVER_SPEC);
+    let (sid_send, sid_recv) = channel();
+    let (addr_send, addr_recv) = channel();
+    let (written_send, written_recv) = channel();
+    let (checked_send, checked_recv) = channel::<()>();
+
+    let xous_server = xous_kernel::create_process_as_thread(xous_kernel::ProcessArgsAsThread::new(
+        "shared_memory_lend server",
+        move || {
+            let sid = xous_kernel::create_server(b"shared_memory_ln")
+                .expect("couldn't create test server");
+            sid_send.send(sid).unwrap();
+            let envelope = xous_kernel::receive_message(sid).expect("couldn't receive message");
+            if let xous_kernel::Message::MutableBorrow(m) = envelope.body {
+                let bytes =
+                    unsafe { core::slice::from_raw_parts_mut(m.buf.as_mut_ptr(), m.buf.len()) };
+                bytes[..6].copy_from_slice(b"shared");
+                written_send.send(()).unwrap();
+                checked_recv.recv().unwrap();
+                xous_kernel::return_memory(envelope.sender, m.buf).unwrap();
+            } else {
+                panic!("unexpected message type");
+            }
+        },
+    ))
+    .expect("couldn't start server");
+
+    let xous_client = xous_kernel::create_process_as_thread(xous_kernel::ProcessArgsAsThread::new(
+        "shared_memory_lend client",
+        move || {
+            let sid = sid_recv.recv().unwrap();
+            let conn = xous_kernel::try_connect(sid).expect("couldn't connect to server");
+            let range = xous_kernel::map_memory(
+                None,
+                None,
+                4096,
+                xous_kernel::MemoryFlags::R | xous_kernel::MemoryFlags::W,
+            )
+            .expect("couldn't map memory");
+            addr_send.send(range.as_ptr() as usize).unwrap();
+            xous_kernel::try_send_message(
+                conn,
+                xous_kernel::Message::MutableBorrow(xous_kernel::MemoryMessage {
+                    id: 1,
+                    buf: range,
+                    offset: None,
+                    valid: None,
+                }),
+            )
+            .expect("couldn't lend memory");
+            let bytes = unsafe { core::slice::from_raw_parts(range.as_ptr(), 6) };
+            assert_eq!(bytes, b"shared");
+            xous_kernel::unmap_memory(range).expect("couldn't unmap memory");
+        },
+    ))
+    .expect("couldn't start client");
+
+    // The lend hasn't been returned yet, but the client can already see
+    // what the server wrote.
+    let addr = addr_recv.recv().unwrap();
+    written_recv.recv().unwrap();
+    let lent = unsafe { core::slice::from_raw_parts(addr as *const u8, 6) };
+    assert_eq!(lent, b"shared");
+    checked_send.send(()).unwrap();
+
+    crate::wait_process_as_thread(xous_server).expect("couldn't join server process");
+    crate::wait_process_as_thread(xous_client).expect("couldn't join client process");
+    shutdown_kernel();
+    main_thread.join().expect("couldn't join kernel process");
+}
+
+/// The program that `spawned_program` has the kernel start, which is this
+/// test binary running just this test.  Run any other way, it does nothing.
+#[test]
+fn spawned_program_child() {
+    if std::env::var("XOUS_PROCESS_KEY").is_err() {
+        return;
+    }
+    xous_kernel::arch::ensure_connection().expect("couldn't connect to kernel");
+    let sid = xous_kernel::SID::from_bytes(b"spawned_program0").unwrap();
+    let conn = xous_kernel::try_connect(sid).expect("couldn't connect to parent");
+    let pid: usize = std::env::var("XOUS_PID").unwrap().parse().unwrap();
+    xous_kernel::try_send_message(
+        conn,
+        xous_kernel::Message::Scalar(xous_kernel::ScalarMessage {
+            id: 8,
+            arg1: pid,
+            arg2: 0,
+            arg3: 0,
+            arg4: 0,
+        }),
+    )
+    .expect("couldn't send message");
+}
+
+#[test]
+fn spawned_program() {
+    let main_thread = start_kernel(SERVER_SPEC);
+    xous_kernel::arch::ensure_connection().expect("couldn't connect as PID 1");
+    let sid = xous_kernel::create_server(b"spawned_program0").expect("couldn't create server");
+
+    // The kernel starts the program, which connects with the key it was
+    // given and is the process the kernel said it would be.
+    let exe = std::env::current_exe().unwrap();
+    let child = xous_kernel::create_process(xous_kernel::ProcessArgs::program(
+        "child",
+        exe.to_str().unwrap(),
+        &["test::spawned_program_child", "--exact", "--quiet"],
+    ))
+    .expect("couldn't start program");
+    let envelope = xous_kernel::receive_message(sid).expect("couldn't receive message");
+    let pid = match envelope.body {
+        xous_kernel::Message::Scalar(scalar) => {
+            assert_eq!(scalar.id, 8);
+            scalar.arg1
+        }
+        other => panic!("unexpected message {:?}", other),
+    };
+    assert_eq!(pid, envelope.sender_pid().unwrap().get() as usize);
+
+    // The process goes away once the program exits.
+    xous_kernel::wait_process(child).expect("couldn't wait for program");
+
+    // A program that can't be started leaves no process behind.
+    assert_eq!(
+        xous_kernel::create_process(xous_kernel::ProcessArgs::program(
+            "missing",
+            "/nonexistent/xous-program",
+            &[],
+        ))
+        .err(),
+        Some(xous_kernel::Error::InternalError)
+    );
+
+    shutdown_kernel();
+    main_thread.join().expect("couldn't join kernel process");
+}
+
+#[test]
+fn virtual_clock() {
+    let (main_thread, console) = start_kernel_with(SERVER_SPEC, crate::arch::set_virtual_clock);
+    xous_kernel::arch::ensure_connection().expect("couldn't connect as PID 1");
+    let sid = xous_kernel::create_server(b"virtual_clock000").expect("couldn't create server");
+
+    // This thread is running, so the clock stands still until it's moved.
+    let (woke_send, woke_recv) = channel();
+    let sleeper = xous_kernel::create_process_as_thread(xous_kernel::ProcessArgsAsThread::new(
+        "sleeper",
+        move || {
+            xous_kernel::sleep_thread(50).expect("couldn't sleep");
+            woke_send
+                .send(xous_kernel::get_ticks().expect("couldn't get ticks"))
+                .unwrap();
+
+            // Once every process is waiting, the clock skips ahead to the
+            // next deadline rather than taking the time to get there.
+            xous_kernel::sleep_thread(60_000).expect("couldn't sleep");
+            let conn = xous_kernel::try_connect(sid).expect("couldn't connect to server");
+            xous_kernel::try_send_message(
+                conn,
+                xous_kernel::Message::Scalar(xous_kernel::ScalarMessage {
+                    id: 1,
+                    arg1: xous_kernel::get_ticks().expect("couldn't get ticks") as usize,
+                    arg2: 0,
+                    arg3: 0,
+                    arg4: 0,
+                }),
+            )
+            .expect("couldn't send message");
+        },
+    ))
+    .expect("couldn't start sleeper");
+    assert!(woke_recv
+        .recv_timeout(std::time::Duration::from_millis(200))
+        .is_err());
+    console.send("advance 50".to_owned()).unwrap();
+    assert_eq!(woke_recv.recv().unwrap(), 50);
+
+    let envelope = xous_kernel::receive_message(sid).expect("couldn't receive message");
+    match envelope.body {
+        xous_kernel::Message::Scalar(scalar) => assert_eq!(scalar.arg1, 60_050),
+        other => panic!("unexpected message {:?}", other),
+    }
+
+    // The clock starts at zero, so timestamps come out the same every run.
+    assert_eq!(
+        xous_kernel::message_timestamp().expect("couldn't get message timestamp"),
+        60_050 * 1_000_000
+    );
+    xous_kernel::wait_process_as_thread(sleeper).expect("couldn't wait for sleeper");
+
+    shutdown_kernel();
+    main_thread.join().expect("couldn't join kernel process");
+}
+
+#[test]
+fn graceful_shutdown() {
+    let (main_thread, console) = start_kernel_with(SERVER_SPEC, crate::arch::set_virtual_clock);
+    xous_kernel::arch::ensure_connection().expect("couldn't connect as PID 1");
+    let sid = xous_kernel::create_server(b"graceful_shutdwn").expect("couldn't create server");
+
+    // Each subscriber reports the time it heard about the shutdown, and
+    // then either gets out of the way or keeps the system waiting.
+    let (heard_send, heard_recv) = channel();
+    for (name, ready) in [(b"shutdown_ready00", true), (b"shutdown_slow000", false)] {
+        let heard_send = heard_send.clone();
+        xous_kernel::create_process_as_thread(xous_kernel::ProcessArgsAsThread::new(
+            "shutdown subscriber",
+            move || {
+                let own_sid = xous_kernel::create_server(name).expect("couldn't create server");
+                let own_conn = xous_kernel::try_connect(own_sid).expect("couldn't connect");
+                xous_kernel::subscribe(xous_kernel::SHUTDOWN_BROADCAST, own_conn, 7)
+                    .expect("couldn't subscribe");
+                heard_send.send(None).unwrap();
+
+                let envelope =
+                    xous_kernel::receive_message(own_sid).expect("couldn't receive message");
+                match envelope.body {
+                    xous_kernel::Message::Scalar(scalar) => {
+                        assert_eq!((scalar.id, scalar.arg1), (7, crate::shutdown::GRACE_MS))
+                    }
+                    other => panic!("unexpected message {:?}", other),
+                }
+                let heard = xous_kernel::get_ticks().expect("couldn't get ticks");
+                heard_send.send(Some((ready, heard))).unwrap();
+                if ready {
+                    xous_kernel::unsubscribe(xous_kernel::SHUTDOWN_BROADCAST, own_conn)
+                        .expect("couldn't unsubscribe");
+                    return;
+                }
+
+                // Still up well into the grace period, even though the other
+                // subscriber is ready.
+                xous_kernel::sleep_thread(1000).expect("couldn't sleep");
+                let conn = xous_kernel::try_connect(sid).expect("couldn't connect to server");
+                xous_kernel::try_send_message(
+                    conn,
+                    xous_kernel::Message::Scalar(xous_kernel::ScalarMessage {
+                        id: 1,
+                        arg1: xous_kernel::get_ticks().expect("couldn't get ticks") as usize,
+                        arg2: 0,
+                        arg3: 0,
+                        arg4: 0,
+                    }),
+                )
+                .expect("couldn't send message");
+                xous_kernel::receive_message(own_sid).ok();
+            },
+        ))
+        .expect("couldn't start subscriber");
+    }
+    assert_eq!(heard_recv.recv().unwrap(), None);
+    assert_eq!(heard_recv.recv().unwrap(), None);
+
+    // Shutting down returns right away, and the subscribers hear about it
+    // at the same moment.
+    shutdown_kernel();
+    let mut heard: Vec<_> = (0..2)
+        .map(|_| heard_recv.recv().unwrap().unwrap())
+        .collect();
+    heard.sort();
+    let start = heard[0].1;
+    assert_eq!(heard, [(false, start), (true, start)]);
+
+    let envelope = xous_kernel::receive_message(sid).expect("couldn't receive message");
+    match envelope.body {
+        xous_kernel::Message::Scalar(scalar) => assert_eq!(scalar.arg1 as u64, start + 1000),
+        other => panic!("unexpected message {:?}", other),
+    }
+
+    // The slow subscriber never gets ready, so the system goes down once the
+    // grace period is over.
+    let rest = crate::shutdown::GRACE_MS - 1000;
+    console.send(format!("advance {}", rest)).unwrap();
+    main_thread.join().expect("couldn't join kernel process");
+}
+
+#[test]
+fn reboot() {
+    let main_thread = start_kernel(SERVER_SPEC);
+
+    let (ready_send, ready_recv) = channel();
+    let subscriber = xous_kernel::create_process_as_thread(xous_kernel::ProcessArgsAsThread::new(
+        "reboot subscriber",
+        move || {
+            let sid =
+                xous_kernel::create_server(b"reboot_subscribe").expect("couldn't create server");
+            let conn = xous_kernel::try_connect(sid).expect("couldn't connect to server");
+            xous_kernel::subscribe(xous_kernel::SHUTDOWN_BROADCAST, conn, 3)
+                .expect("couldn't subscribe");
+            ready_send.send(()).unwrap();
+
+            // Subscribers are told that the system is coming back, and how.
+            let envelope = xous_kernel::receive_message(sid).expect("couldn't receive message");
+            assert_eq!(
+                envelope.body,
+                xous_kernel::Message::Scalar(xous_kernel::ScalarMessage {
+                    id: 3,
+                    arg1: crate::shutdown::GRACE_MS,
+                    arg2: RebootMode::Warm as usize,
+                    arg3: 0,
+                    arg4: 0,
+                })
+            );
+            xous_kernel::unsubscribe(xous_kernel::SHUTDOWN_BROADCAST, conn)
+                .expect("couldn't unsubscribe");
+        },
+    ))
+    .expect("couldn't start subscriber");
+    ready_recv.recv().unwrap();
+
+    xous_kernel::wait_process_as_thread(
+        xous_kernel::create_process_as_thread(
+            xous_kernel::ProcessArgsAsThread::new("reboot", || {
+                // There's no loader in hosted mode to wait for an update.
+                assert_eq!(
+                    xous_kernel::reboot(RebootMode::Update),
+                    Err(xous_kernel::Error::UnhandledSyscall)
+                );
+                xous_kernel::reboot(RebootMode::Warm).expect("couldn't reboot");
+            })
+            .capabilities(xous_kernel::Capabilities::SHUTDOWN),
+        )
+        .expect("couldn't start rebooting process"),
+    )
+    .expect("couldn't wait for the rebooting process to end");
+
+    // The kernel goes down once the subscriber is ready, and asks to be
+    // started again.
+    xous_kernel::wait_process_as_thread(subscriber).expect("couldn't wait for subscriber");
+    assert_eq!(
+        main_thread.join().expect("couldn't join kernel process"),
+        Some(RebootMode::Warm)
+    );
+}
+
+#[test]
+fn suspend() {
+    let (main_thread, console) = start_kernel_with_console(SERVER_SPEC);
+    xous_kernel::arch::ensure_connection().expect("couldn't connect as PID 1");
+    let sid = xous_kernel::create_server(b"suspend_events!!").expect("couldn't create server");
+
+    // The subscriber passes along each event it hears about.
+    let (ready_send, ready_recv) = channel();
+    let subscriber = xous_kernel::create_process_as_thread(xous_kernel::ProcessArgsAsThread::new(
+        "suspend subscriber",
+        move || {
+            let own_sid =
+                xous_kernel::create_server(b"suspend_subscrib").expect("couldn't create server");
+            let own_conn = xous_kernel::try_connect(own_sid).expect("couldn't connect");
+            xous_kernel::subscribe(xous_kernel::SUSPEND_BROADCAST, own_conn, 5)
+                .expect("couldn't subscribe");
+            let conn = xous_kernel::try_connect(sid).expect("couldn't connect to server");
+            ready_send.send(()).unwrap();
+
+            for _ in 0..2 {
+                let envelope =
+                    xous_kernel::receive_message(own_sid).expect("couldn't receive message");
+                xous_kernel::try_send_message(conn, envelope.body).expect("couldn't send message");
+            }
+            xous_kernel::unsubscribe(xous_kernel::SUSPEND_BROADCAST, own_conn)
+                .expect("couldn't unsubscribe");
+        },
+    ))
+    .expect("couldn't start subscriber");
+    ready_recv.recv().unwrap();
+
+    let suspender = xous_kernel::create_process_as_thread(
+        xous_kernel::ProcessArgsAsThread::new("suspend", || {
+            xous_kernel::suspend().expect("couldn't suspend");
+        })
+        .capabilities(xous_kernel::Capabilities::SHUTDOWN),
+    )
+    .expect("couldn't start suspending process");
+
+    let event = |arg1| {
+        xous_kernel::Message::Scalar(xous_kernel::ScalarMessage {
+            id: 5,
+            arg1,
+            arg2: 0,
+            arg3: 0,
+            arg4: 0,
+        })
+    };
+    let envelope = xous_kernel::receive_message(sid).expect("couldn't receive message");
+    assert_eq!(
+        envelope.body,
+        event(xous_kernel::SuspendEvent::Suspending as usize)
+    );
+    assert_eq!(
+        xous_kernel::suspend(),
+        Err(xous_kernel::Error::ShareViolation)
+    );
+
+    // The wake comes in before every process is waiting, and is held until
+    // the system suspends, which it does once this thread waits as well.
+    console.send("wake".to_owned()).unwrap();
+    let envelope = xous_kernel::receive_message(sid).expect("couldn't receive message");
+    assert_eq!(
+        envelope.body,
+        event(xous_kernel::SuspendEvent::Resumed as usize)
+    );
+    xous_kernel::wait_process_as_thread(suspender).expect("couldn't wait for suspending process");
+    xous_kernel::wait_process_as_thread(subscriber).expect("couldn't wait for subscriber");
+
+    shutdown_kernel();
+    main_thread.join().expect("couldn't join kernel process");
+}
+
+#[test]
+fn clock_arbitration() {
+    let main_thread = start_kernel(SERVER_SPEC);
+    let run = |name, f: fn()| {
+        xous_kernel::wait_process_as_thread(
+            xous_kernel::create_process_as_thread(
+                xous_kernel::ProcessArgsAsThread::new(name, f)
+                    .capabilities(xous_kernel::Capabilities::POWER),
+            )
+            .expect("couldn't start process"),
+        )
+        .expect("couldn't wait for process");
+    };
+
+    // The first driver needs domain 2 and a fast CPU until it's told to
+    // exit.
+    let (ready_send, ready_recv) = channel();
+    let (exit_send, exit_recv) = channel::<()>();
+    let first = xous_kernel::create_process_as_thread(
+        xous_kernel::ProcessArgsAsThread::new("first driver", move || {
+            assert_eq!(xous_kernel::set_clock_state(2, ClockState::On), Ok(true));
+            assert_eq!(
+                xous_kernel::request_cpu_frequency(50_000_000),
+                Ok(50_000_000)
+            );
+            ready_send.send(()).unwrap();
+            exit_recv.recv().unwrap();
+        })
+        .capabilities(xous_kernel::Capabilities::POWER),
+    )
+    .expect("couldn't start first driver");
+    ready_recv.recv().unwrap();
+
+    // A second driver can't slow the CPU down or gate the domain while the
+    // first still needs them.
+    run("second driver", || {
+        assert_eq!(
+            xous_kernel::request_cpu_frequency(20_000_000),
+            Ok(50_000_000)
+        );
+        assert_eq!(xous_kernel::set_clock_state(2, ClockState::On), Ok(true));
+        assert_eq!(xous_kernel::set_clock_state(2, ClockState::Off), Ok(true));
+        assert_eq!(
+            xous_kernel::set_clock_state(crate::arch::clock_domains(), ClockState::On),
+            Err(xous_kernel::Error::InvalidSyscall)
+        );
+    });
+
+    // Once the first driver exits, nothing holds either of them up.
+    exit_send.send(()).unwrap();
+    xous_kernel::wait_process_as_thread(first).expect("couldn't wait for first driver");
+    run("third driver", || {
+        assert_eq!(
+            xous_kernel::request_cpu_frequency(20_000_000),
+            Ok(20_000_000)
+        );
+        assert_eq!(xous_kernel::set_clock_state(2, ClockState::Off), Ok(false));
+    });
+
+    xous_kernel::wait_process_as_thread(
+        xous_kernel::create_process_as_thread(xous_kernel::ProcessArgsAsThread::new(
+            "unprivileged",
+            || {
+                assert_eq!(
+                    xous_kernel::set_clock_state(0, ClockState::Off),
+                    Err(xous_kernel::Error::AccessDenied)
+                );
+                assert_eq!(
+                    xous_kernel::request_cpu_frequency(0),
+                    Err(xous_kernel::Error::AccessDenied)
+                );
+            },
+        ))
+        .expect("couldn't start unprivileged process"),
+    )
+    .expect("couldn't wait for unprivileged process");
+
+    shutdown_kernel();
+    main_thread.join().expect("couldn't join kernel process");
+}
+
+#[test]
+fn dump_coverage() {
+    let main_thread = start_kernel(SERVER_SPEC);
+
+    let xous_process = xous_kernel::create_process_as_thread(
+        xous_kernel::ProcessArgsAsThread::new("dump_coverage process", || {
+            if cfg!(feature = "coverage") {
+                xous_kernel::dump_coverage().expect("couldn't dump coverage");
+            } else {
+                assert_eq!(
+                    xous_kernel::dump_coverage(),
+                    Err(xous_kernel::Error::UnhandledSyscall)
+                );
+            }
+        }),
+    )
+    .expect("couldn't start process");
+
+    xous_kernel::wait_process_as_thread(xous_process).expect("couldn't join process");
+    shutdown_kernel();
+
+    main_thread.join().expect("couldn't join kernel process");
+}
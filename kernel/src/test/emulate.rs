@@ -0,0 +1,184 @@
+//! Tests for the RISC-V instruction decoding used to emulate missing parts of
+//! the ISA, checked against instructions as an assembler encodes them.
+
+use crate::arch::riscv_decode::{self as decode, Atomic, Counter, MemoryAccess};
+
+fn access(
+    store: bool,
+    width: usize,
+    signed: bool,
+    base: usize,
+    offset: isize,
+    reg: usize,
+) -> Option<MemoryAccess> {
+    Some(MemoryAccess {
+        store,
+        width,
+        signed,
+        base,
+        offset,
+        reg,
+    })
+}
+
+#[test]
+fn decode_counters() {
+    // rdcycle a0
+    assert_eq!(decode::counter(0xc000_2573, 4), Some((10, Counter::Cycle)));
+    // rdtime t0
+    assert_eq!(decode::counter(0xc010_22f3, 4), Some((5, Counter::Time)));
+    // rdinstret a0
+    assert_eq!(
+        decode::counter(0xc020_2573, 4),
+        Some((10, Counter::Instret))
+    );
+    // rdcycleh a0
+    assert_eq!(decode::counter(0xc800_2573, 4), Some((10, Counter::CycleH)));
+    // rdtimeh a0
+    assert_eq!(decode::counter(0xc810_2573, 4), Some((10, Counter::TimeH)));
+    // rdinstreth a1
+    assert_eq!(
+        decode::counter(0xc820_25f3, 4),
+        Some((11, Counter::InstretH))
+    );
+
+    // csrr a0, sstatus
+    assert_eq!(decode::counter(0x1000_2573, 4), None);
+    // csrrw a0, cycle, x0
+    assert_eq!(decode::counter(0xc000_1573, 4), None);
+    // csrrs a0, cycle, a1
+    assert_eq!(decode::counter(0xc005_a573, 4), None);
+    // A compressed instruction with the same low half
+    assert_eq!(decode::counter(0x2573, 2), None);
+}
+
+#[test]
+fn decode_atomics() {
+    let regs = |funct5| {
+        Some(Atomic {
+            funct5,
+            rd: 10,
+            rs1: 11,
+            rs2: 12,
+        })
+    };
+    // amoadd.w a0, a2, (a1)
+    assert_eq!(decode::atomic(0x00c5_a52f, 4), regs(0b00000));
+    // amoswap.w.aqrl a0, a2, (a1)
+    assert_eq!(decode::atomic(0x0ec5_a52f, 4), regs(0b00001));
+    // sc.w a0, a2, (a1)
+    assert_eq!(decode::atomic(0x18c5_a52f, 4), regs(decode::FUNCT5_SC));
+    // lr.w a0, (a1)
+    assert_eq!(
+        decode::atomic(0x1005_a52f, 4),
+        Some(Atomic {
+            funct5: decode::FUNCT5_LR,
+            rd: 10,
+            rs1: 11,
+            rs2: 0,
+        })
+    );
+
+    // amoadd.d a0, a2, (a1) is 64 bits wide
+    assert_eq!(decode::atomic(0x00c5_b52f, 4), None);
+    // sw a1, -4(a0)
+    assert_eq!(decode::atomic(0xfeb5_2e23, 4), None);
+}
+
+#[test]
+fn amo_results() {
+    let minus_one = -1i32 as u32;
+    // amoswap, amoadd, amoxor, amoand, amoor
+    assert_eq!(decode::amo(0b00001, 5, 7), Some(7));
+    assert_eq!(decode::amo(0b00000, minus_one, 2), Some(1));
+    assert_eq!(decode::amo(0b00100, 0b1100, 0b1010), Some(0b0110));
+    assert_eq!(decode::amo(0b01100, 0b1100, 0b1010), Some(0b1000));
+    assert_eq!(decode::amo(0b01000, 0b1100, 0b1010), Some(0b1110));
+
+    // amomin and amomax compare as signed, amominu and amomaxu as unsigned
+    assert_eq!(decode::amo(0b10000, minus_one, 1), Some(minus_one));
+    assert_eq!(decode::amo(0b10100, minus_one, 1), Some(1));
+    assert_eq!(decode::amo(0b11000, minus_one, 1), Some(1));
+    assert_eq!(decode::amo(0b11100, minus_one, 1), Some(minus_one));
+
+    // LR.W, SC.W, and unassigned encodings aren't AMOs
+    assert_eq!(decode::amo(decode::FUNCT5_LR, 1, 2), None);
+    assert_eq!(decode::amo(decode::FUNCT5_SC, 1, 2), None);
+    assert_eq!(decode::amo(0b00101, 1, 2), None);
+}
+
+#[test]
+fn decode_loads_and_stores() {
+    // lw a0, -8(sp)
+    assert_eq!(
+        decode::memory_access(0xff81_2503, 4),
+        access(false, 4, true, 2, -8, 10)
+    );
+    // lh a0, 2(a1)
+    assert_eq!(
+        decode::memory_access(0x0025_9503, 4),
+        access(false, 2, true, 11, 2, 10)
+    );
+    // lhu a0, 2(a1)
+    assert_eq!(
+        decode::memory_access(0x0025_d503, 4),
+        access(false, 2, false, 11, 2, 10)
+    );
+    // sw a1, -4(a0)
+    assert_eq!(
+        decode::memory_access(0xfeb5_2e23, 4),
+        access(true, 4, false, 10, -4, 11)
+    );
+    // sh a1, 6(a0)
+    assert_eq!(
+        decode::memory_access(0x00b5_1323, 4),
+        access(true, 2, false, 10, 6, 11)
+    );
+    // sw a1, 2047(a0)
+    assert_eq!(
+        decode::memory_access(0x7eb5_2fa3, 4),
+        access(true, 4, false, 10, 2047, 11)
+    );
+
+    // c.lw a0, 68(a1)
+    assert_eq!(
+        decode::memory_access(0x41e8, 2),
+        access(false, 4, true, 11, 68, 10)
+    );
+    // c.lw a0, 124(a1)
+    assert_eq!(
+        decode::memory_access(0x5de8, 2),
+        access(false, 4, true, 11, 124, 10)
+    );
+    // c.sw a2, 8(a0)
+    assert_eq!(
+        decode::memory_access(0xc510, 2),
+        access(true, 4, false, 10, 8, 12)
+    );
+    // c.lwsp ra, 12(sp)
+    assert_eq!(
+        decode::memory_access(0x40b2, 2),
+        access(false, 4, true, 2, 12, 1)
+    );
+    // c.lwsp a0, 252(sp)
+    assert_eq!(
+        decode::memory_access(0x557e, 2),
+        access(false, 4, true, 2, 252, 10)
+    );
+    // c.swsp ra, 12(sp)
+    assert_eq!(
+        decode::memory_access(0xc606, 2),
+        access(true, 4, false, 2, 12, 1)
+    );
+    // c.swsp a0, 252(sp)
+    assert_eq!(
+        decode::memory_access(0xdfaa, 2),
+        access(true, 4, false, 2, 252, 10)
+    );
+
+    // lb a0, 0(a1) and sb a1, 0(a0) are never misaligned
+    assert_eq!(decode::memory_access(0x0005_8503, 4), None);
+    assert_eq!(decode::memory_access(0x00b5_0023, 4), None);
+    // c.addi a0, 1
+    assert_eq!(decode::memory_access(0x0505, 2), None);
+}
@@ -0,0 +1,321 @@
+//! Tests that drive the kernel's state machines directly.  Rather than
+//! starting a kernel on a thread of its own and talking to it over a socket,
+//! these boot `SystemServices` on the test's own thread, with the hosted
+//! arch layer standing in for hardware, and make syscalls by calling
+//! `syscall::handle()`.  Processes have no connection, so a result that a
+//! thread is handed after it blocks is kept for the test to check, along
+//! with any memory that was returned to it.
+
+use crate::arch::process::{set_current_pid, take_direct_result};
+use crate::services::SystemServices;
+use crate::syscall::SysCallOutcome;
+use xous_kernel::{
+    Capabilities, Error, MemoryMessage, MemoryRange, Message, MessageEnvelope, ProcessInit,
+    ProcessKey, Quotas, Result, ScalarMessage, SysCall, ThreadInit, CID, PID, SID,
+};
+
+/// Kernel state on the current thread, with PID 1 already created
+struct Kernel {
+    pid1: PID,
+}
+
+impl Kernel {
+    fn boot() -> Kernel {
+        let init = ProcessInit {
+            key: ProcessKey::new([0; 16]),
+            capabilities: Capabilities::all(),
+            quotas: Quotas::unlimited(),
+            command: None,
+        };
+        let pid1 = SystemServices::with_mut(|ss| ss.create_process(init)).unwrap();
+        assert_eq!(pid1.get(), 1);
+        Kernel { pid1 }
+    }
+
+    /// Create a process with a single running thread, as though it had just
+    /// connected.
+    fn spawn(&self) -> PID {
+        set_current_pid(self.pid1);
+        let init = ProcessInit {
+            key: ProcessKey::new([0; 16]),
+            capabilities: Capabilities::empty(),
+            quotas: Quotas::unlimited(),
+            command: None,
+        };
+        SystemServices::with_mut(|ss| {
+            let pid = ss.create_process(init)?;
+            ss.create_thread(pid, ThreadInit {})?;
+            ss.switch_to_thread(pid, None)?;
+            Ok::<_, Error>(pid)
+        })
+        .unwrap()
+    }
+
+    /// Make a syscall from thread 1 of `pid`, returning its result, or
+    /// `None` if the thread blocked.
+    fn call(&self, pid: PID, call: SysCall) -> Option<Result> {
+        set_current_pid(pid);
+        match crate::syscall::handle(pid, 1, call) {
+            Ok(SysCallOutcome::Return(result)) => Some(result),
+            Ok(SysCallOutcome::Resume) => take_direct_result(pid, 1).map(|(result, _)| result),
+            Ok(SysCallOutcome::Blocked) => None,
+            Err(e) => Some(Result::Error(e)),
+        }
+    }
+
+    /// The result thread 1 of `pid` was handed after it blocked, along with
+    /// any memory that was returned to it.
+    fn result(&self, pid: PID) -> Option<(Result, Option<Vec<u8>>)> {
+        take_direct_result(pid, 1)
+    }
+
+    /// Create a server in `server` and connect `client` to it.
+    fn connect(&self, server: PID, client: PID, name: &[u8; 16]) -> (SID, CID) {
+        let sid = SID::from_bytes(name).unwrap();
+        match self.call(server, SysCall::CreateServer(sid)) {
+            Some(Result::NewServerID(new_sid, _)) => assert_eq!(new_sid, sid),
+            other => panic!("couldn't create server: {:?}", other),
+        }
+        match self.call(client, SysCall::TryConnect(sid)) {
+            Some(Result::ConnectionID(cid)) => (sid, cid),
+            other => panic!("couldn't connect to server: {:?}", other),
+        }
+    }
+
+    fn receive(&self, server: PID, sid: SID) -> MessageEnvelope {
+        match self.call(server, SysCall::ReceiveMessage(sid)) {
+            Some(Result::Message(envelope)) => envelope,
+            other => panic!("couldn't receive message: {:?}", other),
+        }
+    }
+}
+
+#[test]
+fn harness_blocking_scalar() {
+    let kernel = Kernel::boot();
+    let server = kernel.spawn();
+    let client = kernel.spawn();
+    let (sid, cid) = kernel.connect(server, client, b"harness_scalar00");
+
+    // Nobody is receiving yet, so the message is queued and the client waits.
+    let scalar = ScalarMessage {
+        id: 1,
+        arg1: 41,
+        arg2: 0,
+        arg3: 0,
+        arg4: 0,
+    };
+    let message = Message::BlockingScalar(scalar);
+    assert_eq!(
+        kernel.call(client, SysCall::TrySendMessage(cid, message)),
+        None
+    );
+    assert!(kernel.result(client).is_none());
+
+    let envelope = kernel.receive(server, sid);
+    assert_eq!(envelope.body, Message::BlockingScalar(scalar));
+    assert_eq!(
+        kernel.call(server, SysCall::ReturnScalar1(envelope.sender, 42)),
+        Some(Result::Ok)
+    );
+    assert_eq!(kernel.result(client), Some((Result::Scalar1(42), None)));
+
+    // The message has been answered, so it can't be answered again.
+    assert_eq!(
+        kernel.call(server, SysCall::ReturnScalar1(envelope.sender, 43)),
+        Some(Result::Error(Error::ProcessNotFound))
+    );
+    assert!(kernel.result(client).is_none());
+}
+
+#[test]
+fn harness_lend_and_return() {
+    let kernel = Kernel::boot();
+    let server = kernel.spawn();
+    let client = kernel.spawn();
+    let (sid, cid) = kernel.connect(server, client, b"harness_lend_000");
+
+    let mut data = b"lent to a server".to_vec();
+    let buf = MemoryRange::new(data.as_mut_ptr() as usize, data.len()).unwrap();
+    let message = Message::MutableBorrow(MemoryMessage {
+        id: 2,
+        buf,
+        offset: None,
+        valid: None,
+    });
+    assert_eq!(
+        kernel.call(client, SysCall::TrySendMessage(cid, message)),
+        None
+    );
+
+    let envelope = kernel.receive(server, sid);
+    let lent = match envelope.body {
+        Message::MutableBorrow(m) => m.buf,
+        other => panic!("unexpected message {:?}", other),
+    };
+    assert_eq!(lent.len(), data.len());
+
+    let bytes = unsafe { core::slice::from_raw_parts_mut(lent.as_mut_ptr(), lent.len()) };
+    bytes.make_ascii_uppercase();
+    assert_eq!(
+        kernel.call(server, SysCall::ReturnMemory(envelope.sender, lent)),
+        Some(Result::Ok)
+    );
+    assert_eq!(
+        kernel.result(client),
+        Some((Result::Ok, Some(b"LENT TO A SERVER".to_vec())))
+    );
+    assert_eq!(
+        kernel.call(server, SysCall::ReturnMemory(envelope.sender, lent)),
+        Some(Result::Error(Error::ProcessNotFound))
+    );
+}
+
+#[test]
+fn harness_errors() {
+    let kernel = Kernel::boot();
+    let server = kernel.spawn();
+    let client = kernel.spawn();
+    let (sid, cid) = kernel.connect(server, client, b"harness_errors00");
+
+    // Only the process that created a server may receive its messages.
+    assert_eq!(
+        kernel.call(client, SysCall::ReceiveMessage(sid)),
+        Some(Result::Error(Error::ServerNotFound))
+    );
+
+    // A connection that was never made goes nowhere.
+    let scalar = Message::Scalar(ScalarMessage {
+        id: 3,
+        arg1: 0,
+        arg2: 0,
+        arg3: 0,
+        arg4: 0,
+    });
+    assert_eq!(
+        kernel.call(client, SysCall::TrySendMessage(cid + 1, scalar)),
+        Some(Result::Error(Error::ServerNotFound))
+    );
+}
+
+#[test]
+fn harness_blocking_scalar_priorities() {
+    let kernel = Kernel::boot();
+    // Processes may only go as high as their parent was when it created
+    // them.
+    kernel.call(kernel.pid1, SysCall::SetPriority(5));
+    let server = kernel.spawn();
+    let low = kernel.spawn();
+    let high = kernel.spawn();
+    let (sid, low_cid) = kernel.connect(server, low, b"harness_prio0000");
+    let high_cid = match kernel.call(high, SysCall::TryConnect(sid)) {
+        Some(Result::ConnectionID(cid)) => cid,
+        other => panic!("couldn't connect to server: {:?}", other),
+    };
+    for (pid, base) in [(server, 2), (low, 1), (high, 5)].iter() {
+        kernel.call(*pid, SysCall::SetPriority(*base));
+    }
+    // Setting a priority recalculates them all, so look without touching.
+    let priority = |pid| SystemServices::with(|ss| ss.get_process(pid).unwrap().priority());
+
+    let scalar = ScalarMessage {
+        id: 4,
+        arg1: 0,
+        arg2: 0,
+        arg3: 0,
+        arg4: 0,
+    };
+    // A client below the server's priority doesn't change it, but one above
+    // it does.
+    assert_eq!(
+        kernel.call(
+            low,
+            SysCall::TrySendMessage(low_cid, Message::BlockingScalar(scalar))
+        ),
+        None
+    );
+    assert_eq!(priority(server), 2);
+    assert_eq!(
+        kernel.call(
+            high,
+            SysCall::TrySendMessage(high_cid, Message::BlockingScalar(scalar))
+        ),
+        None
+    );
+    assert_eq!(priority(server), 5);
+
+    // Answering the less important client leaves the server running at the
+    // priority of the one still waiting.
+    let envelope = kernel.receive(server, sid);
+    assert_eq!(
+        kernel.call(server, SysCall::ReturnScalar1(envelope.sender, 1)),
+        Some(Result::Ok)
+    );
+    assert_eq!(kernel.result(low), Some((Result::Scalar1(1), None)));
+    assert_eq!(priority(server), 5);
+
+    let envelope = kernel.receive(server, sid);
+    assert_eq!(
+        kernel.call(server, SysCall::ReturnScalar1(envelope.sender, 5)),
+        Some(Result::Ok)
+    );
+    assert_eq!(kernel.result(high), Some((Result::Scalar1(5), None)));
+    assert_eq!(priority(server), 2);
+}
+
+#[test]
+fn harness_priority_ceiling() {
+    let kernel = Kernel::boot();
+    kernel.call(kernel.pid1, SysCall::SetPriority(4));
+    let parent = kernel.spawn();
+
+    // A process may go as high as its parent was when it was created, and
+    // come back up after going lower, but no further.
+    assert_eq!(
+        kernel.call(parent, SysCall::SetPriority(4)),
+        Some(Result::Scalar1(4))
+    );
+    assert_eq!(
+        kernel.call(parent, SysCall::SetPriority(5)),
+        Some(Result::Error(Error::AccessDenied))
+    );
+    assert_eq!(
+        kernel.call(parent, SysCall::SetPriority(2)),
+        Some(Result::Scalar1(2))
+    );
+    assert_eq!(
+        kernel.call(parent, SysCall::SetPriority(4)),
+        Some(Result::Scalar1(4))
+    );
+
+    // Its own children are held to wherever it was when it made them.
+    kernel.call(parent, SysCall::SetPriority(2));
+    set_current_pid(parent);
+    let init = ProcessInit {
+        key: ProcessKey::new([0; 16]),
+        capabilities: Capabilities::empty(),
+        quotas: Quotas::unlimited(),
+        command: None,
+    };
+    let child = SystemServices::with_mut(|ss| {
+        let pid = ss.create_process(init)?;
+        ss.create_thread(pid, ThreadInit {})?;
+        ss.switch_to_thread(pid, None)?;
+        Ok::<_, Error>(pid)
+    })
+    .unwrap();
+    assert_eq!(
+        kernel.call(child, SysCall::SetPriority(3)),
+        Some(Result::Error(Error::AccessDenied))
+    );
+    assert_eq!(
+        kernel.call(child, SysCall::SetPriority(2)),
+        Some(Result::Scalar1(2))
+    );
+
+    // PID 1 holds every capability, so it has no ceiling.
+    assert_eq!(
+        kernel.call(kernel.pid1, SysCall::SetPriority(255)),
+        Some(Result::Scalar1(255))
+    );
+}
@@ -0,0 +1,282 @@
+//! One-shot and periodic timers, so that a service that needs to do
+//! something later doesn't have to keep a thread around just to sleep.  A
+//! timer belongs to the process that created it, and when it expires the
+//! kernel sends a `Scalar` message to the server it was set up with, as
+//! though the owner had sent it.  The message's first argument is the ID of
+//! the timer.
+//!
+//! Threads that call `SleepThread` wait here too, and are readied once
+//! their deadline passes, as does the grace period of a pending shutdown.
+//!
+//! Deadlines are checked on each timer tick, so a timer may fire up to a
+//! tick late.  In a hosted environment the kernel wakes up for the next
+//! deadline instead.
+
+use xous_kernel::{PID, SID, TID};
+
+/// The most timers that may exist at once
+const MAX_TIMERS: usize = 32;
+
+/// The most threads that may sleep at once
+const MAX_SLEEPERS: usize = 32;
+
+#[derive(Copy, Clone)]
+pub struct Timer {
+    /// The process that created the timer
+    pub owner: PID,
+
+    /// The thread the message is sent from
+    pub tid: TID,
+
+    /// The server the message is sent to
+    pub sid: SID,
+
+    /// The message ID that the timer arrives as
+    pub opcode: usize,
+
+    /// When the timer next expires, as a timestamp
+    deadline: u64,
+
+    /// How long to wait between expiries, or `0` if the timer only expires
+    /// once
+    period: u64,
+}
+
+/// A thread waiting in `SleepThread`
+#[derive(Copy, Clone)]
+struct Sleeper {
+    pid: PID,
+    tid: TID,
+
+    /// When the thread should be woken up, as a timestamp
+    deadline: u64,
+}
+
+struct Timers {
+    timers: [Option<Timer>; MAX_TIMERS],
+    sleepers: [Option<Sleeper>; MAX_SLEEPERS],
+}
+
+const EMPTY: Timers = Timers {
+    timers: [None; MAX_TIMERS],
+    sleepers: [None; MAX_SLEEPERS],
+};
+
+#[cfg(baremetal)]
+static mut TIMERS: Timers = EMPTY;
+
+#[cfg(not(baremetal))]
+std::thread_local!(static TIMERS: core::cell::RefCell<Timers> = const { core::cell::RefCell::new(EMPTY) });
+
+fn with_mut<F, R>(f: F) -> R
+where
+    F: FnOnce(&mut Timers) -> R,
+{
+    #[cfg(baremetal)]
+    unsafe {
+        f(&mut TIMERS)
+    }
+
+    #[cfg(not(baremetal))]
+    TIMERS.with(|timers| f(&mut timers.borrow_mut()))
+}
+
+/// Convert `ms` milliseconds into timestamp units.
+///
+/// # Errors
+///
+/// * **UnhandledSyscall**: The kernel doesn't know how fast timestamps run
+fn from_ms(ms: usize) -> Result<u64, xous_kernel::Error> {
+    let hz = crate::arch::timebase().ok_or(xous_kernel::Error::UnhandledSyscall)?;
+    Ok((ms as u64).saturating_mul(hz as u64) / 1000)
+}
+
+/// Create a timer owned by `owner` that sends message `opcode` to server
+/// `sid` after `delay_ms`, and then every `period_ms` if that isn't `0`.
+/// Returns the ID of the timer, which is one more than the slot it lives in
+/// so that `0` is never a valid ID.
+///
+/// # Errors
+///
+/// * **UnhandledSyscall**: The kernel doesn't know how fast timestamps run
+/// * **OutOfMemory**: Too many timers already exist
+pub fn create(
+    owner: PID,
+    tid: TID,
+    sid: SID,
+    opcode: usize,
+    delay_ms: usize,
+    period_ms: usize,
+) -> Result<usize, xous_kernel::Error> {
+    let delay = from_ms(delay_ms)?;
+    let period = from_ms(period_ms)?;
+    let deadline = crate::arch::timestamp().saturating_add(delay);
+    with_mut(|timers| {
+        let (index, slot) = timers
+            .timers
+            .iter_mut()
+            .enumerate()
+            .find(|(_, slot)| slot.is_none())
+            .ok_or(xous_kernel::Error::OutOfMemory)?;
+        *slot = Some(Timer {
+            owner,
+            tid,
+            sid,
+            opcode,
+            deadline,
+            period,
+        });
+        Ok(index + 1)
+    })
+}
+
+/// Stop timer `id` before it expires again.
+///
+/// # Errors
+///
+/// * **InvalidSyscall**: The timer doesn't exist
+/// * **AccessDenied**: `pid` doesn't own the timer
+pub fn cancel(id: usize, pid: PID) -> Result<(), xous_kernel::Error> {
+    with_mut(|timers| {
+        if id == 0 || id > MAX_TIMERS {
+            return Err(xous_kernel::Error::InvalidSyscall);
+        }
+        match timers.timers[id - 1] {
+            None => Err(xous_kernel::Error::InvalidSyscall),
+            Some(timer) if timer.owner != pid => Err(xous_kernel::Error::AccessDenied),
+            Some(_) => {
+                timers.timers[id - 1] = None;
+                Ok(())
+            }
+        }
+    })
+}
+
+/// Take a timer that has expired as of `now`, along with its ID.  A
+/// periodic timer is given its next deadline, skipping any it has already
+/// missed, and a one-shot timer is removed.
+pub fn take_expired(now: u64) -> Option<(usize, Timer)> {
+    with_mut(|timers| {
+        let (index, slot) = timers
+            .timers
+            .iter_mut()
+            .enumerate()
+            .find(|(_, slot)| matches!(slot, Some(timer) if timer.deadline <= now))?;
+        let timer = (*slot)?;
+        if timer.period == 0 {
+            *slot = None;
+        } else if let Some(next) = slot.as_mut() {
+            next.deadline = timer.deadline.saturating_add(timer.period);
+            if next.deadline <= now {
+                next.deadline = now.saturating_add(timer.period);
+            }
+        }
+        Some((index + 1, timer))
+    })
+}
+
+/// Put thread `tid` of `pid` to sleep for `ms` milliseconds.  It's up to
+/// the caller to block the thread.
+///
+/// # Errors
+///
+/// * **UnhandledSyscall**: The kernel doesn't know how fast timestamps run
+/// * **OutOfMemory**: Too many threads are already sleeping
+pub fn sleep(pid: PID, tid: TID, ms: usize) -> Result<(), xous_kernel::Error> {
+    let deadline = crate::arch::timestamp().saturating_add(from_ms(ms)?);
+    with_mut(|timers| {
+        let slot = timers
+            .sleepers
+            .iter_mut()
+            .find(|slot| slot.is_none())
+            .ok_or(xous_kernel::Error::OutOfMemory)?;
+        *slot = Some(Sleeper { pid, tid, deadline });
+        Ok(())
+    })
+}
+
+/// Take a sleeping thread whose deadline has passed as of `now`.
+fn take_woken(now: u64) -> Option<(PID, TID)> {
+    with_mut(|timers| {
+        let slot = timers
+            .sleepers
+            .iter_mut()
+            .find(|slot| matches!(slot, Some(sleeper) if sleeper.deadline <= now))?;
+        slot.take().map(|sleeper| (sleeper.pid, sleeper.tid))
+    })
+}
+
+/// The earliest deadline of any timer, sleeping thread or pending shutdown,
+/// or `None` if there are none.
+pub fn next_deadline() -> Option<u64> {
+    with_mut(|timers| {
+        let timers_due = timers.timers.iter().flatten().map(|timer| timer.deadline);
+        let sleepers_due = timers
+            .sleepers
+            .iter()
+            .flatten()
+            .map(|sleeper| sleeper.deadline);
+        timers_due
+            .chain(sleepers_due)
+            .chain(crate::shutdown::deadline())
+            .min()
+    })
+}
+
+/// Wake every sleeping thread whose deadline has passed, and send a message
+/// for every timer that has expired.  A timer whose server can't take the
+/// message misses that expiry.  Then see whether a pending shutdown can
+/// finish.
+pub fn check() {
+    let now = crate::arch::timestamp();
+    while let Some((pid, tid)) = take_woken(now) {
+        if crate::services::SystemServices::with_mut(|ss| {
+            ss.ready_thread(pid, tid)
+                .and_then(|_| ss.switch_to_thread(pid, Some(tid)))
+                .and_then(|_| ss.set_thread_result(pid, tid, xous_kernel::Result::Ok))
+        })
+        .is_err()
+        {
+            xous_kernel::cover!("timers: sleeping thread couldn't be woken");
+        }
+    }
+    while let Some((id, timer)) = take_expired(now) {
+        if crate::services::SystemServices::with_mut(|ss| {
+            crate::syscall::deliver_timer(ss, id, &timer)
+        })
+        .is_err()
+        {
+            xous_kernel::cover!("timers: timer message couldn't be delivered");
+        }
+    }
+    crate::shutdown::expire(now);
+}
+
+/// Drop every timer that sends messages to server `sid`, which no longer
+/// exists.
+pub fn forget_server(sid: SID) {
+    with_mut(|timers| {
+        for slot in timers.timers.iter_mut() {
+            if matches!(slot, Some(timer) if timer.sid == sid) {
+                *slot = None;
+            }
+        }
+    })
+}
+
+/// Drop every timer owned by `pid`, which is going away, and forget its
+/// sleeping threads.
+pub fn forget_process(pid: PID) {
+    with_mut(|timers| {
+        for slot in timers.timers.iter_mut() {
+            if matches!(slot, Some(timer) if timer.owner == pid) {
+                *slot = None;
+            }
+        }
+        for slot in timers.sleepers.iter_mut() {
+            if matches!(slot, Some(sleeper) if sleeper.pid == pid) {
+                *slot = None;
+            }
+        }
+    })
+}
@@ -0,0 +1,150 @@
+//! The trace buffer.  With the `trace` feature, the kernel records
+//! syscalls, context switches and messages as small binary records in a
+//! ring in memory, which disturbs timing far less than printing them with
+//! `debug-print`.  A trace collector holding `Capabilities::TRACE` drains
+//! the ring with `ReadTrace` and sends the records off the device, where
+//! the `chrome-trace` tool turns them into something a trace viewer can
+//! show.
+//!
+//! The kernel runs with interrupts disabled on a single hart, so records
+//! are only ever added or taken by one thing at a time and the ring needs
+//! no lock.  When the collector falls behind, the oldest records are
+//! overwritten, and the next record it reads says how many it missed.
+
+use crate::syscall::{SysCallOutcome, SysCallResult};
+use xous_kernel::{SysCall, TraceEvent, TraceKind, PID, TID};
+
+/// How many records are kept before the oldest is overwritten
+const MAX_RECORDS: usize = 512;
+
+/// A record as it's kept in the ring, which is 16 bytes on a 32-bit system
+#[derive(Copy, Clone)]
+struct Record {
+    timestamp: u64,
+    arg: usize,
+    kind: u8,
+    pid: u8,
+    tid: u8,
+}
+
+struct Trace {
+    records: [Record; MAX_RECORDS],
+
+    /// The number of records that have ever been added
+    written: usize,
+
+    /// The number of records that have been taken or overwritten
+    read: usize,
+
+    /// How many records were overwritten since the last one was taken
+    dropped: usize,
+}
+
+const EMPTY: Trace = Trace {
+    records: [Record {
+        timestamp: 0,
+        arg: 0,
+        kind: 0,
+        pid: 0,
+        tid: 0,
+    }; MAX_RECORDS],
+    written: 0,
+    read: 0,
+    dropped: 0,
+};
+
+#[cfg(baremetal)]
+static mut TRACE: Trace = EMPTY;
+
+#[cfg(not(baremetal))]
+std::thread_local!(static TRACE: core::cell::RefCell<Trace> = core::cell::RefCell::new(EMPTY));
+
+fn with_mut<F, R>(f: F) -> R
+where
+    F: FnOnce(&mut Trace) -> R,
+{
+    #[cfg(baremetal)]
+    unsafe {
+        f(&mut TRACE)
+    }
+
+    #[cfg(not(baremetal))]
+    TRACE.with(|trace| f(&mut trace.borrow_mut()))
+}
+
+/// Add a record to the ring, overwriting the oldest one if it's full.
+pub fn record(kind: TraceKind, pid: PID, tid: TID, arg: usize) {
+    let timestamp = crate::arch::timestamp();
+    with_mut(|trace| {
+        if trace.written.wrapping_sub(trace.read) == MAX_RECORDS {
+            trace.read = trace.read.wrapping_add(1);
+            trace.dropped += 1;
+        }
+        trace.records[trace.written % MAX_RECORDS] = Record {
+            timestamp,
+            arg,
+            kind: kind as u8,
+            pid: pid.get(),
+            tid: tid as u8,
+        };
+        trace.written = trace.written.wrapping_add(1);
+    })
+}
+
+/// Take the oldest record out of the ring, or `None` if it's empty.
+pub fn take() -> Option<TraceEvent> {
+    with_mut(|trace| {
+        if trace.written == trace.read {
+            return None;
+        }
+        let record = trace.records[trace.read % MAX_RECORDS];
+        trace.read = trace.read.wrapping_add(1);
+        let dropped = core::mem::replace(&mut trace.dropped, 0);
+        Some(TraceEvent {
+            kind: TraceKind::from_usize(record.kind as usize)?,
+            pid: PID::new(record.pid)?,
+            tid: record.tid as TID,
+            arg: record.arg,
+            timestamp: record.timestamp,
+            dropped,
+        })
+    })
+}
+
+/// Record `pid:tid` making `call`, along with the message it sends or
+/// returns, if any.  Returns `false` for the calls that drain the ring,
+/// which are left out so that the collector doesn't trace itself.
+pub fn syscall_entry(pid: PID, tid: TID, call: &SysCall) -> bool {
+    if let SysCall::ReadTrace = call {
+        return false;
+    }
+    record(TraceKind::SyscallEntry, pid, tid, call.as_args()[0]);
+    match call {
+        SysCall::SendMessage(cid, _)
+        | SysCall::TrySendMessage(cid, _)
+        | SysCall::CallMessage(cid, _)
+        | SysCall::ForwardMessage(_, cid, _) => {
+            record(TraceKind::MessageSent, pid, tid, *cid as usize)
+        }
+        SysCall::ReturnMemory(sender, _)
+        | SysCall::ReturnScalar1(sender, _)
+        | SysCall::ReturnScalar2(sender, _, _) => {
+            record(TraceKind::MessageReturned, pid, tid, *sender)
+        }
+        _ => (),
+    }
+    true
+}
+
+/// Record the syscall `pid:tid` made finishing with `result`, along with
+/// the message it received, if any.
+pub fn syscall_exit(pid: PID, tid: TID, result: &SysCallResult) {
+    if let Ok(SysCallOutcome::Return(xous_kernel::Result::Message(envelope))) = result {
+        record(TraceKind::MessageReceived, pid, tid, envelope.sender);
+    }
+    let error = match result {
+        Ok(_) => 0,
+        Err(e) => e.to_usize(),
+    };
+    record(TraceKind::SyscallExit, pid, tid, error);
+}
@@ -0,0 +1,128 @@
+//! Checks on the addresses that processes pass to syscalls.  Every syscall
+//! is checked here before it's handled, so that a range that runs into the
+//! kernel, or that isn't page-aligned where whole pages are needed, is
+//! turned away in one place rather than by each syscall in its own way.
+//! Anything that depends on the state of memory, such as whether a page is
+//! mapped, is still up to the syscall itself.
+//!
+//! PID 1 is exempt from the range checks, since it may map memory outside
+//! of the user area.
+
+use crate::arch::mem::USER_AREA_END;
+use crate::mem::PAGE_SIZE;
+use xous_kernel::{Error, SysCall, PID};
+
+/// Make sure the `size` bytes at `addr` lie within the user area.
+fn user_range(pid: PID, addr: usize, size: usize) -> Result<(), Error> {
+    if pid.get() == 1 {
+        return Ok(());
+    }
+    match addr.checked_add(size) {
+        Some(end) if end <= USER_AREA_END => Ok(()),
+        _ => Err(Error::BadAddress),
+    }
+}
+
+/// Make sure `addr` and `size` are both multiples of the page size.
+fn page_aligned(addr: usize, size: usize) -> Result<(), Error> {
+    if addr & (PAGE_SIZE - 1) != 0 || size & (PAGE_SIZE - 1) != 0 {
+        return Err(Error::BadAlignment);
+    }
+    Ok(())
+}
+
+/// Make sure the whole pages from `addr` to `addr + size` lie within the
+/// user area.
+fn user_pages(pid: PID, addr: usize, size: usize) -> Result<(), Error> {
+    page_aligned(addr, size)?;
+    user_range(pid, addr, size)
+}
+
+/// Make sure the memory attached to `message`, if any, lies within the user
+/// area.  For a scattered message that means every one of its pieces.
+/// Hosted processes lend from their own address space, which the kernel
+/// knows nothing about unless it's simulating their pagetables, so otherwise
+/// there is nothing to check there.
+fn message(pid: PID, message: &xous_kernel::Message) -> Result<(), Error> {
+    use xous_kernel::Message;
+    #[cfg(not(baremetal))]
+    if !crate::arch::mem::has_page_tables() {
+        return Ok(());
+    }
+    match message {
+        Message::MutableBorrow(memory) | Message::Borrow(memory) | Message::Move(memory) => {
+            match memory.scatter_list() {
+                Some(list) => {
+                    user_range(
+                        pid,
+                        list as usize,
+                        core::mem::size_of::<xous_kernel::ScatterList>(),
+                    )?;
+                    for range in crate::scatter::read(list)?.iter() {
+                        user_pages(pid, range.as_ptr() as usize, range.len())?;
+                    }
+                    Ok(())
+                }
+                None => user_pages(pid, memory.buf.as_ptr() as usize, memory.buf.len()),
+            }
+        }
+        Message::Scalar(_) | Message::BlockingScalar(_) => Ok(()),
+    }
+}
+
+/// Check every address in `call`, which `pid` is making.
+///
+/// # Errors
+///
+/// * **BadAddress**: An address or range isn't within the user area
+/// * **BadAlignment**: A range that has to cover whole pages doesn't
+pub fn syscall(pid: PID, call: &SysCall) -> Result<(), Error> {
+    match call {
+        SysCall::MapMemory(_, virt, size, _) => match virt {
+            Some(virt) => user_pages(pid, virt.get(), size.get()),
+            None => page_aligned(0, size.get()),
+        },
+        SysCall::ReserveAddressSpace(virt, size) => match virt {
+            Some(virt) => user_pages(pid, virt.get(), size.get()),
+            None => page_aligned(0, size.get()),
+        },
+        SysCall::UnmapMemory(range) => {
+            page_aligned(range.as_ptr() as usize, range.len())?;
+            // Hosted processes also unmap memory they got from the host.
+            #[cfg(baremetal)]
+            user_range(pid, range.as_ptr() as usize, range.len())?;
+            Ok(())
+        }
+        SysCall::FinalizeCode(range) => user_pages(pid, range.as_ptr() as usize, range.len()),
+        SysCall::UpdateMemoryFlags(virt, count, _) => {
+            let size = count.checked_mul(PAGE_SIZE).ok_or(Error::BadAddress)?;
+            user_pages(pid, virt.get(), size)
+        }
+        SysCall::MapSegment(base, offset, size, _) => {
+            let start = base.get().checked_add(*offset).ok_or(Error::BadAddress)?;
+            user_pages(pid, start, size.get())
+        }
+        SysCall::ShareSegment(src, _, dest_base) => {
+            user_range(pid, src.get(), 1)?;
+            user_range(pid, dest_base.get(), 1)
+        }
+        SysCall::SetExceptionHandler(Some(handler)) => user_range(pid, handler.get(), 1),
+        SysCall::SetExceptionStack(Some(stack)) => {
+            user_range(pid, stack.as_ptr() as usize, stack.len())
+        }
+        #[cfg(baremetal)]
+        SysCall::ClaimInterrupt(_, callback, _) => user_range(pid, callback.get(), 1),
+        #[cfg(baremetal)]
+        SysCall::CreateThread(init) => {
+            user_range(pid, init.call as usize, 1)?;
+            user_range(pid, init.stack.as_ptr() as usize, init.stack.len())
+        }
+        #[cfg(baremetal)]
+        SysCall::ReturnMemory(_, buf) => user_pages(pid, buf.as_ptr() as usize, buf.len()),
+        SysCall::SendMessage(_, msg)
+        | SysCall::TrySendMessage(_, msg)
+        | SysCall::CallMessage(_, msg)
+        | SysCall::ForwardMessage(_, _, msg) => message(pid, msg),
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,202 @@
+//! A watchdog for processes that must never get stuck, such as the graphics
+//! server.  A process promises to check in at least once every so many
+//! ticks, and then pokes its heartbeat at least that often.  When a process
+//! misses its deadline, the kernel signals the supervisor's notification so
+//! that it can find out which process is stuck and restart it.  If there is
+//! no supervisor, a missed heartbeat panics the kernel on baremetal, which
+//! leaves it to the hardware watchdog to reset the device.
+//!
+//! Deadlines are checked on each timer tick, as well as whenever the
+//! supervisor asks which processes have missed theirs.
+
+use xous_kernel::PID;
+
+/// The most processes that may have a heartbeat at once
+const MAX_HEARTBEATS: usize = 16;
+
+#[derive(Copy, Clone)]
+struct Heartbeat {
+    pid: PID,
+
+    /// How many ticks the process may go without poking its heartbeat
+    interval: u64,
+
+    /// The tick by which the process has to poke its heartbeat next
+    deadline: u64,
+
+    /// Whether the process has missed its deadline, and the supervisor
+    /// hasn't taken it yet
+    missed: bool,
+}
+
+struct Watchdog {
+    heartbeats: [Option<Heartbeat>; MAX_HEARTBEATS],
+
+    /// The process that is told about missed heartbeats, and the
+    /// notification it wants signalled
+    supervisor: Option<(PID, usize)>,
+}
+
+const EMPTY: Watchdog = Watchdog {
+    heartbeats: [None; MAX_HEARTBEATS],
+    supervisor: None,
+};
+
+#[cfg(baremetal)]
+static mut WATCHDOG: Watchdog = EMPTY;
+
+#[cfg(not(baremetal))]
+std::thread_local!(static WATCHDOG: core::cell::RefCell<Watchdog> = const { core::cell::RefCell::new(EMPTY) });
+
+fn with_mut<F, R>(f: F) -> R
+where
+    F: FnOnce(&mut Watchdog) -> R,
+{
+    #[cfg(baremetal)]
+    unsafe {
+        f(&mut WATCHDOG)
+    }
+
+    #[cfg(not(baremetal))]
+    WATCHDOG.with(|watchdog| f(&mut watchdog.borrow_mut()))
+}
+
+/// Have `pid` poke its heartbeat at least once every `ticks`, starting now,
+/// or stop watching it if `ticks` is `0`.
+///
+/// # Errors
+///
+/// * **OutOfMemory**: Too many processes already have a heartbeat
+pub fn set_interval(pid: PID, ticks: u64) -> Result<(), xous_kernel::Error> {
+    with_mut(|watchdog| {
+        let existing = watchdog
+            .heartbeats
+            .iter()
+            .position(|slot| matches!(slot, Some(heartbeat) if heartbeat.pid == pid));
+        if ticks == 0 {
+            if let Some(index) = existing {
+                watchdog.heartbeats[index] = None;
+            }
+            return Ok(());
+        }
+        let index = existing
+            .or_else(|| watchdog.heartbeats.iter().position(|slot| slot.is_none()))
+            .ok_or(xous_kernel::Error::OutOfMemory)?;
+        watchdog.heartbeats[index] = Some(Heartbeat {
+            pid,
+            interval: ticks,
+            deadline: crate::arch::timestamp().saturating_add(ticks),
+            missed: false,
+        });
+        Ok(())
+    })
+}
+
+/// Note that `pid` is still alive, giving it a fresh deadline.
+///
+/// # Errors
+///
+/// * **InvalidSyscall**: The process doesn't have a heartbeat
+pub fn beat(pid: PID) -> Result<(), xous_kernel::Error> {
+    with_mut(|watchdog| {
+        let heartbeat = watchdog
+            .heartbeats
+            .iter_mut()
+            .flatten()
+            .find(|heartbeat| heartbeat.pid == pid)
+            .ok_or(xous_kernel::Error::InvalidSyscall)?;
+        heartbeat.deadline = crate::arch::timestamp().saturating_add(heartbeat.interval);
+        heartbeat.missed = false;
+        Ok(())
+    })
+}
+
+/// Make `pid` the supervisor, to be told about missed heartbeats by having
+/// notification `id` signalled, or stop having a supervisor if `id` is `0`.
+/// It's up to the caller to make sure that `pid` owns the notification.
+pub fn set_supervisor(pid: PID, id: usize) {
+    with_mut(|watchdog| watchdog.supervisor = if id == 0 { None } else { Some((pid, id)) })
+}
+
+/// Mark every process that has gone past its deadline as of `now` as having
+/// missed it.  Returns how many processes newly missed their deadlines,
+/// along with the first of them.
+fn expire(watchdog: &mut Watchdog, now: u64) -> (usize, Option<PID>) {
+    let mut missed = 0;
+    let mut first = None;
+    for heartbeat in watchdog.heartbeats.iter_mut().flatten() {
+        if !heartbeat.missed && now > heartbeat.deadline {
+            heartbeat.missed = true;
+            missed += 1;
+            first = first.or(Some(heartbeat.pid));
+        }
+    }
+    (missed, first)
+}
+
+/// Take a process that has missed its deadline on behalf of the supervisor
+/// `pid`.  The process is given a fresh deadline, so it's reported again if
+/// it stays stuck.  Returns `None` if every process is keeping up.
+///
+/// # Errors
+///
+/// * **AccessDenied**: `pid` isn't the supervisor
+pub fn take_missed(pid: PID) -> Result<Option<PID>, xous_kernel::Error> {
+    with_mut(|watchdog| {
+        if !matches!(watchdog.supervisor, Some((supervisor, _)) if supervisor == pid) {
+            return Err(xous_kernel::Error::AccessDenied);
+        }
+        let now = crate::arch::timestamp();
+        expire(watchdog, now);
+        Ok(watchdog
+            .heartbeats
+            .iter_mut()
+            .flatten()
+            .find(|heartbeat| heartbeat.missed)
+            .map(|heartbeat| {
+                heartbeat.deadline = now.saturating_add(heartbeat.interval);
+                heartbeat.missed = false;
+                heartbeat.pid
+            }))
+    })
+}
+
+/// Stop watching `pid`, which is going away, and drop it as the supervisor
+/// if it was one.
+pub fn forget_process(pid: PID) {
+    with_mut(|watchdog| {
+        for slot in watchdog.heartbeats.iter_mut() {
+            if matches!(slot, Some(heartbeat) if heartbeat.pid == pid) {
+                *slot = None;
+            }
+        }
+        if matches!(watchdog.supervisor, Some((supervisor, _)) if supervisor == pid) {
+            watchdog.supervisor = None;
+        }
+    })
+}
+
+/// Signal the supervisor once for every process that has missed its
+/// deadline since the last check, or panic if there is no supervisor.
+#[cfg(baremetal)]
+pub fn check() {
+    let now = crate::arch::timestamp();
+    let (missed, first, supervisor) = with_mut(|watchdog| {
+        let (missed, first) = expire(watchdog, now);
+        (missed, first, watchdog.supervisor)
+    });
+    let first = match first {
+        Some(pid) => pid,
+        None => return,
+    };
+    match supervisor {
+        Some((_, id)) => {
+            println!("watchdog: PID {} missed its heartbeat", first);
+            crate::services::SystemServices::with_mut(|ss| ss.signal_notification(id, missed)).ok();
+        }
+        None => panic!(
+            "watchdog: PID {} missed its heartbeat and there is no supervisor",
+            first
+        ),
+    }
+}
@@ -6,6 +6,7 @@ mod args;
 use args::{KernelArgument, KernelArguments};
 
 use core::{mem, ptr, slice};
+use sha3::{Digest, Sha3_256};
 
 pub type XousPid = u8;
 pub const PAGE_SIZE: usize = 4096;
@@ -100,6 +101,11 @@ pub struct BootConfig {
     /// is the kernel, and any subsequent elements are init processes.
     processes: &'static mut [InitialProcess],
 
+    /// A SHA3-256 hash of each program, in the same order as `processes`.
+    /// These live directly after the process table, which is where the
+    /// kernel expects to find them.
+    measurements: &'static mut [[u8; 32]],
+
     /// The number of 'Init' tags discovered
     init_process_count: usize,
 }
@@ -119,6 +125,7 @@ impl Default for BootConfig {
             runtime_page_tracker: Default::default(),
             init_process_count: 0,
             processes: Default::default(),
+            measurements: Default::default(),
         }
     }
 }
@@ -231,16 +238,23 @@ impl MiniElf {
         let pt_addr = allocator.alloc() as usize;
         allocator.map_page(satp, pt_addr, PAGE_TABLE_OFFSET, FLG_R | FLG_W);
 
-        // Allocate stack pages.
-        for i in 0..STACK_PAGE_COUNT {
-            let sp_page = allocator.alloc() as usize;
-            allocator.map_page(
+        // Allocate the first stack page, and reserve the rest.  The kernel
+        // will allocate and zero the reserved pages as the stack grows into
+        // them, which saves doing it here for pages that may never be used.
+        let sp_page = allocator.alloc() as usize;
+        allocator.map_page(
+            satp,
+            sp_page,
+            stack_addr & !(PAGE_SIZE - 1),
+            FLG_U | FLG_R | FLG_W,
+        );
+        allocator.change_owner(pid as XousPid, sp_page);
+        for i in 1..STACK_PAGE_COUNT {
+            allocator.reserve_page(
                 satp,
-                sp_page,
                 (stack_addr - PAGE_SIZE * i) & !(PAGE_SIZE - 1),
-                FLG_U | FLG_R | FLG_W,
+                FLG_R | FLG_W,
             );
-            allocator.change_owner(pid as XousPid, sp_page);
         }
 
         // Example: Page starts at 0xf0c0 and is 8192 bytes long.
@@ -261,6 +275,9 @@ impl MiniElf {
                 | FLG_R
                 | if section.flags() & 1 == 1 { FLG_W } else { 0 }
                 | if section.flags() & 4 == 4 { FLG_X } else { 0 };
+            if flag_defaults & (FLG_W | FLG_X) == (FLG_W | FLG_X) {
+                panic!("init section at {:08x} is both writable and executable", section.virt);
+            }
 
             if (section.virt as usize) < previous_addr {
                 panic!("init section addresses are not strictly increasing");
@@ -454,7 +471,7 @@ impl ProgramDescription {
                 satp,
                 load_offset + offset + rounded_data_bss,
                 self.text_offset as usize + offset,
-                flag_defaults | FLG_X,
+                (flag_defaults & !FLG_W) | FLG_X,
             );
             allocator.change_owner(pid as XousPid, load_offset + offset);
         }
@@ -491,15 +508,28 @@ impl ProgramDescription {
     }
 }
 
-unsafe fn bzero<T>(mut sbss: *mut T, ebss: *mut T)
+/// Zero the memory from `sbss` up to `ebss`.  Most of the range is cleared a
+/// word at a time, even if `T` is smaller, with any unaligned bytes at either
+/// end cleared individually.
+unsafe fn bzero<T>(sbss: *mut T, ebss: *mut T)
 where
     T: Copy,
 {
     println!("ZERO: {:08x} - {:08x}", sbss as usize, ebss as usize);
-    while sbss < ebss {
-        // NOTE(volatile) to prevent this from being transformed into `memclr`
-        ptr::write_volatile(sbss, mem::zeroed());
-        sbss = sbss.offset(1);
+    let mut addr = sbss as usize;
+    let end = ebss as usize;
+    // NOTE(volatile) to prevent this from being transformed into `memclr`
+    while addr < end && addr & (WORD_SIZE - 1) != 0 {
+        ptr::write_volatile(addr as *mut u8, 0);
+        addr += 1;
+    }
+    while addr + WORD_SIZE <= end {
+        ptr::write_volatile(addr as *mut usize, 0);
+        addr += WORD_SIZE;
+    }
+    while addr < end {
+        ptr::write_volatile(addr as *mut u8, 0);
+        addr += 1;
     }
 }
 
@@ -516,11 +546,23 @@ where
         dest as usize,
         dest as usize + count
     );
+    // Only whole `T`s are copied.  If both pointers are word-aligned, copy a
+    // word at a time even if `T` is smaller, since programs are copied as
+    // bytes.
+    let len = count / mem::size_of::<T>() * mem::size_of::<T>();
+    let dest = dest as usize;
+    let src = src as usize;
     let mut offset = 0;
-    while offset < (count / mem::size_of::<T>()) {
-        dest.add(offset)
-            .write_volatile(src.add(offset).read_volatile());
-        offset += 1
+    if (dest | src) & (WORD_SIZE - 1) == 0 {
+        while offset + WORD_SIZE <= len {
+            ((dest + offset) as *mut usize)
+                .write_volatile(((src + offset) as *const usize).read_volatile());
+            offset += WORD_SIZE;
+        }
+    }
+    while offset < len {
+        ((dest + offset) as *mut u8).write_volatile(((src + offset) as *const u8).read_volatile());
+        offset += 1;
     }
 }
 
@@ -873,6 +915,22 @@ impl BootConfig {
         }
     }
 
+    /// Reserve the given virtual address in the specified process table
+    /// without backing it with a page.  The kernel allocates a page the
+    /// first time it's accessed.
+    pub fn reserve_page(&mut self, root: &mut PageTable, virt: usize, flags: usize) {
+        // Map a placeholder to ensure the second-level table exists, and then
+        // turn the entry into a reservation by clearing everything but the
+        // permissions.
+        self.map_page(root, 0, virt, flags);
+        let vpn1 = (virt >> 22) & ((1 << 10) - 1);
+        let vpn0 = (virt >> 12) & ((1 << 10) - 1);
+        let l0_pt = unsafe {
+            &mut (*(((root.entries[vpn1] << 2) & !((1 << 12) - 1)) as *mut PageTable))
+        };
+        l0_pt.entries[vpn0] = flags & (FLG_R | FLG_W | FLG_X);
+    }
+
     pub fn map_page_32(&mut self, root: &mut PageTable, phys: usize, virt: usize, flags: usize) {
         let ppn1 = (phys >> 22) & ((1 << 12) - 1);
         let ppn0 = (phys >> 12) & ((1 << 10) - 1);
@@ -909,6 +967,11 @@ impl BootConfig {
         //     panic!("Page already allocated!");
         // }
         let previous_flags = l0_pt[vpn0] & 0xf;
+        // Sections that share a page have their flags merged.  Refuse to
+        // create a page that ends up both writable and executable.
+        if (flags | previous_flags) & (FLG_W | FLG_X) == (FLG_W | FLG_X) {
+            panic!("page {:08x} would be both writable and executable", virt);
+        }
         l0_pt[vpn0] =
             (ppn1 << 20) | (ppn0 << 10) | flags | previous_flags | FLG_VALID | FLG_D | FLG_A;
 
@@ -966,18 +1029,65 @@ pub fn allocate_regions(cfg: &mut BootConfig) {
 pub fn allocate_processes(cfg: &mut BootConfig) {
     let process_count = cfg.init_process_count + 1;
     let table_size = process_count * mem::size_of::<InitialProcess>();
-    // Allocate the process table
-    cfg.init_size += table_size;
+    let measurements_size = process_count * mem::size_of::<[u8; 32]>();
+    // Allocate the process table, followed by the measurements
+    cfg.init_size += table_size + measurements_size;
     let processes = cfg.get_top();
     unsafe {
         bzero(
             processes,
-            processes.add((table_size / mem::size_of::<usize>()) as usize),
+            processes.add(((table_size + measurements_size) / mem::size_of::<usize>()) as usize),
         );
     }
     cfg.processes = unsafe {
         slice::from_raw_parts_mut(processes as *mut InitialProcess, process_count as usize)
     };
+    cfg.measurements = unsafe {
+        slice::from_raw_parts_mut(
+            processes.add(table_size / mem::size_of::<usize>()) as *mut [u8; 32],
+            process_count as usize,
+        )
+    };
+}
+
+/// Hash the kernel and each init program as they appear in the image, so
+/// the kernel can report what it was booted with.  Each hash covers the
+/// program's tag, minus its load offset, followed by the bytes that get
+/// copied into RAM.  The kernel is always first, followed by init programs
+/// in the order they will be assigned PIDs.
+pub fn measure_images(cfg: &mut BootConfig) {
+    let mut init_index = 0;
+    for tag in cfg.args.iter() {
+        let (slot, image_size) = if tag.name == u32::from_le_bytes(*b"XKrn") {
+            let prog = unsafe { &*(tag.data.as_ptr() as *const ProgramDescription) };
+            (0, prog.text_size as usize + prog.data_size as usize)
+        } else if tag.name == u32::from_le_bytes(*b"IniE") {
+            let inie = MiniElf::new(&tag);
+            let image_size = inie
+                .sections
+                .iter()
+                .filter(|section| !section.no_copy())
+                .map(|section| section.len())
+                .sum();
+            init_index += 1;
+            (init_index, image_size)
+        } else {
+            continue;
+        };
+
+        let mut hasher = Sha3_256::new();
+        for word in tag.data[1..].iter() {
+            hasher.input(word.to_le_bytes());
+        }
+        let image = unsafe {
+            let src_addr = cfg
+                .base_addr
+                .add(tag.data[0] as usize / mem::size_of::<usize>());
+            slice::from_raw_parts(src_addr as *const u8, image_size)
+        };
+        hasher.input(image);
+        cfg.measurements[slot].copy_from_slice(hasher.result().as_slice());
+    }
 }
 
 pub fn copy_args(cfg: &mut BootConfig) {
@@ -1065,6 +1175,9 @@ fn phase_1(cfg: &mut BootConfig) {
     println!("Allocating processes");
     allocate_processes(cfg);
 
+    println!("Measuring images");
+    measure_images(cfg);
+
     // Copy the arguments, if requested
     if cfg.no_copy {
         // TODO: place args into cfg.args
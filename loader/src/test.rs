@@ -400,6 +400,26 @@ fn tracker_sane() {
     }
 }
 
+#[test]
+fn measure_images() {
+    let mut env = TestEnvironment::new(0);
+    crate::allocate_regions(&mut env.cfg);
+    crate::allocate_processes(&mut env.cfg);
+    crate::measure_images(&mut env.cfg);
+
+    let first: Vec<[u8; 32]> = env.cfg.measurements.to_vec();
+    for (index, measurement) in first.iter().enumerate() {
+        assert_ne!(measurement, &[0u8; 32], "image {} wasn't measured", index);
+        for other in first[index + 1..].iter() {
+            assert_ne!(measurement, other, "two images have the same measurement");
+        }
+    }
+
+    // Measuring the same images again must produce the same result
+    crate::measure_images(&mut env.cfg);
+    assert_eq!(first, env.cfg.measurements.to_vec());
+}
+
 // Create a fake "start_kernel" function to allow
 // this module to compile when not running natively.
 #[export_name = "start_kernel"]
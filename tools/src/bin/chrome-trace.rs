@@ -0,0 +1,118 @@
+use std::env;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::process;
+
+/// Turn `TRACE` lines printed by a trace collector into the JSON trace
+/// format that `chrome://tracing` and Perfetto load.  Each line holds one
+/// record drained from a kernel built with the `trace` feature:
+///
+/// ```text
+/// TRACE <timestamp> <kind> <pid> <tid> <arg> <dropped>
+/// ```
+///
+/// Syscalls become slices on the thread that made them, and everything else
+/// becomes an instant event.  Timestamps are divided by `--hz` to get
+/// seconds, which defaults to the nanoseconds used by the hosted kernel.
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let usage = || -> ! {
+        eprintln!(
+            "Usage: {} [--hz <timebase>] [console.log]",
+            args.first().map(|s| s.as_str()).unwrap_or("chrome-trace")
+        );
+        process::exit(1);
+    };
+
+    let mut hz = 1_000_000_000f64;
+    let mut input = None;
+    let mut remaining = args.iter().skip(1);
+    while let Some(arg) = remaining.next() {
+        if arg == "--hz" {
+            hz = match remaining.next().and_then(|s| s.parse().ok()) {
+                Some(hz) if hz > 0.0 => hz,
+                _ => usage(),
+            };
+        } else if arg.starts_with("--") {
+            usage();
+        } else {
+            input = Some(arg);
+        }
+    }
+
+    let reader: Box<dyn BufRead> = match input {
+        Some(filename) => Box::new(BufReader::new(File::open(filename).unwrap_or_else(|e| {
+            eprintln!("Unable to open {}: {}", filename, e);
+            usage()
+        }))),
+        None => Box::new(BufReader::new(io::stdin())),
+    };
+
+    let mut events = vec![];
+    for line in reader.lines() {
+        let line = line.unwrap_or_else(|e| {
+            eprintln!("Unable to read input: {}", e);
+            process::exit(1);
+        });
+
+        // Console output may be interleaved with other messages, so look for
+        // the marker anywhere on the line.
+        let record = match line.find("TRACE ") {
+            Some(offset) => &line[offset + 6..],
+            None => continue,
+        };
+        let fields: Vec<u64> = match record
+            .split_whitespace()
+            .take(6)
+            .map(|field| field.parse())
+            .collect()
+        {
+            Ok(fields) => fields,
+            Err(_) => continue,
+        };
+        if fields.len() < 6 {
+            continue;
+        }
+        let (timestamp, kind, pid, tid, arg, dropped) = (
+            fields[0], fields[1], fields[2], fields[3], fields[4], fields[5],
+        );
+        let common = format!(
+            r#""pid":{},"tid":{},"ts":{:.3}"#,
+            pid,
+            tid,
+            timestamp as f64 * 1_000_000.0 / hz
+        );
+
+        if dropped > 0 {
+            events.push(format!(
+                r#"{{"name":"{} records dropped","ph":"i","s":"g",{}}}"#,
+                dropped, common
+            ));
+        }
+        events.push(match kind {
+            1 => format!(r#"{{"name":"syscall {}","ph":"B",{}}}"#, arg, common),
+            2 => format!(r#"{{"ph":"E",{},"args":{{"error":{}}}}}"#, common, arg),
+            3 => format!(r#"{{"name":"switch in","ph":"i","s":"t",{}}}"#, common),
+            4 => format!(
+                r#"{{"name":"send","ph":"i","s":"t",{},"args":{{"cid":{}}}}}"#,
+                common, arg
+            ),
+            5 => format!(
+                r#"{{"name":"receive","ph":"i","s":"t",{},"args":{{"sender":{}}}}}"#,
+                common, arg
+            ),
+            6 => format!(
+                r#"{{"name":"return","ph":"i","s":"t",{},"args":{{"sender":{}}}}}"#,
+                common, arg
+            ),
+            _ => continue,
+        });
+    }
+
+    println!("{{\"traceEvents\":[");
+    for (index, event) in events.iter().enumerate() {
+        let separator = if index + 1 < events.len() { "," } else { "" };
+        println!("{}{}", event, separator);
+    }
+    println!("]}}");
+}
@@ -0,0 +1,73 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::process;
+
+/// Collect `COV <hits> <name>` lines printed by programs built with the
+/// `coverage` feature, and report which points were never reached.  Points
+/// that appear more than once, such as from several dumps, are added up.
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let reader: Box<dyn BufRead> = match args.get(1) {
+        Some(filename) => Box::new(BufReader::new(File::open(filename).unwrap_or_else(|e| {
+            eprintln!("Unable to open {}: {}", filename, e);
+            eprintln!(
+                "Usage: {} [console.log]",
+                args.first().unwrap_or(&"coverage-report".to_owned())
+            );
+            process::exit(1);
+        }))),
+        None => Box::new(BufReader::new(io::stdin())),
+    };
+
+    let mut points: BTreeMap<String, usize> = BTreeMap::new();
+    for line in reader.lines() {
+        let line = line.unwrap_or_else(|e| {
+            eprintln!("Unable to read input: {}", e);
+            process::exit(1);
+        });
+        let record = match line.find("COV ") {
+            Some(offset) => &line[offset + 4..],
+            None => continue,
+        };
+        let mut fields = record.splitn(2, ' ');
+        let hits = match fields.next().and_then(|h| h.parse::<usize>().ok()) {
+            Some(hits) => hits,
+            None => continue,
+        };
+        let name = match fields.next() {
+            Some(name) => name.trim().to_owned(),
+            None => continue,
+        };
+        *points.entry(name).or_insert(0) += hits;
+    }
+
+    if points.is_empty() {
+        eprintln!("No coverage points found");
+        process::exit(1);
+    }
+
+    let missed: Vec<&String> = points
+        .iter()
+        .filter(|(_, hits)| **hits == 0)
+        .map(|(name, _)| name)
+        .collect();
+    for (name, hits) in points.iter().filter(|(_, hits)| **hits != 0) {
+        println!("{:>8} {}", hits, name);
+    }
+    if !missed.is_empty() {
+        println!();
+        println!("Never reached:");
+        for name in missed.iter() {
+            println!("         {}", name);
+        }
+    }
+    println!();
+    println!(
+        "{} of {} points reached ({}%)",
+        points.len() - missed.len(),
+        points.len(),
+        (points.len() - missed.len()) * 100 / points.len()
+    );
+}
@@ -7,8 +7,11 @@ use std::fs::File;
 
 use tools::elf::{read_minielf, read_program};
 use tools::tags::bflg::Bflg;
+use tools::tags::freq::Freq;
 use tools::tags::inie::IniE;
 use tools::tags::memory::{MemoryRegion, MemoryRegions};
+use tools::tags::schd::{Schd, POLICIES};
+use tools::tags::tick::Tick;
 use tools::tags::xkrn::XousKernel;
 use tools::utils::{parse_csr_csv, parse_u32};
 use tools::xous_arguments::XousArguments;
@@ -66,6 +69,29 @@ fn main() {
                 .takes_value(false)
                 .help("Reduce kernel-userspace security and enable debugging programs"),
         )
+        .arg(
+            Arg::with_name("timebase")
+                .short("t")
+                .long("timebase")
+                .takes_value(true)
+                .value_name("HZ")
+                .help("Frequency of the `time` CSR, used to convert timestamps to real time"),
+        )
+        .arg(
+            Arg::with_name("tick-timer")
+                .long("tick-timer")
+                .takes_value(true)
+                .value_name("ADDRESS:IRQ:CYCLES")
+                .help("Timer the kernel uses to preempt processes, and how many cycles each tick lasts"),
+        )
+        .arg(
+            Arg::with_name("scheduler")
+                .long("scheduler")
+                .takes_value(true)
+                .value_name("POLICY")
+                .possible_values(&POLICIES)
+                .help("Scheduling policy to use instead of the one the kernel was built with"),
+        )
         .arg(
             Arg::with_name("output")
                 .value_name("OUTPUT")
@@ -154,6 +180,41 @@ fn main() {
         args.add(Bflg::new().debug());
     }
 
+    if let Some(val) = matches.value_of("timebase") {
+        match parse_u32(val) {
+            Ok(hz) => args.add(Freq::new(hz)),
+            Err(e) => {
+                eprintln!("Error: Unable to parse {}: {:?}", val, e);
+                return;
+            }
+        }
+    }
+
+    if let Some(val) = matches.value_of("tick-timer") {
+        let tick_parts: Vec<&str> = val.split(':').collect();
+        if tick_parts.len() != 3 {
+            eprintln!(
+                "Error: --tick-timer argument should be of the form [address]:[irq]:[cycles]"
+            );
+            return;
+        }
+        let mut tick_values = [0u32; 3];
+        for (value, part) in tick_values.iter_mut().zip(tick_parts.iter()) {
+            *value = match parse_u32(part) {
+                Ok(v) => v,
+                Err(e) => {
+                    eprintln!("Error: Unable to parse {}: {:?}", part, e);
+                    return;
+                }
+            };
+        }
+        args.add(Tick::new(tick_values[0], tick_values[1], tick_values[2]));
+    }
+
+    if let Some(val) = matches.value_of("scheduler") {
+        args.add(Schd::new(val).expect("clap accepted an unknown policy"));
+    }
+
     let kernel = read_program(
         matches
             .value_of("kernel")
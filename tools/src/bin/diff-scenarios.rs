@@ -0,0 +1,69 @@
+use std::env;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::process;
+
+/// Collect the `SCENARIO` lines printed by `ipc-scenario` from a console log.
+fn scenario_lines(filename: &str) -> Vec<String> {
+    let file = File::open(filename).unwrap_or_else(|e| {
+        eprintln!("Unable to open {}: {}", filename, e);
+        process::exit(2);
+    });
+
+    let mut lines = vec![];
+    for line in BufReader::new(file).lines() {
+        let line = line.unwrap_or_else(|e| {
+            eprintln!("Unable to read {}: {}", filename, e);
+            process::exit(2);
+        });
+
+        // Console output may be interleaved with other messages, so look for
+        // the marker anywhere on the line.
+        if let Some(offset) = line.find("SCENARIO ") {
+            lines.push(line[offset + 9..].trim_end().to_owned());
+        }
+    }
+    lines
+}
+
+/// Compare the results of running `ipc-scenario` under two different kernels,
+/// usually the hosted kernel and a baremetal one running in an emulator.
+/// Every scenario that produced a different result is printed, and the exit
+/// status is nonzero if there were any differences.
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() != 3 {
+        eprintln!(
+            "Usage: {} [hosted.log] [baremetal.log]",
+            args.first().unwrap_or(&"diff-scenarios".to_owned())
+        );
+        process::exit(2);
+    }
+
+    let left = scenario_lines(&args[1]);
+    let right = scenario_lines(&args[2]);
+    let mut differences = 0;
+
+    for (name, lines) in [(&args[1], &left), (&args[2], &right)].iter() {
+        if lines.last().map(|l| l.as_str()) != Some("done") {
+            println!("{}: scenarios did not run to completion", name);
+            differences += 1;
+        }
+    }
+
+    for index in 0..left.len().max(right.len()) {
+        let l = left.get(index);
+        let r = right.get(index);
+        if l != r {
+            println!("- {}", l.map(|s| s.as_str()).unwrap_or("<missing>"));
+            println!("+ {}", r.map(|s| s.as_str()).unwrap_or("<missing>"));
+            differences += 1;
+        }
+    }
+
+    if differences > 0 {
+        println!("{} difference(s) found", differences);
+        process::exit(1);
+    }
+    println!("{} scenario results match", left.len());
+}
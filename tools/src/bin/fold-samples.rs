@@ -0,0 +1,60 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::process;
+
+/// Turn `PROF` lines from a kernel built with the `profile` feature into
+/// folded stacks, one per line, as expected by flamegraph tools.  The stack
+/// words captured with each sample are not necessarily return addresses, so
+/// they are only included when `--with-stack` is given.
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let with_stack = args.iter().any(|a| a == "--with-stack");
+    let input = args.iter().skip(1).find(|a| !a.starts_with("--"));
+
+    let reader: Box<dyn BufRead> = match input {
+        Some(filename) => Box::new(BufReader::new(File::open(filename).unwrap_or_else(|e| {
+            eprintln!("Unable to open {}: {}", filename, e);
+            eprintln!(
+                "Usage: {} [--with-stack] [console.log]",
+                args.first().unwrap_or(&"fold-samples".to_owned())
+            );
+            process::exit(1);
+        }))),
+        None => Box::new(BufReader::new(io::stdin())),
+    };
+
+    let mut folded: BTreeMap<String, usize> = BTreeMap::new();
+    for line in reader.lines() {
+        let line = line.unwrap_or_else(|e| {
+            eprintln!("Unable to read input: {}", e);
+            process::exit(1);
+        });
+
+        // Console output may be interleaved with other messages, so look for
+        // the marker anywhere on the line.
+        let sample = match line.find("PROF ") {
+            Some(offset) => &line[offset + 5..],
+            None => continue,
+        };
+        let fields: Vec<&str> = sample.split_whitespace().collect();
+        if fields.len() < 3 {
+            continue;
+        }
+
+        // Frames are listed from the outermost to the innermost.
+        let mut frames = vec![format!("pid{}", fields[0]), format!("tid{}", fields[1])];
+        if with_stack {
+            for word in fields[3..].iter().rev() {
+                frames.push(format!("0x{}", word));
+            }
+        }
+        frames.push(format!("0x{}", fields[2]));
+        *folded.entry(frames.join(";")).or_insert(0) += 1;
+    }
+
+    for (stack, count) in folded {
+        println!("{} {}", stack, count);
+    }
+}
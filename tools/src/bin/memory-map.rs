@@ -0,0 +1,241 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::process;
+
+const PAGE_SIZE: u64 = 4096;
+const MEGAPAGE_SIZE: u64 = 4 * 1024 * 1024;
+
+/// Width and height of a single page in the SVG rendering.  Each row is one
+/// megapage, so it is 1024 pages wide.
+const CELL_WIDTH: u64 = 1;
+const CELL_HEIGHT: u64 = 12;
+const LABEL_WIDTH: u64 = 90;
+const ROW_WIDTH: u64 = MEGAPAGE_SIZE / PAGE_SIZE * CELL_WIDTH;
+
+/// A run of pages that are contiguous in the virtual address space and
+/// share the same flags and tag.
+struct Region {
+    start: u64,
+    end: u64,
+    flags: String,
+    tag: String,
+}
+
+/// Parse a `MAP <pid> <virt> <phys> <size> <flags> <tag>` line, returning
+/// the PID, the virtual address, the size, the flags, and the tag.
+fn parse_line(line: &str) -> Option<(u32, u64, u64, String, String)> {
+    // Console output may be interleaved with other messages, so look for
+    // the marker anywhere on the line.
+    let offset = line.find("MAP ")?;
+    let fields: Vec<&str> = line[offset + 4..].split_whitespace().collect();
+    if fields.len() < 6 {
+        return None;
+    }
+    let pid = fields[0].parse().ok()?;
+    let virt = u64::from_str_radix(fields[1], 16).ok()?;
+    let size = u64::from_str_radix(fields[3], 16).ok()?;
+    Some((pid, virt, size, fields[4].to_owned(), fields[5].to_owned()))
+}
+
+/// Merge the pages of a process into regions.  `pages` must be sorted by
+/// address.
+fn regions(pages: &[(u64, u64, String, String)]) -> Vec<Region> {
+    let mut regions: Vec<Region> = vec![];
+    for (virt, size, flags, tag) in pages {
+        if let Some(last) = regions.last_mut() {
+            if last.end == *virt && last.flags == *flags && last.tag == *tag {
+                last.end += size;
+                continue;
+            }
+        }
+        regions.push(Region {
+            start: *virt,
+            end: virt + size,
+            flags: flags.clone(),
+            tag: tag.clone(),
+        });
+    }
+    regions
+}
+
+fn format_size(bytes: u64) -> String {
+    if bytes >= 1024 * 1024 && bytes & (1024 * 1024 - 1) == 0 {
+        format!("{}M", bytes / (1024 * 1024))
+    } else {
+        format!("{}K", bytes / 1024)
+    }
+}
+
+fn print_text(processes: &BTreeMap<u32, Vec<Region>>) {
+    for (pid, regions) in processes {
+        let mapped: u64 = regions.iter().map(|r| r.end - r.start).sum();
+        let holes = regions
+            .windows(2)
+            .filter(|pair| pair[0].end != pair[1].start)
+            .count();
+        println!(
+            "PID {}: {} mapped in {} regions, {} holes",
+            pid,
+            format_size(mapped),
+            regions.len(),
+            holes
+        );
+        for region in regions {
+            println!(
+                "    {:08x} - {:08x} {:>6} {} {}",
+                region.start,
+                region.end,
+                format_size(region.end - region.start),
+                region.flags,
+                region.tag
+            );
+        }
+    }
+}
+
+fn tag_color(tag: &str) -> &'static str {
+    match tag {
+        "program" => "#4e79a7",
+        "heap" => "#59a14f",
+        "message" => "#f28e2b",
+        "default" => "#e15759",
+        "image" => "#b07aa1",
+        "kernel" => "#9c755f",
+        _ => "#bab0ab",
+    }
+}
+
+/// Draw each process as a block of rows, with one row for every megapage
+/// that has anything mapped in it and one cell per page.  Pages are colored
+/// by their tag, and writable pages are drawn darker than read-only ones.
+fn print_svg(processes: &BTreeMap<u32, Vec<Region>>) {
+    let mut body = String::new();
+    let mut y = 0;
+    for (pid, regions) in processes {
+        y += CELL_HEIGHT * 2;
+        body.push_str(&format!(
+            "<text x=\"0\" y=\"{}\" font-size=\"{}\">PID {}</text>\n",
+            y - CELL_HEIGHT / 2,
+            CELL_HEIGHT,
+            pid
+        ));
+
+        let mut rows: Vec<u64> = vec![];
+        for region in regions {
+            let mut row = region.start / MEGAPAGE_SIZE;
+            while row * MEGAPAGE_SIZE < region.end {
+                if rows.last() != Some(&row) {
+                    rows.push(row);
+                }
+                row += 1;
+            }
+        }
+
+        for row in rows {
+            let row_start = row * MEGAPAGE_SIZE;
+            let row_end = row_start + MEGAPAGE_SIZE;
+            body.push_str(&format!(
+                "<text x=\"0\" y=\"{}\" font-size=\"{}\" font-family=\"monospace\">{:08x}</text>\n",
+                y + CELL_HEIGHT - 2,
+                CELL_HEIGHT - 2,
+                row_start
+            ));
+            body.push_str(&format!(
+                "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"#eeeeee\"/>\n",
+                LABEL_WIDTH, y, ROW_WIDTH, CELL_HEIGHT
+            ));
+            for region in regions
+                .iter()
+                .filter(|r| r.start < row_end && r.end > row_start)
+            {
+                let start = region.start.max(row_start) - row_start;
+                let end = region.end.min(row_end) - row_start;
+                let opacity = if region.flags.contains('w') { 1.0 } else { 0.6 };
+                body.push_str(&format!(
+                    "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"{}\" fill-opacity=\"{}\"><title>{:08x} - {:08x} {} {}</title></rect>\n",
+                    LABEL_WIDTH + start / PAGE_SIZE * CELL_WIDTH,
+                    y,
+                    (end - start) / PAGE_SIZE * CELL_WIDTH,
+                    CELL_HEIGHT,
+                    tag_color(&region.tag),
+                    opacity,
+                    region.start,
+                    region.end,
+                    region.flags,
+                    region.tag
+                ));
+            }
+            y += CELL_HEIGHT + 1;
+        }
+    }
+
+    println!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\">",
+        LABEL_WIDTH + ROW_WIDTH,
+        y + CELL_HEIGHT
+    );
+    print!("{}", body);
+    println!("</svg>");
+}
+
+/// Render the `MAP` lines printed when `m` is pressed on the debug console
+/// of a kernel built with the `print-mappings` feature.  By default each
+/// process' mappings are listed as regions of contiguous pages; pass `--svg`
+/// to draw them instead.
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let svg = args.iter().any(|a| a == "--svg");
+    let input = args.iter().skip(1).find(|a| !a.starts_with("--"));
+
+    let reader: Box<dyn BufRead> = match input {
+        Some(filename) => Box::new(BufReader::new(File::open(filename).unwrap_or_else(|e| {
+            eprintln!("Unable to open {}: {}", filename, e);
+            eprintln!(
+                "Usage: {} [--svg] [console.log]",
+                args.first().unwrap_or(&"memory-map".to_owned())
+            );
+            process::exit(1);
+        }))),
+        None => Box::new(BufReader::new(io::stdin())),
+    };
+
+    // If the map was dumped more than once, only the last dump of each
+    // process is kept.
+    let mut pages: BTreeMap<u32, BTreeMap<u64, (u64, String, String)>> = BTreeMap::new();
+    let mut last_pid = None;
+    for line in reader.lines() {
+        let line = line.unwrap_or_else(|e| {
+            eprintln!("Unable to read input: {}", e);
+            process::exit(1);
+        });
+        if let Some((pid, virt, size, flags, tag)) = parse_line(&line) {
+            if last_pid != Some(pid) {
+                pages.insert(pid, BTreeMap::new());
+                last_pid = Some(pid);
+            }
+            pages
+                .get_mut(&pid)
+                .unwrap()
+                .insert(virt, (size, flags, tag));
+        }
+    }
+
+    let processes: BTreeMap<u32, Vec<Region>> = pages
+        .into_iter()
+        .map(|(pid, pages)| {
+            let pages: Vec<(u64, u64, String, String)> = pages
+                .into_iter()
+                .map(|(virt, (size, flags, tag))| (virt, size, flags, tag))
+                .collect();
+            (pid, regions(&pages))
+        })
+        .collect();
+
+    if svg {
+        print_svg(&processes);
+    } else {
+        print_text(&processes);
+    }
+}
@@ -0,0 +1,33 @@
+use crate::xous_arguments::{XousArgument, XousArgumentCode, XousSize};
+use std::fmt;
+use std::io;
+
+#[derive(Debug)]
+pub struct Freq {
+    /// Number of times per second that the `time` CSR increments
+    timebase: u32,
+}
+
+impl fmt::Display for Freq {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "    Freq: timebase of {} Hz", self.timebase)
+    }
+}
+
+impl Freq {
+    pub fn new(timebase: u32) -> Freq {
+        Freq { timebase }
+    }
+}
+
+impl XousArgument for Freq {
+    fn code(&self) -> XousArgumentCode {
+        u32::from_le_bytes(*b"Freq")
+    }
+    fn length(&self) -> XousSize {
+        4
+    }
+    fn serialize(&self, output: &mut dyn io::Write) -> io::Result<usize> {
+        output.write(&self.timebase.to_le_bytes())
+    }
+}
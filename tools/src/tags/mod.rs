@@ -1,4 +1,7 @@
 pub mod bflg;
+pub mod freq;
 pub mod inie;
 pub mod memory;
+pub mod schd;
+pub mod tick;
 pub mod xkrn;
@@ -0,0 +1,48 @@
+use crate::xous_arguments::{XousArgument, XousArgumentCode, XousSize};
+use std::fmt;
+use std::io;
+
+/// The scheduling policies the kernel knows about, in the order they are
+/// numbered in the argument.
+pub const POLICIES: [&str; 3] = ["round-robin", "priority", "deadline"];
+
+#[derive(Debug)]
+pub struct Schd {
+    /// Index into `POLICIES` of the policy the kernel should schedule with
+    policy: u32,
+}
+
+impl fmt::Display for Schd {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "    Schd: {} scheduling",
+            POLICIES.get(self.policy as usize).unwrap_or(&"unknown")
+        )
+    }
+}
+
+impl Schd {
+    /// Create an argument selecting the policy called `name`, or `None` if
+    /// there is no such policy.
+    pub fn new(name: &str) -> Option<Schd> {
+        POLICIES
+            .iter()
+            .position(|policy| *policy == name)
+            .map(|policy| Schd {
+                policy: policy as u32,
+            })
+    }
+}
+
+impl XousArgument for Schd {
+    fn code(&self) -> XousArgumentCode {
+        u32::from_le_bytes(*b"Schd")
+    }
+    fn length(&self) -> XousSize {
+        4
+    }
+    fn serialize(&self, output: &mut dyn io::Write) -> io::Result<usize> {
+        output.write(&self.policy.to_le_bytes())
+    }
+}
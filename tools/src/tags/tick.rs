@@ -0,0 +1,46 @@
+use crate::xous_arguments::{XousArgument, XousArgumentCode, XousSize};
+use std::fmt;
+use std::io;
+
+#[derive(Debug)]
+pub struct Tick {
+    /// Physical address of the timer the kernel uses for preemption
+    base: u32,
+
+    /// Interrupt number the timer raises
+    irq: u32,
+
+    /// Number of timer cycles between each tick
+    period: u32,
+}
+
+impl fmt::Display for Tick {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "    Tick: timer at {:08x} on IRQ {}, every {} cycles",
+            self.base, self.irq, self.period
+        )
+    }
+}
+
+impl Tick {
+    pub fn new(base: u32, irq: u32, period: u32) -> Tick {
+        Tick { base, irq, period }
+    }
+}
+
+impl XousArgument for Tick {
+    fn code(&self) -> XousArgumentCode {
+        u32::from_le_bytes(*b"Tick")
+    }
+    fn length(&self) -> XousSize {
+        12
+    }
+    fn serialize(&self, output: &mut dyn io::Write) -> io::Result<usize> {
+        let mut written = output.write(&self.base.to_le_bytes())?;
+        written += output.write(&self.irq.to_le_bytes())?;
+        written += output.write(&self.period.to_le_bytes())?;
+        Ok(written)
+    }
+}
@@ -3,14 +3,20 @@ use std::collections::HashMap;
 use std::convert::TryInto;
 use std::io::{Read, Write};
 use std::mem::size_of;
-use std::net::{IpAddr, Ipv4Addr, SocketAddr, TcpStream, ToSocketAddrs};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
 use std::thread_local;
 
-use crate::{Result, PID, TID};
+use crate::{Capabilities, MemoryAddress, Quotas, Result, PID, TID};
 
+pub mod compress;
 mod mem;
 pub use mem::*;
+pub mod program;
+pub mod shared;
+pub mod transport;
+use transport::{Address, Stream};
 
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub struct ProcessKey([u8; 16]);
@@ -25,12 +31,27 @@ pub struct ThreadInit {}
 
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub struct ProcessInit {
+    /// The key the new process connects with.  The kernel picks its own
+    /// for a process that it starts from `command`.
     pub key: ProcessKey,
+
+    /// The privileged operations the new process may perform
+    pub capabilities: Capabilities,
+
+    /// How many kernel objects the new process may hold
+    pub quotas: Quotas,
+
+    /// A command block describing a host program for the kernel to start as
+    /// the new process, or `None` if something else will start it.  See
+    /// `program` for the format.
+    pub command: Option<MemoryAddress>,
 }
 
 pub struct ProcessArgsAsThread<F: FnOnce()> {
     main: F,
     name: String,
+    capabilities: Capabilities,
+    quotas: Quotas,
 }
 
 impl<F> ProcessArgsAsThread<F>
@@ -41,15 +62,30 @@ where
         ProcessArgsAsThread {
             main,
             name: name.to_owned(),
+            capabilities: Capabilities::empty(),
+            quotas: Quotas::default(),
         }
     }
+
+    /// Grant the new process `capabilities`, which this process must hold.
+    pub fn capabilities(mut self, capabilities: Capabilities) -> ProcessArgsAsThread<F> {
+        self.capabilities = capabilities;
+        self
+    }
+
+    /// Limit the new process to `quotas`, or to this process' own quotas
+    /// if those are lower.
+    pub fn quotas(mut self, quotas: Quotas) -> ProcessArgsAsThread<F> {
+        self.quotas = quotas;
+        self
+    }
 }
 pub struct ProcessHandleAsThread(std::thread::JoinHandle<()>);
 
 /// If no connection exists, create a new connection to the server. This means
 /// our parent PID will be PID1. Otherwise, reuse the same connection.
 pub fn create_process_pre_as_thread<F>(
-    _args: &ProcessArgsAsThread<F>,
+    args: &ProcessArgsAsThread<F>,
 ) -> core::result::Result<ProcessInit, crate::Error>
 where
     F: FnOnce(),
@@ -63,6 +99,9 @@ where
         key: PROCESS_KEY
             .with(|pk| *pk.borrow())
             .unwrap_or_else(default_process_key),
+        capabilities: args.capabilities,
+        quotas: args.quotas,
+        command: None,
     })
 }
 
@@ -80,12 +119,12 @@ where
     let thread_main = std::thread::Builder::new()
         .name(args.name)
         .spawn(move || {
-            set_xous_address(server_address);
+            set_xous_address(server_address.clone());
             THREAD_ID.with(|tid| *tid.borrow_mut() = 1);
             PROCESS_ID.with(|p| *p.borrow_mut() = pid);
             XOUS_SERVER_CONNECTION.with(|xsc| {
                 let mut xsc = xsc.borrow_mut();
-                match xous_connect_impl(server_address, &init.key) {
+                match xous_connect_impl(&server_address, &init.key) {
                     Ok(a) => {
                         *xsc = Some(a);
                         Ok(())
@@ -94,7 +133,9 @@ where
                 }
             })?;
 
-            crate::create_thread(f)
+            let thread = crate::create_thread(f);
+            thread_finished();
+            thread
         })
         .map_err(|_| crate::Error::InternalError)?
         .join()
@@ -112,25 +153,53 @@ pub fn wait_process_as_thread(joiner: ProcessHandleAsThread) -> crate::SysCallRe
 }
 
 pub struct ProcessArgs {
-    command: String,
-    name: String,
+    /// The program to run, as a command block
+    command: Vec<u8>,
+    capabilities: Capabilities,
+    quotas: Quotas,
 }
 
 impl ProcessArgs {
+    /// Run `command` with the host's shell.
     pub fn new(name: &str, command: String) -> ProcessArgs {
+        ProcessArgs::from_program(&program::Program::shell(name, &command))
+    }
+
+    /// Run the host program at `path`, passing it `args`.
+    pub fn program(name: &str, path: &str, args: &[&str]) -> ProcessArgs {
+        ProcessArgs::from_program(&program::Program::new(name, path, args))
+    }
+
+    fn from_program(program: &program::Program) -> ProcessArgs {
         ProcessArgs {
-            command,
-            name: name.to_owned(),
+            command: program.encode(),
+            capabilities: Capabilities::empty(),
+            quotas: Quotas::default(),
         }
     }
+
+    /// Grant the new process `capabilities`, which this process must hold.
+    pub fn capabilities(mut self, capabilities: Capabilities) -> ProcessArgs {
+        self.capabilities = capabilities;
+        self
+    }
+
+    /// Limit the new process to `quotas`, or to this process' own quotas
+    /// if those are lower.
+    pub fn quotas(mut self, quotas: Quotas) -> ProcessArgs {
+        self.quotas = quotas;
+        self
+    }
 }
 
+/// A process the kernel started from a host program.  The kernel owns the
+/// program, and kills it if the process is terminated.
 #[derive(Debug)]
-pub struct ProcessHandle(std::process::Child);
+pub struct ProcessHandle(PID);
 
 /// If no connection exists, create a new connection to the server. This means
 /// our parent PID will be PID1. Otherwise, reuse the same connection.
-pub fn create_process_pre(_args: &ProcessArgs) -> core::result::Result<ProcessInit, crate::Error> {
+pub fn create_process_pre(args: &ProcessArgs) -> core::result::Result<ProcessInit, crate::Error> {
     ensure_connection()?;
 
     // Ensure there is a connection, because after this function returns
@@ -140,63 +209,67 @@ pub fn create_process_pre(_args: &ProcessArgs) -> core::result::Result<ProcessIn
         key: PROCESS_KEY
             .with(|pk| *pk.borrow())
             .unwrap_or_else(default_process_key),
+        capabilities: args.capabilities,
+        quotas: args.quotas,
+        command: MemoryAddress::new(args.command.as_ptr() as usize),
     })
 }
 
+/// The kernel has already started the program by the time `CreateProcess`
+/// returns, so there's nothing left to do.
 pub fn create_process_post(
-    args: ProcessArgs,
-    init: ProcessInit,
+    _args: ProcessArgs,
+    _init: ProcessInit,
     pid: PID,
 ) -> core::result::Result<ProcessHandle, crate::Error> {
-    use std::process::Command;
-    let server_env = format!("{}", xous_address());
-    let pid_env = format!("{}", pid);
-    let process_name_env = args.name.to_string();
-    let process_key_env = hex::encode(&init.key.0);
-    let (shell, args) = if cfg!(windows) {
-        ("cmd", ["/C", &args.command])
-    } else if cfg!(unix) {
-        ("sh", ["-c", &args.command])
-    } else {
-        panic!("unrecognized platform -- don't know how to shell out");
-    };
-
-    // println!("Launching process...");
-    Command::new(shell)
-        .args(&args)
-        .env("XOUS_SERVER", server_env)
-        .env("XOUS_PID", pid_env)
-        .env("XOUS_PROCESS_NAME", process_name_env)
-        .env("XOUS_PROCESS_KEY", process_key_env)
-        .spawn()
-        .map(ProcessHandle)
-        .map_err(|_| {
-            // eprintln!("couldn't start command: {}", e);
-            crate::Error::InternalError
-        })
+    Ok(ProcessHandle(pid))
 }
 
-pub fn wait_process(mut joiner: ProcessHandle) -> crate::SysCallResult {
-    joiner
-        .0
-        .wait()
-        .or(Err(crate::Error::InternalError))
-        .and_then(|e| {
-            if e.success() {
-                Ok(crate::Result::Ok)
-            } else {
-                Err(crate::Error::UnknownError)
-            }
-        })
+/// Wait for the process to be terminated, which happens when its program
+/// exits.  Only the kernel sees how the program exited.
+pub fn wait_process(joiner: ProcessHandle) -> crate::SysCallResult {
+    loop {
+        match crate::rsyscall(crate::SysCall::QueryScheduler(joiner.0, 1)) {
+            Err(crate::Error::ProcessNotFound) => return Ok(crate::Result::Ok),
+            Err(e) => return Err(e),
+            Ok(_) => std::thread::sleep(std::time::Duration::from_millis(50)),
+        }
+    }
 }
 
 pub struct WaitHandle<T>(std::thread::JoinHandle<T>);
 
+/// Sent in place of a syscall number once a thread has run to completion,
+/// so that the kernel doesn't wait for it to make another call.
+pub const THREAD_FINISHED: usize = usize::MAX;
+
+/// Tell the kernel that the current thread won't make any more syscalls.
+fn thread_finished() {
+    let tid = THREAD_ID.with(|tid| *tid.borrow());
+    XOUS_SERVER_CONNECTION.with(|xsc| {
+        if let Some(conn) = xsc.borrow().as_ref() {
+            let mut pkt = vec![];
+            pkt.extend_from_slice(&tid.to_le_bytes());
+            for word in &[THREAD_FINISHED, 0, 0, 0, 0, 0, 0, 0] {
+                pkt.extend_from_slice(&word.to_le_bytes());
+            }
+            conn.send.lock().unwrap().write_all(&pkt).ok();
+        }
+    });
+}
+
 #[derive(Clone)]
 struct ServerConnection {
-    send: Arc<Mutex<TcpStream>>,
-    recv: Arc<Mutex<TcpStream>>,
+    send: Arc<Mutex<Stream>>,
+    recv: Arc<Mutex<Stream>>,
     mailbox: Arc<Mutex<HashMap<TID, Result>>>,
+
+    /// Whether memory sent over this connection may be compressed
+    compress: bool,
+
+    /// Memory shared with the kernel, which `map_memory()` hands out pages
+    /// of so that they may be lent without being copied
+    shared: Option<Arc<shared::Arena>>,
 }
 
 pub fn thread_to_args(call: usize, _init: &ThreadInit) -> [usize; 8] {
@@ -210,9 +283,9 @@ pub fn process_to_args(call: usize, init: &ProcessInit) -> [usize; 8] {
         u32::from_le_bytes(init.key.0[4..8].try_into().unwrap()) as _,
         u32::from_le_bytes(init.key.0[8..12].try_into().unwrap()) as _,
         u32::from_le_bytes(init.key.0[12..16].try_into().unwrap()) as _,
-        0,
-        0,
-        0,
+        init.capabilities.bits(),
+        init.quotas.to_usize(),
+        init.command.map(|command| command.get()).unwrap_or(0),
     ]
 }
 
@@ -233,9 +306,9 @@ pub fn args_to_process(
     a2: usize,
     a3: usize,
     a4: usize,
-    _a5: usize,
-    _a6: usize,
-    _a7: usize,
+    a5: usize,
+    a6: usize,
+    a7: usize,
 ) -> core::result::Result<ProcessInit, crate::Error> {
     let mut v = vec![];
     v.extend_from_slice(&(a1 as u32).to_le_bytes());
@@ -246,24 +319,24 @@ pub fn args_to_process(
     key.copy_from_slice(&v);
     Ok(ProcessInit {
         key: ProcessKey(key),
+        capabilities: Capabilities::from_bits_truncate(a5),
+        quotas: Quotas::from_usize(a6),
+        command: MemoryAddress::new(a7),
     })
 }
 
-thread_local!(static NETWORK_CONNECT_ADDRESS: RefCell<Option<SocketAddr>> = RefCell::new(None));
+thread_local!(static NETWORK_CONNECT_ADDRESS: RefCell<Option<Address>> = RefCell::new(None));
 thread_local!(static XOUS_SERVER_CONNECTION: RefCell<Option<ServerConnection>> = RefCell::new(None));
 thread_local!(static THREAD_ID: RefCell<TID> = RefCell::new(1));
 thread_local!(static PROCESS_ID: RefCell<PID> = RefCell::new(PID::new(1).unwrap()));
 thread_local!(static PROCESS_KEY: RefCell<Option<ProcessKey>> = RefCell::new(None));
 
-fn default_xous_address() -> SocketAddr {
+fn default_xous_address() -> Address {
     std::env::var("XOUS_SERVER")
-        .map(|s| {
-            s.to_socket_addrs()
-                .expect("invalid server address")
-                .next()
-                .expect("unable to resolve server address")
+        .map(|s| Address::parse(&s).expect("invalid server address"))
+        .unwrap_or_else(|_| {
+            Address::Tcp(SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 0))
         })
-        .unwrap_or_else(|_| SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 0))
 }
 
 fn default_process_key() -> ProcessKey {
@@ -276,12 +349,17 @@ fn default_process_key() -> ProcessKey {
         .unwrap_or(ProcessKey([0u8; 16]))
 }
 
+/// The memory this thread's process shares with the kernel, if it has any.
+fn shared_arena() -> Option<Arc<shared::Arena>> {
+    XOUS_SERVER_CONNECTION.with(|xsc| xsc.borrow().as_ref()?.shared.clone())
+}
+
 pub fn set_process_key(new_key: &[u8; 16]) {
     PROCESS_KEY.with(|pk| *pk.borrow_mut() = Some(ProcessKey(*new_key)));
 }
 
 /// Set the network address for this particular thread.
-pub fn set_xous_address(new_address: SocketAddr) {
+pub fn set_xous_address(new_address: Address) {
     NETWORK_CONNECT_ADDRESS.with(|nca| {
         let mut address = nca.borrow_mut();
         *address = Some(new_address);
@@ -289,10 +367,10 @@ pub fn set_xous_address(new_address: SocketAddr) {
     });
 }
 
-/// Set the network address for this particular thread.
-fn xous_address() -> SocketAddr {
+/// The network address this thread connects to the kernel on.
+pub fn xous_address() -> Address {
     NETWORK_CONNECT_ADDRESS
-        .with(|nca| *nca.borrow())
+        .with(|nca| nca.borrow().clone())
         .unwrap_or_else(default_xous_address)
 }
 
@@ -347,7 +425,9 @@ where
             THREAD_ID.with(|tid| *tid.borrow_mut() = thread_id);
             PROCESS_ID.with(|pid| *pid.borrow_mut() = process_id);
             XOUS_SERVER_CONNECTION.with(|xsc| *xsc.borrow_mut() = Some(server_connection));
-            f()
+            let result = f();
+            thread_finished();
+            result
         })
         .map(WaitHandle)
         .map_err(|_| crate::Error::InternalError)?)
@@ -366,11 +446,11 @@ pub fn ensure_connection() -> core::result::Result<(), crate::Error> {
         let mut xsc = xsc.borrow_mut();
         if xsc.is_none() {
             NETWORK_CONNECT_ADDRESS.with(|nca| {
-                let addr = nca.borrow().unwrap_or_else(default_xous_address);
+                let addr = nca.borrow().clone().unwrap_or_else(default_xous_address);
                 let pid1_key = PROCESS_KEY
                     .with(|pk| *pk.borrow())
                     .unwrap_or_else(default_process_key);
-                match xous_connect_impl(addr, &pid1_key) {
+                match xous_connect_impl(&addr, &pid1_key) {
                     Ok(a) => {
                         *xsc = Some(a);
                         Ok(())
@@ -385,18 +465,53 @@ pub fn ensure_connection() -> core::result::Result<(), crate::Error> {
 }
 
 fn xous_connect_impl(
-    addr: SocketAddr,
+    addr: &Address,
     key: &ProcessKey,
 ) -> core::result::Result<ServerConnection, ()> {
     // eprintln!("Opening connection to Xous server @ {} with key {:?}...", addr, key);
     assert_ne!(&key.0, &[0u8; 16]);
-    match TcpStream::connect(addr) {
+    match Stream::connect(addr) {
         Ok(mut conn) => {
             conn.write_all(&key.0).unwrap(); // Send key to authenticate us as PID 1
+
+            // A kernel on the same machine may be able to map memory that's
+            // shared with it, so offer some.
+            let shared_memory = if addr.is_local() {
+                shared::SharedMemory::create(shared::SHARED_MEMORY_SIZE).ok()
+            } else {
+                None
+            };
+
+            // Offer what this process supports, and learn which of those the
+            // kernel agreed to.  The kernel hangs up instead if it doesn't
+            // recognise the key, or if its words aren't the same size.
+            let mut offered = compress::capabilities() | transport::word_size_capability();
+            if let Some((memory, path)) = shared_memory.as_ref() {
+                offered |= shared::CAP_SHARED_MEMORY;
+                conn.write_all(&offered.to_le_bytes()).unwrap();
+                shared::write_descriptor(&mut conn, path, memory.region()).unwrap();
+            } else {
+                conn.write_all(&offered.to_le_bytes()).unwrap();
+            }
+            let mut agreed = [0u8; 4];
+            let answer = conn.read_exact(&mut agreed);
+
+            // By now the kernel has mapped the shared memory if it's going to,
+            // so nothing else needs to find it.
+            if let Some((_, path)) = shared_memory.as_ref() {
+                std::fs::remove_file(path).ok();
+            }
+            answer.map_err(|_| ())?;
+            let agreed = u32::from_le_bytes(agreed);
+
             Ok(ServerConnection {
                 send: Arc::new(Mutex::new(conn.try_clone().unwrap())),
                 recv: Arc::new(Mutex::new(conn)),
                 mailbox: Arc::new(Mutex::new(HashMap::new())),
+                compress: agreed & compress::CAP_COMPRESSION != 0,
+                shared: shared_memory
+                    .filter(|_| agreed & shared::CAP_SHARED_MEMORY != 0)
+                    .map(|(memory, _)| Arc::new(shared::Arena::new(memory))),
             })
         }
         Err(_e) => {
@@ -439,6 +554,8 @@ pub fn _xous_syscall(
                 a7,
                 &call,
                 &mut xsc_asmut.send.lock().unwrap(),
+                xsc_asmut.compress,
+                xsc_asmut.shared.as_ref().map(|arena| arena.region()),
             );
             _xous_syscall_result(&call, ret, *tid.borrow(), xsc_asmut);
         })
@@ -456,10 +573,8 @@ fn _xous_syscall_result(
     {
         let mut mailbox = server_connection.mailbox.lock().unwrap();
         if let Some(entry) = mailbox.remove(&thread_id) {
-            if Result::BlockedProcess != entry {
-                *ret = entry;
-                return;
-            }
+            *ret = entry;
+            return;
         }
     }
 
@@ -471,10 +586,8 @@ fn _xous_syscall_result(
         {
             let mut mailbox = server_connection.mailbox.lock().unwrap();
             if let Some(entry) = mailbox.remove(&thread_id) {
-                if Result::BlockedProcess != entry {
-                    *ret = entry;
-                    return;
-                }
+                *ret = entry;
+                return;
             }
         }
 
@@ -491,17 +604,17 @@ fn _xous_syscall_result(
         {
             let mut mailbox = server_connection.mailbox.lock().unwrap();
             if let Some(entry) = mailbox.remove(&thread_id) {
-                if Result::BlockedProcess != entry {
-                    *ret = entry;
-                    return;
-                }
+                *ret = entry;
+                return;
             }
         }
 
         // This thread_id doesn't exist in the mailbox, so read additional data.
         let mut pkt = [0usize; 8];
         let mut raw_bytes = [0u8; size_of::<usize>() * 9];
-        stream.read_exact(&mut raw_bytes).expect("Server shut down");
+        stream
+            .read_exact(&mut raw_bytes)
+            .unwrap_or_else(|e| server_shut_down(e));
 
         let mut raw_bytes_chunks = raw_bytes.chunks(size_of::<usize>());
 
@@ -516,45 +629,73 @@ fn _xous_syscall_result(
         let response = Result::from_args(pkt);
 
         // println!("   Response: {:?}", response);
-        if Result::BlockedProcess == response {
-            // println!("   Waiting again");
-            continue;
-        }
+        // A server works on shared memory in place, so there's nothing to
+        // copy back.
+        let shared = server_connection.shared.as_ref();
+        let in_place = shared
+            .map(|arena| arena.region().lends(call))
+            .unwrap_or(false);
         match &call {
-            crate::SysCall::SendMessage(_, msg) | crate::SysCall::TrySendMessage(_, msg) => {
+            _ if in_place => (),
+            crate::SysCall::SendMessage(_, msg)
+            | crate::SysCall::TrySendMessage(_, msg)
+            | crate::SysCall::CallMessage(_, msg) => {
                 match msg {
-                    crate::Message::MutableBorrow(crate::MemoryMessage {
-                        id: _id,
-                        buf,
-                        offset: _offset,
-                        valid: _valid,
-                    }) => {
+                    crate::Message::MutableBorrow(
+                        msg @ crate::MemoryMessage {
+                            id: _id,
+                            buf,
+                            offset: _offset,
+                            valid: _valid,
+                        },
+                    ) => {
                         // Read the buffer back from the remote host.
                         use core::slice;
-                        let mut data = unsafe {
-                            slice::from_raw_parts_mut(buf.addr.get() as _, buf.size.get())
-                        };
-                        stream.read_exact(&mut data).expect("Server shut down");
+                        if let Some(scatter) = scatter_list(msg) {
+                            let mut data = vec![0u8; buf.len()];
+                            compress::read_payload(
+                                &mut *stream,
+                                &mut data,
+                                server_connection.compress,
+                            )
+                            .unwrap_or_else(|e| server_shut_down(e));
+                            scatter_into(&scatter, &data);
+                        } else {
+                            let data = unsafe {
+                                slice::from_raw_parts_mut(buf.addr.get() as _, buf.size.get())
+                            };
+                            compress::read_payload(&mut *stream, data, server_connection.compress)
+                                .unwrap_or_else(|e| server_shut_down(e));
+                        }
                         // pkt.extend_from_slice(data);
                     }
 
-                    crate::Message::Borrow(crate::MemoryMessage {
-                        id: _id,
-                        buf,
-                        offset: _offset,
-                        valid: _valid,
-                    }) => {
+                    crate::Message::Borrow(
+                        msg @ crate::MemoryMessage {
+                            id: _id,
+                            buf,
+                            offset: _offset,
+                            valid: _valid,
+                        },
+                    ) => {
                         // Read the buffer back from the remote host and ensure it's the same
                         use core::slice;
                         let mut check_data = Vec::new();
                         check_data.resize(buf.len(), 0);
-                        let data =
-                            unsafe { slice::from_raw_parts_mut(buf.as_mut_ptr(), buf.len()) };
-                        stream
-                            .read_exact(&mut check_data)
-                            .expect("Server shut down");
-
-                        assert_eq!(data, check_data.as_slice());
+                        compress::read_payload(
+                            &mut *stream,
+                            &mut check_data,
+                            server_connection.compress,
+                        )
+                        .unwrap_or_else(|e| server_shut_down(e));
+
+                        if let Some(scatter) = scatter_list(msg) {
+                            assert_eq!(gather(&scatter, buf.len()), check_data);
+                        } else {
+                            let data =
+                                unsafe { slice::from_raw_parts_mut(buf.as_mut_ptr(), buf.len()) };
+                            assert_eq!(data, check_data.as_slice());
+                        }
                     }
 
                     crate::Message::Move(crate::MemoryMessage {
@@ -565,8 +706,11 @@ fn _xous_syscall_result(
                     }) => {
                         // In a hosted environment, the message contents are leaked when
                         // it gets converted into a MemoryMessage. Now that the call is
-                        // complete, free the memory.
-                        mem::unmap_memory_post(*buf).unwrap();
+                        // complete, free the memory.  If the move was refused, such as
+                        // by `CallMessage`, the memory is still ours.
+                        if !matches!(response, Result::Error(_)) {
+                            mem::free_memory(*buf, shared.map(|arena| arena.as_ref()));
+                        }
                     }
                     // Nothing to do for Immutable borrow, since the memory can't change
                     crate::Message::Scalar(_) | crate::Message::BlockingScalar(_) => (),
@@ -604,7 +748,9 @@ fn _xous_syscall_to(
     a6: usize,
     a7: usize,
     call: &crate::SysCall,
-    xsc: &mut TcpStream,
+    xsc: &mut Stream,
+    compress: bool,
+    shared: Option<shared::Region>,
 ) {
     // println!(
     //     "Making Syscall: {:?}",
@@ -618,21 +764,149 @@ fn _xous_syscall_to(
         pkt.extend_from_slice(&word.to_le_bytes());
     }
     match call {
-        crate::SysCall::SendMessage(_, ref msg) | crate::SysCall::TrySendMessage(_, ref msg) => {
-            match msg {
-                crate::Message::MutableBorrow(m)
-                | crate::Message::Borrow(m)
-                | crate::Message::Move(m) => {
-                    use core::slice;
+        // Memory lent from the shared region is already where the kernel
+        // can see it, so only its address is sent.
+        _ if shared.map(|region| region.lends(call)).unwrap_or(false) => (),
+        crate::SysCall::SendMessage(_, ref msg)
+        | crate::SysCall::TrySendMessage(_, ref msg)
+        | crate::SysCall::CallMessage(_, ref msg) => match msg {
+            crate::Message::MutableBorrow(m)
+            | crate::Message::Borrow(m)
+            | crate::Message::Move(m) => {
+                use core::slice;
+                if let Some(scatter) = scatter_list(m) {
+                    compress::write_payload(&mut pkt, &gather(&scatter, m.buf.len()), compress);
+                } else {
                     let data: &[u8] =
                         unsafe { slice::from_raw_parts(m.buf.addr.get() as _, m.buf.size.get()) };
-                    pkt.extend_from_slice(data);
+                    compress::write_payload(&mut pkt, data, compress);
                 }
-                crate::Message::Scalar(_) | crate::Message::BlockingScalar(_) => (),
             }
-        }
+            crate::Message::Scalar(_) | crate::Message::BlockingScalar(_) => (),
+        },
+        // The kernel needs its own copy of the program to start.
+        crate::SysCall::CreateProcess(crate::ProcessInit {
+            command: Some(command),
+            ..
+        }) => pkt.extend_from_slice(unsafe { program::block(*command) }),
         _ => (),
     }
 
-    xsc.write_all(&pkt).expect("Server shut down");
+    xsc.write_all(&pkt).unwrap_or_else(|e| server_shut_down(e));
+}
+
+/// The pieces of a scattered memory message, copied out of the sender's
+/// list.  The kernel only ever sees the gathered buffer.
+fn scatter_list(msg: &crate::MemoryMessage) -> Option<crate::ScatterList> {
+    msg.scatter_list().map(|list| unsafe { *list })
+}
+
+/// Copy the pieces of a scattered message into one buffer of `len` bytes,
+/// which is how the kernel expects to receive it.
+fn gather(scatter: &crate::ScatterList, len: usize) -> Vec<u8> {
+    let mut data = Vec::with_capacity(len);
+    for range in scatter.iter() {
+        data.extend_from_slice(unsafe { core::slice::from_raw_parts(range.as_ptr(), range.len()) });
+    }
+    data.resize(len, 0);
+    data
+}
+
+/// Copy a buffer that came back from the kernel out into the pieces of a
+/// scattered message.
+fn scatter_into(scatter: &crate::ScatterList, data: &[u8]) {
+    let mut remaining = data;
+    for range in scatter.iter() {
+        let len = range.len().min(remaining.len());
+        unsafe { core::slice::from_raw_parts_mut(range.as_mut_ptr(), len) }
+            .copy_from_slice(&remaining[..len]);
+        remaining = &remaining[len..];
+    }
+}
+
+/// Set once the host has asked this process to stop.
+static SHUTDOWN_SIGNALLED: AtomicBool = AtomicBool::new(false);
+
+/// Set when this is a real process rather than a thread standing in for
+/// one, as the kernel tests do.
+static STANDALONE: AtomicBool = AtomicBool::new(false);
+
+/// The kernel closed our connection, which it does when it shuts down.  A
+/// standalone process simply exits, but a thread standing in for a process
+/// can't exit without taking everything else down with it.
+fn server_shut_down(e: std::io::Error) -> ! {
+    if STANDALONE.load(Ordering::SeqCst) {
+        std::process::exit(0);
+    }
+    panic!("Server shut down: {}", e);
+}
+
+/// Catch SIGINT and SIGTERM so that they set a flag rather than killing the
+/// process, letting it shut Xous down in an orderly way instead.  Poll
+/// `shutdown_signalled()` to find out when one has arrived.
+#[cfg(unix)]
+pub fn catch_shutdown_signals() {
+    const SIGINT: i32 = 2;
+    const SIGTERM: i32 = 15;
+    extern "C" {
+        fn signal(signum: i32, handler: extern "C" fn(i32)) -> usize;
+    }
+    extern "C" fn handler(_signum: i32) {
+        SHUTDOWN_SIGNALLED.store(true, Ordering::SeqCst);
+    }
+    unsafe {
+        signal(SIGINT, handler);
+        signal(SIGTERM, handler);
+    }
+}
+
+/// Catch Ctrl-C and console close events so that they set a flag rather
+/// than killing the process.  Poll `shutdown_signalled()` to find out when
+/// one has arrived.
+#[cfg(windows)]
+pub fn catch_shutdown_signals() {
+    extern "system" {
+        fn SetConsoleCtrlHandler(handler: extern "system" fn(u32) -> i32, add: i32) -> i32;
+    }
+    extern "system" fn handler(_ctrl_type: u32) -> i32 {
+        SHUTDOWN_SIGNALLED.store(true, Ordering::SeqCst);
+        1
+    }
+    unsafe {
+        SetConsoleCtrlHandler(handler, 1);
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+pub fn catch_shutdown_signals() {}
+
+/// Whether a signal caught by `catch_shutdown_signals()` has arrived.
+pub fn shutdown_signalled() -> bool {
+    SHUTDOWN_SIGNALLED.load(Ordering::SeqCst)
+}
+
+/// Turn SIGINT and SIGTERM into a `Shutdown` syscall, so that stopping any
+/// hosted process takes the whole system down the same way the syscall
+/// does rather than leaving the kernel to find a dropped socket.  This is
+/// called when a standalone process starts, which also makes it exit
+/// quietly once the kernel closes its connection.
+pub fn forward_shutdown_signals() {
+    fn wait_for_signal(_arg: usize) {
+        while !shutdown_signalled() {
+            std::thread::sleep(std::time::Duration::from_millis(100));
+        }
+        crate::rsyscall(crate::SysCall::Shutdown(crate::ScrubLevel::None)).ok();
+    }
+    STANDALONE.store(true, Ordering::SeqCst);
+    catch_shutdown_signals();
+    crate::create_thread_simple(wait_for_signal, 0).expect("couldn't start signal thread");
+}
+
+/// Get the number of nanoseconds since the Unix epoch.  This is used rather
+/// than `Instant` so that timestamps may be compared between processes.
+pub fn timestamp() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or_default()
 }
@@ -0,0 +1,92 @@
+//! Compression of the memory that hosted processes and the kernel send each
+//! other along with memory messages.  Both sides say what they support when a
+//! process connects, and payloads are only compressed if both sides support
+//! it.  Once compression is on, every payload starts with a word giving its
+//! compressed length, or `0` if it was sent as-is because it was too small or
+//! didn't compress.  The uncompressed length is always known from the
+//! message itself.
+
+use std::io::Read;
+use std::mem::size_of;
+
+/// Set in the capabilities of a connection when LZ4 compression is supported
+pub const CAP_COMPRESSION: u32 = 1;
+
+/// Payloads smaller than this are always sent as-is
+pub const COMPRESSION_THRESHOLD: usize = 16 * 1024;
+
+/// The capabilities this build supports, which are sent by a process when
+/// it connects and answered by the kernel with the ones it also supports.
+pub fn capabilities() -> u32 {
+    if cfg!(feature = "hosted-compression") {
+        CAP_COMPRESSION
+    } else {
+        0
+    }
+}
+
+/// Append `data` to `pkt`, compressing it if `compress` was negotiated and
+/// it's worth doing.
+pub fn write_payload(pkt: &mut Vec<u8>, data: &[u8], compress: bool) {
+    if !compress {
+        pkt.extend_from_slice(data);
+        return;
+    }
+
+    #[cfg(feature = "hosted-compression")]
+    {
+        if data.len() >= COMPRESSION_THRESHOLD {
+            let compressed = lz4_flex::block::compress(data);
+            if compressed.len() < data.len() {
+                pkt.extend_from_slice(&compressed.len().to_le_bytes());
+                pkt.extend_from_slice(&compressed);
+                return;
+            }
+        }
+    }
+
+    pkt.extend_from_slice(&0usize.to_le_bytes());
+    pkt.extend_from_slice(data);
+}
+
+/// Read a payload written by `write_payload()` into `buf`, which must be
+/// exactly as large as the uncompressed payload.
+pub fn read_payload<R: Read>(
+    stream: &mut R,
+    buf: &mut [u8],
+    compress: bool,
+) -> std::io::Result<()> {
+    if !compress {
+        return stream.read_exact(buf);
+    }
+
+    let mut len_bytes = [0u8; size_of::<usize>()];
+    stream.read_exact(&mut len_bytes)?;
+    let compressed_len = usize::from_le_bytes(len_bytes);
+    if compressed_len == 0 {
+        return stream.read_exact(buf);
+    }
+
+    let mut compressed = vec![0u8; compressed_len];
+    stream.read_exact(&mut compressed)?;
+    decompress(&compressed, buf)
+}
+
+#[cfg(feature = "hosted-compression")]
+fn decompress(compressed: &[u8], buf: &mut [u8]) -> std::io::Result<()> {
+    match lz4_flex::block::decompress_into(compressed, buf) {
+        Ok(len) if len == buf.len() => Ok(()),
+        _ => Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "corrupt compressed payload",
+        )),
+    }
+}
+
+#[cfg(not(feature = "hosted-compression"))]
+fn decompress(_compressed: &[u8], _buf: &mut [u8]) -> std::io::Result<()> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::InvalidData,
+        "compressed payload received without compression support",
+    ))
+}
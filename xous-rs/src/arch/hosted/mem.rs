@@ -1,3 +1,4 @@
+use super::shared::Arena;
 use crate::{Error, MemoryAddress, MemoryFlags, MemoryRange};
 
 extern crate alloc;
@@ -19,9 +20,14 @@ pub fn map_memory_post(
     _flags: MemoryFlags,
     mut range: MemoryRange,
 ) -> core::result::Result<MemoryRange, Error> {
-    let layout = Layout::from_size_align(range.len(), 4096).unwrap();
-    let new_mem = MemoryAddress::new(unsafe { alloc(layout) } as usize).ok_or(Error::BadAddress)?;
-    range.addr = new_mem;
+    // Pages shared with the kernel may be lent without being copied, so
+    // they're used whenever there are enough of them free.
+    let shared = super::shared_arena().and_then(|arena| arena.alloc(range.len()));
+    let new_mem = shared.unwrap_or_else(|| {
+        let layout = Layout::from_size_align(range.len(), 4096).unwrap();
+        unsafe { alloc(layout) }
+    });
+    range.addr = MemoryAddress::new(new_mem as usize).ok_or(Error::BadAddress)?;
     Ok(range)
 }
 
@@ -34,8 +40,20 @@ pub fn unmap_memory_pre(
 pub fn unmap_memory_post(
     range: MemoryRange
 ) -> core::result::Result<(), Error> {
-    let layout = Layout::from_size_align(range.len(), 4096).unwrap();
+    free_memory(range, super::shared_arena().as_deref());
+    Ok(())
+}
+
+/// Free memory handed out by `map_memory_post()`, which came from `arena` if
+/// it lies within it.
+pub(super) fn free_memory(range: MemoryRange, arena: Option<&Arena>) {
     let ptr = range.as_mut_ptr();
+    if arena
+        .map(|arena| arena.free(ptr, range.len()))
+        .unwrap_or(false)
+    {
+        return;
+    }
+    let layout = Layout::from_size_align(range.len(), 4096).unwrap();
     unsafe { dealloc(ptr, layout) };
-    Ok(())
 }
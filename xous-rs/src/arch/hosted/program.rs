@@ -0,0 +1,86 @@
+//! Host programs that the kernel starts as new processes.
+//!
+//! A process asks for one by making `CreateProcess` with a command in its
+//! `ProcessInit`.  The command is a block of bytes: a little-endian `u32`
+//! giving the length of the rest, followed by the process name, the path to
+//! the program and each of its arguments, every one ending in a NUL.  The
+//! block is sent along with the syscall, the same as a lend, so the kernel
+//! has a copy of its own at the address it finds in `ProcessInit`.
+
+use crate::MemoryAddress;
+
+/// A program to run on the host, and what to call the process it becomes
+#[derive(Clone, Debug, PartialEq)]
+pub struct Program {
+    pub name: String,
+    pub path: String,
+    pub args: Vec<String>,
+}
+
+impl Program {
+    pub fn new(name: &str, path: &str, args: &[&str]) -> Program {
+        Program {
+            name: name.to_owned(),
+            path: path.to_owned(),
+            args: args.iter().map(|arg| (*arg).to_owned()).collect(),
+        }
+    }
+
+    /// Run `command` with the host's shell.
+    pub fn shell(name: &str, command: &str) -> Program {
+        if cfg!(windows) {
+            Program::new(name, "cmd", &["/C", command])
+        } else {
+            Program::new(name, "sh", &["-c", command])
+        }
+    }
+
+    /// Turn this program into a command block.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut strings = vec![];
+        let program = [&self.name, &self.path];
+        for s in program.iter().copied().chain(&self.args) {
+            strings.extend_from_slice(s.as_bytes());
+            strings.push(0);
+        }
+        let mut block = (strings.len() as u32).to_le_bytes().to_vec();
+        block.extend_from_slice(&strings);
+        block
+    }
+
+    /// Read back what `encode()` made, or `None` if `block` isn't a whole
+    /// command.
+    pub fn decode(block: &[u8]) -> Option<Program> {
+        let strings = block.get(4..)?;
+        if strings.len() != block_len(block)? - 4 || strings.last() != Some(&0) {
+            return None;
+        }
+        let mut strings = strings[..strings.len() - 1]
+            .split(|&b| b == 0)
+            .map(|s| String::from_utf8_lossy(s).into_owned());
+        Some(Program {
+            name: strings.next()?,
+            path: strings.next()?,
+            args: strings.collect(),
+        })
+    }
+}
+
+/// The length of the command block that starts with `header`, including
+/// the length itself, or `None` if there aren't enough bytes to tell.
+pub fn block_len(header: &[u8]) -> Option<usize> {
+    let mut len = [0u8; 4];
+    len.copy_from_slice(header.get(..4)?);
+    Some(u32::from_le_bytes(len) as usize + 4)
+}
+
+/// The whole of the command block at `addr`.
+///
+/// # Safety
+///
+/// `addr` must point to a command block made by `Program::encode()`, which
+/// must outlive the slice.
+pub unsafe fn block<'a>(addr: MemoryAddress) -> &'a [u8] {
+    let header = core::slice::from_raw_parts(addr.get() as *const u8, 4);
+    core::slice::from_raw_parts(addr.get() as *const u8, block_len(header).unwrap())
+}
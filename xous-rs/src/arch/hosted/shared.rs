@@ -0,0 +1,302 @@
+//! Memory that a hosted process shares with the kernel, so that lending a
+//! buffer doesn't mean copying it over the connection and back again.
+//!
+//! A process on the same machine as the kernel creates a file, maps it, and
+//! offers it to the kernel when it connects.  If the kernel is able to map
+//! the same file, `map_memory()` hands out pages from it in place of the
+//! heap.  A lend from those pages is then sent as just an address, which
+//! the kernel turns into the same place in its own mapping, and whatever a
+//! server writes there is already in the lender's memory when the lend is
+//! returned.  Moves are still copied, since the receiver frees moved memory
+//! with its own allocator.
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use crate::{Message, SysCall};
+
+/// Set in the capabilities of a connection when the process has memory to
+/// share with the kernel.  The process follows its capabilities with where
+/// to find that memory.
+pub const CAP_SHARED_MEMORY: u32 = 4;
+
+/// How much memory each process shares with the kernel.  The file is
+/// sparse, so a page takes up nothing until it's used.
+pub const SHARED_MEMORY_SIZE: usize = 64 * 1024 * 1024;
+
+const PAGE_SIZE: usize = 4096;
+
+/// Whether this build is able to share memory with the other side.
+pub fn capabilities() -> u32 {
+    if cfg!(unix) {
+        CAP_SHARED_MEMORY
+    } else {
+        0
+    }
+}
+
+/// Where a process's shared memory sits in its own address space
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Region {
+    pub base: usize,
+    pub len: usize,
+}
+
+impl Region {
+    /// Whether `call` lends memory that lies entirely within this region,
+    /// and so is sent as an address rather than a copy.  Scattered messages
+    /// are always gathered into a copy.
+    pub fn lends(&self, call: &SysCall) -> bool {
+        let msg = match call {
+            SysCall::SendMessage(_, msg)
+            | SysCall::TrySendMessage(_, msg)
+            | SysCall::CallMessage(_, msg) => match msg {
+                Message::MutableBorrow(msg) | Message::Borrow(msg) => msg,
+                _ => return false,
+            },
+            _ => return false,
+        };
+        let offset = msg.buf.as_ptr() as usize;
+        msg.scatter_list().is_none()
+            && offset >= self.base
+            && offset - self.base <= self.len
+            && msg.buf.len() <= self.len - (offset - self.base)
+    }
+}
+
+/// A file that's mapped into memory, and shared with whatever else maps it
+#[derive(Debug)]
+pub struct SharedMemory {
+    base: *mut u8,
+    len: usize,
+}
+
+// The memory is only ever handed out a page at a time, and the side that
+// holds a page is the only one using it.
+unsafe impl Send for SharedMemory {}
+unsafe impl Sync for SharedMemory {}
+
+impl SharedMemory {
+    /// Create `len` bytes of shared memory, returning it along with the
+    /// path that the other side opens it by.
+    pub fn create(len: usize) -> io::Result<(SharedMemory, PathBuf)> {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "xous-shared-{}-{}",
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        ));
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)?;
+        let memory = file
+            .set_len(len as u64)
+            .and_then(|_| map(&file, len))
+            .map(|base| SharedMemory { base, len });
+        if memory.is_err() {
+            std::fs::remove_file(&path).ok();
+        }
+        Ok((memory?, path))
+    }
+
+    /// Map the shared memory that was created at `path`, which must be
+    /// `len` bytes long.
+    pub fn open(path: &Path, len: usize) -> io::Result<SharedMemory> {
+        let file = OpenOptions::new().read(true).write(true).open(path)?;
+        if file.metadata()?.len() != len as u64 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "shared memory is the wrong size",
+            ));
+        }
+        Ok(SharedMemory {
+            base: map(&file, len)?,
+            len,
+        })
+    }
+
+    /// Where this memory sits in the current address space
+    pub fn region(&self) -> Region {
+        Region {
+            base: self.base as usize,
+            len: self.len,
+        }
+    }
+
+    /// Whether `len` bytes at `addr` lie within this memory.
+    pub fn contains(&self, addr: *const u8, len: usize) -> bool {
+        let region = self.region();
+        let addr = addr as usize;
+        addr >= region.base
+            && addr - region.base <= region.len
+            && len <= region.len - (addr - region.base)
+    }
+}
+
+impl Drop for SharedMemory {
+    fn drop(&mut self) {
+        unsafe { unmap(self.base, self.len) };
+    }
+}
+
+/// Tell the other side where to find shared memory that was created at
+/// `path`, and where it's mapped in this process.
+pub fn write_descriptor<W: Write>(w: &mut W, path: &Path, region: Region) -> io::Result<()> {
+    let path = path.to_string_lossy();
+    let mut descriptor = vec![];
+    descriptor.extend_from_slice(&(path.len() as u32).to_le_bytes());
+    descriptor.extend_from_slice(path.as_bytes());
+    descriptor.extend_from_slice(&(region.base as u64).to_le_bytes());
+    descriptor.extend_from_slice(&(region.len as u64).to_le_bytes());
+    w.write_all(&descriptor)
+}
+
+/// Read what `write_descriptor()` sent.
+pub fn read_descriptor<R: Read>(r: &mut R) -> io::Result<(PathBuf, Region)> {
+    let mut len = [0u8; 4];
+    r.read_exact(&mut len)?;
+    let mut path = vec![0u8; u32::from_le_bytes(len) as usize];
+    r.read_exact(&mut path)?;
+    let mut words = [0u8; 16];
+    r.read_exact(&mut words)?;
+    let mut base = [0u8; 8];
+    let mut len = [0u8; 8];
+    base.copy_from_slice(&words[..8]);
+    len.copy_from_slice(&words[8..]);
+    Ok((
+        PathBuf::from(String::from_utf8_lossy(&path).into_owned()),
+        Region {
+            base: u64::from_le_bytes(base) as usize,
+            len: u64::from_le_bytes(len) as usize,
+        },
+    ))
+}
+
+/// Shared memory that a process hands out pages of in place of the heap
+#[derive(Debug)]
+pub struct Arena {
+    memory: SharedMemory,
+
+    /// Whether each page has been handed out
+    used: Mutex<Vec<bool>>,
+}
+
+impl Arena {
+    pub fn new(memory: SharedMemory) -> Arena {
+        let pages = memory.len / PAGE_SIZE;
+        Arena {
+            memory,
+            used: Mutex::new(vec![false; pages]),
+        }
+    }
+
+    pub fn region(&self) -> Region {
+        self.memory.region()
+    }
+
+    /// Take enough pages for `len` bytes, or `None` if there isn't a run of
+    /// free pages that long.
+    pub fn alloc(&self, len: usize) -> Option<*mut u8> {
+        let count = page_count(len);
+        let mut used = self.used.lock().unwrap();
+        let mut start = 0;
+        while start + count <= used.len() {
+            match used[start..start + count].iter().rposition(|&u| u) {
+                Some(taken) => start += taken + 1,
+                None => {
+                    used[start..start + count]
+                        .iter_mut()
+                        .for_each(|u| *u = true);
+                    return Some(unsafe { self.memory.base.add(start * PAGE_SIZE) });
+                }
+            }
+        }
+        None
+    }
+
+    /// Give back the pages for `len` bytes at `addr`, returning `false` if
+    /// they didn't come from this arena.
+    pub fn free(&self, addr: *mut u8, len: usize) -> bool {
+        if !self.memory.contains(addr, len) {
+            return false;
+        }
+        let start = (addr as usize - self.memory.base as usize) / PAGE_SIZE;
+        let count = page_count(len);
+        let mut used = self.used.lock().unwrap();
+        used[start..start + count]
+            .iter_mut()
+            .for_each(|u| *u = false);
+        true
+    }
+}
+
+/// The number of pages needed to hold `len` bytes, which is at least one
+fn page_count(len: usize) -> usize {
+    (len.saturating_add(PAGE_SIZE - 1) / PAGE_SIZE).max(1)
+}
+
+#[cfg(unix)]
+mod sys {
+    pub const PROT_READ: i32 = 1;
+    pub const PROT_WRITE: i32 = 2;
+    pub const MAP_SHARED: i32 = 1;
+
+    extern "C" {
+        pub fn mmap(
+            addr: *mut u8,
+            len: usize,
+            prot: i32,
+            flags: i32,
+            fd: i32,
+            offset: i64,
+        ) -> *mut u8;
+        pub fn munmap(addr: *mut u8, len: usize) -> i32;
+    }
+}
+
+/// Map all `len` bytes of `file` into memory, shared with anything else
+/// that maps it.
+#[cfg(unix)]
+pub(crate) fn map(file: &File, len: usize) -> io::Result<*mut u8> {
+    use std::os::unix::io::AsRawFd;
+    let base = unsafe {
+        sys::mmap(
+            core::ptr::null_mut(),
+            len,
+            sys::PROT_READ | sys::PROT_WRITE,
+            sys::MAP_SHARED,
+            file.as_raw_fd(),
+            0,
+        )
+    };
+    if base as usize == usize::MAX {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(base)
+}
+
+#[cfg(not(unix))]
+pub(crate) fn map(_file: &File, _len: usize) -> io::Result<*mut u8> {
+    Err(io::Error::new(
+        io::ErrorKind::Other,
+        "shared memory isn't supported on this host",
+    ))
+}
+
+/// Undo `map()`.
+///
+/// # Safety
+///
+/// Nothing may use the memory once it has been unmapped.
+#[cfg(unix)]
+pub(crate) unsafe fn unmap(base: *mut u8, len: usize) {
+    sys::munmap(base, len);
+}
+
+#[cfg(not(unix))]
+pub(crate) unsafe fn unmap(_base: *mut u8, _len: usize) {}
@@ -0,0 +1,526 @@
+//! The link between hosted processes and the kernel.  The transport is picked
+//! by the address the kernel listens on, which is passed to processes in
+//! `XOUS_SERVER`:
+//!
+//! * `127.0.0.1:1234` is a TCP socket, which works everywhere.  A kernel
+//!   listening on `0.0.0.0:1234` also accepts processes running on other
+//!   machines, as long as each one was registered with the kernel's
+//!   `XOUS_REMOTE_KEYS` and connects with one of those keys.
+//! * `unix:/tmp/xous.sock` is a UNIX domain socket
+//! * `shm:/tmp/xous.sock` connects over a UNIX domain socket, then moves all
+//!   data into a pair of rings in shared memory.  The socket is only used as
+//!   a doorbell to wake up a side that is waiting for data.
+//!
+//! Everything above this module sees a plain byte stream, whichever
+//! transport is in use.
+
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::mem::size_of;
+use std::net::{
+    IpAddr, Ipv4Addr, Ipv6Addr, Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs,
+};
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+#[cfg(unix)]
+use std::path::PathBuf;
+
+/// Where the kernel listens for processes
+#[derive(Clone, Debug, PartialEq)]
+pub enum Address {
+    Tcp(SocketAddr),
+    #[cfg(unix)]
+    Unix(PathBuf),
+    #[cfg(unix)]
+    Shm(PathBuf),
+}
+
+impl Address {
+    /// Parse an address of the form `unix:<path>`, `shm:<path>`, or
+    /// `<host>:<port>`.
+    pub fn parse(s: &str) -> Option<Address> {
+        #[cfg(unix)]
+        {
+            if let Some(path) = s.strip_prefix("unix:") {
+                return Some(Address::Unix(PathBuf::from(path)));
+            }
+            if let Some(path) = s.strip_prefix("shm:") {
+                return Some(Address::Shm(PathBuf::from(path)));
+            }
+        }
+        s.to_socket_addrs().ok()?.next().map(Address::Tcp)
+    }
+
+    /// Whether a process connecting to this address is on the same machine
+    /// as the kernel.
+    pub fn is_local(&self) -> bool {
+        match self {
+            Address::Tcp(addr) => addr.ip().is_loopback(),
+            #[cfg(unix)]
+            Address::Unix(_) | Address::Shm(_) => true,
+        }
+    }
+
+    /// The address a process on the same machine connects to.  A kernel
+    /// listening on every interface is reached through loopback, since
+    /// not every host lets a connection be made to `0.0.0.0`.
+    pub fn local(&self) -> Address {
+        match self {
+            Address::Tcp(addr) if addr.ip().is_unspecified() => {
+                let loopback = match addr.ip() {
+                    IpAddr::V4(_) => IpAddr::V4(Ipv4Addr::LOCALHOST),
+                    IpAddr::V6(_) => IpAddr::V6(Ipv6Addr::LOCALHOST),
+                };
+                Address::Tcp(SocketAddr::new(loopback, addr.port()))
+            }
+            other => other.clone(),
+        }
+    }
+}
+
+/// Set in the capabilities of a connection by a side whose words are 64
+/// bits wide.  Packets are made of whole words, so a process and a kernel
+/// on different machines must agree on this rather than negotiate it.
+pub const CAP_WORDS_64: u32 = 2;
+
+/// The word size this build sends along with its other capabilities.
+pub fn word_size_capability() -> u32 {
+    if size_of::<usize>() == 8 {
+        CAP_WORDS_64
+    } else {
+        0
+    }
+}
+
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Address::Tcp(addr) => write!(f, "{}", addr),
+            #[cfg(unix)]
+            Address::Unix(path) => write!(f, "unix:{}", path.display()),
+            #[cfg(unix)]
+            Address::Shm(path) => write!(f, "shm:{}", path.display()),
+        }
+    }
+}
+
+/// A connection between a process and the kernel
+#[derive(Debug)]
+pub enum Stream {
+    Tcp(TcpStream),
+    #[cfg(unix)]
+    Unix(UnixStream),
+    #[cfg(unix)]
+    Shm(shm::ShmStream),
+}
+
+impl Stream {
+    /// Connect to the kernel listening on `addr`.
+    pub fn connect(addr: &Address) -> io::Result<Stream> {
+        match addr {
+            Address::Tcp(addr) => {
+                let conn = TcpStream::connect(addr)?;
+                conn.set_nodelay(true)?;
+                Ok(Stream::Tcp(conn))
+            }
+            #[cfg(unix)]
+            Address::Unix(path) => UnixStream::connect(path).map(Stream::Unix),
+            #[cfg(unix)]
+            Address::Shm(path) => {
+                shm::ShmStream::connect(UnixStream::connect(path)?).map(Stream::Shm)
+            }
+        }
+    }
+
+    /// Get another handle to the same connection, so that one thread may
+    /// read from it while another writes to it.
+    pub fn try_clone(&self) -> io::Result<Stream> {
+        match self {
+            Stream::Tcp(s) => s.try_clone().map(Stream::Tcp),
+            #[cfg(unix)]
+            Stream::Unix(s) => s.try_clone().map(Stream::Unix),
+            #[cfg(unix)]
+            Stream::Shm(s) => s.try_clone().map(Stream::Shm),
+        }
+    }
+
+    /// Close the connection in both directions, which wakes up anything
+    /// blocked reading from either end.
+    pub fn shutdown(&self) -> io::Result<()> {
+        match self {
+            Stream::Tcp(s) => s.shutdown(Shutdown::Both),
+            #[cfg(unix)]
+            Stream::Unix(s) => s.shutdown(Shutdown::Both),
+            #[cfg(unix)]
+            Stream::Shm(s) => s.shutdown(),
+        }
+    }
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Stream::Tcp(s) => s.read(buf),
+            #[cfg(unix)]
+            Stream::Unix(s) => s.read(buf),
+            #[cfg(unix)]
+            Stream::Shm(s) => s.read(buf),
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Stream::Tcp(s) => s.write(buf),
+            #[cfg(unix)]
+            Stream::Unix(s) => s.write(buf),
+            #[cfg(unix)]
+            Stream::Shm(s) => s.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Stream::Tcp(s) => s.flush(),
+            #[cfg(unix)]
+            Stream::Unix(s) => s.flush(),
+            #[cfg(unix)]
+            Stream::Shm(_) => Ok(()),
+        }
+    }
+}
+
+/// The kernel's end of a transport, which processes connect to
+pub enum Listener {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(UnixListener, Address),
+}
+
+impl Listener {
+    /// Start listening on `addr`.  A UNIX domain socket left behind by a
+    /// kernel that is no longer running is replaced.
+    pub fn bind(addr: &Address) -> io::Result<Listener> {
+        match addr {
+            Address::Tcp(socket_addr) => TcpListener::bind(socket_addr).map(Listener::Tcp),
+            #[cfg(unix)]
+            Address::Unix(path) | Address::Shm(path) => {
+                if path.exists() && UnixStream::connect(path).is_err() {
+                    std::fs::remove_file(path)?;
+                }
+                UnixListener::bind(path).map(|listener| Listener::Unix(listener, addr.clone()))
+            }
+        }
+    }
+
+    /// The address processes should connect to, which for TCP includes the
+    /// port that was picked if the kernel was asked to listen on port `0`.
+    pub fn local_addr(&self) -> io::Result<Address> {
+        match self {
+            Listener::Tcp(listener) => listener.local_addr().map(Address::Tcp),
+            #[cfg(unix)]
+            Listener::Unix(_, addr) => Ok(addr.clone()),
+        }
+    }
+
+    pub fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        match self {
+            Listener::Tcp(listener) => listener.set_nonblocking(nonblocking),
+            #[cfg(unix)]
+            Listener::Unix(listener, _) => listener.set_nonblocking(nonblocking),
+        }
+    }
+
+    /// Accept a new connection from a process.  The connection is always
+    /// blocking, even if the listener isn't.  TCP connections send each
+    /// packet as soon as it's written, since a syscall can't go on until
+    /// its answer arrives, and that's slow to learn across a network.
+    pub fn accept(&self) -> io::Result<Stream> {
+        match self {
+            Listener::Tcp(listener) => {
+                let (conn, _) = listener.accept()?;
+                conn.set_nonblocking(false)?;
+                conn.set_nodelay(true)?;
+                Ok(Stream::Tcp(conn))
+            }
+            #[cfg(unix)]
+            Listener::Unix(listener, addr) => {
+                let (conn, _) = listener.accept()?;
+                conn.set_nonblocking(false)?;
+                match addr {
+                    // Only this connection failed, not the listener, so
+                    // report it the same way as one that hung up early.
+                    Address::Shm(_) => shm::ShmStream::accept(conn)
+                        .map(Stream::Shm)
+                        .map_err(|e| io::Error::new(io::ErrorKind::ConnectionAborted, e)),
+                    _ => Ok(Stream::Unix(conn)),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+impl Drop for Listener {
+    fn drop(&mut self) {
+        if let Listener::Unix(_, Address::Unix(path)) | Listener::Unix(_, Address::Shm(path)) = self
+        {
+            std::fs::remove_file(path).ok();
+        }
+    }
+}
+
+#[cfg(unix)]
+mod shm {
+    //! A pair of single-producer, single-consumer rings in a shared file,
+    //! one for each direction.  The process creates the file and sends its
+    //! path over the socket, and the kernel maps it and removes it.  After
+    //! that, a byte is only sent over the socket when the other side has
+    //! said that it's asleep waiting for data.
+
+    use std::fs::{File, OpenOptions};
+    use std::io::{self, Read, Write};
+    use std::net::Shutdown;
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::net::UnixStream;
+    use std::path::Path;
+    use std::sync::atomic::{fence, AtomicBool, AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    /// How many bytes each ring holds.  This must be a power of two.
+    const RING_SIZE: usize = 1024 * 1024;
+
+    /// Room for the header at the start of each ring, which keeps the head
+    /// and tail counters of the two rings on different cache lines.
+    const HEADER_SIZE: usize = 128;
+
+    const MAP_SIZE: usize = 2 * (HEADER_SIZE + RING_SIZE);
+
+    /// The ring that processes write to and the kernel reads from.  The
+    /// other ring goes the opposite way.
+    const TO_KERNEL: usize = 0;
+    const TO_PROCESS: usize = 1;
+
+    /// The start of each ring.  A newly created file is all zeroes, which
+    /// is an empty ring that nobody is waiting on.
+    #[repr(C)]
+    struct RingHeader {
+        /// How many bytes have ever been written to the ring
+        head: AtomicUsize,
+
+        /// How many bytes have ever been read from the ring
+        tail: AtomicUsize,
+
+        /// Set by the reader before it sleeps on the doorbell
+        waiting: AtomicBool,
+
+        /// Set once either side has shut the connection down
+        closed: AtomicBool,
+    }
+
+    #[derive(Debug)]
+    struct Mapping {
+        base: *mut u8,
+    }
+
+    // The rings are only ever touched through atomics, or in the part of the
+    // ring that the atomics hand to one side or the other.
+    unsafe impl Send for Mapping {}
+    unsafe impl Sync for Mapping {}
+
+    impl Mapping {
+        fn new(file: &File) -> io::Result<Mapping> {
+            Ok(Mapping {
+                base: super::super::shared::map(file, MAP_SIZE)?,
+            })
+        }
+
+        fn header(&self, ring: usize) -> &RingHeader {
+            unsafe { &*(self.base.add(ring * (HEADER_SIZE + RING_SIZE)) as *const RingHeader) }
+        }
+
+        fn data(&self, ring: usize) -> *mut u8 {
+            unsafe {
+                self.base
+                    .add(ring * (HEADER_SIZE + RING_SIZE) + HEADER_SIZE)
+            }
+        }
+    }
+
+    impl Drop for Mapping {
+        fn drop(&mut self) {
+            unsafe { super::super::shared::unmap(self.base, MAP_SIZE) };
+        }
+    }
+
+    #[derive(Debug)]
+    pub struct ShmStream {
+        mapping: Arc<Mapping>,
+        tx: usize,
+        rx: usize,
+        doorbell: UnixStream,
+    }
+
+    impl ShmStream {
+        /// Create the shared rings on the process side, and tell the kernel
+        /// where to find them.
+        pub fn connect(mut doorbell: UnixStream) -> io::Result<ShmStream> {
+            static NEXT: AtomicUsize = AtomicUsize::new(0);
+            let path = std::env::temp_dir().join(format!(
+                "xous-shm-{}-{}",
+                std::process::id(),
+                NEXT.fetch_add(1, Ordering::Relaxed)
+            ));
+            let file = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create_new(true)
+                .open(&path)?;
+            file.set_len(MAP_SIZE as u64)?;
+            let mapping = Mapping::new(&file)?;
+
+            let path_bytes = path.as_os_str().as_bytes();
+            doorbell.write_all(&(path_bytes.len() as u32).to_le_bytes())?;
+            doorbell.write_all(path_bytes)?;
+            Ok(ShmStream {
+                mapping: Arc::new(mapping),
+                tx: TO_KERNEL,
+                rx: TO_PROCESS,
+                doorbell,
+            })
+        }
+
+        /// Map the rings that a newly connected process created.
+        pub fn accept(mut doorbell: UnixStream) -> io::Result<ShmStream> {
+            let mut len = [0u8; 4];
+            doorbell.read_exact(&mut len)?;
+            let mut path = vec![0u8; u32::from_le_bytes(len) as usize];
+            doorbell.read_exact(&mut path)?;
+            let path = Path::new(std::ffi::OsStr::from_bytes(&path));
+
+            let file = OpenOptions::new().read(true).write(true).open(path)?;
+            if file.metadata()?.len() != MAP_SIZE as u64 {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "shared memory is the wrong size",
+                ));
+            }
+            let mapping = Mapping::new(&file)?;
+
+            // Both sides have it mapped now, so nothing else needs to find it.
+            std::fs::remove_file(path).ok();
+            Ok(ShmStream {
+                mapping: Arc::new(mapping),
+                tx: TO_PROCESS,
+                rx: TO_KERNEL,
+                doorbell,
+            })
+        }
+
+        pub fn try_clone(&self) -> io::Result<ShmStream> {
+            Ok(ShmStream {
+                mapping: self.mapping.clone(),
+                tx: self.tx,
+                rx: self.rx,
+                doorbell: self.doorbell.try_clone()?,
+            })
+        }
+
+        pub fn shutdown(&self) -> io::Result<()> {
+            self.mapping
+                .header(self.tx)
+                .closed
+                .store(true, Ordering::SeqCst);
+            self.mapping
+                .header(self.rx)
+                .closed
+                .store(true, Ordering::SeqCst);
+            self.doorbell.shutdown(Shutdown::Both)
+        }
+    }
+
+    impl Read for ShmStream {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let header = self.mapping.header(self.rx);
+            let data = self.mapping.data(self.rx);
+            loop {
+                let tail = header.tail.load(Ordering::Relaxed);
+                let available = header.head.load(Ordering::Acquire).wrapping_sub(tail);
+                if available > 0 {
+                    let count = available.min(buf.len());
+                    let start = tail & (RING_SIZE - 1);
+                    let first = count.min(RING_SIZE - start);
+                    unsafe {
+                        core::ptr::copy_nonoverlapping(data.add(start), buf.as_mut_ptr(), first);
+                        core::ptr::copy_nonoverlapping(
+                            data,
+                            buf.as_mut_ptr().add(first),
+                            count - first,
+                        );
+                    }
+                    header
+                        .tail
+                        .store(tail.wrapping_add(count), Ordering::Release);
+                    return Ok(count);
+                }
+                if header.closed.load(Ordering::SeqCst) {
+                    return Ok(0);
+                }
+
+                // Say that we're about to sleep, then check once more in case
+                // the writer filled the ring before it could see that.
+                header.waiting.store(true, Ordering::SeqCst);
+                if header.head.load(Ordering::SeqCst) == tail {
+                    let mut bell = [0u8; 1];
+                    if self.doorbell.read(&mut bell)? == 0 {
+                        header.waiting.store(false, Ordering::SeqCst);
+                        return Ok(0);
+                    }
+                }
+                header.waiting.store(false, Ordering::SeqCst);
+            }
+        }
+    }
+
+    impl Write for ShmStream {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            let header = self.mapping.header(self.tx);
+            let data = self.mapping.data(self.tx);
+            loop {
+                if header.closed.load(Ordering::SeqCst) {
+                    return Err(io::ErrorKind::BrokenPipe.into());
+                }
+                let head = header.head.load(Ordering::Relaxed);
+                let space = RING_SIZE - head.wrapping_sub(header.tail.load(Ordering::Acquire));
+                if space == 0 {
+                    // The reader has already been woken up for what's in the
+                    // ring, so wait for it to make some room.
+                    std::thread::yield_now();
+                    continue;
+                }
+
+                let count = space.min(buf.len());
+                let start = head & (RING_SIZE - 1);
+                let first = count.min(RING_SIZE - start);
+                unsafe {
+                    core::ptr::copy_nonoverlapping(buf.as_ptr(), data.add(start), first);
+                    core::ptr::copy_nonoverlapping(buf.as_ptr().add(first), data, count - first);
+                }
+                header
+                    .head
+                    .store(head.wrapping_add(count), Ordering::Release);
+
+                fence(Ordering::SeqCst);
+                if header.waiting.load(Ordering::SeqCst) {
+                    self.doorbell.write_all(&[0])?;
+                }
+                return Ok(count);
+            }
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+}
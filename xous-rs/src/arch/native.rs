@@ -1,4 +1,4 @@
-use crate::{MemoryAddress, MemoryRange, PID, TID};
+use crate::{Capabilities, MemoryAddress, MemoryRange, Quotas, PID, TID};
 use core::convert::TryInto;
 
 mod mem;
@@ -55,6 +55,12 @@ impl ProcessKey {
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub struct ProcessInit {
     pub key: ProcessKey,
+
+    /// The privileged operations the new process may perform
+    pub capabilities: Capabilities,
+
+    /// How many kernel objects the new process may hold
+    pub quotas: Quotas,
 }
 
 pub struct WaitHandle<T>(core::marker::PhantomData<T>);
@@ -148,8 +154,8 @@ pub fn process_to_args(call: usize, init: &ProcessInit) -> [usize; 8] {
         u32::from_le_bytes(init.key.0[4..8].try_into().unwrap()) as _,
         u32::from_le_bytes(init.key.0[8..12].try_into().unwrap()) as _,
         u32::from_le_bytes(init.key.0[12..16].try_into().unwrap()) as _,
-        0,
-        0,
+        init.capabilities.bits(),
+        init.quotas.to_usize(),
         0,
     ]
 }
@@ -216,3 +222,17 @@ pub fn wait_process(_joiner: ProcessHandle) -> crate::SysCallResult {
         crate::wait_event();
     }
 }
+
+/// Read the `time` CSR.  On RV32 the counter is split across two registers,
+/// so the upper half is read again to make sure the lower half didn't wrap
+/// in between.
+pub fn timestamp() -> u64 {
+    use riscv::register::{time, timeh};
+    loop {
+        let high = timeh::read();
+        let low = time::read();
+        if timeh::read() == high {
+            return ((high as u64) << 32) | low as u64;
+        }
+    }
+}
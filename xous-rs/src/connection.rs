@@ -0,0 +1,74 @@
+//! A Connection owns a reference to a kernel connection, and disconnects
+//! when it is dropped so that short-lived clients don't leak Connection IDs.
+
+use crate::{Error, CID, SID};
+
+#[derive(Debug)]
+pub struct Connection {
+    cid: CID,
+    sid: SID,
+}
+
+impl Connection {
+    /// Connect to `server`, blocking until it is available.
+    pub fn connect(server: SID) -> Result<Self, Error> {
+        crate::connect(server).map(|cid| Connection { cid, sid: server })
+    }
+
+    /// Connect to `server`, failing if it doesn't exist yet.
+    ///
+    /// # Errors
+    ///
+    /// * **ServerNotFound**: The server could not be found.
+    pub fn try_connect(server: SID) -> Result<Self, Error> {
+        crate::try_connect(server).map(|cid| Connection { cid, sid: server })
+    }
+
+    /// The Connection ID to pass to `send_message()` and friends.
+    pub fn cid(&self) -> CID {
+        self.cid
+    }
+
+    /// The server this connection goes to.
+    pub fn sid(&self) -> SID {
+        self.sid
+    }
+
+    /// Take another reference to the same connection.  Both must be dropped
+    /// before the kernel frees the Connection ID.
+    ///
+    /// # Errors
+    ///
+    /// * **ServerNotFound**: The server has exited, so no new references can
+    ///                       be made to it.
+    pub fn try_clone(&self) -> Result<Self, Error> {
+        Self::try_connect(self.sid)
+    }
+
+    /// Give up ownership of the connection without disconnecting, returning
+    /// the Connection ID.  The connection stays open for as long as the
+    /// process runs, unless it is passed to `disconnect()`.
+    pub fn leak(self) -> CID {
+        let cid = self.cid;
+        core::mem::forget(self);
+        cid
+    }
+}
+
+impl Clone for Connection {
+    /// # Panics
+    ///
+    /// Panics if the server has exited.  Use `try_clone()` to handle that
+    /// case.
+    fn clone(&self) -> Self {
+        self.try_clone().expect("couldn't clone connection")
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        // There's nothing sensible to do if this fails, since it means the
+        // connection was already released through its raw Connection ID.
+        crate::disconnect(self.cid).ok();
+    }
+}
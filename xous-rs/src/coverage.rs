@@ -0,0 +1,69 @@
+//! Coverage counters for on-target test runs.
+//!
+//! When built with the `coverage` feature, every `cover!()` point places a
+//! counter in the `xous_coverage` link section.  After a test run, the
+//! counters can be read back with `points()` and printed as
+//! `COV <hits> <name>` lines, which the `coverage-report` tool collects to
+//! show which points were never reached.
+//!
+//! The linker provides `__start_xous_coverage` and `__stop_xous_coverage`
+//! around the section, so this only works with ELF targets.
+
+use core::cell::UnsafeCell;
+
+#[repr(C)]
+pub struct CoveragePoint {
+    name: &'static str,
+    hits: UnsafeCell<usize>,
+}
+
+// Counters are only ever incremented, so a racing update can at worst cause
+// a point to be undercounted.
+unsafe impl Sync for CoveragePoint {}
+
+impl CoveragePoint {
+    pub const fn new(name: &'static str) -> CoveragePoint {
+        CoveragePoint {
+            name,
+            hits: UnsafeCell::new(0),
+        }
+    }
+
+    pub fn hit(&self) {
+        unsafe { *self.hits.get() = (*self.hits.get()).wrapping_add(1) };
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn hits(&self) -> usize {
+        unsafe { *self.hits.get() }
+    }
+
+    pub fn reset(&self) {
+        unsafe { *self.hits.get() = 0 };
+    }
+}
+
+extern "C" {
+    // Only the addresses of these symbols are used.
+    static __start_xous_coverage: u8;
+    static __stop_xous_coverage: u8;
+}
+
+/// Return every coverage point in this program.
+pub fn points() -> &'static [CoveragePoint] {
+    // This point ensures the section always exists, even in programs that
+    // don't have any points of their own.
+    crate::cover!("coverage read");
+
+    unsafe {
+        let start = &__start_xous_coverage as *const u8 as *const CoveragePoint;
+        let end = &__stop_xous_coverage as *const u8 as *const CoveragePoint;
+        core::slice::from_raw_parts(
+            start,
+            (end as usize - start as usize) / core::mem::size_of::<CoveragePoint>(),
+        )
+    }
+}
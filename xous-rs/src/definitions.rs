@@ -122,7 +122,9 @@ pub enum Error {
     ShareViolation = 19,
     InvalidThread = 20,
     InvalidPID = 21,
-    UnknownError = 22,
+    AccessDenied = 22,
+    LimitReached = 23,
+    UnknownError = 24,
 }
 
 impl Error {
@@ -151,6 +153,8 @@ impl Error {
             19 => ShareViolation,
             20 => InvalidThread,
             21 => InvalidPID,
+            22 => AccessDenied,
+            23 => LimitReached,
             _ => UnknownError,
         }
     }
@@ -179,6 +183,8 @@ impl Error {
             ShareViolation => 19,
             InvalidThread => 20,
             InvalidPID => 21,
+            AccessDenied => 22,
+            LimitReached => 23,
             UnknownError => usize::MAX,
         }
     }
@@ -236,6 +242,33 @@ impl MemoryMessage {
             valid,
         })
     }
+
+    /// Create a message that lends the pieces in `scatter` as though they
+    /// were one buffer.  The buffer's address refers to `scatter` rather than
+    /// to any of the pieces, so the list must stay put until the message has
+    /// been sent.  Only lends may be scattered.
+    pub fn scattered(id: usize, scatter: &ScatterList) -> Option<MemoryMessage> {
+        Some(MemoryMessage {
+            id,
+            buf: MemoryRange::from_parts(
+                MemoryAddress::new(scatter as *const ScatterList as usize | SCATTERED)?,
+                MemorySize::new(scatter.len())?,
+            ),
+            offset: None,
+            valid: None,
+        })
+    }
+
+    /// The list of pieces that a scattered message is gathered from, or
+    /// `None` if its buffer is contiguous.
+    pub fn scatter_list(&self) -> Option<*const ScatterList> {
+        let addr = self.buf.addr.get();
+        if addr & SCATTERED != 0 {
+            Some((addr & !SCATTERED) as *const ScatterList)
+        } else {
+            None
+        }
+    }
     pub fn to_usize(&self) -> [usize; 5] {
         [
             self.id,
@@ -247,6 +280,94 @@ impl MemoryMessage {
     }
 }
 
+/// The most pieces that a scattered memory message may be gathered from
+pub const MAX_SCATTER_RANGES: usize = 8;
+
+/// Set in the buffer address of a scattered memory message, which points to
+/// its `ScatterList`.  Lent memory must otherwise be page-aligned, so this
+/// can't be mistaken for an ordinary buffer.
+const SCATTERED: usize = 1;
+
+#[repr(C)]
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+/// A list of page-aligned pieces of memory that are lent as a single buffer.
+/// The kernel maps them one after another into the server, so that memory
+/// which is logically contiguous but scattered in the client, such as a
+/// chain of packet fragments, can be lent without copying it first.
+pub struct ScatterList {
+    /// How many entries of `ranges` are in use
+    count: usize,
+
+    /// The address and length of each piece, in order
+    ranges: [[usize; 2]; MAX_SCATTER_RANGES],
+}
+
+impl ScatterList {
+    /// Gather `ranges` into a list, in order.
+    ///
+    /// # Errors
+    ///
+    /// * **BadAddress**: There are no ranges
+    /// * **BadAlignment**: A range doesn't start and end on a page boundary
+    /// * **OutOfMemory**: There are more than `MAX_SCATTER_RANGES` ranges
+    pub fn new(ranges: &[MemoryRange]) -> core::result::Result<ScatterList, Error> {
+        if ranges.len() > MAX_SCATTER_RANGES {
+            return Err(Error::OutOfMemory);
+        }
+        let mut list = ScatterList {
+            count: ranges.len(),
+            ..Default::default()
+        };
+        for (entry, range) in list.ranges.iter_mut().zip(ranges) {
+            *entry = [range.addr.get(), range.size.get()];
+        }
+        list.check()?;
+        Ok(list)
+    }
+
+    /// Make sure the list is well-formed, as one that was read out of
+    /// another process might not be.  The errors are the same as `new()`.
+    pub fn check(&self) -> core::result::Result<(), Error> {
+        if self.count == 0 {
+            return Err(Error::BadAddress);
+        }
+        if self.count > MAX_SCATTER_RANGES {
+            return Err(Error::OutOfMemory);
+        }
+        for &[addr, size] in &self.ranges[..self.count] {
+            if addr == 0 || size == 0 {
+                return Err(Error::BadAddress);
+            }
+            if addr & 0xfff != 0 || size & 0xfff != 0 {
+                return Err(Error::BadAlignment);
+            }
+        }
+        Ok(())
+    }
+
+    /// The pieces in the list, in order.  If the list is malformed, this
+    /// stops at the first bad piece.
+    pub fn iter(&self) -> impl Iterator<Item = MemoryRange> + '_ {
+        self.ranges[..self.count.min(MAX_SCATTER_RANGES)]
+            .iter()
+            .map_while(|&[addr, size]| {
+                Some(MemoryRange::from_parts(
+                    MemoryAddress::new(addr)?,
+                    MemorySize::new(size)?,
+                ))
+            })
+    }
+
+    /// The total length of the pieces
+    pub fn len(&self) -> usize {
+        self.iter().map(|range| range.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
 #[repr(C)]
 #[derive(Debug, PartialEq, Clone, Copy)]
 /// A simple scalar message.  This is similar to a `move` message.
@@ -282,8 +403,16 @@ impl ScalarMessage {
 #[repr(usize)]
 #[derive(Debug, PartialEq)]
 pub enum Message {
+    /// Lend memory to the server, which may read and write it.  The memory is
+    /// unmapped from the client until the server returns it.
     MutableBorrow(MemoryMessage),
+
+    /// Lend memory to the server, which may only read it.  The client's own
+    /// mapping is made read-only until the server returns the memory, so
+    /// the server sees the same contents for as long as it holds the lend
+    /// and doesn't need to copy them before checking them.
     Borrow(MemoryMessage),
+
     Move(MemoryMessage),
     Scalar(ScalarMessage),
     BlockingScalar(ScalarMessage),
@@ -321,11 +450,32 @@ impl Message {
 #[derive(Debug, PartialEq)]
 pub struct MessageEnvelope {
     pub sender: MessageSender,
+
+    /// The word the server attached to the sender's connection with
+    /// `set_connection_data()`, or `0` if it hasn't attached one
+    pub connection_data: usize,
+
     pub body: Message,
 }
 
 impl MessageEnvelope {
-    pub fn to_usize(&self) -> [usize; 7] {
+    /// The process that sent this message, as recorded by the kernel when
+    /// the message was delivered.  Servers may use this to decide what each
+    /// client is allowed to do.
+    pub fn sender_pid(&self) -> Option<PID> {
+        PID::new((self.sender >> 24) as u8)
+    }
+
+    /// The connection this message arrived on, which is the receiving
+    /// process' own connection to the server.  This is how a thread waiting
+    /// on a poll set tells which server a message was sent to.
+    pub fn server_cid(&self) -> CID {
+        (self.sender >> 16) & 0xff
+    }
+
+    /// Encode the envelope as its message type, followed by the sender, the
+    /// connection data, and the five words of the message itself.
+    pub fn to_usize(&self) -> [usize; 8] {
         let ret = match &self.body {
             Message::MutableBorrow(m) => (0, m.to_usize()),
             Message::Borrow(m) => (1, m.to_usize()),
@@ -334,8 +484,9 @@ impl MessageEnvelope {
             Message::BlockingScalar(m) => (4, m.to_usize()),
         };
         [
-            self.sender,
             ret.0,
+            self.sender,
+            self.connection_data,
             ret.1[0],
             ret.1[1],
             ret.1[2],
@@ -364,11 +515,10 @@ impl Drop for MessageEnvelope {
 }
 
 impl MemoryRange {
+    /// A range of `size` bytes starting at `addr`.  Neither may be zero, and
+    /// since a process may pass anything at all to the kernel, a range that
+    /// breaks that rule is an error rather than a panic.
     pub fn new(addr: usize, size: usize) -> core::result::Result<MemoryRange, Error> {
-        assert!(
-            addr != 0,
-            "tried to construct a memory range with a null pointer"
-        );
         Ok(MemoryRange {
             addr: MemoryAddress::new(addr).ok_or(Error::BadAddress)?,
             size: MemorySize::new(size).ok_or(Error::BadAddress)?,
@@ -423,6 +573,717 @@ impl From<usize> for MemoryType {
     }
 }
 
+/// Points during startup at which the kernel records a timestamp.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum BootStage {
+    /// The loader has jumped to the kernel.
+    KernelEntry = 0,
+
+    /// The memory manager has taken over the loader's page tracker.
+    MemoryManager = 1,
+
+    /// The initial processes have been created.
+    Processes = 2,
+
+    /// Interrupts and other architecture-specific features are set up.
+    ArchInit = 3,
+
+    /// The kernel is about to run the first process.
+    Scheduler = 4,
+}
+
+impl BootStage {
+    pub fn from_usize(arg: usize) -> Option<Self> {
+        match arg {
+            0 => Some(BootStage::KernelEntry),
+            1 => Some(BootStage::MemoryManager),
+            2 => Some(BootStage::Processes),
+            3 => Some(BootStage::ArchInit),
+            4 => Some(BootStage::Scheduler),
+            _ => None,
+        }
+    }
+}
+
+/// Which part of handling a message a latency refers to.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum LatencyStage {
+    /// From the message being sent until the server receives it.
+    QueueWait = 0,
+
+    /// From the server receiving a blocking message until it responds.
+    Service = 1,
+}
+
+impl LatencyStage {
+    pub fn from_usize(arg: usize) -> Option<Self> {
+        match arg {
+            0 => Some(LatencyStage::QueueWait),
+            1 => Some(LatencyStage::Service),
+            _ => None,
+        }
+    }
+}
+
+/// How much a single thread has been scheduled.  Counters start at zero
+/// when the thread is created.
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct ThreadStats {
+    /// The total time this thread has spent running, in the same units as
+    /// `xous::timestamp::now()`.
+    pub run_time: u64,
+
+    /// The number of times this thread has been switched to.
+    pub schedules: usize,
+
+    /// The number of times this thread gave up the CPU itself, for example
+    /// by blocking on a message or yielding.
+    pub voluntary_switches: usize,
+
+    /// The number of times this thread was preempted because it used up its
+    /// quantum.
+    pub involuntary_switches: usize,
+}
+
+/// A snapshot of one server registered with the kernel, for tools that
+/// show what is talking to what.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ServerInfo {
+    /// The process that owns the server.
+    pub pid: PID,
+
+    /// The number of messages the server's queue can hold.
+    pub queue_length: usize,
+
+    /// The number of messages waiting for the server to receive them.
+    pub queued: usize,
+
+    /// The number of messages the server has received but not yet
+    /// responded to.
+    pub outstanding: usize,
+
+    /// The number of processes connected to the server, including the
+    /// server's own process if it has connected to itself.
+    pub connections: usize,
+
+    /// The number of server threads waiting for a message.
+    pub parked_threads: usize,
+}
+
+/// What happened in an entry of the kernel's audit log.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum AuditKind {
+    /// A process was created.  `arg` is the PID of its parent.
+    ProcessCreated = 1,
+
+    /// A process exited or was terminated.  `arg` is the PID of its parent.
+    ProcessTerminated = 2,
+
+    /// A process claimed an interrupt.  `arg` is the IRQ number.
+    InterruptClaimed = 3,
+
+    /// A process mapped physical memory.  `arg` is the physical address and
+    /// `arg2` is the size.
+    PhysicalMapping = 4,
+
+    /// A process tried to do something that needs a capability it doesn't
+    /// hold.  `arg` holds the bits of the missing capability.
+    CapabilityDenied = 5,
+
+    /// A process made a syscall that its filter doesn't allow.  `arg` is the
+    /// syscall number.
+    SyscallDenied = 6,
+}
+
+impl AuditKind {
+    pub fn from_usize(arg: usize) -> Option<Self> {
+        match arg {
+            1 => Some(AuditKind::ProcessCreated),
+            2 => Some(AuditKind::ProcessTerminated),
+            3 => Some(AuditKind::InterruptClaimed),
+            4 => Some(AuditKind::PhysicalMapping),
+            5 => Some(AuditKind::CapabilityDenied),
+            6 => Some(AuditKind::SyscallDenied),
+            _ => None,
+        }
+    }
+}
+
+/// One entry of the kernel's audit log.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct AuditEvent {
+    /// Where this entry falls in the log.  Sequence numbers start at zero
+    /// and go up by one for every event, so a gap means that entries were
+    /// overwritten before they were read.
+    pub sequence: usize,
+
+    /// What happened.
+    pub kind: AuditKind,
+
+    /// The process the event concerns.
+    pub pid: PID,
+
+    /// Details that depend on `kind`.
+    pub arg: usize,
+
+    /// More details that depend on `kind`.
+    pub arg2: usize,
+
+    /// When the event happened, in the same units as
+    /// `xous::timestamp::now()`.
+    pub timestamp: u64,
+}
+
+/// How much memory the kernel wipes when the system shuts down, so that
+/// nothing is left for a cold-boot attack to find.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ScrubLevel {
+    /// Don't wipe anything.
+    None = 0,
+
+    /// Wipe the kernel's own secrets, such as the state of its random number
+    /// generator, and every page of RAM that no process owns.
+    Freed = 1,
+
+    /// Wipe everything `Freed` does, and every page that any process has
+    /// mapped.  Only the kernel's own bookkeeping is left behind.
+    All = 2,
+}
+
+impl ScrubLevel {
+    pub fn from_usize(arg: usize) -> Option<Self> {
+        match arg {
+            0 => Some(ScrubLevel::None),
+            1 => Some(ScrubLevel::Freed),
+            2 => Some(ScrubLevel::All),
+            _ => None,
+        }
+    }
+}
+
+/// How the system starts up again after a `Reboot`
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum RebootMode {
+    /// Reset the CPU and start the kernel again, leaving the rest of the
+    /// SoC as it was.  A hosted kernel starts over without exiting.
+    Warm = 1,
+
+    /// Reset the whole SoC, the same as turning it off and on again.  A
+    /// hosted kernel runs its program again from scratch.
+    Cold = 2,
+
+    /// Reset the whole SoC, and have the loader wait for a firmware update
+    /// rather than starting the kernel.
+    Update = 3,
+}
+
+impl RebootMode {
+    pub fn from_usize(arg: usize) -> Option<Self> {
+        match arg {
+            1 => Some(RebootMode::Warm),
+            2 => Some(RebootMode::Cold),
+            3 => Some(RebootMode::Update),
+            _ => None,
+        }
+    }
+}
+
+/// The broadcast list that the kernel announces a shutdown or a reboot on.
+/// It always exists, and only the kernel may broadcast on it.  Each
+/// subscriber is sent the grace period in milliseconds as the message's
+/// first argument, and the `RebootMode` as its second, or `0` if the system
+/// isn't coming back.  It should save whatever it needs to and then
+/// unsubscribe.  The system goes down once every subscriber has unsubscribed
+/// or exited, or once the grace period is over.
+pub const SHUTDOWN_BROADCAST: usize = usize::MAX;
+
+/// What the kernel tells servers subscribed to `SUSPEND_BROADCAST`
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum SuspendEvent {
+    /// The system is about to suspend.  Devices should be stopped and their
+    /// state saved, since whatever isn't in RAM is lost.
+    Suspending = 1,
+
+    /// The system has woken up again, and devices can be brought back.
+    Resumed = 2,
+}
+
+impl SuspendEvent {
+    pub fn from_usize(arg: usize) -> Option<Self> {
+        match arg {
+            1 => Some(SuspendEvent::Suspending),
+            2 => Some(SuspendEvent::Resumed),
+            _ => None,
+        }
+    }
+}
+
+/// Whether a process needs a clock domain running
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ClockState {
+    /// The process can do without the domain.
+    Off = 0,
+
+    /// The process needs the domain running.
+    On = 1,
+}
+
+impl ClockState {
+    pub fn from_usize(arg: usize) -> Option<Self> {
+        match arg {
+            0 => Some(ClockState::Off),
+            1 => Some(ClockState::On),
+            _ => None,
+        }
+    }
+}
+
+/// The broadcast list that the kernel announces a suspend on.  It always
+/// exists, and only the kernel may broadcast on it.  Each subscriber is sent
+/// the `SuspendEvent` as the message's first argument, once before the
+/// system suspends and once after it wakes.  Nothing needs to be done to
+/// say it's ready, other than to go back to waiting for messages, since the
+/// system only suspends once nothing is left to run.
+pub const SUSPEND_BROADCAST: usize = usize::MAX - 1;
+
+/// Something the kernel recorded in its trace buffer.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum TraceKind {
+    /// A thread made a syscall.  `arg` is the syscall number.
+    SyscallEntry = 1,
+
+    /// A syscall finished.  `arg` is the error it failed with, or `0` if it
+    /// didn't fail.  A syscall that blocks finishes before the thread is
+    /// woken up again.
+    SyscallExit = 2,
+
+    /// A thread was switched to.  `arg` is unused.
+    ContextSwitch = 3,
+
+    /// A thread sent or forwarded a message.  `arg` is the connection it
+    /// was sent on.
+    MessageSent = 4,
+
+    /// A server thread was given a message.  `arg` is the sender, as passed
+    /// to the return syscalls.
+    MessageReceived = 5,
+
+    /// A server thread returned a message.  `arg` is the sender.
+    MessageReturned = 6,
+}
+
+impl TraceKind {
+    pub fn from_usize(arg: usize) -> Option<Self> {
+        match arg {
+            1 => Some(TraceKind::SyscallEntry),
+            2 => Some(TraceKind::SyscallExit),
+            3 => Some(TraceKind::ContextSwitch),
+            4 => Some(TraceKind::MessageSent),
+            5 => Some(TraceKind::MessageReceived),
+            6 => Some(TraceKind::MessageReturned),
+            _ => None,
+        }
+    }
+}
+
+/// One record of the kernel's trace buffer.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TraceEvent {
+    /// What happened.
+    pub kind: TraceKind,
+
+    /// The process it happened in.
+    pub pid: PID,
+
+    /// The thread it happened on.
+    pub tid: TID,
+
+    /// Details that depend on `kind`.
+    pub arg: usize,
+
+    /// When it happened, in the same units as `xous::timestamp::now()`.
+    pub timestamp: u64,
+
+    /// How many records were overwritten before they could be read since
+    /// the last one that was read.
+    pub dropped: usize,
+}
+
+/// The number of buckets in each syscall latency histogram.  Bucket `n`
+/// counts the calls that took at least `2^n` and less than `2^(n+1)`
+/// cycles, except that the first bucket also counts calls that took none,
+/// and the last bucket counts every call that took longer.
+pub const LATENCY_BUCKETS: usize = 16;
+
+/// How long the kernel has spent handling one syscall.  The histogram
+/// itself is read one bucket at a time with `read_latency_bucket()`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SyscallLatency {
+    /// How many times the syscall has been made.
+    pub calls: usize,
+
+    /// The cycles spent handling all of them together.
+    pub total: u64,
+
+    /// The most cycles spent handling any one of them.
+    pub max: usize,
+}
+
+/// The number of words from the top of the stack kept with each profiler
+/// sample.
+pub const PROFILE_STACK_WORDS: usize = 4;
+
+/// Where a thread was when the kernel's sampling profiler interrupted it.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ProfileSample {
+    /// The process that was running.
+    pub pid: PID,
+
+    /// The thread that was running.
+    pub tid: TID,
+
+    /// The address of the instruction it was about to run.
+    pub pc: usize,
+
+    /// The words at the top of its stack, starting at the stack pointer.
+    /// These often hold return addresses, but may be anything.
+    pub stack: [usize; PROFILE_STACK_WORDS],
+}
+
+/// A part of the kernel whose log messages can be turned up or down while
+/// it's running.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum LogSubsystem {
+    /// Syscalls as they're made, and what they return.
+    Syscalls = 0,
+
+    /// Memory being mapped, unmapped, lent and moved.
+    Memory = 1,
+
+    /// Interrupts being claimed and handled.
+    Interrupts = 2,
+
+    /// Processes being created and terminated, and threads being switched.
+    Scheduler = 3,
+}
+
+impl LogSubsystem {
+    pub fn from_usize(arg: usize) -> Option<Self> {
+        match arg {
+            0 => Some(LogSubsystem::Syscalls),
+            1 => Some(LogSubsystem::Memory),
+            2 => Some(LogSubsystem::Interrupts),
+            3 => Some(LogSubsystem::Scheduler),
+            _ => None,
+        }
+    }
+}
+
+/// How much a part of the kernel logs.  Each level also logs everything the
+/// levels below it do.
+#[derive(Debug, Copy, Clone, PartialEq, PartialOrd)]
+pub enum LogLevel {
+    /// Nothing at all.
+    Off = 0,
+
+    /// Only things that went wrong.
+    Errors = 1,
+
+    /// Each thing that happens.
+    Info = 2,
+
+    /// Each thing that happens, in enough detail to follow it step by step.
+    Debug = 3,
+}
+
+impl LogLevel {
+    pub fn from_usize(arg: usize) -> Option<Self> {
+        match arg {
+            0 => Some(LogLevel::Off),
+            1 => Some(LogLevel::Errors),
+            2 => Some(LogLevel::Info),
+            3 => Some(LogLevel::Debug),
+            _ => None,
+        }
+    }
+}
+
+/// The number of registers saved in a crash report, which are `x1` through
+/// `x31` on RISC-V.
+pub const CRASH_REGISTERS: usize = 31;
+
+/// The number of words saved from the top of a crashed thread's stack.
+pub const CRASH_STACK_WORDS: usize = 16;
+
+/// A process that crashed, and was terminated by the kernel.  The crashed
+/// thread's registers, followed by the words at the top of its stack, are
+/// read one at a time with `read_crash_word()`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct CrashReport {
+    /// Where this report falls among all crash reports.  Sequence numbers
+    /// start at zero and go up by one for every crash.
+    pub sequence: usize,
+
+    /// The process that crashed.
+    pub pid: PID,
+
+    /// The parent of the process that crashed.
+    pub ppid: PID,
+
+    /// The thread that crashed.
+    pub tid: TID,
+
+    /// What the CPU said went wrong, which is `scause` on RISC-V.
+    pub cause: usize,
+
+    /// The address of the instruction that crashed.
+    pub pc: usize,
+
+    /// The address that was being accessed, if it was a memory fault.
+    pub addr: usize,
+}
+
+bitflags! {
+    /// Optional features that a kernel was built with.
+    pub struct KernelFeatures: usize {
+        const DEBUG_PRINT        = 1 << 0;
+        const PRINT_PANICS       = 1 << 1;
+        const REPORT_MEMORY      = 1 << 2;
+        const PROFILE            = 1 << 3;
+        const COVERAGE           = 1 << 4;
+        const EMULATE_ATOMICS    = 1 << 5;
+        const EMULATE_MISALIGNED = 1 << 6;
+        const PRINT_MAPPINGS     = 1 << 7;
+        const TRACE_LENDS        = 1 << 8;
+        const SCHED_ROUND_ROBIN  = 1 << 9;
+        const SCHED_PRIORITY     = 1 << 10;
+        const TRACE              = 1 << 11;
+        const SYSCALL_LATENCY    = 1 << 12;
+    }
+}
+
+bitflags! {
+    /// Privileged operations that a process may perform.  A process is
+    /// given these by its parent when it is created, and can only be given
+    /// ones that its parent holds.  Processes started by the kernel hold all
+    /// of them.
+    pub struct Capabilities: usize {
+        /// Claim and raise interrupts.
+        const INTERRUPTS      = 1 << 0;
+
+        /// Map memory at a specific physical address, such as a device's
+        /// registers.
+        const PHYSICAL_MEMORY = 1 << 1;
+
+        /// Shut down, reboot or suspend the system.
+        const SHUTDOWN        = 1 << 2;
+
+        /// Read the kernel's audit log.
+        const AUDIT           = 1 << 3;
+
+        /// Drain the kernel's trace buffer and profiler samples, and read
+        /// its syscall latency histograms.
+        const TRACE           = 1 << 4;
+
+        /// Gate clock domains and set how fast the CPU runs.
+        const POWER           = 1 << 5;
+
+        /// Let other processes map memory that is both writable and
+        /// executable, with `AllowJit`.
+        const ALLOW_JIT       = 1 << 6;
+
+        /// Hand physical I/O regions to other processes before anyone maps
+        /// them, with `AssignIoRegion`.
+        const IO_REGIONS      = 1 << 7;
+
+        /// List every server in the system, and be told when other
+        /// processes crash or miss their heartbeats.
+        const SUPERVISE       = 1 << 8;
+
+        /// Reserve CPU time in the deadline scheduling class, change how
+        /// long each priority class runs before it is preempted, and run at
+        /// a higher priority than the parent that created the process.
+        const SCHEDULING      = 1 << 9;
+    }
+}
+
+/// How many of each kind of kernel object a process may hold at once, so
+/// that one process can't use up what every process shares.  A process is
+/// given these by its parent when it is created, and can't be given more
+/// than its parent has.  Processes started by the kernel have no limits.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Quotas {
+    /// The number of servers the process may have.
+    pub servers: usize,
+
+    /// The number of connections the process may have open.
+    pub connections: usize,
+
+    /// The number of threads the process may have.
+    pub threads: usize,
+}
+
+impl Quotas {
+    /// The most any single quota can be.
+    pub const MAX: usize = 255;
+
+    /// Quotas that don't limit anything.
+    pub const fn unlimited() -> Quotas {
+        Quotas {
+            servers: Quotas::MAX,
+            connections: Quotas::MAX,
+            threads: Quotas::MAX,
+        }
+    }
+
+    /// The lower of each quota in `self` and `other`.
+    pub fn min(&self, other: &Quotas) -> Quotas {
+        Quotas {
+            servers: self.servers.min(other.servers),
+            connections: self.connections.min(other.connections),
+            threads: self.threads.min(other.threads),
+        }
+    }
+
+    /// Pack the quotas into a single word, one byte each.
+    pub fn to_usize(&self) -> usize {
+        let byte = |quota: usize| quota.min(Quotas::MAX);
+        byte(self.servers) | (byte(self.connections) << 8) | (byte(self.threads) << 16)
+    }
+
+    pub fn from_usize(arg: usize) -> Quotas {
+        Quotas {
+            servers: arg & 0xff,
+            connections: (arg >> 8) & 0xff,
+            threads: (arg >> 16) & 0xff,
+        }
+    }
+}
+
+impl Default for Quotas {
+    /// The quotas a process gets unless its creator asks for others.
+    /// Servers come from a table that every process shares, so a process
+    /// may only take a quarter of it.  Connections and threads come from the
+    /// process' own tables, so they're only limited by the size of those.
+    fn default() -> Quotas {
+        Quotas {
+            servers: 8,
+            connections: 32,
+            threads: 32,
+        }
+    }
+}
+
+/// The syscalls that a process may make, as a bitmap of syscall numbers.  A
+/// process may always make `TerminateProcess`, so that it can exit.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SyscallFilter([u32; 4]);
+
+impl SyscallFilter {
+    /// A filter that allows every syscall.
+    pub const fn all() -> SyscallFilter {
+        SyscallFilter([u32::MAX; 4])
+    }
+
+    /// A filter that allows nothing but `TerminateProcess`.
+    pub const fn empty() -> SyscallFilter {
+        SyscallFilter([0; 4])
+    }
+
+    /// Allow `call` as well.
+    pub fn allow(mut self, call: crate::SysCallNumber) -> SyscallFilter {
+        let call = call as usize;
+        if let Some(word) = self.0.get_mut(call / 32) {
+            *word |= 1 << (call % 32);
+        }
+        self
+    }
+
+    /// Whether syscall number `call` is allowed.
+    pub fn allows(&self, call: usize) -> bool {
+        call == crate::SysCallNumber::TerminateProcess as usize
+            || self
+                .0
+                .get(call / 32)
+                .map(|word| word & (1 << (call % 32)) != 0)
+                .unwrap_or(false)
+    }
+
+    /// The syscalls allowed by both `self` and `other`.
+    pub fn intersect(&self, other: &SyscallFilter) -> SyscallFilter {
+        let mut words = self.0;
+        for (word, other) in words.iter_mut().zip(other.0.iter()) {
+            *word &= *other;
+        }
+        SyscallFilter(words)
+    }
+
+    pub fn from_words(words: [usize; 4]) -> SyscallFilter {
+        SyscallFilter([
+            words[0] as u32,
+            words[1] as u32,
+            words[2] as u32,
+            words[3] as u32,
+        ])
+    }
+
+    pub fn to_words(&self) -> [usize; 4] {
+        [
+            self.0[0] as usize,
+            self.0[1] as usize,
+            self.0[2] as usize,
+            self.0[3] as usize,
+        ]
+    }
+}
+
+/// The architecture a kernel was built for.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum KernelArch {
+    /// The kernel runs as a process on another operating system.
+    Hosted = 0,
+
+    /// The kernel runs directly on a 32-bit RISC-V CPU.
+    Riscv32 = 1,
+}
+
+impl KernelArch {
+    pub fn from_usize(arg: usize) -> Option<Self> {
+        match arg {
+            0 => Some(KernelArch::Hosted),
+            1 => Some(KernelArch::Riscv32),
+            _ => None,
+        }
+    }
+}
+
+/// How a kernel was built, so that programs can check that they're running
+/// on a kernel they're compatible with and size things to fit its limits.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct KernelConfig {
+    /// The architecture the kernel was built for.
+    pub arch: KernelArch,
+
+    /// The size of a page of memory, in bytes.
+    pub page_size: usize,
+
+    /// The optional features the kernel was built with.  Features that this
+    /// library doesn't know about are left out.
+    pub features: KernelFeatures,
+
+    /// The most processes that may exist at once.
+    pub max_processes: usize,
+
+    /// The most threads that a process may have.
+    pub max_threads: usize,
+
+    /// The most servers that may exist at once.
+    pub max_servers: usize,
+
+    /// The most messages a server may ask to be able to queue.
+    pub max_queue_length: usize,
+}
+
 #[repr(C)]
 #[derive(Debug, PartialEq)]
 pub enum Result {
@@ -439,7 +1300,6 @@ pub enum Result {
         usize,
         /* pid2 */ usize, /* context2 */
     ),
-    ResumeProcess,
     ServerID(SID),
     ConnectionID(CID),
     NewServerID(SID, CID),
@@ -450,15 +1310,40 @@ pub enum Result {
     /// The requested system call is unimplemented
     Unimplemented,
 
-    /// The process is blocked and should perform the read() again
-    BlockedProcess,
-
     /// A scalar with one value
     Scalar1(usize),
 
     /// A scalar with two values
     Scalar2(usize, usize),
 
+    /// Scheduler statistics for a thread
+    ThreadStats(ThreadStats),
+
+    /// How the kernel was built
+    KernelConfig(KernelConfig),
+
+    /// The state of a server
+    ServerInfo(ServerInfo),
+
+    /// An entry of the audit log
+    AuditEvent(AuditEvent),
+
+    /// A process that crashed
+    CrashReport(CrashReport),
+
+    /// A page of the core of a crashed process, and the address it was at
+    /// in that process
+    CorePage(MemoryRange, usize),
+
+    /// A record of the trace buffer
+    TraceEvent(TraceEvent),
+
+    /// How long the kernel has spent handling a syscall
+    SyscallLatency(SyscallLatency),
+
+    /// A sample taken by the profiler
+    ProfileSample(ProfileSample),
+
     UnknownResult(usize, usize, usize, usize, usize, usize, usize),
 }
 
@@ -472,28 +1357,118 @@ impl Result {
             Result::ReadyThreads(count, pid0, ctx0, pid1, ctx1, pid2, ctx2) => {
                 [4, *count, *pid0, *ctx0, *pid1, *ctx1, *pid2, *ctx2]
             }
-            Result::ResumeProcess => [5, 0, 0, 0, 0, 0, 0, 0],
             Result::ServerID(sid) => {
                 let s = sid.to_u32();
                 [6, s.0 as _, s.1 as _, s.2 as _, s.3 as _, 0, 0, 0]
             }
             Result::ConnectionID(cid) => [7, *cid, 0, 0, 0, 0, 0, 0],
+            // There's no room left for the message type, so it goes in the
+            // upper bits of the tag.
             Result::Message(me) => {
                 let me_enc = me.to_usize();
                 [
-                    8, me_enc[0], me_enc[1], me_enc[2], me_enc[3], me_enc[4], me_enc[5], me_enc[6],
+                    8 | (me_enc[0] << 8),
+                    me_enc[1],
+                    me_enc[2],
+                    me_enc[3],
+                    me_enc[4],
+                    me_enc[5],
+                    me_enc[6],
+                    me_enc[7],
                 ]
             }
             Result::ThreadID(ctx) => [9, *ctx as usize, 0, 0, 0, 0, 0, 0],
             Result::ProcessID(pid) => [10, pid.get() as _, 0, 0, 0, 0, 0, 0],
             Result::Unimplemented => [11, 0, 0, 0, 0, 0, 0, 0],
-            Result::BlockedProcess => [12, 0, 0, 0, 0, 0, 0, 0],
             Result::Scalar1(a) => [13, *a, 0, 0, 0, 0, 0, 0],
             Result::Scalar2(a, b) => [14, *a, *b, 0, 0, 0, 0, 0],
             Result::NewServerID(sid, cid) => {
                 let s = sid.to_u32();
                 [15, s.0 as _, s.1 as _, s.2 as _, s.3 as _, *cid, 0, 0]
             }
+            Result::ThreadStats(stats) => [
+                16,
+                stats.run_time as u32 as usize,
+                (stats.run_time >> 32) as usize,
+                stats.schedules,
+                stats.voluntary_switches,
+                stats.involuntary_switches,
+                0,
+                0,
+            ],
+            Result::KernelConfig(config) => [
+                17,
+                config.arch as usize,
+                config.page_size,
+                config.features.bits(),
+                config.max_processes,
+                config.max_threads,
+                config.max_servers,
+                config.max_queue_length,
+            ],
+            Result::ServerInfo(info) => [
+                18,
+                info.pid.get() as _,
+                info.queue_length,
+                info.queued,
+                info.outstanding,
+                info.connections,
+                info.parked_threads,
+                0,
+            ],
+            Result::AuditEvent(event) => [
+                19,
+                event.sequence,
+                event.kind as usize,
+                event.pid.get() as _,
+                event.arg,
+                event.arg2,
+                event.timestamp as u32 as usize,
+                (event.timestamp >> 32) as usize,
+            ],
+            Result::CrashReport(report) => [
+                20,
+                report.sequence,
+                report.pid.get() as _,
+                report.ppid.get() as _,
+                report.tid,
+                report.cause,
+                report.pc,
+                report.addr,
+            ],
+            Result::CorePage(range, addr) => {
+                [21, range.addr.get(), range.size.get(), *addr, 0, 0, 0, 0]
+            }
+            Result::TraceEvent(event) => [
+                22,
+                event.kind as usize,
+                event.pid.get() as _,
+                event.tid,
+                event.arg,
+                event.timestamp as u32 as usize,
+                (event.timestamp >> 32) as usize,
+                event.dropped,
+            ],
+            Result::SyscallLatency(latency) => [
+                23,
+                latency.calls,
+                latency.total as u32 as usize,
+                (latency.total >> 32) as usize,
+                latency.max,
+                0,
+                0,
+                0,
+            ],
+            Result::ProfileSample(sample) => [
+                24,
+                sample.pid.get() as _,
+                sample.tid,
+                sample.pc,
+                sample.stack[0],
+                sample.stack[1],
+                sample.stack[2],
+                sample.stack[3],
+            ],
             Result::UnknownResult(arg1, arg2, arg3, arg4, arg5, arg6, arg7) => {
                 [usize::MAX, *arg1, *arg2, *arg3, *arg4, *arg5, *arg6, *arg7]
             }
@@ -521,7 +1496,6 @@ impl Result {
                 Result::MemoryRange(MemoryRange { addr, size })
             }
             4 => Result::ReadyThreads(src[1], src[2], src[3], src[4], src[5], src[6], src[7]),
-            5 => Result::ResumeProcess,
             6 => Result::ServerID(SID::from_u32(
                 src[1] as _,
                 src[2] as _,
@@ -529,9 +1503,10 @@ impl Result {
                 src[4] as _,
             )),
             7 => Result::ConnectionID(src[1] as CID),
-            8 => {
+            tag if tag & 0xff == 8 => {
                 let sender = src[1];
-                let message = match src[2] {
+                let connection_data = src[2];
+                let message = match tag >> 8 {
                     0 => match MemoryMessage::from_usize(src[3], src[4], src[5], src[6], src[7]) {
                         None => return Result::Error(Error::InternalError),
                         Some(s) => Message::MutableBorrow(s),
@@ -554,19 +1529,100 @@ impl Result {
                 };
                 Result::Message(MessageEnvelope {
                     sender,
+                    connection_data,
                     body: message,
                 })
             }
             9 => Result::ThreadID(src[1] as TID),
             10 => Result::ProcessID(PID::new(src[1] as _).unwrap()),
             11 => Result::Unimplemented,
-            12 => Result::BlockedProcess,
             13 => Result::Scalar1(src[1]),
             14 => Result::Scalar2(src[1], src[2]),
             15 => Result::NewServerID(
                 SID::from_u32(src[1] as _, src[2] as _, src[3] as _, src[4] as _),
                 src[5] as _,
             ),
+            16 => Result::ThreadStats(ThreadStats {
+                run_time: ((src[2] as u64) << 32) | src[1] as u32 as u64,
+                schedules: src[3],
+                voluntary_switches: src[4],
+                involuntary_switches: src[5],
+            }),
+            17 => match KernelArch::from_usize(src[1]) {
+                None => Result::Error(Error::InternalError),
+                Some(arch) => Result::KernelConfig(KernelConfig {
+                    arch,
+                    page_size: src[2],
+                    features: KernelFeatures::from_bits_truncate(src[3]),
+                    max_processes: src[4],
+                    max_threads: src[5],
+                    max_servers: src[6],
+                    max_queue_length: src[7],
+                }),
+            },
+            18 => match PID::new(src[1] as _) {
+                None => Result::Error(Error::InternalError),
+                Some(pid) => Result::ServerInfo(ServerInfo {
+                    pid,
+                    queue_length: src[2],
+                    queued: src[3],
+                    outstanding: src[4],
+                    connections: src[5],
+                    parked_threads: src[6],
+                }),
+            },
+            19 => match (AuditKind::from_usize(src[2]), PID::new(src[3] as _)) {
+                (Some(kind), Some(pid)) => Result::AuditEvent(AuditEvent {
+                    sequence: src[1],
+                    kind,
+                    pid,
+                    arg: src[4],
+                    arg2: src[5],
+                    timestamp: ((src[7] as u64) << 32) | src[6] as u32 as u64,
+                }),
+                _ => Result::Error(Error::InternalError),
+            },
+            20 => match (PID::new(src[2] as _), PID::new(src[3] as _)) {
+                (Some(pid), Some(ppid)) => Result::CrashReport(CrashReport {
+                    sequence: src[1],
+                    pid,
+                    ppid,
+                    tid: src[4],
+                    cause: src[5],
+                    pc: src[6],
+                    addr: src[7],
+                }),
+                _ => Result::Error(Error::InternalError),
+            },
+            21 => match (MemoryAddress::new(src[1]), MemorySize::new(src[2])) {
+                (Some(addr), Some(size)) => Result::CorePage(MemoryRange { addr, size }, src[3]),
+                _ => Result::Error(Error::InternalError),
+            },
+            22 => match (TraceKind::from_usize(src[1]), PID::new(src[2] as _)) {
+                (Some(kind), Some(pid)) => Result::TraceEvent(TraceEvent {
+                    kind,
+                    pid,
+                    tid: src[3],
+                    arg: src[4],
+                    timestamp: ((src[6] as u64) << 32) | src[5] as u32 as u64,
+                    dropped: src[7],
+                }),
+                _ => Result::Error(Error::InternalError),
+            },
+            23 => Result::SyscallLatency(SyscallLatency {
+                calls: src[1],
+                total: ((src[3] as u64) << 32) | src[2] as u32 as u64,
+                max: src[4],
+            }),
+            24 => match PID::new(src[1] as _) {
+                Some(pid) => Result::ProfileSample(ProfileSample {
+                    pid,
+                    tid: src[2],
+                    pc: src[3],
+                    stack: [src[4], src[5], src[6], src[7]],
+                }),
+                None => Result::Error(Error::InternalError),
+            },
             _ => Result::UnknownResult(src[0], src[1], src[2], src[3], src[4], src[5], src[6]),
         }
     }
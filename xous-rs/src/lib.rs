@@ -10,9 +10,13 @@ pub use macros::xous_main;
 pub mod arch;
 
 pub mod carton;
+pub mod connection;
+#[cfg(feature = "coverage")]
+pub mod coverage;
 pub mod definitions;
 mod messages;
 pub mod syscall;
+pub mod timestamp;
 
 pub use arch::{ProcessArgs, ProcessInit, ProcessKey, ThreadInit};
 pub use definitions::*;
@@ -32,6 +36,28 @@ macro_rules! make_name {
     }};
 }
 
+/// Count each time this point is reached.  Does nothing unless the
+/// `coverage` feature is enabled.
+#[cfg(feature = "coverage")]
+#[macro_export]
+macro_rules! cover {
+    ($name:expr) => {{
+        #[link_section = "xous_coverage"]
+        #[used]
+        static POINT: $crate::coverage::CoveragePoint =
+            $crate::coverage::CoveragePoint::new(concat!(file!(), ":", line!(), ": ", $name));
+        POINT.hit();
+    }};
+}
+
+/// Count each time this point is reached.  Does nothing unless the
+/// `coverage` feature is enabled.
+#[cfg(not(feature = "coverage"))]
+#[macro_export]
+macro_rules! cover {
+    ($name:expr) => {{}};
+}
+
 #[cfg(not(target_os = "none"))]
 #[macro_export]
 macro_rules! maybe_main {
@@ -41,6 +67,7 @@ macro_rules! maybe_main {
         }
         fn main() {
             xous::arch::ensure_connection().unwrap();
+            xous::arch::forward_shutdown_signals();
             unsafe { xous_entry() };
         }
     };
@@ -1,7 +1,9 @@
 use crate::{
-    pid_from_usize, CpuID, Error, MemoryAddress, MemoryFlags, MemoryMessage, MemoryRange,
-    MemorySize, MemoryType, Message, MessageEnvelope, MessageSender, ProcessArgs, ProcessInit,
-    Result, ScalarMessage, SysCallResult, ThreadInit, CID, PID, SID,
+    pid_from_usize, BootStage, CpuID, LatencyStage, Error, MemoryAddress, MemoryFlags, MemoryMessage,
+    MemoryRange, MemorySize, MemoryType, Message, MessageEnvelope, MessageSender, ProcessArgs,
+    ProcessInit, Result, ScalarMessage, SysCallResult, ThreadInit, ThreadStats, CID, PID, SID, TID,
+    KernelConfig, ServerInfo, SyscallFilter, AuditEvent, ScrubLevel, CrashReport, TraceEvent,
+    SyscallLatency, ProfileSample, LogSubsystem, LogLevel, RebootMode, ClockState,
 };
 // use num_derive::FromPrimitive;
 // use num_traits::FromPrimitive;
@@ -25,6 +27,17 @@ pub enum SysCall {
     ///                     page width.
     /// * **OutOfMemory**: A contiguous chunk of memory couldn't be found, or
     ///                    the system's memory size has been exceeded.
+    /// * **MemoryInUse**: Part of the physical range is main RAM that already
+    ///                    belongs to a process, part of the physical range
+    ///                    is an I/O region that belongs to another process,
+    ///                    or part of the virtual range has been set aside
+    ///                    with `ReserveAddressSpace`.
+    /// * **AccessDenied**: The flags request an executable region, and the
+    ///                     process does not hold the JIT capability.  Code
+    ///                     must be mapped writable and then passed to
+    ///                     `FinalizeCode`.  Also returned when a physical
+    ///                     address is given and the process doesn't hold
+    ///                     `Capabilities::PHYSICAL_MEMORY`.
     MapMemory(
         Option<MemoryAddress>, /* phys */
         Option<MemoryAddress>, /* virt */
@@ -36,6 +49,8 @@ pub enum SysCall {
     ///
     /// # Errors
     ///
+    /// * **BadAlignment**: The range doesn't cover whole pages
+    /// * **BadAddress**: The range runs outside of the user area
     UnmapMemory(MemoryRange),
 
     /// Sets the offset and size of a given memory region.  This call may only
@@ -74,6 +89,8 @@ pub enum SysCall {
     ///                     page width.
     /// * **OutOfMemory**: A contiguous chunk of memory couldn't be found, or
     ///                    the system's memory size has been exceeded.
+    /// * **AccessDenied**: The flags request an executable region, and the
+    ///                     process does not hold the JIT capability.
     IncreaseHeap(usize /* number of bytes to add */, MemoryFlags),
 
     /// Remove the given number of bytes from the heap.
@@ -102,6 +119,10 @@ pub enum SysCall {
     ///                        process.
     /// * **MemoryInUse**: The given PID has already been started, and it is not
     ///                    legal to modify memory flags anymore.
+    /// * **AccessDenied**: The new flags would make the region executable, and
+    ///                     the process does not hold the JIT capability.
+    /// * **BadAlignment**: The address isn't page-aligned
+    /// * **BadAddress**: The pages run outside of the user area
     UpdateMemoryFlags(
         MemoryAddress, /* virt */
         usize,         /* number of pages */
@@ -117,7 +138,9 @@ pub enum SysCall {
 
     /// This context will now wait for a message with the given server ID. You
     /// can set up a pool by having multiple threads call `ReceiveMessage` with
-    /// the same SID.
+    /// the same SID.  A server doesn't have to respond to a blocking message
+    /// before receiving the next one: it may hold on to the `MessageSender`
+    /// and respond later, in any order.
     ReceiveMessage(SID),
 
     /// Stop running the given process and return control to the parent. This
@@ -128,25 +151,33 @@ pub enum SysCall {
     ///
     /// * **ProcessNotChild**: The given PID is not a child of the current
     ///   process
+    /// * **InvalidSyscall**: The given CPU does not exist
     ReturnToParentI(PID, CpuID),
 
     /// Claims an interrupt and unmasks it immediately.  The provided function
     /// will be called from within an interrupt context, but using the ordinary
     /// privilege level of the process.
     ///
+    /// Several processes may share one interrupt line.  When it fires, they
+    /// take turns being called, so a level-triggered line that is still
+    /// asserted fires again for the next one.  Claiming an interrupt this
+    /// process already shares replaces its handler.
+    ///
     /// # Errors
     ///
     /// * **InterruptNotFound**: The specified interrupt isn't valid on this
     ///   system
-    /// * **InterruptInUse**: The specified interrupt has already been claimed
+    /// * **InterruptInUse**: Too many processes already share the interrupt
+    /// * **AccessDenied**: The process doesn't hold `Capabilities::INTERRUPTS`
     ClaimInterrupt(
         usize,                 /* IRQ number */
         MemoryAddress,         /* function pointer */
         Option<MemoryAddress>, /* argument */
     ),
 
-    /// Returns the interrupt back to the operating system and masks it again.
-    /// This function is implicitly called when a process exits.
+    /// Returns the interrupt back to the operating system, and masks it again
+    /// if no other process shares it.  This function is implicitly called
+    /// when a process exits.
     ///
     /// # Errors
     ///
@@ -215,13 +246,18 @@ pub enum SysCall {
     /// # Returns
     ///
     /// The ServerId can be assembled to form a 128-bit server ID in native byte
-    /// order.
+    /// order.  A server hash of all zeroes asks the kernel to pick an
+    /// unguessable ID from its random number generator instead.
     ///
     /// # Errors
     ///
     /// * **OutOfMemory**: The server table was full and a new server couldn't
     ///                    be created.
     /// * **ServerExists**: The server hash is already in use.
+    /// * **UnhandledSyscall**: A random ID was asked for, but the kernel has no
+    ///                         TRNG to seed its generator from
+    /// * **LimitReached**: This process already has as many servers as its
+    ///                     quota allows
     CreateServer(SID /* server hash */),
 
     /// Connect to a server.   This turns a 128-bit Serever ID into a 32-bit
@@ -238,6 +274,10 @@ pub enum SysCall {
     /// # Errors
     ///
     /// * **ServerNotFound**: The server could not be found.
+    /// * **AccessDenied**: The server is private, and hasn't allowed this
+    ///                     process to connect
+    /// * **LimitReached**: This process already has as many connections as
+    ///                     its quota allows
     TryConnect(SID /* server id */),
 
     /// Send a message to a server (blocking until it's ready)
@@ -256,17 +296,1023 @@ pub enum SysCall {
     ReturnScalar2(MessageSender, usize, usize),
 
     /// Spawn a new thread
+    ///
+    /// # Errors
+    ///
+    /// * **ThreadNotAvailable**: Every thread slot in this process is in use
+    /// * **LimitReached**: This process already has as many threads as its
+    ///                     quota allows
     CreateThread(ThreadInit),
 
     /// Create a new process, setting the current process as the parent ID.
-    /// Does not start the process immediately.
+    /// Does not start the process immediately.  The new process is given the
+    /// capabilities in `ProcessInit` that the current process holds, and
+    /// the rest are silently dropped.  Each of its quotas is the lower of the
+    /// one in `ProcessInit` and the current process' own.
     CreateProcess(ProcessInit),
 
     /// Terminate the current process, closing all server connections.
     TerminateProcess,
 
-    /// Shut down the entire system
-    Shutdown,
+    /// Shut down the entire system, first wiping as much memory as
+    /// `ScrubLevel` asks for.  Wiping all of RAM takes a while, but leaves
+    /// nothing behind for anybody who gets hold of the device afterwards.
+    ///
+    /// Servers subscribed to `SHUTDOWN_BROADCAST` are told first, and the
+    /// system waits for them to be ready or for the grace period to end, so
+    /// this returns before anything has been shut down.  Asking again while
+    /// a shutdown is on its way only raises the `ScrubLevel`.
+    ///
+    /// # Errors
+    ///
+    /// * **AccessDenied**: The process doesn't hold `Capabilities::SHUTDOWN`
+    /// * **ShareViolation**: The system is already going down for a reboot
+    Shutdown(ScrubLevel),
+
+    /// Print the kernel's coverage counters to its console as one
+    /// `COV <hits> <name>` line per point, and then reset them.
+    ///
+    /// # Errors
+    ///
+    /// * **UnhandledSyscall**: The kernel wasn't built with the `coverage` feature
+    DumpCoverage,
+
+    /// Set the function that is called when a thread in this process raises a
+    /// CPU exception that the kernel can't resolve, such as an illegal
+    /// instruction or an access to unmapped memory.  The handler runs on the
+    /// faulting thread and is passed the cause, the faulting PC, and the
+    /// faulting address.  The thread's state at the time of the fault is not
+    /// preserved, and the thread exits when the handler returns.  A thread
+    /// that faults again while running the handler is not given a second
+    /// call, and crashes the process as if there were no handler.  See
+    /// `SetCrashSupervisor` for what happens then.  Pass `None` to remove
+    /// the handler.
+    ///
+    /// # Errors
+    ///
+    /// * **BadAddress**: The handler is outside of the user area
+    SetExceptionHandler(Option<MemoryAddress> /* handler */),
+
+    /// Give the calling thread an alternate stack to run its exception handler
+    /// on, so that a thread that faults because its own stack overflowed can
+    /// still be handled.  Pass `None` to go back to using the thread's own
+    /// stack.  New threads start out without an alternate stack.
+    ///
+    /// # Errors
+    ///
+    /// * **BadAddress**: The stack is outside of the user area
+    SetExceptionStack(Option<MemoryRange> /* stack */),
+
+    /// Print every page that is mapped into the given process to the
+    /// kernel's console, along with the physical address it is mapped to
+    /// and its flags.  This is intended for working out why a syscall
+    /// returned `BadAddress`.
+    ///
+    /// # Errors
+    ///
+    /// * **ProcessNotFound**: The given PID does not exist
+    /// * **UnhandledSyscall**: The kernel wasn't built with the `print-mappings` feature
+    DumpMemoryMap(PID),
+
+    /// Get the number of times per second that the counter returned by
+    /// `xous::timestamp::now()` increments.
+    ///
+    /// # Errors
+    ///
+    /// * **UnhandledSyscall**: The kernel wasn't told the timebase when it was booted
+    GetTimebase,
+
+    /// Get the timestamp at which the kernel reached the given stage while
+    /// booting, in the same units as `xous::timestamp::now()`.  The value is
+    /// returned as a `Scalar2` of the low and high words.  Stages that the
+    /// kernel never passed through, such as those before the scheduler on a
+    /// hosted system, return `0`.
+    ///
+    /// # Errors
+    ///
+    /// * **InvalidSyscall**: The stage number was not recognized
+    BootStageTime(BootStage),
+
+    /// Set the scheduling priority of the calling process.  Higher numbers
+    /// are more important, and runnable processes with a higher priority are
+    /// always scheduled ahead of those with a lower one.  Processes start out
+    /// at priority `0`, and may go as high as their parent's priority when it
+    /// created them.  Going any higher needs `Capabilities::SCHEDULING`.
+    ///
+    /// A server inherits the priority of the most important client that is
+    /// blocked waiting on one of its messages, for as long as that client
+    /// remains blocked.  This keeps a low-priority server from holding up a
+    /// high-priority client.  The priority the process is actually running
+    /// at, including anything it has inherited, is returned as a `Scalar1`.
+    ///
+    /// # Errors
+    ///
+    /// * **AccessDenied**: The priority is above the process' ceiling, and it
+    ///                     doesn't hold `Capabilities::SCHEDULING`
+    /// * **InvalidSyscall**: The priority does not fit in a `u8`
+    SetPriority(u8 /* priority */),
+
+    /// Set how many timer ticks a process in the given priority class may
+    /// run for before it is preempted and another process gets a turn.
+    /// Priorities are split into four classes: `0-63`, `64-127`, `128-191`
+    /// and `192-255`.  The previous quantum is returned as a `Scalar1`.
+    /// Preemption only happens if the kernel was given a tick timer when it
+    /// was booted.  The quanta are shared by every process, so changing them
+    /// needs `Capabilities::SCHEDULING`.
+    ///
+    /// # Errors
+    ///
+    /// * **AccessDenied**: The process doesn't hold `Capabilities::SCHEDULING`
+    /// * **InvalidSyscall**: The class doesn't exist, or the quantum is `0`
+    SetQuantum(usize /* priority class */, usize /* ticks */),
+
+    /// Release a connection.  The kernel counts how many times each
+    /// connection has been made, and the Connection ID is only freed once it
+    /// has been disconnected as many times.  A process' connection to its
+    /// own server is never freed, since replies are routed through it.
+    ///
+    /// # Errors
+    ///
+    /// * **ServerNotFound**: The Connection ID is not in use
+    Disconnect(CID),
+
+    /// Turn a range of memory that code has been written to into
+    /// read-only, executable memory.  This is the only way for a process
+    /// without the JIT capability to get executable memory: map it writable,
+    /// fill it in, and then finalize it.  The range can never be made
+    /// writable again, and must be unmapped to reuse it.
+    ///
+    /// Every page must be backed by main RAM, and must have been written to
+    /// at least once so that it is present.
+    ///
+    /// # Errors
+    ///
+    /// * **BadAlignment**: The range isn't page-aligned
+    /// * **BadAddress**: A page in the range isn't mapped
+    /// * **ShareViolation**: A page is lent out, or shared with another
+    ///                       process
+    /// * **AccessDenied**: A page isn't backed by main RAM
+    FinalizeCode(MemoryRange),
+
+    /// Read eight bytes of a boot measurement.  Measurement `0` is the
+    /// measurement register, which the kernel extended with the SHA3-256
+    /// hash of each boot image in turn, starting with the kernel.  Any other
+    /// measurement is the hash of the image that was loaded as that PID.
+    ///
+    /// Measurements are 32 bytes long, so `chunk` is between `0` and `3`.
+    /// The bytes come back as two little-endian 32-bit words in a
+    /// `Scalar2`.
+    ///
+    /// # Errors
+    ///
+    /// * **InvalidSyscall**: There is no such measurement, or `chunk` is out
+    ///                       of range
+    GetMeasurement(usize /* index */, usize /* chunk */),
+
+    /// Get scheduler statistics for thread `TID` of process `PID`.  Any
+    /// process may ask about any thread, so that tools like `top` can show
+    /// where CPU time is going.  Threads that have never run report all
+    /// zeroes.
+    ///
+    /// # Errors
+    ///
+    /// * **ProcessNotFound**: The process does not exist
+    /// * **InvalidThread**: The thread ID is out of range
+    QueryScheduler(PID, TID),
+
+    /// Move the calling thread into the deadline scheduling class, reserving
+    /// `budget` timer ticks out of every `period`.  Threads with budget left
+    /// are run earliest deadline first, ahead of all normal priorities.  A
+    /// thread that uses up its budget is scheduled at its process' normal
+    /// priority until the next period begins.  A period and budget of `0`
+    /// return the thread to the normal class, which any thread may do.
+    ///
+    /// # Errors
+    ///
+    /// * **AccessDenied**: The process doesn't hold `Capabilities::SCHEDULING`
+    /// * **InvalidSyscall**: The budget is `0` or longer than the period
+    /// * **OutOfMemory**: Too many threads have reservations, or the new
+    ///                    reservation would take more of the CPU than the
+    ///                    deadline class may have
+    SetDeadline(usize /* period */, usize /* budget */),
+
+    /// Estimate the latency of messages to the server at the other end of
+    /// connection `CID`, returning the `percentile` latency of `stage` and
+    /// the number of messages it was estimated from as a `Scalar2`.  The
+    /// kernel keeps a random sample of latencies for every server, so this
+    /// needs no help from the server or its clients.  Latencies are in the
+    /// same units as `xous::timestamp::now()`, and are capped at
+    /// `usize::MAX`.
+    ///
+    /// # Errors
+    ///
+    /// * **ServerNotFound**: The connection doesn't exist
+    /// * **InvalidSyscall**: The percentile is greater than `100`
+    QueryLatency(CID, LatencyStage, usize /* percentile */),
+
+    /// Give the rest of this time slice to thread `TID` of process `PID`,
+    /// which is typically a server that has just been sent a message.  The
+    /// target runs in the caller's place, and goes back to wherever the
+    /// caller would have gone when it yields.  If the target isn't ready to
+    /// run, this behaves like `Yield`.
+    ///
+    /// # Errors
+    ///
+    /// * **ProcessNotFound**: The process doesn't exist
+    /// * **InvalidThread**: The thread ID isn't valid
+    YieldTo(PID, TID),
+
+    /// Create a new server like `CreateServer`, but able to queue `usize`
+    /// messages rather than the default of one page's worth.  The kernel
+    /// allocates the pages the queue needs from the calling process.
+    ///
+    /// # Errors
+    ///
+    /// * **OutOfMemory**: The server table was full, the queue's pages
+    ///                    couldn't be allocated, or the queue length is
+    ///                    more than the kernel allows
+    /// * **InvalidSyscall**: The queue length is `0`
+    /// * **ServerExists**: The server hash is already in use.
+    CreateServerWithQueue(SID /* server hash */, usize /* queue length */),
+
+    /// Set aside `size` bytes of address space, without backing them with
+    /// memory, so that a dynamic loader can place an image's segments inside
+    /// it with `MapSegment`.  If a virtual address is specified, the
+    /// reservation starts there.  Otherwise it is placed at the Default
+    /// offset.  Nothing else is ever mapped into a reservation, and it lasts
+    /// until the process exits.
+    ///
+    /// Returns a `MemoryRange` describing the reservation.
+    ///
+    /// # Errors
+    ///
+    /// * **BadAlignment**: The address or size isn't page-aligned
+    /// * **BadAddress**: The range is outside of the user area
+    /// * **MemoryInUse**: Part of the range is already mapped or reserved
+    /// * **OutOfMemory**: The reservation table is full, or no free range is
+    ///                    big enough
+    ReserveAddressSpace(
+        Option<MemoryAddress>, /* virt */
+        MemorySize,            /* region size */
+    ),
+
+    /// Map `MemorySize` bytes of fresh memory at `usize` bytes into the
+    /// reservation that starts at `MemoryAddress`, and remember it as one of
+    /// the process' image segments.  Pages are allocated when they are first
+    /// touched, as with `MapMemory`.  Executable segments must be mapped
+    /// writable and then passed to `FinalizeCode`.
+    ///
+    /// Returns a `MemoryRange` describing the segment.
+    ///
+    /// # Errors
+    ///
+    /// * **BadAlignment**: The offset or size isn't page-aligned
+    /// * **BadAddress**: There is no reservation at that address, or the
+    ///                   segment doesn't fit inside it
+    /// * **MemoryInUse**: The segment overlaps one that is already mapped
+    /// * **OutOfMemory**: The segment table is full
+    /// * **AccessDenied**: The flags request an executable segment, and the
+    ///                     process does not hold the JIT capability
+    MapSegment(
+        MemoryAddress, /* reservation */
+        usize,         /* offset */
+        MemorySize,    /* segment size */
+        MemoryFlags,   /* flags */
+    ),
+
+    /// Share the image segment that starts at the first `MemoryAddress` with
+    /// child process `PID`, placing it at the same offset into the child's
+    /// reservation that starts at the second `MemoryAddress`.  This lets a
+    /// library be loaded once and used by every process it's shared with.
+    /// Read-only pages such as text are mapped into both processes, and
+    /// writable pages such as the GOT are copy-on-write, so each process can
+    /// apply its own relocations.  Every page of the segment must already
+    /// have been touched.
+    ///
+    /// Returns a `MemoryRange` describing the segment in the child.
+    ///
+    /// # Errors
+    ///
+    /// * **BadAddress**: There is no segment at the source address, part of
+    ///                   it hasn't been touched, or it doesn't fit in the
+    ///                   child's reservation
+    /// * **ProcessNotFound**: The child process doesn't exist
+    /// * **AccessDenied**: The process isn't a child of the caller
+    /// * **MemoryInUse**: The segment overlaps one the child already has
+    /// * **ShareViolation**: Part of the segment is currently lent
+    /// * **OutOfMemory**: Too many segments or pages are already shared
+    ShareSegment(
+        MemoryAddress, /* segment */
+        PID,           /* child */
+        MemoryAddress, /* child's reservation */
+    ),
+
+    /// Attach a word of data to the connection that sent the message
+    /// `MessageSender`.  The kernel hands the word back in the
+    /// `connection_data` of every later envelope from that connection, so
+    /// servers can find their state for a client without having to look it
+    /// up.  Setting the data to `0` detaches it.  The data is forgotten once
+    /// the client disconnects or exits.
+    ///
+    /// # Errors
+    ///
+    /// * **ServerNotFound**: The message wasn't sent to a server owned by
+    ///                       this process
+    /// * **ProcessNotFound**: `MessageSender` doesn't name a sending process
+    /// * **OutOfMemory**: Data is already attached to too many connections
+    SetConnectionData(MessageSender, usize),
+
+    /// Create a notification, a counting semaphore for telling threads that
+    /// something happened without the cost of sending a message.  The
+    /// notification belongs to the calling process, and only its threads
+    /// may wait on it, but any process that knows its ID may signal it.
+    /// The notification is destroyed when the process exits.
+    ///
+    /// Returns: a `Scalar1` containing the ID of the new notification
+    ///
+    /// # Errors
+    ///
+    /// * **OutOfMemory**: Too many notifications already exist
+    CreateNotification,
+
+    /// Signal notification `usize` `usize` times.  Each signal wakes up one
+    /// thread waiting on the notification, oldest first, and signals that
+    /// nobody is waiting for are counted for later waits to consume.
+    ///
+    /// # Errors
+    ///
+    /// * **InvalidSyscall**: The notification doesn't exist
+    SignalNotification(usize /* id */, usize /* count */),
+
+    /// Block the current thread until notification `usize` is signalled,
+    /// returning immediately if a signal is already counted.
+    ///
+    /// # Errors
+    ///
+    /// * **InvalidSyscall**: The notification doesn't exist
+    /// * **AccessDenied**: The notification belongs to another process
+    /// * **OutOfMemory**: Too many threads are already waiting on it
+    WaitNotification(usize /* id */),
+
+    /// Consume a signal from notification `usize` if one is counted, without
+    /// blocking.
+    ///
+    /// Returns: a `Scalar1` that is `1` if a signal was consumed, or `0` if
+    ///          there was none
+    ///
+    /// # Errors
+    ///
+    /// * **InvalidSyscall**: The notification doesn't exist
+    /// * **AccessDenied**: The notification belongs to another process
+    PollNotification(usize /* id */),
+
+    /// Create a poll set, which lets one thread wait for a message on any of
+    /// several servers owned by the calling process.  The poll set is
+    /// destroyed when the process exits.
+    ///
+    /// Returns: a `Scalar1` containing the ID of the new poll set
+    ///
+    /// # Errors
+    ///
+    /// * **OutOfMemory**: Too many poll sets already exist
+    CreatePollSet,
+
+    /// Add server `SID` to poll set `usize`.  Adding a server that is
+    /// already in the set does nothing.
+    ///
+    /// Returns: a `Scalar1` containing the `CID` that messages to this server
+    ///          arrive on, as returned by `MessageEnvelope::server_cid()`
+    ///
+    /// # Errors
+    ///
+    /// * **InvalidSyscall**: The poll set doesn't exist
+    /// * **AccessDenied**: The poll set belongs to another process
+    /// * **ServerNotFound**: The server doesn't exist or belongs to another
+    ///                       process
+    /// * **OutOfMemory**: The poll set is full
+    AddToPollSet(usize /* id */, SID),
+
+    /// Receive the next message sent to any server in poll set `usize`,
+    /// blocking until one arrives.  Servers are checked for waiting
+    /// messages in the order they were added.
+    ///
+    /// Returns: a `Message` whose `server_cid()` tells which server it was
+    ///          sent to
+    ///
+    /// # Errors
+    ///
+    /// * **InvalidSyscall**: The poll set doesn't exist
+    /// * **AccessDenied**: The poll set belongs to another process
+    /// * **ServerNotFound**: None of the servers in the set exist anymore
+    /// * **OutOfMemory**: Too many threads are already waiting on poll sets
+    ReceiveAny(usize /* id */),
+
+    /// Describe how the kernel was built: its architecture, page size,
+    /// optional features, and limits.
+    ///
+    /// Returns: a `KernelConfig`
+    GetKernelConfig,
+
+    /// Pass a `Borrow`, `MutableBorrow`, or `BlockingScalar` message that
+    /// one of this process' servers received on to the server at the other
+    /// end of connection `CID`, without copying it.  `MessageSender` is the
+    /// sender of the received message, and `Message` is the message as it
+    /// should be delivered, which must use the buffer that was received.  The
+    /// new server sees the message as coming from the original client, and
+    /// its response goes straight back to that client, so this process must
+    /// not respond to the message itself.  Forwarded memory is no longer
+    /// accessible to this process.
+    ///
+    /// # Errors
+    ///
+    /// * **ServerNotFound**: Either server doesn't exist, or the message
+    ///                       wasn't received by this process
+    /// * **InvalidSyscall**: The message can't be forwarded, or is a
+    ///                       different kind than the one that was received
+    /// * **BadAddress**: The buffer isn't the one that was received
+    /// * **ServerQueueFull**: The new server's queue is full
+    /// * **ShareViolation**: Tried to forward memory mutably that was only
+    ///                       lent immutably
+    ForwardMessage(MessageSender, CID, Message),
+
+    /// Find the first message to server `SID` in queue slot `usize` or later
+    /// that the server has received but not yet responded to.  This is how a
+    /// server that defers its responses finds every client still waiting on
+    /// it, such as when shutting down.
+    ///
+    /// Returns: a `Scalar1` containing the `MessageSender` to respond to, or
+    ///          `0` if there are no more.  The slot is the lower 16 bits of
+    ///          the `MessageSender`, so the search may be continued from one
+    ///          past it.
+    ///
+    /// # Errors
+    ///
+    /// * **ServerNotFound**: The server doesn't exist or belongs to another
+    ///                       process
+    NextOutstandingSender(SID, usize /* slot */),
+
+    /// Signal notification `usize` once the server at the other end of
+    /// connection `CID` goes away, such as when its process terminates.
+    /// The connection is useless after that, and should be disconnected
+    /// before connecting to the server again once it has restarted.  Each
+    /// process may watch a connection with one notification, and watching
+    /// with `0` stops watching it.
+    ///
+    /// # Errors
+    ///
+    /// * **ServerNotFound**: The server has already gone away
+    /// * **InvalidSyscall**: The notification doesn't exist
+    /// * **AccessDenied**: The notification belongs to another process
+    /// * **OutOfMemory**: Too many processes are already watching the server
+    WatchDisconnect(CID, usize /* notification */),
+
+    /// Send a message to a server and wait for it to reply, as one call.  A
+    /// `Scalar` message is delivered as a `BlockingScalar`, so the server
+    /// must respond to it, and lends block until they are returned as usual.
+    /// Since the client is already waiting, the server may answer before
+    /// this thread is even rescheduled, and the kernel is free to switch
+    /// straight to the server.
+    ///
+    /// Returns: the reply, which is a `Scalar1` or `Scalar2` for scalar
+    ///          messages, and `Ok` once lent memory has been returned.
+    ///
+    /// # Errors
+    ///
+    /// * **InvalidSyscall**: The message is a `Move`, which can't be replied to
+    /// * Any error that `TrySendMessage` may return
+    CallMessage(CID, Message),
+
+    /// Create a broadcast list, which lets the calling process send the same
+    /// scalar message to many servers with a single call.  Only the calling
+    /// process may broadcast on the list, but any process that knows its ID
+    /// may subscribe to it.  The list is destroyed when the process exits.
+    ///
+    /// Returns: a `Scalar1` containing the ID of the new broadcast list
+    ///
+    /// # Errors
+    ///
+    /// * **OutOfMemory**: Too many broadcast lists already exist
+    CreateBroadcast,
+
+    /// Subscribe the server at the other end of connection `CID` to
+    /// broadcast list `usize`, so that each broadcast arrives there as a
+    /// `Scalar` message whose ID is the given opcode.  Subscribing the same
+    /// server again changes its opcode.  A subscription ends when the
+    /// subscribing process exits or the server goes away.
+    ///
+    /// # Errors
+    ///
+    /// * **InvalidSyscall**: The broadcast list doesn't exist
+    /// * **ServerNotFound**: The connection isn't valid
+    /// * **OutOfMemory**: The broadcast list is full
+    Subscribe(usize /* id */, CID, usize /* opcode */),
+
+    /// Stop delivering broadcasts on list `usize` to the server at the other
+    /// end of connection `CID`.  Unsubscribing a server that isn't
+    /// subscribed does nothing.
+    ///
+    /// # Errors
+    ///
+    /// * **InvalidSyscall**: The broadcast list doesn't exist
+    /// * **ServerNotFound**: The connection isn't valid
+    Unsubscribe(usize /* id */, CID),
+
+    /// Send a `Scalar` message with the given arguments to every server
+    /// subscribed to broadcast list `usize`, each with its own opcode as the
+    /// message ID.  This never blocks: servers whose queues are full miss
+    /// the message.
+    ///
+    /// Returns: a `Scalar1` containing the number of servers the message
+    ///          was delivered to
+    ///
+    /// # Errors
+    ///
+    /// * **InvalidSyscall**: The broadcast list doesn't exist
+    /// * **AccessDenied**: The broadcast list belongs to another process
+    Broadcast(
+        usize, /* id */
+        usize, /* arg1 */
+        usize, /* arg2 */
+        usize, /* arg3 */
+        usize, /* arg4 */
+    ),
+
+    /// Get the time at which the message that the calling thread most
+    /// recently received was queued for its server, in the same units as
+    /// `xous::timestamp::now()`.  Messages that were handed straight to a
+    /// thread that was waiting for them are stamped as they're delivered.
+    /// This lets a server tell how long a message waited, or drop messages
+    /// that are too old to be worth handling.  The value is returned as a
+    /// `Scalar2` of the low and high words, and is `0` if the thread hasn't
+    /// received a message.
+    MessageTimestamp,
+
+    /// Limit how long servers may hold memory that the calling process
+    /// lends them, in the same units as `xous::timestamp::now()`.  If a
+    /// server hasn't returned the memory in time, the kernel takes it back
+    /// and reports the server, and the thread that lent it is woken with a
+    /// `Timeout` error.  A limit of `0`, which is the default, lets servers
+    /// hold memory for as long as they like.  This only applies to memory
+    /// lent after it's set, and deadlines are checked on each timer tick, so
+    /// kernels without a tick timer never enforce them.
+    ///
+    /// Returns: a `Scalar1` containing the previous limit
+    SetLendTimeout(usize /* ticks */),
+
+    /// Describe the server in slot `usize` of the kernel's server table,
+    /// for tools like `lsipc` that list every server.  Slots are numbered
+    /// from `0`, and empty slots are skipped over by returning
+    /// `ServerNotFound`, so a tool can walk the table until it gets
+    /// `InvalidSyscall`.
+    ///
+    /// Returns: a `ServerInfo` describing the server
+    ///
+    /// # Errors
+    ///
+    /// * **AccessDenied**: The process doesn't hold `Capabilities::SUPERVISE`
+    /// * **ServerNotFound**: There is no server in that slot
+    /// * **InvalidSyscall**: The slot is past the end of the table
+    QueryServices(usize /* index */),
+
+    /// Promise that the calling process will call `Heartbeat` at least once
+    /// every `usize` ticks, in the same units as `xous::timestamp::now()`,
+    /// starting now.  A process that misses its deadline is reported to the
+    /// watchdog supervisor, and if there is no supervisor, a baremetal
+    /// kernel panics so that the hardware watchdog resets the device.  Pass
+    /// `0` to stop being watched.
+    ///
+    /// # Errors
+    ///
+    /// * **OutOfMemory**: Too many processes already have a heartbeat
+    SetHeartbeat(usize /* ticks */),
+
+    /// Tell the watchdog that the calling process is still alive, giving it
+    /// a fresh deadline.  This is as cheap as a system call gets, so it may
+    /// be called as often as is convenient.
+    ///
+    /// # Errors
+    ///
+    /// * **InvalidSyscall**: The process hasn't called `SetHeartbeat`
+    Heartbeat,
+
+    /// Make the calling process the watchdog supervisor, which has
+    /// notification `usize` signalled once for each process that misses
+    /// its heartbeat, and then finds out which with `TakeMissedHeartbeat`.
+    /// There is only one supervisor, so this replaces any previous one.
+    /// Pass `0` to stop having a supervisor.
+    ///
+    /// # Errors
+    ///
+    /// * **AccessDenied**: The process doesn't hold `Capabilities::SUPERVISE`,
+    ///                     or doesn't own the notification
+    /// * **InvalidSyscall**: The notification doesn't exist
+    SetWatchdogSupervisor(usize /* notification */),
+
+    /// Find out which process missed its heartbeat.  The process is given a
+    /// fresh deadline, so it's reported again if it stays stuck.
+    ///
+    /// Returns: a `ProcessID` of the process that missed its heartbeat
+    ///
+    /// # Errors
+    ///
+    /// * **AccessDenied**: The calling process isn't the watchdog supervisor
+    /// * **ProcessNotFound**: Every process is keeping up
+    TakeMissedHeartbeat,
+
+    /// Claims an interrupt like `ClaimInterrupt`, but rather than calling a
+    /// function, each interrupt is delivered as a `Scalar` message with the
+    /// given opcode to the server at the other end of connection `CID`, so
+    /// that a driver can handle it in its normal receive loop.  The first
+    /// argument is the IRQ number, and the second is how many times it
+    /// fired.  Interrupts that fire while the last message is still queued
+    /// are added to that message instead of queueing another one.  Every
+    /// process sharing the line that wants a message gets one.
+    ///
+    /// # Errors
+    ///
+    /// * **InterruptNotFound**: The specified interrupt isn't valid on this
+    ///   system
+    /// * **InterruptInUse**: Too many processes already share the interrupt
+    /// * **ServerNotFound**: The connection isn't valid
+    /// * **AccessDenied**: The process doesn't hold `Capabilities::INTERRUPTS`
+    ClaimInterruptMessage(usize /* IRQ number */, CID, usize /* opcode */),
+
+    /// Start telling this process about interrupt `usize` again after a call
+    /// to `DisableIrq`.
+    ///
+    /// # Errors
+    ///
+    /// * **InterruptNotFound**: The interrupt doesn't exist, or this process
+    ///   hasn't claimed it
+    EnableIrq(usize /* IRQ number */),
+
+    /// Stop telling this process about interrupt `usize`, so that a driver
+    /// can mask its source while handling it.  Other processes sharing the
+    /// line are still told about it, and the line itself is only masked
+    /// once every process sharing it has disabled it.
+    ///
+    /// # Errors
+    ///
+    /// * **InterruptNotFound**: The interrupt doesn't exist, or this process
+    ///   hasn't claimed it
+    DisableIrq(usize /* IRQ number */),
+
+    /// Get the number of times the kernel's tick timer has fired since
+    /// boot.  This never goes backwards, and keeps counting while the CPU
+    /// is idle.  How long a tick lasts depends on the platform, and in a
+    /// hosted environment it is one millisecond.
+    ///
+    /// Returns: a `Scalar2` containing the low and high halves of the count
+    ///
+    /// # Errors
+    ///
+    /// * **UnhandledSyscall**: The kernel wasn't given a tick timer
+    GetTicks,
+
+    /// Create a timer that sends a `Scalar` message with the given opcode to
+    /// the server at the other end of connection `CID` after the given
+    /// number of milliseconds, and then again every period if the period
+    /// isn't `0`.  The message's first argument is the ID of the timer.  A
+    /// periodic timer that falls behind skips the expiries it missed.  The
+    /// timer is destroyed when the process exits or the server goes away.
+    ///
+    /// Returns: a `Scalar1` containing the ID of the new timer
+    ///
+    /// # Errors
+    ///
+    /// * **ServerNotFound**: The connection isn't valid
+    /// * **OutOfMemory**: Too many timers already exist
+    /// * **UnhandledSyscall**: The kernel doesn't know how fast time passes
+    CreateTimer(
+        CID,
+        usize, /* opcode */
+        usize, /* delay in milliseconds */
+        usize, /* period in milliseconds */
+    ),
+
+    /// Stop timer `usize` before it expires again.
+    ///
+    /// # Errors
+    ///
+    /// * **InvalidSyscall**: The timer doesn't exist
+    /// * **AccessDenied**: The timer belongs to another process
+    CancelTimer(usize /* id */),
+
+    /// Put the calling thread to sleep for `usize` milliseconds.  The thread
+    /// uses no CPU time while it sleeps, and is woken on the first timer
+    /// tick after its deadline.  Sleeping for `0` milliseconds returns
+    /// right away.
+    ///
+    /// # Errors
+    ///
+    /// * **OutOfMemory**: Too many threads are already sleeping
+    /// * **UnhandledSyscall**: The kernel doesn't know how fast time passes
+    SleepThread(usize /* milliseconds */),
+
+    /// Fire interrupt `usize` from software, as though the hardware had
+    /// raised it, so that interrupt handling can be exercised on demand.  On
+    /// hardware the interrupt is taken as soon as this call returns.  In a
+    /// hosted environment its messages are delivered straight away, and
+    /// callbacks are never made.
+    ///
+    /// # Errors
+    ///
+    /// * **AccessDenied**: The process doesn't hold `Capabilities::INTERRUPTS`
+    /// * **InterruptNotFound**: The interrupt doesn't exist
+    RaiseIrq(usize /* IRQ number */),
+
+    /// Limit the syscalls that child process `PID` may make to the ones in
+    /// the filter.  Anything else fails with `AccessDenied`.  This must be
+    /// done after `CreateProcess` and before the child starts running.  A
+    /// child starts out with its parent's filter, and installing another
+    /// one only takes syscalls away, so a confined process can't create a
+    /// child that is less confined than itself.
+    ///
+    /// # Errors
+    ///
+    /// * **ProcessNotChild**: The process isn't a child of this one
+    /// * **AccessDenied**: The process has already started running
+    SetSyscallFilter(PID, SyscallFilter),
+
+    /// Give the physical I/O region at `MemoryAddress` to process `PID`
+    /// before any other process maps it.  Pages outside main RAM, such as a
+    /// peripheral's registers, belong to the first process that maps them,
+    /// and `MapMemory` fails with `MemoryInUse` for anybody else until that
+    /// process exits, even if it unmaps them.
+    ///
+    /// # Errors
+    ///
+    /// * **AccessDenied**: The process doesn't hold `Capabilities::IO_REGIONS`
+    /// * **BadAlignment**: The address or size isn't page-aligned
+    /// * **ProcessNotFound**: The process doesn't exist
+    /// * **MemoryInUse**: Part of the region belongs to another process
+    /// * **OutOfMemory**: Too many regions are already owned
+    AssignIoRegion(PID, MemoryAddress /* phys */, MemorySize),
+
+    /// Get two words of randomness from the kernel's random number
+    /// generator, which is seeded from the hardware TRNG early in boot.  This
+    /// is meant for things that need randomness before the TRNG server is
+    /// running, such as stack canaries.
+    ///
+    /// Returns: a `Scalar2` of random words
+    ///
+    /// # Errors
+    ///
+    /// * **UnhandledSyscall**: The kernel has no TRNG to seed its generator
+    ///                         from
+    GetRandom,
+
+    /// Stir four words into the kernel's random number generator, such as
+    /// output from the TRNG server.  This can't make the generator's output
+    /// any easier to guess, so any process may do it.
+    AddEntropy(usize, usize, usize, usize),
+
+    /// Make a server that belongs to this process private.  From then on,
+    /// only this process and the processes it allows with `AllowConnect` may
+    /// connect to it.  Connections that were made before are left alone.
+    ///
+    /// # Errors
+    ///
+    /// * **ServerNotFound**: This process doesn't have a server with that ID
+    MakeServerPrivate(SID),
+
+    /// Let a process connect to a private server that belongs to this
+    /// process.  The permission goes away when that process exits.
+    ///
+    /// # Errors
+    ///
+    /// * **ServerNotFound**: This process doesn't have a server with that ID
+    /// * **ProcessNotFound**: The process doesn't exist
+    /// * **OutOfMemory**: The server already allows as many processes as it
+    ///                    can
+    AllowConnect(SID, PID),
+
+    /// Read the oldest entry of the kernel's audit log whose sequence number
+    /// is at least `usize`.  The kernel records security-relevant events,
+    /// such as processes being created or being denied a capability, in a
+    /// small ring that overwrites its oldest entries, so an auditor should
+    /// keep reading from one past the last sequence number it saw.
+    ///
+    /// Returns: an `AuditEvent`, or `Ok` if there is no such entry yet
+    ///
+    /// # Errors
+    ///
+    /// * **AccessDenied**: The process doesn't hold `Capabilities::AUDIT`
+    ReadAuditLog(usize /* sequence */),
+
+    /// Make the calling process the crash supervisor, which has notification
+    /// `usize` signalled once for each process that crashes, and then finds
+    /// out what happened with `ReadCrashReport`.  A process crashes when it
+    /// faults without an exception handler to deal with it, and the kernel
+    /// terminates it rather than halting the system.  There is only one
+    /// supervisor, so this replaces any previous one.  Pass `0` to stop
+    /// having a supervisor.
+    ///
+    /// # Errors
+    ///
+    /// * **AccessDenied**: The process doesn't hold `Capabilities::SUPERVISE`,
+    ///                     or doesn't own the notification
+    /// * **InvalidSyscall**: The notification doesn't exist
+    SetCrashSupervisor(usize /* notification */),
+
+    /// Read the oldest crash report whose sequence number is at least
+    /// `usize`.  The crash supervisor may read every report, and any other
+    /// process only the reports of its own children.  Only the last few
+    /// reports are kept, so a reader should keep reading from one past the
+    /// last sequence number it saw.
+    ///
+    /// Returns: a `CrashReport`, or `Ok` if there is no such report yet
+    ReadCrashReport(usize /* sequence */),
+
+    /// Read word `index` of the registers and stack saved with crash report
+    /// `sequence`.  The first `CRASH_REGISTERS` words are the registers,
+    /// and the next `CRASH_STACK_WORDS` are the top of the stack, starting
+    /// at the stack pointer.
+    ///
+    /// Returns: a `Scalar1` holding the word
+    ///
+    /// # Errors
+    ///
+    /// * **AccessDenied**: The calling process may not read the report
+    /// * **InvalidSyscall**: The report is no longer kept, or `index` is
+    ///                       past the end
+    ReadCrashWord(usize /* sequence */, usize /* index */),
+
+    /// Take the next page of the core of crash report `usize`, which is
+    /// mapped into the calling process.  When there is a crash supervisor,
+    /// the kernel keeps the writable pages of a process that crashes until
+    /// the supervisor has taken all of them, so that it can save them or
+    /// send them off the device.  Only one core is kept at a time, so a
+    /// process that crashes while the supervisor is still taking a core
+    /// gets only a report.  Unmap each page once it has been dealt with.
+    ///
+    /// Returns: a `CorePage` with where the page is now, and where it was in
+    /// the crashed process, or `Ok` if every page has been taken or there
+    /// is no core of that crash
+    ///
+    /// # Errors
+    ///
+    /// * **AccessDenied**: The calling process isn't the crash supervisor
+    /// * **OutOfMemory**: There's no room for the page in the calling process
+    TakeCorePage(usize /* sequence */),
+
+    /// Take the oldest record out of the kernel's trace buffer.  A kernel
+    /// built with the `trace` feature records syscalls, context switches
+    /// and messages in a ring in memory, which is much less disruptive than
+    /// printing them, and a trace collector drains it to send the records
+    /// off the device.  Records are overwritten if they aren't drained fast
+    /// enough, and each record says how many were lost before it.  The
+    /// collector's own calls to this aren't recorded.
+    ///
+    /// Returns: a `TraceEvent`, or `Ok` if the buffer is empty
+    ///
+    /// # Errors
+    ///
+    /// * **AccessDenied**: The process doesn't hold `Capabilities::TRACE`
+    /// * **UnhandledSyscall**: The kernel wasn't built with the `trace` feature
+    ReadTrace,
+
+    /// Read how long the kernel has spent handling syscall number `usize`.
+    /// A kernel built with the `syscall-latency` feature times every
+    /// syscall with the cycle counter, from when it enters the kernel to
+    /// when the kernel is done with it, so a call that blocks is only
+    /// timed until the kernel switches away from it.
+    ///
+    /// Returns: a `SyscallLatency`
+    ///
+    /// # Errors
+    ///
+    /// * **AccessDenied**: The process doesn't hold `Capabilities::TRACE`
+    /// * **InvalidSyscall**: `usize` is too large to be a syscall number
+    /// * **UnhandledSyscall**: The kernel wasn't built with the
+    ///                         `syscall-latency` feature
+    ReadSyscallLatency(usize /* number */),
+
+    /// Read bucket `bucket` of the latency histogram of syscall number
+    /// `number`.  See `LATENCY_BUCKETS` for what each bucket counts.
+    ///
+    /// Returns: a `Scalar1` holding the number of calls in the bucket
+    ///
+    /// # Errors
+    ///
+    /// * **AccessDenied**: The process doesn't hold `Capabilities::TRACE`
+    /// * **InvalidSyscall**: `number` is too large to be a syscall number,
+    ///                       or `bucket` is past the end
+    /// * **UnhandledSyscall**: The kernel wasn't built with the
+    ///                         `syscall-latency` feature
+    ReadLatencyBucket(usize /* number */, usize /* bucket */),
+
+    /// Start or stop the kernel's sampling profiler.  A kernel built with
+    /// the `profile` feature records which thread was running, where it was,
+    /// and the top of its stack on every tick of the tick timer, or on
+    /// every few interrupts if it has no tick timer.  Samples are kept in a
+    /// ring that is drained with `ReadProfileSample`, and the oldest are
+    /// overwritten if it isn't drained fast enough.  Starting the profiler
+    /// throws away any samples that are still in the ring.  The profiler is
+    /// running when the kernel starts.
+    ///
+    /// # Errors
+    ///
+    /// * **AccessDenied**: The process doesn't hold `Capabilities::TRACE`
+    /// * **UnhandledSyscall**: The kernel wasn't built with the `profile`
+    ///                         feature
+    SetProfiling(bool /* running */),
+
+    /// Take the oldest sample out of the profiler's ring.
+    ///
+    /// Returns: a `ProfileSample`, or `Ok` if the ring is empty
+    ///
+    /// # Errors
+    ///
+    /// * **AccessDenied**: The process doesn't hold `Capabilities::TRACE`
+    /// * **UnhandledSyscall**: The kernel wasn't built with the `profile`
+    ///                         feature
+    ReadProfileSample,
+
+    /// Set how much `LogSubsystem` of the kernel prints to the debug
+    /// console, so that it can be turned up on a running device without
+    /// building a new image.  A kernel built with the `debug-print` feature
+    /// starts with every subsystem at `LogLevel::Info`, and any other kernel
+    /// starts with them all off.  A kernel without a debug console accepts
+    /// this, but has nowhere to print to.
+    ///
+    /// Returns: a `Scalar1` holding the level the subsystem was at before
+    ///
+    /// # Errors
+    ///
+    /// * **AccessDenied**: The process doesn't hold `Capabilities::TRACE`
+    SetLogLevel(LogSubsystem, LogLevel),
+
+    /// Restart the system as `RebootMode` says.  It goes down the same way
+    /// as it does for `Shutdown`, telling servers subscribed to
+    /// `SHUTDOWN_BROADCAST` first, so this returns before anything has
+    /// happened.  Nothing is wiped, since the system is about to use the
+    /// memory again.
+    ///
+    /// # Errors
+    ///
+    /// * **AccessDenied**: The process doesn't hold `Capabilities::SHUTDOWN`
+    /// * **UnhandledSyscall**: This system has no way to restart that way
+    /// * **ShareViolation**: The system is already going down
+    Reboot(RebootMode),
+
+    /// Suspend the system to RAM until an interrupt wakes it up.  Servers
+    /// subscribed to `SUSPEND_BROADCAST` are told first, and the system
+    /// suspends once nothing is left to run, so every process should be
+    /// waiting for something by then.  Whatever was running picks up where
+    /// it left off once the system wakes, and subscribers are told again.
+    /// This blocks until then.
+    ///
+    /// # Errors
+    ///
+    /// * **AccessDenied**: The process doesn't hold `Capabilities::SHUTDOWN`
+    /// * **UnhandledSyscall**: This system has no way to suspend
+    /// * **InterruptNotFound**: No process has claimed an interrupt that
+    ///                          could wake the system up again
+    /// * **ShareViolation**: The system is already suspending or going down
+    Suspend,
+
+    /// Say whether this process needs clock domain `usize` running.  The
+    /// domain runs while any process needs it, and is gated once none do,
+    /// so drivers sharing a domain don't have to agree on when to turn it
+    /// off.  A process stops needing every domain when it exits.
+    ///
+    /// Returns: a `Scalar1` that's `1` if the domain is running now, or `0`
+    /// if it's gated
+    ///
+    /// # Errors
+    ///
+    /// * **AccessDenied**: The process doesn't hold `Capabilities::POWER`
+    /// * **UnhandledSyscall**: This system has no clocks to manage
+    /// * **InvalidSyscall**: The domain doesn't exist
+    SetClockState(usize, ClockState),
+
+    /// Ask for the CPU to run at least `usize` Hz, or `0` if this process
+    /// doesn't care how fast it runs.  The CPU runs fast enough for the
+    /// process that needs it fastest, and as slowly as it can once nobody
+    /// does.  A process's request goes away when it exits.
+    ///
+    /// Returns: a `Scalar1` holding the frequency the CPU runs at now, which
+    /// is lower than asked for if it can't go that fast
+    ///
+    /// # Errors
+    ///
+    /// * **AccessDenied**: The process doesn't hold `Capabilities::POWER`
+    /// * **UnhandledSyscall**: This system can't change how fast the CPU runs
+    RequestCpuFrequency(usize),
+
+    /// Let process `PID` map memory that is both writable and executable,
+    /// which is the "JIT" capability.  Every other process has to map code
+    /// writable and then finalize it with `FinalizeCode`.  Nobody holds it
+    /// to begin with, not even PID 1, and only a process holding
+    /// `Capabilities::ALLOW_JIT` may grant it.  It can't be taken away again,
+    /// since that wouldn't unmap what the process has already mapped.
+    ///
+    /// # Errors
+    ///
+    /// * **AccessDenied**: The process doesn't hold `Capabilities::ALLOW_JIT`
+    /// * **ProcessNotFound**: The process doesn't exist
+    AllowJit(PID),
 
     /// This syscall does not exist. It captures all possible
     /// arguments so detailed analysis can be performed.
@@ -301,6 +1347,79 @@ pub enum SysCallNumber {
     TryConnect = 25,
     ReturnScalar1 = 26,
     ReturnScalar2 = 27,
+    DumpCoverage = 28,
+    SetExceptionHandler = 29,
+    SetExceptionStack = 30,
+    DumpMemoryMap = 31,
+    GetTimebase = 32,
+    BootStageTime = 33,
+    SetPriority = 34,
+    SetQuantum = 35,
+    Disconnect = 36,
+    FinalizeCode = 37,
+    GetMeasurement = 38,
+    QueryScheduler = 39,
+    SetDeadline = 40,
+    QueryLatency = 41,
+    YieldTo = 42,
+    CreateServerWithQueue = 43,
+    ReserveAddressSpace = 44,
+    MapSegment = 45,
+    ShareSegment = 46,
+    SetConnectionData = 47,
+    CreateNotification = 48,
+    SignalNotification = 49,
+    WaitNotification = 50,
+    PollNotification = 51,
+    CreatePollSet = 52,
+    AddToPollSet = 53,
+    ReceiveAny = 54,
+    GetKernelConfig = 55,
+    ForwardMessage = 56,
+    NextOutstandingSender = 57,
+    WatchDisconnect = 58,
+    CallMessage = 59,
+    CreateBroadcast = 60,
+    Subscribe = 61,
+    Unsubscribe = 62,
+    Broadcast = 63,
+    MessageTimestamp = 64,
+    SetLendTimeout = 65,
+    QueryServices = 66,
+    SetHeartbeat = 67,
+    Heartbeat = 68,
+    SetWatchdogSupervisor = 69,
+    TakeMissedHeartbeat = 70,
+    ClaimInterruptMessage = 71,
+    EnableIrq = 72,
+    DisableIrq = 73,
+    GetTicks = 74,
+    CreateTimer = 75,
+    CancelTimer = 76,
+    SleepThread = 77,
+    RaiseIrq = 78,
+    SetSyscallFilter = 79,
+    AssignIoRegion = 80,
+    GetRandom = 81,
+    AddEntropy = 82,
+    MakeServerPrivate = 83,
+    AllowConnect = 84,
+    ReadAuditLog = 85,
+    SetCrashSupervisor = 86,
+    ReadCrashReport = 87,
+    ReadCrashWord = 88,
+    TakeCorePage = 89,
+    ReadTrace = 90,
+    ReadSyscallLatency = 91,
+    ReadLatencyBucket = 92,
+    SetProfiling = 93,
+    ReadProfileSample = 94,
+    SetLogLevel = 95,
+    Reboot = 96,
+    Suspend = 97,
+    SetClockState = 98,
+    RequestCpuFrequency = 99,
+    AllowJit = 100,
     Invalid,
 }
 
@@ -334,6 +1453,79 @@ impl SysCallNumber {
             25 => TryConnect,
             26 => ReturnScalar1,
             27 => ReturnScalar2,
+            28 => DumpCoverage,
+            29 => SetExceptionHandler,
+            30 => SetExceptionStack,
+            31 => DumpMemoryMap,
+            32 => GetTimebase,
+            33 => BootStageTime,
+            34 => SetPriority,
+            35 => SetQuantum,
+            36 => Disconnect,
+            37 => FinalizeCode,
+            38 => GetMeasurement,
+            39 => QueryScheduler,
+            40 => SetDeadline,
+            41 => QueryLatency,
+            42 => YieldTo,
+            43 => CreateServerWithQueue,
+            44 => ReserveAddressSpace,
+            45 => MapSegment,
+            46 => ShareSegment,
+            47 => SetConnectionData,
+            48 => CreateNotification,
+            49 => SignalNotification,
+            50 => WaitNotification,
+            51 => PollNotification,
+            52 => CreatePollSet,
+            53 => AddToPollSet,
+            54 => ReceiveAny,
+            55 => GetKernelConfig,
+            56 => ForwardMessage,
+            57 => NextOutstandingSender,
+            58 => WatchDisconnect,
+            59 => CallMessage,
+            60 => CreateBroadcast,
+            61 => Subscribe,
+            62 => Unsubscribe,
+            63 => Broadcast,
+            64 => MessageTimestamp,
+            65 => SetLendTimeout,
+            66 => QueryServices,
+            67 => SetHeartbeat,
+            68 => Heartbeat,
+            69 => SetWatchdogSupervisor,
+            70 => TakeMissedHeartbeat,
+            71 => ClaimInterruptMessage,
+            72 => EnableIrq,
+            73 => DisableIrq,
+            74 => GetTicks,
+            75 => CreateTimer,
+            76 => CancelTimer,
+            77 => SleepThread,
+            78 => RaiseIrq,
+            79 => SetSyscallFilter,
+            80 => AssignIoRegion,
+            81 => GetRandom,
+            82 => AddEntropy,
+            83 => MakeServerPrivate,
+            84 => AllowConnect,
+            85 => ReadAuditLog,
+            86 => SetCrashSupervisor,
+            87 => ReadCrashReport,
+            88 => ReadCrashWord,
+            89 => TakeCorePage,
+            90 => ReadTrace,
+            91 => ReadSyscallLatency,
+            92 => ReadLatencyBucket,
+            93 => SetProfiling,
+            94 => ReadProfileSample,
+            95 => SetLogLevel,
+            96 => Reboot,
+            97 => Suspend,
+            98 => SetClockState,
+            99 => RequestCpuFrequency,
+            100 => AllowJit,
             _ => Invalid,
         }
     }
@@ -361,6 +1553,526 @@ impl SysCall {
                 0,
                 0,
             ],
+            SysCall::ReserveAddressSpace(virt, size) => [
+                SysCallNumber::ReserveAddressSpace as usize,
+                virt.map(|x| x.get()).unwrap_or_default(),
+                size.get(),
+                0,
+                0,
+                0,
+                0,
+                0,
+            ],
+            SysCall::MapSegment(base, offset, size, flags) => [
+                SysCallNumber::MapSegment as usize,
+                base.get(),
+                *offset,
+                size.get(),
+                flags.bits(),
+                0,
+                0,
+                0,
+            ],
+            SysCall::ShareSegment(src, pid, dest_base) => [
+                SysCallNumber::ShareSegment as usize,
+                src.get(),
+                pid.get() as usize,
+                dest_base.get(),
+                0,
+                0,
+                0,
+                0,
+            ],
+            SysCall::SetConnectionData(sender, data) => [
+                SysCallNumber::SetConnectionData as usize,
+                *sender,
+                *data,
+                0,
+                0,
+                0,
+                0,
+                0,
+            ],
+            SysCall::CreateNotification => [
+                SysCallNumber::CreateNotification as usize,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+            ],
+            SysCall::SignalNotification(id, count) => [
+                SysCallNumber::SignalNotification as usize,
+                *id,
+                *count,
+                0,
+                0,
+                0,
+                0,
+                0,
+            ],
+            SysCall::WaitNotification(id) => [
+                SysCallNumber::WaitNotification as usize,
+                *id,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+            ],
+            SysCall::PollNotification(id) => [
+                SysCallNumber::PollNotification as usize,
+                *id,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+            ],
+            SysCall::CreatePollSet => [
+                SysCallNumber::CreatePollSet as usize,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+            ],
+            SysCall::AddToPollSet(id, sid) => {
+                let s = sid.to_u32();
+                [
+                    SysCallNumber::AddToPollSet as usize,
+                    *id,
+                    s.0 as _,
+                    s.1 as _,
+                    s.2 as _,
+                    s.3 as _,
+                    0,
+                    0,
+                ]
+            }
+            SysCall::ReceiveAny(id) => [
+                SysCallNumber::ReceiveAny as usize,
+                *id,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+            ],
+            SysCall::GetKernelConfig => [
+                SysCallNumber::GetKernelConfig as usize,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+            ],
+            // There's no room left for the message type, so it goes in the
+            // upper bits of the connection ID.
+            SysCall::ForwardMessage(sender, cid, message) => match message {
+                Message::MutableBorrow(mm) | Message::Borrow(mm) | Message::Move(mm) => [
+                    SysCallNumber::ForwardMessage as usize,
+                    *sender,
+                    *cid | (message.message_type() << 8),
+                    mm.id as usize,
+                    mm.buf.as_ptr() as usize,
+                    mm.buf.len(),
+                    mm.offset.map(|x| x.get()).unwrap_or(0) as usize,
+                    mm.valid.map(|x| x.get()).unwrap_or(0) as usize,
+                ],
+                Message::Scalar(sc) | Message::BlockingScalar(sc) => [
+                    SysCallNumber::ForwardMessage as usize,
+                    *sender,
+                    *cid | (message.message_type() << 8),
+                    sc.id as usize,
+                    sc.arg1,
+                    sc.arg2,
+                    sc.arg3,
+                    sc.arg4,
+                ],
+            },
+            SysCall::NextOutstandingSender(sid, slot) => {
+                let s = sid.to_u32();
+                [
+                    SysCallNumber::NextOutstandingSender as usize,
+                    s.0 as _,
+                    s.1 as _,
+                    s.2 as _,
+                    s.3 as _,
+                    *slot,
+                    0,
+                    0,
+                ]
+            }
+            SysCall::WatchDisconnect(cid, notification) => [
+                SysCallNumber::WatchDisconnect as usize,
+                *cid,
+                *notification,
+                0,
+                0,
+                0,
+                0,
+                0,
+            ],
+            SysCall::MessageTimestamp => [
+                SysCallNumber::MessageTimestamp as usize,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+            ],
+            SysCall::SetLendTimeout(ticks) => [
+                SysCallNumber::SetLendTimeout as usize,
+                *ticks,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+            ],
+            SysCall::QueryServices(index) => [
+                SysCallNumber::QueryServices as usize,
+                *index,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+            ],
+            SysCall::SetHeartbeat(ticks) => [
+                SysCallNumber::SetHeartbeat as usize,
+                *ticks,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+            ],
+            SysCall::Heartbeat => [SysCallNumber::Heartbeat as usize, 0, 0, 0, 0, 0, 0, 0],
+            SysCall::SetWatchdogSupervisor(id) => [
+                SysCallNumber::SetWatchdogSupervisor as usize,
+                *id,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+            ],
+            SysCall::TakeMissedHeartbeat => [
+                SysCallNumber::TakeMissedHeartbeat as usize,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+            ],
+            SysCall::ClaimInterruptMessage(irq, cid, opcode) => [
+                SysCallNumber::ClaimInterruptMessage as usize,
+                *irq,
+                *cid,
+                *opcode,
+                0,
+                0,
+                0,
+                0,
+            ],
+            SysCall::EnableIrq(irq) => [SysCallNumber::EnableIrq as usize, *irq, 0, 0, 0, 0, 0, 0],
+            SysCall::DisableIrq(irq) => {
+                [SysCallNumber::DisableIrq as usize, *irq, 0, 0, 0, 0, 0, 0]
+            }
+            SysCall::GetTicks => [SysCallNumber::GetTicks as usize, 0, 0, 0, 0, 0, 0, 0],
+            SysCall::CreateTimer(cid, opcode, delay, period) => [
+                SysCallNumber::CreateTimer as usize,
+                *cid,
+                *opcode,
+                *delay,
+                *period,
+                0,
+                0,
+                0,
+            ],
+            SysCall::CancelTimer(id) => {
+                [SysCallNumber::CancelTimer as usize, *id, 0, 0, 0, 0, 0, 0]
+            }
+            SysCall::SleepThread(ms) => {
+                [SysCallNumber::SleepThread as usize, *ms, 0, 0, 0, 0, 0, 0]
+            }
+            SysCall::RaiseIrq(irq) => [SysCallNumber::RaiseIrq as usize, *irq, 0, 0, 0, 0, 0, 0],
+            SysCall::SetSyscallFilter(pid, filter) => {
+                let words = filter.to_words();
+                [
+                    SysCallNumber::SetSyscallFilter as usize,
+                    pid.get() as usize,
+                    words[0],
+                    words[1],
+                    words[2],
+                    words[3],
+                    0,
+                    0,
+                ]
+            }
+            SysCall::AssignIoRegion(pid, phys, size) => [
+                SysCallNumber::AssignIoRegion as usize,
+                pid.get() as usize,
+                phys.get(),
+                size.get(),
+                0,
+                0,
+                0,
+                0,
+            ],
+            SysCall::GetRandom => [SysCallNumber::GetRandom as usize, 0, 0, 0, 0, 0, 0, 0],
+            SysCall::AddEntropy(a1, a2, a3, a4) => [
+                SysCallNumber::AddEntropy as usize,
+                *a1,
+                *a2,
+                *a3,
+                *a4,
+                0,
+                0,
+                0,
+            ],
+            SysCall::MakeServerPrivate(sid) => {
+                let s = sid.to_u32();
+                [
+                    SysCallNumber::MakeServerPrivate as usize,
+                    s.0 as _,
+                    s.1 as _,
+                    s.2 as _,
+                    s.3 as _,
+                    0,
+                    0,
+                    0,
+                ]
+            }
+            SysCall::AllowConnect(sid, pid) => {
+                let s = sid.to_u32();
+                [
+                    SysCallNumber::AllowConnect as usize,
+                    s.0 as _,
+                    s.1 as _,
+                    s.2 as _,
+                    s.3 as _,
+                    pid.get() as usize,
+                    0,
+                    0,
+                ]
+            }
+            SysCall::ReadAuditLog(sequence) => [
+                SysCallNumber::ReadAuditLog as usize,
+                *sequence,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+            ],
+            SysCall::SetCrashSupervisor(id) => [
+                SysCallNumber::SetCrashSupervisor as usize,
+                *id,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+            ],
+            SysCall::ReadCrashReport(sequence) => [
+                SysCallNumber::ReadCrashReport as usize,
+                *sequence,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+            ],
+            SysCall::ReadCrashWord(sequence, index) => [
+                SysCallNumber::ReadCrashWord as usize,
+                *sequence,
+                *index,
+                0,
+                0,
+                0,
+                0,
+                0,
+            ],
+            SysCall::TakeCorePage(sequence) => [
+                SysCallNumber::TakeCorePage as usize,
+                *sequence,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+            ],
+            SysCall::ReadTrace => [SysCallNumber::ReadTrace as usize, 0, 0, 0, 0, 0, 0, 0],
+            SysCall::ReadSyscallLatency(number) => [
+                SysCallNumber::ReadSyscallLatency as usize,
+                *number,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+            ],
+            SysCall::ReadLatencyBucket(number, bucket) => [
+                SysCallNumber::ReadLatencyBucket as usize,
+                *number,
+                *bucket,
+                0,
+                0,
+                0,
+                0,
+                0,
+            ],
+            SysCall::SetProfiling(running) => [
+                SysCallNumber::SetProfiling as usize,
+                *running as usize,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+            ],
+            SysCall::ReadProfileSample => [
+                SysCallNumber::ReadProfileSample as usize,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+            ],
+            SysCall::SetLogLevel(subsystem, level) => [
+                SysCallNumber::SetLogLevel as usize,
+                *subsystem as usize,
+                *level as usize,
+                0,
+                0,
+                0,
+                0,
+                0,
+            ],
+            SysCall::Reboot(mode) => [
+                SysCallNumber::Reboot as usize,
+                *mode as usize,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+            ],
+            SysCall::Suspend => [SysCallNumber::Suspend as usize, 0, 0, 0, 0, 0, 0, 0],
+            SysCall::SetClockState(domain, state) => [
+                SysCallNumber::SetClockState as usize,
+                *domain,
+                *state as usize,
+                0,
+                0,
+                0,
+                0,
+                0,
+            ],
+            SysCall::RequestCpuFrequency(hz) => [
+                SysCallNumber::RequestCpuFrequency as usize,
+                *hz,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+            ],
+            SysCall::AllowJit(pid) => [
+                SysCallNumber::AllowJit as usize,
+                pid.get() as usize,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+            ],
+            SysCall::CreateBroadcast => {
+                [SysCallNumber::CreateBroadcast as usize, 0, 0, 0, 0, 0, 0, 0]
+            }
+            SysCall::Subscribe(id, cid, opcode) => [
+                SysCallNumber::Subscribe as usize,
+                *id,
+                *cid,
+                *opcode,
+                0,
+                0,
+                0,
+                0,
+            ],
+            SysCall::Unsubscribe(id, cid) => [
+                SysCallNumber::Unsubscribe as usize,
+                *id,
+                *cid,
+                0,
+                0,
+                0,
+                0,
+                0,
+            ],
+            SysCall::Broadcast(id, arg1, arg2, arg3, arg4) => [
+                SysCallNumber::Broadcast as usize,
+                *id,
+                *arg1,
+                *arg2,
+                *arg3,
+                *arg4,
+                0,
+                0,
+            ],
+            SysCall::CallMessage(a1, ref a2) => match a2 {
+                Message::MutableBorrow(mm) | Message::Borrow(mm) | Message::Move(mm) => [
+                    SysCallNumber::CallMessage as usize,
+                    *a1,
+                    a2.message_type(),
+                    mm.id as usize,
+                    mm.buf.as_ptr() as usize,
+                    mm.buf.len(),
+                    mm.offset.map(|x| x.get()).unwrap_or(0) as usize,
+                    mm.valid.map(|x| x.get()).unwrap_or(0) as usize,
+                ],
+                Message::Scalar(sc) | Message::BlockingScalar(sc) => [
+                    SysCallNumber::CallMessage as usize,
+                    *a1,
+                    a2.message_type(),
+                    sc.id as usize,
+                    sc.arg1,
+                    sc.arg2,
+                    sc.arg3,
+                    sc.arg4,
+                ],
+            },
             SysCall::UnmapMemory(range) => [
                 SysCallNumber::UnmapMemory as usize,
                 range.as_ptr() as usize,
@@ -483,6 +2195,19 @@ impl SysCall {
                     0,
                 ]
             }
+            SysCall::CreateServerWithQueue(sid, queue_length) => {
+                let s = sid.to_u32();
+                [
+                    SysCallNumber::CreateServerWithQueue as usize,
+                    s.0 as _,
+                    s.1 as _,
+                    s.2 as _,
+                    s.3 as _,
+                    *queue_length,
+                    0,
+                    0,
+                ]
+            }
             SysCall::Connect(sid) => {
                 let s = sid.to_u32();
                 [
@@ -544,7 +2269,148 @@ impl SysCall {
                 0,
                 0,
             ],
-            SysCall::Shutdown => [SysCallNumber::Shutdown as usize, 0, 0, 0, 0, 0, 0, 0],
+            SysCall::Shutdown(scrub) => [
+                SysCallNumber::Shutdown as usize,
+                *scrub as usize,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+            ],
+            SysCall::DumpCoverage => [SysCallNumber::DumpCoverage as usize, 0, 0, 0, 0, 0, 0, 0],
+            SysCall::SetExceptionHandler(a1) => [
+                SysCallNumber::SetExceptionHandler as usize,
+                a1.map(|x| x.get()).unwrap_or_default(),
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+            ],
+            SysCall::SetExceptionStack(a1) => [
+                SysCallNumber::SetExceptionStack as usize,
+                a1.map(|x| x.as_ptr() as usize).unwrap_or_default(),
+                a1.map(|x| x.len()).unwrap_or_default(),
+                0,
+                0,
+                0,
+                0,
+                0,
+            ],
+            SysCall::DumpMemoryMap(a1) => [
+                SysCallNumber::DumpMemoryMap as usize,
+                a1.get() as usize,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+            ],
+            SysCall::GetTimebase => [SysCallNumber::GetTimebase as usize, 0, 0, 0, 0, 0, 0, 0],
+            SysCall::BootStageTime(stage) => [
+                SysCallNumber::BootStageTime as usize,
+                *stage as usize,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+            ],
+            SysCall::SetPriority(priority) => [
+                SysCallNumber::SetPriority as usize,
+                *priority as usize,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+            ],
+            SysCall::SetQuantum(class, ticks) => [
+                SysCallNumber::SetQuantum as usize,
+                *class,
+                *ticks,
+                0,
+                0,
+                0,
+                0,
+                0,
+            ],
+            SysCall::FinalizeCode(range) => [
+                SysCallNumber::FinalizeCode as usize,
+                range.as_ptr() as usize,
+                range.len(),
+                0,
+                0,
+                0,
+                0,
+                0,
+            ],
+            SysCall::Disconnect(cid) => [
+                SysCallNumber::Disconnect as usize,
+                *cid,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+            ],
+            SysCall::GetMeasurement(index, chunk) => [
+                SysCallNumber::GetMeasurement as usize,
+                *index,
+                *chunk,
+                0,
+                0,
+                0,
+                0,
+                0,
+            ],
+            SysCall::QueryScheduler(pid, tid) => [
+                SysCallNumber::QueryScheduler as usize,
+                pid.get() as usize,
+                *tid,
+                0,
+                0,
+                0,
+                0,
+                0,
+            ],
+            SysCall::SetDeadline(period, budget) => [
+                SysCallNumber::SetDeadline as usize,
+                *period,
+                *budget,
+                0,
+                0,
+                0,
+                0,
+                0,
+            ],
+            SysCall::QueryLatency(cid, stage, percentile) => [
+                SysCallNumber::QueryLatency as usize,
+                *cid as usize,
+                *stage as usize,
+                *percentile,
+                0,
+                0,
+                0,
+                0,
+            ],
+            SysCall::YieldTo(pid, tid) => [
+                SysCallNumber::YieldTo as usize,
+                pid.get() as usize,
+                *tid,
+                0,
+                0,
+                0,
+                0,
+                0,
+            ],
             SysCall::TryConnect(sid) => {
                 let s = sid.to_u32();
                 [
@@ -613,6 +2479,132 @@ impl SysCall {
                 MemoryAddress::new(a3).ok_or(Error::InvalidSyscall)?,
                 MemoryFlags::from_bits(a4).ok_or(Error::InvalidSyscall)?,
             ),
+            SysCallNumber::ReserveAddressSpace => SysCall::ReserveAddressSpace(
+                MemoryAddress::new(a1),
+                MemoryAddress::new(a2).ok_or(Error::InvalidSyscall)?,
+            ),
+            SysCallNumber::MapSegment => SysCall::MapSegment(
+                MemoryAddress::new(a1).ok_or(Error::InvalidSyscall)?,
+                a2,
+                MemoryAddress::new(a3).ok_or(Error::InvalidSyscall)?,
+                MemoryFlags::from_bits(a4).ok_or(Error::InvalidSyscall)?,
+            ),
+            SysCallNumber::ShareSegment => SysCall::ShareSegment(
+                MemoryAddress::new(a1).ok_or(Error::InvalidSyscall)?,
+                pid_from_usize(a2)?,
+                MemoryAddress::new(a3).ok_or(Error::InvalidSyscall)?,
+            ),
+            SysCallNumber::SetConnectionData => SysCall::SetConnectionData(a1, a2),
+            SysCallNumber::CreateNotification => SysCall::CreateNotification,
+            SysCallNumber::SignalNotification => SysCall::SignalNotification(a1, a2),
+            SysCallNumber::WaitNotification => SysCall::WaitNotification(a1),
+            SysCallNumber::PollNotification => SysCall::PollNotification(a1),
+            SysCallNumber::CreatePollSet => SysCall::CreatePollSet,
+            SysCallNumber::AddToPollSet => {
+                SysCall::AddToPollSet(a1, SID::from_u32(a2 as _, a3 as _, a4 as _, a5 as _))
+            }
+            SysCallNumber::ReceiveAny => SysCall::ReceiveAny(a1),
+            SysCallNumber::GetKernelConfig => SysCall::GetKernelConfig,
+            SysCallNumber::ForwardMessage => SysCall::ForwardMessage(
+                a1,
+                a2 & 0xff,
+                match a2 >> 8 {
+                    1 => Message::MutableBorrow(
+                        MemoryMessage::from_usize(a3, a4, a5, a6, a7).ok_or(Error::InvalidSyscall)?,
+                    ),
+                    2 => Message::Borrow(
+                        MemoryMessage::from_usize(a3, a4, a5, a6, a7).ok_or(Error::InvalidSyscall)?,
+                    ),
+                    3 => Message::Move(
+                        MemoryMessage::from_usize(a3, a4, a5, a6, a7).ok_or(Error::InvalidSyscall)?,
+                    ),
+                    4 => Message::Scalar(ScalarMessage::from_usize(a3, a4, a5, a6, a7)),
+                    5 => Message::BlockingScalar(ScalarMessage::from_usize(a3, a4, a5, a6, a7)),
+                    _ => return Err(Error::InvalidSyscall),
+                },
+            ),
+            SysCallNumber::NextOutstandingSender => SysCall::NextOutstandingSender(
+                SID::from_u32(a1 as _, a2 as _, a3 as _, a4 as _),
+                a5,
+            ),
+            SysCallNumber::WatchDisconnect => SysCall::WatchDisconnect(a1, a2),
+            SysCallNumber::MessageTimestamp => SysCall::MessageTimestamp,
+            SysCallNumber::SetLendTimeout => SysCall::SetLendTimeout(a1),
+            SysCallNumber::QueryServices => SysCall::QueryServices(a1),
+            SysCallNumber::SetHeartbeat => SysCall::SetHeartbeat(a1),
+            SysCallNumber::Heartbeat => SysCall::Heartbeat,
+            SysCallNumber::SetWatchdogSupervisor => SysCall::SetWatchdogSupervisor(a1),
+            SysCallNumber::TakeMissedHeartbeat => SysCall::TakeMissedHeartbeat,
+            SysCallNumber::ClaimInterruptMessage => SysCall::ClaimInterruptMessage(a1, a2, a3),
+            SysCallNumber::EnableIrq => SysCall::EnableIrq(a1),
+            SysCallNumber::DisableIrq => SysCall::DisableIrq(a1),
+            SysCallNumber::GetTicks => SysCall::GetTicks,
+            SysCallNumber::CreateTimer => SysCall::CreateTimer(a1, a2, a3, a4),
+            SysCallNumber::CancelTimer => SysCall::CancelTimer(a1),
+            SysCallNumber::SleepThread => SysCall::SleepThread(a1),
+            SysCallNumber::RaiseIrq => SysCall::RaiseIrq(a1),
+            SysCallNumber::SetSyscallFilter => SysCall::SetSyscallFilter(
+                pid_from_usize(a1)?,
+                SyscallFilter::from_words([a2, a3, a4, a5]),
+            ),
+            SysCallNumber::AssignIoRegion => SysCall::AssignIoRegion(
+                pid_from_usize(a1)?,
+                MemoryAddress::new(a2).ok_or(Error::InvalidSyscall)?,
+                MemorySize::new(a3).ok_or(Error::InvalidSyscall)?,
+            ),
+            SysCallNumber::GetRandom => SysCall::GetRandom,
+            SysCallNumber::AddEntropy => SysCall::AddEntropy(a1, a2, a3, a4),
+            SysCallNumber::MakeServerPrivate => {
+                SysCall::MakeServerPrivate(SID::from_u32(a1 as _, a2 as _, a3 as _, a4 as _))
+            }
+            SysCallNumber::AllowConnect => SysCall::AllowConnect(
+                SID::from_u32(a1 as _, a2 as _, a3 as _, a4 as _),
+                pid_from_usize(a5)?,
+            ),
+            SysCallNumber::ReadAuditLog => SysCall::ReadAuditLog(a1),
+            SysCallNumber::SetCrashSupervisor => SysCall::SetCrashSupervisor(a1),
+            SysCallNumber::ReadCrashReport => SysCall::ReadCrashReport(a1),
+            SysCallNumber::ReadCrashWord => SysCall::ReadCrashWord(a1, a2),
+            SysCallNumber::TakeCorePage => SysCall::TakeCorePage(a1),
+            SysCallNumber::ReadTrace => SysCall::ReadTrace,
+            SysCallNumber::ReadSyscallLatency => SysCall::ReadSyscallLatency(a1),
+            SysCallNumber::ReadLatencyBucket => SysCall::ReadLatencyBucket(a1, a2),
+            SysCallNumber::SetProfiling => SysCall::SetProfiling(a1 != 0),
+            SysCallNumber::ReadProfileSample => SysCall::ReadProfileSample,
+            SysCallNumber::SetLogLevel => SysCall::SetLogLevel(
+                LogSubsystem::from_usize(a1).ok_or(Error::InvalidSyscall)?,
+                LogLevel::from_usize(a2).ok_or(Error::InvalidSyscall)?,
+            ),
+            SysCallNumber::Reboot => {
+                SysCall::Reboot(RebootMode::from_usize(a1).ok_or(Error::InvalidSyscall)?)
+            }
+            SysCallNumber::Suspend => SysCall::Suspend,
+            SysCallNumber::SetClockState => {
+                SysCall::SetClockState(a1, ClockState::from_usize(a2).ok_or(Error::InvalidSyscall)?)
+            }
+            SysCallNumber::RequestCpuFrequency => SysCall::RequestCpuFrequency(a1),
+            SysCallNumber::AllowJit => SysCall::AllowJit(pid_from_usize(a1)?),
+            SysCallNumber::CreateBroadcast => SysCall::CreateBroadcast,
+            SysCallNumber::Subscribe => SysCall::Subscribe(a1, a2, a3),
+            SysCallNumber::Unsubscribe => SysCall::Unsubscribe(a1, a2),
+            SysCallNumber::Broadcast => SysCall::Broadcast(a1, a2, a3, a4, a5),
+            SysCallNumber::CallMessage => SysCall::CallMessage(
+                a1,
+                match a2 {
+                    1 => Message::MutableBorrow(
+                        MemoryMessage::from_usize(a3, a4, a5, a6, a7).ok_or(Error::InvalidSyscall)?,
+                    ),
+                    2 => Message::Borrow(
+                        MemoryMessage::from_usize(a3, a4, a5, a6, a7).ok_or(Error::InvalidSyscall)?,
+                    ),
+                    3 => Message::Move(
+                        MemoryMessage::from_usize(a3, a4, a5, a6, a7).ok_or(Error::InvalidSyscall)?,
+                    ),
+                    4 => Message::Scalar(ScalarMessage::from_usize(a3, a4, a5, a6, a7)),
+                    5 => Message::BlockingScalar(ScalarMessage::from_usize(a3, a4, a5, a6, a7)),
+                    _ => return Err(Error::InvalidSyscall),
+                },
+            ),
             SysCallNumber::UnmapMemory => {
                 SysCall::UnmapMemory(MemoryRange::new(a1, a2).or(Err(Error::InvalidSyscall))?)
             }
@@ -649,6 +2641,10 @@ impl SysCall {
             SysCallNumber::CreateServer => {
                 SysCall::CreateServer(SID::from_u32(a1 as _, a2 as _, a3 as _, a4 as _))
             }
+            SysCallNumber::CreateServerWithQueue => SysCall::CreateServerWithQueue(
+                SID::from_u32(a1 as _, a2 as _, a3 as _, a4 as _),
+                a5,
+            ),
             SysCallNumber::Connect => {
                 SysCall::Connect(SID::from_u32(a1 as _, a2 as _, a3 as _, a4 as _))
             }
@@ -710,7 +2706,41 @@ impl SysCall {
                 SysCall::CreateProcess(crate::arch::args_to_process(a1, a2, a3, a4, a5, a6, a7)?)
             }
             SysCallNumber::TerminateProcess => SysCall::TerminateProcess,
-            SysCallNumber::Shutdown => SysCall::Shutdown,
+            SysCallNumber::Shutdown => {
+                SysCall::Shutdown(ScrubLevel::from_usize(a1).ok_or(Error::InvalidSyscall)?)
+            }
+            SysCallNumber::DumpCoverage => SysCall::DumpCoverage,
+            SysCallNumber::SetExceptionHandler => SysCall::SetExceptionHandler(MemoryAddress::new(a1)),
+            SysCallNumber::SetExceptionStack => SysCall::SetExceptionStack(if a1 == 0 {
+                None
+            } else {
+                Some(MemoryRange::new(a1, a2)?)
+            }),
+            SysCallNumber::DumpMemoryMap => SysCall::DumpMemoryMap(pid_from_usize(a1)?),
+            SysCallNumber::GetTimebase => SysCall::GetTimebase,
+            SysCallNumber::BootStageTime => {
+                SysCall::BootStageTime(BootStage::from_usize(a1).ok_or(Error::InvalidSyscall)?)
+            }
+            SysCallNumber::SetPriority => {
+                if a1 > u8::MAX as usize {
+                    return Err(Error::InvalidSyscall);
+                }
+                SysCall::SetPriority(a1 as u8)
+            }
+            SysCallNumber::SetQuantum => SysCall::SetQuantum(a1, a2),
+            SysCallNumber::Disconnect => SysCall::Disconnect(a1),
+            SysCallNumber::FinalizeCode => {
+                SysCall::FinalizeCode(MemoryRange::new(a1, a2).or(Err(Error::InvalidSyscall))?)
+            }
+            SysCallNumber::GetMeasurement => SysCall::GetMeasurement(a1, a2),
+            SysCallNumber::QueryScheduler => SysCall::QueryScheduler(pid_from_usize(a1)?, a2),
+            SysCallNumber::SetDeadline => SysCall::SetDeadline(a1, a2),
+            SysCallNumber::QueryLatency => SysCall::QueryLatency(
+                a1 as _,
+                LatencyStage::from_usize(a2).ok_or(Error::InvalidSyscall)?,
+                a3,
+            ),
+            SysCallNumber::YieldTo => SysCall::YieldTo(pid_from_usize(a1)?, a2),
             SysCallNumber::TryConnect => {
                 SysCall::TryConnect(SID::from_u32(a1 as _, a2 as _, a3 as _, a4 as _))
             }
@@ -837,6 +2867,12 @@ pub fn unmap_memory(range: MemoryRange) -> core::result::Result<(), Error> {
     }
 }
 
+/// Make a range of memory that has had code written into it read-only and
+/// executable.  See `SysCall::FinalizeCode` for details.
+pub fn finalize_code(range: MemoryRange) -> core::result::Result<(), Error> {
+    rsyscall(SysCall::FinalizeCode(range)).map(|_| ())
+}
+
 /// Map the given physical address to the given virtual address.
 /// The `size` field must be page-aligned.
 pub fn return_memory(sender: MessageSender, mem: MemoryRange) -> core::result::Result<(), Error> {
@@ -896,6 +2932,242 @@ pub fn claim_interrupt(
     }
 }
 
+/// Claim interrupt `irq_no`, having it delivered as a message with ID
+/// `opcode` to the server at the other end of `cid`.  See
+/// `SysCall::ClaimInterruptMessage` for details.
+pub fn claim_interrupt_message(
+    irq_no: usize,
+    cid: CID,
+    opcode: usize,
+) -> core::result::Result<(), Error> {
+    rsyscall(SysCall::ClaimInterruptMessage(irq_no, cid, opcode)).map(|_| ())
+}
+
+/// Start being told about interrupt `irq_no` again.  See `SysCall::EnableIrq`
+/// for details.
+pub fn enable_irq(irq_no: usize) -> core::result::Result<(), Error> {
+    rsyscall(SysCall::EnableIrq(irq_no)).map(|_| ())
+}
+
+/// Stop being told about interrupt `irq_no`, without affecting any other
+/// process sharing it.  See `SysCall::DisableIrq` for details.
+pub fn disable_irq(irq_no: usize) -> core::result::Result<(), Error> {
+    rsyscall(SysCall::DisableIrq(irq_no)).map(|_| ())
+}
+
+/// Fire interrupt `irq_no` from software.  See `SysCall::RaiseIrq` for
+/// details.
+pub fn raise_irq(irq_no: usize) -> core::result::Result<(), Error> {
+    rsyscall(SysCall::RaiseIrq(irq_no)).map(|_| ())
+}
+
+/// Limit child process `pid`, which hasn't started yet, to the syscalls in
+/// `filter`.  See `SysCall::SetSyscallFilter` for details.
+pub fn set_syscall_filter(pid: PID, filter: SyscallFilter) -> core::result::Result<(), Error> {
+    rsyscall(SysCall::SetSyscallFilter(pid, filter)).map(|_| ())
+}
+
+/// Give the `size` bytes of physical I/O space at `phys` to process `pid`.
+/// See `SysCall::AssignIoRegion` for details.
+pub fn assign_io_region(
+    pid: PID,
+    phys: MemoryAddress,
+    size: MemorySize,
+) -> core::result::Result<(), Error> {
+    rsyscall(SysCall::AssignIoRegion(pid, phys, size)).map(|_| ())
+}
+
+/// Fill `buf` from the kernel's random number generator.  See
+/// `SysCall::GetRandom` for details.
+pub fn get_random(buf: &mut [u8]) -> core::result::Result<(), Error> {
+    const WORD: usize = core::mem::size_of::<usize>();
+    for chunk in buf.chunks_mut(2 * WORD) {
+        match rsyscall(SysCall::GetRandom)? {
+            Result::Scalar2(a, b) => {
+                let mut words = [0u8; 2 * WORD];
+                words[..WORD].copy_from_slice(&a.to_le_bytes());
+                words[WORD..].copy_from_slice(&b.to_le_bytes());
+                chunk.copy_from_slice(&words[..chunk.len()]);
+            }
+            _ => return Err(Error::InternalError),
+        }
+    }
+    Ok(())
+}
+
+/// Stir `words` into the kernel's random number generator.  See
+/// `SysCall::AddEntropy` for details.
+pub fn add_entropy(words: [usize; 4]) -> core::result::Result<(), Error> {
+    rsyscall(SysCall::AddEntropy(words[0], words[1], words[2], words[3])).map(|_| ())
+}
+
+/// Make `server`, which belongs to this process, private.  See
+/// `SysCall::MakeServerPrivate` for details.
+pub fn make_server_private(server: SID) -> core::result::Result<(), Error> {
+    rsyscall(SysCall::MakeServerPrivate(server)).map(|_| ())
+}
+
+/// Let process `pid` connect to `server` once it is private.  See
+/// `SysCall::AllowConnect` for details.
+pub fn allow_connect(server: SID, pid: PID) -> core::result::Result<(), Error> {
+    rsyscall(SysCall::AllowConnect(server, pid)).map(|_| ())
+}
+
+/// Read the oldest entry of the audit log numbered `sequence` or later, or
+/// `None` if there isn't one yet.  See `SysCall::ReadAuditLog` for details.
+pub fn read_audit_log(sequence: usize) -> core::result::Result<Option<AuditEvent>, Error> {
+    match rsyscall(SysCall::ReadAuditLog(sequence))? {
+        Result::AuditEvent(event) => Ok(Some(event)),
+        Result::Ok => Ok(None),
+        _ => Err(Error::InternalError),
+    }
+}
+
+/// Become the crash supervisor, with `notification` signalled whenever a
+/// process crashes.  See `SysCall::SetCrashSupervisor` for details.
+pub fn set_crash_supervisor(notification: usize) -> core::result::Result<(), Error> {
+    rsyscall(SysCall::SetCrashSupervisor(notification)).map(|_| ())
+}
+
+/// Read the oldest crash report numbered `sequence` or later, or `None` if
+/// there isn't one yet.  See `SysCall::ReadCrashReport` for details.
+pub fn read_crash_report(sequence: usize) -> core::result::Result<Option<CrashReport>, Error> {
+    match rsyscall(SysCall::ReadCrashReport(sequence))? {
+        Result::CrashReport(report) => Ok(Some(report)),
+        Result::Ok => Ok(None),
+        _ => Err(Error::InternalError),
+    }
+}
+
+/// Read word `index` of the registers and stack saved with crash report
+/// `sequence`.  See `SysCall::ReadCrashWord` for details.
+pub fn read_crash_word(sequence: usize, index: usize) -> core::result::Result<usize, Error> {
+    match rsyscall(SysCall::ReadCrashWord(sequence, index))? {
+        Result::Scalar1(word) => Ok(word),
+        _ => Err(Error::InternalError),
+    }
+}
+
+/// Take the next page of the core of crash report `sequence`, along with
+/// the address it was at in the crashed process, or `None` once there are
+/// no more.  See `SysCall::TakeCorePage` for details.
+pub fn take_core_page(
+    sequence: usize,
+) -> core::result::Result<Option<(MemoryRange, usize)>, Error> {
+    match rsyscall(SysCall::TakeCorePage(sequence))? {
+        Result::CorePage(range, addr) => Ok(Some((range, addr))),
+        Result::Ok => Ok(None),
+        _ => Err(Error::InternalError),
+    }
+}
+
+/// Take the oldest record out of the kernel's trace buffer, or `None` if
+/// it's empty.  See `SysCall::ReadTrace` for details.
+pub fn read_trace() -> core::result::Result<Option<TraceEvent>, Error> {
+    match rsyscall(SysCall::ReadTrace)? {
+        Result::TraceEvent(event) => Ok(Some(event)),
+        Result::Ok => Ok(None),
+        _ => Err(Error::InternalError),
+    }
+}
+
+/// Read how long the kernel has spent handling syscall number `number`.
+/// See `SysCall::ReadSyscallLatency` for details.
+pub fn read_syscall_latency(number: usize) -> core::result::Result<SyscallLatency, Error> {
+    match rsyscall(SysCall::ReadSyscallLatency(number))? {
+        Result::SyscallLatency(latency) => Ok(latency),
+        _ => Err(Error::InternalError),
+    }
+}
+
+/// Read how many times syscall number `number` took as long as bucket
+/// `bucket` of its latency histogram covers.  See `SysCall::ReadLatencyBucket`
+/// for details.
+pub fn read_latency_bucket(number: usize, bucket: usize) -> core::result::Result<usize, Error> {
+    match rsyscall(SysCall::ReadLatencyBucket(number, bucket))? {
+        Result::Scalar1(count) => Ok(count),
+        _ => Err(Error::InternalError),
+    }
+}
+
+/// Start the kernel's sampling profiler, throwing away any samples left
+/// over from before, or stop it.  See `SysCall::SetProfiling` for details.
+pub fn set_profiling(running: bool) -> core::result::Result<(), Error> {
+    rsyscall(SysCall::SetProfiling(running)).map(|_| ())
+}
+
+/// Take the oldest sample out of the profiler's ring, or `None` if it's
+/// empty.  See `SysCall::ReadProfileSample` for details.
+pub fn read_profile_sample() -> core::result::Result<Option<ProfileSample>, Error> {
+    match rsyscall(SysCall::ReadProfileSample)? {
+        Result::ProfileSample(sample) => Ok(Some(sample)),
+        Result::Ok => Ok(None),
+        _ => Err(Error::InternalError),
+    }
+}
+
+/// Set how much `subsystem` of the kernel logs, returning how much it
+/// logged before.  See `SysCall::SetLogLevel` for details.
+pub fn set_log_level(
+    subsystem: LogSubsystem,
+    level: LogLevel,
+) -> core::result::Result<LogLevel, Error> {
+    match rsyscall(SysCall::SetLogLevel(subsystem, level))? {
+        Result::Scalar1(previous) => LogLevel::from_usize(previous).ok_or(Error::InternalError),
+        _ => Err(Error::InternalError),
+    }
+}
+
+/// Restart the system once everything subscribed to `SHUTDOWN_BROADCAST` is
+/// ready.  See `SysCall::Reboot` for details.
+pub fn reboot(mode: RebootMode) -> core::result::Result<(), Error> {
+    rsyscall(SysCall::Reboot(mode)).map(|_| ())
+}
+
+/// Suspend the system to RAM, returning once it has woken up again.  See
+/// `SysCall::Suspend` for details.
+pub fn suspend() -> core::result::Result<(), Error> {
+    rsyscall(SysCall::Suspend).map(|_| ())
+}
+
+/// Say whether this process needs clock `domain` running, returning whether
+/// it runs now.  See `SysCall::SetClockState` for details.
+pub fn set_clock_state(domain: usize, state: ClockState) -> core::result::Result<bool, Error> {
+    match rsyscall(SysCall::SetClockState(domain, state))? {
+        Result::Scalar1(running) => Ok(running != 0),
+        _ => Err(Error::InternalError),
+    }
+}
+
+/// Ask for the CPU to run at least `hz` Hz, returning how fast it runs now.
+/// See `SysCall::RequestCpuFrequency` for details.
+pub fn request_cpu_frequency(hz: usize) -> core::result::Result<usize, Error> {
+    match rsyscall(SysCall::RequestCpuFrequency(hz))? {
+        Result::Scalar1(hz) => Ok(hz),
+        _ => Err(Error::InternalError),
+    }
+}
+
+/// Let process `pid` map memory that is both writable and executable.  See
+/// `SysCall::AllowJit` for details.
+pub fn allow_jit(pid: PID) -> core::result::Result<(), Error> {
+    rsyscall(SysCall::AllowJit(pid)).map(|_| ())
+}
+
+/// Give an interrupt claimed with `claim_interrupt()` or
+/// `claim_interrupt_message()` back to the kernel, masking it again.  This
+/// happens automatically when the process exits.
+pub fn free_interrupt(irq_no: usize) -> core::result::Result<(), Error> {
+    let result = rsyscall(SysCall::FreeInterrupt(irq_no))?;
+    if let crate::Result::Ok = result {
+        Ok(())
+    } else if let Result::Error(e) = result {
+        Err(e)
+    } else {
+        Err(Error::InternalError)
+    }
+}
+
 /// Create a new server with the given name.  This enables other processes to
 /// connect to this server to send messages.  The name is a UTF-8 token that
 /// will be mixed with other random data that is unique to each process.
@@ -919,6 +3191,41 @@ pub fn create_server(name_bytes: &[u8; 16]) -> core::result::Result<SID, Error>
     }
 }
 
+/// Create a new server whose ID is picked by the kernel, so that nobody can
+/// guess it.  Processes can only connect to it once they are given the ID.
+///
+/// # Errors
+///
+/// * **UnhandledSyscall**: The kernel has no TRNG to seed its random number
+///                         generator from
+pub fn create_unnamed_server() -> core::result::Result<SID, Error> {
+    match rsyscall(SysCall::CreateServer(SID::from_u32(0, 0, 0, 0)))? {
+        Result::NewServerID(sid, _cid) => Ok(sid),
+        _ => Err(Error::InternalError),
+    }
+}
+
+/// Create a server like `create_server()`, but able to queue `queue_length`
+/// messages.  See `SysCall::CreateServerWithQueue` for details.
+///
+/// # Errors
+///
+/// * **ServerExists**: A server has already registered with that name
+/// * **InvalidString**: The name was not a valid UTF-8 string
+/// * **InvalidSyscall**: The queue length is `0`
+/// * **OutOfMemory**: The queue couldn't be allocated
+pub fn create_server_with_queue(
+    name_bytes: &[u8; 16],
+    queue_length: usize,
+) -> core::result::Result<SID, Error> {
+    let sid = SID::from_bytes(name_bytes).ok_or(Error::InvalidString)?;
+
+    match rsyscall(SysCall::CreateServerWithQueue(sid, queue_length))? {
+        Result::NewServerID(sid, _cid) => Ok(sid),
+        _ => Err(Error::InternalError),
+    }
+}
+
 /// Connect to a server with the given SID
 pub fn connect(server: SID) -> core::result::Result<CID, Error> {
     let result = rsyscall(SysCall::Connect(server))?;
@@ -943,6 +3250,12 @@ pub fn try_connect(server: SID) -> core::result::Result<CID, Error> {
     }
 }
 
+/// Release a connection made with `connect()` or `try_connect()`.  See
+/// `SysCall::Disconnect` for details.
+pub fn disconnect(connection: CID) -> core::result::Result<(), Error> {
+    rsyscall(SysCall::Disconnect(connection)).map(|_| ())
+}
+
 /// Suspend the current process until a message is received.  This thread will
 /// block until a message is received.
 ///
@@ -963,11 +3276,17 @@ pub fn receive_message(server: SID) -> core::result::Result<MessageEnvelope, Err
 /// will either block (borrow) or return immediately (move).
 /// If the message type is `borrow`, then the memory addresses pointed to will be
 /// unavailable to this process until this function returns.
+/// A borrow made with `MemoryMessage::scattered()` is mapped into the server
+/// as one contiguous buffer, and each piece returns to its place afterwards.
 ///
 /// # Errors
 ///
 /// * **ServerNotFound**: The server does not exist so the connection is now invalid
 /// * **BadAddress**: The client tried to pass a Memory message using an address it doesn't own
+/// * **BadAlignment**: A piece of a scattered borrow wasn't page-aligned
+/// * **OutOfMemory**: A scattered borrow had too many pieces
+/// * **ShareViolation**: Two pieces of a scattered borrow overlap
+/// * **InvalidSyscall**: A move was scattered
 /// * **ServerQueueFull**: The queue in the server is full, and this call would block
 /// * **Timeout**: The timeout limit has been reached
 pub fn try_send_message(connection: CID, message: Message) -> core::result::Result<Result, Error> {
@@ -999,6 +3318,400 @@ pub fn send_message(connection: CID, message: Message) -> core::result::Result<(
     }
 }
 
+/// Ask the kernel to print and reset its coverage counters.
+pub fn dump_coverage() -> core::result::Result<(), Error> {
+    rsyscall(SysCall::DumpCoverage).map(|_| ())
+}
+
+/// Call `handler` when a thread in this process raises a CPU exception that the
+/// kernel can't resolve.  See `SysCall::SetExceptionHandler` for details.
+pub fn set_exception_handler(
+    handler: Option<fn(cause: usize, pc: usize, addr: usize)>,
+) -> core::result::Result<(), Error> {
+    rsyscall(SysCall::SetExceptionHandler(
+        handler.and_then(|h| MemoryAddress::new(h as *mut usize as usize)),
+    ))
+    .map(|_| ())
+}
+
+/// Run the calling thread's exception handler on `stack` rather than on the
+/// thread's own stack.
+pub fn set_exception_stack(stack: Option<MemoryRange>) -> core::result::Result<(), Error> {
+    rsyscall(SysCall::SetExceptionStack(stack)).map(|_| ())
+}
+
+/// Get the time at which the kernel reached `stage` while booting.  See
+/// `SysCall::BootStageTime` for details.
+pub fn boot_stage_time(stage: BootStage) -> core::result::Result<u64, Error> {
+    match rsyscall(SysCall::BootStageTime(stage))? {
+        Result::Scalar2(low, high) => Ok(((high as u64) << 32) | low as u64),
+        _ => Err(Error::InternalError),
+    }
+}
+
+/// Set the scheduling priority of the calling process, returning the
+/// priority it is actually running at.  See `SysCall::SetPriority` for
+/// details.
+pub fn set_priority(priority: u8) -> core::result::Result<u8, Error> {
+    match rsyscall(SysCall::SetPriority(priority))? {
+        Result::Scalar1(effective) => Ok(effective as u8),
+        _ => Err(Error::InternalError),
+    }
+}
+
+/// Set how many timer ticks processes in priority `class` may run for before
+/// being preempted, returning the previous quantum.  See `SysCall::SetQuantum`
+/// for details.
+pub fn set_quantum(class: usize, ticks: usize) -> core::result::Result<usize, Error> {
+    match rsyscall(SysCall::SetQuantum(class, ticks))? {
+        Result::Scalar1(previous) => Ok(previous),
+        _ => Err(Error::InternalError),
+    }
+}
+
+/// Get boot measurement `index`, where `0` is the measurement register and
+/// any other index is the hash of the image loaded as that PID.  See
+/// `SysCall::GetMeasurement` for details.
+pub fn measurement(index: usize) -> core::result::Result<[u8; 32], Error> {
+    let mut measurement = [0u8; 32];
+    for (chunk, bytes) in measurement.chunks_mut(8).enumerate() {
+        match rsyscall(SysCall::GetMeasurement(index, chunk))? {
+            Result::Scalar2(low, high) => {
+                bytes[..4].copy_from_slice(&(low as u32).to_le_bytes());
+                bytes[4..].copy_from_slice(&(high as u32).to_le_bytes());
+            }
+            _ => return Err(Error::InternalError),
+        }
+    }
+    Ok(measurement)
+}
+
+/// Get scheduler statistics for thread `tid` of process `pid`.  See
+/// `SysCall::QueryScheduler` for details.
+pub fn query_scheduler(pid: PID, tid: TID) -> core::result::Result<ThreadStats, Error> {
+    match rsyscall(SysCall::QueryScheduler(pid, tid))? {
+        Result::ThreadStats(stats) => Ok(stats),
+        _ => Err(Error::InternalError),
+    }
+}
+
+/// Reserve `budget` timer ticks out of every `period` for the calling thread,
+/// or pass `0` for both to give up a reservation.  See `SysCall::SetDeadline`
+/// for details.
+pub fn set_deadline(period: usize, budget: usize) -> core::result::Result<(), Error> {
+    rsyscall(SysCall::SetDeadline(period, budget)).map(|_| ())
+}
+
+/// Estimate the `percentile` latency of `stage` for messages to the server
+/// at the other end of `cid`, returning it along with the number of messages
+/// it was estimated from.  See `SysCall::QueryLatency` for details.
+pub fn query_latency(
+    cid: CID,
+    stage: LatencyStage,
+    percentile: usize,
+) -> core::result::Result<(usize, usize), Error> {
+    match rsyscall(SysCall::QueryLatency(cid, stage, percentile))? {
+        Result::Scalar2(latency, count) => Ok((latency, count)),
+        _ => Err(Error::InternalError),
+    }
+}
+
+/// Give the rest of this time slice to thread `tid` of process `pid`, or
+/// just yield if it isn't ready to run.  See `SysCall::YieldTo` for details.
+pub fn yield_to(pid: PID, tid: TID) -> core::result::Result<(), Error> {
+    rsyscall(SysCall::YieldTo(pid, tid)).map(|_| ())
+}
+
+/// Set aside `size` bytes of address space for a dynamic loader to map an
+/// image's segments into, optionally starting at `virt`.  See
+/// `SysCall::ReserveAddressSpace` for details.
+pub fn reserve_address_space(
+    virt: Option<MemoryAddress>,
+    size: usize,
+) -> core::result::Result<MemoryRange, Error> {
+    let size = MemorySize::new(size).ok_or(Error::InvalidSyscall)?;
+    match rsyscall(SysCall::ReserveAddressSpace(virt, size))? {
+        Result::MemoryRange(range) => Ok(range),
+        _ => Err(Error::InternalError),
+    }
+}
+
+/// Map a segment of `size` bytes at `offset` into the reservation `base`.
+/// See `SysCall::MapSegment` for details.
+pub fn map_segment(
+    base: MemoryAddress,
+    offset: usize,
+    size: usize,
+    flags: MemoryFlags,
+) -> core::result::Result<MemoryRange, Error> {
+    let size = MemorySize::new(size).ok_or(Error::InvalidSyscall)?;
+    match rsyscall(SysCall::MapSegment(base, offset, size, flags))? {
+        Result::MemoryRange(range) => Ok(range),
+        _ => Err(Error::InternalError),
+    }
+}
+
+/// Share the image segment at `src` with the child process `pid`, at the
+/// same offset into its reservation `dest_base`.  See `SysCall::ShareSegment`
+/// for details.
+pub fn share_segment(
+    src: MemoryAddress,
+    pid: PID,
+    dest_base: MemoryAddress,
+) -> core::result::Result<MemoryRange, Error> {
+    match rsyscall(SysCall::ShareSegment(src, pid, dest_base))? {
+        Result::MemoryRange(range) => Ok(range),
+        _ => Err(Error::InternalError),
+    }
+}
+
+/// Attach `data` to the connection that sent the message `sender`, to be
+/// returned with every later message from it.  See
+/// `SysCall::SetConnectionData` for details.
+pub fn set_connection_data(sender: MessageSender, data: usize) -> core::result::Result<(), Error> {
+    rsyscall(SysCall::SetConnectionData(sender, data)).map(|_| ())
+}
+
+/// Create a notification owned by this process, and return its ID.  See
+/// `SysCall::CreateNotification` for details.
+pub fn create_notification() -> core::result::Result<usize, Error> {
+    match rsyscall(SysCall::CreateNotification)? {
+        Result::Scalar1(id) => Ok(id),
+        _ => Err(Error::InternalError),
+    }
+}
+
+/// Signal notification `id` `count` times, waking up to `count` of the
+/// threads waiting on it.
+pub fn signal_notification(id: usize, count: usize) -> core::result::Result<(), Error> {
+    rsyscall(SysCall::SignalNotification(id, count)).map(|_| ())
+}
+
+/// Block until notification `id` is signalled.
+pub fn wait_notification(id: usize) -> core::result::Result<(), Error> {
+    rsyscall(SysCall::WaitNotification(id)).map(|_| ())
+}
+
+/// Consume a signal from notification `id` without blocking.  Returns `true`
+/// if there was one.
+pub fn poll_notification(id: usize) -> core::result::Result<bool, Error> {
+    match rsyscall(SysCall::PollNotification(id))? {
+        Result::Scalar1(signalled) => Ok(signalled != 0),
+        _ => Err(Error::InternalError),
+    }
+}
+
+/// Create a poll set for waiting on several of this process' servers at
+/// once, and return its ID.  See `SysCall::CreatePollSet` for details.
+pub fn create_poll_set() -> core::result::Result<usize, Error> {
+    match rsyscall(SysCall::CreatePollSet)? {
+        Result::Scalar1(id) => Ok(id),
+        _ => Err(Error::InternalError),
+    }
+}
+
+/// Add server `sid` to poll set `id`, and return the `CID` that its messages
+/// will arrive on.
+pub fn add_to_poll_set(id: usize, sid: SID) -> core::result::Result<CID, Error> {
+    match rsyscall(SysCall::AddToPollSet(id, sid))? {
+        Result::Scalar1(cid) => Ok(cid),
+        _ => Err(Error::InternalError),
+    }
+}
+
+/// Block until a message arrives on any server in poll set `id`.  Use
+/// `MessageEnvelope::server_cid()` to tell which server received it.
+pub fn receive_any(id: usize) -> core::result::Result<MessageEnvelope, Error> {
+    match rsyscall(SysCall::ReceiveAny(id))? {
+        Result::Message(envelope) => Ok(envelope),
+        _ => Err(Error::InternalError),
+    }
+}
+
+/// Describe how the kernel was built.  See `SysCall::GetKernelConfig` for
+/// details.
+pub fn kernel_config() -> core::result::Result<KernelConfig, Error> {
+    match rsyscall(SysCall::GetKernelConfig)? {
+        Result::KernelConfig(config) => Ok(config),
+        _ => Err(Error::InternalError),
+    }
+}
+
+/// Pass a message that one of this process' servers received from `sender`
+/// on to the server at the other end of `cid`, which will respond to the
+/// original client.  See `SysCall::ForwardMessage` for details.
+pub fn forward_message(
+    sender: MessageSender,
+    cid: CID,
+    message: Message,
+) -> core::result::Result<(), Error> {
+    rsyscall(SysCall::ForwardMessage(sender, cid, message)).map(|_| ())
+}
+
+/// Find the next client that server `sid` has received a blocking message
+/// from but not yet responded to.  Pass `None` to start from the beginning,
+/// and then the previous result to continue from there.  See
+/// `SysCall::NextOutstandingSender` for details.
+pub fn next_outstanding_sender(
+    sid: SID,
+    after: Option<MessageSender>,
+) -> core::result::Result<Option<MessageSender>, Error> {
+    let slot = after.map(|sender| (sender & 0xffff) + 1).unwrap_or(0);
+    match rsyscall(SysCall::NextOutstandingSender(sid, slot))? {
+        Result::Scalar1(0) => Ok(None),
+        Result::Scalar1(sender) => Ok(Some(sender)),
+        _ => Err(Error::InternalError),
+    }
+}
+
+/// Signal `notification` once the server at the other end of `cid` goes
+/// away.  See `SysCall::WatchDisconnect` for details.
+pub fn watch_disconnect(cid: CID, notification: usize) -> core::result::Result<(), Error> {
+    rsyscall(SysCall::WatchDisconnect(cid, notification)).map(|_| ())
+}
+
+/// Get the time at which the message this thread most recently received was
+/// queued.  Call this right after receiving the message, since the next
+/// message replaces it.  See `SysCall::MessageTimestamp` for details.
+pub fn message_timestamp() -> core::result::Result<u64, Error> {
+    match rsyscall(SysCall::MessageTimestamp)? {
+        Result::Scalar2(low, high) => Ok(((high as u64) << 32) | low as u64),
+        _ => Err(Error::InternalError),
+    }
+}
+
+/// Get the number of kernel ticks since boot.  See `SysCall::GetTicks` for
+/// details.
+pub fn get_ticks() -> core::result::Result<u64, Error> {
+    match rsyscall(SysCall::GetTicks)? {
+        Result::Scalar2(low, high) => Ok(((high as u64) << 32) | low as u64),
+        _ => Err(Error::InternalError),
+    }
+}
+
+/// Have message `opcode` sent to the server at the other end of `cid` after
+/// `delay_ms`, and then every `period_ms` unless that's `0`, and return the
+/// ID of the timer.  See `SysCall::CreateTimer` for details.
+pub fn create_timer(
+    cid: CID,
+    opcode: usize,
+    delay_ms: usize,
+    period_ms: usize,
+) -> core::result::Result<usize, Error> {
+    match rsyscall(SysCall::CreateTimer(cid, opcode, delay_ms, period_ms))? {
+        Result::Scalar1(id) => Ok(id),
+        _ => Err(Error::InternalError),
+    }
+}
+
+/// Stop timer `id`.  See `SysCall::CancelTimer` for details.
+pub fn cancel_timer(id: usize) -> core::result::Result<(), Error> {
+    rsyscall(SysCall::CancelTimer(id)).map(|_| ())
+}
+
+/// Sleep for `ms` milliseconds without using any CPU time.  See
+/// `SysCall::SleepThread` for details.
+pub fn sleep_thread(ms: usize) -> core::result::Result<(), Error> {
+    rsyscall(SysCall::SleepThread(ms)).map(|_| ())
+}
+
+/// Limit how long servers may hold memory that this process lends them, and
+/// return the previous limit.  See `SysCall::SetLendTimeout` for details.
+pub fn set_lend_timeout(ticks: usize) -> core::result::Result<usize, Error> {
+    match rsyscall(SysCall::SetLendTimeout(ticks))? {
+        Result::Scalar1(previous) => Ok(previous),
+        _ => Err(Error::InternalError),
+    }
+}
+
+/// Describe the server in slot `index` of the kernel's server table.  See
+/// `SysCall::QueryServices` for details.
+pub fn query_services(index: usize) -> core::result::Result<ServerInfo, Error> {
+    match rsyscall(SysCall::QueryServices(index))? {
+        Result::ServerInfo(info) => Ok(info),
+        _ => Err(Error::InternalError),
+    }
+}
+
+/// Promise to call `heartbeat()` at least once every `ticks`, or stop being
+/// watched if `ticks` is `0`.  See `SysCall::SetHeartbeat` for details.
+pub fn set_heartbeat(ticks: usize) -> core::result::Result<(), Error> {
+    rsyscall(SysCall::SetHeartbeat(ticks)).map(|_| ())
+}
+
+/// Tell the watchdog that this process is still alive.  See
+/// `SysCall::Heartbeat` for details.
+pub fn heartbeat() -> core::result::Result<(), Error> {
+    rsyscall(SysCall::Heartbeat).map(|_| ())
+}
+
+/// Become the watchdog supervisor, with `notification` signalled whenever a
+/// process misses its heartbeat.  See `SysCall::SetWatchdogSupervisor` for
+/// details.
+pub fn set_watchdog_supervisor(notification: usize) -> core::result::Result<(), Error> {
+    rsyscall(SysCall::SetWatchdogSupervisor(notification)).map(|_| ())
+}
+
+/// Find out which process missed its heartbeat, or `None` if every process
+/// is keeping up.  See `SysCall::TakeMissedHeartbeat` for details.
+pub fn take_missed_heartbeat() -> core::result::Result<Option<PID>, Error> {
+    match rsyscall(SysCall::TakeMissedHeartbeat) {
+        Ok(Result::ProcessID(pid)) => Ok(Some(pid)),
+        Err(Error::ProcessNotFound) => Ok(None),
+        Ok(_) => Err(Error::InternalError),
+        Err(e) => Err(e),
+    }
+}
+
+/// Create a broadcast list owned by this process, and return its ID.  See
+/// `SysCall::CreateBroadcast` for details.
+pub fn create_broadcast() -> core::result::Result<usize, Error> {
+    match rsyscall(SysCall::CreateBroadcast)? {
+        Result::Scalar1(id) => Ok(id),
+        _ => Err(Error::InternalError),
+    }
+}
+
+/// Have broadcasts on list `id` delivered to the server at the other end of
+/// `cid` as messages with ID `opcode`.  See `SysCall::Subscribe` for
+/// details.
+pub fn subscribe(id: usize, cid: CID, opcode: usize) -> core::result::Result<(), Error> {
+    rsyscall(SysCall::Subscribe(id, cid, opcode)).map(|_| ())
+}
+
+/// Stop delivering broadcasts on list `id` to the server at the other end of
+/// `cid`.  See `SysCall::Unsubscribe` for details.
+pub fn unsubscribe(id: usize, cid: CID) -> core::result::Result<(), Error> {
+    rsyscall(SysCall::Unsubscribe(id, cid)).map(|_| ())
+}
+
+/// Send a scalar message with `args` to every server subscribed to
+/// broadcast list `id`, and return how many it reached.  See
+/// `SysCall::Broadcast` for details.
+pub fn broadcast(id: usize, args: [usize; 4]) -> core::result::Result<usize, Error> {
+    match rsyscall(SysCall::Broadcast(id, args[0], args[1], args[2], args[3]))? {
+        Result::Scalar1(delivered) => Ok(delivered),
+        _ => Err(Error::InternalError),
+    }
+}
+
+/// Send `message` to a server and wait for its reply.  See
+/// `SysCall::CallMessage` for details.
+pub fn call_message(connection: CID, message: Message) -> core::result::Result<Result, Error> {
+    match rsyscall(SysCall::CallMessage(connection, message))? {
+        reply @ Result::Ok | reply @ Result::Scalar1(_) | reply @ Result::Scalar2(_, _) => {
+            Ok(reply)
+        }
+        _ => Err(Error::InternalError),
+    }
+}
+
+/// Ask the kernel to print the memory mappings of process `pid` to its
+/// console.
+pub fn dump_memory_map(pid: PID) -> core::result::Result<(), Error> {
+    rsyscall(SysCall::DumpMemoryMap(pid)).map(|_| ())
+}
+
 /// Return execution to the kernel. This function may return at any time,
 /// including immediately
 pub fn yield_slice() {
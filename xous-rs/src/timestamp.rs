@@ -0,0 +1,70 @@
+//! High-resolution timestamps.
+//!
+//! `now()` returns a free-running counter that may be read without making a
+//! syscall.  On hardware this is the RISC-V `time` CSR, and on a hosted system
+//! it counts nanoseconds.  To turn ticks into real time, fetch the rate the
+//! counter runs at with `Timebase::get()` once, and then use it to convert as
+//! many timestamps as you like.
+
+use crate::{rsyscall, Error, Result, SysCall};
+
+/// Read the current timestamp, in ticks.
+pub fn now() -> u64 {
+    crate::arch::timestamp()
+}
+
+/// Measure the number of ticks it takes to read a timestamp.  This is the
+/// smallest interval `now()` is able to resolve, and may be subtracted from
+/// benchmark results.  If the CPU doesn't allow userspace to read the counter
+/// directly then the kernel emulates it, which makes this much larger.
+pub fn overhead() -> u64 {
+    let mut best = u64::MAX;
+    for _ in 0..8 {
+        let start = now();
+        let end = now();
+        best = best.min(end.wrapping_sub(start));
+    }
+    best
+}
+
+/// The rate at which timestamps increment.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Timebase {
+    ticks_per_second: u64,
+}
+
+impl Timebase {
+    /// Ask the kernel how fast timestamps increment.
+    pub fn get() -> core::result::Result<Timebase, Error> {
+        match rsyscall(SysCall::GetTimebase)? {
+            Result::Scalar1(hz) if hz != 0 => Ok(Timebase::new(hz as u64)),
+            _ => Err(Error::InternalError),
+        }
+    }
+
+    /// Create a timebase for a counter that increments `ticks_per_second`
+    /// times per second.  This is useful if the rate is already known, or
+    /// has been measured against another clock.
+    pub const fn new(ticks_per_second: u64) -> Timebase {
+        Timebase { ticks_per_second }
+    }
+
+    pub fn ticks_per_second(&self) -> u64 {
+        self.ticks_per_second
+    }
+
+    /// Convert a number of ticks into nanoseconds.
+    pub fn to_ns(&self, ticks: u64) -> u64 {
+        (ticks as u128 * 1_000_000_000 / self.ticks_per_second as u128) as u64
+    }
+
+    /// Convert a number of ticks into microseconds.
+    pub fn to_us(&self, ticks: u64) -> u64 {
+        (ticks as u128 * 1_000_000 / self.ticks_per_second as u128) as u64
+    }
+
+    /// Convert a number of nanoseconds into ticks, rounding down.
+    pub fn from_ns(&self, ns: u64) -> u64 {
+        (ns as u128 * self.ticks_per_second as u128 / 1_000_000_000) as u64
+    }
+}
@@ -37,6 +37,9 @@ fn try_main() -> Result<(), DynError> {
         Some("renode-image-debug") => image(true)?,
         Some("run") => run(false)?,
         Some("debug") => run(true)?,
+        Some("scenario-image") => scenario_image()?,
+        Some("run-scenario") => run_scenario()?,
+        Some("metrics-image") => metrics_image()?,
         _ => print_help(),
     }
     Ok(())
@@ -49,14 +52,39 @@ renode-image            builds a test image for renode
 renode-image-debug      builds a test image for renode in debug mode
 run                     runs a release build using a hosted environment
 debug                   runs a debug build using a hosted environment
+scenario-image          builds a renode image that runs the IPC scenarios
+run-scenario            runs the IPC scenarios using a hosted environment
+metrics-image           builds a renode image that prints the metrics server's exports
 "
     )
 }
 
 fn image(debug: bool) -> Result<(), DynError> {
+    build_image(
+        &["shell", "log-server", "graphics-server", "xous-names"],
+        debug,
+    )
+}
+
+/// Build an image whose only initial program is `ipc-scenario`.  Its console
+/// output can be compared against that of `run-scenario` using the
+/// `diff-scenarios` tool.
+fn scenario_image() -> Result<(), DynError> {
+    build_image(&["ipc-scenario"], false)
+}
+
+/// Build an image that runs the metrics server alongside `metrics-export`,
+/// which prints every metric to the console every few seconds.  The hosted
+/// kernel can't hand lent memory from one process to another, so there is
+/// no hosted version of this.
+fn metrics_image() -> Result<(), DynError> {
+    build_image(&["metrics-server", "metrics-export"], false)
+}
+
+fn build_image(packages: &[&str], debug: bool) -> Result<(), DynError> {
     let kernel = build_kernel(debug)?;
     let mut init = vec![];
-    for pkg in &["shell", "log-server", "graphics-server"] {
+    for pkg in packages {
         init.push(build(pkg, debug, Some(TARGET), None)?);
     }
     build("loader", debug, Some(TARGET), Some("loader".into()))?;
@@ -67,11 +95,30 @@ fn image(debug: bool) -> Result<(), DynError> {
 }
 
 fn run(debug: bool) -> Result<(), DynError> {
+    if !run_hosted(
+        &["shell", "log-server", "graphics-server", "xous-names"],
+        debug,
+    )? {
+        return Err("cargo build failed".into());
+    }
+    Ok(())
+}
+
+/// Run `ipc-scenario` under the hosted kernel.  The kernel doesn't always
+/// exit cleanly once the scenarios shut it down, so its exit status is
+/// ignored.
+fn run_scenario() -> Result<(), DynError> {
+    run_hosted(&["ipc-scenario"], false)?;
+    Ok(())
+}
+
+/// Build the given packages and run them under the hosted kernel, returning
+/// whether the kernel exited successfully.
+fn run_hosted(init: &[&str], debug: bool) -> Result<bool, DynError> {
     let stream = if debug { "debug" } else { "release" };
-    let init = ["shell", "log-server", "graphics-server"];
 
     // let mut init_paths = vec![];
-    for pkg in &init {
+    for pkg in init {
         build(pkg, debug, None, None)?;
     }
     // println!("Built packages: {:?}", init_paths);
@@ -85,7 +132,7 @@ fn run(debug: bool) -> Result<(), DynError> {
     args.push("--");
 
     let mut paths = vec![];
-    for i in &init {
+    for i in init {
         let tmp: PathBuf = Path::new(&format!(
             "..{}target{}{}{}{}",
             MAIN_SEPARATOR, MAIN_SEPARATOR, stream, MAIN_SEPARATOR, i
@@ -107,11 +154,8 @@ fn run(debug: bool) -> Result<(), DynError> {
         .current_dir(dir)
         .args(&args)
         .status()?;
-    if !status.success() {
-        return Err("cargo build failed".into());
-    }
 
-    Ok(())
+    Ok(status.success())
 }
 
 fn build_kernel(debug: bool) -> Result<PathBuf, DynError> {